@@ -0,0 +1,11 @@
+/// Link to a system installation of Cbc.
+///
+/// The location of the library can be given with the `CBC_LIB_DIR` environment variable if
+/// it is not on the default search path.
+fn main() {
+    println!("cargo:rerun-if-env-changed=CBC_LIB_DIR");
+    if let Ok(dir) = std::env::var("CBC_LIB_DIR") {
+        println!("cargo:rustc-link-search=native={}", dir);
+    }
+    println!("cargo:rustc-link-lib=CbcSolver");
+}
@@ -0,0 +1,56 @@
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+
+//! Bindings to the subset of Cbc's C interface (`Cbc_C_Interface.h`) used by pywr.
+
+use std::os::raw::{c_char, c_double, c_int};
+
+pub type CoinBigIndex = c_int;
+
+#[repr(C)]
+pub struct Cbc_Model {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    pub fn Cbc_getVersion() -> *const c_char;
+    pub fn Cbc_newModel() -> *mut Cbc_Model;
+    pub fn Cbc_deleteModel(model: *mut Cbc_Model);
+    pub fn Cbc_loadProblem(
+        model: *mut Cbc_Model,
+        numcols: c_int,
+        numrows: c_int,
+        start: *const CoinBigIndex,
+        index: *const c_int,
+        value: *const c_double,
+        collb: *const c_double,
+        colub: *const c_double,
+        obj: *const c_double,
+        rowlb: *const c_double,
+        rowub: *const c_double,
+    );
+    pub fn Cbc_setInteger(model: *mut Cbc_Model, iColumn: c_int);
+    pub fn Cbc_setContinuous(model: *mut Cbc_Model, iColumn: c_int);
+    pub fn Cbc_setObjSense(model: *mut Cbc_Model, sense: c_double);
+    pub fn Cbc_setObjCoeff(model: *mut Cbc_Model, index: c_int, value: c_double);
+    pub fn Cbc_setRowLower(model: *mut Cbc_Model, index: c_int, value: c_double);
+    pub fn Cbc_setRowUpper(model: *mut Cbc_Model, index: c_int, value: c_double);
+    pub fn Cbc_setColLower(model: *mut Cbc_Model, index: c_int, value: c_double);
+    pub fn Cbc_setColUpper(model: *mut Cbc_Model, index: c_int, value: c_double);
+    pub fn Cbc_setLogLevel(model: *mut Cbc_Model, logLevel: c_int);
+    pub fn Cbc_setParameter(model: *mut Cbc_Model, name: *const c_char, value: *const c_char);
+    pub fn Cbc_solve(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_getNumCols(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_getNumRows(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_getColSolution(model: *mut Cbc_Model) -> *const c_double;
+    pub fn Cbc_getRowPrice(model: *mut Cbc_Model) -> *const c_double;
+    pub fn Cbc_getReducedCost(model: *mut Cbc_Model) -> *const c_double;
+    pub fn Cbc_getObjValue(model: *mut Cbc_Model) -> c_double;
+    pub fn Cbc_isProvenOptimal(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_isProvenInfeasible(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_isContinuousUnbounded(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_status(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_secondaryStatus(model: *mut Cbc_Model) -> c_int;
+    pub fn Cbc_getIterationCount(model: *mut Cbc_Model) -> c_int;
+}
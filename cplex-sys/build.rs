@@ -0,0 +1,61 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Link to a licensed CPLEX installation.
+///
+/// The installation is found using the `CPLEX_HOME` environment variable, which should point
+/// to the `cplex` directory of a CPLEX Optimization Studio installation. The versioned library
+/// name (e.g. `cplex2010`) is found from the installation's library directory, or it can be
+/// given explicitly with `CPLEX_LIB`.
+fn main() {
+    println!("cargo:rerun-if-env-changed=CPLEX_HOME");
+    println!("cargo:rerun-if-env-changed=CPLEX_LIB");
+
+    let lib_dir = match env::var("CPLEX_HOME") {
+        Ok(home) => find_library_dir(&PathBuf::from(home).join("bin")),
+        Err(_) => None,
+    };
+    if let Some(dir) = &lib_dir {
+        println!("cargo:rustc-link-search=native={}", dir.display());
+    }
+
+    let lib_name = match env::var("CPLEX_LIB") {
+        Ok(name) => Some(name),
+        Err(_) => lib_dir.and_then(|dir| find_library(&dir)),
+    };
+
+    match lib_name {
+        Some(name) => println!("cargo:rustc-link-lib={}", name),
+        // Don't fail here so the workspace can still be built without CPLEX installed; linking
+        // will fail instead if the bindings are actually used.
+        None => println!("cargo:warning=Unable to find the CPLEX library; set CPLEX_HOME or CPLEX_LIB."),
+    }
+}
+
+/// Find the platform specific directory (e.g. `bin/x86-64_linux`) containing the library.
+fn find_library_dir(bin_dir: &PathBuf) -> Option<PathBuf> {
+    for entry in fs::read_dir(bin_dir).ok()? {
+        let path = entry.ok()?.path();
+        if path.is_dir() && find_library(&path).is_some() {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Find the name of the versioned CPLEX C library in `dir`.
+fn find_library(dir: &PathBuf) -> Option<String> {
+    for entry in fs::read_dir(dir).ok()? {
+        let file_name = entry.ok()?.file_name().into_string().ok()?;
+        let stem = file_name.split('.').next()?;
+        let name = stem.strip_prefix("lib").unwrap_or(stem);
+
+        if let Some(version) = name.strip_prefix("cplex") {
+            if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) {
+                return Some(name.to_string());
+            }
+        }
+    }
+    None
+}
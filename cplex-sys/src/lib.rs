@@ -0,0 +1,117 @@
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+
+//! Bindings to the subset of the CPLEX Callable Library (`cplex.h`) used by pywr.
+
+use std::os::raw::{c_char, c_double, c_int, c_longlong};
+
+#[repr(C)]
+pub struct cpxenv {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct cpxlp {
+    _private: [u8; 0],
+}
+
+pub type CPXENVptr = *mut cpxenv;
+pub type CPXCENVptr = *const cpxenv;
+pub type CPXLPptr = *mut cpxlp;
+pub type CPXCLPptr = *const cpxlp;
+
+pub const CPX_INFBOUND: c_double = 1.0e20;
+pub const CPXMESSAGEBUFSIZE: usize = 1024;
+
+pub const CPX_CONTINUOUS: c_char = b'C' as c_char;
+pub const CPX_INTEGER: c_char = b'I' as c_char;
+
+/// Solution status codes
+pub const CPX_STAT_OPTIMAL: c_int = 1;
+pub const CPX_STAT_UNBOUNDED: c_int = 2;
+pub const CPX_STAT_INFEASIBLE: c_int = 3;
+pub const CPXMIP_OPTIMAL: c_int = 101;
+pub const CPXMIP_OPTIMAL_TOL: c_int = 102;
+
+/// Parameters
+pub const CPX_PARAM_EPOPT: c_int = 1014;
+pub const CPX_PARAM_EPRHS: c_int = 1016;
+pub const CPX_PARAM_PREIND: c_int = 1030;
+pub const CPX_PARAM_SCRIND: c_int = 1035;
+pub const CPX_PARAM_TILIM: c_int = 1039;
+pub const CPX_PARAM_LPMETHOD: c_int = 1062;
+pub const CPX_PARAM_THREADS: c_int = 1067;
+
+/// Values of `CPX_PARAM_LPMETHOD`
+pub const CPX_ALG_AUTOMATIC: c_int = 0;
+pub const CPX_ALG_PRIMAL: c_int = 1;
+pub const CPX_ALG_DUAL: c_int = 2;
+pub const CPX_ALG_BARRIER: c_int = 4;
+pub const CPX_ALG_CONCURRENT: c_int = 6;
+
+pub const CPX_OFF: c_int = 0;
+pub const CPX_ON: c_int = 1;
+
+extern "C" {
+    pub fn CPXopenCPLEX(status_p: *mut c_int) -> CPXENVptr;
+    pub fn CPXcloseCPLEX(env_p: *mut CPXENVptr) -> c_int;
+    pub fn CPXgeterrorstring(env: CPXCENVptr, errcode: c_int, buffer_str: *mut c_char) -> *const c_char;
+    pub fn CPXsetintparam(env: CPXENVptr, whichparam: c_int, newvalue: c_int) -> c_int;
+    pub fn CPXsetdblparam(env: CPXENVptr, whichparam: c_int, newvalue: c_double) -> c_int;
+    pub fn CPXcreateprob(env: CPXCENVptr, status_p: *mut c_int, probname_str: *const c_char) -> CPXLPptr;
+    pub fn CPXfreeprob(env: CPXCENVptr, lp_p: *mut CPXLPptr) -> c_int;
+    pub fn CPXnewcols(
+        env: CPXCENVptr,
+        lp: CPXLPptr,
+        ccnt: c_int,
+        obj: *const c_double,
+        lb: *const c_double,
+        ub: *const c_double,
+        xctype: *const c_char,
+        colname: *const *const c_char,
+    ) -> c_int;
+    pub fn CPXaddrows(
+        env: CPXCENVptr,
+        lp: CPXLPptr,
+        ccnt: c_int,
+        rcnt: c_int,
+        nzcnt: c_int,
+        rhs: *const c_double,
+        sense: *const c_char,
+        rmatbeg: *const c_int,
+        rmatind: *const c_int,
+        rmatval: *const c_double,
+        colname: *const *const c_char,
+        rowname: *const *const c_char,
+    ) -> c_int;
+    pub fn CPXchgobj(env: CPXCENVptr, lp: CPXLPptr, cnt: c_int, indices: *const c_int, values: *const c_double)
+        -> c_int;
+    pub fn CPXchgbds(
+        env: CPXCENVptr,
+        lp: CPXLPptr,
+        cnt: c_int,
+        indices: *const c_int,
+        lu: *const c_char,
+        bd: *const c_double,
+    ) -> c_int;
+    pub fn CPXchgcoeflist(
+        env: CPXCENVptr,
+        lp: CPXLPptr,
+        numcoefs: c_int,
+        rowlist: *const c_int,
+        collist: *const c_int,
+        vallist: *const c_double,
+    ) -> c_int;
+    pub fn CPXlpopt(env: CPXCENVptr, lp: CPXLPptr) -> c_int;
+    pub fn CPXmipopt(env: CPXCENVptr, lp: CPXLPptr) -> c_int;
+    pub fn CPXgetstat(env: CPXCENVptr, lp: CPXCLPptr) -> c_int;
+    pub fn CPXgetitcnt(env: CPXCENVptr, lp: CPXCLPptr) -> c_int;
+    pub fn CPXgetmipitcnt(env: CPXCENVptr, lp: CPXCLPptr) -> c_longlong;
+    pub fn CPXgetobjval(env: CPXCENVptr, lp: CPXCLPptr, objval_p: *mut c_double) -> c_int;
+    pub fn CPXgetx(env: CPXCENVptr, lp: CPXCLPptr, x: *mut c_double, begin: c_int, end: c_int) -> c_int;
+    pub fn CPXgetpi(env: CPXCENVptr, lp: CPXCLPptr, pi: *mut c_double, begin: c_int, end: c_int) -> c_int;
+    pub fn CPXgetdj(env: CPXCENVptr, lp: CPXCLPptr, dj: *mut c_double, begin: c_int, end: c_int) -> c_int;
+    pub fn CPXwriteprob(env: CPXCENVptr, lp: CPXCLPptr, filename_str: *const c_char, filetype_str: *const c_char)
+        -> c_int;
+}
@@ -0,0 +1,11 @@
+/// Link to a system installation of GLPK.
+///
+/// The location of the library can be given with the `GLPK_LIB_DIR` environment variable if
+/// it is not on the default search path.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GLPK_LIB_DIR");
+    if let Ok(dir) = std::env::var("GLPK_LIB_DIR") {
+        println!("cargo:rustc-link-search=native={}", dir);
+    }
+    println!("cargo:rustc-link-lib=glpk");
+}
@@ -0,0 +1,142 @@
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+
+//! Bindings to the subset of GLPK's API (`glpk.h`, version 4.65 or later) used by pywr.
+
+use std::os::raw::{c_char, c_double, c_int, c_void};
+
+#[repr(C)]
+pub struct glp_prob {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct glp_tree {
+    _private: [u8; 0],
+}
+
+/// Optimisation direction flags
+pub const GLP_MIN: c_int = 1;
+pub const GLP_MAX: c_int = 2;
+
+/// Kinds of structural variable
+pub const GLP_CV: c_int = 1;
+pub const GLP_IV: c_int = 2;
+pub const GLP_BV: c_int = 3;
+
+/// Types of variable
+pub const GLP_FR: c_int = 1;
+pub const GLP_LO: c_int = 2;
+pub const GLP_UP: c_int = 3;
+pub const GLP_DB: c_int = 4;
+pub const GLP_FX: c_int = 5;
+
+/// Solution status
+pub const GLP_UNDEF: c_int = 1;
+pub const GLP_FEAS: c_int = 2;
+pub const GLP_INFEAS: c_int = 3;
+pub const GLP_NOFEAS: c_int = 4;
+pub const GLP_OPT: c_int = 5;
+pub const GLP_UNBND: c_int = 6;
+
+/// Message levels
+pub const GLP_MSG_OFF: c_int = 0;
+pub const GLP_MSG_ERR: c_int = 1;
+pub const GLP_MSG_ON: c_int = 2;
+pub const GLP_MSG_ALL: c_int = 3;
+
+/// Simplex methods
+pub const GLP_PRIMAL: c_int = 1;
+pub const GLP_DUALP: c_int = 2;
+pub const GLP_DUAL: c_int = 3;
+
+pub const GLP_ON: c_int = 1;
+pub const GLP_OFF: c_int = 0;
+
+/// Simplex solver control parameters
+#[repr(C)]
+pub struct glp_smcp {
+    pub msg_lev: c_int,
+    pub meth: c_int,
+    pub pricing: c_int,
+    pub r_test: c_int,
+    pub tol_bnd: c_double,
+    pub tol_dj: c_double,
+    pub tol_piv: c_double,
+    pub obj_ll: c_double,
+    pub obj_ul: c_double,
+    pub it_lim: c_int,
+    pub tm_lim: c_int,
+    pub out_frq: c_int,
+    pub out_dly: c_int,
+    pub presolve: c_int,
+    pub excl: c_int,
+    pub shift: c_int,
+    pub aorn: c_int,
+    pub foo_bar: [c_double; 33],
+}
+
+/// Integer optimizer control parameters
+#[repr(C)]
+pub struct glp_iocp {
+    pub msg_lev: c_int,
+    pub br_tech: c_int,
+    pub bt_tech: c_int,
+    pub tol_int: c_double,
+    pub tol_obj: c_double,
+    pub tm_lim: c_int,
+    pub out_frq: c_int,
+    pub out_dly: c_int,
+    pub cb_func: Option<unsafe extern "C" fn(tree: *mut glp_tree, info: *mut c_void)>,
+    pub cb_info: *mut c_void,
+    pub cb_size: c_int,
+    pub pp_tech: c_int,
+    pub mip_gap: c_double,
+    pub mir_cuts: c_int,
+    pub gmi_cuts: c_int,
+    pub cov_cuts: c_int,
+    pub clq_cuts: c_int,
+    pub presolve: c_int,
+    pub binarize: c_int,
+    pub fp_heur: c_int,
+    pub ps_heur: c_int,
+    pub ps_tm_lim: c_int,
+    pub sr_heur: c_int,
+    pub use_sol: c_int,
+    pub save_sol: *const c_char,
+    pub alien: c_int,
+    pub flip: c_int,
+    pub foo_bar: [c_double; 23],
+}
+
+extern "C" {
+    pub fn glp_version() -> *const c_char;
+    pub fn glp_term_out(flag: c_int) -> c_int;
+    pub fn glp_create_prob() -> *mut glp_prob;
+    pub fn glp_delete_prob(P: *mut glp_prob);
+    pub fn glp_set_obj_dir(P: *mut glp_prob, dir: c_int);
+    pub fn glp_add_rows(P: *mut glp_prob, nrs: c_int) -> c_int;
+    pub fn glp_add_cols(P: *mut glp_prob, ncs: c_int) -> c_int;
+    pub fn glp_set_row_bnds(P: *mut glp_prob, i: c_int, type_: c_int, lb: c_double, ub: c_double);
+    pub fn glp_set_col_bnds(P: *mut glp_prob, j: c_int, type_: c_int, lb: c_double, ub: c_double);
+    pub fn glp_set_col_kind(P: *mut glp_prob, j: c_int, kind: c_int);
+    pub fn glp_set_obj_coef(P: *mut glp_prob, j: c_int, coef: c_double);
+    pub fn glp_set_mat_row(P: *mut glp_prob, i: c_int, len: c_int, ind: *const c_int, val: *const c_double);
+    pub fn glp_load_matrix(P: *mut glp_prob, ne: c_int, ia: *const c_int, ja: *const c_int, ar: *const c_double);
+    pub fn glp_get_num_rows(P: *mut glp_prob) -> c_int;
+    pub fn glp_get_num_cols(P: *mut glp_prob) -> c_int;
+    pub fn glp_init_smcp(parm: *mut glp_smcp);
+    pub fn glp_simplex(P: *mut glp_prob, parm: *const glp_smcp) -> c_int;
+    pub fn glp_get_status(P: *mut glp_prob) -> c_int;
+    pub fn glp_get_obj_val(P: *mut glp_prob) -> c_double;
+    pub fn glp_get_col_prim(P: *mut glp_prob, j: c_int) -> c_double;
+    pub fn glp_get_row_dual(P: *mut glp_prob, i: c_int) -> c_double;
+    pub fn glp_get_col_dual(P: *mut glp_prob, j: c_int) -> c_double;
+    pub fn glp_get_it_cnt(P: *mut glp_prob) -> c_int;
+    pub fn glp_init_iocp(parm: *mut glp_iocp);
+    pub fn glp_intopt(P: *mut glp_prob, parm: *const glp_iocp) -> c_int;
+    pub fn glp_mip_status(P: *mut glp_prob) -> c_int;
+    pub fn glp_mip_obj_val(P: *mut glp_prob) -> c_double;
+    pub fn glp_mip_col_val(P: *mut glp_prob, j: c_int) -> c_double;
+}
@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Link to a licensed Gurobi installation.
+///
+/// The installation is found using the `GUROBI_HOME` environment variable. The versioned library
+/// name (e.g. `gurobi100`) is found from the installation's `lib` directory, or it can be given
+/// explicitly with `GUROBI_LIB`.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GUROBI_HOME");
+    println!("cargo:rerun-if-env-changed=GUROBI_LIB");
+
+    let lib_dir = match env::var("GUROBI_HOME") {
+        Ok(home) => {
+            let dir = PathBuf::from(home).join("lib");
+            println!("cargo:rustc-link-search=native={}", dir.display());
+            Some(dir)
+        }
+        Err(_) => None,
+    };
+
+    let lib_name = match env::var("GUROBI_LIB") {
+        Ok(name) => Some(name),
+        Err(_) => lib_dir.and_then(|dir| find_library(&dir)),
+    };
+
+    match lib_name {
+        Some(name) => println!("cargo:rustc-link-lib={}", name),
+        // Don't fail here so the workspace can still be built without Gurobi installed; linking
+        // will fail instead if the bindings are actually used.
+        None => println!("cargo:warning=Unable to find the Gurobi library; set GUROBI_HOME or GUROBI_LIB."),
+    }
+}
+
+/// Find the name of the versioned Gurobi C library in `dir`.
+fn find_library(dir: &PathBuf) -> Option<String> {
+    for entry in fs::read_dir(dir).ok()? {
+        let file_name = entry.ok()?.file_name().into_string().ok()?;
+        let stem = file_name.split('.').next()?;
+        let name = stem.strip_prefix("lib").unwrap_or(stem);
+
+        if let Some(version) = name.strip_prefix("gurobi") {
+            if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) {
+                return Some(name.to_string());
+            }
+        }
+    }
+    None
+}
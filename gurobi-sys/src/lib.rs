@@ -0,0 +1,93 @@
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+
+//! Bindings to the subset of Gurobi's C API (`gurobi_c.h`) used by pywr.
+
+use std::os::raw::{c_char, c_double, c_int};
+
+#[repr(C)]
+pub struct GRBenv {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct GRBmodel {
+    _private: [u8; 0],
+}
+
+pub const GRB_INFINITY: c_double = 1e100;
+
+pub const GRB_CONTINUOUS: c_char = b'C' as c_char;
+pub const GRB_INTEGER: c_char = b'I' as c_char;
+pub const GRB_BINARY: c_char = b'B' as c_char;
+
+pub const GRB_LESS_EQUAL: c_char = b'<' as c_char;
+pub const GRB_GREATER_EQUAL: c_char = b'>' as c_char;
+pub const GRB_EQUAL: c_char = b'=' as c_char;
+
+/// Model status codes
+pub const GRB_LOADED: c_int = 1;
+pub const GRB_OPTIMAL: c_int = 2;
+pub const GRB_INFEASIBLE: c_int = 3;
+pub const GRB_INF_OR_UNBD: c_int = 4;
+pub const GRB_UNBOUNDED: c_int = 5;
+pub const GRB_TIME_LIMIT: c_int = 9;
+
+extern "C" {
+    pub fn GRBloadenv(envP: *mut *mut GRBenv, logfilename: *const c_char) -> c_int;
+    pub fn GRBfreeenv(env: *mut GRBenv);
+    pub fn GRBgetenv(model: *mut GRBmodel) -> *mut GRBenv;
+    pub fn GRBgeterrormsg(env: *mut GRBenv) -> *const c_char;
+    pub fn GRBsetintparam(env: *mut GRBenv, paramname: *const c_char, value: c_int) -> c_int;
+    pub fn GRBsetdblparam(env: *mut GRBenv, paramname: *const c_char, value: c_double) -> c_int;
+    pub fn GRBnewmodel(
+        env: *mut GRBenv,
+        modelP: *mut *mut GRBmodel,
+        Pname: *const c_char,
+        numvars: c_int,
+        obj: *const c_double,
+        lb: *const c_double,
+        ub: *const c_double,
+        vtype: *const c_char,
+        varnames: *const *const c_char,
+    ) -> c_int;
+    pub fn GRBfreemodel(model: *mut GRBmodel) -> c_int;
+    pub fn GRBaddconstrs(
+        model: *mut GRBmodel,
+        numconstrs: c_int,
+        numnz: c_int,
+        cbeg: *const c_int,
+        cind: *const c_int,
+        cval: *const c_double,
+        sense: *const c_char,
+        rhs: *const c_double,
+        constrnames: *const *const c_char,
+    ) -> c_int;
+    pub fn GRBupdatemodel(model: *mut GRBmodel) -> c_int;
+    pub fn GRBoptimize(model: *mut GRBmodel) -> c_int;
+    pub fn GRBwrite(model: *mut GRBmodel, filename: *const c_char) -> c_int;
+    pub fn GRBgetintattr(model: *mut GRBmodel, attrname: *const c_char, valueP: *mut c_int) -> c_int;
+    pub fn GRBgetdblattr(model: *mut GRBmodel, attrname: *const c_char, valueP: *mut c_double) -> c_int;
+    pub fn GRBgetdblattrarray(
+        model: *mut GRBmodel,
+        attrname: *const c_char,
+        first: c_int,
+        len: c_int,
+        values: *mut c_double,
+    ) -> c_int;
+    pub fn GRBchgcoeffs(
+        model: *mut GRBmodel,
+        cnt: c_int,
+        cind: *const c_int,
+        vind: *const c_int,
+        val: *const c_double,
+    ) -> c_int;
+    pub fn GRBsetdblattrarray(
+        model: *mut GRBmodel,
+        attrname: *const c_char,
+        first: c_int,
+        len: c_int,
+        newvalues: *const c_double,
+    ) -> c_int;
+}
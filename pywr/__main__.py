@@ -15,23 +15,23 @@ def simple1(use_python_parameter=False):
     model.connect_nodes("my-link", "my-output")
 
     class ConstantParameter:
-        def compute(self):
+        def compute(self, timestep, scenario_index, scenario_indices, values):
             return 3.1415
 
     if use_python_parameter:
         model.add_python_parameter("pi", ConstantParameter())
+        model.set_node_constraint("my-output", "max_flow", "pi")
     else:
-        model.add_constant("pi", 3.1415)
-    model.set_node_constraint("my-output", "pi")
+        # Constants are given directly, without creating a parameter.
+        model.set_node_constraint("my-output", "max_flow", 3.1415)
 
-    model.add_constant("output-cost", -10.0)
-    model.set_node_cost("my-output", "output-cost")
+    model.set_node_cost("my-output", -10.0)
 
     model.run("clp")
 
 
 class RandomParameter:
-    def compute(self):
+    def compute(self, timestep, scenario_index, scenario_indices, values):
         return random.random()
 
 
@@ -39,8 +39,6 @@ def zones(num_zones: int, use_python_parameter=False):
     """Create a model with some interconnected zones."""
     model = PyModel()
 
-    model.add_constant("output-cost", -10.0)
-
     zones = [f"zone{i:02d}" for i in range(num_zones)]
     for zone in zones:
         model.add_input_node(f"{zone}-input")
@@ -50,16 +48,14 @@ def zones(num_zones: int, use_python_parameter=False):
         model.connect_nodes(f"{zone}-input", f"{zone}-link")
         model.connect_nodes(f"{zone}-link", f"{zone}-output")
 
-        model.set_node_cost(f"{zone}-output", "output-cost")
+        model.set_node_cost(f"{zone}-output", -10.0)
 
         if use_python_parameter:
             model.add_python_parameter(f"{zone}-supply", RandomParameter())
+            model.set_node_constraint(f"{zone}-input", "max_flow", f"{zone}-supply")
         else:
-            model.add_constant(f"{zone}-supply", random.random())
-        model.add_constant(f"{zone}-demand", random.random())
-
-        model.set_node_constraint(f"{zone}-input", f"{zone}-supply")
-        model.set_node_constraint(f"{zone}-output", f"{zone}-demand")
+            model.set_node_constraint(f"{zone}-input", "max_flow", random.random())
+        model.set_node_constraint(f"{zone}-output", "max_flow", random.random())
 
     for zone_from, zone_to in product(zones, zones):
         if zone_from == zone_to:
@@ -4,7 +4,8 @@ from typing import List, Optional, Dict, Union
 from pydantic import BaseModel
 from .pywr import PyModel, ParameterNotFoundError  # type: ignore
 from .parameters import ParameterCollection
-from .recorders import RecorderCollection
+from .recorders import RecorderCollection, ArrayRecorder
+from .results import Results
 import json
 import yaml
 
@@ -57,6 +58,8 @@ class InputNode(BaseNode):
     def set_constraints(self, r_model: PyModel):
         if self.cost is not None:
             r_model.set_node_cost(self.name, self.cost)
+        if self.min_flow is not None:
+            r_model.set_node_constraint(self.name, "min_flow", self.min_flow)
         if self.max_flow is not None:
             r_model.set_node_constraint(self.name, "max_flow", self.max_flow)
 
@@ -72,6 +75,8 @@ class LinkNode(BaseNode):
     def set_constraints(self, r_model: PyModel):
         if self.cost is not None:
             r_model.set_node_cost(self.name, self.cost)
+        if self.min_flow is not None:
+            r_model.set_node_constraint(self.name, "min_flow", self.min_flow)
         if self.max_flow is not None:
             r_model.set_node_constraint(self.name, "max_flow", self.max_flow)
 
@@ -87,6 +92,8 @@ class OutputNode(BaseNode):
     def set_constraints(self, r_model: PyModel):
         if self.cost is not None:
             r_model.set_node_cost(self.name, self.cost)
+        if self.min_flow is not None:
+            r_model.set_node_constraint(self.name, "min_flow", self.min_flow)
         if self.max_flow is not None:
             r_model.set_node_constraint(self.name, "max_flow", self.max_flow)
 
@@ -103,6 +110,8 @@ class StorageNode(BaseNode):
     def set_constraints(self, r_model: PyModel):
         if self.cost is not None:
             r_model.set_node_cost(self.name, self.cost)
+        if self.min_volume is not None:
+            r_model.set_node_constraint(self.name, "min_volume", self.min_volume)
         if self.max_volume is not None:
             r_model.set_node_constraint(self.name, "max_volume", self.max_volume)
 
@@ -175,9 +184,40 @@ class BaseOutput(BaseModel):
 
 class HDF5Output(BaseOutput):
     filename: Path
+    units: Optional[str] = None
+    long_name: Optional[str] = None
+    attributes: Dict[str, str] = {}
 
     def create_output(self, r_model: PyModel):
         r_model.add_hdf5_output(self.name, str(self.filename))
+        _set_output_metadata(self, r_model)
+
+
+class CSVOutput(BaseOutput):
+    filename: Path
+    units: Optional[str] = None
+    long_name: Optional[str] = None
+    attributes: Dict[str, str] = {}
+
+    def create_output(self, r_model: PyModel):
+        r_model.add_csv_output(self.name, str(self.filename))
+        _set_output_metadata(self, r_model)
+
+
+class StreamOutput(BaseOutput):
+    address: str
+
+    def create_output(self, r_model: PyModel):
+        r_model.add_stream_output(self.name, self.address)
+
+
+def _set_output_metadata(output, r_model: PyModel):
+    if output.units is not None:
+        r_model.set_recorder_units(output.name, output.units)
+    if output.long_name is not None:
+        r_model.set_recorder_long_name(output.name, output.long_name)
+    for key, value in output.attributes.items():
+        r_model.set_recorder_attribute(output.name, key, value)
 
 
 class OutputCollection:
@@ -232,10 +272,16 @@ class Timestepper(BaseModel):
     timestep: int
 
 
+class Scenario(BaseModel):
+    name: str
+    size: int
+
+
 class Model(BaseModel):
     timestepper: Timestepper
     nodes: NodeCollection
     edges: List[Edge]
+    scenarios: List[Scenario] = []
     parameters: ParameterCollection = ParameterCollection()
     recorders: RecorderCollection = RecorderCollection()
     outputs: OutputCollection = OutputCollection()
@@ -272,6 +318,9 @@ class Model(BaseModel):
         """Construct a `PyModel`"""
 
         r_model = PyModel()
+        for scenario in self.scenarios:
+            r_model.add_scenario(scenario.name, scenario.size)
+
         for node in self.nodes:
             node.create_nodes(r_model)
 
@@ -309,10 +358,33 @@ class Model(BaseModel):
 
         return r_model
 
-    def run(self):
+    def run(self, solver="clp", progress=None) -> Results:
+        """Build and run the model, returning its results.
+
+        The solver is either the name of a solver or a Python object implementing
+        `setup(model_info)` and `solve(bounds, costs)`. If `progress` is given it is called
+        as `progress(fraction, date, speed)` as the run progresses.
+        """
+        r_model = self.build()
+        statistics = r_model.run(
+            solver,
+            self.timestepper.start,
+            self.timestepper.end,
+            self.timestepper.timestep,
+            progress,
+        )
+        array_recorders = [r.name for r in self.recorders if isinstance(r, ArrayRecorder)]
+        return Results(r_model, statistics, array_recorders)
+
+    def steps(self, solver="clp"):
+        """Build the model and start a run, which solves a timestep each time it is iterated.
+
+        Each iteration yields the state of every scenario after the timestep, e.g.
+        `state.node_in_flow("demand")`.
+        """
         r_model = self.build()
-        r_model.run(
-            "clp",
+        return r_model.steps(
+            solver,
             self.timestepper.start,
             self.timestepper.end,
             self.timestepper.timestep,
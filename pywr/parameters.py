@@ -1,5 +1,6 @@
+from importlib import import_module
 from pathlib import Path
-from typing import Optional, Dict, Any, List
+from typing import Optional, Dict, Any, List, Tuple
 
 import numpy as np
 from pydantic import BaseModel
@@ -49,6 +50,31 @@ class DataFrameParameter(BaseParameter):
         r_model.add_array(self.name, df.values)
 
 
+class DailyProfileParameter(BaseParameter):
+    values: List[float]
+
+    def create_parameter(self, r_model: PyModel, path: Path):
+        r_model.add_daily_profile(self.name, self.values)
+
+
+class PythonParameter(BaseParameter):
+    """A parameter computed by an instance of a Python class.
+
+    `object` is the class as `module.ClassName`. The instance's `setup` method, if it has one, is
+    called before each run with `kwargs` as keyword arguments, and its `compute` method is given
+    the values of `metrics`, each a pair of a metric and the name of its node or parameter.
+    """
+
+    object: str
+    kwargs: Dict[str, Any] = {}
+    metrics: List[Tuple[str, str]] = []
+
+    def create_parameter(self, r_model: PyModel, path: Path):
+        module_name, class_name = self.object.rsplit(".", 1)
+        klass = getattr(import_module(module_name), class_name)
+        r_model.add_python_parameter(self.name, klass(), self.metrics, self.kwargs)
+
+
 class AggregatedParameter(BaseParameter):
     agg_func: str  # TODO enum?
     parameters: List[str]
@@ -22,6 +22,16 @@ class HDF5Recorder(BaseRecorder):
         r_model.add_hdf5_recorder(self.name, self.comment)
 
 
+class ArrayRecorder(BaseRecorder):
+    """Keep the values of a metric in memory, to be read from the results of the run."""
+
+    component: str
+    metric: str
+
+    def create_recorder(self, r_model: PyModel):
+        r_model.add_array_recorder(self.name, self.component, self.metric)
+
+
 class AssertionRecorder(BaseRecorder):
     component: str
     metric: str
@@ -33,14 +43,23 @@ class AssertionRecorder(BaseRecorder):
         )
 
 
+class EdgeAssertionRecorder(BaseRecorder):
+    from_node: str
+    to_node: str
+    values: List[float]
+
+    def create_recorder(self, r_model: PyModel):
+        r_model.add_python_edge_recorder(
+            self.name, self.from_node, self.to_node, _AssertionRecorder(self.values)
+        )
+
+
 class _AssertionRecorder:
     def __init__(self, values: List[float]):
         self._iter = iter(values)
 
-    def save(self, timestep, value: float):
-        print(timestep, value)
-
-        assert next(self._iter) == value
+    def save(self, timestep, scenario_index: int, values: List[float]):
+        assert next(self._iter) == values[0]
 
 
 class RecorderCollection:
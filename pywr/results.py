@@ -0,0 +1,55 @@
+from typing import Any, Dict, Iterable
+
+from .pywr import PyModel  # type: ignore
+
+
+class Results:
+    """The results of a run: its statistics, and the values of its array recorders as DataFrames.
+
+    The DataFrames have a row for each saved timestep, indexed by its start, and a column for
+    each scenario, indexed by its member of each scenario group. The values are copied from the
+    model when the results are created, so a later run of the same model does not change them.
+    """
+
+    def __init__(
+        self, r_model: PyModel, statistics: Dict[str, Any], recorder_names: Iterable[str]
+    ):
+        self.statistics = statistics
+        self._arrays = {name: r_model.recorder_array(name) for name in recorder_names}
+        self._datetimes = {
+            name: list(r_model.recorder_datetimes(name)) for name in self._arrays
+        }
+        names, members = r_model.scenario_columns()
+        self._scenario_names = list(names)
+        self._scenario_members = [tuple(m) for m in members]
+
+    def to_dataframe(self, name: str):
+        """The values of the array recorder `name` as a pandas DataFrame."""
+        import pandas  # type: ignore
+
+        values = self._arrays[name]
+        index = pandas.DatetimeIndex(self._datetimes[name], name="time")
+
+        if self._scenario_names:
+            columns = pandas.MultiIndex.from_tuples(
+                self._scenario_members, names=self._scenario_names
+            )
+        else:
+            columns = pandas.RangeIndex(values.shape[1], name="scenario")
+        return pandas.DataFrame(values, index=index, columns=columns)
+
+    def to_polars(self, name: str):
+        """The values of the array recorder `name` as a Polars DataFrame.
+
+        Polars has no column index, and so there is a `time` column followed by a column for each
+        scenario, named by its members of the scenario groups joined with `/`.
+        """
+        import pandas  # type: ignore
+        import polars  # type: ignore
+
+        df = self.to_dataframe(name)
+        if isinstance(df.columns, pandas.MultiIndex):
+            df.columns = ["/".join(str(m) for m in members) for members in df.columns]
+        else:
+            df.columns = [str(c) for c in df.columns]
+        return polars.from_pandas(df.reset_index())
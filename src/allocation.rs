@@ -0,0 +1,255 @@
+use crate::edge::EdgeIndex;
+use crate::node::NodeIndex;
+use crate::solvers::{ProblemSolution, ProblemUpdate, Solver};
+use crate::PywrError;
+use std::str::FromStr;
+
+/// The number of bisections used to find the largest proportion of demand that can be supplied
+/// to every node of a priority class.
+const PROPORTION_BISECTIONS: usize = 20;
+
+/// How the available water is allocated between the demands of a timestep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AllocationMode {
+    /// Minimise the total cost of the flows in a single solve.
+    MinimumCost,
+    /// Supply each priority class in turn, and share the supply to a class in proportion to demand.
+    ///
+    /// The priority classes are the output nodes with a negative cost, grouped by cost, and are
+    /// supplied in order of increasing cost. The first stage maximises the total supply to a class
+    /// given the supply to the classes before it. The second stage supplies every node of the class
+    /// the same proportion of its maximum flow, reducing the proportion until the network can
+    /// provide it, and then tops up any nodes that can still be supplied. Once every class is
+    /// allocated, the remaining flows are found by minimising cost as usual.
+    ///
+    /// This requires several solves per timestep, and relies on the solver reporting infeasible
+    /// problems; it should not be used with the `"pdlp"` solver.
+    PriorityProportional,
+}
+
+impl AllocationMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MinimumCost => "minimum_cost",
+            Self::PriorityProportional => "priority_proportional",
+        }
+    }
+}
+
+impl FromStr for AllocationMode {
+    type Err = PywrError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "minimum_cost" => Ok(Self::MinimumCost),
+            "priority_proportional" => Ok(Self::PriorityProportional),
+            _ => Err(PywrError::InvalidAllocationMode(name.to_string())),
+        }
+    }
+}
+
+/// The output nodes that are supplied with equal priority, with the edges that supply each node.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PriorityClass {
+    demands: Vec<(NodeIndex, Vec<EdgeIndex>)>,
+}
+
+impl PriorityClass {
+    pub(crate) fn new(demands: Vec<(NodeIndex, Vec<EdgeIndex>)>) -> Self {
+        Self { demands }
+    }
+
+    /// Edge costs that maximise the total supply to this class, ignoring all other costs.
+    fn supply_costs(&self, nedges: usize) -> Vec<f64> {
+        let mut costs = vec![0.0; nedges];
+        for (_, edges) in &self.demands {
+            for &edge in edges {
+                costs[*edge] = -1.0;
+            }
+        }
+        costs
+    }
+
+    /// The flow to each node of this class in `solution`.
+    fn supplies(&self, solution: &ProblemSolution) -> Vec<f64> {
+        self.demands
+            .iter()
+            .map(|(_, edges)| edges.iter().map(|&edge| solution.edge_flows[*edge]).sum())
+            .collect()
+    }
+
+    /// Node bounds that supply every node of this class at least `proportion` of its maximum flow.
+    fn proportional_bounds(&self, node_bounds: &[(f64, f64)], proportion: f64) -> Vec<(f64, f64)> {
+        let mut bounds = node_bounds.to_vec();
+        for &(node, _) in &self.demands {
+            let (min_flow, max_flow) = node_bounds[*node];
+            bounds[*node] = (min_flow.max(proportion * max_flow), max_flow);
+        }
+        bounds
+    }
+}
+
+/// Solve `update` by supplying each of `classes` in turn, as described by
+/// `AllocationMode::PriorityProportional`.
+pub(crate) fn solve_priority_proportional(
+    solver: &mut dyn Solver,
+    update: &ProblemUpdate,
+    classes: &[PriorityClass],
+) -> Result<ProblemSolution, PywrError> {
+    let mut node_bounds = update.node_bounds.clone();
+
+    for class in classes {
+        let mut stage = update.clone();
+        stage.edge_costs = class.supply_costs(update.edge_costs.len());
+
+        // Stage one: the largest total supply to this class.
+        stage.node_bounds = node_bounds.clone();
+        let mut solution = solver.solve(&stage)?;
+        let total_supply: f64 = class.supplies(&solution).iter().sum();
+        let total_demand: f64 = class.demands.iter().map(|&(node, _)| node_bounds[*node].1).sum();
+
+        // Stage two: the largest proportion of demand that every node can be supplied, which is
+        // the total supply shared in proportion to demand if the network allows it.
+        let mut proportion = if total_demand > 0.0 {
+            (total_supply / total_demand).min(1.0)
+        } else {
+            0.0
+        };
+        stage.node_bounds = class.proportional_bounds(&node_bounds, proportion);
+        match solver.solve(&stage) {
+            Ok(s) => solution = s,
+            Err(e) if e.is_infeasible() => {
+                // The stage one solution is feasible with a proportion of zero.
+                let (mut lower, mut upper) = (0.0, proportion);
+                for _ in 0..PROPORTION_BISECTIONS {
+                    proportion = 0.5 * (lower + upper);
+                    stage.node_bounds = class.proportional_bounds(&node_bounds, proportion);
+                    match solver.solve(&stage) {
+                        Ok(s) => {
+                            solution = s;
+                            lower = proportion;
+                        }
+                        Err(e) if e.is_infeasible() => upper = proportion,
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        // Fix the supply to this class while the later classes are allocated.
+        for (&(node, _), supply) in class.demands.iter().zip(class.supplies(&solution)) {
+            node_bounds[*node] = (supply, supply);
+        }
+    }
+
+    let mut update = update.clone();
+    update.node_bounds = node_bounds;
+    solver.solve(&update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::solvers::export::ProblemFormat;
+    use crate::solvers::simplex::SimplexError;
+    use crate::solvers::{SolverFeature, SolverSettings, SolverStats};
+    use std::path::Path;
+
+    #[test]
+    fn test_allocation_mode_names() {
+        for mode in &[AllocationMode::MinimumCost, AllocationMode::PriorityProportional] {
+            assert_eq!(AllocationMode::from_str(mode.name()), Ok(*mode));
+        }
+        assert_eq!(
+            AllocationMode::from_str("proportional"),
+            Err(PywrError::InvalidAllocationMode("proportional".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_proportional_bounds() {
+        let class = PriorityClass::new(vec![
+            (NodeIndex(1), vec![EdgeIndex(0)]),
+            (NodeIndex(2), vec![EdgeIndex(1)]),
+        ]);
+        let bounds = vec![(0.0, f64::MAX), (0.0, 10.0), (4.0, 20.0)];
+        assert_eq!(
+            class.proportional_bounds(&bounds, 0.5),
+            vec![(0.0, f64::MAX), (5.0, 10.0), (10.0, 20.0)]
+        );
+        assert_eq!(class.supply_costs(3), vec![-1.0, -1.0, 0.0]);
+    }
+
+    /// A solver that supplies every edge in its first solve and then fails with a Python error of
+    /// `message`.
+    struct FailingSolver {
+        solves: usize,
+        message: String,
+        stats: SolverStats,
+    }
+
+    impl FailingSolver {
+        fn new(message: &str) -> Self {
+            Self {
+                solves: 0,
+                message: message.to_string(),
+                stats: SolverStats::default(),
+            }
+        }
+    }
+
+    impl Solver for FailingSolver {
+        fn features(&self) -> &'static [SolverFeature] {
+            &[]
+        }
+
+        fn new_instance(&self) -> Box<dyn Solver> {
+            Box::new(FailingSolver::new(&self.message))
+        }
+
+        fn setup(&mut self, _model: &Model, _settings: &SolverSettings) -> Result<(), PywrError> {
+            Ok(())
+        }
+
+        fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+            self.solves += 1;
+            match self.solves {
+                1 => Ok(ProblemSolution {
+                    edge_flows: vec![1.0; update.edge_costs.len()],
+                    ..Default::default()
+                }),
+                _ => Err(PywrError::PythonError(self.message.clone())),
+            }
+        }
+
+        fn write_problem(&self, _path: &Path, _format: ProblemFormat) -> Result<(), PywrError> {
+            Ok(())
+        }
+
+        fn stats(&self) -> &SolverStats {
+            &self.stats
+        }
+    }
+
+    #[test]
+    fn test_solver_errors_are_propagated() {
+        let classes = vec![PriorityClass::new(vec![(NodeIndex(1), vec![EdgeIndex(0)])])];
+        let update = ProblemUpdate {
+            edge_costs: vec![0.0],
+            node_bounds: vec![(0.0, 10.0), (0.0, 10.0)],
+            ..Default::default()
+        };
+
+        // An error other than infeasibility in the proportional stage is not treated as an
+        // infeasible proportion.
+        let mut solver = FailingSolver::new("solve failed");
+        assert_eq!(
+            solve_priority_proportional(&mut solver, &update, &classes),
+            Err(PywrError::PythonError("solve failed".to_string()))
+        );
+        assert!(PywrError::SimplexError(SimplexError::Infeasible).is_infeasible());
+        assert!(!PywrError::PythonError("solve failed".to_string()).is_infeasible());
+    }
+}
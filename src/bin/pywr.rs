@@ -0,0 +1,5 @@
+use std::process;
+
+fn main() {
+    process::exit(pywr::cli::main(std::env::args().skip(1)));
+}
@@ -0,0 +1,247 @@
+use crate::model::Model;
+use crate::node::{Constraint, ConstraintValue};
+use crate::parameters::{AggFunc, AggregatedParameter, ConstantParameter, _Parameter};
+use crate::recorders::_Recorder;
+use crate::schema::ValueSchema;
+use crate::virtual_node::VirtualNodeType;
+use crate::PywrError;
+
+/// Builds a model by chaining calls, with nodes, parameters and constraints referred to by name:
+///
+/// ```ignore
+/// let model = ModelBuilder::new()
+///     .input("inflow")
+///     .link("river")
+///     .output("demand")
+///     .connect("inflow", "river")
+///     .connect("river", "demand")
+///     .max_flow("inflow", 15.0)
+///     .constant("demand", 10.0)
+///     .max_flow("demand", "demand")
+///     .cost("demand", -10.0)
+///     .build()?;
+/// ```
+///
+/// A value is a number or the name of a parameter already added. The first error, e.g. of a
+/// duplicate or unknown name, is returned by `build` and the calls after it are ignored.
+#[derive(Default)]
+pub struct ModelBuilder {
+    model: Model,
+    error: Option<PywrError>,
+}
+
+impl ModelBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make a change to the model, unless an earlier change failed.
+    fn apply<F: FnOnce(&mut Model) -> Result<(), PywrError>>(mut self, change: F) -> Self {
+        if self.error.is_none() {
+            if let Err(error) = change(&mut self.model) {
+                self.error = Some(error);
+            }
+        }
+        self
+    }
+
+    pub fn input(self, name: &str) -> Self {
+        self.apply(|model| model.add_input_node(name).map(|_| ()))
+    }
+
+    pub fn output(self, name: &str) -> Self {
+        self.apply(|model| model.add_output_node(name).map(|_| ()))
+    }
+
+    pub fn link(self, name: &str) -> Self {
+        self.apply(|model| model.add_link_node(name).map(|_| ()))
+    }
+
+    pub fn storage(self, name: &str, initial_volume: f64) -> Self {
+        self.apply(|model| model.add_storage_node(name, initial_volume).map(|_| ()))
+    }
+
+    /// Add a virtual node of the total flow through `nodes`, each scaled by its factor.
+    pub fn virtual_sum(self, name: &str, nodes: &[(&str, f64)]) -> Self {
+        self.apply(|model| {
+            let nodes = nodes
+                .iter()
+                .map(|(n, factor)| model.get_node_by_name(n).map(|node| (node.index(), *factor)))
+                .collect::<Result<Vec<_>, _>>()?;
+            model.add_virtual_node(name, VirtualNodeType::Sum(nodes)).map(|_| ())
+        })
+    }
+
+    /// Add a virtual node of the net inflow to `nodes`.
+    pub fn virtual_balance(self, name: &str, nodes: &[&str]) -> Self {
+        self.apply(|model| {
+            let nodes = nodes
+                .iter()
+                .map(|n| model.get_node_by_name(n).map(|node| node.index()))
+                .collect::<Result<Vec<_>, _>>()?;
+            model
+                .add_virtual_node(name, VirtualNodeType::Balance(nodes))
+                .map(|_| ())
+        })
+    }
+
+    /// Add an edge from the node `from` to the node `to`.
+    pub fn connect(self, from: &str, to: &str) -> Self {
+        self.apply(|model| {
+            let from = model.get_node_by_name(from)?;
+            let to = model.get_node_by_name(to)?;
+            model.connect_nodes(&from, &to).map(|_| ())
+        })
+    }
+
+    pub fn max_flow<V: Into<ValueSchema>>(self, node: &str, value: V) -> Self {
+        self.constraint(node, value.into(), Constraint::MaxFlow)
+    }
+
+    pub fn min_flow<V: Into<ValueSchema>>(self, node: &str, value: V) -> Self {
+        self.constraint(node, value.into(), Constraint::MinFlow)
+    }
+
+    pub fn max_volume<V: Into<ValueSchema>>(self, node: &str, value: V) -> Self {
+        self.constraint(node, value.into(), Constraint::MaxVolume)
+    }
+
+    pub fn min_volume<V: Into<ValueSchema>>(self, node: &str, value: V) -> Self {
+        self.constraint(node, value.into(), Constraint::MinVolume)
+    }
+
+    fn constraint(self, node: &str, value: ValueSchema, constraint: Constraint) -> Self {
+        self.apply(|model| {
+            let value = constraint_value(model, value)?;
+            model.get_node_by_name(node)?.set_constraint(value, constraint)
+        })
+    }
+
+    pub fn cost<V: Into<ValueSchema>>(self, node: &str, value: V) -> Self {
+        let value = value.into();
+        self.apply(|model| {
+            let value = constraint_value(model, value)?;
+            model.get_node_by_name(node)?.set_cost(value);
+            Ok(())
+        })
+    }
+
+    /// Limit the flow along the edge from the node `from` to the node `to`.
+    pub fn edge_max_flow<V: Into<ValueSchema>>(self, from: &str, to: &str, value: V) -> Self {
+        self.edge_constraint(from, to, value.into(), Constraint::MaxFlow)
+    }
+
+    pub fn edge_min_flow<V: Into<ValueSchema>>(self, from: &str, to: &str, value: V) -> Self {
+        self.edge_constraint(from, to, value.into(), Constraint::MinFlow)
+    }
+
+    fn edge_constraint(self, from: &str, to: &str, value: ValueSchema, constraint: Constraint) -> Self {
+        self.apply(|model| {
+            let value = constraint_value(model, value)?;
+            model
+                .get_edge_by_node_names(from, to)?
+                .set_constraint(value, constraint)
+        })
+    }
+
+    pub fn edge_cost<V: Into<ValueSchema>>(self, from: &str, to: &str, value: V) -> Self {
+        let value = value.into();
+        self.apply(|model| {
+            let value = constraint_value(model, value)?;
+            model.get_edge_by_node_names(from, to)?.set_cost(value);
+            Ok(())
+        })
+    }
+
+    /// Require the flows through the nodes with the names `nodes` to be in proportion to `factors`.
+    pub fn flow_factors<V: Into<ValueSchema> + Clone>(self, nodes: &[&str], factors: &[V]) -> Self {
+        let factors: Vec<ValueSchema> = factors.iter().cloned().map(Into::into).collect();
+        self.apply(|model| {
+            let nodes = nodes
+                .iter()
+                .map(|n| model.get_node_by_name(n))
+                .collect::<Result<Vec<_>, _>>()?;
+            let factors = factors
+                .into_iter()
+                .map(|f| constraint_value(model, f))
+                .collect::<Result<Vec<_>, _>>()?;
+            model.add_flow_factors(&nodes, factors)
+        })
+    }
+
+    pub fn parameter<P: _Parameter + 'static>(self, parameter: P) -> Self {
+        self.apply(|model| model.add_parameter(Box::new(parameter)).map(|_| ()))
+    }
+
+    /// Add a `ConstantParameter`.
+    pub fn constant(self, name: &str, value: f64) -> Self {
+        self.parameter(ConstantParameter::new(name, value))
+    }
+
+    /// Add an `AggregatedParameter` of the parameters with the names `parameters`.
+    pub fn aggregated(self, name: &str, parameters: &[&str], agg_func: AggFunc) -> Self {
+        self.apply(|model| {
+            let parameters = parameters
+                .iter()
+                .map(|p| model.get_parameter_by_name(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            let parameter = AggregatedParameter::new(name, parameters, agg_func);
+            model.add_parameter(Box::new(parameter)).map(|_| ())
+        })
+    }
+
+    pub fn recorder<R: _Recorder + 'static>(self, recorder: R) -> Self {
+        self.apply(|model| model.add_recorder(Box::new(recorder)).map(|_| ()))
+    }
+
+    /// Add a scenario group of `size` members.
+    pub fn scenario(self, name: &str, size: usize) -> Self {
+        self.apply(|model| model.add_scenario(name, size))
+    }
+
+    pub fn build(self) -> Result<Model, PywrError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.model),
+        }
+    }
+}
+
+fn constraint_value(model: &Model, value: ValueSchema) -> Result<ConstraintValue, PywrError> {
+    match value {
+        ValueSchema::Constant(v) => Ok(ConstraintValue::Scalar(v)),
+        ValueSchema::Parameter(name) => Ok(ConstraintValue::Parameter(model.get_parameter_by_name(&name)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_builder() {
+        let model = ModelBuilder::new()
+            .input("inflow")
+            .link("river")
+            .output("demand")
+            .connect("inflow", "river")
+            .connect("river", "demand")
+            .max_flow("inflow", 15.0)
+            .constant("demand", 10.0)
+            .max_flow("demand", "demand")
+            .cost("demand", -10.0)
+            .build()
+            .unwrap();
+        assert_eq!(model.nodes.len(), 3);
+        assert_eq!(model.edges.len(), 2);
+        assert!(model.validate().is_empty());
+
+        let result = ModelBuilder::new()
+            .input("inflow")
+            .max_flow("inflow", "missing")
+            .input("inflow")
+            .build();
+        // Only the first error is returned.
+        assert_eq!(result.err(), Some(PywrError::ParameterNotFound("missing".to_string())));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::scenario::ScenarioIndex;
+use crate::state::NetworkState;
+use crate::timestep::Timestep;
+use crate::PywrError;
+
+/// Called before each timestep is solved, with the state of each scenario at the end of the
+/// previous timestep.
+///
+/// The states may be changed before they are solved (e.g. to set the volumes of storage nodes
+/// from a coupled model), but the flows are only read. Returning an error stops the run.
+pub trait BeforeTimestepCallback: Send + Sync {
+    fn before_timestep(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        states: &mut [NetworkState],
+    ) -> Result<(), PywrError>;
+}
+
+impl<F> BeforeTimestepCallback for F
+where
+    F: FnMut(&Timestep, &[ScenarioIndex], &mut [NetworkState]) -> Result<(), PywrError> + Send + Sync,
+{
+    fn before_timestep(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        states: &mut [NetworkState],
+    ) -> Result<(), PywrError> {
+        self(timestep, scenario_indices, states)
+    }
+}
+
+/// Called after each timestep is solved, with the state of each scenario. Returning an error
+/// stops the run.
+pub trait AfterTimestepCallback: Send + Sync {
+    fn after_timestep(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        states: &[NetworkState],
+    ) -> Result<(), PywrError>;
+}
+
+impl<F> AfterTimestepCallback for F
+where
+    F: FnMut(&Timestep, &[ScenarioIndex], &[NetworkState]) -> Result<(), PywrError> + Send + Sync,
+{
+    fn after_timestep(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        states: &[NetworkState],
+    ) -> Result<(), PywrError> {
+        self(timestep, scenario_indices, states)
+    }
+}
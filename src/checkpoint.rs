@@ -0,0 +1,439 @@
+use crate::node::NodeIndex;
+use crate::state::{DerivedValues, FlowHistory, FlowState, NetworkState, NodeState, Precision, StorageState};
+use crate::PywrError;
+use chrono::NaiveDateTime;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const HEADER: &str = "pywr-checkpoint 1";
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// The full state of a run between two timesteps, from which the run can be resumed.
+///
+/// A checkpoint holds the state of every scenario's network, the memory each parameter keeps
+/// between timesteps and the next timestep to be solved. It is written as plain text, one item
+/// per line, so that it can be inspected and does not depend on the platform.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// The index of the next timestep to be solved.
+    pub next_timestep: usize,
+    /// The start of the next timestep, or `None` if the run was complete.
+    pub next_datetime: Option<NaiveDateTime>,
+    /// The state of each scenario, in the order of the run's scenario indices.
+    pub states: Vec<NetworkState>,
+    /// The saved memory of each parameter, in the order of the parameter indices.
+    pub parameters: Vec<Vec<f64>>,
+}
+
+impl Checkpoint {
+    /// Write the checkpoint to `path`.
+    ///
+    /// The checkpoint is written to a temporary file that then replaces `path`, so that an
+    /// earlier checkpoint is not lost if the run is interrupted while writing.
+    pub fn write(&self, path: &Path) -> Result<(), PywrError> {
+        let tmp_path = path.with_extension("tmp");
+        let file = File::create(&tmp_path).map_err(|e| PywrError::IOError(e.to_string()))?;
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|e| PywrError::IOError(e.to_string()))?;
+        drop(writer);
+        fs::rename(&tmp_path, path).map_err(|e| PywrError::IOError(e.to_string()))
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        match &self.next_datetime {
+            Some(dt) => writeln!(writer, "timestep {} {}", self.next_timestep, dt.format(DATETIME_FORMAT))?,
+            None => writeln!(writer, "timestep {} end", self.next_timestep)?,
+        }
+
+        writeln!(writer, "scenarios {}", self.states.len())?;
+        for state in &self.states {
+            writeln!(writer, "nodes {}", state.node_states().len())?;
+            for node_state in state.node_states() {
+                match node_state {
+                    NodeState::Flow(s) => writeln!(writer, "flow {} {}", s.in_flow, s.out_flow)?,
+                    NodeState::Storage(s) => {
+                        writeln!(writer, "storage {} {} {}", s.volume, s.flows.in_flow, s.flows.out_flow)?
+                    }
+                }
+            }
+            write_values(writer, "edges", &state.edge_flows())?;
+            write_values(writer, "duals", state.node_duals())?;
+            write_values(writer, "reduced_costs", state.edge_reduced_costs())?;
+            writeln!(writer, "histories {}", state.flow_histories().len())?;
+            for history in state.flow_histories() {
+                let flow = if history.in_flow { "in" } else { "out" };
+                write!(
+                    writer,
+                    "history {} {} {} {} {}",
+                    history.node_index,
+                    flow,
+                    history.length,
+                    history.precision().name(),
+                    history.len()
+                )?;
+                for flow in history.iter() {
+                    write!(writer, " {}", flow)?;
+                }
+                writeln!(writer)?;
+            }
+            // Values that are not defined for a node are written as NaN.
+            let derived = state.derived_values();
+            let proportional_volumes: Vec<f64> = derived
+                .iter()
+                .map(|d| d.proportional_volume.unwrap_or(f64::NAN))
+                .collect();
+            let deficits: Vec<f64> = derived.iter().map(|d| d.deficit.unwrap_or(f64::NAN)).collect();
+            write_values(writer, "proportional_volumes", &proportional_volumes)?;
+            write_values(writer, "deficits", &deficits)?;
+        }
+
+        writeln!(writer, "parameters {}", self.parameters.len())?;
+        for values in &self.parameters {
+            write_values(writer, "parameter", values)?;
+        }
+        Ok(())
+    }
+
+    /// Read a checkpoint written by `write` from `path`.
+    pub fn read(path: &Path) -> Result<Self, PywrError> {
+        let file = File::open(path).map_err(|e| PywrError::IOError(e.to_string()))?;
+        let mut lines = Lines::new(BufReader::new(file))?;
+
+        if lines.next()?.join(" ") != HEADER {
+            return Err(lines.error("not a checkpoint file"));
+        }
+
+        let fields = lines.expect("timestep", 2)?;
+        let next_timestep = lines.parse(&fields[0])?;
+        let next_datetime = match fields[1].as_str() {
+            "end" => None,
+            dt => Some(NaiveDateTime::parse_from_str(dt, DATETIME_FORMAT).map_err(|e| lines.error(&e.to_string()))?),
+        };
+
+        let fields = lines.expect("scenarios", 1)?;
+        let count: usize = lines.parse(&fields[0])?;
+        let mut states = Vec::with_capacity(count);
+        for _ in 0..count {
+            let fields = lines.expect("nodes", 1)?;
+            let nodes: usize = lines.parse(&fields[0])?;
+            let mut node_states = Vec::with_capacity(nodes);
+            for _ in 0..nodes {
+                let fields = lines.next()?;
+                let node_state = match (fields[0].as_str(), fields.len()) {
+                    ("flow", 3) => NodeState::Flow(FlowState {
+                        in_flow: lines.parse(&fields[1])?,
+                        out_flow: lines.parse(&fields[2])?,
+                    }),
+                    ("storage", 4) => {
+                        // The start volume of the saved timestep is not needed to resume from it.
+                        let volume = lines.parse(&fields[1])?;
+                        NodeState::Storage(StorageState {
+                            volume,
+                            start_volume: volume,
+                            flows: FlowState {
+                                in_flow: lines.parse(&fields[2])?,
+                                out_flow: lines.parse(&fields[3])?,
+                            },
+                        })
+                    }
+                    _ => return Err(lines.error("expected a flow or storage node state")),
+                };
+                node_states.push(node_state);
+            }
+            let edge_flows = lines.values("edges")?;
+            let node_duals = lines.values("duals")?;
+            let edge_reduced_costs = lines.values("reduced_costs")?;
+
+            let fields = lines.expect("histories", 1)?;
+            let histories: usize = lines.parse(&fields[0])?;
+            let mut flow_histories = Vec::with_capacity(histories);
+            for _ in 0..histories {
+                let fields = lines.next()?;
+                if fields[0] != "history" || fields.len() < 6 {
+                    return Err(lines.error("expected a flow history"));
+                }
+                let in_flow = match fields[2].as_str() {
+                    "in" => true,
+                    "out" => false,
+                    _ => return Err(lines.error("expected the flow of a history to be `in` or `out`")),
+                };
+                let precision = Precision::from_str(&fields[4])
+                    .map_err(|_| lines.error("expected a precision of `f64` or `f32`"))?;
+                let mut history = FlowHistory::new(
+                    NodeIndex(lines.parse(&fields[1])?),
+                    in_flow,
+                    lines.parse(&fields[3])?,
+                    precision,
+                );
+                let count: usize = lines.parse(&fields[5])?;
+                if fields.len() != count + 6 {
+                    return Err(lines.error(&format!("expected {} flows in the history", count)));
+                }
+                for flow in &fields[6..] {
+                    history.push_oldest(lines.parse(flow)?);
+                }
+                flow_histories.push(history);
+            }
+
+            let proportional_volumes = lines.values("proportional_volumes")?;
+            let deficits = lines.values("deficits")?;
+            if proportional_volumes.len() != deficits.len() {
+                return Err(lines.error("expected as many deficits as proportional volumes"));
+            }
+            let defined = |v: f64| if v.is_nan() { None } else { Some(v) };
+            let derived_values = proportional_volumes
+                .into_iter()
+                .zip(deficits)
+                .map(|(p, d)| DerivedValues {
+                    proportional_volume: defined(p),
+                    deficit: defined(d),
+                })
+                .collect();
+
+            states.push(NetworkState::from_parts(
+                node_states,
+                &edge_flows,
+                node_duals,
+                edge_reduced_costs,
+                flow_histories,
+                derived_values,
+            ));
+        }
+
+        let fields = lines.expect("parameters", 1)?;
+        let count: usize = lines.parse(&fields[0])?;
+        let mut parameters = Vec::with_capacity(count);
+        for _ in 0..count {
+            parameters.push(lines.values("parameter")?);
+        }
+
+        Ok(Self {
+            next_timestep,
+            next_datetime,
+            states,
+            parameters,
+        })
+    }
+}
+
+/// The state a run starts from in place of the initial volumes of the storage nodes.
+///
+/// This allows a planning run to start from the state at the end of a previous run, e.g. the
+/// simulated reservoir levels of today. The previous run may have one scenario, whose state is
+/// used for every scenario of the new run, or the same scenarios as the new run.
+#[derive(Clone, Debug)]
+pub enum InitialState {
+    /// The network states and parameter memory saved in a checkpoint.
+    Checkpoint(Checkpoint),
+    /// The volume of storage nodes, by node name, for each scenario label.
+    Volumes(Vec<(String, Vec<(String, f64)>)>),
+}
+
+impl InitialState {
+    /// The state saved in the checkpoint at `path`.
+    pub fn from_checkpoint(path: &Path) -> Result<Self, PywrError> {
+        Ok(Self::Checkpoint(Checkpoint::read(path)?))
+    }
+
+    /// The values of the nodes at the end of `date` in a results file written by the CSV recorder.
+    ///
+    /// If `date` is not given the last date in the file is used. Only the volumes of storage nodes
+    /// are taken from the results; the values of other nodes are ignored.
+    pub fn from_csv(path: &Path, date: Option<&str>) -> Result<Self, PywrError> {
+        let contents = fs::read_to_string(path).map_err(|e| PywrError::CSVError(e.to_string()))?;
+        let mut lines = contents.lines().filter(|l| !l.starts_with('#') && !l.trim().is_empty());
+
+        let header: Vec<&str> = match lines.next() {
+            Some(l) => l.split(',').collect(),
+            None => return Err(PywrError::InvalidInitialState("the results file is empty".to_string())),
+        };
+        if header.len() < 2 || header[0] != "date" || header[1] != "scenario" {
+            return Err(PywrError::InvalidInitialState(
+                "the results file must start with date and scenario columns".to_string(),
+            ));
+        }
+        let rows: Vec<Vec<&str>> = lines.map(|l| l.split(',').collect()).collect();
+
+        let date = match (date, rows.last()) {
+            (Some(date), _) => date,
+            (None, Some(row)) => row[0],
+            (None, None) => {
+                return Err(PywrError::InvalidInitialState(
+                    "the results file has no rows".to_string(),
+                ))
+            }
+        };
+
+        let mut volumes = Vec::new();
+        for row in rows.iter().filter(|r| r[0] == date) {
+            if row.len() != header.len() {
+                return Err(PywrError::InvalidInitialState(format!(
+                    "a row of {} has {} values but there are {} columns",
+                    date,
+                    row.len(),
+                    header.len()
+                )));
+            }
+            let values = header[2..]
+                .iter()
+                .zip(&row[2..])
+                .map(|(name, value)| match value.parse() {
+                    Ok(v) => Ok((name.to_string(), v)),
+                    Err(_) => Err(PywrError::InvalidInitialState(format!(
+                        "invalid value `{}` of {}",
+                        value, name
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            volumes.push((row[1].to_string(), values));
+        }
+
+        if volumes.is_empty() {
+            return Err(PywrError::InvalidInitialState(format!(
+                "the results file has no rows for {}",
+                date
+            )));
+        }
+        Ok(Self::Volumes(volumes))
+    }
+}
+
+/// Write a line of `key`, the number of values and then the values themselves.
+fn write_values<W: Write>(writer: &mut W, key: &str, values: &[f64]) -> std::io::Result<()> {
+    write!(writer, "{} {}", key, values.len())?;
+    for v in values {
+        write!(writer, " {}", v)?;
+    }
+    writeln!(writer)
+}
+
+/// The lines of a checkpoint file, split into whitespace separated fields.
+struct Lines {
+    lines: Vec<String>,
+    line: usize,
+}
+
+impl Lines {
+    fn new<R: BufRead>(reader: R) -> Result<Self, PywrError> {
+        let lines = reader
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PywrError::IOError(e.to_string()))?;
+        Ok(Self { lines, line: 0 })
+    }
+
+    fn error(&self, reason: &str) -> PywrError {
+        PywrError::InvalidCheckpoint {
+            line: self.line,
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Return the fields of the next non-empty line.
+    fn next(&mut self) -> Result<Vec<String>, PywrError> {
+        loop {
+            self.line += 1;
+            match self.lines.get(self.line - 1) {
+                Some(l) if l.trim().is_empty() => continue,
+                Some(l) => return Ok(l.split_whitespace().map(|f| f.to_string()).collect()),
+                None => return Err(self.error("unexpected end of file")),
+            }
+        }
+    }
+
+    /// Return the fields after `key` of the next line, which must have `count` of them.
+    fn expect(&mut self, key: &str, count: usize) -> Result<Vec<String>, PywrError> {
+        let fields = self.next()?;
+        if fields[0] != key || fields.len() != count + 1 {
+            return Err(self.error(&format!("expected `{}` with {} values", key, count)));
+        }
+        Ok(fields[1..].to_vec())
+    }
+
+    /// Return the values of the next line, which starts with `key` and the number of values.
+    fn values(&mut self, key: &str) -> Result<Vec<f64>, PywrError> {
+        let fields = self.next()?;
+        if fields[0] != key || fields.len() < 2 {
+            return Err(self.error(&format!("expected `{}` values", key)));
+        }
+        let count: usize = self.parse(&fields[1])?;
+        if fields.len() != count + 2 {
+            return Err(self.error(&format!("expected {} `{}` values", count, key)));
+        }
+        fields[2..].iter().map(|v| self.parse(v)).collect()
+    }
+
+    fn parse<T: std::str::FromStr>(&self, field: &str) -> Result<T, PywrError> {
+        field
+            .parse()
+            .map_err(|_| self.error(&format!("invalid value `{}`", field)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::EdgeIndex;
+    use crate::state::EdgeState;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_write_read() {
+        let mut state = NetworkState::new();
+        state.push_node_state(NodeState::new_flow_state());
+        state.push_node_state(NodeState::new_storage_state(1.0 / 3.0));
+        state.push_edge_state(EdgeState::new());
+        state.set_node_duals(vec![-1.5, f64::INFINITY]);
+        state.push_flow_history(FlowHistory::new(NodeIndex(0), false, 3, Precision::Double));
+        state.push_flow_history(FlowHistory::new(NodeIndex(1), true, 2, Precision::Single));
+        state.update_flow_histories().unwrap();
+        state.derived_values_mut().push(DerivedValues {
+            proportional_volume: None,
+            deficit: Some(2.5),
+        });
+        state.derived_values_mut().push(DerivedValues {
+            proportional_volume: Some(0.25),
+            deficit: None,
+        });
+
+        let checkpoint = Checkpoint {
+            next_timestep: 3,
+            next_datetime: Some(NaiveDateTime::parse_from_str("2020-01-04T00:00:00", DATETIME_FORMAT).unwrap()),
+            states: vec![state.clone(), state],
+            parameters: vec![vec![], vec![0.1, 2.0]],
+        };
+
+        let dir = TempDir::new("checkpoint").unwrap();
+        let path = dir.path().join("run.checkpoint");
+        checkpoint.write(&path).unwrap();
+        let read = Checkpoint::read(&path).unwrap();
+
+        assert_eq!(read.next_timestep, 3);
+        assert_eq!(read.next_datetime, checkpoint.next_datetime);
+        assert_eq!(read.states.len(), 2);
+        assert_eq!(read.states[1].get_node_volume(NodeIndex(1)).unwrap(), 1.0 / 3.0);
+        assert_eq!(read.states[1].get_edge_flow(EdgeIndex(0)).unwrap(), 0.0);
+        assert_eq!(read.states[1].get_node_dual(NodeIndex(1)).unwrap(), f64::INFINITY);
+        assert_eq!(
+            read.states[1].get_edge_reduced_cost(EdgeIndex(0)),
+            Err(PywrError::DualsNotAvailable)
+        );
+        assert_eq!(read.states[0].flow_histories(), checkpoint.states[0].flow_histories());
+        assert_eq!(read.states[0].derived_values(), checkpoint.states[0].derived_values());
+        assert_eq!(read.parameters, checkpoint.parameters);
+
+        std::fs::write(&path, "pywr-checkpoint 1\ntimestep 3 end\nscenarios x\n").unwrap();
+        assert_eq!(
+            Checkpoint::read(&path).unwrap_err(),
+            PywrError::InvalidCheckpoint {
+                line: 3,
+                reason: "invalid value `x`".to_string()
+            }
+        );
+    }
+}
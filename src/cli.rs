@@ -0,0 +1,307 @@
+//! The `pywr` command line interface, to run, check and draw models in model files (see
+//! `schema`) without writing any code.
+use crate::model::Model;
+use crate::schema::ModelSchema;
+use crate::solvers::{available_solvers, solver_by_name, SolverSettings};
+use crate::timestep::Timestepper;
+use crate::PywrError;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "Usage:
+    pywr run <model.json> [--solver <name>] [--output-dir <dir>] [--slice <scenario>:<start>..<end>]...
+    pywr validate <model.json>
+    pywr export-dot <model.json> [--output <file.dot>]
+
+Commands:
+    run         Run the model, saving the outputs of its recorders relative to the output directory
+                (by default that of the model file) and printing their aggregated values.
+    validate    Check the model for problems, failing if any of them are errors.
+    export-dot  Write the network as a Graphviz DOT graph, to standard output by default.";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Run {
+        model: PathBuf,
+        solver: Option<String>,
+        output_dir: Option<PathBuf>,
+        /// The scenario groups to run only some members of, with the range of their members.
+        slices: Vec<(String, Range<usize>)>,
+    },
+    Validate {
+        model: PathBuf,
+    },
+    ExportDot {
+        model: PathBuf,
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    /// The arguments are not valid; the usage is shown with the reason.
+    Usage(String),
+    Pywr(PywrError),
+    /// The model has `errors` problems that are errors, listed with any warnings in `report`.
+    InvalidModel {
+        errors: usize,
+        report: String,
+    },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage(reason) => write!(f, "{}\n\n{}", reason, USAGE),
+            Self::Pywr(error) => write!(f, "{}", error),
+            Self::InvalidModel { errors: 1, report } => write!(f, "{}the model has 1 error", report),
+            Self::InvalidModel { errors, report } => write!(f, "{}the model has {} errors", report, errors),
+        }
+    }
+}
+
+impl From<PywrError> for CliError {
+    fn from(error: PywrError) -> Self {
+        Self::Pywr(error)
+    }
+}
+
+impl CliError {
+    /// The exit code of the process: 2 for invalid arguments and 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl Command {
+    /// Parse the arguments following the name of the program.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, CliError> {
+        let usage = |reason: String| CliError::Usage(reason);
+        let mut args = args.into_iter();
+        let command = args.next().ok_or_else(|| usage("no command given".to_string()))?;
+
+        let mut model = None;
+        let mut options: Vec<(String, String)> = Vec::new();
+        while let Some(arg) = args.next() {
+            if arg.starts_with("--") {
+                let value = args
+                    .next()
+                    .ok_or_else(|| usage(format!("option `{}` has no value", arg)))?;
+                options.push((arg, value));
+            } else if model.is_none() {
+                model = Some(PathBuf::from(arg));
+            } else {
+                return Err(usage(format!("unexpected argument `{}`", arg)));
+            }
+        }
+        let model = model.ok_or_else(|| usage("no model file given".to_string()))?;
+
+        let allowed: &[&str] = match command.as_str() {
+            "run" => &["--solver", "--output-dir", "--slice"],
+            "validate" => &[],
+            "export-dot" => &["--output"],
+            _ => return Err(usage(format!("unrecognised command `{}`", command))),
+        };
+        if let Some((option, _)) = options.iter().find(|(o, _)| !allowed.contains(&o.as_str())) {
+            return Err(usage(format!("unrecognised option `{}` for `{}`", option, command)));
+        }
+        let last = |name: &str| options.iter().rev().find(|(o, _)| o == name).map(|(_, v)| v.clone());
+
+        Ok(match command.as_str() {
+            "run" => Command::Run {
+                model,
+                solver: last("--solver"),
+                output_dir: last("--output-dir").map(PathBuf::from),
+                slices: options
+                    .iter()
+                    .filter(|(o, _)| o == "--slice")
+                    .map(|(_, v)| parse_slice(v))
+                    .collect::<Result<_, _>>()?,
+            },
+            "validate" => Command::Validate { model },
+            _ => Command::ExportDot {
+                model,
+                output: last("--output").map(PathBuf::from),
+            },
+        })
+    }
+
+    /// Carry out the command, returning what it prints to standard output.
+    pub fn execute(&self) -> Result<String, CliError> {
+        match self {
+            Self::Run {
+                model,
+                solver,
+                output_dir,
+                slices,
+            } => {
+                let schema = ModelSchema::read(model)?;
+                let directory = match output_dir {
+                    Some(dir) => {
+                        fs::create_dir_all(dir).map_err(|e| PywrError::IOError(e.to_string()))?;
+                        dir.as_path()
+                    }
+                    None => model.parent().unwrap_or_else(|| Path::new("")),
+                };
+                let (mut built, timestepper) = schema.build(directory)?;
+                for (name, members) in slices {
+                    built.slice_scenario(name, members.clone())?;
+                }
+                // The first available solver is the best of those in this build.
+                let solver = match solver {
+                    Some(name) => solver_by_name(name)?,
+                    None => solver_by_name(available_solvers()[0])?,
+                };
+                built.run(timestepper, solver.as_ref(), &SolverSettings::default())?;
+
+                let mut output = String::new();
+                for recorder in &schema.recorders {
+                    if let Ok(value) = built.get_aggregated_value(&recorder.name) {
+                        output.push_str(&format!("{}: {}\n", recorder.name, value));
+                    }
+                }
+                Ok(output)
+            }
+            Self::Validate { model } => {
+                let (built, _) = Self::build(model)?;
+                let issues = built.validate();
+                let mut report = String::new();
+                for issue in &issues {
+                    report.push_str(&format!("{:?}: {}\n", issue.severity(), issue));
+                }
+                match issues.iter().filter(|i| i.is_error()).count() {
+                    0 => Ok(report),
+                    errors => Err(CliError::InvalidModel { errors, report }),
+                }
+            }
+            Self::ExportDot { model, output } => {
+                let (built, _) = Self::build(model)?;
+                match output {
+                    Some(path) => {
+                        fs::write(path, built.to_dot()).map_err(|e| PywrError::IOError(e.to_string()))?;
+                        Ok(String::new())
+                    }
+                    None => Ok(built.to_dot()),
+                }
+            }
+        }
+    }
+
+    fn build(model: &Path) -> Result<(Model, Timestepper), CliError> {
+        Ok(Model::from_schema(model)?)
+    }
+}
+
+/// Parse a slice of a scenario group, as `<name>:<start>..<end>`.
+fn parse_slice(value: &str) -> Result<(String, Range<usize>), CliError> {
+    let invalid = || {
+        CliError::Usage(format!(
+            "invalid slice `{}`, expected `<scenario>:<start>..<end>`",
+            value
+        ))
+    };
+    let (name, range) = value.rsplit_once(':').ok_or_else(invalid)?;
+    let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+    let start = start.parse().map_err(|_| invalid())?;
+    let end = end.parse().map_err(|_| invalid())?;
+    Ok((name.to_string(), start..end))
+}
+
+/// Run the command given by `args`, printing its output or any error, and return the exit code.
+pub fn main<I: IntoIterator<Item = String>>(args: I) -> i32 {
+    match Command::parse(args).and_then(|command| command.execute()) {
+        Ok(output) => {
+            print!("{}", output);
+            0
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            error.exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    const MODEL: &str = r#"{
+        "timestepper": {"start": "2020-01-01", "end": "2020-01-10"},
+        "scenarios": [{"name": "demand", "size": 3}],
+        "nodes": [
+            {"name": "supply", "type": "input", "max_flow": 15},
+            {"name": "demand", "type": "output", "max_flow": 20, "cost": -10}
+        ],
+        "edges": [{"from": "supply", "to": "demand"}],
+        "recorders": [
+            {"name": "outputs", "type": "csv", "path": "outputs.csv"},
+            {"name": "deficit", "type": "aggregated", "metric": "node_deficit", "component": "demand",
+             "temporal_agg_func": "sum", "scenario_agg_func": "max"}
+        ]
+    }"#;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Command::parse(args("run model.json --solver simplex --slice demand:1..3")).unwrap(),
+            Command::Run {
+                model: PathBuf::from("model.json"),
+                solver: Some("simplex".to_string()),
+                output_dir: None,
+                slices: vec![("demand".to_string(), 1..3)],
+            }
+        );
+        assert!(matches!(
+            Command::parse(args("draw model.json")),
+            Err(CliError::Usage(_))
+        ));
+        assert!(matches!(
+            Command::parse(args("validate model.json --solver clp")),
+            Err(CliError::Usage(_))
+        ));
+        assert!(matches!(
+            Command::parse(args("run model.json --slice demand")),
+            Err(CliError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_execute() {
+        let dir = TempDir::new("cli").unwrap();
+        let model = dir.path().join("model.json");
+        fs::write(&model, MODEL).unwrap();
+        let model = model.to_str().unwrap();
+
+        let outputs = dir.path().join("outputs");
+        let command = format!(
+            "run {} --solver simplex --output-dir {} --slice demand:0..2",
+            model,
+            outputs.to_str().unwrap()
+        );
+        let output = Command::parse(args(&command)).unwrap().execute().unwrap();
+        assert_eq!(output, "deficit: 50\n");
+        assert!(outputs.join("outputs.csv").exists());
+
+        let output = Command::parse(args(&format!("validate {}", model)))
+            .unwrap()
+            .execute()
+            .unwrap();
+        assert_eq!(output, "");
+
+        let output = Command::parse(args(&format!("export-dot {}", model)))
+            .unwrap()
+            .execute()
+            .unwrap();
+        assert!(output.starts_with("digraph"));
+    }
+}
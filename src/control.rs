@@ -0,0 +1,105 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct RunControl {
+    paused: bool,
+    cancelled: bool,
+}
+
+/// A handle to pause, resume or cancel a run from another thread, e.g. a GUI or service frontend.
+///
+/// The handle is cheap to clone and all clones control the same runs. Requests are acted on
+/// between timesteps: a paused run waits before its next timestep until it is resumed or
+/// cancelled, and a cancelled run stops and finalises its recorders with the timesteps solved
+/// so far. A cancellation applies to the next run only, after which the handle may be reused.
+#[derive(Clone, Debug, Default)]
+pub struct RunHandle {
+    control: Arc<(Mutex<RunControl>, Condvar)>,
+}
+
+impl RunHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pause the run before its next timestep.
+    pub fn pause(&self) {
+        self.update(|c| c.paused = true);
+    }
+
+    /// Resume a paused run.
+    pub fn resume(&self) {
+        self.update(|c| c.paused = false);
+    }
+
+    /// Stop the run before its next timestep, even if it is paused.
+    pub fn cancel(&self) {
+        self.update(|c| c.cancelled = true);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RunControl> {
+        // The lock is never held while anything can panic, but recover the state if it was.
+        self.control.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update<F: FnOnce(&mut RunControl)>(&self, f: F) {
+        f(&mut self.lock());
+        self.control.1.notify_all();
+    }
+
+    /// Wait while the run is paused, and return true if it should be cancelled.
+    pub(crate) fn wait(&self) -> bool {
+        let guard = self.lock();
+        let guard = self
+            .control
+            .1
+            .wait_while(guard, |c| c.paused && !c.cancelled)
+            .unwrap_or_else(|e| e.into_inner());
+        guard.cancelled
+    }
+
+    /// Clear the cancellation once a run has stopped, returning whether it was cancelled.
+    pub(crate) fn take_cancelled(&self) -> bool {
+        let mut guard = self.lock();
+        std::mem::replace(&mut guard.cancelled, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunHandle;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    /// Test a paused wait is released by another thread
+    fn test_pause_resume() {
+        let handle = RunHandle::new();
+        assert!(!handle.wait());
+
+        handle.pause();
+        assert!(handle.is_paused());
+        let other = handle.clone();
+        let resumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            other.resume();
+        });
+        assert!(!handle.wait());
+        assert!(!handle.is_paused());
+        resumer.join().unwrap();
+
+        handle.pause();
+        handle.cancel();
+        assert!(handle.wait());
+        assert!(handle.take_cancelled());
+        assert!(!handle.is_cancelled());
+    }
+}
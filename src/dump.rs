@@ -0,0 +1,234 @@
+use crate::json::{json_number, json_string, Json};
+use crate::PywrError;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The state of one scenario of a run after a timestep, for debugging.
+///
+/// A dump holds the flows, volumes, duals and derived values of every node, the flow and reduced
+/// cost of every edge and the value of every parameter, each keyed by a path of names such as
+/// `node/reservoir/volume`. It is written as JSON, one value per line, so that the dumps of two
+/// runs (e.g. of two versions of a model, or with two solvers) can be compared with `diff` to find
+/// where they diverge.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateDump {
+    /// The index of the timestep, or `None` for the initial state of a run.
+    pub timestep: Option<usize>,
+    /// The start of the timestep as written to outputs, or `None` for the initial state of a run.
+    pub date: Option<String>,
+    /// The label of the scenario.
+    pub scenario: String,
+    /// The values of the state, in the order of the nodes, edges and parameters of the model.
+    ///
+    /// JSON has no representation of non-finite numbers, so these are read back as NaN.
+    pub values: Vec<(String, f64)>,
+}
+
+/// A value that differs between two state dumps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateDifference {
+    pub key: String,
+    /// The value in the first dump, or `None` if it is not in it.
+    pub left: Option<f64>,
+    /// The value in the second dump, or `None` if it is not in it.
+    pub right: Option<f64>,
+}
+
+impl fmt::Display for StateDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |v: Option<f64>| match v {
+            Some(v) => v.to_string(),
+            None => "missing".to_string(),
+        };
+        write!(f, "{}: {} != {}", self.key, value(self.left), value(self.right))
+    }
+}
+
+impl StateDump {
+    /// Write the dump as JSON to `path`.
+    pub fn write(&self, path: &Path) -> Result<(), PywrError> {
+        fs::write(path, self.to_json()).map_err(|e| PywrError::IOError(e.to_string()))
+    }
+
+    /// Read a dump written by `write` from `path`.
+    pub fn read(path: &Path) -> Result<Self, PywrError> {
+        let contents = fs::read_to_string(path).map_err(|e| PywrError::IOError(e.to_string()))?;
+        Self::from_json(&contents)
+    }
+
+    pub fn to_json(&self) -> String {
+        let optional = |v: Option<String>| v.unwrap_or_else(|| "null".to_string());
+        let values: Vec<String> = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("    {}: {}", json_string(key), json_number(*value)))
+            .collect();
+        format!(
+            "{{\n  \"timestep\": {},\n  \"date\": {},\n  \"scenario\": {},\n  \"values\": {{\n{}\n  }}\n}}\n",
+            optional(self.timestep.map(|t| t.to_string())),
+            optional(self.date.as_deref().map(json_string)),
+            json_string(&self.scenario),
+            values.join(",\n")
+        )
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, PywrError> {
+        let json = Json::parse(json).map_err(|e| invalid(&e))?;
+        if json.as_object().is_none() {
+            return Err(invalid("expected an object"));
+        }
+        let field = |key: &str| match json.get(key) {
+            Some(value) => Ok(value),
+            None => Err(invalid(&format!("missing `{}`", key))),
+        };
+
+        let timestep = match field("timestep")? {
+            Json::Null => None,
+            Json::Number(t) if *t >= 0.0 && t.fract() == 0.0 => Some(*t as usize),
+            _ => return Err(invalid("expected `timestep` to be an index or null")),
+        };
+        let date = match field("date")? {
+            Json::Null => None,
+            Json::String(d) => Some(d.clone()),
+            _ => return Err(invalid("expected `date` to be a string or null")),
+        };
+        let scenario = match field("scenario")? {
+            Json::String(s) => s.clone(),
+            _ => return Err(invalid("expected `scenario` to be a string")),
+        };
+        let values = match field("values")? {
+            Json::Object(values) => values
+                .iter()
+                .map(|(key, value)| match value {
+                    Json::Number(v) => Ok((key.clone(), *v)),
+                    Json::Null => Ok((key.clone(), f64::NAN)),
+                    _ => Err(invalid(&format!("expected `{}` to be a number or null", key))),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(invalid("expected `values` to be an object")),
+        };
+
+        Ok(Self {
+            timestep,
+            date,
+            scenario,
+            values,
+        })
+    }
+
+    /// The values that differ by more than `tolerance` between this dump and `other`, and those
+    /// in only one of them.
+    ///
+    /// The differences are in the order of this dump's values followed by those only in `other`,
+    /// so the first difference is at the first node, edge or parameter that diverges.
+    pub fn diff(&self, other: &StateDump, tolerance: f64) -> Vec<StateDifference> {
+        let others: HashMap<&str, f64> = other.values.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        let keys: HashSet<&str> = self.values.iter().map(|(k, _)| k.as_str()).collect();
+
+        let mut differences = Vec::new();
+        for (key, value) in &self.values {
+            match others.get(key.as_str()) {
+                Some(other) if same(*value, *other, tolerance) => {}
+                other => differences.push(StateDifference {
+                    key: key.clone(),
+                    left: Some(*value),
+                    right: other.copied(),
+                }),
+            }
+        }
+        for (key, value) in other.values.iter().filter(|(k, _)| !keys.contains(k.as_str())) {
+            differences.push(StateDifference {
+                key: key.clone(),
+                left: None,
+                right: Some(*value),
+            });
+        }
+        differences
+    }
+}
+
+/// Values are the same if they are within `tolerance` of each other or both NaN.
+fn same(left: f64, right: f64, tolerance: f64) -> bool {
+    left == right || (left - right).abs() <= tolerance || (left.is_nan() && right.is_nan())
+}
+
+fn invalid(reason: &str) -> PywrError {
+    PywrError::InvalidStateDump(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump(values: &[(&str, f64)]) -> StateDump {
+        StateDump {
+            timestep: Some(2),
+            date: Some("2020-01-03".to_string()),
+            scenario: "wet/\"high\"".to_string(),
+            values: values.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let original = dump(&[
+            ("node/input/out_flow", 10.0),
+            ("node/reservoir/volume", 1.0 / 3.0),
+            ("node/a\\b/dual", -2.5e-7),
+        ]);
+        assert_eq!(StateDump::from_json(&original.to_json()).unwrap(), original);
+
+        let initial = StateDump {
+            timestep: None,
+            date: None,
+            scenario: "0".to_string(),
+            values: vec![("edge/input/link/reduced_cost".to_string(), f64::INFINITY)],
+        };
+        let read = StateDump::from_json(&initial.to_json()).unwrap();
+        assert_eq!(read.timestep, None);
+        assert_eq!(read.date, None);
+        assert!(read.values[0].1.is_nan());
+
+        assert!(matches!(
+            StateDump::from_json("{\"timestep\": 1}"),
+            Err(PywrError::InvalidStateDump(_))
+        ));
+        assert!(matches!(
+            StateDump::from_json("{\"values\": [1]}"),
+            Err(PywrError::InvalidStateDump(_))
+        ));
+    }
+
+    #[test]
+    fn test_diff() {
+        let left = dump(&[("a", 1.0), ("b", 2.0), ("c", f64::NAN), ("d", 4.0)]);
+        let right = dump(&[("e", 5.0), ("d", 4.0 + 1e-9), ("c", f64::NAN), ("b", 2.5)]);
+
+        let differences = left.diff(&right, 1e-6);
+        assert_eq!(
+            differences,
+            vec![
+                StateDifference {
+                    key: "a".to_string(),
+                    left: Some(1.0),
+                    right: None
+                },
+                StateDifference {
+                    key: "b".to_string(),
+                    left: Some(2.0),
+                    right: Some(2.5)
+                },
+                StateDifference {
+                    key: "e".to_string(),
+                    left: None,
+                    right: Some(5.0)
+                },
+            ]
+        );
+        assert_eq!(differences[1].to_string(), "b: 2 != 2.5");
+        assert_eq!(differences[2].to_string(), "e: missing != 5");
+        assert_eq!(left.diff(&right, 0.0).len(), 4);
+    }
+}
@@ -1,50 +1,141 @@
-use crate::node::{Node, NodeIndex};
+use crate::metric::Metric;
+use crate::node::{Constraint, ConstraintValue, FlowConstraints, Node, NodeIndex};
 use crate::PywrError;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::{Arc, RwLock};
 
-pub type EdgeIndex = usize;
-pub type EdgeRef = Rc<RefCell<_Edge>>;
+index_type!(EdgeIndex);
+pub type EdgeRef = Arc<RwLock<_Edge>>;
+
+/// What connecting two nodes that are already connected does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateEdges {
+    /// Return a `NodeConnectionAlreadyExists` error.
+    Error,
+    /// Return the existing edge.
+    Reuse,
+}
 
 #[derive(Debug, PartialEq)]
 pub struct _Edge {
     pub index: EdgeIndex,
     pub from_node: Node,
     pub to_node: Node,
+    pub flow_constraints: FlowConstraints,
+    pub cost: ConstraintValue,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Edge(EdgeRef);
 
+impl PartialEq for Edge {
+    fn eq(&self, other: &Edge) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 impl Edge {
     pub(crate) fn new(index: &EdgeIndex, from_node: &Node, to_node: &Node) -> Self {
         let edge = _Edge {
             index: *index,
             from_node: from_node.clone(),
             to_node: to_node.clone(),
+            flow_constraints: FlowConstraints::new(),
+            cost: ConstraintValue::None,
         };
-        Edge(Rc::new(RefCell::new(edge)))
+        Edge(Arc::new(RwLock::new(edge)))
     }
 
     pub fn index(&self) -> EdgeIndex {
-        self.0.borrow().index
+        self.0.read().unwrap().index
     }
 
     pub fn from_node_index(&self) -> NodeIndex {
-        self.0.borrow().from_node.index()
+        self.0.read().unwrap().from_node.index()
     }
 
     pub fn to_node_index(&self) -> NodeIndex {
-        self.0.borrow().to_node.index()
+        self.0.read().unwrap().to_node.index()
+    }
+
+    pub fn default_metric(&self) -> Metric {
+        Metric::EdgeFlow(self.index())
+    }
+
+    /// Set a flow constraint on the edge. Edges have no volume constraints.
+    pub fn set_constraint(&self, value: ConstraintValue, constraint: Constraint) -> Result<(), PywrError> {
+        let mut edge = self.0.write().unwrap();
+        match constraint {
+            Constraint::MinFlow => edge.flow_constraints.min_flow = value,
+            Constraint::MaxFlow => edge.flow_constraints.max_flow = value,
+            Constraint::MinAndMaxFlow => {
+                edge.flow_constraints.min_flow = value.clone();
+                edge.flow_constraints.max_flow = value;
+            }
+            Constraint::MinVolume | Constraint::MaxVolume => return Err(PywrError::StorageConstraintsUndefined),
+        }
+        Ok(())
+    }
+
+    /// The value of one of the edge's flow constraints. The minimum and maximum flow must be got
+    /// separately.
+    pub fn get_constraint(&self, constraint: Constraint) -> Result<ConstraintValue, PywrError> {
+        let edge = self.0.read().unwrap();
+        match constraint {
+            Constraint::MinFlow => Ok(edge.flow_constraints.min_flow.clone()),
+            Constraint::MaxFlow => Ok(edge.flow_constraints.max_flow.clone()),
+            Constraint::MinVolume | Constraint::MaxVolume => Err(PywrError::StorageConstraintsUndefined),
+            Constraint::MinAndMaxFlow => Err(PywrError::InvalidConstraintType(
+                "the minimum and maximum flow can not be got together".to_string(),
+            )),
+        }
+    }
+
+    /// Set the cost of flow along the edge, which is added to the costs of the nodes it connects.
+    pub fn set_cost(&self, value: ConstraintValue) {
+        self.0.write().unwrap().cost = value;
+    }
+
+    pub fn get_cost(&self) -> ConstraintValue {
+        self.0.read().unwrap().cost.clone()
+    }
+
+    /// The edge's cost and flow constraints, by the name of the attribute, e.g. `max_flow`.
+    pub fn attributes(&self) -> Vec<(&'static str, ConstraintValue)> {
+        let edge = self.0.read().unwrap();
+        vec![
+            ("cost", edge.cost.clone()),
+            ("min_flow", edge.flow_constraints.min_flow.clone()),
+            ("max_flow", edge.flow_constraints.max_flow.clone()),
+        ]
+    }
+
+    /// Whether either of the edge's flow constraints is defined.
+    pub fn is_constrained(&self) -> bool {
+        let edge = self.0.read().unwrap();
+        edge.flow_constraints.min_flow != ConstraintValue::None
+            || edge.flow_constraints.max_flow != ConstraintValue::None
+    }
+
+    /// Return the edge's minimum and maximum flow as resolved from the current parameter state.
+    pub(crate) fn get_current_flow_bounds(&self, parameter_states: &[f64]) -> (f64, f64) {
+        let edge = self.0.read().unwrap();
+        (
+            edge.flow_constraints.get_min_flow(parameter_states),
+            edge.flow_constraints.get_max_flow(parameter_states),
+        )
     }
 
     pub(crate) fn cost(&self, parameter_states: &[f64]) -> Result<f64, PywrError> {
-        let from_node = &self.0.borrow().from_node;
-        let to_node = &self.0.borrow().to_node;
+        let edge = self.0.read().unwrap();
 
-        let from_cost = from_node.get_outgoing_cost(parameter_states);
-        let to_cost = to_node.get_incoming_cost(parameter_states);
+        let from_cost = edge.from_node.get_outgoing_cost(parameter_states);
+        let to_cost = edge.to_node.get_incoming_cost(parameter_states);
+        let edge_cost = match &edge.cost {
+            ConstraintValue::None => 0.0,
+            ConstraintValue::Scalar(v) => *v,
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
+        };
 
-        Ok(from_cost + to_cost)
+        Ok(from_cost + to_cost + edge_cost)
     }
 }
@@ -0,0 +1,304 @@
+use crate::edge::EdgeIndex;
+use crate::model::Model;
+use crate::node::{Node, NodeIndex, NodeType};
+use crate::PywrError;
+use std::collections::VecDeque;
+
+/// A single path through the network from a source (input or storage) to a sink (output or storage).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub(crate) edges: Vec<EdgeIndex>,
+    pub(crate) nodes: Vec<NodeIndex>,
+}
+
+impl Route {
+    pub fn edges(&self) -> &[EdgeIndex] {
+        &self.edges
+    }
+
+    pub fn nodes(&self) -> &[NodeIndex] {
+        &self.nodes
+    }
+
+    pub(crate) fn first_node(&self) -> NodeIndex {
+        self.nodes[0]
+    }
+}
+
+/// The routes from `node` that follow outgoing edges through link nodes until they reach an
+/// output or storage node. Routes that would revisit a node are discarded.
+pub fn routes_from(model: &Model, node: &Node) -> Result<Vec<Route>, PywrError> {
+    let mut routes = Vec::new();
+    visit_routes(model, node, None, &mut vec![node.index()], &mut Vec::new(), &mut routes)?;
+    Ok(routes)
+}
+
+/// All of the routes from `from` to `to`, which pass only through link nodes in between.
+///
+/// Flow does not pass through an output or storage node within a timestep, so there are no
+/// routes beyond one; routes that would revisit a node are discarded.
+pub fn routes_between(model: &Model, from: &Node, to: &Node) -> Result<Vec<Route>, PywrError> {
+    let mut routes = Vec::new();
+    visit_routes(
+        model,
+        from,
+        Some(to.index()),
+        &mut vec![from.index()],
+        &mut Vec::new(),
+        &mut routes,
+    )?;
+    Ok(routes)
+}
+
+/// Follow the outgoing edges of `node`, adding the routes that end at `target`, or at any output
+/// or storage node if there is no target.
+fn visit_routes(
+    model: &Model,
+    node: &Node,
+    target: Option<NodeIndex>,
+    nodes: &mut Vec<NodeIndex>,
+    edges: &mut Vec<EdgeIndex>,
+    routes: &mut Vec<Route>,
+) -> Result<(), PywrError> {
+    for edge in node.get_outgoing_edges()? {
+        let to_node = model
+            .nodes
+            .get(*edge.to_node_index())
+            .ok_or(PywrError::NodeIndexNotFound(edge.to_node_index()))?;
+
+        if nodes.contains(&to_node.index()) {
+            // Cyclic route
+            continue;
+        }
+
+        nodes.push(to_node.index());
+        edges.push(edge.index());
+
+        let route = || Route {
+            edges: edges.clone(),
+            nodes: nodes.clone(),
+        };
+        match to_node.node_type() {
+            _ if target == Some(to_node.index()) => routes.push(route()),
+            NodeType::Output | NodeType::Storage if target.is_none() => routes.push(route()),
+            NodeType::Output | NodeType::Storage => {}
+            NodeType::Link => visit_routes(model, to_node, target, nodes, edges, routes)?,
+            NodeType::Input => return Err(PywrError::InvalidNodeConnectionToInput),
+        }
+
+        nodes.pop();
+        edges.pop();
+    }
+    Ok(())
+}
+
+/// The groups of nodes that form cycles without passing through a storage node, each in
+/// ascending order of index.
+///
+/// Flow can circulate around such a cycle within a timestep, which is unbounded if the costs of
+/// its nodes are negative. Cycles through a storage node are allowed, as it separates the flow in
+/// to it from the flow out of it.
+pub fn cycles(model: &Model) -> Vec<Vec<NodeIndex>> {
+    let successors: Vec<Vec<NodeIndex>> = model
+        .nodes
+        .iter()
+        .map(|node| match node.node_type() {
+            NodeType::Storage => Vec::new(),
+            _ => model
+                .edges
+                .iter()
+                .filter(|e| e.from_node_index() == node.index())
+                .map(|e| e.to_node_index())
+                .filter(|n| !matches!(model.nodes[**n].node_type(), NodeType::Storage))
+                .collect(),
+        })
+        .collect();
+
+    let mut search = Tarjan {
+        successors: &successors,
+        index: 0,
+        indices: vec![None; successors.len()],
+        low_links: vec![0; successors.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; successors.len()],
+        components: Vec::new(),
+    };
+    for node in 0..successors.len() {
+        if search.indices[node].is_none() {
+            search.visit(NodeIndex(node));
+        }
+    }
+
+    let mut cycles: Vec<Vec<NodeIndex>> = search
+        .components
+        .into_iter()
+        .filter(|c| c.len() > 1 || successors[*c[0]].contains(&c[0]))
+        .map(|mut c| {
+            c.sort_unstable();
+            c
+        })
+        .collect();
+    cycles.sort();
+    cycles
+}
+
+/// Tarjan's algorithm for the strongly connected components of a graph.
+struct Tarjan<'a> {
+    successors: &'a [Vec<NodeIndex>],
+    index: usize,
+    indices: Vec<Option<usize>>,
+    low_links: Vec<usize>,
+    stack: Vec<NodeIndex>,
+    on_stack: Vec<bool>,
+    components: Vec<Vec<NodeIndex>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, node: NodeIndex) {
+        self.indices[*node] = Some(self.index);
+        self.low_links[*node] = self.index;
+        self.index += 1;
+        self.stack.push(node);
+        self.on_stack[*node] = true;
+
+        for &successor in &self.successors[*node] {
+            match self.indices[*successor] {
+                None => {
+                    self.visit(successor);
+                    self.low_links[*node] = self.low_links[*node].min(self.low_links[*successor]);
+                }
+                Some(index) if self.on_stack[*successor] => {
+                    self.low_links[*node] = self.low_links[*node].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(self.low_links[*node]) == self.indices[*node] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack[*member] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+/// The groups of nodes connected to each other by edges in either direction, each in ascending
+/// order of index and in order of their first node.
+///
+/// A model of more than one component is several independent networks.
+pub fn connected_components(model: &Model) -> Vec<Vec<NodeIndex>> {
+    let mut neighbours = vec![Vec::new(); model.nodes.len()];
+    for edge in &model.edges {
+        neighbours[*edge.from_node_index()].push(edge.to_node_index());
+        neighbours[*edge.to_node_index()].push(edge.from_node_index());
+    }
+
+    let mut component_of = vec![None; model.nodes.len()];
+    let mut components = Vec::new();
+    for start in 0..model.nodes.len() {
+        if component_of[start].is_some() {
+            continue;
+        }
+        let mut component = Vec::new();
+        let mut queue = VecDeque::from(vec![NodeIndex(start)]);
+        component_of[start] = Some(components.len());
+        while let Some(node) = queue.pop_front() {
+            component.push(node);
+            for &neighbour in &neighbours[*node] {
+                if component_of[*neighbour].is_none() {
+                    component_of[*neighbour] = Some(components.len());
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        component.sort_unstable();
+        components.push(component);
+    }
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two inputs feeding a loop of links, which supplies an output and a reservoir, and a
+    /// separate input and output.
+    fn model() -> Model {
+        let mut model = Model::new();
+        let input_a = model.add_input_node("input-a").unwrap();
+        let input_b = model.add_input_node("input-b").unwrap();
+        let link_a = model.add_link_node("link-a").unwrap();
+        let link_b = model.add_link_node("link-b").unwrap();
+        let output = model.add_output_node("output").unwrap();
+        let reservoir = model.add_storage_node("reservoir", 0.0).unwrap();
+        let spring = model.add_input_node("spring").unwrap();
+        let sink = model.add_output_node("sink").unwrap();
+
+        model.connect_nodes(&input_a, &link_a).unwrap();
+        model.connect_nodes(&input_b, &link_b).unwrap();
+        model.connect_nodes(&link_a, &link_b).unwrap();
+        model.connect_nodes(&link_b, &link_a).unwrap();
+        model.connect_nodes(&link_b, &output).unwrap();
+        model.connect_nodes(&link_a, &reservoir).unwrap();
+        model.connect_nodes(&reservoir, &link_a).unwrap();
+        model.connect_nodes(&spring, &sink).unwrap();
+        model
+    }
+
+    #[test]
+    fn test_routes_between() {
+        let model = model();
+        let node = |name: &str| model.get_node_by_name(name).unwrap();
+
+        let routes = routes_between(&model, &node("input-a"), &node("output")).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(
+            routes[0].nodes(),
+            &[NodeIndex(0), NodeIndex(2), NodeIndex(3), NodeIndex(4)]
+        );
+        assert_eq!(routes[0].edges(), &[EdgeIndex(0), EdgeIndex(2), EdgeIndex(4)]);
+
+        let routes = routes_between(&model, &node("reservoir"), &node("link-b")).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].nodes(), &[NodeIndex(5), NodeIndex(2), NodeIndex(3)]);
+
+        assert!(routes_between(&model, &node("spring"), &node("output"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(routes_from(&model, &node("input-b")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let model = model();
+        // The loop through the reservoir is allowed.
+        assert_eq!(cycles(&model), vec![vec![NodeIndex(2), NodeIndex(3)]]);
+    }
+
+    #[test]
+    fn test_connected_components() {
+        let mut model = model();
+        model.add_link_node("canal").unwrap();
+        assert_eq!(
+            connected_components(&model),
+            vec![
+                vec![
+                    NodeIndex(0),
+                    NodeIndex(1),
+                    NodeIndex(2),
+                    NodeIndex(3),
+                    NodeIndex(4),
+                    NodeIndex(5)
+                ],
+                vec![NodeIndex(6), NodeIndex(7)],
+                vec![NodeIndex(8)]
+            ]
+        );
+    }
+}
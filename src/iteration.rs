@@ -0,0 +1,80 @@
+use crate::parameters::ParameterIndex;
+use crate::scenario::ScenarioIndex;
+use crate::timestep::Timestep;
+
+/// Settings for re-solving each timestep until the parameters that depend on its solution converge.
+///
+/// Some constraints depend on the solution itself, e.g. the head of a reservoir, and so the
+/// capacity of its turbines or abstractions, depends on its volume at the end of the timestep.
+/// The parameters are first computed from the state at the start of the timestep. After each
+/// solve the `parameters` are recomputed from the trial solution, along with any parameters that
+/// depend on them, and the timestep is solved again. This continues until no parameter changes
+/// by more than `tolerance`, or `max_iterations` solves have been made.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TimestepIteration {
+    pub(crate) parameters: Vec<ParameterIndex>,
+    pub(crate) max_iterations: usize,
+    pub(crate) tolerance: f64,
+}
+
+impl TimestepIteration {
+    pub(crate) fn new(parameters: Vec<ParameterIndex>, max_iterations: usize, tolerance: f64) -> Self {
+        Self {
+            parameters,
+            max_iterations: max_iterations.max(1),
+            tolerance,
+        }
+    }
+
+    /// The largest change in any parameter value between two iterations.
+    pub(crate) fn residual(previous: &[f64], current: &[f64]) -> f64 {
+        previous
+            .iter()
+            .zip(current)
+            .map(|(p, c)| (c - p).abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+/// The convergence of the iterations of a timestep of a scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IterationResult {
+    pub timestep_index: usize,
+    pub scenario_index: usize,
+    /// The number of solves made.
+    pub iterations: usize,
+    /// The largest change in any parameter value when recomputed from the final solution.
+    pub residual: f64,
+    /// Whether the residual is within the tolerance.
+    pub converged: bool,
+}
+
+impl IterationResult {
+    pub(crate) fn new(
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        iterations: usize,
+        residual: f64,
+        tolerance: f64,
+    ) -> Self {
+        Self {
+            timestep_index: timestep.index,
+            scenario_index: scenario_index.index,
+            iterations,
+            residual,
+            converged: residual <= tolerance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_residual() {
+        assert_eq!(TimestepIteration::residual(&[1.0, 2.0, 3.0], &[1.5, 0.0, 3.0]), 2.0);
+        assert_eq!(TimestepIteration::residual(&[], &[]), 0.0);
+        assert_eq!(TimestepIteration::new(Vec::new(), 0, 1e-6).max_iterations, 1);
+    }
+}
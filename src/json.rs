@@ -0,0 +1,349 @@
+/// A JSON value, as read by `Json::parse`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// The members of an object, in the order they are written.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parse a JSON document, returning a description of the first error if it is invalid.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: json.chars().collect(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.position < parser.chars.len() {
+            true => Err(parser.error("unexpected characters after the value")),
+            false => Ok(value),
+        }
+    }
+
+    /// The member `key` of an object, or `None` if this is not an object or has no such member.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        self.as_object()?.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Self::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    /// The name of the type of the value, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool(_) => "a boolean",
+            Self::Number(_) => "a number",
+            Self::String(_) => "a string",
+            Self::Array(_) => "an array",
+            Self::Object(_) => "an object",
+        }
+    }
+}
+
+impl Json {
+    /// Write the value as JSON indented by two spaces, with arrays of numbers, strings and other
+    /// values that are not arrays or objects on a single line.
+    pub fn to_pretty_string(&self) -> String {
+        let mut json = String::new();
+        self.write_pretty(&mut json, 0);
+        json
+    }
+
+    fn write_pretty(&self, json: &mut String, indent: usize) {
+        let padding = |depth: usize| "  ".repeat(depth);
+        match self {
+            Self::Null => json.push_str("null"),
+            Self::Bool(b) => json.push_str(&b.to_string()),
+            Self::Number(v) => json.push_str(&json_number(*v)),
+            Self::String(s) => json.push_str(&json_string(s)),
+            Self::Array(values) if values.iter().all(|v| !matches!(v, Self::Array(_) | Self::Object(_))) => {
+                json.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        json.push_str(", ");
+                    }
+                    value.write_pretty(json, indent);
+                }
+                json.push(']');
+            }
+            Self::Array(values) => {
+                json.push_str("[\n");
+                for (i, value) in values.iter().enumerate() {
+                    json.push_str(&padding(indent + 1));
+                    value.write_pretty(json, indent + 1);
+                    json.push_str(if i + 1 < values.len() { ",\n" } else { "\n" });
+                }
+                json.push_str(&padding(indent));
+                json.push(']');
+            }
+            Self::Object(members) if members.is_empty() => json.push_str("{}"),
+            Self::Object(members) => {
+                json.push_str("{\n");
+                for (i, (key, value)) in members.iter().enumerate() {
+                    json.push_str(&padding(indent + 1));
+                    json.push_str(&json_string(key));
+                    json.push_str(": ");
+                    value.write_pretty(json, indent + 1);
+                    json.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                json.push_str(&padding(indent));
+                json.push('}');
+            }
+        }
+    }
+}
+
+/// JSON has no representation of non-finite numbers; these are written as `null`.
+pub(crate) fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn error(&self, reason: &str) -> String {
+        format!("{} at character {}", reason, self.position)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.get(self.position), Some(c) if c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected `{}`", expected))),
+        }
+    }
+
+    /// Consume `word` if it is next, e.g. `null`.
+    fn keyword(&mut self, word: &str) -> bool {
+        let end = self.position + word.len();
+        match self.chars.get(self.position..end) {
+            Some(chars) if chars.iter().copied().eq(word.chars()) => {
+                self.position = end;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ if self.keyword("null") => Ok(Json::Null),
+            _ if self.keyword("true") => Ok(Json::Bool(true)),
+            _ if self.keyword("false") => Ok(Json::Bool(false)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let c = match self.chars.get(self.position) {
+                Some(c) => *c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.position += 1;
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escaped = self.chars.get(self.position).copied();
+                    self.position += 1;
+                    match escaped {
+                        Some('"') => value.push('"'),
+                        Some('\\') => value.push('\\'),
+                        Some('/') => value.push('/'),
+                        Some('b') => value.push('\u{8}'),
+                        Some('f') => value.push('\u{c}'),
+                        Some('n') => value.push('\n'),
+                        Some('r') => value.push('\r'),
+                        Some('t') => value.push('\t'),
+                        Some('u') => {
+                            let end = self.position + 4;
+                            let code = match self.chars.get(self.position..end) {
+                                Some(hex) => u32::from_str_radix(&hex.iter().collect::<String>(), 16).ok(),
+                                None => None,
+                            };
+                            match code.and_then(std::char::from_u32) {
+                                Some(c) => value.push(c),
+                                None => return Err(self.error("invalid unicode escape")),
+                            }
+                            self.position = end;
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while matches!(self.chars.get(self.position), Some(c) if c.is_ascii_digit() || "+-.eE".contains(*c)) {
+            self.position += 1;
+        }
+        let number: String = self.chars[start..self.position].iter().collect();
+        match number.parse() {
+            Ok(v) => Ok(Json::Number(v)),
+            Err(_) => Err(self.error(&format!("invalid number `{}`", number))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("reservoir"), "\"reservoir\"");
+        assert_eq!(json_string("a \"b\"\n"), "\"a \\\"b\\\"\\n\"");
+    }
+
+    #[test]
+    fn test_parse() {
+        let json = Json::parse(r#"{"a": [1, -2.5e3, true, null], "b\u00e9": {"c": "d\"e"}, "f": false}"#).unwrap();
+        let a = json.get("a").and_then(|a| a.as_array()).unwrap();
+        assert_eq!(
+            a,
+            &[Json::Number(1.0), Json::Number(-2500.0), Json::Bool(true), Json::Null]
+        );
+        assert_eq!(
+            json.get("bé").and_then(|b| b.get("c")).and_then(|c| c.as_str()),
+            Some("d\"e")
+        );
+        assert_eq!(json.get("f"), Some(&Json::Bool(false)));
+        assert_eq!(json.get("g"), None);
+
+        assert!(Json::parse("{\"a\": 1,}").is_err());
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("nul").is_err());
+        assert!(Json::parse("{} {}").is_err());
+    }
+
+    #[test]
+    fn test_to_pretty_string() {
+        let json = r#"{"a": [1, 2.5, "b", null], "c": [{"d": true}, []], "e": {}}"#;
+        let pretty = Json::parse(json).unwrap().to_pretty_string();
+        assert_eq!(
+            pretty,
+            "{\n  \"a\": [1, 2.5, \"b\", null],\n  \"c\": [\n    {\n      \"d\": true\n    },\n    []\n  ],\n  \"e\": {}\n}"
+        );
+        assert_eq!(Json::parse(&pretty).unwrap(), Json::parse(json).unwrap());
+    }
+}
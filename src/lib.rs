@@ -1,4 +1,4 @@
-use chrono::ParseError;
+use chrono::{NaiveDate, ParseError};
 use thiserror::Error;
 
 use crate::edge::{Edge, EdgeIndex};
@@ -6,40 +6,94 @@ use crate::node::{Node, NodeIndex};
 use crate::parameters::ParameterIndex;
 use crate::recorders::RecorderIndex;
 use crate::state::{NetworkState, ParameterState};
+use crate::virtual_node::VirtualNodeIndex;
 
+/// Define an index into one of the collections of a model (e.g. its nodes) as a distinct type, so
+/// that the indices of different collections can not be mixed up. It dereferences to the `usize`
+/// position in the collection.
+macro_rules! index_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub(crate) usize);
+
+        impl std::ops::Deref for $name {
+            type Target = usize;
+
+            fn deref(&self) -> &usize {
+                &self.0
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+pub mod allocation;
+pub mod builder;
+pub mod callbacks;
+pub mod checkpoint;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod control;
+pub mod dump;
 pub mod edge;
+pub mod graph;
+pub mod iteration;
+pub mod json;
 mod metric;
 pub mod model;
+pub mod multi_model;
 pub mod node;
 pub mod parameters;
+pub mod progress;
 pub mod python;
-mod recorders;
-mod scenario;
-mod solvers;
+pub mod recorders;
+pub mod relaxation;
+pub mod results;
+pub mod scenario;
+pub mod schema;
+pub mod solvers;
 pub mod state;
-mod timestep;
+pub mod timestep;
 mod utils;
+pub mod v1;
+pub mod validation;
+pub mod variation;
+pub mod virtual_node;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum PywrError {
     #[error("invalid node connect")]
     InvalidNodeConnection,
-    #[error("connection to node is already defined")]
-    NodeConnectionAlreadyExists,
-    #[error("node index not found")]
-    NodeIndexNotFound,
+    #[error("an edge from node `{from}` to node `{to}` already exists")]
+    NodeConnectionAlreadyExists { from: String, to: String },
+    #[error("node index {0} not found")]
+    NodeIndexNotFound(NodeIndex),
     #[error("node with name {0} not found")]
     NodeNotFound(String),
-    #[error("edge index not found")]
-    EdgeIndexNotFound,
-    #[error("parameter index not found")]
-    ParameterIndexNotFound,
+    #[error("edge index {0} not found")]
+    EdgeIndexNotFound(EdgeIndex),
+    #[error("virtual node index {0} not found")]
+    VirtualNodeIndexNotFound(VirtualNodeIndex),
+    #[error("parameter index {0} not found")]
+    ParameterIndexNotFound(ParameterIndex),
+    #[error("no edge from node `{from}` to node `{to}`")]
+    EdgeNotFound { from: String, to: String },
+    #[error("invalid edge `{0}`; edges are named `from->to`")]
+    InvalidEdgeName(String),
     #[error("parameter {0} not found")]
     ParameterNotFound(String),
-    #[error("recorder index not found")]
-    RecorderIndexNotFound,
-    #[error("recorder not found")]
-    RecorderNotFound,
+    #[error("parameter {0} does not have a {1} value")]
+    ParameterValueTypeMismatch(ParameterIndex, &'static str),
+    #[error("recorder index {0} not found")]
+    RecorderIndexNotFound(RecorderIndex),
+    #[error("recorder `{0}` not found")]
+    RecorderNotFound(String),
     #[error("node name `{0}` already exists")]
     NodeNameAlreadyExists(String),
     #[error("parameter name `{0}` already exists on parameter {1}")]
@@ -58,30 +112,148 @@ pub enum PywrError {
     ParseError(#[from] ParseError),
     #[error("timestep index out of range")]
     TimestepIndexOutOfRange,
+    #[error("the run has no more timesteps")]
+    RunComplete,
+    #[error("the run was superseded by a later run of the model, which reset its recorders and parameters")]
+    RunSuperseded,
+    #[error("invalid timestep duration: {0}")]
+    InvalidTimestepDuration(String),
+    #[error("invalid {name} date `{date}` for the format `{format}`: {reason}")]
+    InvalidDate {
+        name: String,
+        date: String,
+        format: String,
+        reason: ParseError,
+    },
+    #[error("the end date {end} is before the start date {start}")]
+    EndDateBeforeStartDate { start: NaiveDate, end: NaiveDate },
     #[error("solver not initialised")]
     SolverNotSetup,
+    #[error("problem update does not match the problem the solver was setup with")]
+    ProblemUpdateMismatch,
     #[error("no edges defined")]
     NoEdgesDefined,
+    #[error("no routes defined")]
+    NoRoutesDefined,
+    #[error("model is infeasible; violated node constraints: {violations:?}")]
+    Infeasible {
+        violations: Vec<solvers::builder::NodeViolation>,
+    },
     #[error("Python error: {0}")]
     PythonError(String),
-    #[error("Unrecognised metric")]
-    UnrecognisedMetric,
-    #[error("Unrecognised solver")]
-    UnrecognisedSolver,
+    #[error("unrecognised metric `{0}`")]
+    UnrecognisedMetric(String),
+    #[error("unrecognised solver `{name}`; available solvers are: {}", .available.join(", "))]
+    UnrecognisedSolver { name: String, available: Vec<String> },
+    #[error("solver `{name}` requires the `{feature}` feature; available solvers are: {}", .available.join(", "))]
+    SolverNotAvailable {
+        name: String,
+        feature: String,
+        available: Vec<String>,
+    },
     #[error("Solve failed")]
     SolveFailed,
     #[error("atleast one parameter is required")]
     AtleastOneParameterRequired,
     #[error("scenario state not found")]
     ScenarioStateNotFound,
+    #[error("scenario group `{0}` not found")]
+    ScenarioGroupNotFound(String),
+    #[error("scenario group `{0}` has no members")]
+    EmptyScenarioGroup(String),
+    #[error("members {start}..{end} are not a valid slice of scenario group `{group}`")]
+    InvalidScenarioSlice { group: String, start: usize, end: usize },
+    #[error("scenario combination {0:?} does not give a member of every scenario group")]
+    InvalidScenarioCombination(Vec<usize>),
+    #[error("scenario group `{group}` has {size} members but the data has {expected}")]
+    ScenarioGroupSizeMismatch {
+        group: String,
+        size: usize,
+        expected: usize,
+    },
+    #[error("scenario index not found")]
+    ScenarioIndexNotFound,
+    #[error("scenario group `{0}` needs a non-negative weight for each member, with a positive total")]
+    InvalidScenarioWeights(String),
+    #[error("the scenarios to run have a total weight of zero")]
+    ZeroScenarioWeight,
+    #[error("scenario group `{group}` has no member {member}")]
+    InvalidScenarioVariation { group: String, member: usize },
+    #[error("no scenarios would be run")]
+    NoScenarios,
+    #[error("latin hypercube sampling can not be used with explicit scenario combinations")]
+    IncompatibleScenarioSampling,
+    #[cfg(feature = "clp")]
     #[error("clp error")]
     ClpError(#[from] solvers::clp::ClpError),
+    #[cfg(feature = "cplex")]
+    #[error("cplex error")]
+    CplexError(#[from] solvers::cplex::CplexError),
+    #[cfg(feature = "cbc")]
+    #[error("cbc error")]
+    CbcError(#[from] solvers::cbc::CbcError),
+    #[cfg(feature = "glpk")]
+    #[error("glpk error")]
+    GlpkError(#[from] solvers::glpk::GlpkError),
+    #[cfg(feature = "gurobi")]
+    #[error("gurobi error")]
+    GurobiError(#[from] solvers::gurobi::GurobiError),
+    #[error("simplex error")]
+    SimplexError(#[from] solvers::simplex::SimplexError),
+    #[error("pdlp error")]
+    PdlpError(#[from] solvers::pdlp::PdlpError),
     #[error("metric not defined")]
     MetricNotDefinedForNode,
+    #[error("the values of virtual nodes are not available to parameters")]
+    MetricNotAvailableToParameters,
+    #[error("the flow history of node {0} is not tracked")]
+    FlowHistoryNotTracked(NodeIndex),
+    #[error("dual values are not available from the solver")]
+    DualsNotAvailable,
+    #[error("derived values have not been computed for this state")]
+    DerivedValuesNotAvailable,
+    #[error("solver does not support the features required by the model: {0:?}")]
+    MissingSolverFeatures(Vec<solvers::SolverFeature>),
+    #[error("node `{0}` must have a finite maximum flow to be mutually exclusive")]
+    UnboundedMutualExclusivity(String),
+    #[error("failed to build the thread pool - {0}")]
+    ThreadPoolBuildError(String),
     #[error("recorder not initialised")]
     RecorderNotInitialised,
     #[error("hdf5 error - {0}")]
     HDF5Error(String),
+    #[error("csv error - {0}")]
+    CSVError(String),
+    #[error("stream error - {0}")]
+    StreamError(String),
+    #[error("io error - {0}")]
+    IOError(String),
+    #[error("unrecognised problem format: {0}")]
+    UnrecognisedProblemFormat(String),
+    #[error("invalid checkpoint at line {line}: {reason}")]
+    InvalidCheckpoint { line: usize, reason: String },
+    #[error("checkpoint does not match the model: {0}")]
+    CheckpointMismatch(String),
+    #[error("invalid initial state: {0}")]
+    InvalidInitialState(String),
+    #[error("invalid state dump: {0}")]
+    InvalidStateDump(String),
+    #[error("component type `{0}` is already registered")]
+    ComponentTypeAlreadyRegistered(String),
+    #[error("invalid model schema: {0}")]
+    InvalidSchema(String),
+    #[error("invalid pywr v1 model: {0}")]
+    InvalidV1Model(String),
+    #[error("model name `{0}` already exists")]
+    ModelNameAlreadyExists(String),
+    #[error("model `{0}` not found")]
+    ModelNotFound(String),
+    #[error("parameter `{0}` can not receive values from another model")]
+    ParameterCannotReceiveValues(String),
+    #[error("invalid multi-model run: {0}")]
+    InvalidMultiModel(String),
+    #[error("mass-balance tolerance exceeded - {0}")]
+    MassBalanceError(String),
     #[error("not implemented by recorder")]
     NotSupportedByRecorder,
     #[error("invalid constraint value: {0}")]
@@ -90,4 +262,99 @@ pub enum PywrError {
     InvalidConstraintType(String),
     #[error("invalid aggregated function: {0}")]
     InvalidAggregationFunction(String),
+    #[error("invalid relaxation: {0}")]
+    InvalidRelaxation(String),
+    #[error("invalid allocation mode: {0}")]
+    InvalidAllocationMode(String),
+    #[error("invalid precision: {0}")]
+    InvalidPrecision(String),
+    #[error("invalid flow factors: {0}")]
+    InvalidFlowFactors(String),
+    #[error("node `{0}` must have a finite maximum flow to be allocated by priority")]
+    UnboundedPriorityDemand(String),
+    #[error("node `{name}`: {source}")]
+    NodeError { name: String, source: Box<PywrError> },
+    #[error("parameter `{name}`: {source}")]
+    ParameterError { name: String, source: Box<PywrError> },
+    #[error("recorder `{name}`: {source}")]
+    RecorderError { name: String, source: Box<PywrError> },
+    #[error("timestep {timestep} ({date}){}: {source}", .scenario.map(|s| format!(", scenario {}", s)).unwrap_or_default())]
+    TimestepError {
+        timestep: usize,
+        date: NaiveDate,
+        /// The scenario being simulated, unless the error applies to all of them.
+        scenario: Option<usize>,
+        source: Box<PywrError>,
+    },
+}
+
+impl PywrError {
+    /// Add the node whose constraints or values gave the error.
+    pub(crate) fn with_node(self, name: String) -> Self {
+        Self::NodeError {
+            name,
+            source: Box::new(self),
+        }
+    }
+
+    /// Add the parameter whose computation gave the error.
+    pub(crate) fn with_parameter(self, name: String) -> Self {
+        Self::ParameterError {
+            name,
+            source: Box::new(self),
+        }
+    }
+
+    /// Add the recorder that gave the error.
+    pub(crate) fn with_recorder(self, name: String) -> Self {
+        Self::RecorderError {
+            name,
+            source: Box::new(self),
+        }
+    }
+
+    /// Add the timestep, and the scenario if only one, being simulated when the error occurred.
+    /// An error that already has a timestep is unchanged.
+    pub(crate) fn at_timestep(self, timestep: &timestep::Timestep, scenario: Option<usize>) -> Self {
+        match self {
+            Self::TimestepError { .. } => self,
+            error => Self::TimestepError {
+                timestep: timestep.index,
+                date: timestep.date,
+                scenario,
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The underlying error, without the context of the timestep and components it occurred in.
+    pub fn root(&self) -> &PywrError {
+        match self {
+            Self::NodeError { source, .. }
+            | Self::ParameterError { source, .. }
+            | Self::RecorderError { source, .. }
+            | Self::TimestepError { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    /// Whether the error is a solver finding no feasible solution, rather than a failure of the
+    /// model or the solver itself. Backends that do not distinguish infeasible problems report
+    /// any solve that is not optimal.
+    pub fn is_infeasible(&self) -> bool {
+        match self.root() {
+            Self::Infeasible { .. } | Self::SimplexError(solvers::simplex::SimplexError::Infeasible) => true,
+            #[cfg(feature = "clp")]
+            Self::ClpError(solvers::clp::ClpError::NotOptimal) => true,
+            #[cfg(feature = "cplex")]
+            Self::CplexError(solvers::cplex::CplexError::NotOptimal(_)) => true,
+            #[cfg(feature = "cbc")]
+            Self::CbcError(solvers::cbc::CbcError::NotOptimal(_)) => true,
+            #[cfg(feature = "glpk")]
+            Self::GlpkError(solvers::glpk::GlpkError::NotOptimal(_)) => true,
+            #[cfg(feature = "gurobi")]
+            Self::GurobiError(solvers::gurobi::GurobiError::NotOptimal(_)) => true,
+            _ => false,
+        }
+    }
 }
@@ -1,7 +1,9 @@
 use crate::edge::EdgeIndex;
+use crate::model::Model;
 use crate::node::NodeIndex;
 use crate::parameters::ParameterIndex;
-use crate::state::NetworkState;
+use crate::state::{DerivedValues, NetworkState};
+use crate::virtual_node::VirtualNodeIndex;
 use crate::PywrError;
 
 #[derive(Clone, Debug)]
@@ -9,21 +11,108 @@ pub enum Metric {
     NodeInFlow(NodeIndex),
     NodeOutFlow(NodeIndex),
     NodeVolume(NodeIndex),
+    NodeNetFlow(NodeIndex),
+    NodeDeficit(NodeIndex),
+    /// The volume of a storage node as a proportion of its maximum volume.
+    NodeProportionalVolume(NodeIndex),
     EdgeFlow(EdgeIndex),
+    /// The dual value (shadow price) of a node's flow or storage constraint.
+    NodeDual(NodeIndex),
+    EdgeReducedCost(EdgeIndex),
     ParameterValue(ParameterIndex),
+    /// The value of a virtual node, computed from the flows of the nodes it observes.
+    VirtualNode(VirtualNodeIndex),
+    Constant(f64),
+    Sum(Vec<Metric>),
+    Difference(Box<Metric>, Box<Metric>),
+}
+
+fn derived_values(
+    model: &Model,
+    idx: NodeIndex,
+    network_state: &NetworkState,
+    parameter_state: &[f64],
+) -> Result<DerivedValues, PywrError> {
+    match model.nodes.get(*idx) {
+        Some(n) => n.derived_values(network_state, parameter_state),
+        None => Err(PywrError::NodeIndexNotFound(idx)),
+    }
 }
 
 impl Metric {
-    pub fn get_value(&self, network_state: &NetworkState, parameter_state: &[f64]) -> Result<f64, PywrError> {
+    pub fn get_value(
+        &self,
+        model: &Model,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<f64, PywrError> {
+        match self {
+            // The derived values are computed after each solve of a run, but not of a single solve.
+            Metric::NodeDeficit(idx) => match network_state.get_node_deficit(*idx) {
+                Err(PywrError::DerivedValuesNotAvailable) => {
+                    derived_values(model, *idx, network_state, parameter_state)?
+                        .deficit
+                        .ok_or(PywrError::MetricNotDefinedForNode)
+                }
+                result => result,
+            },
+            Metric::NodeProportionalVolume(idx) => match network_state.get_node_proportional_volume(*idx) {
+                Err(PywrError::DerivedValuesNotAvailable) => {
+                    derived_values(model, *idx, network_state, parameter_state)?
+                        .proportional_volume
+                        .ok_or(PywrError::MetricNotDefinedForNode)
+                }
+                result => result,
+            },
+            Metric::VirtualNode(idx) => model.get_virtual_node(*idx)?.get_value(model, network_state),
+            Metric::Sum(metrics) => {
+                let mut total = 0.0;
+                for metric in metrics {
+                    total += metric.get_value(model, network_state, parameter_state)?;
+                }
+                Ok(total)
+            }
+            Metric::Difference(a, b) => Ok(a.get_value(model, network_state, parameter_state)?
+                - b.get_value(model, network_state, parameter_state)?),
+            _ => self.get_state_value(network_state, parameter_state),
+        }
+    }
+
+    /// The value of the metric from the states alone, as needed by parameters, which are computed
+    /// without the model. The values of virtual nodes are not available, nor are the derived
+    /// values of nodes before the first timestep is solved.
+    pub(crate) fn get_state_value(
+        &self,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<f64, PywrError> {
         match self {
             Metric::NodeInFlow(idx) => Ok(network_state.get_node_in_flow(*idx)?),
             Metric::NodeOutFlow(idx) => Ok(network_state.get_node_out_flow(*idx)?),
             Metric::NodeVolume(idx) => Ok(network_state.get_node_volume(*idx)?),
+            Metric::NodeNetFlow(idx) => {
+                Ok(network_state.get_node_in_flow(*idx)? - network_state.get_node_out_flow(*idx)?)
+            }
+            Metric::NodeDeficit(idx) => network_state.get_node_deficit(*idx),
+            Metric::NodeProportionalVolume(idx) => network_state.get_node_proportional_volume(*idx),
             Metric::EdgeFlow(idx) => Ok(network_state.get_edge_flow(*idx)?),
-            Metric::ParameterValue(idx) => match parameter_state.get(*idx) {
+            Metric::NodeDual(idx) => Ok(network_state.get_node_dual(*idx)?),
+            Metric::EdgeReducedCost(idx) => Ok(network_state.get_edge_reduced_cost(*idx)?),
+            Metric::ParameterValue(idx) => match parameter_state.get(**idx) {
                 Some(v) => Ok(*v),
-                None => Err(PywrError::ParameterIndexNotFound),
+                None => Err(PywrError::ParameterIndexNotFound(*idx)),
             },
+            Metric::VirtualNode(_) => Err(PywrError::MetricNotAvailableToParameters),
+            Metric::Constant(v) => Ok(*v),
+            Metric::Sum(metrics) => {
+                let mut total = 0.0;
+                for metric in metrics {
+                    total += metric.get_state_value(network_state, parameter_state)?;
+                }
+                Ok(total)
+            }
+            Metric::Difference(a, b) => Ok(a.get_state_value(network_state, parameter_state)?
+                - b.get_state_value(network_state, parameter_state)?),
         }
     }
 }
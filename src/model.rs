@@ -1,25 +1,202 @@
-use crate::edge::{Edge, EdgeIndex};
-use crate::node::{Constraint, Node, NodeIndex};
-use crate::recorders::RecorderIndex;
-use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
-use crate::solvers::Solver;
-use crate::state::{EdgeState, NetworkState, ParameterState};
+use crate::allocation::{solve_priority_proportional, AllocationMode, PriorityClass};
+use crate::callbacks::{AfterTimestepCallback, BeforeTimestepCallback};
+use crate::checkpoint::{Checkpoint, InitialState};
+use crate::control::RunHandle;
+use crate::dump::StateDump;
+use crate::edge::{DuplicateEdges, Edge, EdgeIndex};
+use crate::graph;
+use crate::iteration::{IterationResult, TimestepIteration};
+use crate::metric::Metric;
+use crate::node::{Constraint, ConstraintValue, FlowFactors, Node, NodeIndex, NodeType};
+use crate::parameters::ParameterIndex;
+use crate::progress::{ProgressMonitor, ProgressReporter, ReportInterval};
+use crate::recorders::{
+    ConstraintBounds, ConstraintResult, Direction, ObjectiveValue, RecorderFrequency, RecorderIndex,
+};
+use crate::relaxation::{Relaxation, RelaxedSolve};
+use crate::results::{RunResults, RunStatistics, RunTimings};
+use crate::scenario::{check_scenario_indices, ScenarioGroupCollection, ScenarioIndex, ScenarioSampling};
+use crate::schema::{
+    EdgeSchema, ModelSchema, NodeSchema, NodeSchemaType, ParameterSchema, ParameterType, RecorderSchema, Registry,
+    SchemaExport, ValueSchema,
+};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::pool::SolverPool;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::state::{EdgeState, FlowHistory, NetworkState, ParameterState, Precision};
 use crate::timestep::{Timestep, Timestepper};
+use crate::validation::ValidationIssue;
+use crate::variation::{ScenarioVariation, Variation};
+use crate::virtual_node::{VirtualNode, VirtualNodeIndex, VirtualNodeType};
 use crate::{parameters, recorders, PywrError};
 use ndarray::ArrayView2;
-use std::time::Instant;
+use rayon::prelude::*;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, Span};
 
+/// Escape `value` for a quoted string of a DOT graph.
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Return the current bounds on the flow through a node.
+///
+/// For storage nodes these are the bounds on the net inflow given the available volume.
+fn node_constraint_bounds(
+    node: &Node,
+    timestep: &Timestep,
+    network_state: &NetworkState,
+    parameter_states: &[f64],
+) -> Result<(f64, f64), PywrError> {
+    match node.get_current_flow_bounds(parameter_states) {
+        Ok(bnds) => Ok(bnds),
+        Err(PywrError::FlowConstraintsUndefined) => {
+            // Must be a storage node
+            let (avail, missing) = node.get_current_available_volume_bounds(network_state, parameter_states)?;
+            let dt = timestep.days();
+            Ok((-avail / dt, missing / dt))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Remove `constraint` from the flow `bounds` of `node`, as if it were not set.
+///
+/// Flow constraints only apply to input, link and output nodes, and volume constraints to
+/// storage nodes; other constraints are ignored. A storage node without a minimum volume may be
+/// drawn down to empty.
+fn remove_constraint(
+    node: &Node,
+    bounds: &mut (f64, f64),
+    constraint: Constraint,
+    timestep: &Timestep,
+    network_state: &NetworkState,
+) -> Result<(), PywrError> {
+    let is_storage = matches!(node.node_type(), NodeType::Storage);
+    match constraint {
+        Constraint::MinFlow if !is_storage => bounds.0 = bounds.0.min(0.0),
+        Constraint::MaxFlow if !is_storage => bounds.1 = f64::MAX,
+        Constraint::MinAndMaxFlow if !is_storage => *bounds = (bounds.0.min(0.0), f64::MAX),
+        Constraint::MinVolume if is_storage => {
+            let volume = network_state.get_node_volume(node.index())?;
+            bounds.0 = bounds.0.min(-volume / timestep.days());
+        }
+        Constraint::MaxVolume if is_storage => bounds.1 = f64::MAX,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The parameter state of a solve, with any relaxations it needed and the convergence of its
+/// iterations.
+type IteratedSolve = (ParameterState, Option<RelaxedSolve>, Option<IterationResult>);
+
+/// A run of a model that is advanced one timestep at a time.
+///
+/// This is created by `Model::start_run`, advanced by `Model::step_run` and completed by
+/// `Model::finish_run`. Between steps the state of each scenario can be inspected or changed, and
+/// the model's constraints updated, e.g. to exchange boundary conditions with a coupled model.
+pub struct ModelRun {
+    timesteps: Vec<Timestep>,
+    scenario_indices: Vec<ScenarioIndex>,
+    solvers: SolverPool,
+    states: Vec<NetworkState>,
+    /// The buffer the states after each step are written to, before it is swapped with `states`.
+    next_states: Vec<NetworkState>,
+    parameter_states: Vec<ParameterState>,
+    next: usize,
+    /// The first timestep solved, which is later than the first of the timestepper if the run was
+    /// resumed from a checkpoint.
+    first: usize,
+    /// The number of timesteps solved as one problem.
+    window: usize,
+    started: Instant,
+    timings: RunTimings,
+    /// The span of the run, which is entered while it is started, stepped and finished.
+    span: Span,
+    /// The number of the run amongst those started by the model.
+    id: usize,
+}
+
+impl ModelRun {
+    /// The next timestep to be solved, or `None` if the run is complete.
+    pub fn next_timestep(&self) -> Option<&Timestep> {
+        self.timesteps.get(self.next)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next >= self.timesteps.len()
+    }
+
+    /// The scenarios of the run, in the order of their states.
+    pub fn scenario_indices(&self) -> &[ScenarioIndex] {
+        &self.scenario_indices
+    }
+
+    /// The state of each scenario after the most recent step, or the initial state before the first.
+    pub fn states(&self) -> &[NetworkState] {
+        &self.states
+    }
+
+    /// The state of each scenario, which may be changed before the next step.
+    pub fn states_mut(&mut self) -> &mut [NetworkState] {
+        &mut self.states
+    }
+
+    /// The parameter values of each scenario in the most recent step, or none before the first.
+    pub fn parameter_states(&self) -> &[ParameterState] {
+        &self.parameter_states
+    }
+}
+
+/// A network of nodes and edges, with the parameters and recorders that are evaluated on it.
+///
+/// The states of the network and the solvers of a run are held in its `ModelRun`. The recorders'
+/// saved values and any memory kept by the parameters are not; they are held by the model and
+/// reset when each run is started, and the recorders of a run's `RunResults` are those of the
+/// model. A model therefore has one run at a time: starting a run supersedes any earlier run that
+/// is not finished, which `step_run` and `finish_run` then reject with `RunSuperseded`.
 pub struct Model {
-    pub nodes: Vec<Node>,
-    pub edges: Vec<Edge>,
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) edges: Vec<Edge>,
     parameters: Vec<parameters::Parameter>,
     recorders: Vec<recorders::Recorder>,
     scenarios: ScenarioGroupCollection,
+    aggregated_values: Vec<Option<f64>>,
+    objectives: Vec<(RecorderIndex, Direction)>,
+    constraints: Vec<(RecorderIndex, ConstraintBounds)>,
+    mutual_exclusivities: Vec<Vec<NodeIndex>>,
+    flow_factors: Vec<FlowFactors>,
+    virtual_nodes: Vec<VirtualNode>,
+    progress: Option<ProgressMonitor>,
+    run_handle: Option<RunHandle>,
+    before_timestep_callbacks: Vec<Box<dyn BeforeTimestepCallback>>,
+    after_timestep_callbacks: Vec<Box<dyn AfterTimestepCallback>>,
+    solver_stats: Option<SolverStats>,
+    relaxations: Vec<Relaxation>,
+    relaxed_solves: Vec<RelaxedSolve>,
+    iteration: Option<TimestepIteration>,
+    iteration_results: Vec<IterationResult>,
+    allocation_mode: AllocationMode,
+    warm_up: usize,
+    rolling_horizon: usize,
+    variations: Vec<ScenarioVariation>,
+    checkpointing: Option<(PathBuf, usize)>,
+    initial_state: Option<InitialState>,
+    flow_histories: Vec<(NodeIndex, usize)>,
+    state_dump: Option<(usize, usize, PathBuf)>,
+    storage_precision: Precision,
+    duplicate_edges: DuplicateEdges,
+    /// The time spent saving the recorders in the current run.
+    recorder_time: Duration,
+    /// The number of runs started, the latest of which is the only one that may be continued.
+    runs_started: usize,
 }
 
-// Required for Python API
-unsafe impl Send for Model {}
-
 impl Default for Model {
     fn default() -> Self {
         Self::new()
@@ -34,7 +211,179 @@ impl Model {
             parameters: Vec::new(),
             recorders: Vec::new(),
             scenarios: ScenarioGroupCollection::new(),
+            aggregated_values: Vec::new(),
+            objectives: Vec::new(),
+            constraints: Vec::new(),
+            mutual_exclusivities: Vec::new(),
+            flow_factors: Vec::new(),
+            virtual_nodes: Vec::new(),
+            progress: None,
+            run_handle: None,
+            before_timestep_callbacks: Vec::new(),
+            after_timestep_callbacks: Vec::new(),
+            solver_stats: None,
+            relaxations: Vec::new(),
+            relaxed_solves: Vec::new(),
+            iteration: None,
+            iteration_results: Vec::new(),
+            allocation_mode: AllocationMode::MinimumCost,
+            warm_up: 0,
+            rolling_horizon: 0,
+            variations: Vec::new(),
+            checkpointing: None,
+            initial_state: None,
+            state_dump: None,
+            storage_precision: Precision::Double,
+            duplicate_edges: DuplicateEdges::Error,
+            flow_histories: Vec::new(),
+            recorder_time: Duration::ZERO,
+            runs_started: 0,
+        }
+    }
+
+    /// Build a model and its timestepper from a JSON model file (see `ModelSchema`), with the
+    /// paths of its outputs relative to the file.
+    pub fn from_schema(path: &Path) -> Result<(Self, Timestepper), PywrError> {
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        ModelSchema::read(path)?.build(directory)
+    }
+
+    /// The schema of the model run with `timestepper`, to be written to a model file with
+    /// `ModelSchema::write` and read back with `from_schema`.
+    ///
+    /// The parameters and recorders that can not be represented in a schema are omitted, with
+    /// the node attributes and aggregated parameters that refer to them, and listed in `omitted`.
+    /// The slices, weights and sampling of the scenarios, and any warm-up period, are settings of
+    /// a run and are not included. Timesteps that are not a number of days of the Gregorian
+    /// calendar can not be represented and give an error.
+    pub fn to_schema(&self, timestepper: &Timestepper) -> Result<SchemaExport, PywrError> {
+        let timestepper = timestepper.schema().ok_or_else(|| {
+            PywrError::InvalidSchema(
+                "only timesteps of a number of days of the Gregorian calendar are supported".to_string(),
+            )
+        })?;
+        let mut omitted = Vec::new();
+
+        // Aggregated parameters only refer to parameters added before them.
+        let mut parameters: Vec<ParameterSchema> = Vec::new();
+        for parameter in &self.parameters {
+            let name = parameter.name();
+            match parameter.schema() {
+                Some(ParameterType::Aggregated {
+                    parameters: references, ..
+                }) if !references.iter().all(|r| parameters.iter().any(|p| &p.name == r)) => {
+                    omitted.push(format!("parameter `{}`", name))
+                }
+                Some(parameter_type) => parameters.push(ParameterSchema { name, parameter_type }),
+                None => omitted.push(format!("parameter `{}`", name)),
+            }
+        }
+
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let name = node.name();
+            let mut schema = NodeSchema {
+                name: name.clone(),
+                node_type: match node.node_type() {
+                    NodeType::Input => NodeSchemaType::Input,
+                    NodeType::Output => NodeSchemaType::Output,
+                    NodeType::Link => NodeSchemaType::Link,
+                    NodeType::Storage => NodeSchemaType::Storage,
+                },
+                max_flow: None,
+                min_flow: None,
+                cost: None,
+                max_volume: None,
+                min_volume: None,
+                initial_volume: node.initial_volume().unwrap_or_default(),
+            };
+            for (attribute, value) in node.attributes() {
+                let value = match value {
+                    ConstraintValue::None => continue,
+                    ConstraintValue::Scalar(v) => ValueSchema::Constant(v),
+                    ConstraintValue::Parameter(p) if parameters.iter().any(|q| q.name == p.name()) => {
+                        ValueSchema::Parameter(p.name())
+                    }
+                    ConstraintValue::Parameter(_) => {
+                        omitted.push(format!("the {} of node `{}`", attribute, name));
+                        continue;
+                    }
+                };
+                match attribute {
+                    "max_flow" => schema.max_flow = Some(value),
+                    "min_flow" => schema.min_flow = Some(value),
+                    "cost" => schema.cost = Some(value),
+                    "max_volume" => schema.max_volume = Some(value),
+                    _ => schema.min_volume = Some(value),
+                }
+            }
+            nodes.push(schema);
+        }
+
+        let mut edges = Vec::with_capacity(self.edges.len());
+        for edge in &self.edges {
+            let mut schema = EdgeSchema {
+                from: self.nodes[*edge.from_node_index()].name(),
+                to: self.nodes[*edge.to_node_index()].name(),
+                max_flow: None,
+                min_flow: None,
+                cost: None,
+            };
+            for (attribute, value) in edge.attributes() {
+                let value = match value {
+                    ConstraintValue::None => continue,
+                    ConstraintValue::Scalar(v) => ValueSchema::Constant(v),
+                    ConstraintValue::Parameter(p) if parameters.iter().any(|q| q.name == p.name()) => {
+                        ValueSchema::Parameter(p.name())
+                    }
+                    ConstraintValue::Parameter(_) => {
+                        omitted.push(format!(
+                            "the {} of the edge from `{}` to `{}`",
+                            attribute, schema.from, schema.to
+                        ));
+                        continue;
+                    }
+                };
+                match attribute {
+                    "max_flow" => schema.max_flow = Some(value),
+                    "min_flow" => schema.min_flow = Some(value),
+                    _ => schema.cost = Some(value),
+                }
+            }
+            edges.push(schema);
+        }
+
+        let mut recorders = Vec::new();
+        for recorder in &self.recorders {
+            match recorder.schema(self) {
+                Some(recorder_type) => recorders.push(RecorderSchema {
+                    name: recorder.name(),
+                    recorder_type,
+                }),
+                None => omitted.push(format!("recorder `{}`", recorder.name())),
+            }
         }
+
+        Ok(SchemaExport {
+            schema: ModelSchema {
+                timestepper,
+                scenarios: self.scenarios.schema(),
+                nodes,
+                edges,
+                parameters,
+                recorders,
+                templates: Vec::new(),
+                instances: Vec::new(),
+            },
+            omitted,
+        })
+    }
+
+    /// Build a model as `from_schema`, with the components of custom types built by the
+    /// constructors of `registry`.
+    pub fn from_schema_with_registry(path: &Path, registry: &Registry) -> Result<(Self, Timestepper), PywrError> {
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        ModelSchema::read(path)?.build_with_registry(directory, registry)
     }
 
     /// Returns the initial state of the network
@@ -52,25 +401,49 @@ impl Model {
                 state.push_edge_state(EdgeState::new());
             }
 
+            for (node, length) in self.flow_history_lengths() {
+                let in_flow = matches!(self.nodes.get(*node).map(|n| n.node_type()), Some(NodeType::Output));
+                state.push_flow_history(FlowHistory::new(node, in_flow, length, self.storage_precision));
+            }
+
             states.push(state)
         }
         states
     }
 
-    fn setup(&self, timesteps: &Vec<Timestep>, scenario_indices: &Vec<ScenarioIndex>) -> Result<(), PywrError> {
+    /// The number of timesteps of flow history to keep for each tracked node, which is the longest
+    /// of that requested with `track_flow_history` and required by the parameters.
+    fn flow_history_lengths(&self) -> Vec<(NodeIndex, usize)> {
+        let required = self.parameters.iter().filter_map(|p| p.required_flow_history());
+        let mut lengths: Vec<(NodeIndex, usize)> = Vec::new();
+        for (node, length) in self.flow_histories.iter().copied().chain(required) {
+            match lengths.iter_mut().find(|(n, _)| *n == node) {
+                Some((_, l)) => *l = (*l).max(length),
+                None => lengths.push((node, length)),
+            }
+        }
+        lengths
+    }
+
+    fn setup(&self, timestepper: &Timestepper) -> Result<(), PywrError> {
+        // Setup parameters
+        self.check_scenarios()?;
+
         // Setup recorders
         for recorder in self.recorders.iter() {
-            recorder.setup(&self, timesteps, scenario_indices)?;
+            recorder.setup(&self, timestepper, &self.scenarios)?;
         }
 
         Ok(())
     }
 
-    fn finalise(&self) -> Result<(), PywrError> {
-        // Setup recorders
+    fn finalise(&mut self) -> Result<(), PywrError> {
+        // Finalise recorders, and retain any aggregated values they return
+        let mut aggregated_values = Vec::with_capacity(self.recorders.len());
         for recorder in self.recorders.iter() {
-            recorder.finalise()?;
+            aggregated_values.push(recorder.finalise()?);
         }
+        self.aggregated_values = aggregated_values;
 
         Ok(())
     }
@@ -78,481 +451,3946 @@ impl Model {
     pub fn run(
         &mut self,
         timestepper: Timestepper,
-        scenarios: ScenarioGroupCollection,
-        solver: &mut Box<dyn Solver>,
-    ) -> Result<(), PywrError> {
-        let now = Instant::now();
+        solver: &dyn Solver,
+        settings: &SolverSettings,
+    ) -> Result<RunResults, PywrError> {
+        let mut run = self.start_run(&timestepper, solver, settings)?;
+        while !run.is_complete() && !self.should_cancel() {
+            self.step_run(&mut run)?;
+        }
+        self.finish_run(run)
+    }
 
-        let timesteps = timestepper.timesteps();
-        let scenario_indices = scenarios.scenario_indices();
-        // One state per scenario
-        let mut current_states = self.get_initial_state(&scenario_indices);
+    /// Resume the run saved in the checkpoint at `path` and run its remaining timesteps.
+    ///
+    /// The model, scenarios and `timestepper` must be those of the run that wrote the checkpoint.
+    /// The recorders are set up afresh, and so only save the resumed timesteps.
+    pub fn run_from_checkpoint(
+        &mut self,
+        timestepper: Timestepper,
+        solver: &dyn Solver,
+        settings: &SolverSettings,
+        path: &Path,
+    ) -> Result<RunResults, PywrError> {
+        let checkpoint = Checkpoint::read(path)?;
+        let mut run = self.resume_run(&timestepper, solver, settings, &checkpoint)?;
+        while !run.is_complete() && !self.should_cancel() {
+            self.step_run(&mut run)?;
+        }
+        self.finish_run(run)
+    }
 
-        // Setup the solver
-        let mut count = 0;
-        solver.setup(self)?;
-        self.setup(&timesteps, &scenario_indices)?;
+    /// Set up a run that continues from `checkpoint`, to be advanced by `step_run`.
+    pub fn resume_run(
+        &mut self,
+        timestepper: &Timestepper,
+        solver: &dyn Solver,
+        settings: &SolverSettings,
+        checkpoint: &Checkpoint,
+    ) -> Result<ModelRun, PywrError> {
+        let mut run = self.start_run(timestepper, solver, settings)?;
 
-        // Step a timestep
-        for timestep in timesteps.iter() {
-            let next_states = self.step(timestep, &scenario_indices, solver, &current_states)?;
-            current_states = next_states;
-            count += scenario_indices.len();
+        if checkpoint.states.len() != run.states.len() {
+            return Err(PywrError::CheckpointMismatch(format!(
+                "the checkpoint has {} scenarios but the run has {}",
+                checkpoint.states.len(),
+                run.states.len()
+            )));
         }
-        println!("speed: {} ts/s", count as f64 / now.elapsed().as_secs_f64());
-        // println!("final state: {:?}", initial_state);
-        self.finalise()?;
-        Ok(())
+        if run
+            .states
+            .iter()
+            .zip(&checkpoint.states)
+            .any(|(initial, saved)| !initial.has_same_shape(saved))
+        {
+            return Err(PywrError::CheckpointMismatch(
+                "the nodes and edges of the checkpoint differ from the model".to_string(),
+            ));
+        }
+        if checkpoint.parameters.len() != self.parameters.len() {
+            return Err(PywrError::CheckpointMismatch(format!(
+                "the checkpoint has {} parameters but the model has {}",
+                checkpoint.parameters.len(),
+                self.parameters.len()
+            )));
+        }
+        let next_datetime = run.timesteps.get(checkpoint.next_timestep).map(|t| t.datetime());
+        if checkpoint.next_timestep > run.timesteps.len() || checkpoint.next_datetime != next_datetime {
+            return Err(PywrError::CheckpointMismatch(
+                "the next timestep of the checkpoint is not a timestep of the run".to_string(),
+            ));
+        }
+
+        for (parameter, values) in self.parameters.iter().zip(&checkpoint.parameters) {
+            parameter.restore_state(values)?;
+        }
+        run.states = checkpoint.states.clone();
+        run.next = checkpoint.next_timestep;
+        run.first = checkpoint.next_timestep;
+        Ok(run)
     }
 
-    /// Perform a single timestep with the current state, and return the updated states.
-    pub(crate) fn step(
-        &mut self,
-        timestep: &Timestep,
+    /// Replace the initial states of a run with the `initial_state`, if any.
+    fn apply_initial_state(
+        &self,
         scenario_indices: &[ScenarioIndex],
-        solver: &mut Box<dyn Solver>,
-        current_states: &[NetworkState],
-    ) -> Result<Vec<NetworkState>, PywrError> {
-        let mut next_states = Vec::with_capacity(current_states.len());
+        states: &mut [NetworkState],
+    ) -> Result<(), PywrError> {
+        match &self.initial_state {
+            None => {}
+            Some(InitialState::Checkpoint(checkpoint)) => {
+                let saved = &checkpoint.states;
+                if saved.len() != 1 && saved.len() != states.len() {
+                    return Err(PywrError::InvalidInitialState(format!(
+                        "the checkpoint has {} scenarios but the run has {}",
+                        saved.len(),
+                        states.len()
+                    )));
+                }
+                for (i, state) in states.iter_mut().enumerate() {
+                    let s = &saved[i.min(saved.len() - 1)];
+                    if !state.has_same_shape(s) {
+                        return Err(PywrError::InvalidInitialState(
+                            "the nodes and edges of the checkpoint differ from the model".to_string(),
+                        ));
+                    }
+                    *state = s.clone();
+                }
+                // Parameter memory may be kept per scenario, so is only restored for the same scenarios.
+                if saved.len() == states.len() && checkpoint.parameters.len() == self.parameters.len() {
+                    for (parameter, values) in self.parameters.iter().zip(&checkpoint.parameters) {
+                        parameter.restore_state(values)?;
+                    }
+                }
+            }
+            Some(InitialState::Volumes(volumes)) => {
+                for (scenario_index, state) in scenario_indices.iter().zip(states.iter_mut()) {
+                    let values = match volumes.len() {
+                        1 => &volumes[0].1,
+                        _ => match volumes.iter().find(|(label, _)| *label == scenario_index.label()) {
+                            Some((_, values)) => values,
+                            None => {
+                                return Err(PywrError::InvalidInitialState(format!(
+                                    "no values for scenario {}",
+                                    scenario_index.label()
+                                )))
+                            }
+                        },
+                    };
+                    for (name, volume) in values {
+                        let node = self.get_node_by_name(name)?;
+                        match state.set_node_volume(node.index(), *volume) {
+                            Ok(()) | Err(PywrError::MetricNotDefinedForNode) => {}
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Save the current state of `run`, from which it can be resumed with `resume_run`.
+    pub fn checkpoint(&self, run: &ModelRun) -> Checkpoint {
+        Checkpoint {
+            next_timestep: run.next,
+            next_datetime: run.next_timestep().map(|t| t.datetime()),
+            states: run.states.clone(),
+            parameters: self.parameters.iter().map(|p| p.checkpoint_state()).collect(),
+        }
+    }
+
+    /// Dump the state of scenario `scenario` of `run` after its most recent step, or the initial
+    /// state before the first.
+    pub fn dump_state(&self, run: &ModelRun, scenario: usize) -> Result<StateDump, PywrError> {
+        let (scenario_index, state) = match (run.scenario_indices.get(scenario), run.states.get(scenario)) {
+            (Some(scenario_index), Some(state)) => (scenario_index, state),
+            _ => return Err(PywrError::ScenarioIndexNotFound),
+        };
+        let timestep = match run.next {
+            0 => None,
+            next => Some(&run.timesteps[next - 1]),
+        };
 
-        for scenario_index in scenario_indices.iter() {
-            let current_state = match current_states.get(scenario_index.index) {
-                Some(s) => s,
-                None => return Err(PywrError::ScenarioStateNotFound),
+        let mut values = Vec::new();
+        for node in &self.nodes {
+            let index = node.index();
+            let key = |attribute: &str| format!("node/{}/{}", node.name(), attribute);
+            values.push((key("in_flow"), state.get_node_in_flow(index)?));
+            values.push((key("out_flow"), state.get_node_out_flow(index)?));
+            // Only the values defined for the node, or available from the solver, are dumped.
+            let optional = [
+                ("volume", state.get_node_volume(index)),
+                ("proportional_volume", state.get_node_proportional_volume(index)),
+                ("deficit", state.get_node_deficit(index)),
+                ("dual", state.get_node_dual(index)),
+            ];
+            for (attribute, value) in optional.iter() {
+                if let Ok(value) = value {
+                    values.push((key(attribute), *value));
+                }
+            }
+        }
+        for edge in &self.edges {
+            let key = |attribute: &str| {
+                format!(
+                    "edge/{}/{}/{}",
+                    self.nodes[*edge.from_node_index()].name(),
+                    self.nodes[*edge.to_node_index()].name(),
+                    attribute
+                )
             };
-            let pstate = self.compute_parameters(&timestep, &scenario_index, current_state)?;
+            values.push((key("flow"), state.get_edge_flow(edge.index())?));
+            if let Ok(value) = state.get_edge_reduced_cost(edge.index()) {
+                values.push((key("reduced_cost"), value));
+            }
+        }
+        if let Some(parameter_state) = run.parameter_states.get(scenario) {
+            for parameter in &self.parameters {
+                let index = parameter.index();
+                let key = format!("parameter/{}", parameter.name());
+                match (parameter_state.get_index(index), parameter_state.get_multi_value(index)) {
+                    (Ok(i), _) => values.push((key, i as f64)),
+                    (_, Ok(multi)) => {
+                        for (i, value) in multi.iter().enumerate() {
+                            values.push((format!("{}/{}", key, i), *value));
+                        }
+                    }
+                    _ => values.push((key, parameter_state.get_value(index)?)),
+                }
+            }
+        }
 
-            let next_state = solver.solve(&self, timestep, current_state, &pstate)?;
+        Ok(StateDump {
+            timestep: timestep.map(|t| t.index),
+            date: timestep.map(|t| t.label()),
+            scenario: scenario_index.label(),
+            values,
+        })
+    }
+
+    /// Set up a run of the timesteps of `timestepper` that is advanced by `step_run`.
+    pub fn start_run(
+        &mut self,
+        timestepper: &Timestepper,
+        solver: &dyn Solver,
+        settings: &SolverSettings,
+    ) -> Result<ModelRun, PywrError> {
+        let started = Instant::now();
 
-            self.save_recorders(&timestep, &scenario_index, &next_state, &pstate)?;
+        let timesteps = timestepper.timesteps();
+        let scenario_indices = self.scenarios.scenario_indices();
+        check_scenario_indices(&scenario_indices)?;
+        let span = info_span!("run", timesteps = timesteps.len(), scenarios = scenario_indices.len());
+        let entered = span.enter();
+        // One state per scenario
+        let mut states = self.get_initial_state(&scenario_indices);
 
-            next_states.push(next_state);
+        self.check_solver_features(solver)?;
+        let window = settings.timestep_window.unwrap_or(1).max(1);
+        if window > 1 && !solver.features().contains(&SolverFeature::MultiTimestep) {
+            return Err(PywrError::MissingSolverFeatures(vec![SolverFeature::MultiTimestep]));
         }
+        // Setting up the recorders and parameters resets those of any earlier run.
+        self.runs_started += 1;
+        self.setup(timestepper)?;
 
-        self.after_save_recorders(&timestep)?;
+        // Setup the solvers
+        self.solver_stats = None;
+        self.recorder_time = Duration::ZERO;
+        self.relaxed_solves.clear();
+        self.iteration_results.clear();
+        let solvers = SolverPool::new(self, solver, settings)?;
+        self.apply_initial_state(&scenario_indices, &mut states)?;
+        info!(
+            "starting run of {} timesteps of {} scenarios",
+            timesteps.len(),
+            scenario_indices.len()
+        );
 
-        Ok(next_states)
+        drop(entered);
+        Ok(ModelRun {
+            timesteps,
+            scenario_indices,
+            solvers,
+            next_states: states.clone(),
+            states,
+            parameter_states: Vec::new(),
+            next: 0,
+            first: 0,
+            window,
+            started,
+            timings: RunTimings {
+                setup: started.elapsed(),
+                ..RunTimings::default()
+            },
+            span,
+            id: self.runs_started,
+        })
     }
 
-    fn compute_parameters(
-        &self,
-        timestep: &Timestep,
-        scenario_index: &ScenarioIndex,
-        state: &NetworkState,
-    ) -> Result<ParameterState, PywrError> {
-        let mut parameter_state = ParameterState::with_capacity(self.parameters.len());
-        for parameter in &self.parameters {
-            let value = parameter.compute(timestep, scenario_index, state, &parameter_state)?;
-            parameter_state.push(value);
+    /// Check that `run` is the latest run of the model, whose recorders and parameters it uses.
+    fn check_run(&self, run: &ModelRun) -> Result<(), PywrError> {
+        match run.id == self.runs_started {
+            true => Ok(()),
+            false => Err(PywrError::RunSuperseded),
         }
-
-        Ok(parameter_state)
     }
 
-    fn save_recorders(
-        &self,
-        timestep: &Timestep,
-        scenario_index: &ScenarioIndex,
-        network_state: &NetworkState,
-        parameter_state: &[f64],
-    ) -> Result<(), PywrError> {
-        for recorder in self.recorders.iter() {
-            recorder.save(timestep, scenario_index, network_state, parameter_state)?;
+    /// Solve the next timestep of `run` from its current states, and save the recorders.
+    ///
+    /// If the run's solver settings have a `timestep_window`, the timesteps of the next window are
+    /// solved and saved together.
+    pub fn step_run(&mut self, run: &mut ModelRun) -> Result<(), PywrError> {
+        self.check_run(run)?;
+        let count = run.next;
+        if count >= run.timesteps.len() {
+            return Err(PywrError::RunComplete);
         }
-        Ok(())
-    }
+        let span = run.span.clone();
+        let _enter = span.enter();
 
-    fn after_save_recorders(&self, timestep: &Timestep) -> Result<(), PywrError> {
-        for recorder in self.recorders.iter() {
-            recorder.after_save(timestep)?;
+        let started = Instant::now();
+        let solved = match count < self.warm_up {
+            true => 1,
+            false => run.window.min(run.timesteps.len() - count),
+        };
+        for timestep in &run.timesteps[count..count + solved] {
+            self.before_timestep(timestep)
+                .map_err(|e| e.at_timestep(timestep, None))?;
+        }
+        for callback in self.before_timestep_callbacks.iter_mut() {
+            let timestep = &run.timesteps[count];
+            callback
+                .before_timestep(timestep, &run.scenario_indices, &mut run.states)
+                .map_err(|e| e.at_timestep(timestep, None))?;
         }
-        Ok(())
-    }
 
-    /// Get a NodeIndex from a node's name
-    pub fn get_node_by_name(&self, name: &str) -> Result<Node, PywrError> {
-        match self.nodes.iter().find(|&n| n.name() == name) {
-            Some(node) => Ok(node.clone()),
-            None => Err(PywrError::NodeNotFound(name.to_string())),
+        let (timesteps, scenario_indices, solvers) = (&run.timesteps, &run.scenario_indices, &run.solvers);
+        run.parameter_states = if count < self.warm_up {
+            self.step_warm_up(
+                &timesteps[count],
+                scenario_indices,
+                solvers,
+                &run.states,
+                &mut run.next_states,
+            )
+        } else if self.rolling_horizon > 0 || run.window > 1 {
+            let end = (count + solved + self.rolling_horizon).min(timesteps.len());
+            let window = &timesteps[count..end];
+            self.step_horizon(
+                window,
+                solved,
+                scenario_indices,
+                solvers,
+                &run.states,
+                &mut run.next_states,
+            )
+        } else {
+            self.step(
+                &timesteps[count],
+                scenario_indices,
+                solvers,
+                &run.states,
+                &mut run.next_states,
+            )
         }
-    }
+        // Errors that are not of a single scenario are of all of those of the timestep.
+        .map_err(|e| e.at_timestep(&timesteps[count], None))?;
+        std::mem::swap(&mut run.states, &mut run.next_states);
+        run.next += solved;
+        run.timings.simulation += started.elapsed();
+        debug!(
+            timestep = count,
+            date = %run.timesteps[count].label(),
+            solved,
+            "solved timestep"
+        );
 
-    /// Get a `ParameterIndex` from a parameter's name
-    pub fn get_parameter_by_name(&self, name: &str) -> Result<parameters::Parameter, PywrError> {
-        match self.parameters.iter().find(|p| p.name() == name) {
-            Some(parameter) => Ok(parameter.clone()),
-            None => Err(PywrError::ParameterNotFound(name.to_string())),
+        for callback in self.after_timestep_callbacks.iter_mut() {
+            let timestep = &run.timesteps[run.next - 1];
+            callback
+                .after_timestep(timestep, &run.scenario_indices, &run.states)
+                .map_err(|e| e.at_timestep(timestep, None))?;
         }
-    }
 
-    /// Get a `RecorderIndex` from a recorder's name
-    pub fn get_recorder_by_name(&self, name: &str) -> Result<recorders::Recorder, PywrError> {
-        match self.recorders.iter().find(|r| r.name() == name) {
-            Some(recorder) => Ok(recorder.clone()),
-            None => Err(PywrError::RecorderNotFound),
+        if let Some(progress) = &mut self.progress {
+            progress.update(
+                &run.timesteps[run.next - 1],
+                run.next,
+                run.timesteps.len(),
+                run.scenario_indices.len(),
+                run.started.elapsed(),
+            );
         }
-    }
 
-    /// Add a new Node::Input to the model.
-    pub fn add_input_node(&mut self, name: &str) -> Result<Node, PywrError> {
-        // Check for name.
-        if let Ok(node) = self.get_node_by_name(name) {
-            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+        if let Some((path, interval)) = &self.checkpointing {
+            if run.next / interval > count / interval {
+                self.checkpoint(run).write(path)?;
+            }
         }
 
-        // Now add the node to the network.
-        let node_index = self.nodes.len();
-        let node = Node::new_input(&node_index, name);
-        self.nodes.push(node.clone());
-        Ok(node)
+        if let Some((timestep, scenario, path)) = &self.state_dump {
+            if run.next == timestep + 1 {
+                self.dump_state(run, *scenario)?.write(path)?;
+            }
+        }
+        Ok(())
     }
 
-    /// Add a new Node::Link to the model.
-    pub fn add_link_node(&mut self, name: &str) -> Result<Node, PywrError> {
-        // Check for name.
-        if let Ok(node) = self.get_node_by_name(name) {
-            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+    /// Complete `run`, finalising the recorders and returning its results. The solver statistics
+    /// are also retained by the model.
+    ///
+    /// A run may be finished before all of its timesteps are solved, but not once a later run of the
+    /// model has been started.
+    pub fn finish_run(&mut self, run: ModelRun) -> Result<RunResults, PywrError> {
+        self.check_run(&run)?;
+        let _enter = run.span.enter();
+        let solver_stats = run.solvers.stats();
+        self.solver_stats = Some(solver_stats.clone());
+        let cancelled = match &self.run_handle {
+            Some(handle) => handle.take_cancelled(),
+            None => false,
+        };
+        if cancelled {
+            info!(
+                "run cancelled after {} of {} timesteps",
+                run.next.min(run.timesteps.len()) - run.first,
+                run.timesteps.len() - run.first
+            );
         }
+        let started = Instant::now();
+        self.finalise()?;
 
-        // Now add the node to the network.
-        let node_index = self.nodes.len();
-        let node = Node::new_link(&node_index, name);
-        self.nodes.push(node.clone());
-        Ok(node)
+        let statistics = RunStatistics {
+            timesteps: run.next.min(run.timesteps.len()) - run.first,
+            scenarios: run.scenario_indices.len(),
+            wall_time: run.started.elapsed(),
+            timings: RunTimings {
+                recorders: self.recorder_time,
+                finalise: started.elapsed(),
+                ..run.timings
+            },
+            solver_stats,
+            cancelled,
+        };
+        info!(
+            wall_time = ?statistics.wall_time,
+            "simulated {} timesteps of {} scenarios in {:.2?} ({:.0} timesteps per second)",
+            statistics.timesteps,
+            statistics.scenarios,
+            statistics.wall_time,
+            statistics.speed()
+        );
+        Ok(RunResults {
+            statistics,
+            recorders: self.recorders.clone(),
+            aggregated_values: self.aggregated_values.clone(),
+        })
     }
 
-    /// Add a new Node::Link to the model.
-    pub fn add_output_node(&mut self, name: &str) -> Result<Node, PywrError> {
-        // Check for name.
-        if let Ok(node) = self.get_node_by_name(name) {
-            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+    /// The optional solver features that are required to run this model.
+    pub fn required_solver_features(&self) -> Vec<SolverFeature> {
+        let mut features = Vec::new();
+        if !self.mutual_exclusivities.is_empty() {
+            features.push(SolverFeature::IntegerVariables);
         }
+        if self.rolling_horizon > 0 {
+            features.push(SolverFeature::MultiTimestep);
+        }
+        features
+    }
 
-        // Now add the node to the network.
-        let node_index = self.nodes.len();
-        let node = Node::new_output(&node_index, name);
-        self.nodes.push(node.clone());
-        Ok(node)
+    /// Check that `solver` supports all of the features required by this model.
+    pub fn check_solver_features(&self, solver: &dyn Solver) -> Result<(), PywrError> {
+        let missing: Vec<SolverFeature> = self
+            .required_solver_features()
+            .into_iter()
+            .filter(|f| !solver.features().contains(f))
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(PywrError::MissingSolverFeatures(missing))
+        }
     }
 
-    /// Add a new Node::Link to the model.
-    pub fn add_storage_node(&mut self, name: &str, initial_volume: f64) -> Result<Node, PywrError> {
-        // Check for name.
-        if let Ok(node) = self.get_node_by_name(name) {
-            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+    /// Check the data of the parameters that vary by scenario matches this model's scenarios.
+    ///
+    /// This is done at the start of each run, but may be called beforehand to find any
+    /// mismatches (e.g. a parameter with a column for each member of a group that has a
+    /// different number of members) without building the solvers.
+    pub fn check_scenarios(&self) -> Result<(), PywrError> {
+        for parameter in self.parameters.iter() {
+            parameter.setup(&self.scenarios)?;
+        }
+        for variation in self.variations.iter() {
+            variation.check(&self.scenarios)?;
         }
+        Ok(())
+    }
 
-        // Now add the node to the network.
-        let node_index = self.nodes.len();
-        let node = Node::new_storage(&node_index, name, initial_volume);
-        self.nodes.push(node.clone());
-        Ok(node)
+    /// Set the relaxations to apply, in priority order, when a timestep fails to solve.
+    ///
+    /// A failed solve is retried with each relaxation applied cumulatively until it succeeds, and
+    /// the relaxations that were needed are recorded in `relaxed_solves`. If the problem still
+    /// fails with every relaxation applied, the error from the original solve is returned.
+    pub fn set_infeasibility_relaxations(&mut self, relaxations: Vec<Relaxation>) {
+        self.relaxations = relaxations;
     }
 
-    /// Add a `parameters::Parameter` to the model
-    pub fn add_parameter(
+    /// The timesteps and scenarios of the most recent run that were solved with relaxations.
+    pub fn relaxed_solves(&self) -> &[RelaxedSolve] {
+        &self.relaxed_solves
+    }
+
+    /// Re-solve each timestep until the values of `parameters`, which depend on its solution, converge.
+    ///
+    /// After each solve the parameters are recomputed with the trial network state (i.e. the
+    /// flows and end of timestep volumes of the solution), along with any parameters that depend
+    /// on them, and the timestep is solved again. This stops when no parameter changes by more
+    /// than `tolerance` or `max_iterations` solves have been made, and the convergence of every
+    /// timestep and scenario is reported by `iteration_results`. The scenarios are then solved
+    /// separately even if the solver settings batch them, and the timesteps of a rolling horizon
+    /// or timestep window are not iterated.
+    pub fn set_timestep_iteration(
         &mut self,
-        parameter: Box<dyn parameters::_Parameter>,
-    ) -> Result<parameters::Parameter, PywrError> {
-        // TODO reinstate this check
-        // if let Ok(idx) = self.get_parameter_index(&parameter.meta().name) {
-        //     return Err(PywrError::ParameterNameAlreadyExists(
-        //         parameter.meta().name.to_string(),
-        //         idx,
-        //     ));
-        // }
+        parameters: &[parameters::Parameter],
+        max_iterations: usize,
+        tolerance: f64,
+    ) {
+        let parameters = parameters.iter().map(|p| p.index()).collect();
+        self.iteration = Some(TimestepIteration::new(parameters, max_iterations, tolerance));
+    }
 
-        let parameter_index = self.parameters.len();
+    /// The convergence of each timestep and scenario of the most recent run with
+    /// `set_timestep_iteration`, in the order they were solved.
+    pub fn iteration_results(&self) -> &[IterationResult] {
+        &self.iteration_results
+    }
 
-        let p = parameters::Parameter::new(parameter, parameter_index);
-        self.parameters.push(p.clone());
-        Ok(p)
+    /// Set how the available water is allocated between demands; see `AllocationMode`.
+    ///
+    /// Modes other than `AllocationMode::MinimumCost` need several solves per timestep, so the
+    /// scenarios are solved separately even if the solver settings batch them.
+    pub fn set_allocation_mode(&mut self, mode: AllocationMode) {
+        self.allocation_mode = mode;
     }
 
-    /// Add a `recorders::Recorder` to the model
-    pub fn add_recorder(&mut self, recorder: Box<dyn recorders::_Recorder>) -> Result<recorders::Recorder, PywrError> {
-        // TODO reinstate this check
-        // if let Ok(idx) = self.get_recorder_by_name(&recorder.meta().name) {
-        //     return Err(PywrError::RecorderNameAlreadyExists(
-        //         recorder.meta().name.to_string(),
-        //         idx,
-        //     ));
-        // }
+    /// Run the first `timesteps` of each run as a single warm-up shared by all of the scenarios.
+    ///
+    /// Only the first scenario is solved during the warm-up, and its state is then copied to every
+    /// scenario, so the ensemble branches from a common state without repeating the identical
+    /// spin-up for each scenario. The recorders save the warm-up state for every scenario. Any
+    /// parameters that vary by scenario take the values of the first scenario during the warm-up.
+    /// To exclude a warm-up from the recorders instead, see `Timestepper::with_warm_up`.
+    pub fn set_warm_up(&mut self, timesteps: usize) {
+        self.warm_up = timesteps;
+    }
 
-        let recorder_index = self.recorders.len();
-        let r = recorders::Recorder::new(recorder, recorder_index);
-        self.recorders.push(r.clone());
-        Ok(r)
+    /// Solve each timestep with a lookahead of the following `timesteps`, in a rolling horizon.
+    ///
+    /// Each timestep is solved together with its lookahead as a single problem, in which the
+    /// storage volumes carry over from one timestep to the next, and only the flows of the first
+    /// timestep are applied before rolling forward to the next. The lookahead timesteps use the
+    /// forecasts of any `ForecastParameter` issued at the first timestep, and the state at the
+    /// start of the first timestep for any parameters that depend on the state. The lookahead is
+    /// shortened at the end of the run, and zero disables the rolling horizon. If the run has a
+    /// `SolverSettings::timestep_window` the lookahead follows each window, and all of the
+    /// window's timesteps are applied.
+    ///
+    /// This requires a solver that supports `SolverFeature::MultiTimestep`. The rolling horizon
+    /// always allocates at minimum cost, and its timesteps are not retried with relaxations.
+    pub fn set_rolling_horizon(&mut self, timesteps: usize) {
+        self.rolling_horizon = timesteps;
     }
 
-    /// Connect two nodes together
-    pub(crate) fn connect_nodes(&mut self, from_node: &Node, to_node: &Node) -> Result<Edge, PywrError> {
-        // TODO check whether an edge between these two nodes already exists.
+    /// Keep the flows of `node` in the most recent `timesteps` timesteps in the state of each
+    /// scenario, see `NetworkState::get_node_flow_history`.
+    ///
+    /// This is the out-flow of the node, or the in-flow of an output node. The history is shared
+    /// by everything that reads it, and the histories the parameters require are kept without
+    /// this; it is only needed for other uses, e.g. of a coupled model stepping the run.
+    pub fn track_flow_history(&mut self, node: &Node, timesteps: usize) {
+        self.flow_histories.push((node.index(), timesteps));
+    }
 
-        // Self connections are not allowed.
-        if from_node == to_node {
-            return Err(PywrError::InvalidNodeConnection);
-        }
+    /// Start each run from `initial_state`, e.g. the end of a previous run, instead of the initial
+    /// volumes of the storage nodes.
+    pub fn set_initial_state(&mut self, initial_state: Option<InitialState>) {
+        self.initial_state = initial_state;
+    }
 
-        // Next edge index
-        let edge_index = self.edges.len() as EdgeIndex;
-        let edge = Edge::new(&edge_index, from_node, to_node);
+    /// Write a checkpoint of each run to `path` after every `interval` timesteps.
+    ///
+    /// Each checkpoint replaces the previous one, and a run interrupted after it can be resumed
+    /// with `run_from_checkpoint`. An `interval` of zero disables checkpointing.
+    pub fn set_checkpointing(&mut self, path: &Path, interval: usize) {
+        self.checkpointing = match interval {
+            0 => None,
+            _ => Some((path.to_path_buf(), interval)),
+        };
+    }
 
-        // The model can get in a bad state here if the edge is added to the `from_node`
-        // successfully, but fails on the `to_node`.
-        // Suggest to do a check before attempting to add.
-        from_node.add_outgoing_edge(edge.clone())?;
-        to_node.add_incoming_edge(edge.clone())?;
+    /// Write a dump of the state of scenario `scenario` after timestep `timestep` of each run to
+    /// `path`, or stop writing dumps if `path` is `None`.
+    ///
+    /// The state is only available at the end of each step, so a timestep solved before the last
+    /// of a `timestep_window` is not dumped.
+    pub fn set_state_dump(&mut self, timestep: usize, scenario: usize, path: Option<&Path>) {
+        self.state_dump = path.map(|p| (timestep, scenario, p.to_path_buf()));
+    }
 
-        self.edges.push(edge.clone());
+    /// Set the precision the recorders and flow histories store values in.
+    ///
+    /// A recorder's own precision, set with `set_recorder_precision`, takes priority over this.
+    pub fn set_storage_precision(&mut self, precision: Precision) {
+        self.storage_precision = precision;
+    }
 
-        Ok(edge)
+    pub fn storage_precision(&self) -> Precision {
+        self.storage_precision
     }
 
-    /// Add a scenario to the model.
-    pub fn add_scenario(&mut self, name: &str, size: usize) -> Result<(), PywrError> {
-        self.scenarios.add_group(name, size);
-        Ok(())
+    /// Set a reporter to receive progress updates at the given interval during a run.
+    pub fn set_progress_reporter(&mut self, interval: ReportInterval, reporter: Box<dyn ProgressReporter>) {
+        self.progress = Some(ProgressMonitor::new(interval, reporter));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::metric::Metric;
-    use crate::model::Model;
-    use crate::node::{Constraint, ConstraintValue};
-    use crate::recorders::AssertionRecorder;
-    use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
-    use crate::solvers::clp::ClpSolver;
-    use crate::solvers::Solver;
-    use crate::timestep::Timestepper;
+    /// Remove the progress reporter, if any.
+    pub fn clear_progress_reporter(&mut self) {
+        self.progress = None;
+    }
+
+    pub(crate) fn scenarios(&self) -> &ScenarioGroupCollection {
+        &self.scenarios
+    }
+
+    /// Set a handle through which another thread can pause, resume or cancel the runs of `run`
+    /// and `run_from_checkpoint`.
+    pub fn set_run_handle(&mut self, handle: RunHandle) {
+        self.run_handle = Some(handle);
+    }
+
+    /// Add a callback that is called before each timestep is solved, after those added before it.
+    ///
+    /// When several timesteps are solved as one problem it is called once, before the first.
+    pub fn add_before_timestep_callback(&mut self, callback: Box<dyn BeforeTimestepCallback>) {
+        self.before_timestep_callbacks.push(callback);
+    }
+
+    /// Add a callback that is called after each timestep is solved, after those added before it.
+    ///
+    /// When several timesteps are solved as one problem it is called once, after the last.
+    pub fn add_after_timestep_callback(&mut self, callback: Box<dyn AfterTimestepCallback>) {
+        self.after_timestep_callbacks.push(callback);
+    }
+
+    /// Wait while the run is paused by the run handle, and return true if it has been cancelled.
+    fn should_cancel(&self) -> bool {
+        match &self.run_handle {
+            Some(handle) => handle.wait(),
+            None => false,
+        }
+    }
+
+    /// Perform a single timestep with the current state, write the updated states to
+    /// `next_states` and return the parameter states.
+    ///
+    /// The next states are written in place, so that a run can alternate between two buffers of
+    /// states rather than allocating new states each timestep.
+    ///
+    /// The scenarios are independent within a timestep, so their parameters are computed and
+    /// their problems solved in parallel on the threads of `solvers`, each using its own solver
+    /// instance. If the pool batches scenarios, the parameters are computed in parallel and all
+    /// of the problems are then solved in a single call. The recorders are saved in scenario order.
+    pub(crate) fn step(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        solvers: &SolverPool,
+        current_states: &[NetworkState],
+        next_states: &mut [NetworkState],
+    ) -> Result<Vec<ParameterState>, PywrError> {
+        if next_states.len() != scenario_indices.len() {
+            return Err(PywrError::ScenarioStateNotFound);
+        }
+        if solvers.batch_scenarios() && self.allocation_mode == AllocationMode::MinimumCost && self.iteration.is_none()
+        {
+            return self.step_batch(timestep, scenario_indices, solvers, current_states, next_states);
+        }
+
+        let model = &*self;
+        let solutions = solvers.install(|| {
+            scenario_indices
+                .par_iter()
+                .zip(next_states.par_iter_mut())
+                .map(|(scenario_index, next_state)| {
+                    let current_state = match current_states.get(scenario_index.index) {
+                        Some(s) => s,
+                        None => return Err(PywrError::ScenarioStateNotFound),
+                    };
+                    model
+                        .compute_parameters(timestep, scenario_index, current_state)
+                        .and_then(|pstate| {
+                            solvers.with_solver(|solver| {
+                                model.solve_iterated(
+                                    solver,
+                                    timestep,
+                                    scenario_index,
+                                    current_state,
+                                    pstate,
+                                    next_state,
+                                )
+                            })
+                        })
+                        .map_err(|e| e.at_timestep(timestep, Some(scenario_index.index)))
+                })
+                .collect::<Result<Vec<_>, PywrError>>()
+        })?;
+
+        let mut iteration_results = Vec::new();
+        let solutions = solutions
+            .into_iter()
+            .map(|(pstate, relaxed, iteration)| {
+                iteration_results.extend(iteration);
+                (pstate, relaxed)
+            })
+            .collect();
+        self.iteration_results.extend(iteration_results);
+
+        self.save_step(timestep, scenario_indices, next_states, solutions)
+    }
+
+    /// Perform a single warm-up timestep, solving only the first scenario and sharing its state.
+    fn step_warm_up(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        solvers: &SolverPool,
+        current_states: &[NetworkState],
+        next_states: &mut [NetworkState],
+    ) -> Result<Vec<ParameterState>, PywrError> {
+        let (scenario_index, (next_state, other_states)) =
+            match (scenario_indices.first(), next_states.split_first_mut()) {
+                (Some(s), Some(n)) => (s, n),
+                (None, _) => return self.save_step(timestep, scenario_indices, next_states, Vec::new()),
+                (Some(_), None) => return Err(PywrError::ScenarioStateNotFound),
+            };
+        let current_state = match current_states.get(scenario_index.index) {
+            Some(s) => s,
+            None => return Err(PywrError::ScenarioStateNotFound),
+        };
+
+        let model = &*self;
+        let (pstate, relaxed, iteration) = solvers
+            .install(|| {
+                model
+                    .compute_parameters(timestep, scenario_index, current_state)
+                    .and_then(|pstate| {
+                        solvers.with_solver(|solver| {
+                            model.solve_iterated(solver, timestep, scenario_index, current_state, pstate, next_state)
+                        })
+                    })
+            })
+            .map_err(|e| e.at_timestep(timestep, Some(scenario_index.index)))?;
+        self.iteration_results.extend(iteration);
+        for state in other_states.iter_mut() {
+            state.copy_from(next_state);
+        }
+
+        let solutions = scenario_indices
+            .iter()
+            .map(|scenario_index| {
+                let relaxed = relaxed
+                    .as_ref()
+                    .map(|r| RelaxedSolve::new(timestep, scenario_index, &r.relaxations));
+                (pstate.clone(), relaxed)
+            })
+            .collect();
+
+        self.save_step(timestep, scenario_indices, next_states, solutions)
+    }
+
+    /// Perform the first `apply` of `timesteps`, solving them together with the rest as their
+    /// lookahead, and write the states after the last of them to `next_states`.
+    fn step_horizon(
+        &mut self,
+        timesteps: &[Timestep],
+        apply: usize,
+        scenario_indices: &[ScenarioIndex],
+        solvers: &SolverPool,
+        current_states: &[NetworkState],
+        next_states: &mut [NetworkState],
+    ) -> Result<Vec<ParameterState>, PywrError> {
+        let model = &*self;
+        let solutions = solvers.install(|| {
+            scenario_indices
+                .par_iter()
+                .map(|scenario_index| {
+                    let mut state = match current_states.get(scenario_index.index) {
+                        Some(s) => s.clone(),
+                        None => return Err(PywrError::ScenarioStateNotFound),
+                    };
+                    let solve = || {
+                        let (update, pstates) = model.horizon_update(timesteps, apply, scenario_index, &state)?;
+                        let solutions = solvers.with_solver(|solver| solver.solve_horizon(&update))?;
+                        Ok((pstates, solutions))
+                    };
+                    let (pstates, solutions) = solve().map_err(|e: PywrError| match timesteps.first() {
+                        Some(timestep) => e.at_timestep(timestep, Some(scenario_index.index)),
+                        None => e,
+                    })?;
+
+                    let mut steps = Vec::with_capacity(apply);
+                    for ((timestep, solution), pstate) in timesteps.iter().zip(solutions).zip(pstates) {
+                        state = model.network_state_from_solution(timestep, &state, solution)?;
+                        steps.push((state.clone(), pstate));
+                    }
+                    Ok(steps)
+                })
+                .collect::<Result<Vec<_>, PywrError>>()
+        })?;
+
+        let mut pstates = Vec::new();
+        for (i, timestep) in timesteps.iter().take(apply).enumerate() {
+            let mut step_solutions = Vec::with_capacity(solutions.len());
+            for (steps, next_state) in solutions.iter().zip(next_states.iter_mut()) {
+                match steps.get(i) {
+                    Some((state, pstate)) => {
+                        next_state.copy_from(state);
+                        step_solutions.push((pstate.clone(), None));
+                    }
+                    None => return Err(PywrError::ProblemUpdateMismatch),
+                }
+            }
+            pstates = self.save_step(timestep, scenario_indices, next_states, step_solutions)?;
+        }
+        Ok(pstates)
+    }
+
+    /// Compute the problem of `timesteps` solved as one from `network_state`, and the parameter
+    /// states of the first `apply` timesteps.
+    ///
+    /// The timesteps after the first `apply` are a lookahead forecast at the first timestep.
+    fn horizon_update(
+        &self,
+        timesteps: &[Timestep],
+        apply: usize,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+    ) -> Result<(HorizonUpdate, Vec<ParameterState>), PywrError> {
+        let issued = match timesteps.first() {
+            Some(timestep) => timestep.index,
+            None => return Err(PywrError::RunComplete),
+        };
+
+        let mut horizon = HorizonUpdate::default();
+        let mut pstates = Vec::with_capacity(apply);
+        for (i, timestep) in timesteps.iter().enumerate() {
+            let timestep = match i < apply {
+                true => *timestep,
+                false => Timestep {
+                    forecast_from: Some(issued),
+                    ..*timestep
+                },
+            };
+            let pstate = self.compute_parameters(&timestep, scenario_index, network_state)?;
+            let mut update = self.problem_update(&timestep, scenario_index, network_state, &pstate)?;
+
+            // Convert the storage nodes' bounds on their net inflow to bounds on their change in
+            // volume; these are relative to the volume at the start of the first timestep.
+            let days = timestep.days();
+            for node in self.nodes.iter() {
+                if matches!(node.node_type(), NodeType::Storage) {
+                    let bounds = &mut update.node_bounds[*node.index()];
+                    bounds.0 *= days;
+                    if bounds.1 < f64::MAX {
+                        bounds.1 *= days;
+                    }
+                }
+            }
+            horizon.updates.push(update);
+            horizon.days.push(days);
+            if i < apply {
+                pstates.push(pstate);
+            }
+        }
+
+        Ok((horizon, pstates))
+    }
+
+    /// Perform a single timestep, solving all of the scenarios as one combined problem.
+    fn step_batch(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        solvers: &SolverPool,
+        current_states: &[NetworkState],
+        next_states: &mut [NetworkState],
+    ) -> Result<Vec<ParameterState>, PywrError> {
+        let states = scenario_indices
+            .iter()
+            .map(|scenario_index| {
+                current_states
+                    .get(scenario_index.index)
+                    .ok_or(PywrError::ScenarioStateNotFound)
+            })
+            .collect::<Result<Vec<_>, PywrError>>()?;
+
+        let model = &*self;
+        let solutions = solvers.install(|| {
+            let (pstates, updates): (Vec<_>, Vec<_>) = scenario_indices
+                .par_iter()
+                .zip(states.par_iter())
+                .map(|(scenario_index, current_state)| {
+                    let compute = || {
+                        let pstate = model.compute_parameters(timestep, scenario_index, current_state)?;
+                        let update = model.problem_update(timestep, scenario_index, current_state, &pstate)?;
+                        Ok((pstate, update))
+                    };
+                    compute().map_err(|e: PywrError| e.at_timestep(timestep, Some(scenario_index.index)))
+                })
+                .collect::<Result<Vec<_>, PywrError>>()?
+                .into_iter()
+                .unzip();
+
+            match solvers.with_solver(|solver| solver.solve_batch(&updates)) {
+                Ok(solutions) => states
+                    .iter()
+                    .zip(next_states.iter_mut())
+                    .zip(solutions)
+                    .zip(pstates)
+                    .map(|(((current_state, next_state), solution), pstate)| {
+                        model.apply_solution(timestep, current_state, solution, next_state)?;
+                        Ok((pstate, None))
+                    })
+                    .collect::<Result<Vec<_>, PywrError>>(),
+                // Solve each scenario separately, so that only those that fail are relaxed.
+                Err(e) if e.is_infeasible() && !model.relaxations.is_empty() => scenario_indices
+                    .par_iter()
+                    .zip(states.par_iter())
+                    .zip(next_states.par_iter_mut())
+                    .zip(pstates.into_par_iter())
+                    .map(|(((scenario_index, current_state), next_state), pstate)| {
+                        let relaxed = solvers
+                            .with_solver(|solver| {
+                                model.solve_with_relaxations(
+                                    solver,
+                                    timestep,
+                                    scenario_index,
+                                    current_state,
+                                    &pstate,
+                                    next_state,
+                                )
+                            })
+                            .map_err(|e| e.at_timestep(timestep, Some(scenario_index.index)))?;
+                        Ok((pstate, relaxed))
+                    })
+                    .collect::<Result<Vec<_>, PywrError>>(),
+                Err(e) => Err(e),
+            }
+        })?;
+
+        self.save_step(timestep, scenario_indices, next_states, solutions)
+    }
+
+    /// Call the `before` hooks of the parameters and recorders for `timestep`.
+    fn before_timestep(&self, timestep: &Timestep) -> Result<(), PywrError> {
+        for parameter in self.parameters.iter() {
+            parameter
+                .before(timestep)
+                .map_err(|e| e.with_parameter(parameter.name()))?;
+        }
+        for recorder in self.recorders.iter() {
+            recorder
+                .before(timestep)
+                .map_err(|e| e.with_recorder(recorder.name()))?;
+        }
+        Ok(())
+    }
+
+    /// Compute the derived values of the new state and save the recorders for it and the
+    /// parameters of each scenario, call the parameters' `after` hooks, and return the parameter
+    /// states.
+    ///
+    /// Any scenarios that were solved with relaxations are added to `relaxed_solves`.
+    fn save_step(
+        &mut self,
+        timestep: &Timestep,
+        scenario_indices: &[ScenarioIndex],
+        next_states: &mut [NetworkState],
+        solutions: Vec<(ParameterState, Option<RelaxedSolve>)>,
+    ) -> Result<Vec<ParameterState>, PywrError> {
+        let started = Instant::now();
+        let mut pstates = Vec::with_capacity(solutions.len());
+        for ((scenario_index, next_state), (pstate, relaxed)) in scenario_indices.iter().zip(next_states).zip(solutions)
+        {
+            let scenario = Some(scenario_index.index);
+            self.derive_values(next_state, &pstate)
+                .map_err(|e| e.at_timestep(timestep, scenario))?;
+            self.save_recorders(timestep, scenario_index, next_state, &pstate)
+                .map_err(|e| e.at_timestep(timestep, scenario))?;
+            for parameter in self.parameters.iter() {
+                parameter
+                    .after(timestep, scenario_index, next_state, &pstate)
+                    .map_err(|e| e.with_parameter(parameter.name()).at_timestep(timestep, scenario))?;
+            }
+            pstates.push(pstate);
+            self.relaxed_solves.extend(relaxed);
+        }
+
+        self.after_save_recorders(timestep)?;
+        self.recorder_time += started.elapsed();
+        Ok(pstates)
+    }
+
+    /// Write the problem solved for `timestep` and `scenario_index` from `current_state` to `path`.
+    ///
+    /// The problem is solved before it is written, but the outcome of the solve is ignored. This
+    /// allows the problem from a timestep that fails to solve to be inspected.
+    pub fn write_problem(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        solver: &mut dyn Solver,
+        current_state: &NetworkState,
+        path: &Path,
+        format: ProblemFormat,
+    ) -> Result<(), PywrError> {
+        let pstate = self.compute_parameters(timestep, scenario_index, current_state)?;
+        let _ = self.solve(solver, timestep, scenario_index, current_state, &pstate);
+        solver.write_problem(path, format)
+    }
+
+    /// Solve the allocation problem for `timestep` with `solver`, and return the new network state.
+    pub(crate) fn solve(
+        &self,
+        solver: &mut dyn Solver,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<NetworkState, PywrError> {
+        let update = self.problem_update(timestep, scenario_index, network_state, parameter_state)?;
+        let solution = self.solve_update(solver, &update, parameter_state)?;
+        self.network_state_from_solution(timestep, network_state, solution)
+    }
+
+    /// Solve the allocation problem as `solve`, retrying with the model's relaxations if it is
+    /// infeasible.
+    ///
+    /// The new network state is written to `next_state`, and any relaxations that were needed are
+    /// returned.
+    fn solve_with_relaxations(
+        &self,
+        solver: &mut dyn Solver,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+        next_state: &mut NetworkState,
+    ) -> Result<Option<RelaxedSolve>, PywrError> {
+        let mut update = self.problem_update(timestep, scenario_index, network_state, parameter_state)?;
+        let error = match self.solve_update(solver, &update, parameter_state) {
+            Ok(solution) => {
+                self.apply_solution(timestep, network_state, solution, next_state)?;
+                return Ok(None);
+            }
+            // Only an infeasible problem can be solved by relaxing it; other errors are returned.
+            Err(e) if e.is_infeasible() => e,
+            Err(e) => return Err(e),
+        };
+
+        for (i, &relaxation) in self.relaxations.iter().enumerate() {
+            self.relax(&mut update, relaxation, timestep, network_state)?;
+            // Nodes that are switched off in this scenario stay off.
+            self.apply_variations(&mut update.node_bounds, timestep, scenario_index, network_state)?;
+            match self.solve_update(solver, &update, parameter_state) {
+                Ok(solution) => {
+                    self.apply_solution(timestep, network_state, solution, next_state)?;
+                    return Ok(Some(RelaxedSolve::new(
+                        timestep,
+                        scenario_index,
+                        &self.relaxations[..=i],
+                    )));
+                }
+                Err(e) if e.is_infeasible() => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Solve the allocation problem as `solve_with_relaxations`, and then re-solve it until the
+    /// parameters that depend on the solution converge if the model iterates timesteps.
+    ///
+    /// The network state of the final solve is written to `next_state`, and its parameter state
+    /// returned with any relaxations it needed and the convergence of the iterations.
+    fn solve_iterated(
+        &self,
+        solver: &mut dyn Solver,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: ParameterState,
+        next_state: &mut NetworkState,
+    ) -> Result<IteratedSolve, PywrError> {
+        let mut pstate = parameter_state;
+        let mut relaxed =
+            self.solve_with_relaxations(solver, timestep, scenario_index, network_state, &pstate, next_state)?;
+        let iteration = match &self.iteration {
+            Some(iteration) => iteration,
+            None => return Ok((pstate, relaxed, None)),
+        };
+
+        let mut iterations = 1;
+        loop {
+            let trial_pstate = self.compute_trial_parameters(
+                timestep,
+                scenario_index,
+                network_state,
+                next_state,
+                &iteration.parameters,
+            )?;
+            let residual = TimestepIteration::residual(&pstate, &trial_pstate);
+            if residual <= iteration.tolerance || iterations >= iteration.max_iterations {
+                let result = IterationResult::new(timestep, scenario_index, iterations, residual, iteration.tolerance);
+                return Ok((pstate, relaxed, Some(result)));
+            }
+
+            pstate = trial_pstate;
+            relaxed =
+                self.solve_with_relaxations(solver, timestep, scenario_index, network_state, &pstate, next_state)?;
+            iterations += 1;
+        }
+    }
+
+    /// Solve `update` with `solver` using the model's allocation mode.
+    fn solve_update(
+        &self,
+        solver: &mut dyn Solver,
+        update: &ProblemUpdate,
+        parameter_state: &[f64],
+    ) -> Result<ProblemSolution, PywrError> {
+        match self.allocation_mode {
+            AllocationMode::MinimumCost => solver.solve(update),
+            AllocationMode::PriorityProportional => {
+                let classes = self.priority_classes(update, parameter_state)?;
+                solve_priority_proportional(solver, update, &classes)
+            }
+        }
+    }
+
+    /// Group the output nodes with a negative cost by cost, in order of decreasing priority.
+    fn priority_classes(
+        &self,
+        update: &ProblemUpdate,
+        parameter_state: &[f64],
+    ) -> Result<Vec<PriorityClass>, PywrError> {
+        let mut demands = Vec::new();
+        for node in &self.nodes {
+            if !matches!(node.node_type(), NodeType::Output) {
+                continue;
+            }
+            let cost = node.get_current_cost(parameter_state);
+            if cost >= 0.0 {
+                continue;
+            }
+            if update.node_bounds[*node.index()].1 >= f64::MAX {
+                return Err(PywrError::UnboundedPriorityDemand(node.name()));
+            }
+            let edges = node.get_incoming_edges()?.iter().map(|edge| edge.index()).collect();
+            demands.push((cost, node.index(), edges));
+        }
+        demands.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut classes: Vec<(f64, Vec<_>)> = Vec::new();
+        for (cost, node, edges) in demands {
+            match classes.last_mut() {
+                Some((class_cost, class)) if *class_cost == cost => class.push((node, edges)),
+                _ => classes.push((cost, vec![(node, edges)])),
+            }
+        }
+        Ok(classes
+            .into_iter()
+            .map(|(_, class)| PriorityClass::new(class))
+            .collect())
+    }
+
+    /// Apply `relaxation` to the node bounds of `update`.
+    fn relax(
+        &self,
+        update: &mut ProblemUpdate,
+        relaxation: Relaxation,
+        timestep: &Timestep,
+        network_state: &NetworkState,
+    ) -> Result<(), PywrError> {
+        let constraint = match relaxation {
+            Relaxation::MinFlows => Constraint::MinFlow,
+            Relaxation::MaxFlows => Constraint::MaxFlow,
+            Relaxation::MinVolumes => Constraint::MinVolume,
+            Relaxation::MaxVolumes => Constraint::MaxVolume,
+        };
+        for (node, bounds) in self.nodes.iter().zip(update.node_bounds.iter_mut()) {
+            remove_constraint(node, bounds, constraint, timestep, network_state)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the variations of the scenario `scenario_index` to `node_bounds`.
+    ///
+    /// Constraints are removed before nodes are switched off, so a disabled node has no flow
+    /// whatever the other variations.
+    fn apply_variations(
+        &self,
+        node_bounds: &mut [(f64, f64)],
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+    ) -> Result<(), PywrError> {
+        let mut disabled = Vec::new();
+        for scenario_variation in &self.variations {
+            if !scenario_variation.applies(&self.scenarios, scenario_index)? {
+                continue;
+            }
+            match *scenario_variation.variation() {
+                Variation::DisableNode(node) => disabled.push(node),
+                Variation::RemoveConstraint(node, constraint) => {
+                    let bounds = node_bounds.get_mut(*node).ok_or(PywrError::NodeIndexNotFound(node))?;
+                    remove_constraint(&self.nodes[*node], bounds, constraint, timestep, network_state)?;
+                }
+            }
+        }
+        for node in disabled {
+            *node_bounds.get_mut(*node).ok_or(PywrError::NodeIndexNotFound(node))? = (0.0, 0.0);
+        }
+        Ok(())
+    }
+
+    /// Compute the values of the allocation problem that change between solves.
+    fn problem_update(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<ProblemUpdate, PywrError> {
+        let edge_costs = self
+            .edges
+            .iter()
+            .map(|edge| edge.cost(parameter_state))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut node_bounds = self
+            .nodes
+            .iter()
+            .map(|node| {
+                node_constraint_bounds(node, timestep, network_state, parameter_state)
+                    .map_err(|e| e.with_node(node.name()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.apply_variations(&mut node_bounds, timestep, scenario_index, network_state)?;
+
+        let edge_bounds = self
+            .edges
+            .iter()
+            .map(|edge| edge.get_current_flow_bounds(parameter_state))
+            .collect();
+
+        let mut exclusivity_max_flows = Vec::new();
+        for &node_index in self.mutual_exclusivities.iter().flatten() {
+            let node = self
+                .nodes
+                .get(*node_index)
+                .ok_or(PywrError::NodeIndexNotFound(node_index))?;
+            let max_flow = node.get_current_max_flow(parameter_state)?;
+            if max_flow >= f64::MAX {
+                return Err(PywrError::UnboundedMutualExclusivity(node.name()));
+            }
+            exclusivity_max_flows.push(max_flow);
+        }
+
+        let mut flow_factors = Vec::new();
+        for group in &self.flow_factors {
+            flow_factors.extend(group.get_current_factors(parameter_state));
+        }
+
+        let previous_edge_flows = self
+            .edges
+            .iter()
+            .map(|edge| network_state.get_edge_flow(edge.index()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ProblemUpdate {
+            edge_costs,
+            node_bounds,
+            edge_bounds,
+            exclusivity_max_flows,
+            flow_factors,
+            previous_edge_flows,
+        })
+    }
+
+    /// Create the updated network state from a solution of the allocation problem.
+    fn network_state_from_solution(
+        &self,
+        timestep: &Timestep,
+        network_state: &NetworkState,
+        solution: ProblemSolution,
+    ) -> Result<NetworkState, PywrError> {
+        let mut new_state = NetworkState::new();
+        self.apply_solution(timestep, network_state, solution, &mut new_state)?;
+        Ok(new_state)
+    }
+
+    /// Write the updated network state from a solution of the allocation problem to `new_state`,
+    /// reusing its memory.
+    fn apply_solution(
+        &self,
+        timestep: &Timestep,
+        network_state: &NetworkState,
+        solution: ProblemSolution,
+        new_state: &mut NetworkState,
+    ) -> Result<(), PywrError> {
+        new_state.reset_from(network_state);
+        for edge in &self.edges {
+            new_state.add_flow(edge, timestep, solution.edge_flows[*edge.index()])?;
+        }
+        new_state.update_flow_histories()?;
+        if let Some(node_duals) = solution.node_duals {
+            new_state.set_node_duals(node_duals);
+        }
+        if let Some(edge_reduced_costs) = solution.edge_reduced_costs {
+            new_state.set_edge_reduced_costs(edge_reduced_costs);
+        }
+        Ok(())
+    }
+
+    fn compute_parameters(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        state: &NetworkState,
+    ) -> Result<ParameterState, PywrError> {
+        let mut parameter_state = ParameterState::with_capacity(self.parameters.len());
+        for parameter in &self.parameters {
+            let value = parameter
+                .compute_value(timestep, scenario_index, state, &parameter_state)
+                .map_err(|e| e.with_parameter(parameter.name()))?;
+            parameter_state.push_value(value);
+        }
+
+        Ok(parameter_state)
+    }
+
+    /// Compute the parameters with the `iterated` parameters given the `trial_state` from a solve
+    /// of the timestep, and the others the `state` at the start of the timestep.
+    fn compute_trial_parameters(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        state: &NetworkState,
+        trial_state: &NetworkState,
+        iterated: &[parameters::ParameterIndex],
+    ) -> Result<ParameterState, PywrError> {
+        let mut parameter_state = ParameterState::with_capacity(self.parameters.len());
+        for parameter in &self.parameters {
+            let state = match iterated.contains(&parameter.index()) {
+                true => trial_state,
+                false => state,
+            };
+            let value = parameter
+                .compute_value(timestep, scenario_index, state, &parameter_state)
+                .map_err(|e| e.with_parameter(parameter.name()))?;
+            parameter_state.push_value(value);
+        }
+
+        Ok(parameter_state)
+    }
+
+    /// Compute the values derived from the solution of each node in `network_state`.
+    fn derive_values(&self, network_state: &mut NetworkState, parameter_state: &[f64]) -> Result<(), PywrError> {
+        let mut derived_values = std::mem::take(network_state.derived_values_mut());
+        derived_values.clear();
+        for node in &self.nodes {
+            let values = node
+                .derived_values(network_state, parameter_state)
+                .map_err(|e| e.with_node(node.name()))?;
+            derived_values.push(values);
+        }
+        *network_state.derived_values_mut() = derived_values;
+        Ok(())
+    }
+
+    fn save_recorders(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<(), PywrError> {
+        for recorder in self.recorders.iter() {
+            recorder
+                .save(&self, timestep, scenario_index, network_state, parameter_state)
+                .map_err(|e| e.with_recorder(recorder.name()))?;
+        }
+        Ok(())
+    }
+
+    fn after_save_recorders(&self, timestep: &Timestep) -> Result<(), PywrError> {
+        for recorder in self.recorders.iter() {
+            recorder
+                .after_save(timestep)
+                .map_err(|e| e.with_recorder(recorder.name()))?;
+        }
+        Ok(())
+    }
+
+    /// Check the model for problems before it is run, returning the errors and warnings found in
+    /// the order of the nodes.
+    ///
+    /// Input and output nodes without edges are reported as such; other nodes without edges are
+    /// reported as unconnected. Cycles are reported after the problems with single nodes.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for node in &self.nodes {
+            let name = node.name();
+            let incoming = node.get_incoming_edges().map(|e| e.len()).unwrap_or(0);
+            let outgoing = node.get_outgoing_edges().map(|e| e.len()).unwrap_or(0);
+            match node.node_type() {
+                NodeType::Output if incoming == 0 => {
+                    issues.push(ValidationIssue::OutputWithoutIncomingEdges(name.clone()))
+                }
+                NodeType::Input if outgoing == 0 => {
+                    issues.push(ValidationIssue::InputWithoutOutgoingEdges(name.clone()))
+                }
+                _ if incoming + outgoing == 0 => issues.push(ValidationIssue::UnconnectedNode(name.clone())),
+                _ => {}
+            }
+
+            for (attribute, value) in node.attributes() {
+                match value {
+                    ConstraintValue::Parameter(p)
+                        if self.parameters.get(*p.index()).map(|q| q.name()) != Some(p.name()) =>
+                    {
+                        issues.push(ValidationIssue::MissingParameter {
+                            node: name.clone(),
+                            attribute,
+                            parameter: p.index(),
+                        })
+                    }
+                    ConstraintValue::None if attribute == "max_volume" => {
+                        issues.push(ValidationIssue::StorageWithoutMaxVolume(name.clone()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for cycle in graph::cycles(self) {
+            let nodes = cycle.iter().map(|n| self.nodes[**n].name()).collect();
+            issues.push(ValidationIssue::Cycle(nodes));
+        }
+        issues
+    }
+
+    /// The network as a Graphviz DOT graph, to check its topology visually (e.g. with
+    /// `dot -Tsvg`).
+    ///
+    /// Each node is labelled with its name, its type and the cost and constraints that are set,
+    /// given as a number or the name of a parameter. The shape of a node shows its type: inputs
+    /// are inverted triangles, outputs triangles, links ellipses and storages cylinders.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph model {\n");
+        for node in &self.nodes {
+            let (node_type, shape) = match node.node_type() {
+                NodeType::Input => ("input", "invtriangle"),
+                NodeType::Output => ("output", "triangle"),
+                NodeType::Link => ("link", "ellipse"),
+                NodeType::Storage => ("storage", "cylinder"),
+            };
+            let mut label = vec![node.name(), format!("({})", node_type)];
+            for (attribute, value) in node.attributes() {
+                match value {
+                    ConstraintValue::None => {}
+                    ConstraintValue::Scalar(v) => label.push(format!("{}: {}", attribute, v)),
+                    ConstraintValue::Parameter(p) => label.push(format!("{}: {}", attribute, p.name())),
+                }
+            }
+            let label: Vec<String> = label.iter().map(|l| dot_escape(l)).collect();
+            dot.push_str(&format!(
+                "  n{} [label=\"{}\", shape={}];\n",
+                node.index(),
+                label.join("\\n"),
+                shape
+            ));
+        }
+        for edge in &self.edges {
+            dot.push_str(&format!(
+                "  n{} -> n{};\n",
+                edge.from_node_index(),
+                edge.to_node_index()
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The nodes of the model, in the order of their indices.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The edges of the model, in the order of their indices.
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The parameters of the model, in the order of their indices.
+    pub fn parameters(&self) -> &[parameters::Parameter] {
+        &self.parameters
+    }
+
+    /// The recorders of the model, in the order of their indices.
+    pub fn recorders(&self) -> &[recorders::Recorder] {
+        &self.recorders
+    }
+
+    pub fn get_node(&self, index: NodeIndex) -> Result<&Node, PywrError> {
+        self.nodes.get(*index).ok_or(PywrError::NodeIndexNotFound(index))
+    }
+
+    pub fn get_edge(&self, index: EdgeIndex) -> Result<&Edge, PywrError> {
+        self.edges.get(*index).ok_or(PywrError::EdgeIndexNotFound(index))
+    }
+
+    /// The edge from the node `from` to the node `to`.
+    pub fn get_edge_by_node_names(&self, from: &str, to: &str) -> Result<Edge, PywrError> {
+        let from_node = self.get_node_by_name(from)?;
+        let to_node = self.get_node_by_name(to)?;
+        self.edges
+            .iter()
+            .find(|e| e.from_node_index() == from_node.index() && e.to_node_index() == to_node.index())
+            .cloned()
+            .ok_or_else(|| PywrError::EdgeNotFound {
+                from: from.to_string(),
+                to: to.to_string(),
+            })
+    }
+
+    /// The index of the edge from the node `from` to the node `to`.
+    pub fn get_edge_index(&self, from: &str, to: &str) -> Result<EdgeIndex, PywrError> {
+        Ok(self.get_edge_by_node_names(from, to)?.index())
+    }
+
+    pub fn get_parameter(&self, index: ParameterIndex) -> Result<&parameters::Parameter, PywrError> {
+        self.parameters
+            .get(*index)
+            .ok_or(PywrError::ParameterIndexNotFound(index))
+    }
+
+    /// Get a NodeIndex from a node's name
+    pub fn get_node_by_name(&self, name: &str) -> Result<Node, PywrError> {
+        match self.nodes.iter().find(|&n| n.name() == name) {
+            Some(node) => Ok(node.clone()),
+            None => Err(PywrError::NodeNotFound(name.to_string())),
+        }
+    }
+
+    /// The flow into the node called `name` in `state`.
+    pub fn node_in_flow(&self, state: &NetworkState, name: &str) -> Result<f64, PywrError> {
+        state.get_node_in_flow(self.get_node_by_name(name)?.index())
+    }
+
+    /// The flow out of the node called `name` in `state`.
+    pub fn node_out_flow(&self, state: &NetworkState, name: &str) -> Result<f64, PywrError> {
+        state.get_node_out_flow(self.get_node_by_name(name)?.index())
+    }
+
+    /// The volume of the storage node called `name` in `state`.
+    pub fn node_volume(&self, state: &NetworkState, name: &str) -> Result<f64, PywrError> {
+        state.get_node_volume(self.get_node_by_name(name)?.index())
+    }
+
+    /// The value of the parameter called `name` in `parameter_state`.
+    pub fn parameter_value(&self, parameter_state: &ParameterState, name: &str) -> Result<f64, PywrError> {
+        parameter_state.get_value(self.get_parameter_by_name(name)?.index())
+    }
+
+    /// The metric called `metric` (e.g. `"node_inflow"` or `"parameter"`) of the node, edge or
+    /// parameter called `component`. Edges are named by their nodes as `"from->to"`.
+    pub(crate) fn metric_by_name(&self, metric: &str, component: &str) -> Result<Metric, PywrError> {
+        let node = || self.get_node_by_name(component).map(|n| n.index());
+        let edge = || match component.split_once("->") {
+            Some((from, to)) => self.get_edge_index(from.trim(), to.trim()),
+            None => Err(PywrError::InvalidEdgeName(component.to_string())),
+        };
+        Ok(match metric {
+            "node_inflow" => Metric::NodeInFlow(node()?),
+            "node_outflow" => Metric::NodeOutFlow(node()?),
+            "node_volume" => Metric::NodeVolume(node()?),
+            "node_netflow" => Metric::NodeNetFlow(node()?),
+            "node_deficit" => Metric::NodeDeficit(node()?),
+            "node_proportional_volume" => Metric::NodeProportionalVolume(node()?),
+            "node_dual" => Metric::NodeDual(node()?),
+            "edge_flow" => Metric::EdgeFlow(edge()?),
+            "parameter" => Metric::ParameterValue(self.get_parameter_by_name(component)?.index()),
+            "virtual_node" => Metric::VirtualNode(self.get_virtual_node_by_name(component)?.index()),
+            _ => return Err(PywrError::UnrecognisedMetric(metric.to_string())),
+        })
+    }
+
+    /// The name of `metric` and of its node, edge or parameter, as read by `metric_by_name`, or
+    /// `None` if it has no name.
+    pub(crate) fn metric_name(&self, metric: &Metric) -> Option<(&'static str, String)> {
+        let node = |index: &NodeIndex| self.nodes.get(**index).map(|n| n.name());
+        let edge = |index: &EdgeIndex| {
+            let edge = self.edges.get(**index)?;
+            Some(format!(
+                "{}->{}",
+                node(&edge.from_node_index())?,
+                node(&edge.to_node_index())?
+            ))
+        };
+        match metric {
+            Metric::NodeInFlow(n) => Some(("node_inflow", node(n)?)),
+            Metric::NodeOutFlow(n) => Some(("node_outflow", node(n)?)),
+            Metric::NodeVolume(n) => Some(("node_volume", node(n)?)),
+            Metric::NodeNetFlow(n) => Some(("node_netflow", node(n)?)),
+            Metric::NodeDeficit(n) => Some(("node_deficit", node(n)?)),
+            Metric::NodeProportionalVolume(n) => Some(("node_proportional_volume", node(n)?)),
+            Metric::NodeDual(n) => Some(("node_dual", node(n)?)),
+            Metric::EdgeFlow(e) => Some(("edge_flow", edge(e)?)),
+            Metric::ParameterValue(p) => Some(("parameter", self.parameters.get(**p)?.name())),
+            Metric::VirtualNode(v) => Some(("virtual_node", self.virtual_nodes.get(**v)?.name().to_string())),
+            _ => None,
+        }
+    }
+
+    /// Get a `ParameterIndex` from a parameter's name
+    pub fn get_parameter_by_name(&self, name: &str) -> Result<parameters::Parameter, PywrError> {
+        match self.parameters.iter().find(|p| p.name() == name) {
+            Some(parameter) => Ok(parameter.clone()),
+            None => Err(PywrError::ParameterNotFound(name.to_string())),
+        }
+    }
+
+    /// Get a `RecorderIndex` from a recorder's name
+    pub fn get_recorder_by_name(&self, name: &str) -> Result<recorders::Recorder, PywrError> {
+        match self.recorders.iter().find(|r| r.name() == name) {
+            Some(recorder) => Ok(recorder.clone()),
+            None => Err(PywrError::RecorderNotFound(name.to_string())),
+        }
+    }
+
+    /// Get the aggregated value returned by a recorder at the end of the most recent run.
+    pub fn get_aggregated_value(&self, name: &str) -> Result<f64, PywrError> {
+        let recorder = self.get_recorder_by_name(name)?;
+        match self.aggregated_values.get(*recorder.index()) {
+            Some(Some(value)) => Ok(*value),
+            Some(None) => Err(PywrError::NotSupportedByRecorder),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+
+    /// The statistics of the solvers from the most recent run, or `None` if the model has not
+    /// been run to completion.
+    pub fn solver_stats(&self) -> Option<&SolverStats> {
+        self.solver_stats.as_ref()
+    }
+
+    /// Set which timesteps of a run are saved by a recorder.
+    pub fn set_recorder_frequency(&mut self, name: &str, frequency: RecorderFrequency) -> Result<(), PywrError> {
+        let recorder = self.get_recorder_by_name(name)?;
+        recorder.set_frequency(frequency);
+        Ok(())
+    }
+
+    /// Set the precision a recorder stores its values in, in place of the model's storage precision.
+    pub fn set_recorder_precision(&mut self, name: &str, precision: Precision) -> Result<(), PywrError> {
+        let recorder = self.get_recorder_by_name(name)?;
+        recorder.set_precision(precision);
+        Ok(())
+    }
+
+    /// Flag a recorder's aggregated value as an optimisation objective.
+    pub fn set_recorder_objective(&mut self, name: &str, direction: Direction) -> Result<(), PywrError> {
+        let recorder = self.get_recorder_by_name(name)?;
+        self.objectives.retain(|(idx, _)| *idx != recorder.index());
+        self.objectives.push((recorder.index(), direction));
+        Ok(())
+    }
+
+    /// Flag a recorder's aggregated value as an optimisation constraint with the given bounds.
+    pub fn set_recorder_constraint(
+        &mut self,
+        name: &str,
+        lower: Option<f64>,
+        upper: Option<f64>,
+    ) -> Result<(), PywrError> {
+        let recorder = self.get_recorder_by_name(name)?;
+        self.constraints.retain(|(idx, _)| *idx != recorder.index());
+        self.constraints
+            .push((recorder.index(), ConstraintBounds::new(lower, upper)));
+        Ok(())
+    }
+
+    /// Return the values of the objectives from the most recent run.
+    pub fn objectives(&self) -> Result<Vec<ObjectiveValue>, PywrError> {
+        self.objectives
+            .iter()
+            .map(|(idx, direction)| {
+                let name = self.get_recorder_name(*idx)?;
+                Ok(ObjectiveValue {
+                    value: self.get_aggregated_value(&name)?,
+                    name,
+                    direction: *direction,
+                })
+            })
+            .collect()
+    }
+
+    /// Return the values of the constraints from the most recent run.
+    pub fn constraints(&self) -> Result<Vec<ConstraintResult>, PywrError> {
+        self.constraints
+            .iter()
+            .map(|(idx, bounds)| {
+                let name = self.get_recorder_name(*idx)?;
+                Ok(ConstraintResult {
+                    value: self.get_aggregated_value(&name)?,
+                    name,
+                    bounds: *bounds,
+                })
+            })
+            .collect()
+    }
+
+    fn get_recorder_name(&self, index: RecorderIndex) -> Result<String, PywrError> {
+        match self.recorders.get(*index) {
+            Some(recorder) => Ok(recorder.name()),
+            None => Err(PywrError::RecorderIndexNotFound(index)),
+        }
+    }
+
+    /// Add a virtual node, which measures the flows of other nodes without being part of the
+    /// allocation problem; see `VirtualNode`.
+    ///
+    /// The name must not be that of a node or another virtual node.
+    pub fn add_virtual_node(
+        &mut self,
+        name: &str,
+        virtual_node_type: VirtualNodeType,
+    ) -> Result<VirtualNodeIndex, PywrError> {
+        if self.get_node_by_name(name).is_ok() || self.get_virtual_node_by_name(name).is_ok() {
+            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+        }
+
+        let index = VirtualNodeIndex(self.virtual_nodes.len());
+        let virtual_node = VirtualNode::new(index, name, virtual_node_type);
+        for node in virtual_node.nodes() {
+            self.get_node(node)?;
+        }
+        self.virtual_nodes.push(virtual_node);
+        Ok(index)
+    }
+
+    /// The virtual nodes of the model, in the order of their indices.
+    pub fn virtual_nodes(&self) -> &[VirtualNode] {
+        &self.virtual_nodes
+    }
+
+    pub fn get_virtual_node(&self, index: VirtualNodeIndex) -> Result<&VirtualNode, PywrError> {
+        self.virtual_nodes
+            .get(*index)
+            .ok_or(PywrError::VirtualNodeIndexNotFound(index))
+    }
+
+    pub fn get_virtual_node_by_name(&self, name: &str) -> Result<&VirtualNode, PywrError> {
+        self.virtual_nodes
+            .iter()
+            .find(|v| v.name() == name)
+            .ok_or_else(|| PywrError::NodeNotFound(name.to_string()))
+    }
+
+    /// Add a new Node::Input to the model.
+    pub fn add_input_node(&mut self, name: &str) -> Result<Node, PywrError> {
+        // Check for name.
+        if let Ok(node) = self.get_node_by_name(name) {
+            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+        }
+
+        // Now add the node to the network.
+        let node_index = NodeIndex(self.nodes.len());
+        let node = Node::new_input(&node_index, name);
+        self.nodes.push(node.clone());
+        Ok(node)
+    }
+
+    /// Add a new Node::Link to the model.
+    pub fn add_link_node(&mut self, name: &str) -> Result<Node, PywrError> {
+        // Check for name.
+        if let Ok(node) = self.get_node_by_name(name) {
+            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+        }
+
+        // Now add the node to the network.
+        let node_index = NodeIndex(self.nodes.len());
+        let node = Node::new_link(&node_index, name);
+        self.nodes.push(node.clone());
+        Ok(node)
+    }
+
+    /// Add a new Node::Link to the model.
+    pub fn add_output_node(&mut self, name: &str) -> Result<Node, PywrError> {
+        // Check for name.
+        if let Ok(node) = self.get_node_by_name(name) {
+            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+        }
+
+        // Now add the node to the network.
+        let node_index = NodeIndex(self.nodes.len());
+        let node = Node::new_output(&node_index, name);
+        self.nodes.push(node.clone());
+        Ok(node)
+    }
+
+    /// Add a new Node::Link to the model.
+    pub fn add_storage_node(&mut self, name: &str, initial_volume: f64) -> Result<Node, PywrError> {
+        // Check for name.
+        if let Ok(node) = self.get_node_by_name(name) {
+            return Err(PywrError::NodeNameAlreadyExists(name.to_string()));
+        }
+
+        // Now add the node to the network.
+        let node_index = NodeIndex(self.nodes.len());
+        let node = Node::new_storage(&node_index, name, initial_volume);
+        self.nodes.push(node.clone());
+        Ok(node)
+    }
+
+    /// Add a `parameters::Parameter` to the model
+    pub fn add_parameter(
+        &mut self,
+        parameter: Box<dyn parameters::_Parameter>,
+    ) -> Result<parameters::Parameter, PywrError> {
+        // TODO reinstate this check
+        // if let Ok(idx) = self.get_parameter_index(&parameter.meta().name) {
+        //     return Err(PywrError::ParameterNameAlreadyExists(
+        //         parameter.meta().name.to_string(),
+        //         idx,
+        //     ));
+        // }
+
+        let parameter_index = ParameterIndex(self.parameters.len());
+
+        let p = parameters::Parameter::new(parameter, parameter_index);
+        self.parameters.push(p.clone());
+        Ok(p)
+    }
+
+    /// Add a `recorders::Recorder` to the model
+    pub fn add_recorder(&mut self, recorder: Box<dyn recorders::_Recorder>) -> Result<recorders::Recorder, PywrError> {
+        // TODO reinstate this check
+        // if let Ok(idx) = self.get_recorder_by_name(&recorder.meta().name) {
+        //     return Err(PywrError::RecorderNameAlreadyExists(
+        //         recorder.meta().name.to_string(),
+        //         idx,
+        //     ));
+        // }
+
+        let recorder_index = RecorderIndex(self.recorders.len());
+        let r = recorders::Recorder::new(recorder, recorder_index);
+        self.recorders.push(r.clone());
+        Ok(r)
+    }
+
+    /// Set what connecting two nodes that are already connected does; see `DuplicateEdges`.
+    pub fn set_duplicate_edges(&mut self, duplicate_edges: DuplicateEdges) {
+        self.duplicate_edges = duplicate_edges;
+    }
+
+    /// Connect two nodes together
+    pub(crate) fn connect_nodes(&mut self, from_node: &Node, to_node: &Node) -> Result<Edge, PywrError> {
+        // Self connections are not allowed.
+        if from_node == to_node {
+            return Err(PywrError::InvalidNodeConnection);
+        }
+
+        let existing = self
+            .edges
+            .iter()
+            .find(|e| e.from_node_index() == from_node.index() && e.to_node_index() == to_node.index());
+        if let Some(edge) = existing {
+            return match self.duplicate_edges {
+                DuplicateEdges::Error => Err(PywrError::NodeConnectionAlreadyExists {
+                    from: from_node.name(),
+                    to: to_node.name(),
+                }),
+                DuplicateEdges::Reuse => Ok(edge.clone()),
+            };
+        }
+
+        // Next edge index
+        let edge_index = EdgeIndex(self.edges.len());
+        let edge = Edge::new(&edge_index, from_node, to_node);
+
+        // The model can get in a bad state here if the edge is added to the `from_node`
+        // successfully, but fails on the `to_node`.
+        // Suggest to do a check before attempting to add.
+        from_node.add_outgoing_edge(edge.clone())?;
+        to_node.add_incoming_edge(edge.clone())?;
+
+        self.edges.push(edge.clone());
+
+        Ok(edge)
+    }
+
+    /// Make the flows through `nodes` mutually exclusive.
+    ///
+    /// At most one of the nodes may have a non-zero flow in each timestep. The nodes must have a
+    /// finite maximum flow. This requires a solver that supports `SolverFeature::IntegerVariables`.
+    pub fn add_mutual_exclusivity(&mut self, nodes: &[Node]) -> Result<(), PywrError> {
+        if nodes.iter().any(|n| n.node_type() == NodeType::Storage) {
+            return Err(PywrError::FlowConstraintsUndefined);
+        }
+        self.mutual_exclusivities
+            .push(nodes.iter().map(|n| n.index()).collect());
+        Ok(())
+    }
+
+    /// The sets of mutually exclusive nodes.
+    pub fn mutual_exclusivities(&self) -> &[Vec<NodeIndex>] {
+        &self.mutual_exclusivities
+    }
+
+    /// Require the flows through `nodes` to be in proportion to `factors`, e.g. to blend two
+    /// sources in a fixed ratio.
+    ///
+    /// There must be a factor, which may be a parameter, for each of at least two nodes. Storage
+    /// nodes can not be given factors.
+    pub fn add_flow_factors(&mut self, nodes: &[Node], factors: Vec<ConstraintValue>) -> Result<(), PywrError> {
+        if nodes.len() < 2 || nodes.len() != factors.len() {
+            return Err(PywrError::InvalidFlowFactors(format!(
+                "expected a factor for each of at least two nodes, not {} factors for {} nodes",
+                factors.len(),
+                nodes.len()
+            )));
+        }
+        if factors.contains(&ConstraintValue::None) {
+            return Err(PywrError::InvalidFlowFactors(
+                "every node must have a factor".to_string(),
+            ));
+        }
+        if nodes.iter().any(|n| n.node_type() == NodeType::Storage) {
+            return Err(PywrError::FlowConstraintsUndefined);
+        }
+        self.flow_factors.push(FlowFactors {
+            nodes: nodes.iter().map(|n| n.index()).collect(),
+            factors,
+        });
+        Ok(())
+    }
+
+    /// The groups of nodes with flow factors.
+    pub fn flow_factors(&self) -> &[FlowFactors] {
+        &self.flow_factors
+    }
+
+    /// Add a scenario to the model.
+    pub fn add_scenario(&mut self, name: &str, size: usize) -> Result<(), PywrError> {
+        self.scenarios.add_group(name, size)
+    }
+
+    /// Add a scenario to the model with a label for each of its members.
+    pub fn add_labelled_scenario(&mut self, name: &str, labels: &[&str]) -> Result<(), PywrError> {
+        self.scenarios.add_labelled_group(name, labels)
+    }
+
+    /// Run only `members` of the scenario called `name`.
+    pub fn slice_scenario(&mut self, name: &str, members: Range<usize>) -> Result<(), PywrError> {
+        self.scenarios.slice(name, members)
+    }
+
+    /// Run only the given combinations of the members of the scenarios.
+    pub fn set_scenario_combinations(&mut self, combinations: Vec<Vec<usize>>) -> Result<(), PywrError> {
+        self.scenarios.set_combinations(combinations)
+    }
+
+    /// Set the relative likelihood of each member of the scenario called `name`.
+    ///
+    /// The weights are used when recorders aggregate their values over the scenarios.
+    pub fn set_scenario_weights(&mut self, name: &str, weights: Vec<f64>) -> Result<(), PywrError> {
+        self.scenarios.set_weights(name, weights)
+    }
+
+    /// Apply `variation` in the given `members` of the scenario called `name`.
+    ///
+    /// For example, a scenario with the members "without scheme" and "with scheme" can disable the
+    /// nodes of the scheme in its first member, so that both options are run together.
+    pub fn add_scenario_variation(
+        &mut self,
+        name: &str,
+        members: &[usize],
+        variation: Variation,
+    ) -> Result<(), PywrError> {
+        if self.nodes.get(*variation.node()).is_none() {
+            return Err(PywrError::NodeIndexNotFound(variation.node()));
+        }
+        self.variations.push(ScenarioVariation::new(name, members, variation));
+        Ok(())
+    }
+
+    /// Run only a sample of the scenarios; see `ScenarioSampling`.
+    pub fn set_scenario_sampling(&mut self, sampling: ScenarioSampling) -> Result<(), PywrError> {
+        self.scenarios.set_sampling(sampling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocation::AllocationMode;
+    use crate::builder::ModelBuilder;
+    use crate::dump::StateDump;
+    use crate::metric::Metric;
+    use crate::model::Model;
+    use crate::node::{Constraint, ConstraintValue};
+    use crate::progress::RunProgress;
+    use crate::recorders::{AggregatedRecorder, AssertionRecorder, RecorderAggregation};
+    use crate::relaxation::Relaxation;
+    use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+    #[cfg(feature = "clp")]
+    use crate::solvers::clp::{ClpAlgorithm, ClpSettings, ClpSolver};
+    use crate::solvers::default_solver;
+    use crate::solvers::pdlp::PdlpSolver;
+    use crate::solvers::route::RouteSolver;
+    use crate::solvers::simplex::{SimplexError, SimplexSolver};
+    use crate::solvers::{Solver, SolverFeature, SolverSettings};
+    use crate::timestep::{TimestepDuration, Timestepper};
+    use crate::variation::Variation;
+    use chrono::Duration as ChronoDuration;
     use float_cmp::approx_eq;
     use ndarray::prelude::*;
     use ndarray::Array2;
     use std::ops::Deref;
+    use std::sync::{Arc, Mutex};
+    use tempdir::TempDir;
+
+    fn default_timestepper() -> Timestepper {
+        Timestepper::new("2020-01-01", "2020-01-15", "%Y-%m-%d", 1).unwrap()
+    }
+
+    fn default_scenarios() -> ScenarioGroupCollection {
+        let mut scenarios = ScenarioGroupCollection::new();
+        scenarios.add_group("test-scenario", 10).unwrap();
+        scenarios
+    }
+
+    #[test]
+    fn test_simple_model() {
+        let mut model = Model::new();
+
+        let input_node = model.add_input_node("input").unwrap();
+        let link_node = model.add_link_node("link").unwrap();
+        let output_node = model.add_output_node("output").unwrap();
+
+        assert_eq!(*input_node.index(), 0);
+        assert_eq!(*link_node.index(), 1);
+        assert_eq!(*output_node.index(), 2);
+
+        let edge = model.connect_nodes(&input_node, &link_node).unwrap();
+        assert_eq!(*edge.index(), 0);
+        let edge = model.connect_nodes(&link_node, &output_node).unwrap();
+        assert_eq!(*edge.index(), 1);
+
+        // Now assert the internal instructure is as expected.
+        assert_eq!(input_node.get_outgoing_edges().unwrap().len(), 1);
+        assert_eq!(link_node.get_incoming_edges().unwrap().len(), 1);
+        assert_eq!(link_node.get_outgoing_edges().unwrap().len(), 1);
+        assert_eq!(output_node.get_incoming_edges().unwrap().len(), 1);
+    }
+
+    #[test]
+    /// Test a model and its runs can be moved to, and shared between, threads without any unsafe
+    /// `Send` or `Sync` implementations. This does not make the runs of a model independent; see
+    /// `Model`.
+    fn test_model_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Model>();
+        assert_send_sync::<ModelRun>();
+    }
+
+    #[test]
+    /// Test the duplicate node names are not permitted.
+    fn test_duplicate_node_name() {
+        let mut model = Model::new();
+
+        model.add_input_node("my-node").unwrap();
+        // Second add with the same name
+        assert_eq!(
+            model.add_input_node("my-node"),
+            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+        );
+
+        assert_eq!(
+            model.add_link_node("my-node"),
+            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+        );
+
+        assert_eq!(
+            model.add_output_node("my-node"),
+            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+        );
+
+        assert_eq!(
+            model.add_storage_node("my-node", 10.0),
+            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_edges() {
+        let mut model = Model::new();
+        let input_node = model.add_input_node("input").unwrap();
+        let output_node = model.add_output_node("output").unwrap();
+        let edge = model.connect_nodes(&input_node, &output_node).unwrap();
+        assert_eq!(model.get_edge_index("input", "output"), Ok(edge.index()));
+        assert_eq!(
+            model.get_edge_index("output", "input"),
+            Err(PywrError::EdgeNotFound {
+                from: "output".to_string(),
+                to: "input".to_string()
+            })
+        );
+
+        assert_eq!(
+            model.connect_nodes(&input_node, &output_node),
+            Err(PywrError::NodeConnectionAlreadyExists {
+                from: "input".to_string(),
+                to: "output".to_string()
+            })
+        );
+
+        model.set_duplicate_edges(DuplicateEdges::Reuse);
+        assert_eq!(model.connect_nodes(&input_node, &output_node), Ok(edge));
+        assert_eq!(model.edges.len(), 1);
+    }
+
+    /// Create a simple test model with three nodes.
+    fn simple_model() -> Model {
+        ModelBuilder::new()
+            .input("input")
+            .link("link")
+            .output("output")
+            .connect("input", "link")
+            .connect("link", "output")
+            .parameter(parameters::VectorParameter::new("inflow", vec![10.0; 366]))
+            .max_flow("input", "inflow")
+            .constant("base-demand", 10.0)
+            .constant("demand-factor", 1.2)
+            .aggregated(
+                "total-demand",
+                &["base-demand", "demand-factor"],
+                parameters::AggFunc::Product,
+            )
+            .max_flow("output", "total-demand")
+            .constant("demand-cost", -10.0)
+            .cost("output", "demand-cost")
+            .build()
+            .unwrap()
+    }
+
+    /// A test model with a single storage node.
+    fn simple_storage_model() -> Model {
+        ModelBuilder::new()
+            .storage("reservoir", 100.0)
+            .output("output")
+            .connect("reservoir", "output")
+            .constant("demand", 10.0)
+            .max_flow("output", "demand")
+            .constant("demand-cost", -10.0)
+            .cost("output", "demand-cost")
+            .constant("max-volume", 100.0)
+            .max_volume("reservoir", "max-volume")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    /// Test adding a constant parameter to a model.
+    fn test_constant_parameter() {
+        let mut model = Model::new();
+        let node = model.add_input_node("input").unwrap();
+
+        let input_max_flow = parameters::ConstantParameter::new("my-constant", 10.0);
+        let parameter = model.add_parameter(Box::new(input_max_flow)).unwrap();
+        assert_eq!(*parameter.index(), 0);
+        // assign the new parameter to one of the nodes.
+        node.set_constraint(ConstraintValue::Parameter(parameter.clone()), Constraint::MaxFlow)
+            .unwrap();
+
+        // Try to assign a constraint not defined for particular node type
+        assert_eq!(
+            node.set_constraint(ConstraintValue::Parameter(parameter), Constraint::MaxVolume),
+            Err(PywrError::StorageConstraintsUndefined)
+        );
+    }
+
+    #[test]
+    fn test_step() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        let scenarios = default_scenarios();
+        let solver = default_solver();
+
+        let solvers = SolverPool::new(&model, solver.as_ref(), &SolverSettings::default()).unwrap();
+
+        let timesteps = timestepper.timesteps();
+        let mut ts_iter = timesteps.iter();
+        let scenario_indices = scenarios.scenario_indices();
+        let ts = ts_iter.next().unwrap();
+        let current_state = model.get_initial_state(&scenario_indices);
+        assert_eq!(current_state.len(), scenario_indices.len());
+
+        let mut next_state = current_state.clone();
+        model
+            .step(ts, &scenario_indices, &solvers, &current_state, &mut next_state)
+            .unwrap();
+
+        assert_eq!(next_state.len(), scenario_indices.len());
+
+        let output_node = model.get_node_by_name("output").unwrap();
+
+        let state0 = next_state.get(0).unwrap();
+        let output_inflow = state0.get_node_in_flow(output_node.index()).unwrap();
+        assert!(approx_eq!(f64, output_inflow, 10.0));
+    }
+
+    #[test]
+    /// Test a solver is given only the problem update, and rejects one that does not match its setup
+    fn test_problem_update() {
+        let model = simple_model();
+        let timestepper = default_timestepper();
+        let scenario_indices = default_scenarios().scenario_indices();
+        let mut solver = default_solver();
+        solver.setup(&model, &SolverSettings::default()).unwrap();
+
+        let ts = &timestepper.timesteps()[0];
+        let state = &model.get_initial_state(&scenario_indices)[0];
+        let pstate = model.compute_parameters(ts, &scenario_indices[0], state).unwrap();
+        let update = model.problem_update(ts, &scenario_indices[0], state, &pstate).unwrap();
+        assert_eq!(update.edge_costs.len(), model.edges.len());
+        assert_eq!(update.node_bounds.len(), model.nodes.len());
+        assert!(update.exclusivity_max_flows.is_empty());
+        // The output's cost is given to its incoming edge, and its bound is the demand.
+        assert_eq!(update.edge_costs, vec![0.0, -10.0]);
+        assert!(approx_eq!(f64, update.node_bounds[2].1, 12.0));
+
+        let solution = solver.solve(&update).unwrap();
+        assert_eq!(solution.edge_flows.len(), model.edges.len());
+        for flow in &solution.edge_flows {
+            assert!(approx_eq!(f64, *flow, 10.0));
+        }
+
+        let mut mismatched = update.clone();
+        mismatched.node_bounds.pop();
+        assert_eq!(solver.solve(&mismatched), Err(PywrError::ProblemUpdateMismatch));
+    }
+
+    #[test]
+    /// Test an edge's maximum flow and cost constrain the flow between two links
+    fn test_edge_constraints() {
+        let model = ModelBuilder::new()
+            .input("supply")
+            .link("north")
+            .link("south")
+            .output("demand")
+            .connect("supply", "north")
+            .connect("supply", "south")
+            .connect("north", "demand")
+            .connect("south", "demand")
+            .max_flow("supply", 20.0)
+            .max_flow("demand", 15.0)
+            .cost("demand", -10.0)
+            .edge_max_flow("north", "demand", 4.0)
+            .edge_cost("supply", "south", 1.0)
+            .build()
+            .unwrap();
+
+        let edge = model.get_edge_by_node_names("north", "demand").unwrap();
+        assert_eq!(
+            edge.get_constraint(Constraint::MaxFlow),
+            Ok(ConstraintValue::Scalar(4.0))
+        );
+        assert_eq!(edge.get_constraint(Constraint::MinFlow), Ok(ConstraintValue::None));
+        assert_eq!(
+            edge.set_constraint(ConstraintValue::Scalar(1.0), Constraint::MaxVolume),
+            Err(PywrError::StorageConstraintsUndefined)
+        );
+        assert!(model.get_edge_by_node_names("demand", "north").is_err());
+
+        let timestepper = default_timestepper();
+        let scenario_indices = default_scenarios().scenario_indices();
+        let ts = &timestepper.timesteps()[0];
+        let state = &model.get_initial_state(&scenario_indices)[0];
+        let pstate = model.compute_parameters(ts, &scenario_indices[0], state).unwrap();
+        let update = model.problem_update(ts, &scenario_indices[0], state, &pstate).unwrap();
+        assert_eq!(update.edge_bounds[2], (0.0, 4.0));
+        assert_eq!(update.edge_costs[1], 1.0);
+
+        // The cheaper northern route is used up to the capacity of its edge to the demand.
+        let solvers: Vec<Box<dyn Solver>> = vec![default_solver(), Box::new(RouteSolver::new())];
+        for mut solver in solvers {
+            solver.setup(&model, &SolverSettings::default()).unwrap();
+            let solution = solver.solve(&update).unwrap();
+            let expected = [4.0, 11.0, 4.0, 11.0];
+            for (flow, expected) in solution.edge_flows.iter().zip(expected.iter()) {
+                assert!(approx_eq!(f64, *flow, *expected, epsilon = 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    /// Test two sources are blended in the ratio of their flow factors
+    fn test_flow_factors() {
+        let model = ModelBuilder::new()
+            .input("groundwater")
+            .input("river")
+            .link("blend")
+            .output("demand")
+            .connect("groundwater", "blend")
+            .connect("river", "blend")
+            .connect("blend", "demand")
+            .max_flow("groundwater", 20.0)
+            .max_flow("river", 20.0)
+            .cost("river", 1.0)
+            .max_flow("demand", 8.0)
+            .cost("demand", -10.0)
+            .constant("river-factor", 3.0)
+            .flow_factors(
+                &["groundwater", "river"],
+                &[ValueSchema::from(1.0), "river-factor".into()],
+            )
+            .build()
+            .unwrap();
+        assert_eq!(model.flow_factors()[0].nodes().len(), 2);
+
+        let timestepper = default_timestepper();
+        let scenario_indices = default_scenarios().scenario_indices();
+        let ts = &timestepper.timesteps()[0];
+        let state = &model.get_initial_state(&scenario_indices)[0];
+        let pstate = model.compute_parameters(ts, &scenario_indices[0], state).unwrap();
+        let update = model.problem_update(ts, &scenario_indices[0], state, &pstate).unwrap();
+        assert_eq!(update.flow_factors, vec![1.0, 3.0]);
+
+        // The cheaper groundwater is limited to a quarter of the blend.
+        let solvers: Vec<Box<dyn Solver>> = vec![default_solver(), Box::new(RouteSolver::new())];
+        for mut solver in solvers {
+            solver.setup(&model, &SolverSettings::default()).unwrap();
+            let solution = solver.solve(&update).unwrap();
+            let expected = [2.0, 6.0, 8.0];
+            for (flow, expected) in solution.edge_flows.iter().zip(expected.iter()) {
+                assert!(approx_eq!(f64, *flow, *expected, epsilon = 1e-6));
+            }
+        }
+
+        let mut model = Model::new();
+        let node = model.add_input_node("input").unwrap();
+        assert!(matches!(
+            model.add_flow_factors(&[node], vec![ConstraintValue::Scalar(1.0)]),
+            Err(PywrError::InvalidFlowFactors(_))
+        ));
+    }
+
+    #[test]
+    /// Test virtual nodes measure the flows of other nodes without changing the allocation
+    fn test_virtual_nodes() {
+        let mut model = ModelBuilder::new()
+            .input("supply")
+            .link("treatment")
+            .output("north")
+            .output("south")
+            .connect("supply", "treatment")
+            .connect("treatment", "north")
+            .connect("treatment", "south")
+            .max_flow("supply", 10.0)
+            .max_flow("north", 4.0)
+            .cost("north", -10.0)
+            .max_flow("south", 8.0)
+            .cost("south", -5.0)
+            .virtual_sum("demands", &[("north", 1.0), ("south", 1.0)])
+            .virtual_sum("losses", &[("supply", 0.1)])
+            .virtual_balance("zone", &["treatment", "south"])
+            .build()
+            .unwrap();
+        assert_eq!(
+            model.add_virtual_node("north", VirtualNodeType::Balance(vec![])),
+            Err(PywrError::NodeNameAlreadyExists("north".to_string()))
+        );
+        assert_eq!(model.virtual_nodes().len(), 3);
+
+        let timestepper = default_timestepper();
+        let ntimesteps = timestepper.timesteps().len();
+        // The zone receives all of the supply and only passes the north's 4 on.
+        for (name, expected) in [("demands", 10.0), ("losses", 1.0), ("zone", 6.0)].iter() {
+            let metric = model.metric_by_name("virtual_node", name).unwrap();
+            let expected = Array2::from_elem((ntimesteps, 1), *expected);
+            let recorder = AssertionRecorder::new(name, metric, expected);
+            model.add_recorder(Box::new(recorder)).unwrap();
+        }
+        model
+            .run(timestepper, default_solver().as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let metric = Metric::VirtualNode(model.get_virtual_node_by_name("zone").unwrap().index());
+        assert_eq!(model.metric_name(&metric), Some(("virtual_node", "zone".to_string())));
+    }
+
+    #[test]
+    /// Test flow smoothing keeps the previous split of flow between routes of equal cost
+    fn test_flow_smoothing() {
+        let mut model = Model::new();
+        let input_node = model.add_input_node("input").unwrap();
+        let link_a = model.add_link_node("link-a").unwrap();
+        let link_b = model.add_link_node("link-b").unwrap();
+        let output_node = model.add_output_node("output").unwrap();
+        model.connect_nodes(&input_node, &link_a).unwrap();
+        model.connect_nodes(&input_node, &link_b).unwrap();
+        model.connect_nodes(&link_a, &output_node).unwrap();
+        model.connect_nodes(&link_b, &output_node).unwrap();
+        output_node
+            .set_constraint(ConstraintValue::Scalar(10.0), Constraint::MaxFlow)
+            .unwrap();
+        output_node.set_cost(ConstraintValue::Scalar(-10.0));
+
+        let settings = SolverSettings {
+            flow_smoothing: Some(1e-3),
+            ..Default::default()
+        };
+        let mut solver = SimplexSolver::new();
+        solver.setup(&model, &settings).unwrap();
+
+        let timestepper = default_timestepper();
+        let scenario_indices = default_scenarios().scenario_indices();
+        let ts = &timestepper.timesteps()[0];
+        let state = &model.get_initial_state(&scenario_indices)[0];
+        let mut update = model.problem_update(ts, &scenario_indices[0], state, &[]).unwrap();
+
+        for previous in &[vec![7.0, 3.0, 7.0, 3.0], vec![2.0, 8.0, 2.0, 8.0]] {
+            update.previous_edge_flows = previous.clone();
+            let solution = solver.solve(&update).unwrap();
+            for (flow, expected) in solution.edge_flows.iter().zip(previous) {
+                assert!(approx_eq!(f64, *flow, *expected, epsilon = 1e-9));
+            }
+        }
+    }
+
+    #[test]
+    /// Test stepping with several solver instances gives the same states as with one
+    fn test_step_multiple_solvers() {
+        let mut model = simple_model();
+        let scenarios = default_scenarios();
+        let timesteps = default_timestepper().timesteps();
+        let scenario_indices = scenarios.scenario_indices();
+        let current_state = model.get_initial_state(&scenario_indices);
+
+        let pool = |threads| {
+            let settings = SolverSettings {
+                scenario_threads: Some(threads),
+                ..SolverSettings::default()
+            };
+            SolverPool::new(&model, &SimplexSolver::new(), &settings).unwrap()
+        };
+        let single = pool(1);
+        let multiple = pool(3);
+        assert_eq!(multiple.num_threads(), 3);
+
+        let mut expected = current_state.clone();
+        model
+            .step(&timesteps[0], &scenario_indices, &single, &current_state, &mut expected)
+            .unwrap();
+        let mut next_state = current_state.clone();
+        model
+            .step(
+                &timesteps[0],
+                &scenario_indices,
+                &multiple,
+                &current_state,
+                &mut next_state,
+            )
+            .unwrap();
+        assert_eq!(next_state.len(), scenario_indices.len());
+
+        let output_node = model.get_node_by_name("output").unwrap();
+        for (state, expected_state) in next_state.iter().zip(&expected) {
+            assert!(approx_eq!(
+                f64,
+                state.get_node_in_flow(output_node.index()).unwrap(),
+                expected_state.get_node_in_flow(output_node.index()).unwrap()
+            ));
+        }
+    }
+
+    #[test]
+    /// Test solving the scenarios as one combined problem gives the same states, including duals
+    fn test_step_batch_scenarios() {
+        let mut model = simple_model();
+        let scenarios = default_scenarios();
+        let timesteps = default_timestepper().timesteps();
+        let scenario_indices = scenarios.scenario_indices();
+        let current_state = model.get_initial_state(&scenario_indices);
+
+        let single = SolverPool::new(&model, &SimplexSolver::new(), &SolverSettings::default()).unwrap();
+        let settings = SolverSettings {
+            batch_scenarios: true,
+            ..SolverSettings::default()
+        };
+        let batch = SolverPool::new(&model, &SimplexSolver::new(), &settings).unwrap();
+
+        let mut expected = current_state.clone();
+        model
+            .step(&timesteps[0], &scenario_indices, &single, &current_state, &mut expected)
+            .unwrap();
+        let mut next_state = current_state.clone();
+        model
+            .step(
+                &timesteps[0],
+                &scenario_indices,
+                &batch,
+                &current_state,
+                &mut next_state,
+            )
+            .unwrap();
+        assert_eq!(next_state.len(), scenario_indices.len());
+
+        let input_node = model.get_node_by_name("input").unwrap();
+        let output_node = model.get_node_by_name("output").unwrap();
+        let flow = Metric::NodeInFlow(output_node.index());
+        let dual = Metric::NodeDual(input_node.index());
+        for (state, expected_state) in next_state.iter().zip(&expected) {
+            for metric in &[&flow, &dual] {
+                assert!(approx_eq!(
+                    f64,
+                    metric.get_value(&model, state, &[]).unwrap(),
+                    metric.get_value(&model, expected_state, &[]).unwrap(),
+                    epsilon = 1e-6
+                ));
+            }
+        }
+    }
+
+    #[test]
+    /// Test the first-order solver finds the flows to within its tolerance when batching scenarios
+    fn test_step_batch_pdlp() {
+        let mut model = simple_model();
+        let scenarios = default_scenarios();
+        let timesteps = default_timestepper().timesteps();
+        let scenario_indices = scenarios.scenario_indices();
+        let current_state = model.get_initial_state(&scenario_indices);
+
+        let settings = SolverSettings {
+            batch_scenarios: true,
+            ..SolverSettings::default()
+        };
+        let solvers = SolverPool::new(&model, &PdlpSolver::new(), &settings).unwrap();
+        let mut next_state = current_state.clone();
+        model
+            .step(
+                &timesteps[0],
+                &scenario_indices,
+                &solvers,
+                &current_state,
+                &mut next_state,
+            )
+            .unwrap();
+
+        let output_node = model.get_node_by_name("output").unwrap();
+        for state in &next_state {
+            let flow = state.get_node_in_flow(output_node.index()).unwrap();
+            assert!(approx_eq!(f64, flow, 10.0, epsilon = 1e-6));
+        }
+    }
+
+    #[test]
+    /// Test the node duals are available after a step
+    fn test_step_duals() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        let scenarios = default_scenarios();
+        let solver = default_solver();
+
+        let solvers = SolverPool::new(&model, solver.as_ref(), &SolverSettings::default()).unwrap();
+
+        let timesteps = timestepper.timesteps();
+        let scenario_indices = scenarios.scenario_indices();
+        let current_state = model.get_initial_state(&scenario_indices);
+        let mut next_state = current_state.clone();
+        model
+            .step(
+                &timesteps[0],
+                &scenario_indices,
+                &solvers,
+                &current_state,
+                &mut next_state,
+            )
+            .unwrap();
+
+        let input_node = model.get_node_by_name("input").unwrap();
+        let output_node = model.get_node_by_name("output").unwrap();
+        let state0 = next_state.get(0).unwrap();
+
+        // The supply is limiting; another unit of supply would reduce the cost by 10.0.
+        let input_dual = Metric::NodeDual(input_node.index());
+        let output_dual = Metric::NodeDual(output_node.index());
+        assert!(approx_eq!(
+            f64,
+            input_dual.get_value(&model, state0, &[]).unwrap(),
+            -10.0,
+            epsilon = 1e-6
+        ));
+        assert!(approx_eq!(
+            f64,
+            output_dual.get_value(&model, state0, &[]).unwrap(),
+            0.0,
+            epsilon = 1e-6
+        ));
+    }
+
+    #[test]
+    /// Test writing the problem for a single timestep and scenario
+    fn test_write_problem() {
+        let model = simple_model();
+        let timestepper = default_timestepper();
+        let scenarios = default_scenarios();
+        let mut solver = default_solver();
+
+        solver.setup(&model, &SolverSettings::default()).unwrap();
+
+        let timesteps = timestepper.timesteps();
+        let scenario_indices = scenarios.scenario_indices();
+        let current_state = model.get_initial_state(&scenario_indices);
+
+        let tmp_dir = TempDir::new("pywr-problem").unwrap();
+        let path = tmp_dir.path().join("problem.lp");
+        model
+            .write_problem(
+                &timesteps[0],
+                &scenario_indices[0],
+                solver.as_mut(),
+                &current_state[0],
+                &path,
+                ProblemFormat::Lp,
+            )
+            .unwrap();
+
+        let problem = std::fs::read_to_string(&path).unwrap();
+        assert!(problem.contains(" obj: + 0 edge0_input_link - 10 edge1_link_output"));
+        assert!(problem.contains(" node0_input_ub: + 1 edge0_input_link <= 10"));
+        assert!(problem.contains(" node2_output_ub: + 1 edge1_link_output <= 12"));
+    }
+
+    #[cfg(feature = "clp")]
+    #[test]
+    /// Test running a simple model
+    fn test_run() {
+        run_simple_model(&ClpSolver::new(ClpSettings::default()));
+    }
+
+    #[cfg(feature = "clp")]
+    #[test]
+    fn test_run_clp_barrier() {
+        let settings = ClpSettings {
+            algorithm: ClpAlgorithm::Barrier,
+        };
+        run_simple_model(&ClpSolver::new(settings));
+    }
+
+    #[test]
+    fn test_run_simplex() {
+        run_simple_model(&SimplexSolver::new());
+    }
+
+    #[test]
+    /// Test the solver statistics are available after a run
+    fn test_run_solver_stats() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = SimplexSolver::new();
+        assert!(model.solver_stats().is_none());
+
+        model.run(timestepper, &solver, &SolverSettings::default()).unwrap();
+
+        // One solve per timestep and scenario.
+        let stats = model.solver_stats().unwrap();
+        assert_eq!(stats.solves, 15 * 10);
+        assert!(stats.iterations > 0);
+        assert!(stats.factorisations >= stats.solves as u64);
+
+        let settings = SolverSettings {
+            batch_scenarios: true,
+            ..Default::default()
+        };
+        model.run(default_timestepper(), &solver, &settings).unwrap();
+        assert_eq!(model.solver_stats().unwrap().solves, 15);
+    }
+
+    #[test]
+    /// Test the statistics and recorders of a run are returned.
+    fn test_run_results() {
+        let mut model = simple_model();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let metric = model.metric_by_name("node_inflow", "output").unwrap();
+        let recorder = AggregatedRecorder::new("total", metric, RecorderAggregation::Sum, RecorderAggregation::Mean);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let results = model
+            .run(default_timestepper(), &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        let statistics = &results.statistics;
+        assert_eq!((statistics.timesteps, statistics.scenarios), (15, 10));
+        assert_eq!(statistics.solver_stats.solves, 15 * 10);
+        assert!(statistics.timings.simulation >= statistics.timings.recorders);
+        assert!(statistics.wall_time >= statistics.timings.setup + statistics.timings.simulation);
+        assert!(statistics.speed() > 0.0);
+
+        assert_eq!(results.recorders().len(), 1);
+        assert!(approx_eq!(f64, results.get_aggregated_value("total").unwrap(), 150.0));
+        assert_eq!(
+            results.get_aggregated_value("deficit"),
+            Err(PywrError::RecorderNotFound("deficit".to_string()))
+        );
+    }
+
+    #[test]
+    /// Test the storage volume accounts for the length of weekly timesteps
+    fn test_run_weekly_storage() {
+        let mut model = simple_storage_model();
+        let timestepper =
+            Timestepper::with_duration("2020-01-01", "2020-01-21", "%Y-%m-%d", TimestepDuration::Days(7)).unwrap();
+
+        // A week of the daily demand of 10.0 leaves 30.0, which is then released over the next week.
+        let idx = model.get_node_by_name("reservoir").unwrap().index();
+        let expected = Array2::from_shape_fn((3, 1), |(i, _)| [30.0, 0.0, 0.0][i]);
+        let recorder = AssertionRecorder::new("reservoir-volume", Metric::NodeVolume(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let idx = model.get_node_by_name("output").unwrap().index();
+        let expected = Array2::from_shape_fn((3, 1), |(i, _)| [10.0, 30.0 / 7.0, 0.0][i]);
+        let recorder = AssertionRecorder::new("output-flow", Metric::NodeInFlow(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+    }
+
+    #[test]
+    /// Test the storage volume accounts for the fraction of a day of six-hourly timesteps
+    fn test_run_sub_daily_storage() {
+        let mut model = simple_storage_model();
+        let duration = TimestepDuration::Fixed(ChronoDuration::hours(6));
+        let timestepper = Timestepper::with_duration("2020-01-01", "2020-01-01", "%Y-%m-%d", duration).unwrap();
+
+        // The daily demand of 10.0 is supplied at that rate for each quarter of the day.
+        let idx = model.get_node_by_name("reservoir").unwrap().index();
+        let expected = Array2::from_shape_fn((4, 1), |(i, _)| 100.0 - 2.5 * (i + 1) as f64);
+        let recorder = AssertionRecorder::new("reservoir-volume", Metric::NodeVolume(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+    }
+
+    #[test]
+    /// Test a run stepped one timestep at a time, with the state changed between steps
+    fn test_step_run() {
+        let mut model = simple_storage_model();
+        model.add_scenario("test-scenario", 2).unwrap();
+        let solver = SimplexSolver::new();
+        let reservoir = model.get_node_by_name("reservoir").unwrap().index();
+
+        // The reservoir is refilled by a coupled model after the third timestep.
+        let expected = Array2::from_shape_fn((15, 2), |(i, _)| {
+            if i < 3 {
+                90.0 - 10.0 * i as f64
+            } else {
+                (90.0 - 10.0 * (i - 3) as f64).max(0.0)
+            }
+        });
+        let recorder = AssertionRecorder::new("reservoir-volume", Metric::NodeVolume(reservoir), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let timestepper = default_timestepper();
+        let mut run = model
+            .start_run(&timestepper, &solver, &SolverSettings::default())
+            .unwrap();
+        assert_eq!(run.states().len(), 2);
+        assert_eq!(run.next_timestep().unwrap().index, 0);
+        while !run.is_complete() {
+            model.step_run(&mut run).unwrap();
+            if run.next_timestep().map(|ts| ts.index) == Some(3) {
+                assert!(approx_eq!(
+                    f64,
+                    run.states()[1].get_node_volume(reservoir).unwrap(),
+                    70.0
+                ));
+                for state in run.states_mut() {
+                    state.set_node_volume(reservoir, 100.0).unwrap();
+                }
+            }
+        }
+        assert_eq!(model.step_run(&mut run), Err(PywrError::RunComplete));
+        model.finish_run(run).unwrap();
+        assert_eq!(model.solver_stats().unwrap().solves, 15 * 2);
+    }
+
+    #[test]
+    /// Test a run that is not finished cannot be continued once a later run of the model is started
+    fn test_superseded_run() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        let solver = SimplexSolver::new();
+
+        let mut first = model
+            .start_run(&timestepper, &solver, &SolverSettings::default())
+            .unwrap();
+        model.step_run(&mut first).unwrap();
+        let mut second = model
+            .start_run(&timestepper, &solver, &SolverSettings::default())
+            .unwrap();
+
+        assert_eq!(model.step_run(&mut first), Err(PywrError::RunSuperseded));
+        assert_eq!(model.finish_run(first).unwrap_err(), PywrError::RunSuperseded);
+        model.step_run(&mut second).unwrap();
+        model.finish_run(second).unwrap();
+    }
+
+    #[test]
+    /// Test the delay and rolling mean parameters read the flow history of their node
+    fn test_flow_history() {
+        let mut model = Model::new();
+        let input_node = model.add_input_node("input").unwrap();
+        let output_node = model.add_output_node("output").unwrap();
+        model.connect_nodes(&input_node, &output_node).unwrap();
+
+        // The inflow is 10, 20, 30, ... and is all taken by the output.
+        let inflow = parameters::VectorParameter::new("inflow", (1..=366).map(|i| 10.0 * i as f64).collect());
+        let inflow = model.add_parameter(Box::new(inflow)).unwrap();
+        input_node
+            .set_constraint(ConstraintValue::Parameter(inflow), Constraint::MaxFlow)
+            .unwrap();
+        let cost = parameters::ConstantParameter::new("cost", -10.0);
+        output_node.set_cost(ConstraintValue::Parameter(model.add_parameter(Box::new(cost)).unwrap()));
+
+        let delay = parameters::DelayParameter::new("delay", output_node.index(), 2, 0.0);
+        let delay = model.add_parameter(Box::new(delay)).unwrap();
+        let mean = parameters::RollingMeanParameter::new("mean", output_node.index(), 3, 0.0);
+        let mean = model.add_parameter(Box::new(mean)).unwrap();
+
+        let expected = Array2::from_shape_fn((15, 1), |(i, _)| match i {
+            0 | 1 => 0.0,
+            _ => 10.0 * (i - 1) as f64,
+        });
+        let recorder = AssertionRecorder::new("delay", Metric::ParameterValue(delay.index()), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+        let expected = Array2::from_shape_fn((15, 1), |(i, _)| match i {
+            0 => 0.0,
+            1 => 10.0,
+            2 => 15.0,
+            _ => 10.0 * (i - 1) as f64,
+        });
+        let recorder = AssertionRecorder::new("mean", Metric::ParameterValue(mean.index()), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let mut run = model
+            .start_run(
+                &default_timestepper(),
+                &SimplexSolver::new(),
+                &SolverSettings::default(),
+            )
+            .unwrap();
+        while !run.is_complete() {
+            model.step_run(&mut run).unwrap();
+        }
+        // The history is shared by both parameters and kept for the longest of them.
+        let history = run.states()[0].get_node_flow_history(output_node.index()).unwrap();
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![150.0, 140.0, 130.0]);
+        assert_eq!(
+            run.states()[0].get_node_flow_history(input_node.index()),
+            Err(PywrError::FlowHistoryNotTracked(input_node.index()))
+        );
+        model.finish_run(run).unwrap();
+    }
+
+    #[test]
+    /// Test the state of a run can be queried by node and parameter name
+    fn test_query_by_name() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        let mut run = model
+            .start_run(&timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        assert!(run.parameter_states().is_empty());
+        model.step_run(&mut run).unwrap();
 
-    fn default_timestepper() -> Timestepper {
-        Timestepper::new("2020-01-01", "2020-01-15", "%Y-%m-%d", 1).unwrap()
+        let state = &run.states()[0];
+        assert_eq!(model.node_out_flow(state, "input"), Ok(10.0));
+        assert_eq!(model.node_in_flow(state, "output"), Ok(10.0));
+        assert_eq!(
+            model.node_volume(state, "link"),
+            Err(PywrError::MetricNotDefinedForNode)
+        );
+        assert_eq!(
+            model.node_in_flow(state, "reservoir"),
+            Err(PywrError::NodeNotFound("reservoir".to_string()))
+        );
+
+        let parameter_state = &run.parameter_states()[0];
+        assert!(approx_eq!(
+            f64,
+            model.parameter_value(parameter_state, "total-demand").unwrap(),
+            12.0
+        ));
+        assert_eq!(
+            model.parameter_value(parameter_state, "demand"),
+            Err(PywrError::ParameterNotFound("demand".to_string()))
+        );
+
+        let metric = model.metric_by_name("edge_flow", "link->output").unwrap();
+        assert_eq!(metric.get_value(&model, state, parameter_state), Ok(10.0));
+        assert_eq!(
+            model.metric_name(&metric),
+            Some(("edge_flow", "link->output".to_string()))
+        );
+        assert!(matches!(
+            model.metric_by_name("edge_flow", "output->link"),
+            Err(PywrError::EdgeNotFound { .. })
+        ));
+        assert!(matches!(
+            model.metric_by_name("edge_flow", "link"),
+            Err(PywrError::InvalidEdgeName(_))
+        ));
     }
 
-    fn default_scenarios() -> ScenarioGroupCollection {
-        let mut scenarios = ScenarioGroupCollection::new();
-        scenarios.add_group("test-scenario", 10);
-        scenarios
+    #[test]
+    /// Test the components of a model can be inspected.
+    fn test_introspection() {
+        let model = simple_model();
+        let names: Vec<String> = model.nodes().iter().map(|n| n.name()).collect();
+        assert_eq!(names, vec!["input", "link", "output"]);
+        assert_eq!(model.nodes()[2].node_type(), NodeType::Output);
+
+        let edge = &model.edges()[1];
+        assert_eq!(model.get_node(edge.from_node_index()).unwrap().name(), "link");
+        assert_eq!(model.get_node(edge.to_node_index()).unwrap().name(), "output");
+        assert_eq!(
+            model.get_edge(EdgeIndex(2)).err(),
+            Some(PywrError::EdgeIndexNotFound(EdgeIndex(2)))
+        );
+
+        let output = model.get_node_by_name("output").unwrap();
+        let total_demand = model.get_parameter_by_name("total-demand").unwrap();
+        assert_eq!(
+            output.get_constraint(Constraint::MaxFlow),
+            Ok(ConstraintValue::Parameter(total_demand.clone()))
+        );
+        assert_eq!(output.get_constraint(Constraint::MinFlow), Ok(ConstraintValue::None));
+        assert_eq!(
+            output.get_constraint(Constraint::MaxVolume),
+            Err(PywrError::StorageConstraintsUndefined)
+        );
+        assert!(matches!(output.get_cost(), ConstraintValue::Parameter(_)));
+
+        assert_eq!(model.parameters().len(), 5);
+        assert_eq!(
+            model.get_parameter(total_demand.index()).unwrap().name(),
+            "total-demand"
+        );
+        assert!(model.recorders().is_empty());
     }
 
     #[test]
-    fn test_simple_model() {
-        let mut model = Model::new();
+    /// Test the network of a model as a DOT graph.
+    fn test_to_dot() {
+        let mut model = simple_model();
+        model.add_storage_node("\"tank\"", 0.0).unwrap();
+        let dot = model.to_dot();
+        assert!(dot.starts_with("digraph model {\n"));
+        assert!(dot.contains("  n0 [label=\"input\\n(input)\\nmax_flow: inflow\", shape=invtriangle];\n"));
+        assert!(dot.contains(
+            "  n2 [label=\"output\\n(output)\\ncost: demand-cost\\nmax_flow: total-demand\", shape=triangle];\n"
+        ));
+        assert!(dot.contains("  n3 [label=\"\\\"tank\\\"\\n(storage)\", shape=cylinder];\n"));
+        assert!(dot.contains("  n0 -> n1;\n  n1 -> n2;\n"));
+        assert!(dot.ends_with("}\n"));
+    }
 
-        let input_node = model.add_input_node("input").unwrap();
-        let link_node = model.add_link_node("link").unwrap();
-        let output_node = model.add_output_node("output").unwrap();
+    #[test]
+    /// Test the problems found in a model before it is run.
+    fn test_validate() {
+        assert_eq!(simple_model().validate(), Vec::new());
+        assert_eq!(simple_storage_model().validate(), Vec::new());
 
-        assert_eq!(input_node.index(), 0);
-        assert_eq!(link_node.index(), 1);
-        assert_eq!(output_node.index(), 2);
+        let mut model = simple_model();
+        model.add_input_node("spring").unwrap();
+        model.add_output_node("sink").unwrap();
+        model.add_link_node("canal").unwrap();
+        model.add_storage_node("tank", 0.0).unwrap();
 
-        let edge = model.connect_nodes(&input_node, &link_node).unwrap();
-        assert_eq!(edge.index(), 0);
-        let edge = model.connect_nodes(&link_node, &output_node).unwrap();
-        assert_eq!(edge.index(), 1);
+        // A parameter of another model, which is not in this one.
+        let mut other = simple_model();
+        let missing = parameters::ConstantParameter::new("missing", 1.0);
+        let missing = other.add_parameter(Box::new(missing)).unwrap();
+        let link_node = model.get_node_by_name("link").unwrap();
+        link_node
+            .set_max_flow_constraint(ConstraintValue::Parameter(missing.clone()))
+            .unwrap();
 
-        // Now assert the internal instructure is as expected.
-        assert_eq!(input_node.get_outgoing_edges().unwrap().len(), 1);
-        assert_eq!(link_node.get_incoming_edges().unwrap().len(), 1);
-        assert_eq!(link_node.get_outgoing_edges().unwrap().len(), 1);
-        assert_eq!(output_node.get_incoming_edges().unwrap().len(), 1);
+        let issues = model.validate();
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::MissingParameter {
+                    node: "link".to_string(),
+                    attribute: "max_flow",
+                    parameter: missing.index(),
+                },
+                ValidationIssue::InputWithoutOutgoingEdges("spring".to_string()),
+                ValidationIssue::OutputWithoutIncomingEdges("sink".to_string()),
+                ValidationIssue::UnconnectedNode("canal".to_string()),
+                ValidationIssue::UnconnectedNode("tank".to_string()),
+                ValidationIssue::StorageWithoutMaxVolume("tank".to_string()),
+            ]
+        );
+        assert_eq!(issues.iter().filter(|i| i.is_error()).count(), 2);
+        assert_eq!(issues[3].to_string(), "node `canal` is not connected to any other node");
+
+        let canal = model.get_node_by_name("canal").unwrap();
+        model.connect_nodes(&link_node, &canal).unwrap();
+        model.connect_nodes(&canal, &link_node).unwrap();
+        assert_eq!(
+            model.validate().last(),
+            Some(&ValidationIssue::Cycle(vec!["link".to_string(), "canal".to_string()]))
+        );
     }
 
     #[test]
-    /// Test the duplicate node names are not permitted.
-    fn test_duplicate_node_name() {
-        let mut model = Model::new();
+    /// Test the values derived from each solve are stored in the state.
+    fn test_derived_values() {
+        let mut model = simple_model();
+        let output_node = model.get_node_by_name("output").unwrap();
+        let timestepper = default_timestepper();
+        let mut run = model
+            .start_run(&timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        assert_eq!(
+            run.states()[0].get_node_deficit(output_node.index()),
+            Err(PywrError::DerivedValuesNotAvailable)
+        );
+        model.step_run(&mut run).unwrap();
 
-        model.add_input_node("my-node").unwrap();
-        // Second add with the same name
+        // The demand of 12 is limited by the inflow of 10.
+        let state = &run.states()[0];
+        assert!(approx_eq!(
+            f64,
+            state.get_node_deficit(output_node.index()).unwrap(),
+            2.0
+        ));
         assert_eq!(
-            model.add_input_node("my-node"),
-            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+            state.get_node_proportional_volume(output_node.index()),
+            Err(PywrError::MetricNotDefinedForNode)
         );
 
+        let mut model = simple_storage_model();
+        let storage_node = model.get_node_by_name("reservoir").unwrap();
+        let mut run = model
+            .start_run(&timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        model.step_run(&mut run).unwrap();
+        let state = &run.states()[0];
+        assert!(approx_eq!(
+            f64,
+            state.get_node_proportional_volume(storage_node.index()).unwrap(),
+            0.9
+        ));
+        assert!(approx_eq!(
+            f64,
+            Metric::NodeProportionalVolume(storage_node.index())
+                .get_value(&model, state, run.parameter_states()[0].as_ref())
+                .unwrap(),
+            0.9
+        ));
+    }
+
+    #[test]
+    /// Test dumping the state of a run and comparing dumps.
+    fn test_dump_state() {
+        let mut model = simple_model();
+        let dir = TempDir::new("dump").unwrap();
+        let path = dir.path().join("state.json");
+        model.set_state_dump(1, 0, Some(&path));
+
+        let timestepper = default_timestepper();
+        let mut run = model
+            .start_run(&timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        let initial = model.dump_state(&run, 0).unwrap();
+        assert_eq!(initial.timestep, None);
+        assert!(!initial.values.iter().any(|(k, _)| k.starts_with("parameter/")));
+
+        model.step_run(&mut run).unwrap();
+        let first = model.dump_state(&run, 0).unwrap();
+        assert!(!path.exists());
+        model.step_run(&mut run).unwrap();
+        let second = model.dump_state(&run, 0).unwrap();
+        assert_eq!(StateDump::read(&path).unwrap(), second);
+        assert_eq!(model.dump_state(&run, 1), Err(PywrError::ScenarioIndexNotFound));
+
+        assert_eq!(second.timestep, Some(1));
+        assert_eq!(second.date.as_deref(), Some("2020-01-02"));
+        let value = |key: &str| second.values.iter().find(|(k, _)| k == key).map(|(_, v)| *v);
+        assert_eq!(value("node/output/in_flow"), Some(10.0));
+        assert_eq!(value("node/output/deficit"), Some(2.0));
+        assert_eq!(value("node/link/volume"), None);
+        assert_eq!(value("edge/input/link/flow"), Some(10.0));
+        assert!(approx_eq!(f64, value("parameter/total-demand").unwrap(), 12.0));
+
+        // The model is the same in every timestep.
+        assert!(first.diff(&second, 1e-9).is_empty());
+        let differences = initial.diff(&second, 1e-9);
+        assert_eq!(differences[0].key, "node/input/out_flow");
+    }
+
+    /// A test model of a reservoir supplying a high and a low priority demand.
+    ///
+    /// The high priority demand is in the fourth to sixth timesteps, and is forecast by `forecasts`.
+    fn anticipation_model(forecasts: Array2<f64>) -> Model {
+        let mut model = Model::new();
+        let reservoir = model.add_storage_node("reservoir", 100.0).unwrap();
+        let low = model.add_output_node("low").unwrap();
+        let high = model.add_output_node("high").unwrap();
+        model.connect_nodes(&reservoir, &low).unwrap();
+        model.connect_nodes(&reservoir, &high).unwrap();
+
+        let max_volume = parameters::ConstantParameter::new("max-volume", 100.0);
+        let max_volume = model.add_parameter(Box::new(max_volume)).unwrap();
+        reservoir
+            .set_constraint(ConstraintValue::Parameter(max_volume), Constraint::MaxVolume)
+            .unwrap();
+
+        let low_demand = parameters::ConstantParameter::new("low-demand", 10.0);
+        let low_demand = model.add_parameter(Box::new(low_demand)).unwrap();
+        low.set_constraint(ConstraintValue::Parameter(low_demand), Constraint::MaxFlow)
+            .unwrap();
+        low.set_cost(ConstraintValue::Scalar(-1.0));
+
+        let observed = Array1::from_shape_fn(15, |i| if (3..6).contains(&i) { 30.0 } else { 0.0 });
+        let high_demand = parameters::ForecastParameter::new("high-demand", observed, forecasts);
+        let high_demand = model.add_parameter(Box::new(high_demand)).unwrap();
+        high.set_constraint(ConstraintValue::Parameter(high_demand), Constraint::MaxFlow)
+            .unwrap();
+        high.set_cost(ConstraintValue::Scalar(-10.0));
+
+        model
+    }
+
+    /// The supply to the high priority demand of `anticipation_model` for each timestep of a run
+    /// with a rolling horizon of `lookahead` timesteps.
+    fn rolling_horizon_supply(lookahead: usize, forecasts: Array2<f64>) -> Vec<f64> {
+        let mut model = anticipation_model(forecasts);
+        let high = model.get_node_by_name("high").unwrap();
+        model.set_rolling_horizon(lookahead);
+        let solver = SimplexSolver::new();
+        let mut run = model
+            .start_run(&default_timestepper(), &solver, &SolverSettings::default())
+            .unwrap();
+        let mut supply = Vec::new();
+        while !run.is_complete() {
+            model.step_run(&mut run).unwrap();
+            supply.push(run.states()[0].get_node_in_flow(high.index()).unwrap());
+        }
+        model.finish_run(run).unwrap();
+        supply
+    }
+
+    #[test]
+    /// Test a rolling horizon conserves water for a forecast demand
+    fn test_rolling_horizon() {
+        // Perfect forecasts of the high priority demand.
+        let forecasts = Array2::from_shape_fn(
+            (15, 5),
+            |(i, lead)| {
+                if (3..6).contains(&(i + lead + 1)) {
+                    30.0
+                } else {
+                    0.0
+                }
+            },
+        );
+        let supply = rolling_horizon_supply(5, forecasts);
+        for &s in &supply[3..6] {
+            assert!(approx_eq!(f64, s, 30.0, epsilon = 1e-6));
+        }
+
+        // Without a forecast of the demand the reservoir is drawn down by the low priority demand first.
+        let supply = rolling_horizon_supply(5, Array2::zeros((15, 5)));
+        assert!(supply[5] < 30.0);
+        let supply = rolling_horizon_supply(0, Array2::zeros((15, 5)));
+        let mut expected = vec![0.0; 15];
+        expected[3] = 30.0;
+        expected[4] = 30.0;
+        for (&s, &e) in supply.iter().zip(&expected) {
+            assert!(approx_eq!(f64, s, e, epsilon = 1e-6));
+        }
+
+        let mut model = simple_storage_model();
+        model.set_rolling_horizon(2);
+        assert_eq!(model.required_solver_features(), vec![SolverFeature::MultiTimestep]);
         assert_eq!(
-            model.add_link_node("my-node"),
-            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+            model
+                .run(default_timestepper(), &RouteSolver::new(), &SolverSettings::default())
+                .err(),
+            Some(PywrError::MissingSolverFeatures(vec![SolverFeature::MultiTimestep]))
         );
+    }
+
+    #[test]
+    /// Test consecutive timesteps solved as one problem conserve water for a later demand
+    fn test_run_timestep_window() {
+        let mut model = anticipation_model(Array2::zeros((15, 0)));
+        let high = model.get_node_by_name("high").unwrap().index();
+        let expected = Array2::from_shape_fn((15, 1), |(i, _)| if (3..6).contains(&i) { 30.0 } else { 0.0 });
+        let recorder = AssertionRecorder::new("high-supply", Metric::NodeInFlow(high), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let settings = SolverSettings {
+            timestep_window: Some(6),
+            ..Default::default()
+        };
+        model
+            .run(default_timestepper(), &SimplexSolver::new(), &settings)
+            .unwrap();
+        // Two windows of six timesteps, and the remaining three timesteps.
+        assert_eq!(model.solver_stats().unwrap().solves, 3);
 
         assert_eq!(
-            model.add_output_node("my-node"),
-            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+            model.run(default_timestepper(), &RouteSolver::new(), &settings).err(),
+            Some(PywrError::MissingSolverFeatures(vec![SolverFeature::MultiTimestep]))
         );
+    }
+
+    /// A turbine capacity that depends on the head, and so the volume, of a reservoir.
+    struct HeadParameter {
+        meta: parameters::ParameterMeta,
+        reservoir: NodeIndex,
+    }
+
+    impl parameters::_Parameter for HeadParameter {
+        fn meta(&self) -> &parameters::ParameterMeta {
+            &self.meta
+        }
+        fn compute(
+            &self,
+            _timestep: &Timestep,
+            _scenario_index: &ScenarioIndex,
+            network_state: &NetworkState,
+            _parameter_state: &ParameterState,
+        ) -> Result<f64, PywrError> {
+            Ok(0.2 * network_state.get_node_volume(self.reservoir)?)
+        }
+    }
+
+    /// A monthly abstraction licence, which limits the total flow through a node in each month.
+    struct MonthlyLicence {
+        meta: parameters::ParameterMeta,
+        node: NodeIndex,
+        volume: f64,
+        /// The volume used so far this month in each scenario.
+        used: Vec<f64>,
+    }
+
+    impl parameters::_Parameter for MonthlyLicence {
+        fn meta(&self) -> &parameters::ParameterMeta {
+            &self.meta
+        }
+        fn before(&mut self, timestep: &Timestep) -> Result<(), PywrError> {
+            if timestep.calendar_date.day == 1 {
+                self.used.clear();
+            }
+            Ok(())
+        }
+        fn compute(
+            &self,
+            _timestep: &Timestep,
+            scenario_index: &ScenarioIndex,
+            _network_state: &NetworkState,
+            _parameter_state: &ParameterState,
+        ) -> Result<f64, PywrError> {
+            Ok(self.volume - self.used.get(scenario_index.index).unwrap_or(&0.0))
+        }
+        fn after(
+            &mut self,
+            timestep: &Timestep,
+            scenario_index: &ScenarioIndex,
+            network_state: &NetworkState,
+            _parameter_state: &ParameterState,
+        ) -> Result<(), PywrError> {
+            if self.used.len() <= scenario_index.index {
+                self.used.resize(scenario_index.index + 1, 0.0);
+            }
+            self.used[scenario_index.index] += network_state.get_node_out_flow(self.node)? * timestep.days();
+            Ok(())
+        }
+        fn checkpoint_state(&self) -> Vec<f64> {
+            self.used.clone()
+        }
+        fn restore_state(&mut self, values: &[f64]) -> Result<(), PywrError> {
+            self.used = values.to_vec();
+            Ok(())
+        }
+    }
+
+    /// Create the simple model with two scenarios and its inflow limited by a monthly licence of 25.
+    fn licence_model() -> Model {
+        let mut model = simple_model();
+        model.add_scenario("test-scenario", 2).unwrap();
+        let input = model.get_node_by_name("input").unwrap();
+        let licence = MonthlyLicence {
+            meta: parameters::ParameterMeta {
+                name: "licence".to_string(),
+                comment: "".to_string(),
+            },
+            node: input.index(),
+            volume: 25.0,
+            used: Vec::new(),
+        };
+        let licence = model.add_parameter(Box::new(licence)).unwrap();
+        let link = model.get_node_by_name("link").unwrap();
+        link.set_constraint(ConstraintValue::Parameter(licence), Constraint::MaxFlow)
+            .unwrap();
+        model
+    }
+
+    #[test]
+    /// Test the parameters' hooks are called before and after each timestep
+    fn test_parameter_hooks() {
+        let mut model = licence_model();
+        let input = model.get_node_by_name("input").unwrap();
+
+        // The inflow of 10 is limited by the licence, which is renewed on the 1st of February.
+        let expected = Array2::from_shape_fn((8, 2), |(i, _)| [10.0, 10.0, 5.0, 10.0, 10.0, 5.0, 0.0, 0.0][i]);
+        let recorder = AssertionRecorder::new("input-flow", Metric::NodeOutFlow(input.index()), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let timestepper = Timestepper::new("2020-01-29", "2020-02-05", "%Y-%m-%d", 1).unwrap();
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+    }
+
+    #[test]
+    /// Test a run resumed from a checkpoint continues with the saved state and parameter memory
+    fn test_run_from_checkpoint() {
+        let dir = TempDir::new("checkpoint").unwrap();
+        let path = dir.path().join("run.checkpoint");
+        let timestepper = Timestepper::new("2020-01-29", "2020-02-05", "%Y-%m-%d", 1).unwrap();
+
+        // Interrupt a run after the checkpoint of its first two timesteps.
+        let mut model = licence_model();
+        model.set_checkpointing(&path, 2);
+        let mut run = model
+            .start_run(&timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        for _ in 0..3 {
+            model.step_run(&mut run).unwrap();
+        }
+        drop(run);
+
+        // The resumed run only saves the remaining timesteps, and remembers 20 of the licence is used.
+        let mut model = licence_model();
+        let input = model.get_node_by_name("input").unwrap();
+        let expected = Array2::from_shape_fn((8, 2), |(i, _)| [f64::NAN, f64::NAN, 5.0, 10.0, 10.0, 5.0, 0.0, 0.0][i]);
+        let recorder = AssertionRecorder::new("input-flow", Metric::NodeOutFlow(input.index()), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+        model
+            .run_from_checkpoint(timestepper, &SimplexSolver::new(), &SolverSettings::default(), &path)
+            .unwrap();
+
+        // A checkpoint of a different run is rejected.
+        let timestepper = Timestepper::new("2020-01-30", "2020-02-05", "%Y-%m-%d", 1).unwrap();
+        let result =
+            licence_model().run_from_checkpoint(timestepper, &SimplexSolver::new(), &SolverSettings::default(), &path);
+        assert!(matches!(result, Err(PywrError::CheckpointMismatch(_))));
+    }
+
+    #[test]
+    /// Test a run can start from the volumes at the end of a previous run
+    fn test_initial_state() {
+        let dir = TempDir::new("initial-state").unwrap();
+        let path = dir.path().join("previous.csv");
+
+        // The previous run draws the reservoir down to 70.
+        let mut model = simple_storage_model();
+        model.add_labelled_scenario("climate", &["wet", "dry"]).unwrap();
+        let recorder = recorders::csv::CSVRecorder::new("csv", path.clone());
+        model.add_recorder(Box::new(recorder)).unwrap();
+        let timestepper = Timestepper::new("2020-01-01", "2020-01-03", "%Y-%m-%d", 1).unwrap();
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+
+        let run_from = |initial_state: InitialState, expected: [f64; 2]| {
+            let mut model = simple_storage_model();
+            model.add_labelled_scenario("climate", &["wet", "dry"]).unwrap();
+            model.set_initial_state(Some(initial_state));
+            let reservoir = model.get_node_by_name("reservoir").unwrap();
+            let expected = Array2::from_shape_fn((2, 2), |(i, j)| expected[j] - 10.0 * (i + 1) as f64);
+            let recorder = AssertionRecorder::new("volume", Metric::NodeVolume(reservoir.index()), expected);
+            model.add_recorder(Box::new(recorder)).unwrap();
+            let timestepper = Timestepper::new("2020-01-04", "2020-01-05", "%Y-%m-%d", 1).unwrap();
+            model.run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+        };
+
+        run_from(InitialState::from_csv(&path, None).unwrap(), [70.0, 70.0]).unwrap();
+        run_from(InitialState::from_csv(&path, Some("2020-01-01")).unwrap(), [90.0, 90.0]).unwrap();
+
+        // Each scenario starts from the volume of the scenario with the same label.
+        let volumes = vec![
+            ("dry".to_string(), vec![("reservoir".to_string(), 40.0)]),
+            ("wet".to_string(), vec![("reservoir".to_string(), 80.0)]),
+        ];
+        run_from(InitialState::Volumes(volumes), [80.0, 40.0]).unwrap();
+
+        // A checkpoint of a single scenario is used for every scenario.
+        let mut model = simple_storage_model();
+        let timestepper = Timestepper::new("2020-01-01", "2020-01-03", "%Y-%m-%d", 1).unwrap();
+        let mut run = model
+            .start_run(&timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        model.step_run(&mut run).unwrap();
+        run_from(InitialState::Checkpoint(model.checkpoint(&run)), [90.0, 90.0]).unwrap();
 
         assert_eq!(
-            model.add_storage_node("my-node", 10.0),
-            Err(PywrError::NodeNameAlreadyExists("my-node".to_string()))
+            InitialState::from_csv(&path, Some("2021-01-01")).unwrap_err(),
+            PywrError::InvalidInitialState("the results file has no rows for 2021-01-01".to_string())
         );
     }
 
-    /// Create a simple test model with three nodes.
-    fn simple_model() -> Model {
+    #[test]
+    /// Test a timestep is re-solved until a parameter that depends on its solution converges
+    fn test_timestep_iteration() {
+        let mut model = simple_storage_model();
+        let reservoir = model.get_node_by_name("reservoir").unwrap();
+        let output = model.get_node_by_name("output").unwrap();
+        let capacity = HeadParameter {
+            meta: parameters::ParameterMeta {
+                name: "turbine-capacity".to_string(),
+                comment: "".to_string(),
+            },
+            reservoir: reservoir.index(),
+        };
+        let capacity = model.add_parameter(Box::new(capacity)).unwrap();
+        output
+            .set_constraint(ConstraintValue::Parameter(capacity.clone()), Constraint::MaxFlow)
+            .unwrap();
+
+        // The converged flow satisfies `flow = 0.2 * (100 - flow)`.
+        model.set_timestep_iteration(std::slice::from_ref(&capacity), 50, 1e-9);
+        let solver = SimplexSolver::new();
+        let mut run = model
+            .start_run(&default_timestepper(), &solver, &SolverSettings::default())
+            .unwrap();
+        model.step_run(&mut run).unwrap();
+        let flow = run.states()[0].get_node_in_flow(output.index()).unwrap();
+        assert!(approx_eq!(f64, flow, 100.0 / 6.0, epsilon = 1e-6));
+        model.finish_run(run).unwrap();
+
+        let result = &model.iteration_results()[0];
+        assert_eq!((result.timestep_index, result.scenario_index), (0, 0));
+        assert!(result.converged);
+        assert!(result.iterations > 2 && result.iterations < 50);
+
+        // Without enough iterations the flow is not converged.
+        model.set_timestep_iteration(&[capacity], 2, 1e-9);
+        model
+            .run(default_timestepper(), &solver, &SolverSettings::default())
+            .unwrap();
+        let results = model.iteration_results();
+        assert_eq!(results.len(), 15);
+        assert_eq!(results[0].iterations, 2);
+        assert!(!results[0].converged);
+        assert!(approx_eq!(f64, results[0].residual, 0.8, epsilon = 1e-9));
+    }
+
+    #[test]
+    /// Test the warm-up is solved once and the scenarios branch from its state
+    fn test_run_warm_up() {
+        let mut model = simple_storage_model();
+        model.add_scenario("test-scenario", 10).unwrap();
+        model.set_warm_up(4);
+        let solver = SimplexSolver::new();
+
+        // The same results as solving every scenario throughout.
+        let idx = model.get_node_by_name("reservoir").unwrap().index();
+        let expected = Array2::from_shape_fn((15, 10), |(i, _)| (90.0 - 10.0 * i as f64).max(0.0));
+        let recorder = AssertionRecorder::new("reservoir-volume", Metric::NodeVolume(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        model
+            .run(default_timestepper(), &solver, &SolverSettings::default())
+            .unwrap();
+        assert_eq!(model.solver_stats().unwrap().solves, 4 + 11 * 10);
+    }
+
+    #[test]
+    /// Test an infeasible minimum flow is relaxed, with or without batching the scenarios
+    fn test_run_infeasibility_relaxations() {
+        let mut model = simple_model();
+        let output_node = model.get_node_by_name("output").unwrap();
+        // The minimum flow can not be met from the inflow of 10.0.
+        output_node
+            .set_constraint(ConstraintValue::Scalar(15.0), Constraint::MinFlow)
+            .unwrap();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = SimplexSolver::new();
+
+        let result = model.run(default_timestepper(), &solver, &SolverSettings::default());
+        assert!(result.is_err());
+
+        model.set_infeasibility_relaxations(vec![Relaxation::MaxVolumes, Relaxation::MinFlows]);
+        for &batch_scenarios in &[false, true] {
+            let settings = SolverSettings {
+                batch_scenarios,
+                ..Default::default()
+            };
+            model.run(default_timestepper(), &solver, &settings).unwrap();
+
+            let relaxed = model.relaxed_solves();
+            assert_eq!(relaxed.len(), 15 * 10);
+            assert_eq!(relaxed[0].timestep_index, 0);
+            assert_eq!(
+                relaxed[0].relaxations,
+                vec![Relaxation::MaxVolumes, Relaxation::MinFlows]
+            );
+        }
+
+        // Errors other than infeasibility are returned without relaxing the problem.
+        let settings = SolverSettings {
+            time_limit: Some(0.0),
+            ..Default::default()
+        };
+        let error = model.run(default_timestepper(), &solver, &settings).unwrap_err();
+        assert_eq!(error.root(), &PywrError::SimplexError(SimplexError::TimeLimit));
+    }
+
+    /// A model with one input supplying a high priority output and two lower priority outputs.
+    fn priority_model() -> Model {
         let mut model = Model::new();
+        let input_node = model.add_input_node("input").unwrap();
+        input_node
+            .set_constraint(ConstraintValue::Scalar(20.0), Constraint::MaxFlow)
+            .unwrap();
+
+        for &(name, demand, cost) in &[("high", 8.0, -20.0), ("a", 10.0, -10.0), ("b", 30.0, -10.0)] {
+            let link_node = model.add_link_node(&format!("{}-link", name)).unwrap();
+            let output_node = model.add_output_node(name).unwrap();
+            model.connect_nodes(&input_node, &link_node).unwrap();
+            model.connect_nodes(&link_node, &output_node).unwrap();
+            output_node
+                .set_constraint(ConstraintValue::Scalar(demand), Constraint::MaxFlow)
+                .unwrap();
+            output_node.set_cost(ConstraintValue::Scalar(cost));
+        }
+        model
+    }
+
+    #[test]
+    /// Test the supply to a priority class is shared in proportion to demand
+    fn test_step_priority_proportional() {
+        let mut model = priority_model();
+        model.set_allocation_mode(AllocationMode::PriorityProportional);
+        let timesteps = default_timestepper().timesteps();
+        let scenario_indices = default_scenarios().scenario_indices();
+        let solver = SimplexSolver::new();
+        let flows = |model: &mut Model| {
+            let solvers = SolverPool::new(model, &solver, &SolverSettings::default()).unwrap();
+            let current_state = model.get_initial_state(&scenario_indices);
+            let mut next_state = current_state.clone();
+            model
+                .step(
+                    &timesteps[0],
+                    &scenario_indices,
+                    &solvers,
+                    &current_state,
+                    &mut next_state,
+                )
+                .unwrap();
+            ["high", "a", "b"]
+                .iter()
+                .map(|name| {
+                    let node = model.get_node_by_name(name).unwrap();
+                    next_state[0].get_node_in_flow(node.index()).unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The 12.0 left after the high priority demand is shared 1:3.
+        let supplied = flows(&mut model);
+        for (flow, expected) in supplied.iter().zip(&[8.0, 3.0, 9.0]) {
+            assert!(approx_eq!(f64, *flow, *expected, epsilon = 1e-6));
+        }
+
+        // If "a" can only receive 2.0, "b" receives the same proportion and then the remainder.
+        let link_node = model.get_node_by_name("a-link").unwrap();
+        link_node
+            .set_constraint(ConstraintValue::Scalar(2.0), Constraint::MaxFlow)
+            .unwrap();
+        let supplied = flows(&mut model);
+        for (flow, expected) in supplied.iter().zip(&[8.0, 2.0, 10.0]) {
+            assert!(approx_eq!(f64, *flow, *expected, epsilon = 1e-4));
+        }
+
+        // Every demand in a priority class must be bounded.
+        let output_node = model.get_node_by_name("b").unwrap();
+        output_node
+            .set_constraint(ConstraintValue::None, Constraint::MaxFlow)
+            .unwrap();
+        let solvers = SolverPool::new(&model, &solver, &SolverSettings::default()).unwrap();
+        let current_state = model.get_initial_state(&scenario_indices);
+        assert_eq!(
+            model
+                .step(
+                    &timesteps[0],
+                    &scenario_indices,
+                    &solvers,
+                    &current_state,
+                    &mut current_state.clone()
+                )
+                .err()
+                .as_ref()
+                .map(PywrError::root),
+            Some(&PywrError::UnboundedPriorityDemand("b".to_string()))
+        );
+    }
+
+    #[cfg(feature = "glpk")]
+    #[test]
+    fn test_run_glpk() {
+        run_simple_model(&crate::solvers::glpk::GlpkSolver::new());
+    }
+
+    #[test]
+    fn test_run_route() {
+        run_simple_model(&RouteSolver::new());
+    }
+
+    /// Run the simple model with `solver`, and assert the expected flows are found.
+    fn run_simple_model(solver: &dyn Solver) {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+
+        // Set-up assertion for "input" node
+        let idx = model.get_node_by_name("input").unwrap().index();
+        let expected = Array2::from_elem((366, 10), 10.0);
+        let recorder = AssertionRecorder::new("input-flow", Metric::NodeOutFlow(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let idx = model.get_node_by_name("link").unwrap().index();
+        let expected = Array2::from_elem((366, 10), 10.0);
+        let recorder = AssertionRecorder::new("link-flow", Metric::NodeOutFlow(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let idx = model.get_node_by_name("output").unwrap().index();
+        let expected = Array2::from_elem((366, 10), 10.0);
+        let recorder = AssertionRecorder::new("output-flow", Metric::NodeInFlow(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        // Demand of 12.0 is only partially met by the 10.0 of inflow.
+        let expected = Array2::from_elem((366, 10), 2.0);
+        let recorder = AssertionRecorder::new("output-deficit", Metric::NodeDeficit(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
 
-        let input_node = model.add_input_node("input").unwrap();
-        let link_node = model.add_link_node("link").unwrap();
-        let output_node = model.add_output_node("output").unwrap();
+        let idx = model.get_node_by_name("link").unwrap().index();
+        let expected = Array2::from_elem((366, 10), 0.0);
+        let recorder = AssertionRecorder::new("link-net-flow", Metric::NodeNetFlow(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
 
-        model.connect_nodes(&input_node, &link_node).unwrap();
-        model.connect_nodes(&link_node, &output_node).unwrap();
+        // Derived metrics
+        let input_flow = Metric::NodeOutFlow(model.get_node_by_name("input").unwrap().index());
+        let output_flow = Metric::NodeInFlow(model.get_node_by_name("output").unwrap().index());
 
-        let inflow = parameters::VectorParameter::new("inflow", vec![10.0; 366]);
-        let inflow = model.add_parameter(Box::new(inflow)).unwrap();
+        let expected = Array2::from_elem((366, 10), 0.0);
+        let metric = Metric::Difference(Box::new(input_flow.clone()), Box::new(output_flow.clone()));
+        let recorder = AssertionRecorder::new("balance", metric, expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
 
-        input_node
-            .set_constraint(ConstraintValue::Parameter(inflow), Constraint::MaxFlow)
-            .unwrap();
+        let expected = Array2::from_elem((366, 10), 22.0);
+        let metric = Metric::Sum(vec![input_flow, output_flow, Metric::Constant(2.0)]);
+        let recorder = AssertionRecorder::new("total", metric, expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        let idx = model.get_parameter_by_name("total-demand").unwrap().index();
+        let expected = Array2::from_elem((366, 10), 12.0);
+        let recorder = AssertionRecorder::new("total-demand", Metric::ParameterValue(idx), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
 
-        let base_demand = parameters::ConstantParameter::new("base-demand", 10.0);
-        let base_demand = model.add_parameter(Box::new(base_demand)).unwrap();
+        for edge in model.edges.clone() {
+            let expected = Array2::from_elem((366, 10), 10.0);
+            let name = format!("edge-{}-flow", edge.index());
+            let recorder = AssertionRecorder::new(&name, edge.default_metric(), expected);
+            model.add_recorder(Box::new(recorder)).unwrap();
+        }
 
-        let demand_factor = parameters::ConstantParameter::new("demand-factor", 1.2);
-        let demand_factor = model.add_parameter(Box::new(demand_factor)).unwrap();
+        model.run(timestepper, solver, &SolverSettings::default()).unwrap();
+    }
 
-        let total_demand = parameters::AggregatedParameter::new(
-            "total-demand",
-            vec![base_demand, demand_factor],
-            parameters::AggFunc::Product,
-        );
-        let total_demand = model.add_parameter(Box::new(total_demand)).unwrap();
+    #[test]
+    /// Test the violated constraints are reported for an infeasible model
+    fn test_run_infeasible_diagnostics() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
 
+        // The input must supply 10.0, but the output can only take 5.0.
+        let input_node = model.get_node_by_name("input").unwrap();
+        input_node
+            .set_constraint(ConstraintValue::Scalar(10.0), Constraint::MinFlow)
+            .unwrap();
+        let output_node = model.get_node_by_name("output").unwrap();
         output_node
-            .set_constraint(ConstraintValue::Parameter(total_demand), Constraint::MaxFlow)
+            .set_constraint(ConstraintValue::Scalar(5.0), Constraint::MaxFlow)
             .unwrap();
 
-        let demand_cost = parameters::ConstantParameter::new("demand-cost", -10.0);
-        let demand_cost = model.add_parameter(Box::new(demand_cost)).unwrap();
-
-        output_node.set_cost(ConstraintValue::Parameter(demand_cost));
+        let settings = SolverSettings {
+            diagnose_infeasibility: true,
+            ..SolverSettings::default()
+        };
 
-        model
+        let error = model.run(timestepper, solver.as_ref(), &settings).unwrap_err();
+        assert!(error.to_string().starts_with("timestep 0 (2020-01-01), scenario "));
+        match error.root() {
+            PywrError::Infeasible { violations } => {
+                assert_eq!(violations.len(), 1);
+                assert!(["input", "output"].contains(&violations[0].name.as_str()));
+                assert!(approx_eq!(f64, violations[0].amount(), 5.0, epsilon = 1e-6));
+            }
+            e => panic!("Expected an infeasible error, got {:?}", e),
+        }
     }
 
-    /// A test model with a single storage node.
-    fn simple_storage_model() -> Model {
+    /// A model with two demands competing for the same supply, which are mutually exclusive.
+    fn mutually_exclusive_model() -> Model {
         let mut model = Model::new();
 
-        let storage_node = model.add_storage_node("reservoir", 100.0).unwrap();
-        let output_node = model.add_output_node("output").unwrap();
+        let input_node = model.add_input_node("input").unwrap();
+        let link_node = model.add_link_node("link").unwrap();
+        let output_a = model.add_output_node("output-a").unwrap();
+        let output_b = model.add_output_node("output-b").unwrap();
 
-        model.connect_nodes(&storage_node, &output_node).unwrap();
+        model.connect_nodes(&input_node, &link_node).unwrap();
+        model.connect_nodes(&link_node, &output_a).unwrap();
+        model.connect_nodes(&link_node, &output_b).unwrap();
 
-        // Apply demand to the model
-        // TODO convenience function for adding a constant constraint.
-        let demand = parameters::ConstantParameter::new("demand", 10.0);
-        let demand = model.add_parameter(Box::new(demand)).unwrap();
-        output_node
-            .set_constraint(ConstraintValue::Parameter(demand), Constraint::MaxFlow)
+        input_node
+            .set_constraint(ConstraintValue::Scalar(10.0), Constraint::MaxFlow)
             .unwrap();
-
-        let demand_cost = parameters::ConstantParameter::new("demand-cost", -10.0);
-        let demand_cost = model.add_parameter(Box::new(demand_cost)).unwrap();
-        output_node.set_cost(ConstraintValue::Parameter(demand_cost));
-
-        let max_volume = parameters::ConstantParameter::new("max-volume", 100.0);
-        let max_volume = model.add_parameter(Box::new(max_volume)).unwrap();
-
-        storage_node
-            .set_constraint(ConstraintValue::Parameter(max_volume), Constraint::MaxVolume)
+        output_a
+            .set_constraint(ConstraintValue::Scalar(6.0), Constraint::MaxFlow)
+            .unwrap();
+        output_a.set_cost(ConstraintValue::Scalar(-10.0));
+        output_b
+            .set_constraint(ConstraintValue::Scalar(6.0), Constraint::MaxFlow)
             .unwrap();
+        output_b.set_cost(ConstraintValue::Scalar(-5.0));
 
+        model.add_mutual_exclusivity(&[output_a, output_b]).unwrap();
         model
     }
 
     #[test]
-    /// Test adding a constant parameter to a model.
-    fn test_constant_parameter() {
-        let mut model = Model::new();
-        let node = model.add_input_node("input").unwrap();
-
-        let input_max_flow = parameters::ConstantParameter::new("my-constant", 10.0);
-        let parameter = model.add_parameter(Box::new(input_max_flow)).unwrap();
-        assert_eq!(parameter.index(), 0);
-        // assign the new parameter to one of the nodes.
-        node.set_constraint(ConstraintValue::Parameter(parameter.clone()), Constraint::MaxFlow)
-            .unwrap();
+    /// Test a model requiring integer variables is rejected by a solver without them
+    fn test_run_mutual_exclusivity_unsupported() {
+        let mut model = mutually_exclusive_model();
+        let solver = SimplexSolver::new();
 
-        // Try to assign a constraint not defined for particular node type
+        assert_eq!(model.required_solver_features(), vec![SolverFeature::IntegerVariables]);
         assert_eq!(
-            node.set_constraint(ConstraintValue::Parameter(parameter), Constraint::MaxVolume),
-            Err(PywrError::StorageConstraintsUndefined)
+            model
+                .run(default_timestepper(), &solver, &SolverSettings::default())
+                .err(),
+            Some(PywrError::MissingSolverFeatures(vec![SolverFeature::IntegerVariables]))
         );
     }
 
+    #[cfg(feature = "cbc")]
     #[test]
-    fn test_step() {
-        let mut model = simple_model();
-        let timestepper = default_timestepper();
+    /// Test only one of the mutually exclusive demands is supplied
+    fn test_step_mutual_exclusivity_cbc() {
+        let mut model = mutually_exclusive_model();
         let scenarios = default_scenarios();
-        let mut solver: Box<dyn Solver> = Box::new(ClpSolver::new());
+        let solver = crate::solvers::cbc::CbcSolver::new();
 
-        solver.setup(&model).unwrap();
+        model.check_solver_features(&solver).unwrap();
+        let solvers = SolverPool::new(&model, &solver, &SolverSettings::default()).unwrap();
 
-        let timesteps = timestepper.timesteps();
-        let mut ts_iter = timesteps.iter();
+        let timesteps = default_timestepper().timesteps();
         let scenario_indices = scenarios.scenario_indices();
-        let ts = ts_iter.next().unwrap();
         let current_state = model.get_initial_state(&scenario_indices);
-        assert_eq!(current_state.len(), scenario_indices.len());
+        let mut next_state = current_state.clone();
+        model
+            .step(
+                &timesteps[0],
+                &scenario_indices,
+                &solvers,
+                &current_state,
+                &mut next_state,
+            )
+            .unwrap();
+        let state0 = next_state.get(0).unwrap();
 
-        let next_state = model.step(ts, &scenario_indices, &mut solver, &current_state).unwrap();
+        // Without the exclusivity output-b would receive the remaining 4.0.
+        let output_a = model.get_node_by_name("output-a").unwrap();
+        let output_b = model.get_node_by_name("output-b").unwrap();
+        let flow_a = Metric::NodeInFlow(output_a.index())
+            .get_value(&model, state0, &[])
+            .unwrap();
+        let flow_b = Metric::NodeInFlow(output_b.index())
+            .get_value(&model, state0, &[])
+            .unwrap();
+        assert!(approx_eq!(f64, flow_a, 6.0, epsilon = 1e-6));
+        assert!(approx_eq!(f64, flow_b, 0.0, epsilon = 1e-6));
+    }
 
-        assert_eq!(next_state.len(), scenario_indices.len());
+    #[test]
+    /// Test progress is reported during a run
+    fn test_run_progress() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
 
-        let output_node = model.get_node_by_name("output").unwrap();
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported_clone = reported.clone();
+        model.set_progress_reporter(
+            ReportInterval::Timesteps(5),
+            Box::new(move |p: &RunProgress| reported_clone.lock().unwrap().push(p.timesteps_completed)),
+        );
 
-        let state0 = next_state.get(0).unwrap();
-        let output_inflow = state0.get_node_in_flow(output_node.index()).unwrap();
-        assert!(approx_eq!(f64, output_inflow, 10.0));
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        assert_eq!(*reported.lock().unwrap(), vec![5, 10, 15]);
     }
 
     #[test]
-    /// Test running a simple model
-    fn test_run() {
-        let mut model = simple_model();
-        let timestepper = default_timestepper();
-        let scenarios = default_scenarios();
-        let mut solver: Box<dyn Solver> = Box::new(ClpSolver::new());
+    /// Test callbacks can change the states before each timestep and read them after
+    fn test_timestep_callbacks() {
+        let mut model = simple_storage_model();
+        model.add_scenario("test-scenario", 2).unwrap();
+        let solver = default_solver();
+        let reservoir = model.get_node_by_name("reservoir").unwrap().index();
+        let output = model.get_node_by_name("output").unwrap().index();
 
-        // Set-up assertion for "input" node
-        let idx = model.get_node_by_name("input").unwrap().index();
-        let expected = Array2::from_elem((366, 10), 10.0);
-        let recorder = AssertionRecorder::new("input-flow", Metric::NodeOutFlow(idx), expected);
-        model.add_recorder(Box::new(recorder)).unwrap();
+        // Refill the reservoir before each timestep, so that it never runs dry.
+        model.add_before_timestep_callback(Box::new(
+            move |_: &Timestep, _: &[ScenarioIndex], states: &mut [NetworkState]| {
+                for state in states.iter_mut() {
+                    state.set_node_volume(reservoir, 100.0)?;
+                }
+                Ok(())
+            },
+        ));
 
-        let idx = model.get_node_by_name("link").unwrap().index();
-        let expected = Array2::from_elem((366, 10), 10.0);
-        let recorder = AssertionRecorder::new("link-flow", Metric::NodeOutFlow(idx), expected);
-        model.add_recorder(Box::new(recorder)).unwrap();
+        let flows = Arc::new(Mutex::new(Vec::new()));
+        let flows_clone = flows.clone();
+        model.add_after_timestep_callback(Box::new(
+            move |_: &Timestep, scenario_indices: &[ScenarioIndex], states: &[NetworkState]| {
+                assert_eq!(scenario_indices.len(), states.len());
+                flows_clone.lock().unwrap().push(states[1].get_node_in_flow(output)?);
+                Ok(())
+            },
+        ));
 
-        let idx = model.get_node_by_name("output").unwrap().index();
-        let expected = Array2::from_elem((366, 10), 10.0);
-        let recorder = AssertionRecorder::new("output-flow", Metric::NodeInFlow(idx), expected);
-        model.add_recorder(Box::new(recorder)).unwrap();
+        model
+            .run(default_timestepper(), solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+        assert_eq!(*flows.lock().unwrap(), vec![10.0; 15]);
 
-        let idx = model.get_parameter_by_name("total-demand").unwrap().index();
-        let expected = Array2::from_elem((366, 10), 12.0);
-        let recorder = AssertionRecorder::new("total-demand", Metric::ParameterValue(idx), expected);
-        model.add_recorder(Box::new(recorder)).unwrap();
+        // An error from a callback stops the run.
+        model.add_after_timestep_callback(Box::new(
+            move |_: &Timestep, _: &[ScenarioIndex], _: &[NetworkState]| Err(PywrError::NodeIndexNotFound(output)),
+        ));
+        assert!(model
+            .run(default_timestepper(), solver.as_ref(), &SolverSettings::default())
+            .is_err());
+    }
+
+    #[test]
+    /// Test a run cancelled through its handle stops early, and the handle can be reused
+    fn test_run_handle_cancel() {
+        let mut model = simple_model();
+        let solver = default_solver();
+        let handle = RunHandle::new();
+        model.set_run_handle(handle.clone());
+
+        let canceller = handle.clone();
+        model.set_progress_reporter(
+            ReportInterval::Timesteps(1),
+            Box::new(move |p: &RunProgress| {
+                if p.timesteps_completed == 3 {
+                    canceller.cancel()
+                }
+            }),
+        );
+        let results = model
+            .run(default_timestepper(), solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+        assert!(results.statistics.cancelled);
+        assert_eq!(results.statistics.timesteps, 3);
+        assert!(!handle.is_cancelled());
 
-        model.run(timestepper, scenarios, &mut solver).unwrap();
+        model.set_progress_reporter(ReportInterval::Timesteps(1), Box::new(|_: &RunProgress| {}));
+        let results = model
+            .run(default_timestepper(), solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+        assert!(!results.statistics.cancelled);
+        assert_eq!(results.statistics.timesteps, 15);
     }
 
+    #[cfg(feature = "clp")]
     #[test]
     fn test_run_storage() {
+        run_storage_model(&ClpSolver::new(ClpSettings::default()));
+    }
+
+    #[test]
+    fn test_run_storage_simplex() {
+        run_storage_model(&SimplexSolver::new());
+    }
+
+    #[cfg(feature = "glpk")]
+    #[test]
+    fn test_run_storage_glpk() {
+        run_storage_model(&crate::solvers::glpk::GlpkSolver::new());
+    }
+
+    #[test]
+    fn test_run_storage_route() {
+        run_storage_model(&RouteSolver::new());
+    }
+
+    /// Run the simple storage model with `solver`, and assert the reservoir empties as expected.
+    fn run_storage_model(solver: &dyn Solver) {
         let mut model = simple_storage_model();
         let timestepper = default_timestepper();
-        let scenarios = default_scenarios();
-        let mut solver: Box<dyn Solver> = Box::new(ClpSolver::new());
+        model.add_scenario("test-scenario", 10).unwrap();
 
         let idx = model.get_node_by_name("output").unwrap().index();
 
@@ -568,16 +4406,62 @@ mod tests {
         let recorder = AssertionRecorder::new("reservoir-volume", Metric::NodeVolume(idx), expected);
         model.add_recorder(Box::new(recorder)).unwrap();
 
-        model.run(timestepper, scenarios, &mut solver).unwrap();
+        model.run(timestepper, solver, &SolverSettings::default()).unwrap();
+    }
+
+    #[test]
+    /// Test recorders flagged as objectives and constraints
+    fn test_objectives_and_constraints() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let idx = model.get_node_by_name("output").unwrap().index();
+        let recorder = AggregatedRecorder::new(
+            "total-delivered",
+            Metric::NodeInFlow(idx),
+            RecorderAggregation::Sum,
+            RecorderAggregation::Mean,
+        );
+        model.add_recorder(Box::new(recorder)).unwrap();
+        let recorder = AggregatedRecorder::new(
+            "min-delivered",
+            Metric::NodeInFlow(idx),
+            RecorderAggregation::Min,
+            RecorderAggregation::Min,
+        );
+        model.add_recorder(Box::new(recorder)).unwrap();
+
+        model
+            .set_recorder_objective("total-delivered", Direction::Maximise)
+            .unwrap();
+        model
+            .set_recorder_constraint("min-delivered", Some(12.0), None)
+            .unwrap();
+
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let objectives = model.objectives().unwrap();
+        assert_eq!(objectives.len(), 1);
+        assert_eq!(objectives[0].direction, Direction::Maximise);
+        assert!(approx_eq!(f64, objectives[0].value, 150.0));
+
+        let constraints = model.constraints().unwrap();
+        assert_eq!(constraints.len(), 1);
+        assert!(approx_eq!(f64, constraints[0].value, 10.0));
+        assert!(!constraints[0].is_feasible());
     }
 
     #[test]
     /// Test `ScenarioGroupCollection` iteration
     fn test_scenario_iteration() {
         let mut collection = ScenarioGroupCollection::new();
-        collection.add_group("Scenarion A", 10);
-        collection.add_group("Scenarion B", 2);
-        collection.add_group("Scenarion C", 5);
+        collection.add_group("Scenarion A", 10).unwrap();
+        collection.add_group("Scenarion B", 2).unwrap();
+        collection.add_group("Scenarion C", 5).unwrap();
 
         let scenario_indices = collection.scenario_indices();
         let mut iter = scenario_indices.iter();
@@ -587,77 +4471,88 @@ mod tests {
             iter.next(),
             Some(&ScenarioIndex {
                 index: 0,
-                indices: vec![0, 0, 0]
+                indices: vec![0, 0, 0],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 1,
-                indices: vec![0, 0, 1]
+                indices: vec![0, 0, 1],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 2,
-                indices: vec![0, 0, 2]
+                indices: vec![0, 0, 2],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 3,
-                indices: vec![0, 0, 3]
+                indices: vec![0, 0, 3],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 4,
-                indices: vec![0, 0, 4]
+                indices: vec![0, 0, 4],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 5,
-                indices: vec![0, 1, 0]
+                indices: vec![0, 1, 0],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 6,
-                indices: vec![0, 1, 1]
+                indices: vec![0, 1, 1],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 7,
-                indices: vec![0, 1, 2]
+                indices: vec![0, 1, 2],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 8,
-                indices: vec![0, 1, 3]
+                indices: vec![0, 1, 3],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 9,
-                indices: vec![0, 1, 4]
+                indices: vec![0, 1, 4],
+                labels: None,
             })
         );
         assert_eq!(
             iter.next(),
             Some(&ScenarioIndex {
                 index: 10,
-                indices: vec![1, 0, 0]
+                indices: vec![1, 0, 0],
+                labels: None,
             })
         );
 
@@ -666,8 +4561,269 @@ mod tests {
             iter.last(),
             Some(&ScenarioIndex {
                 index: 99,
-                indices: vec![9, 1, 4]
+                indices: vec![9, 1, 4],
+                labels: None,
+            })
+        );
+    }
+
+    #[test]
+    /// Test the labels of labelled scenario groups are carried through `ScenarioIndex`
+    fn test_labelled_scenario_iteration() {
+        let mut collection = ScenarioGroupCollection::new();
+        collection.add_labelled_group("climate", &["hadgem", "ipsl"]).unwrap();
+        collection.add_group("demand", 3).unwrap();
+
+        let scenario_indices = collection.scenario_indices();
+        assert_eq!(scenario_indices.len(), 6);
+        assert_eq!(
+            scenario_indices[4],
+            ScenarioIndex {
+                index: 4,
+                indices: vec![1, 1],
+                labels: Some(vec!["ipsl".to_string(), "1".to_string()]),
+            }
+        );
+        assert_eq!(scenario_indices[4].label(), "ipsl/1");
+
+        // Without any labelled groups the scenarios are labelled by index.
+        let scenario_indices = default_scenarios().scenario_indices();
+        assert_eq!(scenario_indices[3].label(), "3");
+    }
+
+    #[test]
+    /// Test the member and label of a named group are found for a scenario
+    fn test_scenario_group_lookups() {
+        let mut collection = ScenarioGroupCollection::new();
+        collection.add_labelled_group("climate", &["hadgem", "ipsl"]).unwrap();
+        collection.add_group("demand", 3).unwrap();
+
+        let scenario_index = &collection.scenario_indices()[5];
+        assert_eq!(scenario_index.group_index(&collection, "climate"), Ok(1));
+        assert_eq!(scenario_index.group_index(&collection, "demand"), Ok(2));
+        assert_eq!(
+            scenario_index.group_label(&collection, "climate"),
+            Ok("ipsl".to_string())
+        );
+        assert_eq!(scenario_index.group_label(&collection, "demand"), Ok("2".to_string()));
+        assert_eq!(
+            scenario_index.group_index(&collection, "weather"),
+            Err(PywrError::ScenarioGroupNotFound("weather".to_string()))
+        );
+    }
+
+    #[test]
+    /// Test the scenario data of parameters is checked against the model's scenarios
+    fn test_check_scenarios() {
+        let mut model = simple_model();
+        let data = Array2::zeros((366, 3));
+        let param = parameters::Array2Parameter::new_with_scenario_group("climate-factor", data, "climate");
+        model.add_parameter(Box::new(param)).unwrap();
+
+        assert_eq!(
+            model.check_scenarios(),
+            Err(PywrError::ScenarioGroupNotFound("climate".to_string()))
+        );
+
+        model.add_scenario("climate", 2).unwrap();
+        let expected = Err(PywrError::ScenarioGroupSizeMismatch {
+            group: "climate".to_string(),
+            size: 2,
+            expected: 3,
+        });
+        assert_eq!(model.check_scenarios(), expected);
+        assert_eq!(
+            model
+                .run(default_timestepper(), &SimplexSolver::new(), &SolverSettings::default())
+                .err(),
+            expected.err()
+        );
+
+        let mut model = simple_model();
+        model
+            .add_labelled_scenario("climate", &["hadgem", "ipsl", "miroc"])
+            .unwrap();
+        let data = Array2::zeros((366, 3));
+        let param = parameters::Array2Parameter::new_with_scenario_group("climate-factor", data, "climate");
+        model.add_parameter(Box::new(param)).unwrap();
+        model.check_scenarios().unwrap();
+        model
+            .run(default_timestepper(), &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+    }
+
+    #[test]
+    /// Test nodes and constraints are switched off in some members of a scenario
+    fn test_scenario_variations() {
+        let mut model = simple_model();
+        let scheme = model.add_input_node("scheme").unwrap();
+        let link = model.get_node_by_name("link").unwrap();
+        model.connect_nodes(&scheme, &link).unwrap();
+        scheme
+            .set_constraint(ConstraintValue::Scalar(5.0), Constraint::MaxFlow)
+            .unwrap();
+
+        model
+            .add_labelled_scenario("option", &["without-scheme", "with-scheme"])
+            .unwrap();
+        model.add_scenario("demand", 2).unwrap();
+        model
+            .add_scenario_variation("option", &[0], Variation::DisableNode(scheme.index()))
+            .unwrap();
+        let output = model.get_node_by_name("output").unwrap();
+        let variation = Variation::RemoveConstraint(output.index(), Constraint::MaxFlow);
+        model.add_scenario_variation("demand", &[1], variation).unwrap();
+
+        // The demand of 12.0 is limited by the inflow of 10.0 without the scheme, and unlimited
+        // demand takes the whole 15.0 with it.
+        let expected = Array2::from_shape_fn((15, 4), |(_, j)| [10.0, 10.0, 12.0, 15.0][j]);
+        let recorder = AssertionRecorder::new("output-flow", Metric::NodeInFlow(output.index()), expected);
+        model.add_recorder(Box::new(recorder)).unwrap();
+        model
+            .run(default_timestepper(), &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+
+        model
+            .add_scenario_variation("demand", &[2], Variation::DisableNode(scheme.index()))
+            .unwrap();
+        assert_eq!(
+            model.check_scenarios(),
+            Err(PywrError::InvalidScenarioVariation {
+                group: "demand".to_string(),
+                member: 2
+            })
+        );
+        assert_eq!(
+            model.add_scenario_variation("demand", &[0], Variation::DisableNode(NodeIndex(10))),
+            Err(PywrError::NodeIndexNotFound(NodeIndex(10)))
+        );
+    }
+
+    #[test]
+    /// Test running explicit combinations of the members of scenario groups
+    fn test_scenario_combinations() {
+        let mut collection = ScenarioGroupCollection::new();
+        collection
+            .add_labelled_group("climate", &["hadgem", "ipsl", "miroc"])
+            .unwrap();
+        collection.add_group("demand", 3).unwrap();
+        collection
+            .set_combinations(vec![vec![0, 0], vec![1, 1], vec![2, 2], vec![2, 0]])
+            .unwrap();
+
+        let scenario_indices = collection.scenario_indices();
+        let labels: Vec<String> = scenario_indices.iter().map(|s| s.label()).collect();
+        assert_eq!(labels, vec!["hadgem/0", "ipsl/1", "miroc/2", "miroc/0"]);
+        assert_eq!(scenario_indices[3].index, 3);
+
+        // Slices apply to the combinations.
+        collection.slice("climate", 2..3).unwrap();
+        let scenario_indices = collection.scenario_indices();
+        assert_eq!(scenario_indices.len(), 2);
+        assert_eq!(scenario_indices[1].indices, vec![2, 0]);
+
+        for combination in &[vec![0], vec![0, 3]] {
+            assert_eq!(
+                collection.set_combinations(vec![combination.clone()]),
+                Err(PywrError::InvalidScenarioCombination(combination.clone()))
+            );
+        }
+        assert_eq!(collection.set_combinations(Vec::new()), Err(PywrError::NoScenarios));
+    }
+
+    #[test]
+    /// Test a run with no scenarios, as none of the combinations are in a slice, is an error
+    fn test_run_without_scenarios() {
+        let mut model = simple_model();
+        model.add_scenario("test-scenario", 2).unwrap();
+        model.set_scenario_combinations(vec![vec![0]]).unwrap();
+        model.slice_scenario("test-scenario", 1..2).unwrap();
+
+        let solver = default_solver();
+
+        let error = model
+            .run(default_timestepper(), solver.as_ref(), &SolverSettings::default())
+            .unwrap_err();
+        assert_eq!(error.root(), &PywrError::NoScenarios);
+    }
+
+    #[test]
+    /// Test random and Latin hypercube samples of the scenarios
+    fn test_scenario_sampling() {
+        let mut collection = ScenarioGroupCollection::new();
+        collection.add_group("climate", 100).unwrap();
+        collection.add_group("demand", 1000).unwrap();
+
+        let random = ScenarioSampling::Random { size: 50, seed: 42 };
+        collection.set_sampling(random).unwrap();
+        let scenario_indices = collection.scenario_indices();
+        assert_eq!(scenario_indices.len(), 50);
+        assert_eq!(scenario_indices[49].index, 49);
+        let combinations: Vec<Vec<usize>> = scenario_indices.iter().map(|s| s.indices.clone()).collect();
+        let mut distinct = combinations.clone();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 50);
+        // The same seed gives the same sample.
+        let repeated: Vec<Vec<usize>> = collection.scenario_indices().into_iter().map(|s| s.indices).collect();
+        assert_eq!(combinations, repeated);
+
+        // Every member of a group is sampled once if the sample is the size of the group.
+        collection
+            .set_sampling(ScenarioSampling::LatinHypercube { size: 100, seed: 7 })
+            .unwrap();
+        let mut climates: Vec<usize> = collection.scenario_indices().iter().map(|s| s.indices[0]).collect();
+        climates.sort_unstable();
+        assert_eq!(climates, (0..100).collect::<Vec<_>>());
+
+        assert_eq!(
+            collection.set_combinations(vec![vec![0, 0]]),
+            Err(PywrError::IncompatibleScenarioSampling)
+        );
+
+        for sampling in &[
+            ScenarioSampling::Random { size: 0, seed: 42 },
+            ScenarioSampling::LatinHypercube { size: 0, seed: 42 },
+        ] {
+            assert_eq!(collection.set_sampling(*sampling), Err(PywrError::NoScenarios));
+        }
+    }
+
+    #[test]
+    /// Test running a slice of the members of a scenario group
+    fn test_sliced_scenario_iteration() {
+        let mut collection = ScenarioGroupCollection::new();
+        collection.add_group("climate", 10).unwrap();
+        collection.add_group("demand", 2).unwrap();
+        collection.slice("climate", 3..7).unwrap();
+
+        let scenario_indices = collection.scenario_indices();
+        assert_eq!(scenario_indices.len(), 8);
+        // Scenarios are indexed by their position in the run, but keep the members they refer to.
+        assert_eq!(scenario_indices[3].index, 3);
+        assert_eq!(scenario_indices[3].indices, vec![4, 1]);
+        assert_eq!(scenario_indices[3].label(), "4/1");
+
+        assert_eq!(
+            collection.slice("climate", 8..11),
+            Err(PywrError::InvalidScenarioSlice {
+                group: "climate".to_string(),
+                start: 8,
+                end: 11
             })
         );
+        assert_eq!(
+            collection.slice("weather", 0..1),
+            Err(PywrError::ScenarioGroupNotFound("weather".to_string()))
+        );
+
+        // Groups without any members can not be added.
+        assert_eq!(
+            collection.add_group("weather", 0),
+            Err(PywrError::EmptyScenarioGroup("weather".to_string()))
+        );
+        assert_eq!(
+            collection.add_labelled_group("weather", &[]),
+            Err(PywrError::EmptyScenarioGroup("weather".to_string()))
+        );
     }
 }
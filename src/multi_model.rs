@@ -0,0 +1,316 @@
+use crate::metric::Metric;
+use crate::model::{Model, ModelRun};
+use crate::parameters::Parameter;
+use crate::solvers::{Solver, SolverSettings};
+use crate::timestep::Timestepper;
+use crate::PywrError;
+
+/// A value transferred each timestep from one model of a `MultiModel` to another, e.g. the
+/// volume exported by a regional supply model that is imported by its neighbour.
+///
+/// The `metric` (e.g. `"node_inflow"`) of the node or parameter `component` of the model `from`
+/// is received by the `TransferParameter` called `parameter` of the model `to`, for each scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transfer {
+    from: String,
+    metric: String,
+    component: String,
+    to: String,
+    parameter: String,
+    lagged: bool,
+}
+
+impl Transfer {
+    pub fn new(from: &str, metric: &str, component: &str, to: &str, parameter: &str) -> Self {
+        Self {
+            from: from.to_string(),
+            metric: metric.to_string(),
+            component: component.to_string(),
+            to: to.to_string(),
+            parameter: parameter.to_string(),
+            lagged: false,
+        }
+    }
+
+    /// Transfer the value of the previous timestep, so that the receiving model does not have to
+    /// wait for the sending model to be solved. The parameter has its initial value in the first
+    /// timestep.
+    ///
+    /// Models that transfer values to each other must lag at least one of the transfers.
+    pub fn lagged(mut self) -> Self {
+        self.lagged = true;
+        self
+    }
+}
+
+/// A transfer with its models, metric and parameter found.
+struct ResolvedTransfer {
+    from: usize,
+    metric: Metric,
+    to: usize,
+    parameter: Parameter,
+    lagged: bool,
+}
+
+impl ResolvedTransfer {
+    /// Send the values of the most recent step of the `from` model to the parameter.
+    fn send(&self, models: &[(String, Model)], runs: &[ModelRun]) -> Result<(), PywrError> {
+        let (model, run) = (&models[self.from].1, &runs[self.from]);
+        let values = run
+            .states()
+            .iter()
+            .zip(run.parameter_states())
+            .map(|(state, parameter_state)| self.metric.get_value(model, state, parameter_state))
+            .collect::<Result<Vec<f64>, PywrError>>()?;
+        self.parameter.receive_values(&values)
+    }
+}
+
+/// Several models run in lockstep, exchanging values each timestep by `Transfer`s.
+///
+/// In each timestep the models are solved in turn, each after the models it receives values from
+/// (other than by lagged transfers), and otherwise in the order they were added.
+#[derive(Default)]
+pub struct MultiModel {
+    models: Vec<(String, Model)>,
+    transfers: Vec<Transfer>,
+}
+
+impl MultiModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_model(&mut self, name: &str, model: Model) -> Result<(), PywrError> {
+        if self.models.iter().any(|(n, _)| n == name) {
+            return Err(PywrError::ModelNameAlreadyExists(name.to_string()));
+        }
+        self.models.push((name.to_string(), model));
+        Ok(())
+    }
+
+    pub fn add_transfer(&mut self, transfer: Transfer) -> Result<(), PywrError> {
+        self.model_index(&transfer.from)?;
+        self.model_index(&transfer.to)?;
+        self.transfers.push(transfer);
+        Ok(())
+    }
+
+    pub fn model(&self, name: &str) -> Result<&Model, PywrError> {
+        Ok(&self.models[self.model_index(name)?].1)
+    }
+
+    pub fn model_mut(&mut self, name: &str) -> Result<&mut Model, PywrError> {
+        let index = self.model_index(name)?;
+        Ok(&mut self.models[index].1)
+    }
+
+    fn model_index(&self, name: &str) -> Result<usize, PywrError> {
+        self.models
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or_else(|| PywrError::ModelNotFound(name.to_string()))
+    }
+
+    /// The names of the models in the order they are solved in each timestep.
+    ///
+    /// Models that depend on each other by transfers that are not lagged can not be ordered, and
+    /// give an error.
+    pub fn order(&self) -> Result<Vec<&str>, PywrError> {
+        Ok(self
+            .order_indices()?
+            .into_iter()
+            .map(|m| self.models[m].0.as_str())
+            .collect())
+    }
+
+    fn order_indices(&self) -> Result<Vec<usize>, PywrError> {
+        let dependencies: Vec<(usize, usize)> = self
+            .transfers
+            .iter()
+            .filter(|t| !t.lagged)
+            .map(|t| Ok((self.model_index(&t.from)?, self.model_index(&t.to)?)))
+            .collect::<Result<_, PywrError>>()?;
+
+        let mut order = Vec::with_capacity(self.models.len());
+        while order.len() < self.models.len() {
+            // The first model all of whose dependencies are solved.
+            let next = (0..self.models.len())
+                .find(|m| !order.contains(m) && dependencies.iter().all(|(from, to)| to != m || order.contains(from)));
+            match next {
+                Some(m) => order.push(m),
+                None => {
+                    let remaining: Vec<&str> = (0..self.models.len())
+                        .filter(|m| !order.contains(m))
+                        .map(|m| self.models[m].0.as_str())
+                        .collect();
+                    return Err(PywrError::InvalidMultiModel(format!(
+                        "models `{}` depend on each other; lag one of the transfers between them",
+                        remaining.join("`, `")
+                    )));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Run each of the models over the timesteps of `timestepper` with `solver`.
+    ///
+    /// The models are stepped one timestep at a time, so `settings` can not solve several
+    /// timesteps as one problem.
+    pub fn run(
+        &mut self,
+        timestepper: &Timestepper,
+        solver: &dyn Solver,
+        settings: &SolverSettings,
+    ) -> Result<(), PywrError> {
+        if settings.timestep_window.unwrap_or(1) > 1 {
+            return Err(PywrError::InvalidMultiModel(
+                "the models must be solved one timestep at a time".to_string(),
+            ));
+        }
+        let order = self.order_indices()?;
+        let transfers = self
+            .transfers
+            .iter()
+            .map(|t| {
+                let (from, to) = (self.model_index(&t.from)?, self.model_index(&t.to)?);
+                Ok(ResolvedTransfer {
+                    from,
+                    metric: self.models[from].1.metric_by_name(&t.metric, &t.component)?,
+                    to,
+                    parameter: self.models[to].1.get_parameter_by_name(&t.parameter)?,
+                    lagged: t.lagged,
+                })
+            })
+            .collect::<Result<Vec<_>, PywrError>>()?;
+
+        let mut runs = Vec::with_capacity(self.models.len());
+        for (_, model) in self.models.iter_mut() {
+            runs.push(model.start_run(timestepper, solver, settings)?);
+        }
+
+        while runs.iter().any(|r| !r.is_complete()) {
+            for &m in &order {
+                for transfer in transfers.iter().filter(|t| t.to == m && !t.lagged) {
+                    transfer.send(&self.models, &runs)?;
+                }
+                self.models[m].1.step_run(&mut runs[m])?;
+            }
+            // Lagged transfers are received in the next timestep.
+            for transfer in transfers.iter().filter(|t| t.lagged) {
+                transfer.send(&self.models, &runs)?;
+            }
+        }
+
+        for ((_, model), run) in self.models.iter_mut().zip(runs) {
+            model.finish_run(run)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ModelBuilder;
+    use crate::parameters::TransferParameter;
+    use crate::recorders::{AggregatedRecorder, RecorderAggregation};
+    use crate::solvers::simplex::SimplexSolver;
+    use float_cmp::approx_eq;
+
+    /// A regional model supplying its own demand of 20 and exporting up to 10 from a source of 30.
+    fn regional() -> Model {
+        ModelBuilder::new()
+            .input("source")
+            .output("demand")
+            .output("export")
+            .connect("source", "demand")
+            .connect("source", "export")
+            .max_flow("source", 30.0)
+            .max_flow("demand", 20.0)
+            .cost("demand", -10.0)
+            .max_flow("export", 10.0)
+            .cost("export", -5.0)
+            .build()
+            .unwrap()
+    }
+
+    /// A neighbouring model meeting a demand of 25 from a local source of 5 and its imports.
+    fn neighbour() -> Model {
+        let mut model = ModelBuilder::new()
+            .input("local")
+            .input("import")
+            .output("demand")
+            .connect("local", "demand")
+            .connect("import", "demand")
+            .max_flow("local", 5.0)
+            .parameter(TransferParameter::new("import", 0.0))
+            .max_flow("import", "import")
+            .max_flow("demand", 25.0)
+            .cost("demand", -10.0)
+            .build()
+            .unwrap();
+        let deficit = model.metric_by_name("node_deficit", "demand").unwrap();
+        let recorder = AggregatedRecorder::new("deficit", deficit, RecorderAggregation::Sum, RecorderAggregation::Mean);
+        model.add_recorder(Box::new(recorder)).unwrap();
+        model
+    }
+
+    fn multi_model(transfer: Transfer) -> MultiModel {
+        let mut multi_model = MultiModel::new();
+        // The neighbour is added first, but must be solved after the regional model.
+        multi_model.add_model("neighbour", neighbour()).unwrap();
+        multi_model.add_model("regional", regional()).unwrap();
+        multi_model.add_transfer(transfer).unwrap();
+        multi_model
+    }
+
+    fn deficit(transfer: Transfer) -> f64 {
+        let mut multi_model = multi_model(transfer);
+        let timestepper = Timestepper::new("2020-01-01", "2020-01-10", "%Y-%m-%d", 1).unwrap();
+        multi_model
+            .run(&timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        multi_model
+            .model("neighbour")
+            .unwrap()
+            .get_aggregated_value("deficit")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_transfer() {
+        let transfer = Transfer::new("regional", "node_inflow", "export", "neighbour", "import");
+        assert_eq!(
+            multi_model(transfer.clone()).order().unwrap(),
+            vec!["regional", "neighbour"]
+        );
+        // The import of 10 leaves a deficit of 10 in each of the 10 timesteps.
+        assert!(approx_eq!(f64, deficit(transfer.clone()), 100.0, epsilon = 1e-6));
+        // Lagged, there is no import in the first timestep.
+        assert!(approx_eq!(f64, deficit(transfer.lagged()), 110.0, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn test_order() {
+        let transfer = Transfer::new("regional", "node_inflow", "export", "neighbour", "import");
+        let mut multi_model = multi_model(transfer);
+        assert_eq!(
+            multi_model.add_model("regional", Model::new()),
+            Err(PywrError::ModelNameAlreadyExists("regional".to_string()))
+        );
+        assert_eq!(
+            multi_model.add_transfer(Transfer::new("regional", "node_inflow", "export", "upstream", "import")),
+            Err(PywrError::ModelNotFound("upstream".to_string()))
+        );
+
+        let back = Transfer::new("neighbour", "node_inflow", "demand", "regional", "return");
+        multi_model.add_transfer(back.clone()).unwrap();
+        assert!(matches!(multi_model.order(), Err(PywrError::InvalidMultiModel(_))));
+
+        multi_model.transfers.pop();
+        multi_model.add_transfer(back.lagged()).unwrap();
+        assert_eq!(multi_model.order().unwrap(), vec!["regional", "neighbour"]);
+    }
+}
@@ -1,13 +1,12 @@
 use crate::metric::Metric;
 use crate::parameters::{ConstantParameter, Parameter};
-use crate::state::{NetworkState, NodeState};
+use crate::state::{DerivedValues, NetworkState, NodeState};
 use crate::{Edge, PywrError};
-use std::cell::RefCell;
 use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
+use std::sync::{Arc, RwLock};
 
-pub type NodeIndex = usize;
-pub type NodeRef = Rc<RefCell<_Node>>;
+index_type!(NodeIndex);
+pub type NodeRef = Arc<RwLock<_Node>>;
 
 #[derive(Debug, PartialEq)]
 pub enum _Node {
@@ -17,6 +16,7 @@ pub enum _Node {
     Storage(StorageNode),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum NodeType {
     Input,
     Output,
@@ -24,10 +24,16 @@ pub enum NodeType {
     Storage,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Node(NodeRef);
 
-#[derive(Debug, Clone)]
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Constraint {
     MinFlow,
     MaxFlow,
@@ -47,30 +53,30 @@ impl Node {
     /// Create a new input node
     pub fn new_input(node_index: &NodeIndex, name: &str) -> Self {
         let node = _Node::Input(InputNode::new(node_index, name));
-        Node(Rc::new(RefCell::new(node)))
+        Node(Arc::new(RwLock::new(node)))
     }
 
     /// Create a new output node
     pub fn new_output(node_index: &NodeIndex, name: &str) -> Self {
         let node = _Node::Output(OutputNode::new(node_index, name));
-        Node(Rc::new(RefCell::new(node)))
+        Node(Arc::new(RwLock::new(node)))
     }
 
     /// Create a new link node
     pub fn new_link(node_index: &NodeIndex, name: &str) -> Self {
         let node = _Node::Link(LinkNode::new(node_index, name));
-        Node(Rc::new(RefCell::new(node)))
+        Node(Arc::new(RwLock::new(node)))
     }
 
     /// Create a new storage node
     pub fn new_storage(node_index: &NodeIndex, name: &str, initial_volume: f64) -> Self {
         let node = _Node::Storage(StorageNode::new(node_index, name, initial_volume));
-        Node(Rc::new(RefCell::new(node)))
+        Node(Arc::new(RwLock::new(node)))
     }
 
     /// Get a node's name
     pub fn name(&self) -> String {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(n) => n.meta.name.clone(),
             _Node::Output(n) => n.meta.name.clone(),
             _Node::Link(n) => n.meta.name.clone(),
@@ -80,7 +86,7 @@ impl Node {
 
     /// Get a node's name
     pub fn index(&self) -> NodeIndex {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(n) => n.meta.index,
             _Node::Output(n) => n.meta.index,
             _Node::Link(n) => n.meta.index,
@@ -89,7 +95,7 @@ impl Node {
     }
 
     pub fn node_type(&self) -> NodeType {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(_) => NodeType::Input,
             _Node::Output(_) => NodeType::Output,
             _Node::Link(_) => NodeType::Link,
@@ -101,12 +107,12 @@ impl Node {
     where
         F: Fn(&_Node),
     {
-        f(self.0.borrow().deref());
+        f(self.0.read().unwrap().deref());
     }
 
     pub fn new_state(&self) -> NodeState {
         // TODO add a reference to the node in the state objects?
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(_n) => NodeState::new_flow_state(),
             _Node::Output(_n) => NodeState::new_flow_state(),
             _Node::Link(_n) => NodeState::new_flow_state(),
@@ -114,8 +120,16 @@ impl Node {
         }
     }
 
+    /// The initial volume of a storage node, or `None` for other nodes.
+    pub fn initial_volume(&self) -> Option<f64> {
+        match self.0.read().unwrap().deref() {
+            _Node::Storage(n) => Some(n.initial_volume),
+            _ => None,
+        }
+    }
+
     pub fn default_metric(&self) -> Metric {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(_n) => Metric::NodeOutFlow(self.index()),
             _Node::Output(_n) => Metric::NodeInFlow(self.index()),
             _Node::Link(_n) => Metric::NodeOutFlow(self.index()),
@@ -124,7 +138,7 @@ impl Node {
     }
 
     pub fn add_incoming_edge(&self, edge: Edge) -> Result<(), PywrError> {
-        match self.0.borrow_mut().deref_mut() {
+        match self.0.write().unwrap().deref_mut() {
             _Node::Input(_n) => Err(PywrError::InvalidNodeConnectionToInput),
             _Node::Output(n) => Ok(n.add_incoming_edge(edge)),
             _Node::Link(n) => Ok(n.add_incoming_edge(edge)),
@@ -133,7 +147,7 @@ impl Node {
     }
 
     pub fn add_outgoing_edge(&self, edge: Edge) -> Result<(), PywrError> {
-        match self.0.borrow_mut().deref_mut() {
+        match self.0.write().unwrap().deref_mut() {
             _Node::Input(n) => Ok(n.add_outgoing_edge(edge)),
             _Node::Output(_n) => Err(PywrError::InvalidNodeConnectionFromOutput),
             _Node::Link(n) => Ok(n.add_outgoing_edge(edge)),
@@ -142,7 +156,7 @@ impl Node {
     }
 
     pub fn get_incoming_edges(&self) -> Result<Vec<Edge>, PywrError> {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(_n) => Err(PywrError::InvalidNodeConnectionToInput), // TODO better error
             _Node::Output(n) => Ok(n.incoming_edges.clone()),
             _Node::Link(n) => Ok(n.incoming_edges.clone()),
@@ -151,7 +165,7 @@ impl Node {
     }
 
     pub fn get_outgoing_edges(&self) -> Result<Vec<Edge>, PywrError> {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(n) => Ok(n.outgoing_edges.clone()),
             _Node::Output(_n) => Err(PywrError::InvalidNodeConnectionFromOutput), // TODO better error
             _Node::Link(n) => Ok(n.outgoing_edges.clone()),
@@ -191,7 +205,7 @@ impl Node {
 
     // /// Return a mutable reference to a node's storage constraints if they exist.
     // fn storage_constraints_mut(&self) -> Result<&mut StorageConstraints, PywrError> {
-    //     match self.0.borrow_mut().deref_mut() {
+    //     match self.0.write().unwrap().deref_mut() {
     //         _Node::Input(_) => Err(PywrError::StorageConstraintsUndefined),
     //         _Node::Link(_) => Err(PywrError::StorageConstraintsUndefined),
     //         _Node::Output(_) => Err(PywrError::StorageConstraintsUndefined),
@@ -215,7 +229,7 @@ impl Node {
     }
 
     pub fn set_min_flow_constraint(&self, value: ConstraintValue) -> Result<(), PywrError> {
-        match self.0.borrow_mut().deref_mut() {
+        match self.0.write().unwrap().deref_mut() {
             _Node::Input(n) => Ok(n.set_min_flow(value)),
             _Node::Link(n) => Ok(n.set_min_flow(value)),
             _Node::Output(n) => Ok(n.set_min_flow(value)),
@@ -224,7 +238,7 @@ impl Node {
     }
 
     pub fn get_current_min_flow(&self, parameter_states: &[f64]) -> Result<f64, PywrError> {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(n) => Ok(n.get_min_flow(parameter_states)),
             _Node::Link(n) => Ok(n.get_min_flow(parameter_states)),
             _Node::Output(n) => Ok(n.get_min_flow(parameter_states)),
@@ -233,7 +247,7 @@ impl Node {
     }
 
     pub fn set_max_flow_constraint(&self, value: ConstraintValue) -> Result<(), PywrError> {
-        match self.0.borrow_mut().deref_mut() {
+        match self.0.write().unwrap().deref_mut() {
             _Node::Input(n) => Ok(n.set_max_flow(value)),
             _Node::Link(n) => Ok(n.set_max_flow(value)),
             _Node::Output(n) => Ok(n.set_max_flow(value)),
@@ -242,7 +256,7 @@ impl Node {
     }
 
     pub fn get_current_max_flow(&self, parameter_states: &[f64]) -> Result<f64, PywrError> {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(n) => Ok(n.get_max_flow(parameter_states)),
             _Node::Link(n) => Ok(n.get_max_flow(parameter_states)),
             _Node::Output(n) => Ok(n.get_max_flow(parameter_states)),
@@ -261,7 +275,7 @@ impl Node {
     }
 
     pub fn set_min_volume_constraint(&self, value: ConstraintValue) -> Result<(), PywrError> {
-        match self.0.borrow_mut().deref_mut() {
+        match self.0.write().unwrap().deref_mut() {
             _Node::Input(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Link(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Output(_) => Err(PywrError::StorageConstraintsUndefined),
@@ -270,7 +284,7 @@ impl Node {
     }
 
     pub fn get_current_min_volume(&self, parameter_states: &[f64]) -> Result<f64, PywrError> {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Link(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Output(_) => Err(PywrError::StorageConstraintsUndefined),
@@ -279,7 +293,7 @@ impl Node {
     }
 
     pub fn set_max_volume_constraint(&self, value: ConstraintValue) -> Result<(), PywrError> {
-        match self.0.borrow_mut().deref_mut() {
+        match self.0.write().unwrap().deref_mut() {
             _Node::Input(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Link(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Output(_) => Err(PywrError::StorageConstraintsUndefined),
@@ -288,7 +302,7 @@ impl Node {
     }
 
     pub fn get_current_max_volume(&self, parameter_states: &[f64]) -> Result<f64, PywrError> {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Link(_) => Err(PywrError::StorageConstraintsUndefined),
             _Node::Output(_) => Err(PywrError::StorageConstraintsUndefined),
@@ -327,8 +341,94 @@ impl Node {
         }
     }
 
+    /// Compute the values derived from the node's state after a solve with `parameter_states`.
+    pub fn derived_values(
+        &self,
+        network_state: &NetworkState,
+        parameter_states: &[f64],
+    ) -> Result<DerivedValues, PywrError> {
+        let index = self.index();
+        let mut values = DerivedValues::default();
+        match self.node_type() {
+            NodeType::Storage => {
+                let volume = network_state.get_node_volume(index)?;
+                let max_volume = self.get_current_max_volume(parameter_states)?;
+                values.proportional_volume = Some(match max_volume > 0.0 {
+                    true => volume / max_volume,
+                    false => 0.0,
+                });
+            }
+            node_type => {
+                let max_flow = self.get_current_max_flow(parameter_states)?;
+                // Input nodes have no incoming edges; use the flow leaving them instead.
+                let flow = match node_type {
+                    NodeType::Input => network_state.get_node_out_flow(index)?,
+                    _ => network_state.get_node_in_flow(index)?,
+                };
+                values.deficit = Some((max_flow - flow).max(0.0));
+            }
+        }
+        Ok(values)
+    }
+
+    /// The value of one of the node's constraints. The minimum and maximum flow must be got
+    /// separately.
+    pub fn get_constraint(&self, constraint: Constraint) -> Result<ConstraintValue, PywrError> {
+        let node = self.0.read().unwrap();
+        let flow_constraints = match node.deref() {
+            _Node::Input(n) => Some(&n.flow_constraints),
+            _Node::Output(n) => Some(&n.flow_constraints),
+            _Node::Link(n) => Some(&n.flow_constraints),
+            _Node::Storage(_) => None,
+        };
+        let storage_constraints = match node.deref() {
+            _Node::Storage(n) => Some(&n.storage_constraints),
+            _ => None,
+        };
+        match constraint {
+            Constraint::MinFlow => flow_constraints
+                .map(|c| c.min_flow.clone())
+                .ok_or(PywrError::FlowConstraintsUndefined),
+            Constraint::MaxFlow => flow_constraints
+                .map(|c| c.max_flow.clone())
+                .ok_or(PywrError::FlowConstraintsUndefined),
+            Constraint::MinVolume => storage_constraints
+                .map(|c| c.min_volume.clone())
+                .ok_or(PywrError::StorageConstraintsUndefined),
+            Constraint::MaxVolume => storage_constraints
+                .map(|c| c.max_volume.clone())
+                .ok_or(PywrError::StorageConstraintsUndefined),
+            Constraint::MinAndMaxFlow => Err(PywrError::InvalidConstraintType(
+                "the minimum and maximum flow can not be got together".to_string(),
+            )),
+        }
+    }
+
+    pub fn get_cost(&self) -> ConstraintValue {
+        match self.0.read().unwrap().deref() {
+            _Node::Input(n) => n.cost.clone(),
+            _Node::Output(n) => n.cost.clone(),
+            _Node::Link(n) => n.cost.clone(),
+            _Node::Storage(n) => n.cost.clone(),
+        }
+    }
+
+    /// The node's cost and constraints, by the name of the attribute, e.g. `max_flow`.
+    pub fn attributes(&self) -> Vec<(&'static str, ConstraintValue)> {
+        match self.0.read().unwrap().deref() {
+            _Node::Input(n) => flow_attributes(&n.cost, &n.flow_constraints),
+            _Node::Output(n) => flow_attributes(&n.cost, &n.flow_constraints),
+            _Node::Link(n) => flow_attributes(&n.cost, &n.flow_constraints),
+            _Node::Storage(n) => vec![
+                ("cost", n.cost.clone()),
+                ("min_volume", n.storage_constraints.min_volume.clone()),
+                ("max_volume", n.storage_constraints.max_volume.clone()),
+            ],
+        }
+    }
+
     pub fn set_cost(&self, value: ConstraintValue) {
-        match self.0.borrow_mut().deref_mut() {
+        match self.0.write().unwrap().deref_mut() {
             _Node::Input(n) => n.set_cost(value),
             _Node::Link(n) => n.set_cost(value),
             _Node::Output(n) => n.set_cost(value),
@@ -336,8 +436,18 @@ impl Node {
         }
     }
 
+    /// Return the node's cost as resolved from the current parameter state.
+    pub fn get_current_cost(&self, parameter_states: &[f64]) -> f64 {
+        match self.0.read().unwrap().deref() {
+            _Node::Input(n) => n.get_cost(parameter_states),
+            _Node::Link(n) => n.get_cost(parameter_states),
+            _Node::Output(n) => n.get_cost(parameter_states),
+            _Node::Storage(n) => n.get_cost(parameter_states),
+        }
+    }
+
     pub fn get_outgoing_cost(&self, parameter_states: &[f64]) -> f64 {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(n) => n.get_cost(parameter_states),
             _Node::Link(n) => n.get_cost(parameter_states) / 2.0,
             _Node::Output(n) => n.get_cost(parameter_states),
@@ -346,7 +456,7 @@ impl Node {
     }
 
     pub fn get_incoming_cost(&self, parameter_states: &[f64]) -> f64 {
-        match self.0.borrow().deref() {
+        match self.0.read().unwrap().deref() {
             _Node::Input(n) => n.get_cost(parameter_states),
             _Node::Link(n) => n.get_cost(parameter_states) / 2.0,
             _Node::Output(n) => n.get_cost(parameter_states),
@@ -355,6 +465,14 @@ impl Node {
     }
 }
 
+fn flow_attributes(cost: &ConstraintValue, constraints: &FlowConstraints) -> Vec<(&'static str, ConstraintValue)> {
+    vec![
+        ("cost", cost.clone()),
+        ("min_flow", constraints.min_flow.clone()),
+        ("max_flow", constraints.max_flow.clone()),
+    ]
+}
+
 /// Meta data common to all nodes.
 #[derive(Debug, PartialEq)]
 pub struct NodeMeta {
@@ -380,7 +498,7 @@ pub struct FlowConstraints {
 }
 
 impl FlowConstraints {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             min_flow: ConstraintValue::None,
             max_flow: ConstraintValue::None,
@@ -389,25 +507,57 @@ impl FlowConstraints {
     /// Return the current minimum flow from the parameter state
     ///
     /// Defaults to zero if no parameter is defined.
-    fn get_min_flow(&self, parameter_states: &[f64]) -> f64 {
+    pub(crate) fn get_min_flow(&self, parameter_states: &[f64]) -> f64 {
         match &self.min_flow {
             ConstraintValue::None => 0.0,
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
     /// Return the current maximum flow from the parameter state
     ///
     /// Defaults to f64::MAX if no parameter is defined.
-    fn get_max_flow(&self, parameter_states: &[f64]) -> f64 {
+    pub(crate) fn get_max_flow(&self, parameter_states: &[f64]) -> f64 {
         match &self.max_flow {
             ConstraintValue::None => f64::MAX, // TODO should this return infinity?
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
 }
 
+/// A group of nodes whose flows must be in proportion to their factors, e.g. a blend of two
+/// sources for water quality.
+///
+/// The flow of each node is the flow of the first node scaled by the ratio of their factors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowFactors {
+    pub(crate) nodes: Vec<NodeIndex>,
+    pub(crate) factors: Vec<ConstraintValue>,
+}
+
+impl FlowFactors {
+    pub fn nodes(&self) -> &[NodeIndex] {
+        &self.nodes
+    }
+
+    pub fn factors(&self) -> &[ConstraintValue] {
+        &self.factors
+    }
+
+    /// Return the factor of each node as resolved from the current parameter state.
+    pub(crate) fn get_current_factors(&self, parameter_states: &[f64]) -> Vec<f64> {
+        self.factors
+            .iter()
+            .map(|factor| match factor {
+                ConstraintValue::None => 1.0,
+                ConstraintValue::Scalar(v) => *v,
+                ConstraintValue::Parameter(p) => parameter_states[*p.index()],
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct StorageConstraints {
     pub(crate) min_volume: ConstraintValue,
@@ -428,7 +578,7 @@ impl StorageConstraints {
         match &self.min_volume {
             ConstraintValue::None => 0.0,
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
     /// Return the current maximum volume from the parameter state
@@ -438,7 +588,7 @@ impl StorageConstraints {
         match &self.max_volume {
             ConstraintValue::None => f64::MAX, // TODO should this return infinity?
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
 }
@@ -467,7 +617,7 @@ impl InputNode {
         match &self.cost {
             ConstraintValue::None => 0.0,
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
     fn set_min_flow(&mut self, value: ConstraintValue) {
@@ -511,7 +661,7 @@ impl OutputNode {
         match &self.cost {
             ConstraintValue::None => 0.0,
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
     fn set_min_flow(&mut self, value: ConstraintValue) {
@@ -557,7 +707,7 @@ impl LinkNode {
         match &self.cost {
             ConstraintValue::None => 0.0,
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
     fn set_min_flow(&mut self, value: ConstraintValue) {
@@ -609,7 +759,7 @@ impl StorageNode {
         match &self.cost {
             ConstraintValue::None => 0.0,
             ConstraintValue::Scalar(v) => *v,
-            ConstraintValue::Parameter(p) => parameter_states[p.index()],
+            ConstraintValue::Parameter(p) => parameter_states[*p.index()],
         }
     }
     fn set_min_volume(&mut self, value: ConstraintValue) {
@@ -1,17 +1,19 @@
 pub mod py;
 
 use super::{NetworkState, PywrError};
-use crate::scenario::ScenarioIndex;
+use crate::node::NodeIndex;
+use crate::scenario::{ScenarioGroupCollection, ScenarioGroupLookup, ScenarioIndex};
+use crate::schema::ParameterType;
+use crate::state::{ParameterState, ParameterValue};
 use crate::timestep::Timestep;
 use ndarray::{Array1, Array2};
-use std::cell::RefCell;
 use std::fmt;
 use std::ops::Deref;
-use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 
-pub type ParameterIndex = usize;
-pub type ParameterRef = Rc<RefCell<Box<dyn _Parameter>>>;
+index_type!(ParameterIndex);
+pub type ParameterRef = Arc<RwLock<Box<dyn _Parameter>>>;
 
 /// Meta data common to all parameters.
 #[derive(Debug)]
@@ -29,16 +31,72 @@ impl ParameterMeta {
     }
 }
 
-pub trait _Parameter {
+pub trait _Parameter: Send + Sync {
     fn meta(&self) -> &ParameterMeta;
-    fn before(&self) {}
+    /// Prepare the parameter for a run of `scenarios`.
+    fn setup(&mut self, _scenarios: &ScenarioGroupCollection) -> Result<(), PywrError> {
+        Ok(())
+    }
+    /// Called once before each timestep is solved, before the parameters of any scenario are computed.
+    fn before(&mut self, _timestep: &Timestep) -> Result<(), PywrError> {
+        Ok(())
+    }
     fn compute(
         &self,
         timestep: &Timestep,
         scenario_index: &ScenarioIndex,
         network_state: &NetworkState,
-        parameter_state: &[f64],
+        parameter_state: &ParameterState,
     ) -> Result<f64, PywrError>;
+    /// Compute the value of the parameter, which is the float from `compute` unless it is
+    /// overridden by a parameter with an index or several values.
+    fn compute_value(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &ParameterState,
+    ) -> Result<ParameterValue, PywrError> {
+        Ok(ParameterValue::Float(self.compute(
+            timestep,
+            scenario_index,
+            network_state,
+            parameter_state,
+        )?))
+    }
+    /// Called after each timestep is solved with the new state of each scenario in turn.
+    ///
+    /// This is where a parameter that keeps memory between timesteps (e.g. the remaining volume
+    /// of a licence) updates it from the solution.
+    fn after(
+        &mut self,
+        _timestep: &Timestep,
+        _scenario_index: &ScenarioIndex,
+        _network_state: &NetworkState,
+        _parameter_state: &ParameterState,
+    ) -> Result<(), PywrError> {
+        Ok(())
+    }
+    /// The memory the parameter keeps between timesteps, to be saved in a checkpoint.
+    fn checkpoint_state(&self) -> Vec<f64> {
+        Vec::new()
+    }
+    /// Restore the memory saved by `checkpoint_state` when a run is resumed from a checkpoint.
+    fn restore_state(&mut self, _values: &[f64]) -> Result<(), PywrError> {
+        Ok(())
+    }
+    /// The node whose flow history the parameter reads, and the number of timesteps it needs.
+    fn required_flow_history(&self) -> Option<(NodeIndex, usize)> {
+        None
+    }
+    /// The parameter as a schema, or `None` if it can not be written to a model file.
+    fn schema(&self) -> Option<ParameterType> {
+        None
+    }
+    /// Set the value of each scenario from another model (see `MultiModel`), before they are computed.
+    fn receive_values(&mut self, _values: &[f64]) -> Result<(), PywrError> {
+        Err(PywrError::ParameterCannotReceiveValues(self.meta().name.clone()))
+    }
 }
 
 #[derive(Clone)]
@@ -59,7 +117,7 @@ impl fmt::Debug for Parameter {
 
 impl Parameter {
     pub fn new(parameter: Box<dyn _Parameter>, index: ParameterIndex) -> Self {
-        Self(Rc::new(RefCell::new(parameter)), index)
+        Self(Arc::new(RwLock::new(parameter)), index)
     }
 
     pub fn index(&self) -> ParameterIndex {
@@ -67,7 +125,11 @@ impl Parameter {
     }
 
     pub fn name(&self) -> String {
-        self.0.borrow().deref().meta().name.to_string()
+        self.0.read().unwrap().deref().meta().name.to_string()
+    }
+
+    pub fn setup(&self, scenarios: &ScenarioGroupCollection) -> Result<(), PywrError> {
+        self.0.write().unwrap().setup(scenarios)
     }
 
     pub fn compute(
@@ -75,13 +137,65 @@ impl Parameter {
         timestep: &Timestep,
         scenario_index: &ScenarioIndex,
         network_state: &NetworkState,
-        parameter_state: &[f64],
+        parameter_state: &ParameterState,
     ) -> Result<f64, PywrError> {
         self.0
-            .borrow()
+            .read()
+            .unwrap()
             .deref()
             .compute(timestep, scenario_index, network_state, parameter_state)
     }
+
+    pub fn compute_value(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &ParameterState,
+    ) -> Result<ParameterValue, PywrError> {
+        self.0
+            .read()
+            .unwrap()
+            .deref()
+            .compute_value(timestep, scenario_index, network_state, parameter_state)
+    }
+
+    pub fn before(&self, timestep: &Timestep) -> Result<(), PywrError> {
+        self.0.write().unwrap().before(timestep)
+    }
+
+    pub fn after(
+        &self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &ParameterState,
+    ) -> Result<(), PywrError> {
+        self.0
+            .write()
+            .unwrap()
+            .after(timestep, scenario_index, network_state, parameter_state)
+    }
+
+    pub fn checkpoint_state(&self) -> Vec<f64> {
+        self.0.read().unwrap().checkpoint_state()
+    }
+
+    pub fn restore_state(&self, values: &[f64]) -> Result<(), PywrError> {
+        self.0.write().unwrap().restore_state(values)
+    }
+
+    pub fn required_flow_history(&self) -> Option<(NodeIndex, usize)> {
+        self.0.read().unwrap().required_flow_history()
+    }
+
+    pub fn schema(&self) -> Option<ParameterType> {
+        self.0.read().unwrap().schema()
+    }
+
+    pub fn receive_values(&self, values: &[f64]) -> Result<(), PywrError> {
+        self.0.write().unwrap().receive_values(values)
+    }
 }
 
 pub struct ConstantParameter {
@@ -107,10 +221,13 @@ impl _Parameter for ConstantParameter {
         _timestep: &Timestep,
         _scenario_index: &ScenarioIndex,
         _state: &NetworkState,
-        _parameter_state: &[f64],
+        _parameter_state: &ParameterState,
     ) -> Result<f64, PywrError> {
         Ok(self.value)
     }
+    fn schema(&self) -> Option<ParameterType> {
+        Some(ParameterType::Constant(self.value))
+    }
 }
 
 pub struct VectorParameter {
@@ -136,13 +253,16 @@ impl _Parameter for VectorParameter {
         timestep: &Timestep,
         _scenario_index: &ScenarioIndex,
         _state: &NetworkState,
-        _parameter_state: &[f64],
+        _parameter_state: &ParameterState,
     ) -> Result<f64, PywrError> {
         match self.values.get(timestep.index) {
             Some(v) => Ok(*v),
             None => Err(PywrError::TimestepIndexOutOfRange),
         }
     }
+    fn schema(&self) -> Option<ParameterType> {
+        Some(ParameterType::Vector(self.values.clone()))
+    }
 }
 
 pub struct Array1Parameter {
@@ -168,7 +288,7 @@ impl _Parameter for Array1Parameter {
         timestep: &Timestep,
         _scenario_index: &ScenarioIndex,
         _state: &NetworkState,
-        _parameter_state: &[f64],
+        _parameter_state: &ParameterState,
     ) -> Result<f64, PywrError> {
         // This panics if out-of-bounds
         let value = self.array[[timestep.index]];
@@ -176,9 +296,14 @@ impl _Parameter for Array1Parameter {
     }
 }
 
+/// A parameter with a row of values for each timestep.
+///
+/// The column is given by the member of a scenario group, or is the first column if the
+/// parameter is not associated with a group.
 pub struct Array2Parameter {
     meta: ParameterMeta,
     array: Array2<f64>,
+    scenario_group: Option<ScenarioGroupLookup>,
 }
 
 impl Array2Parameter {
@@ -186,6 +311,16 @@ impl Array2Parameter {
         Self {
             meta: ParameterMeta::new(name),
             array,
+            scenario_group: None,
+        }
+    }
+
+    /// Create a parameter with a column for each member of the scenario group called `group`.
+    pub fn new_with_scenario_group(name: &str, array: Array2<f64>, group: &str) -> Self {
+        Self {
+            meta: ParameterMeta::new(name),
+            array,
+            scenario_group: Some(ScenarioGroupLookup::new(group)),
         }
     }
 }
@@ -194,19 +329,146 @@ impl _Parameter for Array2Parameter {
     fn meta(&self) -> &ParameterMeta {
         &self.meta
     }
+    fn setup(&mut self, scenarios: &ScenarioGroupCollection) -> Result<(), PywrError> {
+        match &mut self.scenario_group {
+            Some(lookup) => lookup.setup(scenarios, self.array.ncols()),
+            None => Ok(()),
+        }
+    }
     fn compute(
         &self,
         timestep: &Timestep,
-        _scenario_index: &ScenarioIndex,
+        scenario_index: &ScenarioIndex,
         _state: &NetworkState,
-        _parameter_state: &[f64],
+        _parameter_state: &ParameterState,
     ) -> Result<f64, PywrError> {
+        let column = match &self.scenario_group {
+            Some(lookup) => lookup.member(scenario_index)?,
+            None => 0,
+        };
         // This panics if out-of-bounds
-        // TODO scenarios!
-        Ok(self.array[[timestep.index, 0]])
+        Ok(self.array[[timestep.index, column]])
+    }
+}
+
+/// A parameter with a value for each day of the year, e.g. a seasonal demand profile.
+///
+/// There is a value for each day of the longest year of the run's calendar: 366 in the Gregorian
+/// calendar (in which the value for the 29th of February is skipped in other years), 365 without
+/// leap days and 360 in a calendar of 30 day months.
+pub struct DailyProfileParameter {
+    meta: ParameterMeta,
+    values: Vec<f64>,
+}
+
+impl DailyProfileParameter {
+    pub fn new(name: &str, values: Vec<f64>) -> Self {
+        Self {
+            meta: ParameterMeta::new(name),
+            values,
+        }
+    }
+}
+
+impl _Parameter for DailyProfileParameter {
+    fn meta(&self) -> &ParameterMeta {
+        &self.meta
+    }
+    fn compute(
+        &self,
+        timestep: &Timestep,
+        _scenario_index: &ScenarioIndex,
+        _state: &NetworkState,
+        _parameter_state: &ParameterState,
+    ) -> Result<f64, PywrError> {
+        match self.values.get(timestep.day_of_year_index()) {
+            Some(v) => Ok(*v),
+            None => Err(PywrError::TimestepIndexOutOfRange),
+        }
+    }
+    /// Only profiles of the Gregorian calendar, with a value for each day of a leap year, can be
+    /// written to a model file.
+    fn schema(&self) -> Option<ParameterType> {
+        match self.values.len() {
+            366 => Some(ParameterType::DailyProfile(self.values.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// A parameter with a value for each hour of the day, e.g. the price of electricity.
+///
+/// The value is given by the hour of the day that the timestep starts.
+pub struct HourlyProfileParameter {
+    meta: ParameterMeta,
+    values: [f64; 24],
+}
+
+impl HourlyProfileParameter {
+    pub fn new(name: &str, values: [f64; 24]) -> Self {
+        Self {
+            meta: ParameterMeta::new(name),
+            values,
+        }
+    }
+}
+
+impl _Parameter for HourlyProfileParameter {
+    fn meta(&self) -> &ParameterMeta {
+        &self.meta
+    }
+    fn compute(
+        &self,
+        timestep: &Timestep,
+        _scenario_index: &ScenarioIndex,
+        _state: &NetworkState,
+        _parameter_state: &ParameterState,
+    ) -> Result<f64, PywrError> {
+        Ok(self.values[timestep.hour() as usize])
+    }
+}
+
+/// A parameter with an observed value for each timestep, and forecasts of the later values.
+///
+/// The forecasts have a row for each timestep they are issued at, and a column for each lead
+/// time from one timestep ahead. They are used for the lookahead timesteps of a rolling horizon
+/// (see `Model::set_rolling_horizon`), and the observed values otherwise.
+pub struct ForecastParameter {
+    meta: ParameterMeta,
+    observed: Array1<f64>,
+    forecasts: Array2<f64>,
+}
+
+impl ForecastParameter {
+    pub fn new(name: &str, observed: Array1<f64>, forecasts: Array2<f64>) -> Self {
+        Self {
+            meta: ParameterMeta::new(name),
+            observed,
+            forecasts,
+        }
+    }
+}
+
+impl _Parameter for ForecastParameter {
+    fn meta(&self) -> &ParameterMeta {
+        &self.meta
+    }
+    fn compute(
+        &self,
+        timestep: &Timestep,
+        _scenario_index: &ScenarioIndex,
+        _state: &NetworkState,
+        _parameter_state: &ParameterState,
+    ) -> Result<f64, PywrError> {
+        let value = match timestep.forecast_from {
+            Some(issued) if timestep.index > issued => self.forecasts.get((issued, timestep.index - issued - 1)),
+            _ => self.observed.get(timestep.index),
+        };
+        value.copied().ok_or(PywrError::TimestepIndexOutOfRange)
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AggFunc {
     Sum,
     Product,
@@ -215,6 +477,18 @@ pub enum AggFunc {
     Max,
 }
 
+impl AggFunc {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sum => "sum",
+            Self::Product => "product",
+            Self::Mean => "mean",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
 impl FromStr for AggFunc {
     type Err = PywrError;
 
@@ -230,6 +504,146 @@ impl FromStr for AggFunc {
     }
 }
 
+/// The flow of a node a number of timesteps ago, e.g. for the travel time along a river reach.
+///
+/// Until the node has flowed for `delay` timesteps the `initial_value` is used.
+pub struct DelayParameter {
+    meta: ParameterMeta,
+    node: NodeIndex,
+    delay: usize,
+    initial_value: f64,
+}
+
+impl DelayParameter {
+    pub fn new(name: &str, node: NodeIndex, delay: usize, initial_value: f64) -> Self {
+        Self {
+            meta: ParameterMeta::new(name),
+            node,
+            delay: delay.max(1),
+            initial_value,
+        }
+    }
+}
+
+impl _Parameter for DelayParameter {
+    fn meta(&self) -> &ParameterMeta {
+        &self.meta
+    }
+    fn compute(
+        &self,
+        _timestep: &Timestep,
+        _scenario_index: &ScenarioIndex,
+        state: &NetworkState,
+        _parameter_state: &ParameterState,
+    ) -> Result<f64, PywrError> {
+        let history = state.get_node_flow_history(self.node)?;
+        Ok(history.get(self.delay - 1).unwrap_or(self.initial_value))
+    }
+    fn required_flow_history(&self) -> Option<(NodeIndex, usize)> {
+        Some((self.node, self.delay))
+    }
+}
+
+/// The mean flow of a node over the previous `timesteps` timesteps.
+///
+/// The mean is of the timesteps that have been solved until there are enough of them, and the
+/// `initial_value` before the first.
+pub struct RollingMeanParameter {
+    meta: ParameterMeta,
+    node: NodeIndex,
+    timesteps: usize,
+    initial_value: f64,
+}
+
+impl RollingMeanParameter {
+    pub fn new(name: &str, node: NodeIndex, timesteps: usize, initial_value: f64) -> Self {
+        Self {
+            meta: ParameterMeta::new(name),
+            node,
+            timesteps: timesteps.max(1),
+            initial_value,
+        }
+    }
+}
+
+impl _Parameter for RollingMeanParameter {
+    fn meta(&self) -> &ParameterMeta {
+        &self.meta
+    }
+    fn compute(
+        &self,
+        _timestep: &Timestep,
+        _scenario_index: &ScenarioIndex,
+        state: &NetworkState,
+        _parameter_state: &ParameterState,
+    ) -> Result<f64, PywrError> {
+        let history = state.get_node_flow_history(self.node)?;
+        let count = history.len().min(self.timesteps);
+        match count {
+            0 => Ok(self.initial_value),
+            _ => Ok(history.iter().take(count).sum::<f64>() / count as f64),
+        }
+    }
+    fn required_flow_history(&self) -> Option<(NodeIndex, usize)> {
+        Some((self.node, self.timesteps))
+    }
+}
+
+/// A value of each scenario transferred from another model run alongside this one by a
+/// `MultiModel`, e.g. the volume exported by a neighbouring supply model.
+///
+/// The `initial_value` is used until a value is received, which is the first timestep of a
+/// lagged transfer.
+pub struct TransferParameter {
+    meta: ParameterMeta,
+    initial_value: f64,
+    values: Vec<f64>,
+}
+
+impl TransferParameter {
+    pub fn new(name: &str, initial_value: f64) -> Self {
+        Self {
+            meta: ParameterMeta::new(name),
+            initial_value,
+            values: Vec::new(),
+        }
+    }
+}
+
+impl _Parameter for TransferParameter {
+    fn meta(&self) -> &ParameterMeta {
+        &self.meta
+    }
+    fn setup(&mut self, scenarios: &ScenarioGroupCollection) -> Result<(), PywrError> {
+        self.values = vec![self.initial_value; scenarios.scenario_indices().len()];
+        Ok(())
+    }
+    fn compute(
+        &self,
+        _timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        _state: &NetworkState,
+        _parameter_state: &ParameterState,
+    ) -> Result<f64, PywrError> {
+        match self.values.get(scenario_index.index) {
+            Some(v) => Ok(*v),
+            None => Err(PywrError::ScenarioIndexNotFound),
+        }
+    }
+    fn receive_values(&mut self, values: &[f64]) -> Result<(), PywrError> {
+        if values.len() != self.values.len() {
+            return Err(PywrError::InvalidMultiModel(format!(
+                "parameter `{}` has {} scenarios but received {} values",
+                self.meta.name,
+                self.values.len(),
+                values.len()
+            )));
+        }
+        self.values.copy_from_slice(values);
+        Ok(())
+    }
+}
+
 pub struct AggregatedParameter {
     meta: ParameterMeta,
     parameters: Vec<Parameter>,
@@ -255,7 +669,7 @@ impl _Parameter for AggregatedParameter {
         _timestep: &Timestep,
         _scenario_index: &ScenarioIndex,
         _state: &NetworkState,
-        parameter_state: &[f64],
+        parameter_state: &ParameterState,
     ) -> Result<f64, PywrError> {
         // TODO scenarios!
 
@@ -263,9 +677,9 @@ impl _Parameter for AggregatedParameter {
             AggFunc::Sum => {
                 let mut total = 0.0_f64;
                 for p in &self.parameters {
-                    total += match parameter_state.get(p.index()) {
+                    total += match parameter_state.get(*p.index()) {
                         Some(v) => v,
-                        None => return Err(PywrError::ParameterIndexNotFound),
+                        None => return Err(PywrError::ParameterIndexNotFound(p.index())),
                     };
                 }
                 total
@@ -273,9 +687,9 @@ impl _Parameter for AggregatedParameter {
             AggFunc::Mean => {
                 let mut total = 0.0_f64;
                 for p in &self.parameters {
-                    total += match parameter_state.get(p.index()) {
+                    total += match parameter_state.get(*p.index()) {
                         Some(v) => v,
-                        None => return Err(PywrError::ParameterIndexNotFound),
+                        None => return Err(PywrError::ParameterIndexNotFound(p.index())),
                     };
                 }
                 total / self.parameters.len() as f64
@@ -283,9 +697,9 @@ impl _Parameter for AggregatedParameter {
             AggFunc::Max => {
                 let mut total = f64::MIN;
                 for p in &self.parameters {
-                    total = total.max(match parameter_state.get(p.index()) {
+                    total = total.max(match parameter_state.get(*p.index()) {
                         Some(v) => *v,
-                        None => return Err(PywrError::ParameterIndexNotFound),
+                        None => return Err(PywrError::ParameterIndexNotFound(p.index())),
                     });
                 }
                 total
@@ -293,9 +707,9 @@ impl _Parameter for AggregatedParameter {
             AggFunc::Min => {
                 let mut total = f64::MAX;
                 for p in &self.parameters {
-                    total = total.min(match parameter_state.get(p.index()) {
+                    total = total.min(match parameter_state.get(*p.index()) {
                         Some(v) => *v,
-                        None => return Err(PywrError::ParameterIndexNotFound),
+                        None => return Err(PywrError::ParameterIndexNotFound(p.index())),
                     });
                 }
                 total
@@ -303,9 +717,9 @@ impl _Parameter for AggregatedParameter {
             AggFunc::Product => {
                 let mut total = 1.0_f64;
                 for p in &self.parameters {
-                    total *= match parameter_state.get(p.index()) {
+                    total *= match parameter_state.get(*p.index()) {
                         Some(v) => *v,
-                        None => return Err(PywrError::ParameterIndexNotFound),
+                        None => return Err(PywrError::ParameterIndexNotFound(p.index())),
                     };
                 }
                 total
@@ -314,6 +728,12 @@ impl _Parameter for AggregatedParameter {
 
         Ok(value)
     }
+    fn schema(&self) -> Option<ParameterType> {
+        Some(ParameterType::Aggregated {
+            agg_func: self.agg_func,
+            parameters: self.parameters.iter().map(|p| p.name()).collect(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -321,7 +741,8 @@ mod tests {
     use super::*;
     use crate::assert_almost_eq;
     use crate::state::ParameterState;
-    use crate::timestep::Timestepper;
+    use crate::timestep::{Calendar, TimestepDuration, Timestepper};
+    use chrono::Duration as ChronoDuration;
     use ndarray::prelude::*;
     use std::f64::consts::PI;
 
@@ -337,6 +758,7 @@ mod tests {
         let si = ScenarioIndex {
             index: 0,
             indices: vec![0],
+            labels: None,
         };
 
         for ts in timestepper.timesteps().iter() {
@@ -346,6 +768,39 @@ mod tests {
         }
     }
 
+    #[test]
+    /// Test index and multi-values are kept alongside the float values of the parameters.
+    fn test_parameter_state_values() {
+        let ts = &test_timestepper().timesteps()[0];
+        let si = ScenarioIndex {
+            index: 0,
+            indices: vec![0],
+            labels: None,
+        };
+        let ns = NetworkState::new();
+
+        let mut ps = ParameterState::new();
+        let param = ConstantParameter::new("my-parameter", PI);
+        ps.push_value(param.compute_value(ts, &si, &ns, &ps).unwrap());
+        ps.push_value(ParameterValue::Index(2));
+        ps.push_value(ParameterValue::Multi(vec![1.0, 2.0]));
+
+        assert_eq!(ps.len(), 3);
+        assert_almost_eq!(ps[0], PI);
+        assert_almost_eq!(ps.get_value(ParameterIndex(1)).unwrap(), 2.0);
+        assert!(ps[2].is_nan());
+        assert_eq!(ps.get_index(ParameterIndex(1)), Ok(2));
+        assert_eq!(ps.get_multi_value(ParameterIndex(2)).unwrap(), &[1.0, 2.0]);
+        assert_eq!(
+            ps.get_index(ParameterIndex(0)),
+            Err(PywrError::ParameterValueTypeMismatch(ParameterIndex(0), "index"))
+        );
+        assert_eq!(
+            ps.get_multi_value(ParameterIndex(3)),
+            Err(PywrError::ParameterIndexNotFound(ParameterIndex(3)))
+        );
+    }
+
     #[test]
     /// Test `Array2Parameter` returns the correct value.
     fn test_array2_parameter() {
@@ -356,6 +811,7 @@ mod tests {
         let si = ScenarioIndex {
             index: 0,
             indices: vec![0],
+            labels: None,
         };
 
         for ts in timestepper.timesteps().iter() {
@@ -365,6 +821,125 @@ mod tests {
         }
     }
 
+    #[test]
+    /// Test `Array2Parameter` selects the column of its scenario group's member.
+    fn test_array2_parameter_scenario_group() {
+        let mut scenarios = ScenarioGroupCollection::new();
+        scenarios.add_group("demand", 2).unwrap();
+        scenarios.add_group("climate", 3).unwrap();
+
+        let data = Array::from_shape_fn((366, 3), |(row, col)| (row * 10 + col) as f64);
+        let mut param = Array2Parameter::new_with_scenario_group("my-array-parameter", data, "climate");
+        param.setup(&scenarios).unwrap();
+
+        let timestepper = test_timestepper();
+        let ns = NetworkState::new();
+        let ps = ParameterState::new();
+        for si in scenarios.scenario_indices() {
+            for ts in timestepper.timesteps().iter() {
+                let expected = (ts.index * 10 + si.indices[1]) as f64;
+                assert_almost_eq!(param.compute(ts, &si, &ns, &ps).unwrap(), expected);
+            }
+        }
+
+        let data = Array::zeros((366, 2));
+        let mut param = Array2Parameter::new_with_scenario_group("my-array-parameter", data, "climate");
+        assert_eq!(
+            param.setup(&scenarios),
+            Err(PywrError::ScenarioGroupSizeMismatch {
+                group: "climate".to_string(),
+                size: 3,
+                expected: 2
+            })
+        );
+    }
+
+    #[test]
+    /// Test `DailyProfileParameter` returns the value of the day of the year in each calendar.
+    fn test_daily_profile_parameter() {
+        let param = DailyProfileParameter::new("my-profile", (0..366).map(|d| d as f64).collect());
+        let si = ScenarioIndex {
+            index: 0,
+            indices: vec![0],
+            labels: None,
+        };
+        let ns = NetworkState::new();
+        let ps = ParameterState::new();
+
+        // The 1st of March of 2021 and the 30th of February of a 360 day calendar.
+        let timestepper = Timestepper::new("2021-01-01", "2021-12-31", "%Y-%m-%d", 1).unwrap();
+        let ts = &timestepper.timesteps()[59];
+        assert_almost_eq!(param.compute(ts, &si, &ns, &ps).unwrap(), 60.0);
+
+        let timestepper = Timestepper::new("2021-01-01", "2021-12-30", "%Y-%m-%d", 1).unwrap();
+        let ts = &timestepper.with_calendar(Calendar::Days360).unwrap().timesteps()[59];
+        assert_almost_eq!(param.compute(ts, &si, &ns, &ps).unwrap(), 59.0);
+
+        let param = DailyProfileParameter::new("my-profile", vec![0.0; 365]);
+        let ts = &Timestepper::new("2020-12-31", "2020-12-31", "%Y-%m-%d", 1)
+            .unwrap()
+            .timesteps()[0];
+        assert_eq!(
+            param.compute(ts, &si, &ns, &ps),
+            Err(PywrError::TimestepIndexOutOfRange)
+        );
+    }
+
+    #[test]
+    /// Test `HourlyProfileParameter` returns the value of the hour of each timestep.
+    fn test_hourly_profile_parameter() {
+        let mut values = [0.0; 24];
+        for (hour, value) in values.iter_mut().enumerate() {
+            *value = hour as f64 * 2.0;
+        }
+        let param = HourlyProfileParameter::new("my-profile", values);
+        let duration = TimestepDuration::Fixed(ChronoDuration::hours(1));
+        let timestepper = Timestepper::with_duration("2020-01-01", "2020-01-02", "%Y-%m-%d", duration).unwrap();
+        let si = ScenarioIndex {
+            index: 0,
+            indices: vec![0],
+            labels: None,
+        };
+
+        for ts in timestepper.timesteps().iter() {
+            let ns = NetworkState::new();
+            let ps = ParameterState::new();
+            assert_almost_eq!(param.compute(ts, &si, &ns, &ps).unwrap(), (ts.index % 24) as f64 * 2.0);
+        }
+    }
+
+    #[test]
+    /// Test `ForecastParameter` returns the forecasts for lookahead timesteps and observed values otherwise.
+    fn test_forecast_parameter() {
+        let observed = Array::range(0.0, 10.0, 1.0);
+        let forecasts = Array2::from_shape_fn((10, 3), |(issued, lead)| 100.0 * issued as f64 + lead as f64 + 1.0);
+        let param = ForecastParameter::new("my-forecast", observed, forecasts);
+        let timestepper = Timestepper::new("2020-01-01", "2020-01-10", "%Y-%m-%d", 1).unwrap();
+        let timesteps = timestepper.timesteps();
+        let si = ScenarioIndex {
+            index: 0,
+            indices: vec![0],
+            labels: None,
+        };
+        let ns = NetworkState::new();
+        let ps = ParameterState::new();
+
+        assert_almost_eq!(param.compute(&timesteps[4], &si, &ns, &ps).unwrap(), 4.0);
+        let lookahead = Timestep {
+            forecast_from: Some(2),
+            ..timesteps[4]
+        };
+        assert_almost_eq!(param.compute(&lookahead, &si, &ns, &ps).unwrap(), 202.0);
+        let lookahead = Timestep {
+            forecast_from: Some(0),
+            ..timesteps[4]
+        };
+        assert_eq!(
+            param.compute(&lookahead, &si, &ns, &ps),
+            Err(PywrError::TimestepIndexOutOfRange)
+        );
+    }
+
     #[test]
     #[should_panic] // TODO this is not great; but a problem with using ndarray slicing.
     /// Test `Array2Parameter` returns the correct value.
@@ -376,6 +951,7 @@ mod tests {
         let si = ScenarioIndex {
             index: 0,
             indices: vec![0],
+            labels: None,
         };
 
         for ts in timestepper.timesteps().iter() {
@@ -1,43 +1,153 @@
 use super::{NetworkState, ParameterMeta, PywrError, Timestep, _Parameter};
-use crate::scenario::ScenarioIndex;
+use crate::metric::Metric;
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::state::ParameterState;
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 
+/// A parameter whose value is computed by a Python object.
+///
+/// The object's `compute(timestep, scenario_index, scenario_indices, values)` method is called for
+/// every timestep and scenario, with the index of the scenario, the index of its member of each
+/// scenario group and the values of the parameter's metrics at the end of the previous timestep.
+/// Metrics of other parameters are of their values for the current timestep, and so those
+/// parameters must be added to the model first.
+///
+/// If the object defines a `setup(model_info, scenario_count, **kwargs)` method it is called
+/// before each run, with a dict of the parameter's name and the size of each scenario group, the
+/// number of scenarios run and the parameter's keyword arguments, e.g. to load data once or to
+/// allocate state for each scenario. If it defines an `after(timestep, scenario_index, values)`
+/// method it is called after each timestep is solved with the values of the metrics in each
+/// scenario.
+///
+/// Once it has been set up, each scenario is given its own shallow copy of the object, and only
+/// that copy's `compute` and `after` methods are called for the scenario. Attributes they assign
+/// are therefore held for each scenario, while the data loaded by `setup` is shared by the copies.
 pub struct PyParameter {
     meta: ParameterMeta,
     object: PyObject,
+    /// The copy of `object` for each scenario, made by `setup`.
+    instances: Vec<PyObject>,
+    metrics: Vec<Metric>,
+    kwargs: Option<Py<PyDict>>,
+    /// Whether the object has an `after` method, which is checked by `setup`.
+    has_after: bool,
 }
 
 impl PyParameter {
-    pub fn new(name: &str, obj: PyObject) -> Self {
+    pub fn new(name: &str, obj: PyObject, metrics: Vec<Metric>) -> Self {
         Self {
             meta: ParameterMeta::new(name),
             object: obj,
+            instances: Vec::new(),
+            metrics,
+            kwargs: None,
+            has_after: false,
         }
     }
+
+    /// Create a parameter whose object's `setup` method is called with `kwargs`.
+    pub fn new_with_kwargs(name: &str, obj: PyObject, metrics: Vec<Metric>, kwargs: Py<PyDict>) -> Self {
+        Self {
+            kwargs: Some(kwargs),
+            ..Self::new(name, obj, metrics)
+        }
+    }
+
+    fn metric_values(&self, state: &NetworkState, parameter_state: &ParameterState) -> Result<Vec<f64>, PywrError> {
+        self.metrics
+            .iter()
+            .map(|m| m.get_state_value(state, parameter_state))
+            .collect()
+    }
+
+    /// The copy of the object for a scenario, or the object itself before the parameter is set up.
+    fn instance(&self, scenario_index: &ScenarioIndex) -> &PyObject {
+        self.instances.get(scenario_index.index).unwrap_or(&self.object)
+    }
+
+    fn call_setup(&self, py: Python, scenarios: &ScenarioGroupCollection) -> Result<(), PywrError> {
+        let groups = PyDict::new(py);
+        for group in scenarios.schema() {
+            groups.set_item(group.name, group.size).map_err(python_error)?;
+        }
+        let model_info = PyDict::new(py);
+        model_info.set_item("name", &self.meta.name).map_err(python_error)?;
+        model_info.set_item("scenario_groups", groups).map_err(python_error)?;
+
+        let args = (model_info, scenarios.scenario_indices().len());
+        let kwargs = self.kwargs.as_ref().map(|k| k.as_ref(py));
+        self.object
+            .call_method(py, "setup", args, kwargs)
+            .map_err(python_error)?;
+        Ok(())
+    }
+}
+
+fn python_error(err: PyErr) -> PywrError {
+    PywrError::PythonError(err.to_string())
 }
 
 impl _Parameter for PyParameter {
     fn meta(&self) -> &ParameterMeta {
         &self.meta
     }
+    fn setup(&mut self, scenarios: &ScenarioGroupCollection) -> Result<(), PywrError> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let object = self.object.as_ref(py);
+        self.has_after = object.hasattr("after").map_err(python_error)?;
+        if object.hasattr("setup").map_err(python_error)? {
+            self.call_setup(py, scenarios)?;
+        }
+
+        let copy = py.import("copy").map_err(python_error)?;
+        self.instances = scenarios
+            .scenario_indices()
+            .iter()
+            .map(|_| copy.call1("copy", (object,)).map(PyObject::from))
+            .collect::<Result<_, _>>()
+            .map_err(python_error)?;
+        Ok(())
+    }
     fn compute(
         &self,
-        _timestep: &Timestep,
-        _scenario_index: &ScenarioIndex,
-        _state: &NetworkState,
-        _parameter_state: &[f64],
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        state: &NetworkState,
+        parameter_state: &ParameterState,
     ) -> Result<f64, PywrError> {
+        let values = self.metric_values(state, parameter_state)?;
+
         let gil = Python::acquire_gil();
         let py = gil.python();
 
-        let value: f64 = match self.object.call_method0(py, "compute") {
-            Ok(py_value) => match py_value.extract(py) {
-                Ok(v) => v,
-                Err(e) => return Err(PywrError::PythonError(e.to_string())),
-            },
-            Err(e) => return Err(PywrError::PythonError(e.to_string())),
-        };
+        let args = (*timestep, scenario_index.index, scenario_index.indices.clone(), values);
+        self.instance(scenario_index)
+            .call_method1(py, "compute", args)
+            .and_then(|v| v.extract(py))
+            .map_err(python_error)
+    }
+    fn after(
+        &mut self,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        state: &NetworkState,
+        parameter_state: &ParameterState,
+    ) -> Result<(), PywrError> {
+        if !self.has_after {
+            return Ok(());
+        }
+        let values = self.metric_values(state, parameter_state)?;
+
+        let gil = Python::acquire_gil();
+        let py = gil.python();
 
-        Ok(value)
+        let args = (*timestep, scenario_index.index, values);
+        self.instance(scenario_index)
+            .call_method1(py, "after", args)
+            .map_err(python_error)?;
+        Ok(())
     }
 }
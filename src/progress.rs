@@ -0,0 +1,118 @@
+use crate::timestep::Timestep;
+use std::time::Duration;
+
+/// How often a `ProgressReporter` is called during a run.
+#[derive(Clone, Copy, Debug)]
+pub enum ReportInterval {
+    /// Report after every `n` timesteps.
+    Timesteps(usize),
+    /// Report each time the run progresses by this percentage.
+    Percent(f64),
+}
+
+impl ReportInterval {
+    /// Returns true if progress should be reported after `completed` of `total` timesteps.
+    fn should_report(&self, completed: usize, total: usize) -> bool {
+        if completed >= total {
+            return true;
+        }
+        match self {
+            Self::Timesteps(n) => completed % (*n).max(1) == 0,
+            Self::Percent(pc) => {
+                let step = |c: usize| (100.0 * c as f64 / total as f64 / pc).floor();
+                step(completed) > step(completed - 1)
+            }
+        }
+    }
+}
+
+/// Progress of a model run.
+#[derive(Clone, Debug)]
+pub struct RunProgress {
+    /// The most recently completed timestep.
+    pub timestep: Timestep,
+    pub timesteps_completed: usize,
+    pub total_timesteps: usize,
+    pub elapsed: Duration,
+    /// Estimated time remaining, based on the average speed so far.
+    pub eta: Duration,
+    /// Number of timesteps (summed over all scenarios) completed per second.
+    pub speed: f64,
+}
+
+impl RunProgress {
+    /// Fraction of the run that has been completed (between 0 and 1).
+    pub fn fraction_complete(&self) -> f64 {
+        self.timesteps_completed as f64 / self.total_timesteps as f64
+    }
+}
+
+/// Receives progress updates during a model run.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&mut self, progress: &RunProgress);
+}
+
+impl<F> ProgressReporter for F
+where
+    F: FnMut(&RunProgress) + Send + Sync,
+{
+    fn report(&mut self, progress: &RunProgress) {
+        self(progress)
+    }
+}
+
+/// A `ProgressReporter` with the interval at which it is called.
+pub(crate) struct ProgressMonitor {
+    interval: ReportInterval,
+    reporter: Box<dyn ProgressReporter>,
+}
+
+impl ProgressMonitor {
+    pub(crate) fn new(interval: ReportInterval, reporter: Box<dyn ProgressReporter>) -> Self {
+        Self { interval, reporter }
+    }
+
+    /// Report progress if `completed` timesteps is on a reporting interval.
+    pub(crate) fn update(
+        &mut self,
+        timestep: &Timestep,
+        completed: usize,
+        total: usize,
+        num_scenarios: usize,
+        elapsed: Duration,
+    ) {
+        if !self.interval.should_report(completed, total) {
+            return;
+        }
+
+        let secs = elapsed.as_secs_f64();
+        let per_timestep = if completed > 0 { secs / completed as f64 } else { 0.0 };
+        let remaining = total.saturating_sub(completed);
+
+        let progress = RunProgress {
+            timestep: *timestep,
+            timesteps_completed: completed,
+            total_timesteps: total,
+            elapsed,
+            eta: Duration::from_secs_f64(per_timestep * remaining as f64),
+            speed: (completed * num_scenarios) as f64 / secs,
+        };
+        self.reporter.report(&progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_interval() {
+        let interval = ReportInterval::Timesteps(5);
+        let reported: Vec<usize> = (1..=12).filter(|c| interval.should_report(*c, 12)).collect();
+        assert_eq!(reported, vec![5, 10, 12]);
+
+        let interval = ReportInterval::Percent(25.0);
+        let reported: Vec<usize> = (1..=10).filter(|c| interval.should_report(*c, 10)).collect();
+        assert_eq!(reported, vec![3, 5, 8, 10]);
+    }
+}
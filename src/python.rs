@@ -1,21 +1,31 @@
+use crate::allocation::AllocationMode;
+use crate::dump::StateDump;
 use crate::metric::Metric;
-use crate::model::Model;
-use crate::node::{Constraint, ConstraintValue};
+use crate::model::{Model, ModelRun};
+use crate::node::{Constraint, ConstraintValue, Node, NodeIndex, NodeType};
 use crate::parameters::AggFunc;
-use crate::scenario::ScenarioGroupCollection;
-use crate::solvers::clp::ClpSolver;
-use crate::solvers::Solver;
-use crate::timestep::Timestepper;
+use crate::progress::{ProgressReporter, ReportInterval, RunProgress};
+use crate::relaxation::Relaxation;
+use crate::results::RunStatistics;
+use crate::solvers::py::PySolver;
+use crate::solvers::{solver_by_name, Solver, SolverSettings};
+use crate::state::{NetworkState, Precision};
+use crate::timestep::{Timestep, Timestepper};
+use crate::virtual_node::VirtualNodeType;
+use crate::PywrError;
 use crate::{parameters, recorders};
-use crate::{EdgeIndex, NodeIndex, PywrError};
+use chrono::{Datelike, Timelike};
 use ndarray::ArrayView1;
-use numpy::{PyArrayDyn, PyReadonlyArray1, PyReadonlyArrayDyn};
-use pyo3::create_exception;
-use pyo3::exceptions::{PyException, PyRuntimeError};
+use numpy::{PyArray2, PyArrayDyn, PyReadonlyArray1, PyReadonlyArray2, PyReadonlyArrayDyn};
 use pyo3::prelude::*;
+use pyo3::types::{PyDate, PyDateTime, PyDict};
+use pyo3::wrap_pyfunction;
 use pyo3::PyErr;
+use pyo3::PyIterProtocol;
+use std::collections::HashMap;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 
 /// Python API
 ///
@@ -29,13 +39,120 @@ enum PyConstraintValue<'a> {
     CatchAll(&'a PyAny), // This extraction never fails
 }
 
-create_exception!(pywr, ParameterNotFoundError, PyException);
+/// The Python exceptions raised for the errors of a model.
+///
+/// All are subclasses of `PywrError`, which is itself a `RuntimeError`.
+mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyRuntimeError;
+
+    create_exception!(pywr, PywrError, PyRuntimeError);
+    create_exception!(pywr, NodeNotFoundError, PywrError);
+    create_exception!(pywr, EdgeNotFoundError, PywrError);
+    create_exception!(pywr, ParameterNotFoundError, PywrError);
+    create_exception!(pywr, RecorderNotFoundError, PywrError);
+    create_exception!(pywr, NameAlreadyExistsError, PywrError);
+    create_exception!(pywr, InvalidModelError, PywrError);
+    create_exception!(pywr, ScenarioError, PywrError);
+    create_exception!(pywr, TimestepperError, PywrError);
+    create_exception!(pywr, SolverError, PywrError);
+    create_exception!(pywr, SolverInfeasibleError, SolverError);
+    create_exception!(pywr, FileFormatError, PywrError);
+    create_exception!(pywr, OutputError, PywrError);
+    create_exception!(pywr, PythonCallbackError, PywrError);
+}
+
+use exceptions::*;
 
 impl std::convert::From<PywrError> for PyErr {
+    /// Raise the exception for the kind of the underlying error, with a message that includes
+    /// the timestep and components it occurred in.
     fn from(err: PywrError) -> PyErr {
-        match err {
-            PywrError::ParameterNotFound(name) => ParameterNotFoundError::new_err(name),
-            _ => PyRuntimeError::new_err(err.to_string()),
+        let message = err.to_string();
+        match err.root() {
+            PywrError::NodeNotFound(_) | PywrError::NodeIndexNotFound(_) | PywrError::VirtualNodeIndexNotFound(_) => {
+                NodeNotFoundError::new_err(message)
+            }
+            PywrError::EdgeNotFound { .. } | PywrError::EdgeIndexNotFound(_) | PywrError::InvalidEdgeName(_) => {
+                EdgeNotFoundError::new_err(message)
+            }
+            PywrError::ParameterNotFound(_) | PywrError::ParameterIndexNotFound(_) => {
+                ParameterNotFoundError::new_err(message)
+            }
+            PywrError::RecorderNotFound(_) | PywrError::RecorderIndexNotFound(_) => {
+                RecorderNotFoundError::new_err(message)
+            }
+            PywrError::NodeNameAlreadyExists(_)
+            | PywrError::ParameterNameAlreadyExists(..)
+            | PywrError::RecorderNameAlreadyExists(..)
+            | PywrError::NodeConnectionAlreadyExists { .. }
+            | PywrError::ModelNameAlreadyExists(_)
+            | PywrError::ComponentTypeAlreadyRegistered(_) => NameAlreadyExistsError::new_err(message),
+            PywrError::InvalidNodeConnection
+            | PywrError::InvalidNodeConnectionFromOutput
+            | PywrError::InvalidNodeConnectionToInput
+            | PywrError::FlowConstraintsUndefined
+            | PywrError::StorageConstraintsUndefined
+            | PywrError::NoEdgesDefined
+            | PywrError::NoRoutesDefined
+            | PywrError::AtleastOneParameterRequired
+            | PywrError::UnrecognisedMetric(_)
+            | PywrError::MetricNotAvailableToParameters
+            | PywrError::InvalidConstraintValue(_)
+            | PywrError::InvalidConstraintType(_)
+            | PywrError::InvalidAggregationFunction(_)
+            | PywrError::InvalidRelaxation(_)
+            | PywrError::InvalidAllocationMode(_)
+            | PywrError::InvalidPrecision(_)
+            | PywrError::InvalidFlowFactors(_)
+            | PywrError::UnboundedMutualExclusivity(_)
+            | PywrError::UnboundedPriorityDemand(_) => InvalidModelError::new_err(message),
+            PywrError::ScenarioStateNotFound
+            | PywrError::ScenarioGroupNotFound(_)
+            | PywrError::EmptyScenarioGroup(_)
+            | PywrError::InvalidScenarioSlice { .. }
+            | PywrError::InvalidScenarioCombination(_)
+            | PywrError::ScenarioGroupSizeMismatch { .. }
+            | PywrError::ScenarioIndexNotFound
+            | PywrError::InvalidScenarioWeights(_)
+            | PywrError::ZeroScenarioWeight
+            | PywrError::InvalidScenarioVariation { .. }
+            | PywrError::NoScenarios
+            | PywrError::IncompatibleScenarioSampling => ScenarioError::new_err(message),
+            PywrError::ParseError(_)
+            | PywrError::TimestepIndexOutOfRange
+            | PywrError::InvalidTimestepDuration(_)
+            | PywrError::InvalidDate { .. }
+            | PywrError::EndDateBeforeStartDate { .. } => TimestepperError::new_err(message),
+            PywrError::Infeasible { .. } => SolverInfeasibleError::new_err(message),
+            PywrError::SolveFailed
+            | PywrError::UnrecognisedSolver { .. }
+            | PywrError::SolverNotAvailable { .. }
+            | PywrError::MissingSolverFeatures(_)
+            | PywrError::SimplexError(_)
+            | PywrError::PdlpError(_) => SolverError::new_err(message),
+            #[cfg(feature = "clp")]
+            PywrError::ClpError(_) => SolverError::new_err(message),
+            #[cfg(feature = "cplex")]
+            PywrError::CplexError(_) => SolverError::new_err(message),
+            #[cfg(feature = "cbc")]
+            PywrError::CbcError(_) => SolverError::new_err(message),
+            #[cfg(feature = "glpk")]
+            PywrError::GlpkError(_) => SolverError::new_err(message),
+            #[cfg(feature = "gurobi")]
+            PywrError::GurobiError(_) => SolverError::new_err(message),
+            PywrError::InvalidSchema(_)
+            | PywrError::InvalidV1Model(_)
+            | PywrError::InvalidCheckpoint { .. }
+            | PywrError::CheckpointMismatch(_)
+            | PywrError::InvalidInitialState(_)
+            | PywrError::InvalidStateDump(_)
+            | PywrError::UnrecognisedProblemFormat(_) => FileFormatError::new_err(message),
+            PywrError::HDF5Error(_) | PywrError::CSVError(_) | PywrError::StreamError(_) | PywrError::IOError(_) => {
+                OutputError::new_err(message)
+            }
+            PywrError::PythonError(_) => PythonCallbackError::new_err(message),
+            _ => exceptions::PywrError::new_err(message),
         }
     }
 }
@@ -43,6 +160,8 @@ impl std::convert::From<PywrError> for PyErr {
 #[pyclass]
 struct PyModel {
     model: Model,
+    /// The timesteps of the most recent run, to index the values of its recorders.
+    timesteps: Vec<Timestep>,
 }
 
 impl PyModel {
@@ -62,58 +181,310 @@ impl PyModel {
             }
         }
     }
+
+    fn get_nodes_by_name(&self, node_names: &[String]) -> Result<Vec<Node>, PywrError> {
+        node_names.iter().map(|n| self.model.get_node_by_name(n)).collect()
+    }
+}
+
+/// Reports the progress of a run to a Python callable.
+struct PyProgressReporter {
+    callback: PyObject,
+}
+
+impl ProgressReporter for PyProgressReporter {
+    fn report(&mut self, progress: &RunProgress) {
+        // The GIL is released during a run, so it is acquired for each report.
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let date = progress.timestep.date;
+        let result = PyDate::new(py, date.year(), date.month() as u8, date.day() as u8).and_then(|date| {
+            self.callback
+                .call1(py, (progress.fraction_complete(), date, progress.speed))
+        });
+        // A failing progress bar should not stop the run, so the error is only printed.
+        if let Err(err) = result {
+            err.print(py);
+        }
+    }
+}
+
+/// Create the solver named by a string, or a solver that calls a Python object.
+fn solver_from_py(solver: &PyAny) -> PyResult<Box<dyn Solver>> {
+    match solver.extract::<&str>() {
+        Ok(name) => Ok(solver_by_name(name)?),
+        Err(_) => Ok(Box::new(PySolver::new(solver.into()))),
+    }
+}
+
+/// The statistics of a run as a dict.
+fn run_statistics_dict(py: Python, statistics: &RunStatistics) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("timesteps", statistics.timesteps)?;
+    dict.set_item("scenarios", statistics.scenarios)?;
+    dict.set_item("wall_time", statistics.wall_time.as_secs_f64())?;
+    dict.set_item("speed", statistics.speed())?;
+    dict.set_item("cancelled", statistics.cancelled)?;
+    Ok(dict.to_object(py))
 }
 
 #[pymethods]
 impl PyModel {
     #[new]
     fn new() -> Self {
-        Self { model: Model::new() }
+        Self {
+            model: Model::new(),
+            timesteps: Vec::new(),
+        }
     }
 
-    fn add_input_node(&mut self, name: &str) -> PyResult<NodeIndex> {
-        let idx = self.model.add_input_node(name)?.index();
+    fn add_input_node(&mut self, name: &str) -> PyResult<usize> {
+        let idx = *self.model.add_input_node(name)?.index();
         Ok(idx)
     }
 
-    fn add_link_node(&mut self, name: &str) -> PyResult<NodeIndex> {
-        let idx = self.model.add_link_node(name)?.index();
+    fn add_link_node(&mut self, name: &str) -> PyResult<usize> {
+        let idx = *self.model.add_link_node(name)?.index();
         Ok(idx)
     }
 
-    fn add_output_node(&mut self, name: &str) -> PyResult<NodeIndex> {
-        let idx = self.model.add_output_node(name)?.index();
+    fn add_output_node(&mut self, name: &str) -> PyResult<usize> {
+        let idx = *self.model.add_output_node(name)?.index();
         Ok(idx)
     }
 
-    fn add_storage_node(&mut self, name: &str, initial_volume: f64) -> PyResult<NodeIndex> {
-        let idx = self.model.add_storage_node(name, initial_volume)?.index();
+    fn add_storage_node(&mut self, name: &str, initial_volume: f64) -> PyResult<usize> {
+        let idx = *self.model.add_storage_node(name, initial_volume)?.index();
         Ok(idx)
     }
 
-    fn connect_nodes(&mut self, from_node_name: &str, to_node_name: &str) -> PyResult<EdgeIndex> {
+    fn connect_nodes(&mut self, from_node_name: &str, to_node_name: &str) -> PyResult<usize> {
         let from_node = self.model.get_node_by_name(from_node_name)?;
         let to_node = self.model.get_node_by_name(to_node_name)?;
 
         let edge = self.model.connect_nodes(&from_node, &to_node)?;
-        Ok(edge.index())
+        Ok(*edge.index())
+    }
+
+    /// Add a virtual node of the type `"sum"`, of the flows of `node_names` scaled by `factors`
+    /// (which are all one if not given), or `"balance"`, of their net inflow.
+    fn add_virtual_node(
+        &mut self,
+        name: &str,
+        virtual_node_type: &str,
+        node_names: Vec<String>,
+        factors: Option<Vec<f64>>,
+    ) -> PyResult<usize> {
+        let nodes = node_names
+            .iter()
+            .map(|n| self.model.get_node_by_name(n).map(|node| node.index()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let virtual_node_type = match (virtual_node_type, factors) {
+            ("sum", Some(factors)) if factors.len() == nodes.len() => {
+                VirtualNodeType::Sum(nodes.into_iter().zip(factors).collect())
+            }
+            ("sum", None) => VirtualNodeType::Sum(nodes.into_iter().map(|n| (n, 1.0)).collect()),
+            ("balance", None) => VirtualNodeType::Balance(nodes),
+            (t, _) => return Err(PyErr::from(PywrError::InvalidConstraintType(t.to_string()))),
+        };
+        let idx = *self.model.add_virtual_node(name, virtual_node_type)?;
+        Ok(idx)
     }
 
-    fn run(&mut self, solver_name: &str, start: &str, end: &str, timestep: i64) -> PyResult<()> {
+    fn get_edge_index(&self, from_node_name: &str, to_node_name: &str) -> PyResult<usize> {
+        let idx = *self.model.get_edge_index(from_node_name, to_node_name)?;
+        Ok(idx)
+    }
+
+    /// Add a scenario group of `size` members.
+    fn add_scenario(&mut self, name: &str, size: usize) -> PyResult<()> {
+        self.model.add_scenario(name, size)?;
+        Ok(())
+    }
+
+    /// Require that at most one of the nodes has a non-zero flow in each timestep.
+    fn add_mutual_exclusivity(&mut self, node_names: Vec<String>) -> PyResult<()> {
+        let nodes = self.get_nodes_by_name(&node_names)?;
+        self.model.add_mutual_exclusivity(&nodes)?;
+        Ok(())
+    }
+
+    /// Require the flows through the nodes to be in proportion to `factors`, each of which is a
+    /// number or the name of a parameter.
+    fn add_flow_factors(&mut self, node_names: Vec<String>, factors: Vec<PyConstraintValue>) -> PyResult<()> {
+        let nodes = self.get_nodes_by_name(&node_names)?;
+        let factors = factors
+            .into_iter()
+            .map(|f| self.to_constraint_value(f))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.model.add_flow_factors(&nodes, factors)?;
+        Ok(())
+    }
+
+    fn set_warm_up(&mut self, timesteps: usize) {
+        self.model.set_warm_up(timesteps);
+    }
+
+    fn set_rolling_horizon(&mut self, timesteps: usize) {
+        self.model.set_rolling_horizon(timesteps);
+    }
+
+    /// Write a checkpoint of each run to `filename` after every `interval` timesteps.
+    fn set_checkpointing(&mut self, filename: &str, interval: usize) {
+        self.model.set_checkpointing(Path::new(filename), interval);
+    }
+
+    /// Run the model from `start` to `end` (as `YYYY-MM-DD`) with timesteps of `timestep` days,
+    /// returning the statistics of the run as a dict.
+    ///
+    /// The solver is either the name of a solver (e.g. `"clp"`) or a Python object implementing
+    /// `setup(model_info)` and `solve(bounds, costs)` (see `PySolver`).
+    ///
+    /// If `progress` is given it is called as `progress(fraction, date, speed)` with the fraction of
+    /// the run completed, the date of the latest timestep and the timesteps solved per second, once
+    /// for every percent of the run, e.g. to update a `tqdm` progress bar.
+    ///
+    /// The GIL is released for the run and only acquired to call Python parameters, recorders and
+    /// the progress callback, so other Python threads continue to run alongside it.
+    fn run(
+        &mut self,
+        py: Python,
+        solver: &PyAny,
+        start: &str,
+        end: &str,
+        timestep: i64,
+        progress: Option<PyObject>,
+    ) -> PyResult<PyObject> {
         let timestepper = Timestepper::new(start, end, "%Y-%m-%d", timestep)?;
-        let mut scenarios = ScenarioGroupCollection::new();
-        scenarios.add_group("test-scenario", 1);
+        let solver = solver_from_py(solver)?;
+        self.timesteps = timestepper.timesteps();
+        let has_progress = progress.is_some();
+        if let Some(callback) = progress {
+            self.model
+                .set_progress_reporter(ReportInterval::Percent(1.0), Box::new(PyProgressReporter { callback }));
+        }
 
-        let mut solver: Box<dyn Solver> = match solver_name {
-            //"glpk" => Box::new(GlpkSolver::new().unwrap()),
-            "clp" => Box::new(ClpSolver::new()),
-            _ => return Err(PyErr::from(PywrError::UnrecognisedSolver)),
-        };
+        // Release the GIL so that Python parameters can acquire it from the threads solving each scenario.
+        let model = &mut self.model;
+        let results = py.allow_threads(move || model.run(timestepper, solver.as_ref(), &SolverSettings::default()));
+        if has_progress {
+            self.model.clear_progress_reporter();
+        }
+        run_statistics_dict(py, &results?.statistics)
+    }
 
-        self.model.run(timestepper, scenarios, &mut solver)?;
+    /// Start a run from `start` to `end` (as `YYYY-MM-DD`) with timesteps of `timestep` days, which
+    /// is solved one timestep at a time by iterating over the returned object.
+    ///
+    /// Each iteration yields the state of each scenario after the timestep. The recorders are
+    /// finalised when the iteration completes, or earlier by calling `finish`.
+    fn steps(mut slf: PyRefMut<Self>, solver: &PyAny, start: &str, end: &str, timestep: i64) -> PyResult<PyModelSteps> {
+        let timestepper = Timestepper::new(start, end, "%Y-%m-%d", timestep)?;
+        let solver = solver_from_py(solver)?;
+        slf.timesteps = timestepper.timesteps();
+
+        let run = slf
+            .model
+            .start_run(&timestepper, solver.as_ref(), &SolverSettings::default())?;
+        let nodes = slf.model.nodes.iter().map(|n| (n.name(), n.index())).collect();
+        Ok(PyModelSteps {
+            model: slf.into(),
+            run: Some(run),
+            statistics: None,
+            nodes: Arc::new(nodes),
+        })
+    }
+
+    /// Resume the run saved in the checkpoint `filename`, which must have been written by a run
+    /// with the same timesteps. Like `run`, the GIL is released for the run.
+    fn run_from_checkpoint(
+        &mut self,
+        py: Python,
+        solver: &PyAny,
+        start: &str,
+        end: &str,
+        timestep: i64,
+        filename: &str,
+    ) -> PyResult<PyObject> {
+        let timestepper = Timestepper::new(start, end, "%Y-%m-%d", timestep)?;
+        let solver = solver_from_py(solver)?;
+        self.timesteps = timestepper.timesteps();
+
+        // Release the GIL so that Python parameters can acquire it from the threads solving each scenario.
+        let model = &mut self.model;
+        let path = Path::new(filename);
+        let results = py.allow_threads(move || {
+            model.run_from_checkpoint(timestepper, solver.as_ref(), &SolverSettings::default(), path)
+        })?;
+        run_statistics_dict(py, &results.statistics)
+    }
+
+    /// Write a JSON dump of the state of `scenario` after `timestep` of each run to `filename`.
+    fn set_state_dump(&mut self, timestep: usize, scenario: usize, filename: &str) {
+        self.model.set_state_dump(timestep, scenario, Some(Path::new(filename)));
+    }
+
+    /// Set the relaxations (e.g. `["min_flows", "max_volumes"]`) to apply, in priority order,
+    /// when a timestep fails to solve.
+    fn set_infeasibility_relaxations(&mut self, relaxations: Vec<String>) -> PyResult<()> {
+        let relaxations = relaxations
+            .iter()
+            .map(|name| Relaxation::from_str(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.model.set_infeasibility_relaxations(relaxations);
+        Ok(())
+    }
+
+    /// Set how the available water is allocated between demands, either `"minimum_cost"` (the
+    /// default) or `"priority_proportional"`.
+    fn set_allocation_mode(&mut self, mode: &str) -> PyResult<()> {
+        self.model.set_allocation_mode(AllocationMode::from_str(mode)?);
+        Ok(())
+    }
+
+    /// Set the precision the recorders and flow histories store values in, either `"f64"` (the
+    /// default) or `"f32"` to halve their memory.
+    fn set_storage_precision(&mut self, precision: &str) -> PyResult<()> {
+        self.model.set_storage_precision(Precision::from_str(precision)?);
+        Ok(())
+    }
+
+    /// Set the precision a recorder stores its values in, either `"f64"` or `"f32"`.
+    fn set_recorder_precision(&mut self, name: &str, precision: &str) -> PyResult<()> {
+        self.model
+            .set_recorder_precision(name, Precision::from_str(precision)?)?;
         Ok(())
     }
 
+    /// The (timestep index, scenario index, relaxations) of each solve in the most recent run
+    /// that needed relaxations.
+    fn relaxed_solves(&self) -> Vec<(usize, usize, Vec<&'static str>)> {
+        self.model
+            .relaxed_solves()
+            .iter()
+            .map(|r| {
+                let names = r.relaxations.iter().map(|relaxation| relaxation.name()).collect();
+                (r.timestep_index, r.scenario_index, names)
+            })
+            .collect()
+    }
+
+    /// The statistics of the solver from the most recent run as a dict, or `None` if the model
+    /// has not been run.
+    fn solver_stats(&self, py: Python) -> PyResult<Option<PyObject>> {
+        let stats = match self.model.solver_stats() {
+            Some(stats) => stats,
+            None => return Ok(None),
+        };
+
+        let dict = PyDict::new(py);
+        dict.set_item("solves", stats.solves)?;
+        dict.set_item("solve_time", stats.solve_time.as_secs_f64())?;
+        dict.set_item("iterations", stats.iterations)?;
+        dict.set_item("factorisations", stats.factorisations)?;
+        Ok(Some(dict.to_object(py)))
+    }
+
     fn set_node_constraint(
         &mut self,
         node_name: &str,
@@ -145,22 +516,126 @@ impl PyModel {
         Ok(())
     }
 
+    /// Set the minimum or maximum flow along the edge between two nodes.
+    fn set_edge_constraint(
+        &mut self,
+        from_node_name: &str,
+        to_node_name: &str,
+        constraint_type: &str,
+        value: PyConstraintValue,
+    ) -> PyResult<()> {
+        let edge = self.model.get_edge_by_node_names(from_node_name, to_node_name)?;
+        let value = self.to_constraint_value(value)?;
+
+        let constraint = match constraint_type {
+            "max_flow" => Constraint::MaxFlow,
+            "min_flow" => Constraint::MinFlow,
+            _ => {
+                return Err(PyErr::from(PywrError::InvalidConstraintType(
+                    constraint_type.to_string(),
+                )))
+            }
+        };
+        edge.set_constraint(value, constraint)?;
+        Ok(())
+    }
+
+    fn set_edge_cost(&mut self, from_node_name: &str, to_node_name: &str, value: PyConstraintValue) -> PyResult<()> {
+        let edge = self.model.get_edge_by_node_names(from_node_name, to_node_name)?;
+        let value = self.to_constraint_value(value)?;
+        edge.set_cost(value);
+        Ok(())
+    }
+
     /// Add a Python object as a parameter.
-    fn add_python_parameter(&mut self, name: &str, object: PyObject) -> PyResult<parameters::ParameterIndex> {
-        let parameter = parameters::py::PyParameter::new(name, object);
-        let idx = self.model.add_parameter(Box::new(parameter))?.index();
+    ///
+    /// Its `compute(timestep, scenario_index, scenario_indices, values)` method is given the values
+    /// of `metrics`, each a pair of the name of a metric (as for `add_python_recorder`) and of its
+    /// node or parameter. If it has a `setup(model_info, scenario_count, **kwargs)` method it is
+    /// called before each run with `kwargs`.
+    fn add_python_parameter(
+        &mut self,
+        name: &str,
+        object: PyObject,
+        metrics: Option<Vec<(String, String)>>,
+        kwargs: Option<Py<PyDict>>,
+    ) -> PyResult<usize> {
+        let metrics = metrics
+            .unwrap_or_default()
+            .iter()
+            .map(|(metric, component)| self.model.metric_by_name(metric, component))
+            .collect::<Result<Vec<_>, _>>()?;
+        if metrics.iter().any(|m| matches!(m, Metric::VirtualNode(_))) {
+            return Err(PyErr::from(PywrError::MetricNotAvailableToParameters));
+        }
+        let parameter = match kwargs {
+            Some(kwargs) => parameters::py::PyParameter::new_with_kwargs(name, object, metrics, kwargs),
+            None => parameters::py::PyParameter::new(name, object, metrics),
+        };
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
         Ok(idx)
     }
 
-    fn add_constant(&mut self, name: &str, value: f64) -> PyResult<parameters::ParameterIndex> {
+    fn add_constant(&mut self, name: &str, value: f64) -> PyResult<usize> {
         let parameter = parameters::ConstantParameter::new(name, value);
-        let idx = self.model.add_parameter(Box::new(parameter))?.index();
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
         Ok(idx)
     }
 
-    fn add_array(&mut self, name: &str, values: PyReadonlyArray1<f64>) -> PyResult<parameters::ParameterIndex> {
+    fn add_array(&mut self, name: &str, values: PyReadonlyArray1<f64>) -> PyResult<usize> {
         let parameter = parameters::Array1Parameter::new(name, values.to_owned_array());
-        let idx = self.model.add_parameter(Box::new(parameter))?.index();
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
+        Ok(idx)
+    }
+
+    /// Add a parameter with a row for each timestep, and a column for each member of
+    /// `scenario_group` if given.
+    fn add_array2(
+        &mut self,
+        name: &str,
+        values: PyReadonlyArray2<f64>,
+        scenario_group: Option<&str>,
+    ) -> PyResult<usize> {
+        let parameter = match scenario_group {
+            Some(group) => parameters::Array2Parameter::new_with_scenario_group(name, values.to_owned_array(), group),
+            None => parameters::Array2Parameter::new(name, values.to_owned_array()),
+        };
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
+        Ok(idx)
+    }
+
+    /// Add a parameter with a value for each day of the year.
+    fn add_daily_profile(&mut self, name: &str, values: Vec<f64>) -> PyResult<usize> {
+        let parameter = parameters::DailyProfileParameter::new(name, values);
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
+        Ok(idx)
+    }
+
+    /// Add a parameter of the flow through a node `delay` timesteps ago.
+    fn add_delay_parameter(
+        &mut self,
+        name: &str,
+        node_name: &str,
+        delay: usize,
+        initial_value: f64,
+    ) -> PyResult<usize> {
+        let node = self.model.get_node_by_name(node_name)?;
+        let parameter = parameters::DelayParameter::new(name, node.index(), delay, initial_value);
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
+        Ok(idx)
+    }
+
+    /// Add a parameter of the mean flow through a node over the previous `timesteps` timesteps.
+    fn add_rolling_mean_parameter(
+        &mut self,
+        name: &str,
+        node_name: &str,
+        timesteps: usize,
+        initial_value: f64,
+    ) -> PyResult<usize> {
+        let node = self.model.get_node_by_name(node_name)?;
+        let parameter = parameters::RollingMeanParameter::new(name, node.index(), timesteps, initial_value);
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
         Ok(idx)
     }
 
@@ -169,7 +644,7 @@ impl PyModel {
         name: &str,
         parameter_names: Vec<String>,
         agg_func: &str,
-    ) -> PyResult<parameters::ParameterIndex> {
+    ) -> PyResult<usize> {
         // Find all the parameters by name
         let mut parameters = Vec::with_capacity(parameter_names.len());
         for name in parameter_names {
@@ -179,29 +654,90 @@ impl PyModel {
         let agg_func = AggFunc::from_str(agg_func)?;
         let parameter = parameters::AggregatedParameter::new(name, parameters, agg_func);
 
-        let idx = self.model.add_parameter(Box::new(parameter))?.index();
+        let idx = *self.model.add_parameter(Box::new(parameter))?.index();
+
+        Ok(idx)
+    }
+
+    fn add_python_recorder(&mut self, name: &str, component: &str, metric: &str, object: PyObject) -> PyResult<usize> {
+        let metric = self.model.metric_by_name(metric, component)?;
+
+        let recorder = recorders::py::PyRecorder::new(name, object, vec![metric]);
+        let idx = *self.model.add_recorder(Box::new(recorder))?.index();
+        Ok(idx)
+    }
+
+    /// Add a recorder which keeps the values of a metric in memory, to be read after the run with
+    /// `recorder_array`.
+    fn add_array_recorder(&mut self, name: &str, component: &str, metric: &str) -> PyResult<usize> {
+        let metric = self.model.metric_by_name(metric, component)?;
 
+        let recorder = recorders::Array2Recorder::new(name, metric);
+        let idx = *self.model.add_recorder(Box::new(recorder))?.index();
         Ok(idx)
     }
 
-    fn add_python_recorder(
+    /// The values saved by the recorder `name` in the most recent run as a NumPy array with a row
+    /// for each saved timestep and a column for each scenario.
+    ///
+    /// The values are copied from the recorder once, and the copy is then owned by the array.
+    fn recorder_array<'py>(&self, py: Python<'py>, name: &str) -> PyResult<&'py PyArray2<f64>> {
+        let array = self.model.get_recorder_by_name(name)?.data_view2()?;
+        Ok(PyArray2::from_owned_array(py, array))
+    }
+
+    /// The start of each timestep saved by the recorder `name` in the most recent run, which index
+    /// the rows of `recorder_array`.
+    fn recorder_datetimes<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Vec<&'py PyDateTime>> {
+        let recorder = self.model.get_recorder_by_name(name)?;
+        self.timesteps
+            .iter()
+            .filter(|ts| recorder.row_index(ts).is_some())
+            .map(|ts| {
+                let dt = ts.datetime();
+                PyDateTime::new(
+                    py,
+                    dt.year(),
+                    dt.month() as u8,
+                    dt.day() as u8,
+                    dt.hour() as u8,
+                    dt.minute() as u8,
+                    dt.second() as u8,
+                    0,
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    /// The names of the scenario groups, and the member of each group of each scenario, which
+    /// label the columns of `recorder_array`. The members are given by their labels if any of the
+    /// groups are labelled, sliced or not fully combined, and otherwise by their indices.
+    fn scenario_columns(&self, py: Python) -> (Vec<String>, Vec<Vec<PyObject>>) {
+        let scenarios = self.model.scenarios();
+        let names = scenarios.schema().into_iter().map(|g| g.name).collect();
+        let members = scenarios
+            .scenario_indices()
+            .into_iter()
+            .map(|scenario_index| match scenario_index.labels {
+                Some(labels) => labels.iter().map(|l| l.to_object(py)).collect(),
+                None => scenario_index.indices.iter().map(|i| i.to_object(py)).collect(),
+            })
+            .collect();
+        (names, members)
+    }
+
+    /// Add a Python object as a recorder of the flow along the edge between two nodes.
+    fn add_python_edge_recorder(
         &mut self,
         name: &str,
-        component: &str,
-        metric: &str,
+        from_node_name: &str,
+        to_node_name: &str,
         object: PyObject,
-    ) -> PyResult<recorders::RecorderIndex> {
-        let metric = match metric {
-            "node_inflow" => Metric::NodeInFlow(self.model.get_node_by_name(component)?.index()),
-            "node_outflow" => Metric::NodeOutFlow(self.model.get_node_by_name(component)?.index()),
-            "node_volume" => Metric::NodeVolume(self.model.get_node_by_name(component)?.index()),
-            // TODO implement edge_flow
-            "parameter" => Metric::ParameterValue(self.model.get_parameter_by_name(component)?.index()),
-            _ => return Err(PyErr::from(PywrError::UnrecognisedMetric)),
-        };
-
-        let recorder = recorders::py::PyRecorder::new(name, object, metric);
-        let idx = self.model.add_recorder(Box::new(recorder))?.index();
+    ) -> PyResult<usize> {
+        let edge = self.model.get_edge_by_node_names(from_node_name, to_node_name)?;
+        let recorder = recorders::py::PyRecorder::new(name, object, vec![edge.default_metric()]);
+        let idx = *self.model.add_recorder(Box::new(recorder))?.index();
         Ok(idx)
     }
 
@@ -212,15 +748,250 @@ impl PyModel {
         let rec = self.model.add_recorder(Box::new(rec))?;
         Ok(())
     }
+
+    fn add_csv_output(&mut self, name: &str, filename: &str) -> PyResult<()> {
+        let path = Path::new(filename);
+        let rec = recorders::csv::CSVRecorder::new(name, path.to_path_buf());
+
+        self.model.add_recorder(Box::new(rec))?;
+        Ok(())
+    }
+
+    fn add_stream_output(&mut self, name: &str, address: &str) -> PyResult<()> {
+        let rec = recorders::stream::StreamRecorder::new(name, address);
+
+        self.model.add_recorder(Box::new(rec))?;
+        Ok(())
+    }
+
+    /// Add a recorder which checks the mass-balance of the model's nodes.
+    fn add_mass_balance_audit(&mut self, name: &str, tolerance: f64, strict: bool) -> PyResult<()> {
+        let rec = recorders::audit::MassBalanceRecorder::new(name, tolerance, strict);
+
+        self.model.add_recorder(Box::new(rec))?;
+        Ok(())
+    }
+
+    /// Add a recorder which accumulates the operating cost of the model's nodes.
+    fn add_operating_cost_recorder(&mut self, name: &str) -> PyResult<()> {
+        let rec = recorders::cost::OperatingCostRecorder::new(name);
+
+        self.model.add_recorder(Box::new(rec))?;
+        Ok(())
+    }
+
+    /// Add a recorder of pumping energy and carbon emissions.
+    ///
+    /// Each component is a node name and the name of the parameter giving the energy used per
+    /// unit of flow through that node.
+    fn add_energy_recorder(
+        &mut self,
+        name: &str,
+        components: Vec<(String, String)>,
+        emission_factor: &str,
+    ) -> PyResult<()> {
+        let mut metrics = Vec::with_capacity(components.len());
+        for (node_name, parameter_name) in components {
+            let node = self.model.get_node_by_name(&node_name)?;
+            let metric = match node.node_type() {
+                NodeType::Output => Metric::NodeInFlow(node.index()),
+                _ => Metric::NodeOutFlow(node.index()),
+            };
+            metrics.push((metric, self.model.get_parameter_by_name(&parameter_name)?.index()));
+        }
+        let emission_factor = self.model.get_parameter_by_name(emission_factor)?.index();
+
+        let rec = recorders::energy::EnergyRecorder::new(name, metrics, emission_factor);
+
+        self.model.add_recorder(Box::new(rec))?;
+        Ok(())
+    }
+
+    fn set_recorder_units(&mut self, name: &str, units: &str) -> PyResult<()> {
+        self.model.get_recorder_by_name(name)?.set_units(units);
+        Ok(())
+    }
+
+    fn set_recorder_long_name(&mut self, name: &str, long_name: &str) -> PyResult<()> {
+        self.model.get_recorder_by_name(name)?.set_long_name(long_name);
+        Ok(())
+    }
+
+    fn set_recorder_attribute(&mut self, name: &str, key: &str, value: &str) -> PyResult<()> {
+        self.model.get_recorder_by_name(name)?.set_attribute(key, value);
+        Ok(())
+    }
+}
+
+/// A run of a `PyModel` that solves a timestep each time it is advanced.
+#[pyclass]
+struct PyModelSteps {
+    model: Py<PyModel>,
+    /// The run, until it is finished.
+    run: Option<ModelRun>,
+    /// The statistics of the run, once it is finished.
+    statistics: Option<RunStatistics>,
+    nodes: Arc<HashMap<String, NodeIndex>>,
+}
+
+impl PyModelSteps {
+    /// Finish the run, if it is not already, finalising the recorders.
+    fn finish_run(&mut self, py: Python) -> Result<(), PywrError> {
+        if let Some(run) = self.run.take() {
+            let mut model = self.model.as_ref(py).borrow_mut();
+            self.statistics = Some(model.model.finish_run(run)?.statistics);
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl PyModelSteps {
+    /// Finish the run before its last timestep, finalising the recorders, and return the
+    /// statistics of the run as a dict.
+    fn finish(&mut self, py: Python) -> PyResult<PyObject> {
+        self.finish_run(py)?;
+        match &self.statistics {
+            Some(statistics) => run_statistics_dict(py, statistics),
+            None => Ok(py.None()),
+        }
+    }
+
+    /// The statistics of the run as a dict once it is finished, or otherwise `None`.
+    #[getter]
+    fn statistics(&self, py: Python) -> PyResult<PyObject> {
+        match &self.statistics {
+            Some(statistics) => run_statistics_dict(py, statistics),
+            None => Ok(py.None()),
+        }
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for PyModelSteps {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyStepState>> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let steps = &mut *slf;
+        let run = match &mut steps.run {
+            Some(run) if !run.is_complete() => run,
+            _ => {
+                steps.finish_run(py)?;
+                return Ok(None);
+            }
+        };
+
+        let timestep = *run.next_timestep().unwrap();
+        let mut py_model = steps.model.as_ref(py).borrow_mut();
+        let model = &mut py_model.model;
+        // Release the GIL so that Python parameters can acquire it from the threads solving each scenario.
+        py.allow_threads(|| model.step_run(run))?;
+
+        Ok(Some(PyStepState {
+            timestep,
+            states: run.states().to_vec(),
+            nodes: steps.nodes.clone(),
+        }))
+    }
+}
+
+/// The state of each scenario after a timestep of `PyModel.steps`.
+#[pyclass]
+struct PyStepState {
+    timestep: Timestep,
+    states: Vec<NetworkState>,
+    nodes: Arc<HashMap<String, NodeIndex>>,
+}
+
+impl PyStepState {
+    /// The value of `f` for the node `name` in each scenario.
+    fn node_values<F>(&self, name: &str, f: F) -> PyResult<Vec<f64>>
+    where
+        F: Fn(&NetworkState, NodeIndex) -> Result<f64, PywrError>,
+    {
+        let index = match self.nodes.get(name) {
+            Some(index) => *index,
+            None => return Err(PyErr::from(PywrError::NodeNotFound(name.to_string()))),
+        };
+        let values = self
+            .states
+            .iter()
+            .map(|state| f(state, index))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(values)
+    }
+}
+
+#[pymethods]
+impl PyStepState {
+    #[getter]
+    fn timestep(&self) -> Timestep {
+        self.timestep
+    }
+
+    #[getter]
+    fn num_scenarios(&self) -> usize {
+        self.states.len()
+    }
+
+    /// The flow into the node `name` in each scenario.
+    fn node_in_flow(&self, name: &str) -> PyResult<Vec<f64>> {
+        self.node_values(name, |state, index| state.get_node_in_flow(index))
+    }
+
+    /// The flow out of the node `name` in each scenario.
+    fn node_out_flow(&self, name: &str) -> PyResult<Vec<f64>> {
+        self.node_values(name, |state, index| state.get_node_out_flow(index))
+    }
+
+    /// The volume of the storage node `name` in each scenario.
+    fn node_volume(&self, name: &str) -> PyResult<Vec<f64>> {
+        self.node_values(name, |state, index| state.get_node_volume(index))
+    }
+}
+
+/// The key of a value that differs between two state dumps, and its value in each dump.
+type PyStateDifference = (String, Option<f64>, Option<f64>);
+
+/// Compare two state dumps, returning the key and the values in each dump of the values that differ.
+#[pyfunction]
+fn diff_state_dumps(left: &str, right: &str, tolerance: f64) -> PyResult<Vec<PyStateDifference>> {
+    let left = StateDump::read(Path::new(left))?;
+    let right = StateDump::read(Path::new(right))?;
+    Ok(left
+        .diff(&right, tolerance)
+        .into_iter()
+        .map(|d| (d.key, d.left, d.right))
+        .collect())
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn pywr(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyModel>()?;
+    m.add_class::<PyModelSteps>()?;
+    m.add_class::<PyStepState>()?;
+    m.add_function(wrap_pyfunction!(diff_state_dumps, m)?)?;
     // m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
     // m.add_class::<recorders::py::PyRecorder>()?;
+    m.add("PywrError", py.get_type::<exceptions::PywrError>())?;
+    m.add("NodeNotFoundError", py.get_type::<NodeNotFoundError>())?;
+    m.add("EdgeNotFoundError", py.get_type::<EdgeNotFoundError>())?;
     m.add("ParameterNotFoundError", py.get_type::<ParameterNotFoundError>())?;
+    m.add("RecorderNotFoundError", py.get_type::<RecorderNotFoundError>())?;
+    m.add("NameAlreadyExistsError", py.get_type::<NameAlreadyExistsError>())?;
+    m.add("InvalidModelError", py.get_type::<InvalidModelError>())?;
+    m.add("ScenarioError", py.get_type::<ScenarioError>())?;
+    m.add("TimestepperError", py.get_type::<TimestepperError>())?;
+    m.add("SolverError", py.get_type::<SolverError>())?;
+    m.add("SolverInfeasibleError", py.get_type::<SolverInfeasibleError>())?;
+    m.add("FileFormatError", py.get_type::<FileFormatError>())?;
+    m.add("OutputError", py.get_type::<OutputError>())?;
+    m.add("PythonCallbackError", py.get_type::<PythonCallbackError>())?;
 
     Ok(())
 }
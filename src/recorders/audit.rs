@@ -0,0 +1,217 @@
+use super::{NetworkState, PywrError, RecorderMeta, Timestep, _Recorder};
+use crate::model::Model;
+use crate::node::{NodeIndex, NodeType};
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::timestep::Timestepper;
+use ndarray::Array2;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// A mass-balance error at a node exceeding the recorder's tolerance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MassBalanceViolation {
+    pub node: String,
+    pub timestep: usize,
+    pub scenario: usize,
+    pub error: f64,
+}
+
+/// A violation ordered by the size of its error, so that the smallest is dropped first.
+struct RankedViolation(MassBalanceViolation);
+
+impl PartialEq for RankedViolation {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankedViolation {}
+
+impl PartialOrd for RankedViolation {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RankedViolation {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.error.abs().total_cmp(&other.0.error.abs())
+    }
+}
+
+/// Verify the mass-balance of every link and storage node.
+///
+/// For each timestep the inflow less the outflow and change in storage of a node should be zero.
+/// The change in storage is from the volume the timestep started from, so timesteps that are not
+/// saved, and runs started from another state, are audited correctly. The largest absolute error
+/// is returned as the aggregated value, and the `max_violations` largest errors above the
+/// tolerance are retained. In strict mode the worst violations are instead returned as an error
+/// when the recorder is finalised.
+pub struct MassBalanceRecorder {
+    meta: RecorderMeta,
+    tolerance: f64,
+    strict: bool,
+    max_violations: usize,
+    node_names: Vec<String>,
+    audited: Vec<bool>,
+    max_errors: Option<Array2<f64>>,
+    num_violations: usize,
+    violations: BinaryHeap<Reverse<RankedViolation>>,
+}
+
+impl MassBalanceRecorder {
+    /// The number of violations retained by default.
+    pub const DEFAULT_MAX_VIOLATIONS: usize = 100;
+
+    pub fn new(name: &str, tolerance: f64, strict: bool) -> Self {
+        Self {
+            meta: RecorderMeta::new(name),
+            tolerance,
+            strict,
+            max_violations: Self::DEFAULT_MAX_VIOLATIONS,
+            node_names: Vec::new(),
+            audited: Vec::new(),
+            max_errors: None,
+            num_violations: 0,
+            violations: BinaryHeap::new(),
+        }
+    }
+
+    /// Retain only the `max_violations` violations with the largest absolute error.
+    pub fn with_max_violations(mut self, max_violations: usize) -> Self {
+        self.max_violations = max_violations;
+        self
+    }
+
+    /// The number of violations found, including those that were not retained.
+    pub fn num_violations(&self) -> usize {
+        self.num_violations
+    }
+
+    /// The retained violations with the largest absolute error, in descending order.
+    pub fn worst_offenders(&self, n: usize) -> Vec<MassBalanceViolation> {
+        let mut violations: Vec<&MassBalanceViolation> = self.violations.iter().map(|v| &(v.0).0).collect();
+        violations.sort_by(|a, b| b.error.abs().total_cmp(&a.error.abs()));
+        violations.into_iter().take(n).cloned().collect()
+    }
+
+    fn add_violation(&mut self, violation: MassBalanceViolation) {
+        self.num_violations += 1;
+        self.violations.push(Reverse(RankedViolation(violation)));
+        if self.violations.len() > self.max_violations {
+            self.violations.pop();
+        }
+    }
+}
+
+impl _Recorder for MassBalanceRecorder {
+    fn meta(&self) -> &RecorderMeta {
+        &self.meta
+    }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
+    fn setup(
+        &mut self,
+        model: &Model,
+        _timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
+    ) -> Result<(), PywrError> {
+        let num_scenarios = scenarios.scenario_indices().len();
+
+        self.node_names = model.nodes.iter().map(|n| n.name()).collect();
+        // Input and output nodes are the boundaries of the network, and are not expected to balance.
+        self.audited = model
+            .nodes
+            .iter()
+            .map(|n| matches!(n.node_type(), NodeType::Link | NodeType::Storage))
+            .collect();
+        self.max_errors = Some(Array2::zeros((model.nodes.len(), num_scenarios)));
+        self.num_violations = 0;
+        self.violations.clear();
+
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        _model: &Model,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        _parameter_state: &[f64],
+    ) -> Result<(), PywrError> {
+        let max_errors = match &mut self.max_errors {
+            Some(e) => e,
+            None => return Err(PywrError::RecorderNotInitialised),
+        };
+
+        let mut violations = Vec::new();
+        for (idx, audited) in self.audited.iter().enumerate() {
+            if !audited {
+                continue;
+            }
+            let node = NodeIndex(idx);
+
+            let net_flow = network_state.get_node_in_flow(node)? - network_state.get_node_out_flow(node)?;
+            let change_in_volume = match network_state.get_node_start_volume(node) {
+                Ok(start_volume) => network_state.get_node_volume(node)? - start_volume,
+                Err(PywrError::MetricNotDefinedForNode) => 0.0,
+                Err(e) => return Err(e),
+            };
+
+            let error = net_flow * timestep.days() - change_in_volume;
+            let max_error = &mut max_errors[[idx, scenario_index.index]];
+            *max_error = max_error.max(error.abs());
+
+            if error.abs() > self.tolerance {
+                violations.push(MassBalanceViolation {
+                    node: self.node_names[idx].clone(),
+                    timestep: timestep.index,
+                    scenario: scenario_index.index,
+                    error,
+                });
+            }
+        }
+        for violation in violations {
+            self.add_violation(violation);
+        }
+
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        let max_errors = match &self.max_errors {
+            Some(e) => e,
+            None => return Err(PywrError::RecorderNotInitialised),
+        };
+
+        if self.strict && self.num_violations > 0 {
+            let worst: Vec<String> = self
+                .worst_offenders(10)
+                .iter()
+                .map(|v| {
+                    format!(
+                        "node `{}` at timestep {} (scenario {}): {}",
+                        v.node, v.timestep, v.scenario, v.error
+                    )
+                })
+                .collect();
+            return Err(PywrError::MassBalanceError(format!(
+                "{} violation(s); worst: {}",
+                self.num_violations,
+                worst.join("; ")
+            )));
+        }
+
+        Ok(Some(max_errors.fold(0.0, |acc, e| acc.max(*e))))
+    }
+
+    /// The largest absolute mass-balance error of each node (rows) and scenario (columns).
+    fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
+        match &self.max_errors {
+            Some(e) => Ok(e.clone()),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+}
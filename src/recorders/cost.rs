@@ -0,0 +1,112 @@
+use super::{NetworkState, PywrError, RecorderMeta, Timestep, _Recorder};
+use crate::model::Model;
+use crate::node::NodeType;
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::timestep::Timestepper;
+use chrono::Datelike;
+use ndarray::{Array1, Array2, Axis};
+use std::collections::BTreeMap;
+
+/// Accumulate the operating cost (flow multiplied by cost) of every node.
+///
+/// Costs are resolved from the parameter state at each timestep, and multiplied by the length
+/// of the timestep in days so that totals are comparable between runs with different timesteps.
+/// Storage nodes are costed on their net inflow. The aggregated value is the total cost of all
+/// nodes averaged over the scenarios.
+pub struct OperatingCostRecorder {
+    meta: RecorderMeta,
+    node_types: Vec<NodeType>,
+    totals: Option<Array2<f64>>,
+    annual_totals: BTreeMap<i32, Array1<f64>>,
+}
+
+impl OperatingCostRecorder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            meta: RecorderMeta::new(name),
+            node_types: Vec::new(),
+            totals: None,
+            annual_totals: BTreeMap::new(),
+        }
+    }
+
+    /// The total cost of all nodes in each calendar year, per scenario.
+    pub fn annual_totals(&self) -> &BTreeMap<i32, Array1<f64>> {
+        &self.annual_totals
+    }
+}
+
+impl _Recorder for OperatingCostRecorder {
+    fn meta(&self) -> &RecorderMeta {
+        &self.meta
+    }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
+    fn setup(
+        &mut self,
+        model: &Model,
+        _timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
+    ) -> Result<(), PywrError> {
+        let num_scenarios = scenarios.scenario_indices().len();
+
+        self.node_types = model.nodes.iter().map(|n| n.node_type()).collect();
+        self.totals = Some(Array2::zeros((model.nodes.len(), num_scenarios)));
+        self.annual_totals.clear();
+
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        model: &Model,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<(), PywrError> {
+        let totals = match &mut self.totals {
+            Some(t) => t,
+            None => return Err(PywrError::RecorderNotInitialised),
+        };
+        let num_scenarios = totals.ncols();
+
+        let mut timestep_cost = 0.0;
+        for (node, node_type) in model.nodes.iter().zip(&self.node_types) {
+            let idx = node.index();
+            let flow = match node_type {
+                NodeType::Input | NodeType::Link => network_state.get_node_out_flow(idx)?,
+                NodeType::Output => network_state.get_node_in_flow(idx)?,
+                NodeType::Storage => network_state.get_node_in_flow(idx)? - network_state.get_node_out_flow(idx)?,
+            };
+
+            let cost = flow * node.get_current_cost(parameter_state) * timestep.days();
+            totals[[*idx, scenario_index.index]] += cost;
+            timestep_cost += cost;
+        }
+
+        let annual = self
+            .annual_totals
+            .entry(timestep.date.year())
+            .or_insert_with(|| Array1::zeros(num_scenarios));
+        annual[scenario_index.index] += timestep_cost;
+
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        match &self.totals {
+            Some(totals) => Ok(totals.sum_axis(Axis(0)).mean()),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+
+    /// The total cost of each node (rows) and scenario (columns).
+    fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
+        match &self.totals {
+            Some(t) => Ok(t.clone()),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::{NetworkState, PywrError, RecorderMeta, Timestep, _Recorder};
+use crate::metric::Metric;
+use crate::model::Model;
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::schema::RecorderType;
+use crate::timestep::Timestepper;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// Quote `value` as a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Quote `value` as a field of a comment line, in which line breaks are escaped so that the
+/// comment does not continue on to the next line.
+fn comment_field(value: &str) -> String {
+    csv_field(&value.replace('\r', "\\r").replace('\n', "\\n"))
+}
+
+/// Write the default metric of every node to a CSV file.
+///
+/// One row is written per timestep and scenario, with the scenario given by its label. The
+/// recorder's attributes are written as comment lines at the start of the file. Names, labels
+/// and attributes are quoted where needed.
+pub(crate) struct CSVRecorder {
+    meta: RecorderMeta,
+    filename: PathBuf,
+    writer: Option<BufWriter<File>>,
+    metrics: Vec<Metric>,
+}
+
+impl CSVRecorder {
+    pub fn new(name: &str, filename: PathBuf) -> Self {
+        Self {
+            meta: RecorderMeta::new(name),
+            filename,
+            writer: None,
+            metrics: Vec::new(),
+        }
+    }
+}
+
+impl _Recorder for CSVRecorder {
+    fn meta(&self) -> &RecorderMeta {
+        &self.meta
+    }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
+    fn schema(&self, _model: &Model) -> Option<RecorderType> {
+        Some(RecorderType::Csv {
+            path: self.filename.to_string_lossy().to_string(),
+        })
+    }
+    fn setup(
+        &mut self,
+        model: &Model,
+        _timestepper: &Timestepper,
+        _scenarios: &ScenarioGroupCollection,
+    ) -> Result<(), PywrError> {
+        let file = match File::create(&self.filename) {
+            Ok(f) => f,
+            Err(e) => return Err(PywrError::CSVError(e.to_string())),
+        };
+        let mut writer = BufWriter::new(file);
+
+        let mut lines: Vec<String> = self
+            .meta
+            .output_attributes()
+            .iter()
+            .map(|(key, value)| format!("# {}: {}", comment_field(key), comment_field(value)))
+            .collect();
+
+        let mut header = vec!["date".to_string(), "scenario".to_string()];
+        self.metrics.clear();
+        for node in &model.nodes {
+            self.metrics.push(node.default_metric());
+            header.push(csv_field(&node.name()));
+        }
+        lines.push(header.join(","));
+
+        for line in lines {
+            if let Err(e) = writeln!(writer, "{}", line) {
+                return Err(PywrError::CSVError(e.to_string()));
+            }
+        }
+
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        model: &Model,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<(), PywrError> {
+        let writer = match &mut self.writer {
+            Some(w) => w,
+            None => return Err(PywrError::RecorderNotInitialised),
+        };
+
+        let mut row = vec![timestep.label(), csv_field(&scenario_index.label())];
+        for metric in &self.metrics {
+            row.push(metric.get_value(model, network_state, parameter_state)?.to_string());
+        }
+
+        match writeln!(writer, "{}", row.join(",")) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PywrError::CSVError(e.to_string())),
+        }
+    }
+
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        match self.writer.take() {
+            Some(mut writer) => match writer.flush() {
+                Ok(_) => Ok(None),
+                Err(e) => Err(PywrError::CSVError(e.to_string())),
+            },
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+}
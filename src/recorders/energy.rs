@@ -0,0 +1,139 @@
+use super::{NetworkState, PywrError, RecorderMeta, Timestep, _Recorder};
+use crate::metric::Metric;
+use crate::model::Model;
+use crate::parameters::ParameterIndex;
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::timestep::Timestepper;
+use chrono::Datelike;
+use ndarray::{Array1, Array2, Axis};
+use std::collections::BTreeMap;
+
+/// Energy use and carbon emissions of a single year, per scenario.
+#[derive(Clone, Debug)]
+pub struct AnnualEnergy {
+    pub energy: Array1<f64>,
+    pub carbon: Array1<f64>,
+}
+
+/// Record the energy used by pumping and the resulting carbon emissions.
+///
+/// Each energy component is a flow metric and a parameter giving the energy used per unit of
+/// flow. The energy of a timestep is the sum of flow × unit-energy × timestep length (days) over
+/// the components. This is converted to CO₂ using the emission factor parameter. The aggregated
+/// value is the total carbon emitted averaged over the scenarios.
+pub struct EnergyRecorder {
+    meta: RecorderMeta,
+    components: Vec<(Metric, ParameterIndex)>,
+    emission_factor: ParameterIndex,
+    energy: Option<Array2<f64>>,
+    carbon: Option<Array2<f64>>,
+    annual: BTreeMap<i32, AnnualEnergy>,
+}
+
+impl EnergyRecorder {
+    pub fn new(name: &str, components: Vec<(Metric, ParameterIndex)>, emission_factor: ParameterIndex) -> Self {
+        Self {
+            meta: RecorderMeta::new(name),
+            components,
+            emission_factor,
+            energy: None,
+            carbon: None,
+            annual: BTreeMap::new(),
+        }
+    }
+
+    /// The carbon emitted in each timestep (rows) and scenario (columns).
+    pub fn carbon(&self) -> Result<&Array2<f64>, PywrError> {
+        match &self.carbon {
+            Some(c) => Ok(c),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+
+    /// The energy use and carbon emissions of each calendar year.
+    pub fn annual_totals(&self) -> &BTreeMap<i32, AnnualEnergy> {
+        &self.annual
+    }
+}
+
+impl _Recorder for EnergyRecorder {
+    fn meta(&self) -> &RecorderMeta {
+        &self.meta
+    }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
+    fn setup(
+        &mut self,
+        _model: &Model,
+        _timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
+    ) -> Result<(), PywrError> {
+        let shape = (self.meta.num_rows(), scenarios.scenario_indices().len());
+
+        self.energy = Some(Array2::zeros(shape));
+        self.carbon = Some(Array2::zeros(shape));
+        self.annual.clear();
+
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        model: &Model,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<(), PywrError> {
+        let row = match self.meta.row_index(timestep) {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+
+        let mut energy = 0.0;
+        for (metric, unit_energy) in &self.components {
+            let flow = metric.get_value(model, network_state, parameter_state)?;
+            let unit_energy = match parameter_state.get(**unit_energy) {
+                Some(v) => *v,
+                None => return Err(PywrError::ParameterIndexNotFound(*unit_energy)),
+            };
+            energy += flow * unit_energy * timestep.days();
+        }
+
+        let carbon = match parameter_state.get(*self.emission_factor) {
+            Some(factor) => energy * factor,
+            None => return Err(PywrError::ParameterIndexNotFound(self.emission_factor)),
+        };
+
+        match (&mut self.energy, &mut self.carbon) {
+            (Some(e), Some(c)) => {
+                e[[row, scenario_index.index]] = energy;
+                c[[row, scenario_index.index]] = carbon;
+
+                let num_scenarios = e.ncols();
+                let annual = self.annual.entry(timestep.date.year()).or_insert_with(|| AnnualEnergy {
+                    energy: Array1::zeros(num_scenarios),
+                    carbon: Array1::zeros(num_scenarios),
+                });
+                annual.energy[scenario_index.index] += energy;
+                annual.carbon[scenario_index.index] += carbon;
+
+                Ok(())
+            }
+            _ => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        Ok(self.carbon()?.sum_axis(Axis(0)).mean())
+    }
+
+    /// The energy used in each timestep (rows) and scenario (columns).
+    fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
+        match &self.energy {
+            Some(e) => Ok(e.clone()),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+}
@@ -1,9 +1,13 @@
 use super::{NetworkState, PywrError, RecorderMeta, Timestep, _Recorder};
 use crate::metric::Metric;
 use crate::model::Model;
-use crate::scenario::ScenarioIndex;
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::schema::RecorderType;
+use crate::timestep::Timestepper;
+use hdf5::types::VarLenUnicode;
 use ndarray::{s, Array2};
 use std::path::PathBuf;
+use tracing::debug;
 
 #[derive(Clone, Debug)]
 pub(crate) struct HDF5Recorder {
@@ -30,24 +34,70 @@ impl _Recorder for HDF5Recorder {
     fn meta(&self) -> &RecorderMeta {
         &self.meta
     }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
+    fn schema(&self, _model: &Model) -> Option<RecorderType> {
+        Some(RecorderType::Hdf5 {
+            path: self.filename.to_string_lossy().to_string(),
+        })
+    }
     fn setup(
         &mut self,
         model: &Model,
-        timesteps: &Vec<Timestep>,
-        scenario_indices: &Vec<ScenarioIndex>,
+        timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
     ) -> Result<(), PywrError> {
         let file = match hdf5::File::create(&self.filename) {
             Ok(f) => f,
             Err(e) => return Err(PywrError::HDF5Error(e.to_string())),
         };
+
+        for (key, value) in self.meta.output_attributes() {
+            let value: VarLenUnicode = match value.parse() {
+                Ok(v) => v,
+                Err(e) => return Err(PywrError::HDF5Error(e.to_string())),
+            };
+            let attr = match file.new_attr::<VarLenUnicode>().create(&*key) {
+                Ok(a) => a,
+                Err(e) => return Err(PywrError::HDF5Error(e.to_string())),
+            };
+            if let Err(e) = attr.write_scalar(&value) {
+                return Err(PywrError::HDF5Error(e.to_string()));
+            }
+        }
+
+        // The label of each column of the datasets.
+        let scenario_labels = match scenarios
+            .scenario_indices()
+            .iter()
+            .map(|s| s.label().parse())
+            .collect::<Result<Vec<VarLenUnicode>, _>>()
+        {
+            Ok(l) => l,
+            Err(e) => return Err(PywrError::HDF5Error(e.to_string())),
+        };
+        let attr = match file
+            .new_attr::<VarLenUnicode>()
+            .shape(scenario_labels.len())
+            .create("scenarios")
+        {
+            Ok(a) => a,
+            Err(e) => return Err(PywrError::HDF5Error(e.to_string())),
+        };
+        if let Err(e) = attr.write_raw(&scenario_labels) {
+            return Err(PywrError::HDF5Error(e.to_string()));
+        }
+
         let mut datasets = Vec::new();
 
-        let shape = (timesteps.len(), scenario_indices.len());
+        let num_scenarios = scenario_labels.len();
+        let shape = (self.meta.num_rows(), num_scenarios);
 
         for node in &model.nodes {
             let metric = node.default_metric();
             let name = node.name().to_string();
-            println!("Adding metric with name: {}", name);
+            debug!("Adding metric with name: {}", name);
             let ds = match file.new_dataset::<f64>().shape(shape).create(&*name) {
                 Ok(ds) => ds,
                 Err(e) => return Err(PywrError::HDF5Error(e.to_string())),
@@ -55,7 +105,7 @@ impl _Recorder for HDF5Recorder {
             datasets.push((metric, ds));
         }
 
-        self.array = Some(Array2::zeros((datasets.len(), scenario_indices.len())));
+        self.array = Some(Array2::zeros((datasets.len(), num_scenarios)));
         self.datasets = Some(datasets);
         self.file = Some(file);
 
@@ -63,6 +113,7 @@ impl _Recorder for HDF5Recorder {
     }
     fn save(
         &mut self,
+        model: &Model,
         timestep: &Timestep,
         scenario_index: &ScenarioIndex,
         network_state: &NetworkState,
@@ -71,7 +122,7 @@ impl _Recorder for HDF5Recorder {
         match (&mut self.array, &self.datasets) {
             (Some(array), Some(datasets)) => {
                 for (idx, (metric, _ds)) in datasets.iter().enumerate() {
-                    let value = metric.get_value(network_state, parameter_state)?;
+                    let value = metric.get_value(model, network_state, parameter_state)?;
                     array[[idx, scenario_index.index]] = value
                 }
                 Ok(())
@@ -81,10 +132,10 @@ impl _Recorder for HDF5Recorder {
     }
 
     fn after_save(&mut self, timestep: &Timestep) -> Result<(), PywrError> {
-        match (&self.array, &mut self.datasets) {
-            (Some(array), Some(datasets)) => {
+        match (&self.array, &mut self.datasets, self.meta.row_index(timestep)) {
+            (Some(array), Some(datasets), Some(row)) => {
                 for (node_idx, (_metric, dataset)) in datasets.iter_mut().enumerate() {
-                    if let Err(e) = dataset.write_slice(array.slice(s![node_idx, ..]), s![timestep.index, ..]) {
+                    if let Err(e) = dataset.write_slice(array.slice(s![node_idx, ..]), s![row, ..]) {
                         return Err(PywrError::HDF5Error(e.to_string()));
                     }
                 }
@@ -94,11 +145,11 @@ impl _Recorder for HDF5Recorder {
         }
     }
 
-    fn finalise(&mut self) -> Result<(), PywrError> {
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
         match self.file.take() {
             Some(file) => {
                 file.close();
-                Ok(())
+                Ok(None)
             }
             None => Err(PywrError::RecorderNotInitialised),
         }
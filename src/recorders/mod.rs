@@ -1,21 +1,82 @@
+pub mod audit;
+pub mod cost;
+pub mod csv;
+pub mod energy;
 pub mod hdf;
 pub mod py;
+pub mod stream;
 
 use crate::assert_almost_eq;
 use crate::metric::Metric;
 use crate::model::Model;
-use crate::scenario::ScenarioIndex;
-use crate::timestep::Timestep;
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::schema::RecorderType;
+use crate::state::Precision;
+use crate::timestep::{Timestep, Timestepper};
 use crate::{NetworkState, PywrError};
+use chrono::NaiveDate;
 use ndarray::prelude::*;
 use ndarray::Array2;
-use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
-pub type RecorderIndex = usize;
-pub type RecorderRef = Rc<RefCell<Box<dyn _Recorder>>>;
+index_type!(RecorderIndex);
+pub type RecorderRef = Arc<Mutex<Box<dyn _Recorder>>>;
+
+/// Controls which timesteps of a run are saved by a recorder.
+#[derive(Clone, Debug)]
+pub struct RecorderFrequency {
+    /// Save every `interval` timesteps.
+    pub interval: usize,
+    /// Only save timesteps on or after this date.
+    pub start: Option<NaiveDate>,
+    /// Only save timesteps on or before this date.
+    pub end: Option<NaiveDate>,
+    /// Number of timesteps at the start of the run to skip.
+    pub warm_up: usize,
+}
+
+impl Default for RecorderFrequency {
+    fn default() -> Self {
+        Self {
+            interval: 1,
+            start: None,
+            end: None,
+            warm_up: 0,
+        }
+    }
+}
+
+impl RecorderFrequency {
+    /// Save every `interval` timesteps.
+    pub fn every(interval: usize) -> Self {
+        Self {
+            interval,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if this timestep should be saved.
+    pub fn includes(&self, timestep: &Timestep) -> bool {
+        if timestep.index < self.warm_up {
+            return false;
+        }
+        if let Some(start) = self.start {
+            if timestep.date < start {
+                return false;
+            }
+        }
+        if let Some(end) = self.end {
+            if timestep.date > end {
+                return false;
+            }
+        }
+        (timestep.index - self.warm_up) % self.interval.max(1) == 0
+    }
+}
 
 /// Meta data common to all parameters.
 #[derive(Clone, Debug)]
@@ -23,6 +84,14 @@ pub struct RecorderMeta {
     pub index: Option<RecorderIndex>,
     pub name: String,
     pub comment: String,
+    pub units: Option<String>,
+    pub long_name: Option<String>,
+    pub attributes: BTreeMap<String, String>,
+    pub frequency: RecorderFrequency,
+    /// The precision the recorder stores its values in, or `None` for the model's precision.
+    pub precision: Option<Precision>,
+    // Mapping from timestep index to the row in the recorder's output (if saved).
+    rows: Vec<Option<usize>>,
 }
 
 impl RecorderMeta {
@@ -31,39 +100,156 @@ impl RecorderMeta {
             index: None,
             name: name.to_string(),
             comment: "".to_string(),
+            units: None,
+            long_name: None,
+            attributes: BTreeMap::new(),
+            frequency: RecorderFrequency::default(),
+            precision: None,
+            rows: Vec::new(),
+        }
+    }
+
+    /// All of the descriptive attributes of the recorder to be written to its outputs.
+    ///
+    /// This includes the `units` and `long_name` if they are defined.
+    pub fn output_attributes(&self) -> BTreeMap<String, String> {
+        let mut attributes = self.attributes.clone();
+        if let Some(units) = &self.units {
+            attributes.insert("units".to_string(), units.clone());
+        }
+        if let Some(long_name) = &self.long_name {
+            attributes.insert("long_name".to_string(), long_name.clone());
         }
+        if !self.comment.is_empty() {
+            attributes.insert("comment".to_string(), self.comment.clone());
+        }
+        attributes
+    }
+
+    /// Compute which output row (if any) each of the timesteps is saved to.
+    ///
+    /// Timesteps in the warm-up period of `timestepper` are never saved.
+    fn setup_rows(&mut self, timestepper: &Timestepper) {
+        let mut next_row = 0;
+        self.rows = timestepper
+            .timesteps()
+            .iter()
+            .map(|ts| {
+                if !timestepper.is_warm_up(ts) && self.frequency.includes(ts) {
+                    next_row += 1;
+                    Some(next_row - 1)
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /// The output row for this timestep, or `None` if it is not saved.
+    pub fn row_index(&self, timestep: &Timestep) -> Option<usize> {
+        self.rows.get(timestep.index).copied().flatten()
+    }
+
+    /// The number of timesteps saved by the recorder.
+    pub fn num_rows(&self) -> usize {
+        self.rows.iter().filter(|r| r.is_some()).count()
+    }
+
+    /// The precision to store values in; the recorder's own, or otherwise that of `model`.
+    pub fn precision(&self, model: &Model) -> Precision {
+        self.precision.unwrap_or_else(|| model.storage_precision())
     }
 }
 
-pub trait _Recorder {
+/// The values saved by a recorder for each row (timestep) and column (scenario).
+///
+/// The values are stored in the recorder's precision, and read back as `f64`.
+enum RecorderArray {
+    Double(Array2<f64>),
+    Single(Array2<f32>),
+}
+
+impl RecorderArray {
+    fn zeros(shape: (usize, usize), precision: Precision) -> Self {
+        match precision {
+            Precision::Double => Self::Double(Array2::zeros(shape)),
+            Precision::Single => Self::Single(Array2::zeros(shape)),
+        }
+    }
+
+    fn set(&mut self, row: usize, column: usize, value: f64) {
+        match self {
+            Self::Double(a) => a[[row, column]] = value,
+            Self::Single(a) => a[[row, column]] = value as f32,
+        }
+    }
+
+    fn ncols(&self) -> usize {
+        match self {
+            Self::Double(a) => a.ncols(),
+            Self::Single(a) => a.ncols(),
+        }
+    }
+
+    fn column(&self, column: usize) -> Array1<f64> {
+        match self {
+            Self::Double(a) => a.column(column).to_owned(),
+            Self::Single(a) => a.column(column).mapv(f64::from),
+        }
+    }
+
+    fn to_f64(&self) -> Array2<f64> {
+        match self {
+            Self::Double(a) => a.clone(),
+            Self::Single(a) => a.mapv(f64::from),
+        }
+    }
+}
+
+pub trait _Recorder: Send {
     fn meta(&self) -> &RecorderMeta;
+    fn meta_mut(&mut self) -> &mut RecorderMeta;
     fn setup(
         &mut self,
         model: &Model,
-        timesteps: &Vec<Timestep>,
-        scenario_indices: &Vec<ScenarioIndex>,
+        timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
     ) -> Result<(), PywrError> {
         Ok(())
     }
-    fn before(&self) {}
+    /// Called once before each saved timestep is solved.
+    fn before(&mut self, _timestep: &Timestep) -> Result<(), PywrError> {
+        Ok(())
+    }
+    /// Save the new state of a scenario after a timestep is solved.
     fn save(
         &mut self,
+        model: &Model,
         timestep: &Timestep,
         scenario_index: &ScenarioIndex,
         network_state: &NetworkState,
         parameter_state: &[f64],
     ) -> Result<(), PywrError>;
+    /// Called after the states of all of the scenarios of a timestep have been saved.
     fn after_save(&mut self, timestep: &Timestep) -> Result<(), PywrError> {
         Ok(())
     }
-    fn finalise(&mut self) -> Result<(), PywrError> {
-        Ok(())
+    /// Finalise the recorder at the end of a run.
+    ///
+    /// Recorders may return an aggregated value which summarises the entire run (e.g. for use
+    /// as an objective function).
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        Ok(None)
     }
 
     // Data access
     fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
         Err(PywrError::NotSupportedByRecorder)
     }
+    /// The recorder as a schema, or `None` if it can not be written to a model file.
+    fn schema(&self, _model: &Model) -> Option<RecorderType> {
+        None
+    }
 }
 
 #[derive(Clone)]
@@ -84,7 +270,7 @@ impl fmt::Debug for Recorder {
 
 impl Recorder {
     pub fn new(parameter: Box<dyn _Recorder>, index: RecorderIndex) -> Self {
-        Self(Rc::new(RefCell::new(parameter)), index)
+        Self(Arc::new(Mutex::new(parameter)), index)
     }
 
     pub fn index(&self) -> RecorderIndex {
@@ -92,44 +278,99 @@ impl Recorder {
     }
 
     pub fn name(&self) -> String {
-        self.0.borrow().deref().meta().name.to_string()
+        self.0.lock().unwrap().deref().meta().name.to_string()
     }
 
     pub fn setup(
         &self,
         model: &Model,
-        timesteps: &Vec<Timestep>,
-        scenario_indices: &Vec<ScenarioIndex>,
+        timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
     ) -> Result<(), PywrError> {
+        let mut recorder = self.0.lock().unwrap();
+        recorder.meta_mut().setup_rows(timestepper);
+        recorder.setup(model, timestepper, scenarios)
+    }
+
+    pub fn schema(&self, model: &Model) -> Option<RecorderType> {
+        self.0.lock().unwrap().schema(model)
+    }
+
+    /// Set which timesteps are saved by this recorder.
+    pub fn set_frequency(&self, frequency: RecorderFrequency) {
+        self.0.lock().unwrap().deref_mut().meta_mut().frequency = frequency;
+    }
+
+    /// Set the precision the recorder stores its values in.
+    pub fn set_precision(&self, precision: Precision) {
+        self.0.lock().unwrap().deref_mut().meta_mut().precision = Some(precision);
+    }
+
+    /// The output row for `timestep` in the most recent run, or `None` if it was not saved.
+    pub fn row_index(&self, timestep: &Timestep) -> Option<usize> {
+        self.0.lock().unwrap().deref().meta().row_index(timestep)
+    }
+
+    /// Set the units of the recorded values.
+    pub fn set_units(&self, units: &str) {
+        self.0.lock().unwrap().deref_mut().meta_mut().units = Some(units.to_string());
+    }
+
+    /// Set a descriptive name of the recorded values.
+    pub fn set_long_name(&self, long_name: &str) {
+        self.0.lock().unwrap().deref_mut().meta_mut().long_name = Some(long_name.to_string());
+    }
+
+    /// Set an arbitrary attribute to be written to the recorder's outputs.
+    pub fn set_attribute(&self, key: &str, value: &str) {
         self.0
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .deref_mut()
-            .setup(model, timesteps, scenario_indices)
+            .meta_mut()
+            .attributes
+            .insert(key.to_string(), value.to_string());
+    }
+
+    pub fn before(&self, timestep: &Timestep) -> Result<(), PywrError> {
+        let mut recorder = self.0.lock().unwrap();
+        if recorder.meta().row_index(timestep).is_none() {
+            return Ok(());
+        }
+        recorder.before(timestep)
     }
 
     pub fn save(
         &self,
+        model: &Model,
         timestep: &Timestep,
         scenario_index: &ScenarioIndex,
         network_state: &NetworkState,
         parameter_state: &[f64],
     ) -> Result<(), PywrError> {
-        self.0
-            .borrow_mut()
-            .deref_mut()
-            .save(timestep, scenario_index, network_state, parameter_state)
+        let mut recorder = self.0.lock().unwrap();
+        if recorder.meta().row_index(timestep).is_none() {
+            return Ok(());
+        }
+        recorder.save(model, timestep, scenario_index, network_state, parameter_state)
     }
 
     pub fn after_save(&self, timestep: &Timestep) -> Result<(), PywrError> {
-        self.0.borrow_mut().deref_mut().after_save(timestep)
+        let mut recorder = self.0.lock().unwrap();
+        if recorder.meta().row_index(timestep).is_none() {
+            return Ok(());
+        }
+        recorder.after_save(timestep)
     }
 
-    pub fn finalise(&self) -> Result<(), PywrError> {
-        self.0.borrow_mut().deref_mut().finalise()
+    pub fn finalise(&self) -> Result<Option<f64>, PywrError> {
+        self.0.lock().unwrap().deref_mut().finalise()
     }
 
-    fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
-        match self.0.borrow().deref().data_view2() {
+    /// A copy of the values the recorder keeps in memory, with a row for each saved timestep and a
+    /// column for each scenario.
+    pub fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
+        match self.0.lock().unwrap().deref().data_view2() {
             Ok(av) => Ok(av),
             Err(e) => Err(e),
         }
@@ -138,7 +379,7 @@ impl Recorder {
 
 pub struct Array2Recorder {
     meta: RecorderMeta,
-    array: Option<Array2<f64>>,
+    array: Option<RecorderArray>,
     metric: Metric,
 }
 
@@ -156,21 +397,25 @@ impl _Recorder for Array2Recorder {
     fn meta(&self) -> &RecorderMeta {
         &self.meta
     }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
 
     fn setup(
         &mut self,
         model: &Model,
-        timesteps: &Vec<Timestep>,
-        scenario_indices: &Vec<ScenarioIndex>,
+        _timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
     ) -> Result<(), PywrError> {
-        // TODO set this up properly.
-        self.array = Some(Array::zeros((365, 10)));
+        let shape = (self.meta.num_rows(), scenarios.scenario_indices().len());
+        self.array = Some(RecorderArray::zeros(shape, self.meta.precision(model)));
 
         Ok(())
     }
 
     fn save(
         &mut self,
+        model: &Model,
         timestep: &Timestep,
         scenario_index: &ScenarioIndex,
         state: &NetworkState,
@@ -178,12 +423,12 @@ impl _Recorder for Array2Recorder {
     ) -> Result<(), PywrError> {
         // This panics if out-of-bounds
 
-        match &mut self.array {
-            Some(array) => {
-                let value = self.metric.get_value(state, parameter_state)?;
-                array[[timestep.index, scenario_index.index]] = value
+        match (&mut self.array, self.meta.row_index(timestep)) {
+            (Some(array), Some(row)) => {
+                let value = self.metric.get_value(model, state, parameter_state)?;
+                array.set(row, scenario_index.index, value)
             }
-            None => return Err(PywrError::RecorderNotInitialised),
+            _ => return Err(PywrError::RecorderNotInitialised),
         };
 
         Ok(())
@@ -191,7 +436,7 @@ impl _Recorder for Array2Recorder {
 
     fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
         match &self.array {
-            Some(a) => Ok(a.clone()),
+            Some(a) => Ok(a.to_f64()),
             None => Err(PywrError::RecorderNotInitialised),
         }
     }
@@ -217,9 +462,13 @@ impl _Recorder for AssertionRecorder {
     fn meta(&self) -> &RecorderMeta {
         &self.meta
     }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
 
     fn save(
         &mut self,
+        model: &Model,
         timestep: &Timestep,
         scenario_index: &ScenarioIndex,
         state: &NetworkState,
@@ -232,12 +481,13 @@ impl _Recorder for AssertionRecorder {
             None => panic!("Simulation produced results out of range."),
         };
 
-        assert_almost_eq!(self.metric.get_value(state, parameter_state)?, expected_value);
+        assert_almost_eq!(self.metric.get_value(model, state, parameter_state)?, expected_value);
 
         Ok(())
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum RecorderAggregation {
     Min,
     Max,
@@ -247,33 +497,331 @@ pub enum RecorderAggregation {
     Quantile(f64),
     CountNonZero,
     CountAboveThreshold(f64),
+    /// The proportion of the values above the threshold.
+    ExceedanceProbability(f64),
 }
 
+impl FromStr for RecorderAggregation {
+    type Err = PywrError;
+
+    /// The aggregation called `name`; those with a quantile or threshold have no name.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            "mean" => Ok(Self::Mean),
+            "median" => Ok(Self::Median),
+            "sum" => Ok(Self::Sum),
+            "count_non_zero" => Ok(Self::CountNonZero),
+            _ => Err(PywrError::InvalidAggregationFunction(name.to_string())),
+        }
+    }
+}
+
+impl RecorderAggregation {
+    /// The name of the aggregation, as read by `from_str`, or `None` if it has a quantile or
+    /// threshold.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Self::Min => Some("min"),
+            Self::Max => Some("max"),
+            Self::Mean => Some("mean"),
+            Self::Median => Some("median"),
+            Self::Sum => Some("sum"),
+            Self::CountNonZero => Some("count_non_zero"),
+            Self::Quantile(_) | Self::CountAboveThreshold(_) | Self::ExceedanceProbability(_) => None,
+        }
+    }
+
+    /// Aggregate the given values to a single value.
+    ///
+    /// `Min` and `Max` of no values are NaN, as are the quantiles.
+    pub fn aggregate(&self, values: ArrayView1<f64>) -> f64 {
+        match self {
+            Self::Min => values.iter().copied().reduce(f64::min).unwrap_or(f64::NAN),
+            Self::Max => values.iter().copied().reduce(f64::max).unwrap_or(f64::NAN),
+            Self::Mean => values.sum() / values.len() as f64,
+            Self::Median => Self::quantile(values, 0.5),
+            Self::Sum => values.sum(),
+            Self::Quantile(q) => Self::quantile(values, *q),
+            Self::CountNonZero => values.iter().filter(|v| **v != 0.0).count() as f64,
+            Self::CountAboveThreshold(threshold) => values.iter().filter(|v| **v > *threshold).count() as f64,
+            Self::ExceedanceProbability(threshold) => {
+                values.iter().filter(|v| **v > *threshold).count() as f64 / values.len() as f64
+            }
+        }
+    }
+
+    /// Aggregate the given values to a single value, giving each the corresponding weight.
+    ///
+    /// The weights are scaled to sum to the number of values, so that equal weights give the same
+    /// result as `aggregate`. Counts are the total weight of the values counted, and values with
+    /// zero weight are ignored by `Min` and `Max`. If the weights do not have a positive total
+    /// every aggregation is NaN.
+    pub fn aggregate_weighted(&self, values: ArrayView1<f64>, weights: ArrayView1<f64>) -> f64 {
+        let n = values.len() as f64;
+        let total = weights.sum();
+        if total.is_nan() || total <= 0.0 {
+            return f64::NAN;
+        }
+        let weighted: Vec<(f64, f64)> = values.iter().zip(weights).map(|(v, w)| (*v, w * n / total)).collect();
+        let weight_where =
+            |f: &dyn Fn(f64) -> bool| -> f64 { weighted.iter().filter(|(v, _)| f(*v)).map(|(_, w)| w).sum() };
+
+        match self {
+            Self::Min => weighted
+                .iter()
+                .filter(|(_, w)| *w > 0.0)
+                .map(|(v, _)| *v)
+                .reduce(f64::min)
+                .unwrap_or(f64::NAN),
+            Self::Max => weighted
+                .iter()
+                .filter(|(_, w)| *w > 0.0)
+                .map(|(v, _)| *v)
+                .reduce(f64::max)
+                .unwrap_or(f64::NAN),
+            Self::Mean => weighted.iter().map(|(v, w)| v * w).sum::<f64>() / n,
+            Self::Median => Self::weighted_quantile(weighted, 0.5),
+            Self::Sum => weighted.iter().map(|(v, w)| v * w).sum(),
+            Self::Quantile(q) => Self::weighted_quantile(weighted, *q),
+            Self::CountNonZero => weight_where(&|v| v != 0.0),
+            Self::CountAboveThreshold(threshold) => weight_where(&|v| v > *threshold),
+            Self::ExceedanceProbability(threshold) => weight_where(&|v| v > *threshold) / n,
+        }
+    }
+
+    /// Compute the `q`th quantile of the values using linear interpolation between points.
+    fn quantile(values: ArrayView1<f64>, q: f64) -> f64 {
+        let mut sorted: Vec<f64> = values.to_vec();
+        if sorted.is_empty() {
+            return f64::NAN;
+        }
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let pos = q.max(0.0).min(1.0) * (sorted.len() - 1) as f64;
+        let lower = pos.floor() as usize;
+        let upper = pos.ceil() as usize;
+        let frac = pos - lower as f64;
+        sorted[lower] + (sorted[upper] - sorted[lower]) * frac
+    }
+
+    /// Compute the `q`th quantile of (value, weight) pairs, with weights that sum to their number.
+    ///
+    /// Each value is placed at the middle of its weight on a scale from zero to the number of
+    /// values less one, and the quantile is interpolated between them. With equal weights the
+    /// values are at their ranks and this is the same as `quantile`.
+    fn weighted_quantile(mut weighted: Vec<(f64, f64)>, q: f64) -> f64 {
+        weighted.retain(|(_, w)| *w > 0.0);
+        if weighted.is_empty() {
+            return f64::NAN;
+        }
+        weighted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let total: f64 = weighted.iter().map(|(_, w)| w).sum();
+        let pos = q.max(0.0).min(1.0) * (total - 1.0);
+        let mut cumulative = 0.0;
+        let mut previous: Option<(f64, f64)> = None;
+        for &(value, weight) in &weighted {
+            let rank = cumulative + (weight - 1.0) / 2.0;
+            cumulative += weight;
+            if pos <= rank {
+                return match previous {
+                    Some((prev_value, prev_rank)) => {
+                        prev_value + (value - prev_value) * (pos - prev_rank) / (rank - prev_rank)
+                    }
+                    None => value,
+                };
+            }
+            previous = Some((value, rank));
+        }
+        weighted[weighted.len() - 1].0
+    }
+}
+
+/// A recorder which stores the values of a metric and returns an aggregated value when finalised.
+///
+/// The values are first aggregated over time for each scenario, and then those per-scenario
+/// values are aggregated to a single value.
+pub struct AggregatedRecorder {
+    meta: RecorderMeta,
+    array: Option<RecorderArray>,
+    metric: Metric,
+    temporal_aggregation: RecorderAggregation,
+    scenario_aggregation: RecorderAggregation,
+    scenario_weights: Option<Array1<f64>>,
+}
+
+impl AggregatedRecorder {
+    pub fn new(
+        name: &str,
+        metric: Metric,
+        temporal_aggregation: RecorderAggregation,
+        scenario_aggregation: RecorderAggregation,
+    ) -> Self {
+        Self {
+            meta: RecorderMeta::new(name),
+            array: None,
+            metric,
+            temporal_aggregation,
+            scenario_aggregation,
+            scenario_weights: None,
+        }
+    }
+}
+
+impl _Recorder for AggregatedRecorder {
+    fn meta(&self) -> &RecorderMeta {
+        &self.meta
+    }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
+    fn schema(&self, model: &Model) -> Option<RecorderType> {
+        let (metric, component) = model.metric_name(&self.metric)?;
+        Some(RecorderType::Aggregated {
+            metric: metric.to_string(),
+            component,
+            temporal_agg_func: self
+                .temporal_aggregation
+                .name()
+                .map(|_| self.temporal_aggregation.clone())?,
+            scenario_agg_func: self
+                .scenario_aggregation
+                .name()
+                .map(|_| self.scenario_aggregation.clone())?,
+        })
+    }
+
+    fn setup(
+        &mut self,
+        model: &Model,
+        _timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
+    ) -> Result<(), PywrError> {
+        let shape = (self.meta.num_rows(), scenarios.scenario_indices().len());
+        self.array = Some(RecorderArray::zeros(shape, self.meta.precision(model)));
+        self.scenario_weights = scenarios.scenario_weights().map(Array1::from);
+        if let Some(weights) = &self.scenario_weights {
+            // A slice or sample of the scenarios may select only members with zero weight.
+            if weights.sum() <= 0.0 {
+                return Err(PywrError::ZeroScenarioWeight);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        model: &Model,
+        timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<(), PywrError> {
+        match (&mut self.array, self.meta.row_index(timestep)) {
+            (Some(array), Some(row)) => {
+                let value = self.metric.get_value(model, state, parameter_state)?;
+                array.set(row, scenario_index.index, value)
+            }
+            _ => return Err(PywrError::RecorderNotInitialised),
+        };
+
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        let array = match &self.array {
+            Some(a) => a,
+            None => return Err(PywrError::RecorderNotInitialised),
+        };
+
+        let scenario_values: Array1<f64> = (0..array.ncols())
+            .map(|column| self.temporal_aggregation.aggregate(array.column(column).view()))
+            .collect();
+
+        let value = match &self.scenario_weights {
+            Some(weights) => self
+                .scenario_aggregation
+                .aggregate_weighted(scenario_values.view(), weights.view()),
+            None => self.scenario_aggregation.aggregate(scenario_values.view()),
+        };
+        Ok(Some(value))
+    }
+
+    fn data_view2(&self) -> Result<Array2<f64>, PywrError> {
+        match &self.array {
+            Some(a) => Ok(a.to_f64()),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Direction {
     Minimise,
     Maximise,
 }
 
-struct RecorderMetric {
-    temporal_aggregation: RecorderAggregation,
-    scenario_aggregation: RecorderAggregation,
-    lower_bounds: Option<f64>,
-    upper_bounds: Option<f64>,
-    objective: Option<Direction>,
+/// Bounds applied to a recorder's aggregated value when it is used as an optimisation constraint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConstraintBounds {
+    pub lower: Option<f64>,
+    pub upper: Option<f64>,
+}
+
+impl ConstraintBounds {
+    pub fn new(lower: Option<f64>, upper: Option<f64>) -> Self {
+        Self { lower, upper }
+    }
+
+    /// Returns true if `value` is within the bounds.
+    pub fn is_satisfied(&self, value: f64) -> bool {
+        let above_lower = self.lower.map_or(true, |lb| value >= lb);
+        let below_upper = self.upper.map_or(true, |ub| value <= ub);
+        above_lower && below_upper
+    }
+}
+
+/// The aggregated value of a recorder flagged as an objective.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectiveValue {
+    pub name: String,
+    pub direction: Direction,
+    pub value: f64,
+}
+
+/// The aggregated value of a recorder flagged as a constraint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstraintResult {
+    pub name: String,
+    pub bounds: ConstraintBounds,
+    pub value: f64,
+}
+
+impl ConstraintResult {
+    pub fn is_feasible(&self) -> bool {
+        self.bounds.is_satisfied(self.value)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::assert_almost_eq;
+    use crate::builder::ModelBuilder;
+    use crate::checkpoint::InitialState;
     use crate::model::Model;
-    use crate::node::{Constraint, ConstraintValue};
+    use crate::node::{Constraint, ConstraintValue, NodeIndex};
     use crate::parameters;
     use crate::scenario::ScenarioGroupCollection;
-    use crate::solvers::clp::ClpSolver;
-    use crate::solvers::Solver;
+    use crate::solvers::default_solver;
+    use crate::solvers::{Solver, SolverSettings};
     use crate::state::{EdgeState, NodeState, ParameterState};
     use crate::timestep::Timestepper;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
     use tempdir::TempDir;
 
     fn default_timestepper() -> Timestepper {
@@ -282,7 +830,7 @@ mod tests {
 
     fn default_scenarios() -> ScenarioGroupCollection {
         let mut scenarios = ScenarioGroupCollection::new();
-        scenarios.add_group("test-scenario", 10);
+        scenarios.add_group("test-scenario", 10).unwrap();
         scenarios
     }
 
@@ -329,20 +877,541 @@ mod tests {
         model
     }
 
+    /// Set up `recorder` and save it for each timestep of `timestepper` and scenario of `scenarios`.
+    ///
+    /// Each timestep is solved from the initial state with the parameters resolved to `pstate`, so
+    /// that the recorder's own methods can be checked afterwards.
+    fn save_recorder(
+        model: &Model,
+        timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
+        pstate: &ParameterState,
+        recorder: &mut dyn _Recorder,
+    ) {
+        let mut solver = default_solver();
+        solver.setup(model, &SolverSettings::default()).unwrap();
+        recorder.meta_mut().setup_rows(timestepper);
+        recorder.setup(model, timestepper, scenarios).unwrap();
+
+        let scenario_indices = scenarios.scenario_indices();
+        let states = model.get_initial_state(&scenario_indices);
+        for ts in timestepper.timesteps().iter() {
+            for (scenario_index, state) in scenario_indices.iter().zip(&states) {
+                let next_state = model.solve(solver.as_mut(), ts, scenario_index, state, pstate).unwrap();
+                recorder.save(model, ts, scenario_index, &next_state, pstate).unwrap();
+            }
+        }
+    }
+
     #[test]
     fn test_array2_recorder() {
         let mut model = simple_model();
         let timestepper = default_timestepper();
-        let scenarios = default_scenarios();
-        let mut solver: Box<dyn Solver> = Box::new(ClpSolver::new());
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
 
-        let rec = Array2Recorder::new("test", Metric::NodeOutFlow(0));
+        let rec = Array2Recorder::new("test", Metric::NodeOutFlow(NodeIndex(0)));
 
         let rec = model.add_recorder(Box::new(rec)).unwrap();
-        model.run(timestepper, scenarios, &mut solver).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
 
         let array = rec.data_view2().unwrap();
 
+        assert_eq!(array.shape(), &[15, 10]);
         assert_almost_eq!(array[[0, 0]], 10.0);
     }
+
+    #[test]
+    fn test_recorder_precision() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        let solver = default_solver();
+        // The demand factor of 1.2 is not exactly representable in `f32`.
+        let demand = model.get_parameter_by_name("demand-factor").unwrap().index();
+        model.set_storage_precision(Precision::Single);
+
+        let single = model
+            .add_recorder(Box::new(Array2Recorder::new("single", Metric::ParameterValue(demand))))
+            .unwrap();
+        let double = model
+            .add_recorder(Box::new(Array2Recorder::new("double", Metric::ParameterValue(demand))))
+            .unwrap();
+        model.set_recorder_precision("double", Precision::Double).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let single = single.data_view2().unwrap();
+        let double = double.data_view2().unwrap();
+        assert_eq!(single.shape(), double.shape());
+        assert_ne!(single[[0, 0]], double[[0, 0]]);
+        assert_eq!(single[[0, 0]], double[[0, 0]] as f32 as f64);
+    }
+
+    #[test]
+    fn test_csv_recorder_attributes() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let tmp_dir = TempDir::new("pywr-csv").unwrap();
+        let filename = tmp_dir.path().join("output.csv");
+
+        let rec = csv::CSVRecorder::new("csv", filename.clone());
+        let rec = model.add_recorder(Box::new(rec)).unwrap();
+        rec.set_units("Ml/d");
+        rec.set_attribute("source", "test");
+        rec.set_long_name("Flow, \"daily\"");
+        rec.set_attribute("notes", "first\nsecond");
+
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let contents = std::fs::read_to_string(filename).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "# long_name: \"Flow, \"\"daily\"\"\"");
+        assert_eq!(lines[1], "# notes: first\\nsecond");
+        assert_eq!(lines[2], "# source: test");
+        assert_eq!(lines[3], "# units: Ml/d");
+        assert_eq!(lines[4], "date,scenario,input,link,output");
+        // One row per timestep and scenario
+        assert_eq!(lines.len(), 5 + 15 * 10);
+    }
+
+    #[test]
+    fn test_csv_recorder_scenario_labels() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model
+            .add_labelled_scenario("climate", &["hadgem", "ipsl", "ipsl, high"])
+            .unwrap();
+        let solver = default_solver();
+
+        let tmp_dir = TempDir::new("pywr-csv").unwrap();
+        let filename = tmp_dir.path().join("output.csv");
+
+        let rec = csv::CSVRecorder::new("csv", filename.clone());
+        model.add_recorder(Box::new(rec)).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let contents = std::fs::read_to_string(filename).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert!(lines[1].starts_with("2020-01-01,hadgem,"));
+        assert!(lines[2].starts_with("2020-01-01,ipsl,"));
+        assert!(lines[3].starts_with("2020-01-01,\"ipsl, high\","));
+    }
+
+    #[test]
+    fn test_csv_recorder_scenario_slice() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        model.slice_scenario("test-scenario", 3..7).unwrap();
+        let solver = default_solver();
+
+        let tmp_dir = TempDir::new("pywr-csv").unwrap();
+        let filename = tmp_dir.path().join("output.csv");
+
+        let rec = csv::CSVRecorder::new("csv", filename.clone());
+        model.add_recorder(Box::new(rec)).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let contents = std::fs::read_to_string(filename).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        // Only the four scenarios of the slice are run, and are labelled by their members.
+        assert_eq!(lines.len(), 1 + 15 * 4);
+        assert!(lines[1].starts_with("2020-01-01,3,"));
+        assert!(lines[4].starts_with("2020-01-01,6,"));
+    }
+
+    #[test]
+    fn test_stream_recorder() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = std::thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            BufReader::new(socket)
+                .lines()
+                .map(|l| l.unwrap())
+                .collect::<Vec<String>>()
+        });
+
+        let rec = stream::StreamRecorder::new("stream", &address);
+        model.add_recorder(Box::new(rec)).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let lines = handle.join().unwrap();
+        assert_eq!(lines.len(), 15);
+        assert!(lines[0].starts_with("{\"index\":0,\"date\":\"2020-01-01\",\"values\":{\"input\":[10,"));
+        assert!(lines[0].ends_with(",\"scenarios\":[\"0\",\"1\",\"2\",\"3\",\"4\",\"5\",\"6\",\"7\",\"8\",\"9\"]}"));
+    }
+
+    #[test]
+    fn test_mass_balance_recorder() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let rec = audit::MassBalanceRecorder::new("mass-balance", 1e-6, true);
+        let rec = model.add_recorder(Box::new(rec)).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        assert!(model.get_aggregated_value("mass-balance").unwrap() < 1e-6);
+        assert_eq!(rec.data_view2().unwrap().shape(), &[3, 10]);
+    }
+
+    #[test]
+    fn test_mass_balance_recorder_initial_state() {
+        let mut model = ModelBuilder::new()
+            .storage("reservoir", 100.0)
+            .link("link")
+            .output("output")
+            .connect("reservoir", "link")
+            .connect("link", "output")
+            .constant("demand", 10.0)
+            .max_flow("output", "demand")
+            .constant("demand-cost", -10.0)
+            .cost("output", "demand-cost")
+            .constant("max-volume", 100.0)
+            .max_volume("reservoir", "max-volume")
+            .build()
+            .unwrap();
+        // Start from a lower volume than the reservoir's initial volume, and only save some of the
+        // timesteps after the warm-up; the volume changes between the saved timesteps.
+        model.set_initial_state(Some(InitialState::Volumes(vec![(
+            String::new(),
+            vec![("reservoir".to_string(), 80.0)],
+        )])));
+        let timestepper = default_timestepper().with_warm_up(NaiveDate::from_ymd_opt(2020, 1, 6).unwrap());
+        let solver = default_solver();
+
+        let rec = model
+            .add_recorder(Box::new(audit::MassBalanceRecorder::new("mass-balance", 1e-6, true)))
+            .unwrap();
+        rec.set_frequency(RecorderFrequency::every(3));
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        assert!(model.get_aggregated_value("mass-balance").unwrap() < 1e-6);
+    }
+
+    #[test]
+    fn test_mass_balance_recorder_max_violations() {
+        let model = simple_model();
+        let timestepper = default_timestepper();
+        let scenarios = default_scenarios();
+
+        // With a negative tolerance every audited node is a violation in every timestep.
+        let mut rec = audit::MassBalanceRecorder::new("mass-balance", -1.0, false).with_max_violations(5);
+        // Resolved values of: inflow, base-demand, demand-factor, total-demand, demand-cost
+        let pstate = ParameterState::from(vec![10.0, 10.0, 1.2, 12.0, -10.0]);
+        save_recorder(&model, &timestepper, &scenarios, &pstate, &mut rec);
+
+        // Only the link node is audited.
+        assert_eq!(rec.num_violations(), 15 * 10);
+        assert_eq!(rec.worst_offenders(10).len(), 5);
+        assert!(rec.finalise().unwrap().unwrap() < 1e-6);
+    }
+
+    #[test]
+    fn test_operating_cost_recorder() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let rec = cost::OperatingCostRecorder::new("cost");
+        let rec = model.add_recorder(Box::new(rec)).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        // Only the output node has a cost; 10.0 flow at -10.0 cost for 15 days.
+        let totals = rec.data_view2().unwrap();
+        assert_almost_eq!(totals[[0, 0]], 0.0);
+        assert_almost_eq!(totals[[2, 0]], -1500.0);
+        assert_almost_eq!(model.get_aggregated_value("cost").unwrap(), -1500.0);
+    }
+
+    #[test]
+    fn test_operating_cost_annual_totals() {
+        let model = simple_model();
+        let timestepper = Timestepper::new("2020-12-30", "2021-01-02", "%Y-%m-%d", 1).unwrap();
+        let scenarios = default_scenarios();
+
+        let mut rec = cost::OperatingCostRecorder::new("cost");
+        // Resolved values of: inflow, base-demand, demand-factor, total-demand, demand-cost
+        let pstate = ParameterState::from(vec![10.0, 10.0, 1.2, 12.0, -10.0]);
+        save_recorder(&model, &timestepper, &scenarios, &pstate, &mut rec);
+
+        let annual = rec.annual_totals();
+        assert_eq!(annual.len(), 2);
+        assert_almost_eq!(annual[&2020][0], -200.0);
+        assert_almost_eq!(annual[&2021][0], -200.0);
+        assert_almost_eq!(rec.finalise().unwrap().unwrap(), -400.0);
+    }
+
+    #[test]
+    fn test_energy_recorder() {
+        let mut model = simple_model();
+        let timestepper = Timestepper::new("2020-12-30", "2021-01-02", "%Y-%m-%d", 1).unwrap();
+        let scenarios = default_scenarios();
+
+        let link_node = model.get_node_by_name("link").unwrap();
+        let pump_energy = parameters::ConstantParameter::new("pump-energy", 0.5);
+        let pump_energy_idx = model.add_parameter(Box::new(pump_energy)).unwrap().index();
+        let emission_factor = parameters::ConstantParameter::new("emission-factor", 0.2);
+        let emission_factor_idx = model.add_parameter(Box::new(emission_factor)).unwrap().index();
+
+        let components = vec![(Metric::NodeOutFlow(link_node.index()), pump_energy_idx)];
+        let mut rec = energy::EnergyRecorder::new("energy", components, emission_factor_idx);
+        // Resolved values of: inflow, base-demand, demand-factor, total-demand, demand-cost,
+        // pump-energy, emission-factor
+        let pstate = ParameterState::from(vec![10.0, 10.0, 1.2, 12.0, -10.0, 0.5, 0.2]);
+        save_recorder(&model, &timestepper, &scenarios, &pstate, &mut rec);
+
+        // 10.0 flow through the link at 0.5 energy per unit flow.
+        let energy = rec.data_view2().unwrap();
+        assert_eq!(energy.shape(), &[4, 10]);
+        assert_almost_eq!(energy[[0, 0]], 5.0);
+        assert_almost_eq!(rec.carbon().unwrap()[[3, 9]], 1.0);
+
+        let annual = rec.annual_totals();
+        assert_almost_eq!(annual[&2020].energy[0], 10.0);
+        assert_almost_eq!(annual[&2021].carbon[0], 2.0);
+        assert_almost_eq!(rec.finalise().unwrap().unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_array2_recorder_frequency() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let rec = Array2Recorder::new("test", Metric::NodeOutFlow(NodeIndex(0)));
+        let rec = model.add_recorder(Box::new(rec)).unwrap();
+        rec.set_frequency(RecorderFrequency {
+            interval: 2,
+            warm_up: 5,
+            ..Default::default()
+        });
+
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        let array = rec.data_view2().unwrap();
+        // Timesteps 5, 7, 9, 11 & 13 are saved.
+        assert_eq!(array.shape(), &[5, 10]);
+    }
+
+    #[test]
+    fn test_recorders_exclude_warm_up() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper().with_warm_up(NaiveDate::from_ymd_opt(2020, 1, 6).unwrap());
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let rec = Array2Recorder::new("test", Metric::NodeOutFlow(NodeIndex(0)));
+        let rec = model.add_recorder(Box::new(rec)).unwrap();
+        let total = AggregatedRecorder::new(
+            "total",
+            Metric::NodeInFlow(NodeIndex(2)),
+            RecorderAggregation::Sum,
+            RecorderAggregation::Mean,
+        );
+        model.add_recorder(Box::new(total)).unwrap();
+
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        // The first five days are simulated but not saved.
+        assert_eq!(rec.data_view2().unwrap().shape(), &[10, 10]);
+        assert_almost_eq!(model.get_aggregated_value("total").unwrap(), 100.0);
+    }
+
+    #[test]
+    fn test_recorder_frequency() {
+        let frequency = RecorderFrequency {
+            interval: 7,
+            start: Some(NaiveDate::from_ymd(2020, 1, 10)),
+            end: Some(NaiveDate::from_ymd(2020, 2, 10)),
+            warm_up: 0,
+        };
+
+        let timesteps = default_timestepper().timesteps();
+        let included: Vec<usize> = timesteps
+            .iter()
+            .filter(|ts| frequency.includes(ts))
+            .map(|ts| ts.index)
+            .collect();
+
+        assert_eq!(included, vec![14]);
+    }
+
+    #[test]
+    fn test_aggregated_recorder() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 10).unwrap();
+        let solver = default_solver();
+
+        let rec = AggregatedRecorder::new(
+            "test",
+            Metric::NodeOutFlow(NodeIndex(0)),
+            RecorderAggregation::Sum,
+            RecorderAggregation::Mean,
+        );
+
+        model.add_recorder(Box::new(rec)).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+
+        assert_almost_eq!(model.get_aggregated_value("test").unwrap(), 150.0);
+    }
+
+    #[test]
+    fn test_recorder_aggregation() {
+        let values = array![1.0, 0.0, 4.0, 3.0, 2.0];
+
+        assert_almost_eq!(RecorderAggregation::Min.aggregate(values.view()), 0.0);
+        assert_almost_eq!(RecorderAggregation::Max.aggregate(values.view()), 4.0);
+        assert_almost_eq!(RecorderAggregation::Mean.aggregate(values.view()), 2.0);
+        assert_almost_eq!(RecorderAggregation::Median.aggregate(values.view()), 2.0);
+        assert_almost_eq!(RecorderAggregation::Sum.aggregate(values.view()), 10.0);
+        assert_almost_eq!(RecorderAggregation::Quantile(0.25).aggregate(values.view()), 1.0);
+        assert_almost_eq!(RecorderAggregation::CountNonZero.aggregate(values.view()), 4.0);
+        assert_almost_eq!(
+            RecorderAggregation::CountAboveThreshold(2.5).aggregate(values.view()),
+            2.0
+        );
+        assert_almost_eq!(
+            RecorderAggregation::ExceedanceProbability(2.5).aggregate(values.view()),
+            0.4
+        );
+
+        let empty = Array1::<f64>::zeros(0);
+        assert!(RecorderAggregation::Min.aggregate(empty.view()).is_nan());
+        assert!(RecorderAggregation::Max.aggregate(empty.view()).is_nan());
+    }
+
+    #[test]
+    fn test_weighted_recorder_aggregation() {
+        let values = array![1.0, 0.0, 4.0, 3.0, 2.0];
+
+        // Equal weights give the unweighted results.
+        let equal = Array1::from_elem(5, 0.2);
+        for aggregation in &[
+            RecorderAggregation::Min,
+            RecorderAggregation::Max,
+            RecorderAggregation::Mean,
+            RecorderAggregation::Median,
+            RecorderAggregation::Sum,
+            RecorderAggregation::Quantile(0.3),
+            RecorderAggregation::CountNonZero,
+            RecorderAggregation::ExceedanceProbability(2.5),
+        ] {
+            assert_almost_eq!(
+                aggregation.aggregate_weighted(values.view(), equal.view()),
+                aggregation.aggregate(values.view())
+            );
+        }
+
+        let weights = array![1.0, 0.0, 1.0, 1.0, 2.0];
+        assert_almost_eq!(
+            RecorderAggregation::Min.aggregate_weighted(values.view(), weights.view()),
+            1.0
+        );
+        assert_almost_eq!(
+            RecorderAggregation::Mean.aggregate_weighted(values.view(), weights.view()),
+            2.4
+        );
+        assert_almost_eq!(
+            RecorderAggregation::ExceedanceProbability(2.5).aggregate_weighted(values.view(), weights.view()),
+            0.4
+        );
+        assert_almost_eq!(
+            RecorderAggregation::Median.aggregate_weighted(values.view(), weights.view()),
+            7.0 / 3.0
+        );
+
+        // Without any weight there is nothing to aggregate.
+        let zero = Array1::<f64>::zeros(5);
+        assert!(RecorderAggregation::Min
+            .aggregate_weighted(values.view(), zero.view())
+            .is_nan());
+        assert!(RecorderAggregation::Mean
+            .aggregate_weighted(values.view(), zero.view())
+            .is_nan());
+    }
+
+    #[test]
+    fn test_aggregated_recorder_scenario_weights() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 2).unwrap();
+        model.set_scenario_weights("test-scenario", vec![3.0, 1.0]).unwrap();
+        let solver = default_solver();
+
+        let rec = AggregatedRecorder::new(
+            "aggregated",
+            Metric::NodeInFlow(NodeIndex(2)),
+            RecorderAggregation::Mean,
+            RecorderAggregation::Mean,
+        );
+        model.add_recorder(Box::new(rec)).unwrap();
+        model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap();
+        assert_almost_eq!(model.get_aggregated_value("aggregated").unwrap(), 10.0);
+
+        assert_eq!(
+            model.set_scenario_weights("test-scenario", vec![1.0, -1.0]),
+            Err(PywrError::InvalidScenarioWeights("test-scenario".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_aggregated_recorder_zero_scenario_weight() {
+        let mut model = simple_model();
+        let timestepper = default_timestepper();
+        model.add_scenario("test-scenario", 2).unwrap();
+        model.set_scenario_weights("test-scenario", vec![0.0, 1.0]).unwrap();
+        model.slice_scenario("test-scenario", 0..1).unwrap();
+        let solver = default_solver();
+
+        let rec = AggregatedRecorder::new(
+            "aggregated",
+            Metric::NodeInFlow(NodeIndex(2)),
+            RecorderAggregation::Mean,
+            RecorderAggregation::Mean,
+        );
+        model.add_recorder(Box::new(rec)).unwrap();
+        let error = model
+            .run(timestepper, solver.as_ref(), &SolverSettings::default())
+            .unwrap_err();
+        assert_eq!(error.root(), &PywrError::ZeroScenarioWeight);
+    }
 }
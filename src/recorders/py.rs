@@ -1,22 +1,27 @@
 use super::{NetworkState, PywrError, RecorderMeta, Timestep, _Recorder};
 use crate::metric::Metric;
+use crate::model::Model;
 use crate::scenario::ScenarioIndex;
 use pyo3::prelude::*;
-use pyo3::types::PyTuple;
 
+/// A recorder which passes the values of its metrics to a Python object.
+///
+/// The object's `save(timestep, scenario_index, values)` method is called for every timestep and
+/// scenario. If the object defines a `finalise()` method it is called at the end of the run; a
+/// numeric return value is used as the recorder's aggregated value.
 #[derive(Clone, Debug)]
 pub struct PyRecorder {
     meta: RecorderMeta,
     object: PyObject,
-    metric: Metric,
+    metrics: Vec<Metric>,
 }
 
 impl PyRecorder {
-    pub fn new(name: &str, obj: PyObject, metric: Metric) -> Self {
+    pub fn new(name: &str, obj: PyObject, metrics: Vec<Metric>) -> Self {
         Self {
             meta: RecorderMeta::new(name),
             object: obj,
-            metric,
+            metrics,
         }
     }
 }
@@ -25,21 +30,50 @@ impl _Recorder for PyRecorder {
     fn meta(&self) -> &RecorderMeta {
         &self.meta
     }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
 
     fn save(
         &mut self,
+        model: &Model,
         timestep: &Timestep,
-        _scenario_index: &ScenarioIndex,
+        scenario_index: &ScenarioIndex,
         network_state: &NetworkState,
         parameter_state: &[f64],
     ) -> Result<(), PywrError> {
+        let values = self
+            .metrics
+            .iter()
+            .map(|m| m.get_value(model, network_state, parameter_state))
+            .collect::<Result<Vec<f64>, PywrError>>()?;
+
         let gil = Python::acquire_gil();
         let py = gil.python();
 
-        let args = (*timestep, self.metric.get_value(network_state, parameter_state)?);
+        let args = (*timestep, scenario_index.index, values);
         match self.object.call_method1(py, "save", args) {
             Ok(_) => Ok(()),
             Err(e) => Err(PywrError::PythonError(e.to_string())),
         }
     }
+
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        match self.object.as_ref(py).hasattr("finalise") {
+            Ok(true) => {}
+            Ok(false) => return Ok(None),
+            Err(e) => return Err(PywrError::PythonError(e.to_string())),
+        }
+
+        match self.object.call_method0(py, "finalise") {
+            Ok(py_value) => match py_value.extract(py) {
+                Ok(v) => Ok(v),
+                Err(e) => Err(PywrError::PythonError(e.to_string())),
+            },
+            Err(e) => Err(PywrError::PythonError(e.to_string())),
+        }
+    }
 }
@@ -0,0 +1,129 @@
+use super::{NetworkState, PywrError, RecorderMeta, Timestep, _Recorder};
+use crate::json::{json_number, json_string};
+use crate::metric::Metric;
+use crate::model::Model;
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::timestep::Timestepper;
+use ndarray::Array2;
+use std::io::{BufWriter, Write};
+use std::net::TcpStream;
+
+/// Stream the default metric of every node to a TCP socket.
+///
+/// Once all scenarios of a timestep are saved a single line of JSON is written to the socket
+/// containing the timestep, an array of values (one per scenario) for each node, and the labels of
+/// the scenarios. This allows another process to consume the results while the run is in progress.
+pub(crate) struct StreamRecorder {
+    meta: RecorderMeta,
+    address: String,
+    stream: Option<BufWriter<TcpStream>>,
+    metrics: Vec<(String, Metric)>,
+    scenario_labels: Vec<String>,
+    array: Option<Array2<f64>>,
+}
+
+impl StreamRecorder {
+    pub fn new(name: &str, address: &str) -> Self {
+        Self {
+            meta: RecorderMeta::new(name),
+            address: address.to_string(),
+            stream: None,
+            metrics: Vec::new(),
+            scenario_labels: Vec::new(),
+            array: None,
+        }
+    }
+
+    /// Format the values saved for the timestep as a line of JSON.
+    fn to_json_line(&self, timestep: &Timestep, array: &Array2<f64>) -> String {
+        let values: Vec<String> = self
+            .metrics
+            .iter()
+            .zip(array.outer_iter())
+            .map(|((name, _metric), row)| {
+                let row: Vec<String> = row.iter().map(|v| json_number(*v)).collect();
+                format!("{}:[{}]", json_string(name), row.join(","))
+            })
+            .collect();
+        let scenarios: Vec<String> = self.scenario_labels.iter().map(|l| json_string(l)).collect();
+
+        format!(
+            "{{\"index\":{},\"date\":\"{}\",\"values\":{{{}}},\"scenarios\":[{}]}}",
+            timestep.index,
+            timestep.label(),
+            values.join(","),
+            scenarios.join(",")
+        )
+    }
+}
+
+impl _Recorder for StreamRecorder {
+    fn meta(&self) -> &RecorderMeta {
+        &self.meta
+    }
+    fn meta_mut(&mut self) -> &mut RecorderMeta {
+        &mut self.meta
+    }
+    fn setup(
+        &mut self,
+        model: &Model,
+        _timestepper: &Timestepper,
+        scenarios: &ScenarioGroupCollection,
+    ) -> Result<(), PywrError> {
+        let stream = match TcpStream::connect(&self.address) {
+            Ok(s) => s,
+            Err(e) => return Err(PywrError::StreamError(e.to_string())),
+        };
+
+        self.metrics = model.nodes.iter().map(|n| (n.name(), n.default_metric())).collect();
+        self.scenario_labels = scenarios.scenario_indices().iter().map(|s| s.label()).collect();
+        self.array = Some(Array2::zeros((self.metrics.len(), self.scenario_labels.len())));
+        self.stream = Some(BufWriter::new(stream));
+
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        model: &Model,
+        _timestep: &Timestep,
+        scenario_index: &ScenarioIndex,
+        network_state: &NetworkState,
+        parameter_state: &[f64],
+    ) -> Result<(), PywrError> {
+        match &mut self.array {
+            Some(array) => {
+                for (idx, (_name, metric)) in self.metrics.iter().enumerate() {
+                    array[[idx, scenario_index.index]] = metric.get_value(model, network_state, parameter_state)?;
+                }
+                Ok(())
+            }
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+
+    fn after_save(&mut self, timestep: &Timestep) -> Result<(), PywrError> {
+        let line = match &self.array {
+            Some(array) => self.to_json_line(timestep, array),
+            None => return Err(PywrError::RecorderNotInitialised),
+        };
+
+        match &mut self.stream {
+            Some(stream) => match writeln!(stream, "{}", line).and_then(|_| stream.flush()) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(PywrError::StreamError(e.to_string())),
+            },
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+
+    fn finalise(&mut self) -> Result<Option<f64>, PywrError> {
+        match self.stream.take() {
+            Some(mut stream) => match stream.flush() {
+                Ok(_) => Ok(None),
+                Err(e) => Err(PywrError::StreamError(e.to_string())),
+            },
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::scenario::ScenarioIndex;
+use crate::timestep::Timestep;
+use crate::PywrError;
+use std::str::FromStr;
+
+/// A relaxation of a class of constraints, applied when a timestep fails to solve.
+///
+/// The relaxations set with `Model::set_infeasibility_relaxations` are applied cumulatively in
+/// priority order; the first is applied alone, then the first and second together, and so on
+/// until the problem solves. The coefficients of mutually exclusive nodes are not relaxed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Relaxation {
+    /// Remove the minimum flows (e.g. minimum residual flows) of input, link and output nodes.
+    MinFlows,
+    /// Remove the maximum flows of input, link and output nodes.
+    MaxFlows,
+    /// Allow storage nodes to be drawn down below their minimum volume, to empty.
+    MinVolumes,
+    /// Allow storage nodes to fill above their maximum volume.
+    MaxVolumes,
+}
+
+impl Relaxation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MinFlows => "min_flows",
+            Self::MaxFlows => "max_flows",
+            Self::MinVolumes => "min_volumes",
+            Self::MaxVolumes => "max_volumes",
+        }
+    }
+}
+
+impl FromStr for Relaxation {
+    type Err = PywrError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "min_flows" => Ok(Self::MinFlows),
+            "max_flows" => Ok(Self::MaxFlows),
+            "min_volumes" => Ok(Self::MinVolumes),
+            "max_volumes" => Ok(Self::MaxVolumes),
+            _ => Err(PywrError::InvalidRelaxation(name.to_string())),
+        }
+    }
+}
+
+/// A timestep of a scenario that was only solved after relaxing some of its constraints.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelaxedSolve {
+    pub timestep_index: usize,
+    pub scenario_index: usize,
+    /// The relaxations that were applied, in the order they were applied.
+    pub relaxations: Vec<Relaxation>,
+}
+
+impl RelaxedSolve {
+    pub(crate) fn new(timestep: &Timestep, scenario_index: &ScenarioIndex, relaxations: &[Relaxation]) -> Self {
+        Self {
+            timestep_index: timestep.index,
+            scenario_index: scenario_index.index,
+            relaxations: relaxations.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relaxation_names() {
+        for relaxation in &[
+            Relaxation::MinFlows,
+            Relaxation::MaxFlows,
+            Relaxation::MinVolumes,
+            Relaxation::MaxVolumes,
+        ] {
+            assert_eq!(Relaxation::from_str(relaxation.name()), Ok(*relaxation));
+        }
+        assert_eq!(
+            Relaxation::from_str("volumes"),
+            Err(PywrError::InvalidRelaxation("volumes".to_string()))
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::recorders::Recorder;
+use crate::solvers::SolverStats;
+use crate::PywrError;
+use std::fmt;
+use std::time::Duration;
+
+/// The time spent in each phase of a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunTimings {
+    /// Setting up the recorders and solvers.
+    pub setup: Duration,
+    /// Simulating the timesteps, including saving the recorders.
+    pub simulation: Duration,
+    /// Saving the recorders after each timestep.
+    pub recorders: Duration,
+    /// Finalising the recorders at the end of the run.
+    pub finalise: Duration,
+}
+
+/// Statistics of a completed run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunStatistics {
+    /// The number of timesteps simulated, which is fewer than those of the timestepper if the run
+    /// was finished early or resumed from a checkpoint.
+    pub timesteps: usize,
+    pub scenarios: usize,
+    pub wall_time: Duration,
+    pub timings: RunTimings,
+    pub solver_stats: SolverStats,
+    /// True if the run was cancelled through its `RunHandle` before its last timestep.
+    pub cancelled: bool,
+}
+
+impl RunStatistics {
+    /// The number of timesteps (summed over all scenarios) simulated per second.
+    pub fn speed(&self) -> f64 {
+        (self.timesteps * self.scenarios) as f64 / self.timings.simulation.as_secs_f64()
+    }
+}
+
+/// The results of a completed run: its statistics and the recorders holding its outputs.
+#[derive(Clone)]
+pub struct RunResults {
+    pub statistics: RunStatistics,
+    pub(crate) recorders: Vec<Recorder>,
+    pub(crate) aggregated_values: Vec<Option<f64>>,
+}
+
+impl fmt::Debug for RunResults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.recorders.iter().map(|r| r.name()).collect();
+        f.debug_struct("RunResults")
+            .field("statistics", &self.statistics)
+            .field("recorders", &names)
+            .field("aggregated_values", &self.aggregated_values)
+            .finish()
+    }
+}
+
+impl RunResults {
+    pub fn recorders(&self) -> &[Recorder] {
+        &self.recorders
+    }
+
+    pub fn get_recorder_by_name(&self, name: &str) -> Result<&Recorder, PywrError> {
+        self.recorders
+            .iter()
+            .find(|r| r.name() == name)
+            .ok_or_else(|| PywrError::RecorderNotFound(name.to_string()))
+    }
+
+    /// The aggregated value of the recorder `name`.
+    pub fn get_aggregated_value(&self, name: &str) -> Result<f64, PywrError> {
+        let recorder = self.get_recorder_by_name(name)?;
+        match self.aggregated_values.get(*recorder.index()) {
+            Some(Some(value)) => Ok(*value),
+            Some(None) => Err(PywrError::NotSupportedByRecorder),
+            None => Err(PywrError::RecorderNotInitialised),
+        }
+    }
+}
@@ -1,9 +1,17 @@
+use crate::schema::ScenarioSchema;
+use crate::PywrError;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
 #[derive(Clone, Debug)]
 pub struct ScenarioGroup {
     name: String,
     size: usize,
-    // TODO labels
-    // labels: Option<Vec<String>>
+    labels: Option<Vec<String>>,
+    /// The relative likelihood of each member, if they are not equally likely.
+    weights: Option<Vec<f64>>,
+    /// The members of the group that are run.
+    members: Range<usize>,
 }
 
 impl ScenarioGroup {
@@ -11,13 +19,80 @@ impl ScenarioGroup {
         Self {
             name: name.to_string(),
             size,
+            labels: None,
+            weights: None,
+            members: 0..size,
         }
     }
+
+    fn new_labelled(name: &str, labels: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            size: labels.len(),
+            labels: Some(labels.iter().map(|l| l.to_string()).collect()),
+            weights: None,
+            members: 0..labels.len(),
+        }
+    }
+
+    /// The label of member `index`, which is its index if the group is not labelled.
+    fn label(&self, index: usize) -> String {
+        match &self.labels {
+            Some(labels) => labels[index].clone(),
+            None => index.to_string(),
+        }
+    }
+
+    /// Whether only some of the members of the group are run.
+    fn is_sliced(&self) -> bool {
+        self.members != (0..self.size)
+    }
+}
+
+/// A strategy for sampling the scenarios that are run, for uncertainty spaces too large to run
+/// every combination of the groups.
+///
+/// The samples are reproducible for a given `seed`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScenarioSampling {
+    /// Run `size` of the combinations chosen at random, without replacement.
+    Random { size: usize, seed: u64 },
+    /// Run `size` combinations chosen by Latin hypercube sampling of the members of each group.
+    ///
+    /// Each group's members are sampled evenly; if `size` is larger than a group its members are
+    /// used more than once.
+    LatinHypercube { size: usize, seed: u64 },
+}
+
+/// A seeded SplitMix64 generator, so that samples do not depend on an external crate's algorithm.
+struct SampleRng(u64);
+
+impl SampleRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A random integer in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A random number in `[0, 1)`.
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ScenarioGroupCollection {
     groups: Vec<ScenarioGroup>,
+    /// The member of each group in each scenario, if not the full cross-product of the groups.
+    combinations: Option<Vec<Vec<usize>>>,
+    sampling: Option<ScenarioSampling>,
     next_index: Option<ScenarioIndex>,
 }
 
@@ -25,44 +100,360 @@ impl ScenarioGroupCollection {
     pub(crate) fn new() -> Self {
         Self {
             groups: Vec::new(),
+            combinations: None,
+            sampling: None,
             next_index: None,
         }
     }
 
     /// Add a `ScenarioGroup` to the collection
-    pub(crate) fn add_group(&mut self, name: &str, size: usize) {
+    pub(crate) fn add_group(&mut self, name: &str, size: usize) -> Result<(), PywrError> {
         // TODO error with duplicate names
+        if size == 0 {
+            return Err(PywrError::EmptyScenarioGroup(name.to_string()));
+        }
         self.groups.push(ScenarioGroup::new(name, size));
+        Ok(())
     }
 
-    /// Return a vector of `ScenarioIndex`s for all combinations of the groups.
-    pub(crate) fn scenario_indices(&self) -> Vec<ScenarioIndex> {
-        let num: usize = self.groups.iter().map(|grp| grp.size).product();
-        let mut scenario_indices: Vec<ScenarioIndex> = Vec::with_capacity(num);
-
-        for scenario_id in 0..num {
-            let mut remaining = scenario_id;
-            let mut indices: Vec<usize> = Vec::with_capacity(self.groups.len());
-            for grp in self.groups.iter().rev() {
-                let idx = remaining % grp.size;
-                remaining /= grp.size;
-                indices.push(idx);
+    /// Add a `ScenarioGroup` with a label for each member (e.g. the name of a climate model).
+    pub(crate) fn add_labelled_group(&mut self, name: &str, labels: &[&str]) -> Result<(), PywrError> {
+        if labels.is_empty() {
+            return Err(PywrError::EmptyScenarioGroup(name.to_string()));
+        }
+        self.groups.push(ScenarioGroup::new_labelled(name, labels));
+        Ok(())
+    }
+
+    /// The position and definition of the group called `name`.
+    fn group(&self, name: &str) -> Result<(usize, &ScenarioGroup), PywrError> {
+        match self.groups.iter().enumerate().find(|(_, grp)| grp.name == name) {
+            Some(found) => Ok(found),
+            None => Err(PywrError::ScenarioGroupNotFound(name.to_string())),
+        }
+    }
+
+    /// The groups as schemas; the slices, weights, combinations and sampling of a run are not
+    /// included.
+    pub(crate) fn schema(&self) -> Vec<ScenarioSchema> {
+        self.groups
+            .iter()
+            .map(|g| ScenarioSchema {
+                name: g.name.clone(),
+                size: g.size,
+                labels: g.labels.clone(),
+            })
+            .collect()
+    }
+
+    /// The number of members of the group called `name`.
+    pub(crate) fn group_size(&self, name: &str) -> Result<usize, PywrError> {
+        let (_, group) = self.group(name)?;
+        Ok(group.size)
+    }
+
+    /// Run only `members` of the group called `name`, e.g. to re-run part of an ensemble.
+    ///
+    /// The `index` of each scenario is its position in the run, while its `indices` and labels
+    /// refer to the members of the full groups.
+    pub(crate) fn slice(&mut self, name: &str, members: Range<usize>) -> Result<(), PywrError> {
+        let group = match self.groups.iter_mut().find(|grp| grp.name == name) {
+            Some(grp) => grp,
+            None => return Err(PywrError::ScenarioGroupNotFound(name.to_string())),
+        };
+        if members.start >= members.end || members.end > group.size {
+            return Err(PywrError::InvalidScenarioSlice {
+                group: name.to_string(),
+                start: members.start,
+                end: members.end,
+            });
+        }
+        group.members = members;
+        Ok(())
+    }
+
+    /// Set the relative likelihood of each member of the group called `name`.
+    ///
+    /// The weight of a scenario is the product of the weights of its members, with the members of
+    /// unweighted groups equally likely. The weights need not sum to one.
+    pub(crate) fn set_weights(&mut self, name: &str, weights: Vec<f64>) -> Result<(), PywrError> {
+        let group = match self.groups.iter_mut().find(|grp| grp.name == name) {
+            Some(grp) => grp,
+            None => return Err(PywrError::ScenarioGroupNotFound(name.to_string())),
+        };
+        let total: f64 = weights.iter().sum();
+        if weights.len() != group.size || weights.iter().any(|w| *w < 0.0 || !w.is_finite()) || total <= 0.0 {
+            return Err(PywrError::InvalidScenarioWeights(name.to_string()));
+        }
+        group.weights = Some(weights);
+        Ok(())
+    }
+
+    /// The weight of each scenario in `scenario_indices`, or `None` if no groups are weighted.
+    pub fn scenario_weights(&self) -> Option<Vec<f64>> {
+        if self.groups.iter().all(|grp| grp.weights.is_none()) {
+            return None;
+        }
+        let weights = self
+            .scenario_indices()
+            .iter()
+            .map(|scenario_index| {
+                self.groups
+                    .iter()
+                    .zip(&scenario_index.indices)
+                    .map(|(grp, &idx)| grp.weights.as_ref().map_or(1.0, |w| w[idx]))
+                    .product()
+            })
+            .collect();
+        Some(weights)
+    }
+
+    /// Run only the given combinations of the groups' members rather than their full cross-product.
+    ///
+    /// Each combination gives the index of a member of every group, in the order the groups were
+    /// added, e.g. to pair each climate scenario with the demand scenario derived from it. Any
+    /// slices of the groups are still applied to these combinations.
+    pub(crate) fn set_combinations(&mut self, combinations: Vec<Vec<usize>>) -> Result<(), PywrError> {
+        if let Some(ScenarioSampling::LatinHypercube { .. }) = self.sampling {
+            return Err(PywrError::IncompatibleScenarioSampling);
+        }
+        if combinations.is_empty() {
+            return Err(PywrError::NoScenarios);
+        }
+        for combination in &combinations {
+            let valid = combination.len() == self.groups.len()
+                && self.groups.iter().zip(combination).all(|(grp, &idx)| idx < grp.size);
+            if !valid {
+                return Err(PywrError::InvalidScenarioCombination(combination.clone()));
+            }
+        }
+        self.combinations = Some(combinations);
+        Ok(())
+    }
+
+    /// Run only a sample of the scenarios.
+    ///
+    /// Random samples are taken from the combinations that would otherwise be run, including any
+    /// slices or explicit combinations. Latin hypercube samples are taken from the members of each
+    /// group within any slices, and can not be used with explicit combinations.
+    pub(crate) fn set_sampling(&mut self, sampling: ScenarioSampling) -> Result<(), PywrError> {
+        if let (ScenarioSampling::LatinHypercube { .. }, Some(_)) = (sampling, &self.combinations) {
+            return Err(PywrError::IncompatibleScenarioSampling);
+        }
+        match sampling {
+            ScenarioSampling::Random { size: 0, .. } | ScenarioSampling::LatinHypercube { size: 0, .. } => {
+                return Err(PywrError::NoScenarios)
             }
-            indices.reverse();
-            scenario_indices.push(ScenarioIndex::new(scenario_id, indices));
+            _ => {}
+        }
+        self.sampling = Some(sampling);
+        Ok(())
+    }
+
+    /// The member of each group in the `scenario_id`-th combination of the groups' members.
+    fn cross_product_combination(&self, scenario_id: usize) -> Vec<usize> {
+        let mut remaining = scenario_id;
+        let mut indices: Vec<usize> = Vec::with_capacity(self.groups.len());
+        for grp in self.groups.iter().rev() {
+            let idx = remaining % grp.members.len();
+            remaining /= grp.members.len();
+            indices.push(grp.members.start + idx);
+        }
+        indices.reverse();
+        indices
+    }
+
+    /// The member of each group in each of the scenarios that are run.
+    fn combinations(&self) -> Vec<Vec<usize>> {
+        if let Some(ScenarioSampling::LatinHypercube { size, seed }) = self.sampling {
+            return self.latin_hypercube(size, seed);
+        }
+
+        let explicit: Option<Vec<&Vec<usize>>> = self.combinations.as_ref().map(|combinations| {
+            combinations
+                .iter()
+                .filter(|c| {
+                    self.groups
+                        .iter()
+                        .zip(c.iter())
+                        .all(|(grp, idx)| grp.members.contains(idx))
+                })
+                .collect()
+        });
+        let num = match &explicit {
+            Some(combinations) => combinations.len(),
+            None => self.groups.iter().map(|grp| grp.members.len()).product(),
+        };
+
+        let scenario_ids = match self.sampling {
+            Some(ScenarioSampling::Random { size, seed }) if size < num => random_sample(num, size, seed),
+            _ => (0..num).collect(),
+        };
+
+        scenario_ids
+            .into_iter()
+            .map(|id| match &explicit {
+                Some(combinations) => combinations[id].clone(),
+                None => self.cross_product_combination(id),
+            })
+            .collect()
+    }
+
+    /// A Latin hypercube sample of `size` combinations of the groups' members.
+    fn latin_hypercube(&self, size: usize, seed: u64) -> Vec<Vec<usize>> {
+        let mut rng = SampleRng(seed);
+        let mut combinations = vec![Vec::with_capacity(self.groups.len()); size];
+        for grp in &self.groups {
+            // One member from each of `size` equal strata of the group, in a random order.
+            let mut strata: Vec<usize> = (0..size).collect();
+            for i in (1..size).rev() {
+                strata.swap(i, rng.below(i + 1));
+            }
+            for (combination, stratum) in combinations.iter_mut().zip(strata) {
+                let position = (stratum as f64 + rng.uniform()) / size as f64;
+                let idx = ((position * grp.members.len() as f64) as usize).min(grp.members.len() - 1);
+                combination.push(grp.members.start + idx);
+            }
+        }
+        combinations
+    }
+
+    /// Return a vector of `ScenarioIndex`s for all of the scenarios that are run.
+    ///
+    /// These are all combinations of the groups unless restricted by `slice`, `set_combinations`
+    /// or `set_sampling`. This may be empty if none of the explicit combinations are within the
+    /// slices of the groups; see `check_scenario_indices`.
+    pub(crate) fn scenario_indices(&self) -> Vec<ScenarioIndex> {
+        let combinations = self.combinations();
+        let labelled = self.combinations.is_some()
+            || self.sampling.is_some()
+            || self.groups.iter().any(|grp| grp.labels.is_some() || grp.is_sliced());
+        let mut scenario_indices: Vec<ScenarioIndex> = Vec::with_capacity(combinations.len());
+
+        for (scenario_id, indices) in combinations.into_iter().enumerate() {
+            let labels = if labelled {
+                Some(
+                    self.groups
+                        .iter()
+                        .zip(&indices)
+                        .map(|(grp, &idx)| grp.label(idx))
+                        .collect(),
+                )
+            } else {
+                None
+            };
+            scenario_indices.push(ScenarioIndex::new(scenario_id, indices, labels));
         }
         scenario_indices
     }
 }
 
+/// Check that there are scenarios to run in `scenario_indices`.
+pub(crate) fn check_scenario_indices(scenario_indices: &[ScenarioIndex]) -> Result<(), PywrError> {
+    match scenario_indices.is_empty() {
+        true => Err(PywrError::NoScenarios),
+        false => Ok(()),
+    }
+}
+
+/// `size` distinct integers chosen at random from `0..num`, in ascending order.
+fn random_sample(num: usize, size: usize, seed: u64) -> Vec<usize> {
+    // Floyd's algorithm, which does not need to enumerate `0..num`.
+    let mut rng = SampleRng(seed);
+    let mut sample = BTreeSet::new();
+    for j in (num - size)..num {
+        let t = rng.below(j + 1);
+        if !sample.insert(t) {
+            sample.insert(j);
+        }
+    }
+    sample.into_iter().collect()
+}
+
+/// The member of a named scenario group in each scenario of a run.
+///
+/// Parameters with data for each member of a scenario group (e.g. a column of inflows per climate
+/// model) use this to select the data for a `ScenarioIndex`. The members are resolved in `setup`
+/// once the scenarios of the run are known, so that `member` is a single lookup during the run.
+#[derive(Clone, Debug)]
+pub struct ScenarioGroupLookup {
+    group: String,
+    members: Vec<usize>,
+}
+
+impl ScenarioGroupLookup {
+    pub fn new(group: &str) -> Self {
+        Self {
+            group: group.to_string(),
+            members: Vec::new(),
+        }
+    }
+
+    /// Resolve the member of the group in each scenario, checking the data has `size` members.
+    pub(crate) fn setup(&mut self, scenarios: &ScenarioGroupCollection, size: usize) -> Result<(), PywrError> {
+        let (_, group) = scenarios.group(&self.group)?;
+        if group.size != size {
+            return Err(PywrError::ScenarioGroupSizeMismatch {
+                group: self.group.clone(),
+                size: group.size,
+                expected: size,
+            });
+        }
+        self.members = scenarios
+            .scenario_indices()
+            .iter()
+            .map(|scenario_index| scenario_index.group_index(scenarios, &self.group))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// The member of the group in the scenario `scenario_index`.
+    pub(crate) fn member(&self, scenario_index: &ScenarioIndex) -> Result<usize, PywrError> {
+        match self.members.get(scenario_index.index) {
+            Some(member) => Ok(*member),
+            None => Err(PywrError::ScenarioIndexNotFound),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ScenarioIndex {
     pub(crate) index: usize,
     pub(crate) indices: Vec<usize>,
+    /// The label of the member of each group, if any of the groups are labelled or not every
+    /// combination of the groups is run.
+    pub(crate) labels: Option<Vec<String>>,
 }
 
 impl ScenarioIndex {
-    pub(crate) fn new(index: usize, indices: Vec<usize>) -> Self {
-        Self { index, indices }
+    pub(crate) fn new(index: usize, indices: Vec<usize>, labels: Option<Vec<String>>) -> Self {
+        Self { index, indices, labels }
+    }
+
+    /// The label used for this scenario in recorder outputs.
+    ///
+    /// This is the labels of its members joined by `/`, or the scenario's index if none of the
+    /// groups are labelled and every combination of the groups is run.
+    pub(crate) fn label(&self) -> String {
+        match &self.labels {
+            Some(labels) => labels.join("/"),
+            None => self.index.to_string(),
+        }
+    }
+
+    /// The member of the group called `name` of `scenarios` in this scenario.
+    pub fn group_index(&self, scenarios: &ScenarioGroupCollection, name: &str) -> Result<usize, PywrError> {
+        let (position, _) = scenarios.group(name)?;
+        match self.indices.get(position) {
+            Some(idx) => Ok(*idx),
+            None => Err(PywrError::ScenarioIndexNotFound),
+        }
+    }
+
+    /// The label of the member of the group called `name` of `scenarios` in this scenario.
+    pub fn group_label(&self, scenarios: &ScenarioGroupCollection, name: &str) -> Result<String, PywrError> {
+        let (_, group) = scenarios.group(name)?;
+        let idx = self.group_index(scenarios, name)?;
+        Ok(group.label(idx))
     }
 }
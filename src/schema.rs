@@ -0,0 +1,1399 @@
+use crate::json::Json;
+use crate::model::Model;
+use crate::node::{Constraint, ConstraintValue, Node};
+use crate::parameters::{self, AggFunc, Parameter, _Parameter};
+use crate::recorders::{self, AggregatedRecorder, RecorderAggregation, _Recorder};
+use crate::timestep::{TimestepDuration, Timestepper};
+use crate::PywrError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A model defined declaratively, as read from a JSON model file by `ModelSchema::read`.
+///
+/// A model file is an object with a `timestepper` and optional `scenarios`, `nodes`, `edges`,
+/// `parameters`, `recorders`, `templates` and `instances` (see `TemplateSchema`):
+///
+/// ```json
+/// {
+///   "timestepper": {"start": "2020-01-01", "end": "2020-12-31", "timestep": 1},
+///   "scenarios": [{"name": "demand", "labels": ["low", "high"]}],
+///   "nodes": [
+///     {"name": "supply", "type": "input", "max_flow": 15},
+///     {"name": "demand", "type": "output", "max_flow": "demand", "cost": -10}
+///   ],
+///   "edges": [{"from": "supply", "to": "demand", "max_flow": 12}],
+///   "parameters": [{"name": "demand", "type": "constant", "value": 12}],
+///   "recorders": [{"name": "outputs", "type": "csv", "path": "outputs.csv"}]
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelSchema {
+    pub timestepper: TimestepperSchema,
+    pub scenarios: Vec<ScenarioSchema>,
+    pub nodes: Vec<NodeSchema>,
+    pub edges: Vec<EdgeSchema>,
+    pub parameters: Vec<ParameterSchema>,
+    pub recorders: Vec<RecorderSchema>,
+    pub templates: Vec<TemplateSchema>,
+    pub instances: Vec<InstanceSchema>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimestepperSchema {
+    /// The first date of the run, as `%Y-%m-%d`.
+    pub start: String,
+    /// The last date of the run, as `%Y-%m-%d`.
+    pub end: String,
+    /// The length of a timestep in days, which is one if it is not given.
+    pub timestep: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenarioSchema {
+    pub name: String,
+    pub size: usize,
+    /// The labels of the members of the scenario group, if they are not to be numbered.
+    pub labels: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeSchema {
+    pub name: String,
+    pub node_type: NodeSchemaType,
+    pub max_flow: Option<ValueSchema>,
+    pub min_flow: Option<ValueSchema>,
+    pub cost: Option<ValueSchema>,
+    pub max_volume: Option<ValueSchema>,
+    pub min_volume: Option<ValueSchema>,
+    /// The initial volume of a storage node.
+    pub initial_volume: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeSchemaType {
+    Input,
+    Output,
+    Link,
+    Storage,
+    /// A type registered with a `Registry`; the other attributes of the node are ignored.
+    Custom(CustomSchema),
+}
+
+/// A component of a type that is not built in, which is built by the constructor registered for
+/// its type name with a `Registry`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomSchema {
+    pub type_name: String,
+    /// The whole definition of the component, including its name and type.
+    pub data: Json,
+}
+
+/// The value of an attribute of a node: a number, or the name of a parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueSchema {
+    Constant(f64),
+    Parameter(String),
+}
+
+impl From<f64> for ValueSchema {
+    fn from(value: f64) -> Self {
+        Self::Constant(value)
+    }
+}
+
+impl From<&str> for ValueSchema {
+    fn from(parameter: &str) -> Self {
+        Self::Parameter(parameter.to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdgeSchema {
+    pub from: String,
+    pub to: String,
+    pub max_flow: Option<ValueSchema>,
+    pub min_flow: Option<ValueSchema>,
+    pub cost: Option<ValueSchema>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterSchema {
+    pub name: String,
+    pub parameter_type: ParameterType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterType {
+    Constant(f64),
+    /// A value for each timestep.
+    Vector(Vec<f64>),
+    /// A value for each day of a leap year.
+    DailyProfile(Vec<f64>),
+    /// The aggregate of the parameters with the given names.
+    Aggregated {
+        agg_func: AggFunc,
+        parameters: Vec<String>,
+    },
+    Custom(CustomSchema),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecorderSchema {
+    pub name: String,
+    pub recorder_type: RecorderType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecorderType {
+    /// Write the flows and volumes of the nodes to a CSV file.
+    Csv {
+        path: String,
+    },
+    /// Write the flows and volumes of the nodes to an HDF5 file.
+    Hdf5 {
+        path: String,
+    },
+    /// Aggregate a metric (e.g. `node_deficit`) of a node or parameter over the timesteps and
+    /// then the scenarios of a run.
+    Aggregated {
+        metric: String,
+        component: String,
+        temporal_agg_func: RecorderAggregation,
+        scenario_agg_func: RecorderAggregation,
+    },
+    Custom(CustomSchema),
+}
+
+/// A sub-network that is repeated in a model, e.g. a water treatment works supplying a demand
+/// through a service reservoir, added to the model once for each `InstanceSchema`.
+///
+/// The nodes and parameters of an instance are named with its prefix, e.g. `north.wtw` for the
+/// node `wtw` of the instance `north`, and are connected to the rest of the model by edges between
+/// these names. The nodes and parameters refer to the `arguments` as if they were parameters, and
+/// each instance gives their values, as a number or the name of a parameter of the model. Any
+/// other names are those of parameters of the model.
+///
+/// ```json
+/// {
+///   "name": "supply_zone",
+///   "arguments": ["capacity", "demand"],
+///   "nodes": [
+///     {"name": "wtw", "type": "link", "max_flow": "capacity"},
+///     {"name": "demand", "type": "output", "max_flow": "demand", "cost": -10}
+///   ],
+///   "edges": [{"from": "wtw", "to": "demand"}]
+/// }
+/// ```
+///
+/// Only the names of custom components are prefixed, not any names in the rest of their definitions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateSchema {
+    pub name: String,
+    pub arguments: Vec<String>,
+    pub nodes: Vec<NodeSchema>,
+    pub edges: Vec<EdgeSchema>,
+    pub parameters: Vec<ParameterSchema>,
+}
+
+/// The nodes, edges and parameters of an instance of a template.
+type Network = (Vec<NodeSchema>, Vec<EdgeSchema>, Vec<ParameterSchema>);
+
+/// An instance of the template called `template`, e.g.
+/// `{"template": "supply_zone", "prefix": "north", "arguments": {"capacity": 10, "demand": "north_demand"}}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceSchema {
+    pub template: String,
+    pub prefix: String,
+    pub arguments: Vec<(String, ValueSchema)>,
+}
+
+/// A schema of a model built in code, from `Model::to_schema`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaExport {
+    pub schema: ModelSchema,
+    /// The parameters, recorders and node attributes that can not be written to a model file, and
+    /// are missing from `schema`, e.g. `parameter `inflow``.
+    pub omitted: Vec<String>,
+}
+
+/// Builds a node of a custom type called `name` from its definition, adding it and any other
+/// nodes it needs to the model. The node must be called `name`, as edges are connected by name.
+pub type NodeConstructor = fn(&mut SchemaBuilder<'_>, &str, &Json) -> Result<Node, PywrError>;
+/// Builds a parameter of a custom type called `name` from its definition.
+pub type ParameterConstructor = fn(&mut SchemaBuilder<'_>, &str, &Json) -> Result<Box<dyn _Parameter>, PywrError>;
+/// Builds a recorder of a custom type called `name` from its definition.
+pub type RecorderConstructor = fn(&mut SchemaBuilder<'_>, &str, &Json) -> Result<Box<dyn _Recorder>, PywrError>;
+
+const NODE_TYPES: [&str; 4] = ["input", "output", "link", "storage"];
+const PARAMETER_TYPES: [&str; 4] = ["constant", "vector", "daily_profile", "aggregated"];
+const RECORDER_TYPES: [&str; 3] = ["csv", "hdf5", "aggregated"];
+
+/// The constructors of the node, parameter and recorder types that are not built in, by type name.
+///
+/// Downstream crates register their own components here so that they can be defined in the same
+/// model files as the built in ones, and then build the model with `ModelSchema::build_with_registry`
+/// or `Model::from_schema_with_registry`.
+#[derive(Default)]
+pub struct Registry {
+    nodes: HashMap<String, NodeConstructor>,
+    parameters: HashMap<String, ParameterConstructor>,
+    recorders: HashMap<String, RecorderConstructor>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_node(&mut self, type_name: &str, constructor: NodeConstructor) -> Result<(), PywrError> {
+        register(&mut self.nodes, &NODE_TYPES, type_name, constructor)
+    }
+
+    pub fn register_parameter(&mut self, type_name: &str, constructor: ParameterConstructor) -> Result<(), PywrError> {
+        register(&mut self.parameters, &PARAMETER_TYPES, type_name, constructor)
+    }
+
+    pub fn register_recorder(&mut self, type_name: &str, constructor: RecorderConstructor) -> Result<(), PywrError> {
+        register(&mut self.recorders, &RECORDER_TYPES, type_name, constructor)
+    }
+}
+
+/// Add `constructor` for `type_name` unless it is a built in type or already registered.
+fn register<T>(
+    constructors: &mut HashMap<String, T>,
+    built_in: &[&str],
+    type_name: &str,
+    constructor: T,
+) -> Result<(), PywrError> {
+    if built_in.contains(&type_name) || constructors.contains_key(type_name) {
+        return Err(PywrError::ComponentTypeAlreadyRegistered(type_name.to_string()));
+    }
+    constructors.insert(type_name.to_string(), constructor);
+    Ok(())
+}
+
+/// The constructor registered for the custom component called `name`.
+fn constructor<T: Copy>(
+    constructors: &HashMap<String, T>,
+    kind: &str,
+    name: &str,
+    custom: &CustomSchema,
+) -> Result<T, PywrError> {
+    constructors.get(&custom.type_name).copied().ok_or_else(|| {
+        invalid(&format!(
+            "unrecognised type `{}` of {} `{}`",
+            custom.type_name, kind, name
+        ))
+    })
+}
+
+impl ModelSchema {
+    pub fn read(path: &Path) -> Result<Self, PywrError> {
+        let contents = fs::read_to_string(path).map_err(|e| PywrError::IOError(e.to_string()))?;
+        Self::from_json(&contents)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, PywrError> {
+        let json = Json::parse(json).map_err(|e| invalid(&e))?;
+        members(&json, "the model")?;
+        Ok(Self {
+            timestepper: TimestepperSchema::from_json(required(&json, "timestepper", "the model")?)?,
+            scenarios: list(&json, "scenarios", ScenarioSchema::from_json)?,
+            nodes: list(&json, "nodes", NodeSchema::from_json)?,
+            edges: list(&json, "edges", EdgeSchema::from_json)?,
+            parameters: list(&json, "parameters", ParameterSchema::from_json)?,
+            recorders: list(&json, "recorders", RecorderSchema::from_json)?,
+            templates: list(&json, "templates", TemplateSchema::from_json)?,
+            instances: list(&json, "instances", InstanceSchema::from_json)?,
+        })
+    }
+
+    /// Write the schema as JSON to `path`, to be read by `read`.
+    pub fn write(&self, path: &Path) -> Result<(), PywrError> {
+        fs::write(path, self.to_json()).map_err(|e| PywrError::IOError(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> String {
+        let list = |items: Vec<Json>| Json::Array(items);
+        let model = object(vec![
+            ("timestepper", self.timestepper.json()),
+            ("scenarios", list(self.scenarios.iter().map(|s| s.json()).collect())),
+            ("nodes", list(self.nodes.iter().map(|n| n.json()).collect())),
+            ("edges", list(self.edges.iter().map(|e| e.json()).collect())),
+            ("parameters", list(self.parameters.iter().map(|p| p.json()).collect())),
+            ("recorders", list(self.recorders.iter().map(|r| r.json()).collect())),
+            ("templates", list(self.templates.iter().map(|t| t.json()).collect())),
+            ("instances", list(self.instances.iter().map(|i| i.json()).collect())),
+        ]);
+        format!("{}\n", model.to_pretty_string())
+    }
+
+    /// Build the model and its timestepper, with the paths of its outputs relative to `directory`.
+    ///
+    /// The parameters may be listed in any order; a parameter may refer to any other as long as
+    /// none refers to itself. The instances of templates are added with the other nodes and
+    /// parameters.
+    pub fn build(&self, directory: &Path) -> Result<(Model, Timestepper), PywrError> {
+        self.build_with_registry(directory, &Registry::new())
+    }
+
+    /// Build the model as `build`, with the components of custom types built by the constructors
+    /// of `registry`.
+    pub fn build_with_registry(
+        &self,
+        directory: &Path,
+        registry: &Registry,
+    ) -> Result<(Model, Timestepper), PywrError> {
+        let schema = self.expanded()?;
+        let mut model = Model::new();
+        let mut builder = SchemaBuilder {
+            parameter_schemas: &schema.parameters,
+            registry,
+            directory,
+            model: &mut model,
+            parameters: HashMap::new(),
+            loading: Vec::new(),
+        };
+
+        for scenario in &schema.scenarios {
+            match &scenario.labels {
+                Some(labels) => {
+                    let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+                    builder.model.add_labelled_scenario(&scenario.name, &labels)?;
+                }
+                None => builder.model.add_scenario(&scenario.name, scenario.size)?,
+            }
+        }
+        builder.add_network(&schema.nodes, &schema.edges)?;
+        for recorder in &schema.recorders {
+            builder.add_recorder(recorder)?;
+        }
+
+        let timestepper = Timestepper::with_duration(
+            &self.timestepper.start,
+            &self.timestepper.end,
+            "%Y-%m-%d",
+            TimestepDuration::Days(self.timestepper.timestep),
+        )?;
+        Ok((model, timestepper))
+    }
+
+    /// The schema with the nodes, edges and parameters of each instance of a template in place of
+    /// the templates and instances.
+    fn expanded(&self) -> Result<ModelSchema, PywrError> {
+        let mut schema = self.clone();
+        schema.templates.clear();
+        schema.instances.clear();
+        for instance in &self.instances {
+            let template = self
+                .templates
+                .iter()
+                .find(|t| t.name == instance.template)
+                .ok_or_else(|| {
+                    invalid(&format!(
+                        "instance `{}` is of an unknown template `{}`",
+                        instance.prefix, instance.template
+                    ))
+                })?;
+            let (nodes, edges, parameters) = template.expand(&instance.prefix, &instance.arguments)?;
+            schema.nodes.extend(nodes);
+            schema.edges.extend(edges);
+            schema.parameters.extend(parameters);
+        }
+        Ok(schema)
+    }
+}
+
+impl TimestepperSchema {
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let owner = "the timestepper";
+        let timestep = match json.get("timestep") {
+            None => 1.0,
+            Some(_) => number(json, "timestep", owner)?,
+        };
+        if timestep < 1.0 || timestep.fract() != 0.0 {
+            return Err(invalid("expected the timestep to be a positive number of days"));
+        }
+        Ok(Self {
+            start: string(json, "start", owner)?.to_string(),
+            end: string(json, "end", owner)?.to_string(),
+            timestep: timestep as i64,
+        })
+    }
+}
+
+impl TimestepperSchema {
+    fn json(&self) -> Json {
+        object(vec![
+            ("start", Json::String(self.start.clone())),
+            ("end", Json::String(self.end.clone())),
+            ("timestep", Json::Number(self.timestep as f64)),
+        ])
+    }
+}
+
+impl ScenarioSchema {
+    fn json(&self) -> Json {
+        match &self.labels {
+            Some(labels) => object(vec![
+                ("name", Json::String(self.name.clone())),
+                (
+                    "labels",
+                    Json::Array(labels.iter().cloned().map(Json::String).collect()),
+                ),
+            ]),
+            None => object(vec![
+                ("name", Json::String(self.name.clone())),
+                ("size", Json::Number(self.size as f64)),
+            ]),
+        }
+    }
+
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let name = string(json, "name", "a scenario")?;
+        let owner = format!("scenario `{}`", name);
+        let labels = match json.get("labels") {
+            Some(_) => Some(strings(json, "labels", &owner)?),
+            None => None,
+        };
+        let size = match &labels {
+            Some(labels) => labels.len(),
+            None => number(json, "size", &owner)? as usize,
+        };
+        Ok(Self {
+            name: name.to_string(),
+            size,
+            labels,
+        })
+    }
+}
+
+impl NodeSchema {
+    fn json(&self) -> Json {
+        let node_type = match &self.node_type {
+            NodeSchemaType::Input => "input",
+            NodeSchemaType::Output => "output",
+            NodeSchemaType::Link => "link",
+            NodeSchemaType::Storage => "storage",
+            NodeSchemaType::Custom(custom) => return custom.data.clone(),
+        };
+        let mut members = vec![
+            ("name", Json::String(self.name.clone())),
+            ("type", Json::String(node_type.to_string())),
+        ];
+        let values = [
+            ("max_flow", &self.max_flow),
+            ("min_flow", &self.min_flow),
+            ("cost", &self.cost),
+            ("max_volume", &self.max_volume),
+            ("min_volume", &self.min_volume),
+        ];
+        for (key, value) in values.iter() {
+            if let Some(value) = value {
+                members.push((key, value_json(value)));
+            }
+        }
+        if self.node_type == NodeSchemaType::Storage {
+            members.push(("initial_volume", Json::Number(self.initial_volume)));
+        }
+        object(members)
+    }
+
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let name = string(json, "name", "a node")?;
+        let owner = format!("node `{}`", name);
+        let node_type = match string(json, "type", &owner)? {
+            "input" => NodeSchemaType::Input,
+            "output" => NodeSchemaType::Output,
+            "link" => NodeSchemaType::Link,
+            "storage" => NodeSchemaType::Storage,
+            kind => NodeSchemaType::Custom(CustomSchema::new(kind, json)),
+        };
+        let value = |key: &str| match json.get(key) {
+            None => Ok(None),
+            Some(_) => value(json, key, &owner).map(Some),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            node_type,
+            max_flow: value("max_flow")?,
+            min_flow: value("min_flow")?,
+            cost: value("cost")?,
+            max_volume: value("max_volume")?,
+            min_volume: value("min_volume")?,
+            initial_volume: match json.get("initial_volume") {
+                Some(_) => number(json, "initial_volume", &owner)?,
+                None => 0.0,
+            },
+        })
+    }
+}
+
+impl EdgeSchema {
+    fn json(&self) -> Json {
+        let mut members = vec![
+            ("from", Json::String(self.from.clone())),
+            ("to", Json::String(self.to.clone())),
+        ];
+        let values = [
+            ("max_flow", &self.max_flow),
+            ("min_flow", &self.min_flow),
+            ("cost", &self.cost),
+        ];
+        for (key, value) in values.iter() {
+            if let Some(value) = value {
+                members.push((key, value_json(value)));
+            }
+        }
+        object(members)
+    }
+
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let from = string(json, "from", "an edge")?;
+        let to = string(json, "to", "an edge")?;
+        let owner = format!("the edge from `{}` to `{}`", from, to);
+        let value = |key: &str| match json.get(key) {
+            None => Ok(None),
+            Some(_) => value(json, key, &owner).map(Some),
+        };
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            max_flow: value("max_flow")?,
+            min_flow: value("min_flow")?,
+            cost: value("cost")?,
+        })
+    }
+}
+
+impl ParameterSchema {
+    fn json(&self) -> Json {
+        let numbers = |values: &[f64]| Json::Array(values.iter().map(|v| Json::Number(*v)).collect());
+        let mut members = vec![("name", Json::String(self.name.clone()))];
+        match &self.parameter_type {
+            ParameterType::Constant(value) => {
+                members.push(("type", Json::String("constant".to_string())));
+                members.push(("value", Json::Number(*value)));
+            }
+            ParameterType::Vector(values) => {
+                members.push(("type", Json::String("vector".to_string())));
+                members.push(("values", numbers(values)));
+            }
+            ParameterType::DailyProfile(values) => {
+                members.push(("type", Json::String("daily_profile".to_string())));
+                members.push(("values", numbers(values)));
+            }
+            ParameterType::Aggregated { agg_func, parameters } => {
+                members.push(("type", Json::String("aggregated".to_string())));
+                members.push(("agg_func", Json::String(agg_func.name().to_string())));
+                let parameters = parameters.iter().cloned().map(Json::String).collect();
+                members.push(("parameters", Json::Array(parameters)));
+            }
+            ParameterType::Custom(custom) => return custom.data.clone(),
+        }
+        object(members)
+    }
+
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let name = string(json, "name", "a parameter")?;
+        let owner = format!("parameter `{}`", name);
+        let parameter_type = match string(json, "type", &owner)? {
+            "constant" => ParameterType::Constant(number(json, "value", &owner)?),
+            "vector" => ParameterType::Vector(numbers(json, "values", &owner)?),
+            "daily_profile" => {
+                let values = numbers(json, "values", &owner)?;
+                if values.len() != 366 {
+                    return Err(invalid(&format!("{} must have 366 values", owner)));
+                }
+                ParameterType::DailyProfile(values)
+            }
+            "aggregated" => ParameterType::Aggregated {
+                agg_func: AggFunc::from_str(string(json, "agg_func", &owner)?)?,
+                parameters: strings(json, "parameters", &owner)?,
+            },
+            kind => ParameterType::Custom(CustomSchema::new(kind, json)),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            parameter_type,
+        })
+    }
+}
+
+impl RecorderSchema {
+    fn json(&self) -> Json {
+        let mut members = vec![("name", Json::String(self.name.clone()))];
+        match &self.recorder_type {
+            RecorderType::Csv { path } => {
+                members.push(("type", Json::String("csv".to_string())));
+                members.push(("path", Json::String(path.clone())));
+            }
+            RecorderType::Hdf5 { path } => {
+                members.push(("type", Json::String("hdf5".to_string())));
+                members.push(("path", Json::String(path.clone())));
+            }
+            RecorderType::Aggregated {
+                metric,
+                component,
+                temporal_agg_func,
+                scenario_agg_func,
+            } => {
+                let aggregation = |a: &RecorderAggregation| Json::String(a.name().unwrap_or_default().to_string());
+                members.push(("type", Json::String("aggregated".to_string())));
+                members.push(("metric", Json::String(metric.clone())));
+                members.push(("component", Json::String(component.clone())));
+                members.push(("temporal_agg_func", aggregation(temporal_agg_func)));
+                members.push(("scenario_agg_func", aggregation(scenario_agg_func)));
+            }
+            RecorderType::Custom(custom) => return custom.data.clone(),
+        }
+        object(members)
+    }
+
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let name = string(json, "name", "a recorder")?;
+        let owner = format!("recorder `{}`", name);
+        let path = || -> Result<String, PywrError> { Ok(string(json, "path", &owner)?.to_string()) };
+        let aggregation = |key: &str| -> Result<RecorderAggregation, PywrError> {
+            match json.get(key) {
+                Some(_) => RecorderAggregation::from_str(string(json, key, &owner)?),
+                None => Ok(RecorderAggregation::Mean),
+            }
+        };
+        let recorder_type = match string(json, "type", &owner)? {
+            "csv" => RecorderType::Csv { path: path()? },
+            "hdf5" => RecorderType::Hdf5 { path: path()? },
+            "aggregated" => RecorderType::Aggregated {
+                metric: string(json, "metric", &owner)?.to_string(),
+                component: string(json, "component", &owner)?.to_string(),
+                temporal_agg_func: aggregation("temporal_agg_func")?,
+                scenario_agg_func: aggregation("scenario_agg_func")?,
+            },
+            kind => RecorderType::Custom(CustomSchema::new(kind, json)),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            recorder_type,
+        })
+    }
+}
+
+impl TemplateSchema {
+    /// Add an instance of the template to a model built in code, with the nodes and parameters
+    /// named with `prefix` and the values of each of the `arguments`. Custom components are not
+    /// supported.
+    ///
+    /// The nodes are then connected to the rest of the model by their prefixed names.
+    pub fn instantiate(
+        &self,
+        model: &mut Model,
+        prefix: &str,
+        arguments: &[(&str, ValueSchema)],
+    ) -> Result<(), PywrError> {
+        let arguments: Vec<(String, ValueSchema)> = arguments.iter().map(|(a, v)| (a.to_string(), v.clone())).collect();
+        let (nodes, edges, parameters) = self.expand(prefix, &arguments)?;
+        let mut builder = SchemaBuilder {
+            parameter_schemas: &parameters,
+            registry: &Registry::new(),
+            directory: Path::new(""),
+            model,
+            parameters: HashMap::new(),
+            loading: Vec::new(),
+        };
+        builder.add_network(&nodes, &edges)
+    }
+
+    /// The nodes, edges and parameters of the instance named `prefix`, with the values of the
+    /// `arguments` in place of their names.
+    fn expand(&self, prefix: &str, arguments: &[(String, ValueSchema)]) -> Result<Network, PywrError> {
+        let owner = format!("instance `{}` of template `{}`", prefix, self.name);
+        if let Some(missing) = self.arguments.iter().find(|a| !arguments.iter().any(|(b, _)| b == *a)) {
+            return Err(invalid(&format!("{} is missing argument `{}`", owner, missing)));
+        }
+        if let Some((unknown, _)) = arguments.iter().find(|(a, _)| !self.arguments.contains(a)) {
+            return Err(invalid(&format!("{} has an unknown argument `{}`", owner, unknown)));
+        }
+        let prefixed = |name: &str| format!("{}.{}", prefix, name);
+        let argument = |name: &str| arguments.iter().find(|(a, _)| a == name).map(|(_, v)| v);
+        let value = |value: &Option<ValueSchema>| match value {
+            Some(ValueSchema::Parameter(name)) => match argument(name) {
+                Some(value) => Some(value.clone()),
+                None if self.parameters.iter().any(|p| &p.name == name) => Some(ValueSchema::Parameter(prefixed(name))),
+                None => Some(ValueSchema::Parameter(name.clone())),
+            },
+            value => value.clone(),
+        };
+
+        let mut parameters = Vec::new();
+        for parameter in &self.parameters {
+            let parameter_type = match &parameter.parameter_type {
+                ParameterType::Aggregated {
+                    agg_func,
+                    parameters: references,
+                } => {
+                    let mut renamed = Vec::with_capacity(references.len());
+                    for name in references {
+                        renamed.push(match argument(name) {
+                            Some(ValueSchema::Parameter(p)) => p.clone(),
+                            // A number is given to the aggregation as a constant parameter.
+                            Some(ValueSchema::Constant(v)) => {
+                                if !parameters.iter().any(|p: &ParameterSchema| p.name == prefixed(name)) {
+                                    parameters.push(ParameterSchema {
+                                        name: prefixed(name),
+                                        parameter_type: ParameterType::Constant(*v),
+                                    });
+                                }
+                                prefixed(name)
+                            }
+                            None if self.parameters.iter().any(|p| &p.name == name) => prefixed(name),
+                            None => name.clone(),
+                        });
+                    }
+                    ParameterType::Aggregated {
+                        agg_func: *agg_func,
+                        parameters: renamed,
+                    }
+                }
+                ParameterType::Custom(custom) => ParameterType::Custom(custom.renamed(&prefixed(&parameter.name))),
+                parameter_type => parameter_type.clone(),
+            };
+            parameters.push(ParameterSchema {
+                name: prefixed(&parameter.name),
+                parameter_type,
+            });
+        }
+
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| NodeSchema {
+                name: prefixed(&node.name),
+                node_type: match &node.node_type {
+                    NodeSchemaType::Custom(custom) => NodeSchemaType::Custom(custom.renamed(&prefixed(&node.name))),
+                    node_type => node_type.clone(),
+                },
+                max_flow: value(&node.max_flow),
+                min_flow: value(&node.min_flow),
+                cost: value(&node.cost),
+                max_volume: value(&node.max_volume),
+                min_volume: value(&node.min_volume),
+                initial_volume: node.initial_volume,
+            })
+            .collect();
+
+        let mut edges = Vec::with_capacity(self.edges.len());
+        for edge in &self.edges {
+            for name in [&edge.from, &edge.to].iter() {
+                if !self.nodes.iter().any(|n| &&n.name == name) {
+                    return Err(invalid(&format!(
+                        "template `{}` has an edge to node `{}`, which is not in the template",
+                        self.name, name
+                    )));
+                }
+            }
+            edges.push(EdgeSchema {
+                from: prefixed(&edge.from),
+                to: prefixed(&edge.to),
+                max_flow: value(&edge.max_flow),
+                min_flow: value(&edge.min_flow),
+                cost: value(&edge.cost),
+            });
+        }
+        Ok((nodes, edges, parameters))
+    }
+
+    fn json(&self) -> Json {
+        let list = |items: Vec<Json>| Json::Array(items);
+        object(vec![
+            ("name", Json::String(self.name.clone())),
+            (
+                "arguments",
+                list(self.arguments.iter().cloned().map(Json::String).collect()),
+            ),
+            ("nodes", list(self.nodes.iter().map(|n| n.json()).collect())),
+            ("edges", list(self.edges.iter().map(|e| e.json()).collect())),
+            ("parameters", list(self.parameters.iter().map(|p| p.json()).collect())),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let name = string(json, "name", "a template")?;
+        let owner = format!("template `{}`", name);
+        Ok(Self {
+            name: name.to_string(),
+            arguments: match json.get("arguments") {
+                Some(_) => strings(json, "arguments", &owner)?,
+                None => Vec::new(),
+            },
+            nodes: list(json, "nodes", NodeSchema::from_json)?,
+            edges: list(json, "edges", EdgeSchema::from_json)?,
+            parameters: list(json, "parameters", ParameterSchema::from_json)?,
+        })
+    }
+}
+
+impl InstanceSchema {
+    fn json(&self) -> Json {
+        let arguments = self.arguments.iter().map(|(a, v)| (a.clone(), value_json(v))).collect();
+        object(vec![
+            ("template", Json::String(self.template.clone())),
+            ("prefix", Json::String(self.prefix.clone())),
+            ("arguments", Json::Object(arguments)),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<Self, PywrError> {
+        let prefix = string(json, "prefix", "an instance")?;
+        let owner = format!("instance `{}`", prefix);
+        let arguments = match json.get("arguments") {
+            Some(arguments) => members(arguments, &format!("the arguments of {}", owner))?
+                .iter()
+                .map(|(key, _)| Ok((key.clone(), value(arguments, key, &owner)?)))
+                .collect::<Result<_, PywrError>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            template: string(json, "template", &owner)?.to_string(),
+            prefix: prefix.to_string(),
+            arguments,
+        })
+    }
+}
+
+impl CustomSchema {
+    /// The component with its name, and the name in its definition, changed to `name`.
+    fn renamed(&self, name: &str) -> Self {
+        let mut data = self.data.clone();
+        if let Json::Object(members) = &mut data {
+            for (key, value) in members.iter_mut() {
+                if key == "name" {
+                    *value = Json::String(name.to_string());
+                }
+            }
+        }
+        Self {
+            type_name: self.type_name.clone(),
+            data,
+        }
+    }
+
+    fn new(type_name: &str, json: &Json) -> Self {
+        Self {
+            type_name: type_name.to_string(),
+            data: json.clone(),
+        }
+    }
+}
+
+/// Builds a model from a `ModelSchema`; passed to the constructors of custom components.
+pub struct SchemaBuilder<'a> {
+    parameter_schemas: &'a [ParameterSchema],
+    registry: &'a Registry,
+    directory: &'a Path,
+    model: &'a mut Model,
+    parameters: HashMap<&'a str, Parameter>,
+    /// The names of the parameters being added, to detect circular references.
+    loading: Vec<&'a str>,
+}
+
+impl<'a> SchemaBuilder<'a> {
+    /// The model being built, to which the nodes, parameters and recorders of the schema are
+    /// added in that order.
+    pub fn model(&mut self) -> &mut Model {
+        self.model
+    }
+
+    /// The directory that paths in the schema are relative to.
+    pub fn directory(&self) -> &Path {
+        self.directory
+    }
+
+    /// The parameter called `name` in the schema, adding it to the model if it has not been already,
+    /// or else the parameter of that name already in the model.
+    pub fn parameter(&mut self, name: &str) -> Result<Parameter, PywrError> {
+        if let Some(parameter) = self.parameters.get(name) {
+            return Ok(parameter.clone());
+        }
+        let schema: &'a ParameterSchema = match self.parameter_schemas.iter().find(|p| p.name == name) {
+            Some(schema) => schema,
+            None => return self.model.get_parameter_by_name(name),
+        };
+        let name = schema.name.as_str();
+        if self.loading.contains(&name) {
+            return Err(invalid(&format!("parameter `{}` refers to itself", name)));
+        }
+
+        self.loading.push(name);
+        let parameter = self.add_parameter(schema);
+        self.loading.pop();
+        let parameter = parameter?;
+        self.parameters.insert(name, parameter.clone());
+        Ok(parameter)
+    }
+
+    fn add_parameter(&mut self, schema: &ParameterSchema) -> Result<Parameter, PywrError> {
+        let name = schema.name.as_str();
+        let parameter: Box<dyn _Parameter> = match &schema.parameter_type {
+            ParameterType::Constant(value) => Box::new(parameters::ConstantParameter::new(name, *value)),
+            ParameterType::Vector(values) => Box::new(parameters::VectorParameter::new(name, values.clone())),
+            ParameterType::DailyProfile(values) => {
+                Box::new(parameters::DailyProfileParameter::new(name, values.clone()))
+            }
+            ParameterType::Aggregated { agg_func, parameters } => {
+                let parameters = parameters
+                    .iter()
+                    .map(|p| self.parameter(p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Box::new(parameters::AggregatedParameter::new(name, parameters, *agg_func))
+            }
+            ParameterType::Custom(custom) => {
+                constructor(&self.registry.parameters, "parameter", name, custom)?(self, name, &custom.data)?
+            }
+        };
+        self.model.add_parameter(parameter)
+    }
+
+    /// Add the parameters, then the nodes and then the edges.
+    fn add_network(&mut self, nodes: &[NodeSchema], edges: &[EdgeSchema]) -> Result<(), PywrError> {
+        for parameter in self.parameter_schemas {
+            self.parameter(&parameter.name)?;
+        }
+        for node in nodes {
+            self.add_node(node)?;
+        }
+        for edge in edges {
+            let from = self.model.get_node_by_name(&edge.from)?;
+            let to = self.model.get_node_by_name(&edge.to)?;
+            let connection = self.model.connect_nodes(&from, &to)?;
+            let constraints = [
+                (&edge.max_flow, Constraint::MaxFlow),
+                (&edge.min_flow, Constraint::MinFlow),
+            ];
+            for (value, constraint) in constraints.iter() {
+                if let Some(value) = value {
+                    let value = self.value(value)?;
+                    connection.set_constraint(value, *constraint)?;
+                }
+            }
+            if let Some(cost) = &edge.cost {
+                let cost = self.value(cost)?;
+                connection.set_cost(cost);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_recorder(&mut self, schema: &RecorderSchema) -> Result<(), PywrError> {
+        let name = schema.name.as_str();
+        let recorder: Box<dyn _Recorder> = match &schema.recorder_type {
+            RecorderType::Csv { path } => Box::new(recorders::csv::CSVRecorder::new(name, self.directory.join(path))),
+            RecorderType::Hdf5 { path } => Box::new(recorders::hdf::HDF5Recorder::new(name, self.directory.join(path))),
+            RecorderType::Aggregated {
+                metric,
+                component,
+                temporal_agg_func,
+                scenario_agg_func,
+            } => Box::new(AggregatedRecorder::new(
+                name,
+                self.model.metric_by_name(metric, component)?,
+                temporal_agg_func.clone(),
+                scenario_agg_func.clone(),
+            )),
+            RecorderType::Custom(custom) => {
+                constructor(&self.registry.recorders, "recorder", name, custom)?(self, name, &custom.data)?
+            }
+        };
+        self.model.add_recorder(recorder)?;
+        Ok(())
+    }
+
+    fn value(&mut self, value: &ValueSchema) -> Result<ConstraintValue, PywrError> {
+        match value {
+            ValueSchema::Constant(v) => Ok(ConstraintValue::Scalar(*v)),
+            ValueSchema::Parameter(name) => Ok(ConstraintValue::Parameter(self.parameter(name)?)),
+        }
+    }
+
+    fn add_node(&mut self, schema: &NodeSchema) -> Result<(), PywrError> {
+        let name = schema.name.as_str();
+        let node = match &schema.node_type {
+            NodeSchemaType::Input => self.model.add_input_node(name)?,
+            NodeSchemaType::Output => self.model.add_output_node(name)?,
+            NodeSchemaType::Link => self.model.add_link_node(name)?,
+            NodeSchemaType::Storage => self.model.add_storage_node(name, schema.initial_volume)?,
+            NodeSchemaType::Custom(custom) => {
+                constructor(&self.registry.nodes, "node", name, custom)?(self, name, &custom.data)?;
+                return Ok(());
+            }
+        };
+        let constraints = [
+            (&schema.max_flow, Constraint::MaxFlow),
+            (&schema.min_flow, Constraint::MinFlow),
+            (&schema.max_volume, Constraint::MaxVolume),
+            (&schema.min_volume, Constraint::MinVolume),
+        ];
+        for (value, constraint) in constraints.iter() {
+            if let Some(value) = value {
+                let value = self.value(value)?;
+                node.set_constraint(value, *constraint)?;
+            }
+        }
+        if let Some(cost) = &schema.cost {
+            let cost = self.value(cost)?;
+            node.set_cost(cost);
+        }
+        Ok(())
+    }
+}
+
+fn invalid(reason: &str) -> PywrError {
+    PywrError::InvalidSchema(reason.to_string())
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(members.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn required<'a>(json: &'a Json, key: &str, owner: &str) -> Result<&'a Json, PywrError> {
+    json.get(key)
+        .ok_or_else(|| invalid(&format!("{} is missing `{}`", owner, key)))
+}
+
+fn members<'a>(json: &'a Json, owner: &str) -> Result<&'a [(String, Json)], PywrError> {
+    json.as_object()
+        .ok_or_else(|| invalid(&format!("expected {} to be an object, not {}", owner, json.type_name())))
+}
+
+fn string<'a>(json: &'a Json, key: &str, owner: &str) -> Result<&'a str, PywrError> {
+    required(json, key, owner)?
+        .as_str()
+        .ok_or_else(|| invalid(&format!("expected `{}` of {} to be a string", key, owner)))
+}
+
+/// The value `key`, which is a number or the name of a parameter.
+fn value(json: &Json, key: &str, owner: &str) -> Result<ValueSchema, PywrError> {
+    match required(json, key, owner)? {
+        Json::Number(v) => Ok(ValueSchema::Constant(*v)),
+        Json::String(p) => Ok(ValueSchema::Parameter(p.clone())),
+        _ => Err(invalid(&format!(
+            "expected `{}` of {} to be a number or the name of a parameter",
+            key, owner
+        ))),
+    }
+}
+
+fn value_json(value: &ValueSchema) -> Json {
+    match value {
+        ValueSchema::Constant(v) => Json::Number(*v),
+        ValueSchema::Parameter(p) => Json::String(p.clone()),
+    }
+}
+
+fn number(json: &Json, key: &str, owner: &str) -> Result<f64, PywrError> {
+    required(json, key, owner)?
+        .as_f64()
+        .ok_or_else(|| invalid(&format!("expected `{}` of {} to be a number", key, owner)))
+}
+
+fn numbers(json: &Json, key: &str, owner: &str) -> Result<Vec<f64>, PywrError> {
+    required(json, key, owner)?
+        .as_array()
+        .and_then(|values| values.iter().map(|v| v.as_f64()).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| invalid(&format!("expected `{}` of {} to be an array of numbers", key, owner)))
+}
+
+fn strings(json: &Json, key: &str, owner: &str) -> Result<Vec<String>, PywrError> {
+    required(json, key, owner)?
+        .as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|v| v.as_str().map(String::from))
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| invalid(&format!("expected `{}` of {} to be an array of strings", key, owner)))
+}
+
+/// The items of the array `key` of the model, each read by `item`; the array may be omitted.
+fn list<T>(json: &Json, key: &str, item: fn(&Json) -> Result<T, PywrError>) -> Result<Vec<T>, PywrError> {
+    match json.get(key) {
+        None => Ok(Vec::new()),
+        Some(Json::Array(items)) => items.iter().map(item).collect(),
+        Some(_) => Err(invalid(&format!("expected `{}` to be an array", key))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::simplex::SimplexSolver;
+    use crate::solvers::SolverSettings;
+    use float_cmp::approx_eq;
+    use tempdir::TempDir;
+
+    const MODEL: &str = r#"{
+        "timestepper": {"start": "2020-01-01", "end": "2020-01-10"},
+        "scenarios": [{"name": "demand", "labels": ["low", "high"]}],
+        "nodes": [
+            {"name": "supply", "type": "input", "max_flow": 15, "cost": 1},
+            {"name": "reservoir", "type": "storage", "max_volume": 100, "initial_volume": 50},
+            {"name": "demand", "type": "output", "max_flow": "demand", "cost": -10}
+        ],
+        "edges": [{"from": "supply", "to": "reservoir"}, {"from": "reservoir", "to": "demand"}],
+        "parameters": [
+            {"name": "demand", "type": "aggregated", "agg_func": "product", "parameters": ["base", "factor"]},
+            {"name": "base", "type": "constant", "value": 10},
+            {"name": "factor", "type": "constant", "value": 2}
+        ],
+        "recorders": [
+            {"name": "outputs", "type": "csv", "path": "outputs.csv"},
+            {"name": "deficit", "type": "aggregated", "metric": "node_deficit", "component": "demand",
+             "temporal_agg_func": "sum", "scenario_agg_func": "max"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_from_schema() {
+        let dir = TempDir::new("schema").unwrap();
+        let path = dir.path().join("model.json");
+        fs::write(&path, MODEL).unwrap();
+
+        let schema = ModelSchema::read(&path).unwrap();
+        assert_eq!(schema.timestepper.timestep, 1);
+        assert_eq!(schema.scenarios[0].size, 2);
+        assert_eq!(
+            schema.nodes[2].max_flow,
+            Some(ValueSchema::Parameter("demand".to_string()))
+        );
+
+        let (mut model, timestepper) = Model::from_schema(&path).unwrap();
+        assert_eq!(model.nodes.len(), 3);
+        assert_eq!(model.edges.len(), 2);
+        assert_eq!(timestepper.timesteps().len(), 10);
+
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        // The reservoir is cheaper than the supply so is emptied by the demand of 20 in the first
+        // three days, after which the supply of 15 leaves a deficit of 5 for the last seven.
+        assert!(approx_eq!(
+            f64,
+            model.get_aggregated_value("deficit").unwrap(),
+            35.0,
+            epsilon = 1e-6
+        ));
+        assert!(dir.path().join("outputs.csv").exists());
+    }
+
+    /// A catchment with a fixed `flow`, as an input node with equal minimum and maximum flows.
+    fn catchment(builder: &mut SchemaBuilder, name: &str, data: &Json) -> Result<Node, PywrError> {
+        let flow = number(data, "flow", name)?;
+        let node = builder.model().add_input_node(name)?;
+        node.set_constraint(ConstraintValue::Scalar(flow), Constraint::MinAndMaxFlow)?;
+        Ok(node)
+    }
+
+    /// A parameter scaling the named `parameter` by `factor`.
+    fn scaled(builder: &mut SchemaBuilder, name: &str, data: &Json) -> Result<Box<dyn _Parameter>, PywrError> {
+        let factor = parameters::ConstantParameter::new(&format!("{}.factor", name), number(data, "factor", name)?);
+        let factor = builder.model().add_parameter(Box::new(factor))?;
+        let parameter = builder.parameter(string(data, "parameter", name)?)?;
+        Ok(Box::new(parameters::AggregatedParameter::new(
+            name,
+            vec![parameter, factor],
+            AggFunc::Product,
+        )))
+    }
+
+    /// The total flow through the named `node` over a run.
+    fn total_flow(builder: &mut SchemaBuilder, name: &str, data: &Json) -> Result<Box<dyn _Recorder>, PywrError> {
+        let node = builder.model().get_node_by_name(string(data, "node", name)?)?;
+        Ok(Box::new(AggregatedRecorder::new(
+            name,
+            node.default_metric(),
+            RecorderAggregation::Sum,
+            RecorderAggregation::Mean,
+        )))
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::new();
+        registry.register_node("catchment", catchment).unwrap();
+        registry.register_parameter("scaled", scaled).unwrap();
+        registry.register_recorder("total_flow", total_flow).unwrap();
+        assert_eq!(
+            registry.register_node("catchment", catchment),
+            Err(PywrError::ComponentTypeAlreadyRegistered("catchment".to_string()))
+        );
+        assert_eq!(
+            registry.register_parameter("constant", scaled),
+            Err(PywrError::ComponentTypeAlreadyRegistered("constant".to_string()))
+        );
+
+        let schema = ModelSchema::from_json(
+            r#"{
+            "timestepper": {"start": "2020-01-01", "end": "2020-01-10"},
+            "nodes": [
+                {"name": "catchment", "type": "catchment", "flow": 8},
+                {"name": "demand", "type": "output", "max_flow": "demand", "cost": -10}
+            ],
+            "edges": [{"from": "catchment", "to": "demand"}],
+            "parameters": [
+                {"name": "demand", "type": "scaled", "parameter": "base", "factor": 1.5},
+                {"name": "base", "type": "constant", "value": 10}
+            ],
+            "recorders": [{"name": "supplied", "type": "total_flow", "node": "demand"}]
+        }"#,
+        )
+        .unwrap();
+        assert!(matches!(schema.nodes[0].node_type, NodeSchemaType::Custom(_)));
+        assert_eq!(
+            schema.build(Path::new(".")).err(),
+            Some(PywrError::InvalidSchema(
+                "unrecognised type `scaled` of parameter `demand`".to_string()
+            ))
+        );
+
+        let (mut model, timestepper) = schema.build_with_registry(Path::new("."), &registry).unwrap();
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        // The catchment's 8 is less than the demand of 15 but, as its flow is fixed, all of it is supplied.
+        assert!(approx_eq!(
+            f64,
+            model.get_aggregated_value("supplied").unwrap(),
+            80.0,
+            epsilon = 1e-6
+        ));
+    }
+
+    #[test]
+    fn test_to_schema() {
+        let dir = TempDir::new("schema").unwrap();
+        let path = dir.path().join("model.json");
+        fs::write(&path, MODEL).unwrap();
+
+        let (mut model, timestepper) = Model::from_schema(&path).unwrap();
+        let hourly = parameters::HourlyProfileParameter::new("hourly", [1.0; 24]);
+        let hourly = model.add_parameter(Box::new(hourly)).unwrap();
+        let supply = model.get_node_by_name("supply").unwrap();
+        supply
+            .set_constraint(ConstraintValue::Parameter(hourly), Constraint::MinFlow)
+            .unwrap();
+        let edge = model.get_edge_by_node_names("supply", "reservoir").unwrap();
+        edge.set_constraint(ConstraintValue::Scalar(100.0), Constraint::MaxFlow)
+            .unwrap();
+
+        let export = model.to_schema(&timestepper).unwrap();
+        assert_eq!(
+            export.omitted,
+            vec![
+                "parameter `hourly`".to_string(),
+                "the min_flow of node `supply`".to_string()
+            ]
+        );
+        assert_eq!(export.schema.parameters.len(), 3);
+        assert_eq!(export.schema.nodes[0].min_flow, None);
+        assert_eq!(export.schema.edges[0].max_flow, Some(ValueSchema::Constant(100.0)));
+
+        let exported = dir.path().join("exported.json");
+        export.schema.write(&exported).unwrap();
+        assert_eq!(ModelSchema::read(&exported).unwrap(), export.schema);
+
+        let (mut model, timestepper) = Model::from_schema(&exported).unwrap();
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        assert!(approx_eq!(
+            f64,
+            model.get_aggregated_value("deficit").unwrap(),
+            35.0,
+            epsilon = 1e-6
+        ));
+    }
+
+    #[test]
+    fn test_templates() {
+        let json = r#"{
+            "timestepper": {"start": "2020-01-01", "end": "2020-01-10"},
+            "nodes": [{"name": "source", "type": "input", "max_flow": 100}],
+            "edges": [{"from": "source", "to": "north.wtw"}, {"from": "source", "to": "south.wtw"}],
+            "parameters": [{"name": "south_demand", "type": "constant", "value": 8}],
+            "templates": [{
+                "name": "supply_zone",
+                "arguments": ["capacity", "demand"],
+                "nodes": [
+                    {"name": "wtw", "type": "link", "max_flow": "capacity"},
+                    {"name": "demand", "type": "output", "max_flow": "peak", "cost": -10}
+                ],
+                "edges": [{"from": "wtw", "to": "demand"}],
+                "parameters": [
+                    {"name": "peak", "type": "aggregated", "agg_func": "product", "parameters": ["demand", "factor"]},
+                    {"name": "factor", "type": "constant", "value": 1.5}
+                ]
+            }],
+            "instances": [
+                {"template": "supply_zone", "prefix": "north", "arguments": {"capacity": 10, "demand": 12}},
+                {"template": "supply_zone", "prefix": "south", "arguments": {"capacity": 10, "demand": "south_demand"}}
+            ],
+            "recorders": [
+                {"name": "north", "type": "aggregated", "metric": "node_deficit", "component": "north.demand",
+                 "temporal_agg_func": "sum"},
+                {"name": "south", "type": "aggregated", "metric": "node_deficit", "component": "south.demand",
+                 "temporal_agg_func": "sum"}
+            ]
+        }"#;
+        let schema = ModelSchema::from_json(json).unwrap();
+        assert_eq!(ModelSchema::from_json(&schema.to_json()).unwrap(), schema);
+
+        let (mut model, timestepper) = schema.build(Path::new(".")).unwrap();
+        assert!(model.get_node_by_name("south.wtw").is_ok());
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        // The peak demands of 18 and 12 are limited by the capacity of 10 of each works.
+        let deficit = |name: &str| model.get_aggregated_value(name).unwrap();
+        assert!(approx_eq!(f64, deficit("north"), 80.0, epsilon = 1e-6));
+        assert!(approx_eq!(f64, deficit("south"), 20.0, epsilon = 1e-6));
+
+        let template = &schema.templates[0];
+        let arguments = [
+            ("capacity", ValueSchema::Constant(5.0)),
+            ("demand", ValueSchema::Parameter("south_demand".to_string())),
+        ];
+        template.instantiate(&mut model, "east", &arguments).unwrap();
+        assert!(model.get_parameter_by_name("east.peak").is_ok());
+        let wtw = model.get_node_by_name("east.wtw").unwrap();
+        model
+            .connect_nodes(&model.get_node_by_name("source").unwrap(), &wtw)
+            .unwrap();
+
+        assert_eq!(
+            template.instantiate(&mut model, "west", &arguments[..1]).err(),
+            Some(PywrError::InvalidSchema(
+                "instance `west` of template `supply_zone` is missing argument `demand`".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let schema = |json: &str| ModelSchema::from_json(json);
+        assert!(matches!(schema("[]"), Err(PywrError::InvalidSchema(_))));
+        assert_eq!(
+            schema(r#"{"timestepper": {"start": "2020-01-01", "end": "2020-01-02"}, "nodes": [{"name": "a"}]}"#).err(),
+            Some(PywrError::InvalidSchema("node `a` is missing `type`".to_string()))
+        );
+
+        let model = r#"{"timestepper": {"start": "2020-01-01", "end": "2020-01-02"},
+            "nodes": [{"name": "a", "type": "output", "max_flow": "a"}],
+            "parameters": [{"name": "a", "type": "aggregated", "agg_func": "sum", "parameters": ["a"]}]}"#;
+        assert_eq!(
+            schema(model).unwrap().build(Path::new(".")).err(),
+            Some(PywrError::InvalidSchema("parameter `a` refers to itself".to_string()))
+        );
+    }
+}
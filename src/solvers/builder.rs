@@ -0,0 +1,1102 @@
+use crate::model::Model;
+use crate::node::{Node, NodeType};
+use crate::solvers::export::{sanitise_name, write_problem, ProblemFormat};
+use crate::solvers::simplex::DenseSimplex;
+use crate::solvers::{HorizonUpdate, ProblemSolution, ProblemUpdate, SolverSettings};
+use crate::PywrError;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Bounds {
+    Free,
+    Lower(f64),
+    Upper(f64),
+    Double(f64, f64),
+    Fixed(f64),
+}
+
+/// A single row of a linear programme.
+#[derive(Clone)]
+pub struct RowBuilder {
+    lower: f64,
+    upper: f64,
+    columns: Vec<(usize, f64)>,
+}
+
+impl Default for RowBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RowBuilder {
+    pub fn new() -> Self {
+        Self {
+            lower: 0.0,
+            upper: f64::MAX,
+            columns: Vec::new(),
+        }
+    }
+
+    pub fn set_upper(&mut self, upper: f64) {
+        self.upper = upper;
+    }
+
+    pub fn set_lower(&mut self, lower: f64) {
+        self.lower = lower
+    }
+
+    pub fn add_element(&mut self, column: usize, value: f64) {
+        self.columns.push((column, value))
+    }
+}
+
+/// Solver independent storage of a linear programme.
+///
+/// The constraint matrix is stored in row-major (CSR) format. Each solver backend is responsible
+/// for loading this data in to its own problem representation.
+#[derive(Clone)]
+pub struct LpBuilder {
+    col_lower: Vec<f64>,
+    col_upper: Vec<f64>,
+    col_obj_coef: Vec<f64>,
+    col_integer: Vec<bool>,
+    row_lower: Vec<f64>,
+    row_upper: Vec<f64>,
+    row_starts: Vec<usize>,
+    columns: Vec<usize>,
+    elements: Vec<f64>,
+}
+
+impl Default for LpBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LpBuilder {
+    pub fn new() -> Self {
+        Self {
+            col_lower: Vec::new(),
+            col_upper: Vec::new(),
+            col_obj_coef: Vec::new(),
+            col_integer: Vec::new(),
+            row_lower: Vec::new(),
+            row_upper: Vec::new(),
+            row_starts: vec![0],
+            columns: Vec::new(),
+            elements: Vec::new(),
+        }
+    }
+
+    pub fn add_column(&mut self, obj_coef: f64, bounds: Bounds) {
+        let (lb, ub): (f64, f64) = match bounds {
+            Bounds::Double(lb, ub) => (lb, ub),
+            Bounds::Lower(lb) => (lb, f64::MAX),
+            Bounds::Fixed(b) => (b, b),
+            Bounds::Free => (f64::MIN, f64::MAX),
+            Bounds::Upper(ub) => (f64::MIN, ub),
+        };
+
+        self.col_lower.push(lb);
+        self.col_upper.push(ub);
+        self.col_obj_coef.push(obj_coef);
+        self.col_integer.push(false);
+    }
+
+    /// Mark a column as only taking integer values.
+    pub fn set_integer(&mut self, col: usize) {
+        self.col_integer[col] = true;
+    }
+
+    pub fn set_obj_coefficient(&mut self, col: usize, obj_coef: f64) {
+        self.col_obj_coef[col] = obj_coef;
+    }
+
+    pub fn set_row_bounds(&mut self, row: usize, lb: f64, ub: f64) {
+        self.row_lower[row] = lb;
+        self.row_upper[row] = ub;
+    }
+
+    /// Update the value of an existing element of the constraint matrix.
+    pub fn set_element(&mut self, row: usize, col: usize, value: f64) {
+        let (start, end) = (self.row_starts[row], self.row_starts[row + 1]);
+        let offset = self.columns[start..end]
+            .iter()
+            .position(|&c| c == col)
+            .expect("element is not defined in row");
+        self.elements[start + offset] = value;
+    }
+
+    pub fn add_row(&mut self, row: RowBuilder) {
+        self.row_lower.push(row.lower);
+        self.row_upper.push(row.upper);
+        let prev_row_start = *self.row_starts.last().unwrap();
+        self.row_starts.push(prev_row_start + row.columns.len());
+        for (column, value) in row.columns {
+            self.columns.push(column);
+            self.elements.push(value);
+        }
+    }
+
+    pub fn nrows(&self) -> usize {
+        self.row_upper.len()
+    }
+
+    pub fn ncols(&self) -> usize {
+        self.col_upper.len()
+    }
+
+    pub fn col_lower(&self) -> &[f64] {
+        &self.col_lower
+    }
+
+    pub fn col_upper(&self) -> &[f64] {
+        &self.col_upper
+    }
+
+    pub fn col_obj_coef(&self) -> &[f64] {
+        &self.col_obj_coef
+    }
+
+    pub fn col_integer(&self) -> &[bool] {
+        &self.col_integer
+    }
+
+    pub fn row_lower(&self) -> &[f64] {
+        &self.row_lower
+    }
+
+    pub fn row_upper(&self) -> &[f64] {
+        &self.row_upper
+    }
+
+    pub fn row_starts(&self) -> &[usize] {
+        &self.row_starts
+    }
+
+    pub fn columns(&self) -> &[usize] {
+        &self.columns
+    }
+
+    pub fn elements(&self) -> &[f64] {
+        &self.elements
+    }
+
+    /// Return the (row, column, value) of each element that differs from `previous`.
+    ///
+    /// This is used by the backends to apply only the coefficients that have been updated since
+    /// the elements were last loaded.
+    pub(crate) fn changed_elements<'a>(
+        &'a self,
+        previous: &'a [f64],
+    ) -> impl Iterator<Item = (usize, usize, f64)> + 'a {
+        (0..self.nrows()).flat_map(move |row| {
+            (self.row_starts[row]..self.row_starts[row + 1])
+                .filter(move |&i| self.elements[i] != previous[i])
+                .map(move |i| (row, self.columns[i], self.elements[i]))
+        })
+    }
+
+    /// Return a block-diagonal programme containing `n` copies of this programme.
+    ///
+    /// Each copy has its own columns and rows, and the copies share no elements.
+    pub(crate) fn block_diagonal(&self, n: usize) -> LpBuilder {
+        let mut builder = LpBuilder::new();
+        for block in 0..n {
+            let offset = block * self.ncols();
+            for col in 0..self.ncols() {
+                builder.add_column(
+                    self.col_obj_coef[col],
+                    Bounds::Double(self.col_lower[col], self.col_upper[col]),
+                );
+                if self.col_integer[col] {
+                    builder.set_integer(offset + col);
+                }
+            }
+
+            for row in 0..self.nrows() {
+                let mut block_row = RowBuilder::new();
+                for i in self.row_starts[row]..self.row_starts[row + 1] {
+                    block_row.add_element(offset + self.columns[i], self.elements[i]);
+                }
+                block_row.set_lower(self.row_lower[row]);
+                block_row.set_upper(self.row_upper[row]);
+                builder.add_row(block_row);
+            }
+        }
+        builder
+    }
+
+    /// Copy the objective coefficients, row bounds and elements of `other` to copy `block`.
+    ///
+    /// This builder must have been created by `other.block_diagonal`.
+    pub(crate) fn copy_block(&mut self, block: usize, other: &LpBuilder) {
+        let (ncols, nrows, nelements) = (other.ncols(), other.nrows(), other.elements.len());
+        self.col_obj_coef[block * ncols..(block + 1) * ncols].copy_from_slice(&other.col_obj_coef);
+        self.row_lower[block * nrows..(block + 1) * nrows].copy_from_slice(&other.row_lower);
+        self.row_upper[block * nrows..(block + 1) * nrows].copy_from_slice(&other.row_upper);
+        self.elements[block * nelements..(block + 1) * nelements].copy_from_slice(&other.elements);
+    }
+
+    /// Return the constraint matrix in column-major (CSC) format.
+    ///
+    /// The returned tuple contains the column starts, row indices and element values.
+    pub fn column_major(&self) -> (Vec<usize>, Vec<usize>, Vec<f64>) {
+        let mut col_counts = vec![0; self.ncols()];
+        for &col in &self.columns {
+            col_counts[col] += 1;
+        }
+
+        let mut col_starts = Vec::with_capacity(self.ncols() + 1);
+        col_starts.push(0);
+        for count in &col_counts {
+            col_starts.push(col_starts.last().unwrap() + count);
+        }
+
+        let mut next = col_starts.clone();
+        let mut rows = vec![0; self.elements.len()];
+        let mut elements = vec![0.0; self.elements.len()];
+        for row in 0..self.nrows() {
+            for i in self.row_starts[row]..self.row_starts[row + 1] {
+                let col = self.columns[i];
+                rows[next[col]] = row;
+                elements[next[col]] = self.elements[i];
+                next[col] += 1;
+            }
+        }
+
+        (col_starts, rows, elements)
+    }
+}
+
+/// A node constraint that could not be satisfied in an infeasible problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeViolation {
+    pub name: String,
+    pub lower: f64,
+    pub upper: f64,
+    /// The flow through the node in the relaxed problem.
+    pub flow: f64,
+}
+
+impl NodeViolation {
+    /// The amount by which the constraint is violated.
+    pub fn amount(&self) -> f64 {
+        if self.flow < self.lower {
+            self.lower - self.flow
+        } else {
+            self.flow - self.upper
+        }
+    }
+}
+
+/// Identify the node constraints that make the problem in `builder` infeasible.
+///
+/// The rows given by `node_rows`, with the names of their nodes, are relaxed with elastic
+/// variables, and the total violation of those rows is minimised with the built-in simplex. All
+/// other rows remain hard constraints. The nodes whose rows are violated in the relaxed solution
+/// are returned.
+pub(crate) fn find_node_violations<'a>(
+    builder: &LpBuilder,
+    node_rows: impl Iterator<Item = (usize, &'a str)>,
+) -> Result<Vec<NodeViolation>, PywrError> {
+    let ncols = builder.ncols();
+    let mut nodes: Vec<Option<&str>> = vec![None; builder.nrows()];
+    for (row, name) in node_rows {
+        nodes[row] = Some(name);
+    }
+
+    let mut elastic = LpBuilder::new();
+    for col in 0..ncols {
+        elastic.add_column(0.0, Bounds::Double(builder.col_lower()[col], builder.col_upper()[col]));
+    }
+
+    for (row, node) in nodes.iter().enumerate() {
+        let mut elastic_row = RowBuilder::new();
+        for i in builder.row_starts()[row]..builder.row_starts()[row + 1] {
+            elastic_row.add_element(builder.columns()[i], builder.elements()[i]);
+        }
+        if node.is_some() {
+            // Elastic variables for violating the lower and upper bounds respectively.
+            let col = elastic.ncols();
+            elastic.add_column(1.0, Bounds::Lower(0.0));
+            elastic.add_column(1.0, Bounds::Lower(0.0));
+            elastic_row.add_element(col, 1.0);
+            elastic_row.add_element(col + 1, -1.0);
+        }
+        elastic_row.set_lower(builder.row_lower()[row]);
+        elastic_row.set_upper(builder.row_upper()[row]);
+        elastic.add_row(elastic_row);
+    }
+
+    let mut simplex = DenseSimplex::from_builder(&elastic);
+    let solution = simplex.solve_builder(&elastic)?;
+    let values = solution.primal_columns();
+
+    let mut violations = Vec::new();
+    let mut col = ncols;
+    for (row, node) in nodes.iter().enumerate() {
+        if let Some(name) = node {
+            if values[col] + values[col + 1] > 1e-6 {
+                let flow = (builder.row_starts()[row]..builder.row_starts()[row + 1])
+                    .map(|i| builder.elements()[i] * values[builder.columns()[i]])
+                    .sum();
+                violations.push(NodeViolation {
+                    name: name.to_string(),
+                    lower: builder.row_lower()[row],
+                    upper: builder.row_upper()[row],
+                    flow,
+                })
+            }
+            col += 2;
+        }
+    }
+
+    Ok(violations)
+}
+
+/// Replace a solver's `error` with the violated node constraints, if any can be found.
+pub(crate) fn diagnose_error<'a>(
+    builder: &LpBuilder,
+    node_rows: impl Iterator<Item = (usize, &'a str)>,
+    error: PywrError,
+) -> PywrError {
+    match find_node_violations(builder, node_rows) {
+        Ok(violations) if !violations.is_empty() => PywrError::Infeasible { violations },
+        _ => error,
+    }
+}
+
+/// Add the elements for the flow through `node` to `row`.
+///
+/// This is the total outflow for input and link nodes, the total inflow for output nodes and
+/// the net inflow for storage nodes.
+fn add_node_flow_elements(node: &Node, row: &mut RowBuilder) {
+    match node.node_type() {
+        NodeType::Link | NodeType::Input => {
+            for edge in node.get_outgoing_edges().unwrap() {
+                row.add_element(*edge.index(), 1.0);
+            }
+        }
+        NodeType::Output => {
+            for edge in node.get_incoming_edges().unwrap() {
+                row.add_element(*edge.index(), 1.0);
+            }
+        }
+        NodeType::Storage => {
+            for edge in node.get_incoming_edges().unwrap() {
+                row.add_element(*edge.index(), 1.0);
+            }
+            for edge in node.get_outgoing_edges().unwrap() {
+                row.add_element(*edge.index(), -1.0);
+            }
+        }
+    }
+}
+
+/// An element of the constraint matrix that is a weighted sum of flow factors, and is updated
+/// with the factors each timestep.
+#[derive(Clone)]
+pub(crate) struct FactorElement {
+    pub(crate) row: usize,
+    pub(crate) col: usize,
+    /// The coefficient and the index in `ProblemUpdate::flow_factors` of each factor in the sum.
+    terms: Vec<(f64, usize)>,
+}
+
+impl FactorElement {
+    pub(crate) fn value(&self, flow_factors: &[f64]) -> f64 {
+        self.terms.iter().map(|&(coef, k)| coef * flow_factors[k]).sum()
+    }
+}
+
+/// Add the rows requiring the flows of each of a model's groups of nodes with flow factors to be
+/// in proportion to their factors, i.e. `factor_0 * flow_i - factor_i * flow_0 = 0` for each node
+/// after the first.
+///
+/// `node_columns` gives the columns, and their coefficients, of the flow of a node. The elements
+/// of the rows, which are updated with the factors, are returned.
+pub(crate) fn add_flow_factor_rows<F: Fn(&Node) -> Vec<(usize, f64)>>(
+    builder: &mut LpBuilder,
+    row_names: &mut Vec<String>,
+    model: &Model,
+    node_columns: F,
+) -> Result<Vec<FactorElement>, PywrError> {
+    let mut elements = Vec::new();
+    let mut offset = 0;
+    for (group_index, group) in model.flow_factors().iter().enumerate() {
+        let nodes = group
+            .nodes()
+            .iter()
+            .map(|&i| model.nodes.get(*i).ok_or(PywrError::NodeIndexNotFound(i)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (i, node) in nodes.iter().enumerate().skip(1) {
+            let mut terms: Vec<(usize, Vec<(f64, usize)>)> = Vec::new();
+            let mut add_term = |col: usize, term: (f64, usize)| match terms.iter_mut().find(|(c, _)| *c == col) {
+                Some((_, col_terms)) => col_terms.push(term),
+                None => terms.push((col, vec![term])),
+            };
+            for (col, coef) in node_columns(node) {
+                add_term(col, (coef, offset));
+            }
+            for (col, coef) in node_columns(nodes[0]) {
+                add_term(col, (-coef, offset + i));
+            }
+
+            let mut row = RowBuilder::new();
+            for (col, col_terms) in terms {
+                // Placeholder coefficient that is replaced with the factors in `update`.
+                row.add_element(col, col_terms.iter().map(|(coef, _)| coef).sum());
+                elements.push(FactorElement {
+                    row: builder.nrows(),
+                    col,
+                    terms: col_terms,
+                });
+            }
+            row.set_lower(0.0);
+            row.set_upper(0.0);
+            builder.add_row(row);
+            row_names.push(sanitise_name(&format!("factors{}_{}", group_index, node.name())));
+        }
+        offset += nodes.len();
+    }
+    Ok(elements)
+}
+
+/// The linear programme used to allocate flows in a `Model`.
+///
+/// This constructs the columns and rows for a model's network once, and then updates the
+/// objective coefficients and row bounds each timestep. It is shared by all solver backends.
+#[derive(Clone)]
+pub struct NetworkLp {
+    builder: LpBuilder,
+    nedges: usize,
+    nnodes: usize,
+    start_node_constraints: Option<usize>,
+    /// The (row, edge index) of the constraint of each edge with a minimum or maximum flow.
+    edge_rows: Vec<(usize, usize)>,
+    /// The (row, column) of the binary column's element in each mutual exclusivity row.
+    exclusivity_elements: Vec<(usize, usize)>,
+    /// The elements of the flow factor rows, and the number of flow factors.
+    factor_elements: Vec<FactorElement>,
+    nflow_factors: usize,
+    start_smoothing_constraints: Option<usize>,
+    /// The index of each storage node.
+    storage_nodes: Vec<usize>,
+    node_names: Vec<String>,
+    col_names: Vec<String>,
+    row_names: Vec<String>,
+}
+
+impl NetworkLp {
+    pub fn new(model: &Model, settings: &SolverSettings) -> Result<Self, PywrError> {
+        let mut lp = Self {
+            builder: LpBuilder::new(),
+            nedges: model.edges.len(),
+            nnodes: model.nodes.len(),
+            start_node_constraints: None,
+            edge_rows: Vec::new(),
+            exclusivity_elements: Vec::new(),
+            factor_elements: Vec::new(),
+            nflow_factors: model.flow_factors().iter().map(|g| g.nodes().len()).sum(),
+            start_smoothing_constraints: None,
+            storage_nodes: model
+                .nodes
+                .iter()
+                .filter(|node| matches!(node.node_type(), NodeType::Storage))
+                .map(|node| *node.index())
+                .collect(),
+            node_names: model.nodes.iter().map(|node| node.name()).collect(),
+            col_names: Vec::new(),
+            row_names: Vec::new(),
+        };
+
+        // Create the columns
+        lp.create_columns(model)?;
+        // Create edge mass balance constraints
+        lp.create_mass_balance_constraints(model);
+        // Create the nodal constraints
+        lp.create_node_constraints(model);
+        // Create the constraints of edges with their own flow bounds
+        lp.create_edge_constraints(model);
+        // Create the binary columns and constraints for mutually exclusive nodes
+        lp.create_mutual_exclusivity_constraints(model)?;
+        // Create the constraints for nodes with flow factors
+        lp.factor_elements = add_flow_factor_rows(&mut lp.builder, &mut lp.row_names, model, |node| {
+            let mut row = RowBuilder::new();
+            add_node_flow_elements(node, &mut row);
+            row.columns
+        })?;
+        // Create the columns and constraints for the flow smoothing objective
+        if let Some(weight) = settings.flow_smoothing {
+            lp.create_flow_smoothing_constraints(model, weight);
+        }
+
+        Ok(lp)
+    }
+
+    pub fn builder(&self) -> &LpBuilder {
+        &self.builder
+    }
+
+    /// Create a column for each edge
+    fn create_columns(&mut self, model: &Model) -> Result<(), PywrError> {
+        // One column per edge
+        let ncols = model.edges.len();
+        if ncols < 1 {
+            return Err(PywrError::NoEdgesDefined);
+        }
+        // Add columns set the columns as x >= 0.0 (i.e. no upper bounds)
+        for edge in &model.edges {
+            self.builder.add_column(0.0, Bounds::Lower(0.0));
+            self.col_names.push(sanitise_name(&format!(
+                "edge{}_{}_{}",
+                edge.index(),
+                model.nodes[*edge.from_node_index()].name(),
+                model.nodes[*edge.to_node_index()].name()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Create mass balance constraints for each edge
+    fn create_mass_balance_constraints(&mut self, model: &Model) {
+        for node in &model.nodes {
+            // Only link nodes create mass-balance constraints
+
+            let mut row = RowBuilder::new();
+
+            if let NodeType::Link = node.node_type() {
+                let incoming_edges = node.get_incoming_edges().unwrap();
+                let outgoing_edges = node.get_outgoing_edges().unwrap();
+
+                // TODO check for length >= 1
+
+                for edge in &incoming_edges {
+                    row.add_element(*edge.index(), 1.0);
+                }
+                for edge in &outgoing_edges {
+                    row.add_element(*edge.index(), -1.0);
+                }
+
+                row.set_upper(0.0);
+                row.set_lower(0.0);
+            }
+
+            self.builder.add_row(row);
+            self.row_names
+                .push(sanitise_name(&format!("balance{}_{}", node.index(), node.name())));
+        }
+    }
+
+    /// Create node constraints
+    ///
+    /// One constraint is created per node to enforce any constraints (flow or storage)
+    /// that it may define.
+    fn create_node_constraints(&mut self, model: &Model) {
+        let start_row = self.builder.nrows();
+
+        for node in &model.nodes {
+            // Create empty arrays to store the matrix data
+            let mut row = RowBuilder::new();
+            add_node_flow_elements(node, &mut row);
+
+            self.builder.add_row(row);
+            self.row_names
+                .push(sanitise_name(&format!("node{}_{}", node.index(), node.name())));
+            self.start_node_constraints = Some(start_row);
+        }
+    }
+
+    /// Create edge constraints
+    ///
+    /// One constraint is created for each edge that defines a minimum or maximum flow. Edges
+    /// without either are bounded only by the nodes they connect.
+    fn create_edge_constraints(&mut self, model: &Model) {
+        for edge in model.edges.iter().filter(|edge| edge.is_constrained()) {
+            let mut row = RowBuilder::new();
+            row.add_element(*edge.index(), 1.0);
+
+            self.edge_rows.push((self.builder.nrows(), *edge.index()));
+            self.builder.add_row(row);
+            self.row_names.push(format!("edge{}", edge.index()));
+        }
+    }
+
+    /// Create the constraints for each set of mutually exclusive nodes
+    ///
+    /// Each node is given a binary column, `b`, that must be one for the node to have any flow
+    /// (i.e. `flow - max_flow * b <= 0`), and at most one of the binary columns in a set may be one.
+    /// The coefficients of the binary columns are updated with the nodes' maximum flows each timestep.
+    fn create_mutual_exclusivity_constraints(&mut self, model: &Model) -> Result<(), PywrError> {
+        for (exclusivity_index, node_indices) in model.mutual_exclusivities().iter().enumerate() {
+            let mut sum_row = RowBuilder::new();
+
+            for &node_index in node_indices {
+                let node = model
+                    .nodes
+                    .get(*node_index)
+                    .ok_or(PywrError::NodeIndexNotFound(node_index))?;
+                let col = self.builder.ncols();
+                self.builder.add_column(0.0, Bounds::Double(0.0, 1.0));
+                self.builder.set_integer(col);
+                self.col_names
+                    .push(sanitise_name(&format!("binary{}_{}", exclusivity_index, node.name())));
+
+                let mut row = RowBuilder::new();
+                add_node_flow_elements(node, &mut row);
+                // Placeholder coefficient that is replaced with the maximum flow in `update`.
+                row.add_element(col, -1.0);
+                row.set_lower(f64::MIN);
+                row.set_upper(0.0);
+                self.exclusivity_elements.push((self.builder.nrows(), col));
+                self.builder.add_row(row);
+                self.row_names.push(sanitise_name(&format!(
+                    "exclusivity{}_{}",
+                    exclusivity_index,
+                    node.name()
+                )));
+
+                sum_row.add_element(col, 1.0);
+            }
+
+            sum_row.set_upper(1.0);
+            self.builder.add_row(sum_row);
+            self.row_names.push(format!("exclusivity{}", exclusivity_index));
+        }
+
+        Ok(())
+    }
+
+    /// Create the constraints for the flow smoothing objective
+    ///
+    /// Each edge is given columns for the increase and decrease of its flow from the previous
+    /// timestep (i.e. `flow - increase + decrease = previous_flow`), both with a cost of `weight`.
+    /// The previous flows are updated each timestep.
+    fn create_flow_smoothing_constraints(&mut self, model: &Model, weight: f64) {
+        let start_row = self.builder.nrows();
+
+        for edge in &model.edges {
+            let col = self.builder.ncols();
+            self.builder.add_column(weight, Bounds::Lower(0.0));
+            self.builder.add_column(weight, Bounds::Lower(0.0));
+            self.col_names.push(format!("increase{}", edge.index()));
+            self.col_names.push(format!("decrease{}", edge.index()));
+
+            let mut row = RowBuilder::new();
+            row.add_element(*edge.index(), 1.0);
+            row.add_element(col, -1.0);
+            row.add_element(col + 1, 1.0);
+            // Placeholder bounds that are replaced with the previous flow in `update`.
+            row.set_lower(0.0);
+            row.set_upper(0.0);
+            self.builder.add_row(row);
+            self.row_names.push(format!("smoothing{}", edge.index()));
+        }
+
+        self.start_smoothing_constraints = Some(start_row);
+    }
+
+    /// Update the objective coefficients, row bounds and exclusivity coefficients from `update`.
+    pub fn update(&mut self, update: &ProblemUpdate) -> Result<(), PywrError> {
+        let start_row = match self.start_node_constraints {
+            Some(r) => r,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        if update.edge_costs.len() != self.nedges
+            || update.node_bounds.len() != self.nnodes
+            || update.edge_bounds.len() != self.nedges
+            || update.exclusivity_max_flows.len() != self.exclusivity_elements.len()
+            || update.flow_factors.len() != self.nflow_factors
+        {
+            return Err(PywrError::ProblemUpdateMismatch);
+        }
+
+        for (col, &cost) in update.edge_costs.iter().enumerate() {
+            self.builder.set_obj_coefficient(col, cost);
+        }
+        for (node_index, &(lb, ub)) in update.node_bounds.iter().enumerate() {
+            self.builder.set_row_bounds(start_row + node_index, lb, ub);
+        }
+        for &(row, edge_index) in &self.edge_rows {
+            let (lb, ub) = update.edge_bounds[edge_index];
+            self.builder.set_row_bounds(row, lb, ub);
+        }
+        for (&(row, col), &max_flow) in self.exclusivity_elements.iter().zip(&update.exclusivity_max_flows) {
+            self.builder.set_element(row, col, -max_flow);
+        }
+        for element in &self.factor_elements {
+            self.builder
+                .set_element(element.row, element.col, element.value(&update.flow_factors));
+        }
+        if let Some(start_row) = self.start_smoothing_constraints {
+            if update.previous_edge_flows.len() != self.nedges {
+                return Err(PywrError::ProblemUpdateMismatch);
+            }
+            for (edge_index, &flow) in update.previous_edge_flows.iter().enumerate() {
+                self.builder.set_row_bounds(start_row + edge_index, flow, flow);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace a solver's `error` with the node constraints that make the current problem
+    /// infeasible, if any can be found.
+    pub fn diagnose_error(&self, error: PywrError) -> PywrError {
+        match self.start_node_constraints {
+            Some(start_row) => diagnose_error(&self.builder, self.node_rows(start_row, 0), error),
+            None => error,
+        }
+    }
+
+    /// The row of each node's constraint, and the node's name, offset by `offset` rows.
+    fn node_rows(&self, start_row: usize, offset: usize) -> impl Iterator<Item = (usize, &str)> {
+        self.node_names
+            .iter()
+            .enumerate()
+            .map(move |(node_index, name)| (offset + start_row + node_index, name.as_str()))
+    }
+
+    /// Write the current problem to `path` in the given format.
+    pub fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        write_problem(&self.builder, &self.col_names, &self.row_names, path, format)
+    }
+
+    /// Extract the edge flows, node duals and edge reduced costs from a solution.
+    ///
+    /// The node duals are those of each node's constraint row. Backends that do not provide
+    /// duals (e.g. for integer problems) give empty slices, and no values are returned.
+    pub fn solution(&self, primal_columns: &[f64], dual_rows: &[f64], reduced_costs: &[f64]) -> ProblemSolution {
+        let node_duals = match self.start_node_constraints {
+            Some(start_row) if dual_rows.len() == self.builder.nrows() => {
+                Some(dual_rows[start_row..start_row + self.nnodes].to_vec())
+            }
+            _ => None,
+        };
+        let edge_reduced_costs = if reduced_costs.len() == self.builder.ncols() {
+            Some(reduced_costs[..self.nedges].to_vec())
+        } else {
+            None
+        };
+
+        ProblemSolution {
+            edge_flows: primal_columns[..self.nedges].to_vec(),
+            node_duals,
+            edge_reduced_costs,
+        }
+    }
+}
+
+/// The linear programmes of several scenarios combined in to one block-diagonal programme.
+///
+/// Each scenario is a copy of a model's `NetworkLp`, with its own columns and rows.
+/// Solving the combined programme solves all of the scenarios of a timestep in a single call.
+pub struct BatchNetworkLp {
+    lp: NetworkLp,
+    builder: LpBuilder,
+    nscenarios: usize,
+}
+
+impl BatchNetworkLp {
+    pub fn new(lp: &NetworkLp, nscenarios: usize) -> Self {
+        Self {
+            lp: lp.clone(),
+            builder: lp.builder().block_diagonal(nscenarios),
+            nscenarios,
+        }
+    }
+
+    pub fn builder(&self) -> &LpBuilder {
+        &self.builder
+    }
+
+    pub fn nscenarios(&self) -> usize {
+        self.nscenarios
+    }
+
+    /// Update each scenario's objective coefficients and row bounds from its `ProblemUpdate`.
+    pub fn update(&mut self, updates: &[ProblemUpdate]) -> Result<(), PywrError> {
+        if updates.len() != self.nscenarios {
+            return Err(PywrError::ScenarioStateNotFound);
+        }
+
+        for (block, update) in updates.iter().enumerate() {
+            self.lp.update(update)?;
+            self.builder.copy_block(block, self.lp.builder());
+        }
+        Ok(())
+    }
+
+    /// Replace a solver's `error` with the node constraints that make the current problem
+    /// infeasible, if any can be found.
+    pub fn diagnose_error(&self, error: PywrError) -> PywrError {
+        match self.lp.start_node_constraints {
+            Some(start_row) => {
+                let nrows = self.lp.builder.nrows();
+                let node_rows = (0..self.nscenarios).flat_map(|block| self.lp.node_rows(start_row, block * nrows));
+                diagnose_error(&self.builder, node_rows, error)
+            }
+            None => error,
+        }
+    }
+
+    /// Extract the solution of each scenario from the solution of the combined programme.
+    pub fn solutions(&self, primal_columns: &[f64], dual_rows: &[f64], reduced_costs: &[f64]) -> Vec<ProblemSolution> {
+        let (ncols, nrows) = (self.lp.builder.ncols(), self.lp.builder.nrows());
+        (0..self.nscenarios)
+            .map(|block| {
+                self.lp.solution(
+                    block_values(primal_columns, block, ncols),
+                    block_values(dual_rows, block, nrows),
+                    block_values(reduced_costs, block, ncols),
+                )
+            })
+            .collect()
+    }
+}
+
+/// The linear programmes of several consecutive timesteps combined in to one programme.
+///
+/// Each timestep is a copy of a model's `NetworkLp`, as in `BatchNetworkLp`, except that the row
+/// of each storage node constrains the change in its volume from the start of the first timestep
+/// to the end of that timestep. The row includes the net inflows of all of the earlier timesteps,
+/// weighted by their lengths in days, so water stored in one timestep is available in the next.
+/// The flow smoothing rows of each timestep after the first penalise changes from the flows of
+/// the previous timestep of the programme.
+pub struct HorizonNetworkLp {
+    lp: NetworkLp,
+    builder: LpBuilder,
+    ntimesteps: usize,
+}
+
+impl HorizonNetworkLp {
+    pub fn new(lp: &NetworkLp, ntimesteps: usize) -> Self {
+        let base = &lp.builder;
+        let (ncols, nrows) = (base.ncols(), base.nrows());
+        let storage_rows: Vec<usize> = match lp.start_node_constraints {
+            Some(start_row) => lp.storage_nodes.iter().map(|node| start_row + node).collect(),
+            None => Vec::new(),
+        };
+        let smoothing_rows = lp.start_smoothing_constraints.map(|start| start..start + lp.nedges);
+
+        let mut builder = LpBuilder::new();
+        for timestep in 0..ntimesteps {
+            for col in 0..ncols {
+                builder.add_column(
+                    base.col_obj_coef[col],
+                    Bounds::Double(base.col_lower[col], base.col_upper[col]),
+                );
+                if base.col_integer[col] {
+                    builder.set_integer(timestep * ncols + col);
+                }
+            }
+        }
+
+        for timestep in 0..ntimesteps {
+            for row in 0..nrows {
+                let mut horizon_row = RowBuilder::new();
+                let first = if storage_rows.contains(&row) { 0 } else { timestep };
+                for t in first..=timestep {
+                    for i in base.row_starts[row]..base.row_starts[row + 1] {
+                        horizon_row.add_element(t * ncols + base.columns[i], base.elements[i]);
+                    }
+                }
+                match &smoothing_rows {
+                    Some(rows) if timestep > 0 && rows.contains(&row) => {
+                        horizon_row.add_element((timestep - 1) * ncols + row - rows.start, -1.0);
+                    }
+                    _ => {}
+                }
+                horizon_row.set_lower(base.row_lower[row]);
+                horizon_row.set_upper(base.row_upper[row]);
+                builder.add_row(horizon_row);
+            }
+        }
+
+        Self {
+            lp: lp.clone(),
+            builder,
+            ntimesteps,
+        }
+    }
+
+    pub fn builder(&self) -> &LpBuilder {
+        &self.builder
+    }
+
+    pub fn ntimesteps(&self) -> usize {
+        self.ntimesteps
+    }
+
+    /// Update each timestep's objective coefficients, row bounds and storage volume coefficients.
+    pub fn update(&mut self, update: &HorizonUpdate) -> Result<(), PywrError> {
+        if update.updates.len() != self.ntimesteps || update.days.len() != self.ntimesteps {
+            return Err(PywrError::ProblemUpdateMismatch);
+        }
+        let start_row = match self.lp.start_node_constraints {
+            Some(r) => r,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        let (ncols, nrows) = (self.lp.builder.ncols(), self.lp.builder.nrows());
+        for (timestep, step) in update.updates.iter().enumerate() {
+            self.lp.update(step)?;
+            let base = &self.lp.builder;
+            for col in 0..ncols {
+                self.builder
+                    .set_obj_coefficient(timestep * ncols + col, base.col_obj_coef[col]);
+            }
+            for row in 0..nrows {
+                self.builder
+                    .set_row_bounds(timestep * nrows + row, base.row_lower[row], base.row_upper[row]);
+            }
+            for (&(row, col), &max_flow) in self.lp.exclusivity_elements.iter().zip(&step.exclusivity_max_flows) {
+                self.builder
+                    .set_element(timestep * nrows + row, timestep * ncols + col, -max_flow);
+            }
+            for element in &self.lp.factor_elements {
+                self.builder.set_element(
+                    timestep * nrows + element.row,
+                    timestep * ncols + element.col,
+                    element.value(&step.flow_factors),
+                );
+            }
+            // Only the first timestep's flows are compared with the flows before the programme.
+            if let (Some(smoothing_row), true) = (self.lp.start_smoothing_constraints, timestep > 0) {
+                for edge_index in 0..self.lp.nedges {
+                    self.builder
+                        .set_row_bounds(timestep * nrows + smoothing_row + edge_index, 0.0, 0.0);
+                }
+            }
+            for &node in &self.lp.storage_nodes {
+                let row = start_row + node;
+                for i in base.row_starts[row]..base.row_starts[row + 1] {
+                    for (t, &days) in update.days.iter().enumerate().take(timestep + 1) {
+                        self.builder.set_element(
+                            timestep * nrows + row,
+                            t * ncols + base.columns[i],
+                            base.elements[i] * days,
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace a solver's `error` with the node constraints that make the current problem
+    /// infeasible, if any can be found.
+    pub fn diagnose_error(&self, error: PywrError) -> PywrError {
+        match self.lp.start_node_constraints {
+            Some(start_row) => {
+                let nrows = self.lp.builder.nrows();
+                let node_rows = (0..self.ntimesteps).flat_map(|t| self.lp.node_rows(start_row, t * nrows));
+                diagnose_error(&self.builder, node_rows, error)
+            }
+            None => error,
+        }
+    }
+
+    /// Extract the solution of each timestep from the solution of the combined programme.
+    pub fn solutions(&self, primal_columns: &[f64], dual_rows: &[f64], reduced_costs: &[f64]) -> Vec<ProblemSolution> {
+        let (ncols, nrows) = (self.lp.builder.ncols(), self.lp.builder.nrows());
+        (0..self.ntimesteps)
+            .map(|t| {
+                self.lp.solution(
+                    block_values(primal_columns, t, ncols),
+                    block_values(dual_rows, t, nrows),
+                    block_values(reduced_costs, t, ncols),
+                )
+            })
+            .collect()
+    }
+}
+
+/// The values of copy `block` of a block-diagonal programme, or an empty slice if the values
+/// are not available.
+fn block_values(values: &[f64], block: usize, size: usize) -> &[f64] {
+    values.get(block * size..(block + 1) * size).unwrap_or(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_column_major() {
+        let mut builder = LpBuilder::new();
+        builder.add_column(0.0, Bounds::Lower(0.0));
+        builder.add_column(0.0, Bounds::Lower(0.0));
+        builder.add_column(0.0, Bounds::Lower(0.0));
+
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(2, 2.0);
+        builder.add_row(row);
+
+        let mut row = RowBuilder::new();
+        row.add_element(1, 3.0);
+        row.add_element(2, 4.0);
+        builder.add_row(row);
+
+        let (starts, rows, elements) = builder.column_major();
+        assert_eq!(starts, vec![0, 1, 2, 4]);
+        assert_eq!(rows, vec![0, 1, 0, 1]);
+        assert_eq!(elements, vec![1.0, 3.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn builder_block_diagonal() {
+        let mut builder = LpBuilder::new();
+        builder.add_column(1.0, Bounds::Lower(0.0));
+        builder.add_column(2.0, Bounds::Double(0.0, 1.0));
+        builder.set_integer(1);
+
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, -1.0);
+        row.set_upper(5.0);
+        builder.add_row(row);
+
+        let mut batch = builder.block_diagonal(2);
+        assert_eq!(batch.ncols(), 4);
+        assert_eq!(batch.nrows(), 2);
+        assert_eq!(batch.columns(), &[0, 1, 2, 3]);
+        assert_eq!(batch.col_integer(), &[false, true, false, true]);
+
+        builder.set_obj_coefficient(0, 3.0);
+        builder.set_row_bounds(0, 1.0, 4.0);
+        builder.set_element(0, 1, -2.0);
+        batch.copy_block(1, &builder);
+        assert_eq!(batch.col_obj_coef(), &[1.0, 2.0, 3.0, 2.0]);
+        assert_eq!(batch.row_lower(), &[0.0, 1.0]);
+        assert_eq!(batch.row_upper(), &[5.0, 4.0]);
+        assert_eq!(batch.elements(), &[1.0, -1.0, 1.0, -2.0]);
+    }
+
+    #[test]
+    fn builder_changed_elements() {
+        let mut builder = LpBuilder::new();
+        builder.add_column(0.0, Bounds::Lower(0.0));
+        builder.add_column(0.0, Bounds::Double(0.0, 1.0));
+
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, -1.0);
+        builder.add_row(row);
+
+        let previous = builder.elements().to_vec();
+        assert_eq!(builder.changed_elements(&previous).count(), 0);
+
+        builder.set_element(0, 1, -5.0);
+        assert_eq!(builder.elements(), &[1.0, -5.0]);
+        assert_eq!(
+            builder.changed_elements(&previous).collect::<Vec<_>>(),
+            vec![(0, 1, -5.0)]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use crate::model::Model;
+use crate::solvers::builder::{BatchNetworkLp, HorizonNetworkLp, LpBuilder, NetworkLp};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::PywrError;
+use cbc_sys::*;
+use libc::{c_double, c_int};
+use std::ffi::CString;
+use std::path::Path;
+use std::slice;
+use std::time::Instant;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CbcError {
+    #[error("Cbc did not find an optimal solution (status {0}).")]
+    NotOptimal(i32),
+    #[error("the Cbc model has not been created")]
+    ModelNotInitialisedError,
+}
+
+struct CbcModel {
+    ptr: *mut Cbc_Model,
+    /// The matrix elements as last loaded from the builder.
+    elements: Vec<f64>,
+    /// Number of iterations in the most recent solve.
+    iterations: u64,
+}
+
+impl CbcModel {
+    /// Create a new Cbc model from the data in `builder`.
+    fn from_builder(builder: &LpBuilder) -> Self {
+        let mut model = unsafe {
+            let ptr = Cbc_newModel();
+            Cbc_setLogLevel(ptr, 0);
+            CbcModel {
+                ptr,
+                elements: Vec::new(),
+                iterations: 0,
+            }
+        };
+        model.load_builder(builder);
+        model
+    }
+
+    /// Load the problem in `builder`, replacing any existing problem.
+    ///
+    /// Any columns marked as integer in the builder are given integer restrictions.
+    fn load_builder(&mut self, builder: &LpBuilder) {
+        let (col_starts, rows, elements) = builder.column_major();
+        let col_starts: Vec<CoinBigIndex> = col_starts.iter().map(|&i| i as CoinBigIndex).collect();
+        let rows: Vec<c_int> = rows.iter().map(|&i| i as c_int).collect();
+
+        let ptr = self.ptr;
+        unsafe {
+            Cbc_loadProblem(
+                ptr,
+                builder.ncols() as c_int,
+                builder.nrows() as c_int,
+                col_starts.as_ptr(),
+                rows.as_ptr(),
+                elements.as_ptr(),
+                builder.col_lower().as_ptr(),
+                builder.col_upper().as_ptr(),
+                builder.col_obj_coef().as_ptr(),
+                builder.row_lower().as_ptr(),
+                builder.row_upper().as_ptr(),
+            );
+            Cbc_setObjSense(ptr, 1.0);
+
+            for (col, &integer) in builder.col_integer().iter().enumerate() {
+                if integer {
+                    Cbc_setInteger(ptr, col as c_int);
+                }
+            }
+        }
+        self.elements = builder.elements().to_vec();
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &str) {
+        let name = CString::new(name).unwrap();
+        let value = CString::new(value).unwrap();
+        unsafe {
+            Cbc_setParameter(self.ptr, name.as_ptr(), value.as_ptr());
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &SolverSettings) {
+        unsafe {
+            Cbc_setLogLevel(self.ptr, if settings.verbose { 1 } else { 0 });
+        }
+        if let Some(presolve) = settings.presolve {
+            self.set_parameter("presolve", if presolve { "on" } else { "off" });
+        }
+        if let Some(tol) = settings.primal_tolerance {
+            self.set_parameter("primalT", &tol.to_string());
+        }
+        if let Some(tol) = settings.dual_tolerance {
+            self.set_parameter("dualT", &tol.to_string());
+        }
+        if let Some(threads) = settings.threads {
+            self.set_parameter("threads", &threads.to_string());
+        }
+        if let Some(time_limit) = settings.time_limit {
+            self.set_parameter("seconds", &time_limit.to_string());
+        }
+    }
+
+    /// The number of iterations in the most recent solve.
+    fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Update the objective and row bounds from `builder` and re-solve.
+    ///
+    /// Cbc can not update individual matrix elements, so the problem is reloaded if any have changed.
+    fn solve_builder(&mut self, builder: &LpBuilder) -> Result<CbcSolution, CbcError> {
+        if builder.changed_elements(&self.elements).next().is_some() {
+            self.load_builder(builder);
+        }
+
+        unsafe {
+            for (col, &coef) in builder.col_obj_coef().iter().enumerate() {
+                Cbc_setObjCoeff(self.ptr, col as c_int, coef as c_double);
+            }
+            for (row, (&lb, &ub)) in builder.row_lower().iter().zip(builder.row_upper()).enumerate() {
+                Cbc_setRowLower(self.ptr, row as c_int, lb as c_double);
+                Cbc_setRowUpper(self.ptr, row as c_int, ub as c_double);
+            }
+
+            let status = Cbc_solve(self.ptr);
+            self.iterations = Cbc_getIterationCount(self.ptr) as u64;
+            if Cbc_isProvenOptimal(self.ptr) == 0 {
+                return Err(CbcError::NotOptimal(status));
+            }
+
+            // Duals are only meaningful if there are no integer columns.
+            let (dual_rows, reduced_costs) = if builder.col_integer().iter().any(|&i| i) {
+                (Vec::new(), Vec::new())
+            } else {
+                (
+                    slice::from_raw_parts(Cbc_getRowPrice(self.ptr), builder.nrows()).to_vec(),
+                    slice::from_raw_parts(Cbc_getReducedCost(self.ptr), builder.ncols()).to_vec(),
+                )
+            };
+
+            let data_ptr = Cbc_getColSolution(self.ptr);
+            Ok(CbcSolution {
+                objective_value: Cbc_getObjValue(self.ptr),
+                primal_columns: slice::from_raw_parts(data_ptr, builder.ncols()).to_vec(),
+                dual_rows,
+                reduced_costs,
+            })
+        }
+    }
+}
+
+impl Drop for CbcModel {
+    fn drop(&mut self) {
+        unsafe {
+            Cbc_deleteModel(self.ptr);
+        }
+    }
+}
+
+// SAFETY: the model is owned exclusively by this value and Cbc does not tie it to the thread
+// that created it, so it may be moved to, used on and dropped by another thread.
+unsafe impl Send for CbcModel {}
+
+#[derive(Debug)]
+pub struct CbcSolution {
+    objective_value: f64,
+    primal_columns: Vec<f64>,
+    dual_rows: Vec<f64>,
+    reduced_costs: Vec<f64>,
+}
+
+impl CbcSolution {
+    pub fn get_solution(&self, col: usize) -> f64 {
+        self.primal_columns[col]
+    }
+}
+
+/// A solver using the Cbc mixed integer programming library.
+///
+/// The LP is constructed in the same way as `ClpSolver`, but any integer columns are solved
+/// using Cbc's branch and cut algorithm.
+pub struct CbcSolver {
+    lp: Option<NetworkLp>,
+    model: Option<CbcModel>,
+    batch: Option<(BatchNetworkLp, CbcModel)>,
+    horizon: Option<(HorizonNetworkLp, CbcModel)>,
+    settings: SolverSettings,
+    stats: SolverStats,
+}
+
+impl CbcSolver {
+    pub(crate) fn new() -> Self {
+        Self {
+            lp: None,
+            model: None,
+            batch: None,
+            horizon: None,
+            settings: SolverSettings::default(),
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+impl Solver for CbcSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[SolverFeature::IntegerVariables, SolverFeature::MultiTimestep]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new())
+    }
+
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.settings = settings.clone();
+        self.batch = None;
+        self.horizon = None;
+        let lp = NetworkLp::new(model, settings)?;
+        let mut cbc = CbcModel::from_builder(lp.builder());
+        cbc.apply_settings(settings);
+        self.model = Some(cbc);
+        self.lp = Some(lp);
+
+        Ok(())
+    }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, cbc) = match (&mut self.lp, &mut self.model) {
+            (Some(lp), Some(cbc)) => (lp, cbc),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = cbc.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), cbc.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solution(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of scenarios changes.
+        if !matches!(&self.batch, Some((lp, _)) if lp.nscenarios() == updates.len()) {
+            let lp = BatchNetworkLp::new(network_lp, updates.len());
+            let mut cbc = CbcModel::from_builder(lp.builder());
+            cbc.apply_settings(&self.settings);
+            self.batch = Some((lp, cbc));
+        }
+        let (lp, cbc) = match &mut self.batch {
+            Some((lp, cbc)) => (lp, cbc),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(updates)?;
+        let start = Instant::now();
+        let result = cbc.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), cbc.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_horizon(&mut self, update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of timesteps changes.
+        if !matches!(&self.horizon, Some((lp, _)) if lp.ntimesteps() == update.updates.len()) {
+            let lp = HorizonNetworkLp::new(network_lp, update.updates.len());
+            let mut cbc = CbcModel::from_builder(lp.builder());
+            cbc.apply_settings(&self.settings);
+            self.horizon = Some((lp, cbc));
+        }
+        let (lp, cbc) = match &mut self.horizon {
+            Some((lp, cbc)) => (lp, cbc),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = cbc.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), cbc.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn cbc_solve_integer() {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(-1.0, Bounds::Double(0.0, 10.0));
+        builder.add_column(-1.0, Bounds::Double(0.0, 10.0));
+        builder.set_integer(0);
+        builder.set_integer(1);
+
+        // 2x + 2y <= 5 only has integer solutions with x + y = 2.
+        let mut row = RowBuilder::new();
+        row.add_element(0, 2.0);
+        row.add_element(1, 2.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(5.0);
+        builder.add_row(row);
+
+        let mut model = CbcModel::from_builder(&builder);
+        let solution = model.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, -2.0));
+        assert!(approx_eq!(
+            f64,
+            solution.get_solution(0) + solution.get_solution(1),
+            2.0
+        ));
+    }
+}
@@ -1,12 +1,16 @@
 use crate::model::Model;
-use crate::node::NodeType;
-use crate::solvers::Solver;
-use crate::timestep::Timestep;
-use crate::{NetworkState, PywrError};
+use crate::solvers::builder::{BatchNetworkLp, HorizonNetworkLp, LpBuilder, NetworkLp};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::PywrError;
 use clp_sys::*;
 use libc::{c_double, c_int};
 use std::ffi::CString;
+use std::path::Path;
 use std::slice;
+use std::time::Instant;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -15,21 +19,71 @@ pub enum ClpError {
     UnknownError,
     #[error("the simplex model has not been created")]
     SimplexNotInitialisedError,
+    #[error("Clp failed to find an optimal solution")]
+    NotOptimal,
 }
 
 pub type CoinBigIndex = c_int;
 
+/// The algorithm Clp uses to solve the LP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClpAlgorithm {
+    /// Dual simplex, warm-started from the basis of the previous solve.
+    DualSimplex,
+    /// Barrier (interior point) with crossover to a basic solution.
+    ///
+    /// Each solve starts from scratch, so this is only likely to be faster than the dual
+    /// simplex for large problems, such as many scenarios solved as one batch.
+    Barrier,
+}
+
+/// Clp specific parameters passed when the solver is setup.
+///
+/// Options common to all solvers are given by `SolverSettings`.
+#[derive(Clone, Debug)]
+pub struct ClpSettings {
+    pub algorithm: ClpAlgorithm,
+}
+
+impl Default for ClpSettings {
+    fn default() -> Self {
+        Self {
+            algorithm: ClpAlgorithm::DualSimplex,
+        }
+    }
+}
+
 struct ClpSimplex {
     ptr: *mut Clp_Simplex,
+    /// Whether the model holds an optimal basis from a previous solve.
+    has_basis: bool,
+    /// Whether to presolve when solving from scratch, or `None` for Clp's default.
+    presolve: Option<bool>,
+    algorithm: ClpAlgorithm,
+    /// Number of iterations in the most recent solve.
+    iterations: u64,
+    /// The matrix elements as last loaded from the builder.
+    elements: Vec<f64>,
 }
 
+// SAFETY: the model is owned exclusively by this value and Clp does not tie it to the thread
+// that created it, so it may be moved to, used on and dropped by another thread.
+unsafe impl Send for ClpSimplex {}
+
 impl ClpSimplex {
     pub fn new() -> ClpSimplex {
         let model: ClpSimplex;
 
         unsafe {
             let ptr = Clp_newModel();
-            model = ClpSimplex { ptr };
+            model = ClpSimplex {
+                ptr,
+                has_basis: false,
+                presolve: None,
+                algorithm: ClpAlgorithm::DualSimplex,
+                iterations: 0,
+                elements: Vec::new(),
+            };
             Clp_setLogLevel(ptr, 0);
             Clp_setObjSense(ptr, 1.0);
         }
@@ -122,12 +176,60 @@ impl ClpSimplex {
         }
     }
 
+    fn initial_solve(&mut self) {
+        unsafe {
+            match self.presolve {
+                None => {
+                    Clp_initialSolve(self.ptr);
+                }
+                Some(presolve) => {
+                    let options = ClpSolve_new();
+                    // Presolve type 0 is on, and 1 is off.
+                    ClpSolve_setPresolveType(options, if presolve { 0 } else { 1 }, -1);
+                    Clp_initialSolveWithOptions(self.ptr, options);
+                    ClpSolve_delete(options);
+                }
+            }
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &SolverSettings) {
+        unsafe {
+            Clp_setLogLevel(self.ptr, if settings.verbose { 1 } else { 0 });
+            if let Some(tol) = settings.primal_tolerance {
+                Clp_setPrimalTolerance(self.ptr, tol);
+            }
+            if let Some(tol) = settings.dual_tolerance {
+                Clp_setDualTolerance(self.ptr, tol);
+            }
+            if let Some(time_limit) = settings.time_limit {
+                Clp_setMaximumSeconds(self.ptr, time_limit);
+            }
+        }
+        self.presolve = settings.presolve;
+    }
+
+    fn is_proven_optimal(&self) -> bool {
+        unsafe { Clp_isProvenOptimal(self.ptr) != 0 }
+    }
+
+    /// The number of iterations in the most recent call to Clp.
+    fn number_iterations(&self) -> u64 {
+        unsafe { Clp_numberIterations(self.ptr) as u64 }
+    }
+
     fn dual_solve(&mut self) {
         unsafe {
             Clp_dual(self.ptr, 0);
         }
     }
 
+    fn barrier_solve(&mut self) {
+        unsafe {
+            Clp_initialBarrierSolve(self.ptr);
+        }
+    }
+
     fn primal_column_solution(&mut self, number: usize) -> Vec<c_double> {
         let solution: Vec<c_double>;
         unsafe {
@@ -155,6 +257,56 @@ impl ClpSimplex {
         ub
     }
 
+    /// Overwrite the entries of one of the model's arrays that differ from `values`.
+    ///
+    /// Returns the number of entries that were changed.
+    unsafe fn update_array(data_ptr: *mut c_double, values: &[c_double]) -> usize {
+        let current = slice::from_raw_parts_mut(data_ptr, values.len());
+        let mut changed = 0;
+        for (c, &v) in current.iter_mut().zip(values) {
+            if *c != v {
+                *c = v;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Update only the objective coefficients, row bounds and matrix elements that differ from
+    /// `builder`. Returns the number of entries that were changed.
+    fn update_from_builder(&mut self, builder: &LpBuilder) -> usize {
+        let mut changed = 0;
+        for (row, col, value) in builder.changed_elements(&self.elements) {
+            unsafe {
+                Clp_modifyCoefficient(self.ptr, row as c_int, col as c_int, value, 1);
+            }
+            changed += 1;
+        }
+        if changed > 0 {
+            self.elements.copy_from_slice(builder.elements());
+        }
+        unsafe {
+            changed
+                + Self::update_array(Clp_objective(self.ptr), builder.col_obj_coef())
+                + Self::update_array(Clp_rowLower(self.ptr), builder.row_lower())
+                + Self::update_array(Clp_rowUpper(self.ptr), builder.row_upper())
+        }
+    }
+
+    fn dual_row_solution(&mut self, number: usize) -> Vec<c_double> {
+        unsafe {
+            let data_ptr = Clp_dualRowSolution(self.ptr);
+            slice::from_raw_parts(data_ptr, number).to_vec()
+        }
+    }
+
+    fn dual_column_solution(&mut self, number: usize) -> Vec<c_double> {
+        unsafe {
+            let data_ptr = Clp_dualColumnSolution(self.ptr);
+            slice::from_raw_parts(data_ptr, number).to_vec()
+        }
+    }
+
     fn objective_value(&self) -> c_double {
         unsafe { Clp_objectiveValue(self.ptr) }
     }
@@ -164,6 +316,8 @@ impl ClpSimplex {
 pub struct ClpSolution {
     objective_value: f64,
     primal_columns: Vec<f64>,
+    dual_rows: Vec<f64>,
+    reduced_costs: Vec<f64>,
 }
 
 impl ClpSolution {
@@ -172,368 +326,226 @@ impl ClpSolution {
     }
 }
 
-#[derive(Debug)]
-pub enum Bounds {
-    Free,
-    Lower(f64),
-    Upper(f64),
-    Double(f64, f64),
-    Fixed(f64),
-}
-
-pub struct ClpModelBuilder {
-    col_lower: Vec<c_double>,
-    col_upper: Vec<c_double>,
-    col_obj_coef: Vec<c_double>,
-    row_lower: Vec<c_double>,
-    row_upper: Vec<c_double>,
-    row_starts: Vec<CoinBigIndex>,
-    columns: Vec<c_int>,
-    elements: Vec<c_double>,
-    model: Option<ClpSimplex>,
-}
-
-impl ClpModelBuilder {
-    pub fn new() -> Self {
-        Self {
-            col_lower: Vec::new(),
-            col_upper: Vec::new(),
-            col_obj_coef: Vec::new(),
-            row_lower: Vec::new(),
-            row_upper: Vec::new(),
-            row_starts: vec![0],
-            columns: Vec::new(),
-            elements: Vec::new(),
-            model: None,
-        }
-    }
-
-    pub fn add_column(&mut self, obj_coef: f64, bounds: Bounds) {
-        let (lb, ub): (f64, f64) = match bounds {
-            Bounds::Double(lb, ub) => (lb, ub),
-            Bounds::Lower(lb) => (lb, f64::MAX),
-            Bounds::Fixed(b) => (b, b),
-            Bounds::Free => (f64::MIN, f64::MAX),
-            Bounds::Upper(ub) => (f64::MIN, ub),
-        };
-
-        self.col_lower.push(lb);
-        self.col_upper.push(ub);
-        self.col_obj_coef.push(obj_coef);
-    }
-
-    pub fn set_obj_coefficient(&mut self, col: usize, obj_coef: f64) {
-        self.col_obj_coef[col] = obj_coef;
-    }
-
-    pub fn set_row_bounds(&mut self, row: usize, lb: f64, ub: f64) {
-        self.row_lower[row] = lb;
-        self.row_upper[row] = ub;
-    }
-
-    pub fn add_row(&mut self, row: ClpRowBuilder) {
-        self.row_lower.push(row.lower);
-        self.row_upper.push(row.upper);
-        let prev_row_start = *self.row_starts.get(&self.row_starts.len() - 1).unwrap();
-        self.row_starts.push(prev_row_start + row.columns.len() as CoinBigIndex);
-        for (column, value) in row.columns {
-            self.columns.push(column);
-            self.elements.push(value);
-        }
-    }
+impl ClpSimplex {
+    /// Create a new simplex model from the data in `builder`.
+    fn from_builder(builder: &LpBuilder) -> Self {
+        let mut model = ClpSimplex::new();
+        model.resize(0, builder.ncols() as c_int);
 
-    pub fn nrows(&self) -> usize {
-        self.row_upper.len()
-    }
+        model.change_column_lower(builder.col_lower());
+        model.change_column_upper(builder.col_upper());
+        model.change_objective_coefficients(builder.col_obj_coef());
 
-    pub fn setup(&mut self) {
-        let mut model = ClpSimplex::new();
-        model.resize(0, self.col_upper.len() as i32);
+        let row_starts: Vec<CoinBigIndex> = builder.row_starts().iter().map(|&i| i as CoinBigIndex).collect();
+        let columns: Vec<c_int> = builder.columns().iter().map(|&i| i as c_int).collect();
 
-        model.change_column_lower(&self.col_lower);
-        model.change_column_upper(&self.col_upper);
-        model.change_objective_coefficients(&self.col_obj_coef);
-        println!("Adding rows ...");
         model.add_rows(
-            &self.row_lower,
-            &self.row_upper,
-            &self.row_starts,
-            &self.columns,
-            &self.elements,
+            builder.row_lower(),
+            builder.row_upper(),
+            &row_starts,
+            &columns,
+            builder.elements(),
         );
+        model.elements = builder.elements().to_vec();
 
-        // println!("row_lower: {:?}", self.row_lower);
-        // println!("row_upper: {:?}", self.row_upper);
-        // println!("row_starts: {:?}", self.row_starts);
-        // println!("columns: {:?}", self.columns);
-        // println!("elements: {:?}", self.elements);
-        // println!("obj_coef: {:?}", self.col_obj_coef);
-
-        model.initial_dual_solve();
-
-        self.model = Some(model);
+        model
     }
 
-    pub fn solve(&mut self) -> Result<ClpSolution, ClpError> {
-        // let mut model = ClpSimplex::new();
-        let model = match &mut self.model {
-            Some(m) => m,
-            None => return Err(ClpError::SimplexNotInitialisedError),
-        };
-
-        //model.change_column_lower(&self.col_lower);
-        //model.change_column_upper(&self.col_upper);
-        model.change_objective_coefficients(&self.col_obj_coef);
-        model.change_row_lower(&self.row_lower);
-        model.change_row_upper(&self.row_upper);
-
-        // println!("number: {}", number);
-        // println!("row_lower: {:?}", self.row_lower);
-        // println!("row_upper: {:?}", self.row_upper);
-        // println!("row_starts: {:?}", self.row_starts);
-        // println!("columns: {:?}", self.columns);
-        // println!("elements: {:?}", self.elements);
-        // println!("obj_coef: {:?}", self.col_obj_coef);
-
-        // model.add_rows(
-        //     &self.row_lower,
-        //     &self.row_upper,
-        //     &self.row_starts,
-        //     &self.columns,
-        //     &self.elements,
-        // );
-        // println!("coef: {:?}", model.get_objective_coefficients(2));
-        // println!("row_upper: {:?}", model.get_row_upper(4));
-        //let now = Instant::now();
-        model.dual_solve();
-        //let t = now.elapsed().as_secs_f64();
-        // println!("dual solve: {} s; {} per s", t, 1.0/t);
-        // println!("coef: {:?}", model.get_objective_coefficients(2));
-
-        let solution = ClpSolution {
-            objective_value: model.objective_value(),
-            primal_columns: model.primal_column_solution(self.col_upper.len()),
-        };
-
-        Ok(solution)
+    /// The number of iterations in the most recent solve.
+    fn iterations(&self) -> u64 {
+        self.iterations
     }
-}
 
-pub struct ClpRowBuilder {
-    lower: f64,
-    upper: f64,
-    columns: Vec<(i32, f64)>,
-}
-
-impl ClpRowBuilder {
-    pub fn new() -> Self {
-        Self {
-            lower: 0.0,
-            upper: f64::MAX,
-            columns: Vec::new(),
+    /// Update the objective and row bounds from `builder` and re-solve.
+    ///
+    /// The constraint matrix is created once in `from_builder`, and only the objective
+    /// coefficients, row bounds and matrix elements that have changed are updated here. The
+    /// structure of the problem is unchanged, so the optimal basis from the previous solve is used
+    /// as the starting point for the dual simplex, even after changes to the matrix elements. If
+    /// there is no previous basis, or the warm-started solve fails, the problem is solved from
+    /// scratch instead.
+    fn solve_builder(&mut self, builder: &LpBuilder) -> Result<ClpSolution, ClpError> {
+        self.update_from_builder(builder);
+        self.iterations = 0;
+
+        let solved = match self.algorithm {
+            ClpAlgorithm::DualSimplex if self.has_basis => {
+                self.dual_solve();
+                true
+            }
+            ClpAlgorithm::Barrier => {
+                self.barrier_solve();
+                true
+            }
+            _ => false,
+        };
+        if solved {
+            self.iterations += self.number_iterations();
         }
-    }
-
-    pub fn set_upper(&mut self, upper: f64) {
-        self.upper = upper;
-    }
 
-    pub fn set_lower(&mut self, lower: f64) {
-        self.lower = lower
-    }
+        if !solved || !self.is_proven_optimal() {
+            self.initial_solve();
+            self.iterations += self.number_iterations();
+        }
 
-    // pub fn set_bounds(&mut self, bounds: Bounds) {
-    //     let (lb, ub) = match bounds
-    // }
+        self.has_basis = self.is_proven_optimal();
+        if !self.has_basis {
+            return Err(ClpError::NotOptimal);
+        }
 
-    pub fn add_element(&mut self, column: i32, value: f64) {
-        self.columns.push((column, value))
+        Ok(ClpSolution {
+            objective_value: self.objective_value(),
+            primal_columns: self.primal_column_solution(builder.ncols()),
+            dual_rows: self.dual_row_solution(builder.nrows()),
+            reduced_costs: self.dual_column_solution(builder.ncols()),
+        })
     }
 }
 
 pub struct ClpSolver {
-    builder: ClpModelBuilder,
-    start_node_constraints: Option<usize>,
+    settings: ClpSettings,
+    lp: Option<NetworkLp>,
+    model: Option<ClpSimplex>,
+    batch: Option<(BatchNetworkLp, ClpSimplex)>,
+    horizon: Option<(HorizonNetworkLp, ClpSimplex)>,
+    solver_settings: SolverSettings,
+    stats: SolverStats,
 }
 
 impl ClpSolver {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(settings: ClpSettings) -> Self {
         Self {
-            builder: ClpModelBuilder::new(),
-            start_node_constraints: None,
+            settings,
+            lp: None,
+            model: None,
+            batch: None,
+            horizon: None,
+            solver_settings: SolverSettings::default(),
+            stats: SolverStats::default(),
         }
     }
+}
 
-    /// Create a column for each edge
-    fn create_columns(&mut self, model: &Model) -> Result<(), PywrError> {
-        // One column per edge
-        let ncols = model.edges.len();
-        if ncols < 1 {
-            return Err(PywrError::NoEdgesDefined);
-        }
-        // Add columns set the columns as x >= 0.0 (i.e. no upper bounds)
-        for _ in 0..ncols {
-            self.builder.add_column(0.0, Bounds::Lower(0.0));
-        }
-
-        Ok(())
+impl Solver for ClpSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[SolverFeature::MultiTimestep]
     }
 
-    /// Create mass balance constraints for each edge
-    fn create_mass_balance_constraints(&mut self, model: &Model) {
-        for node in &model.nodes {
-            // Only link nodes create mass-balance constraints
-
-            let mut row = ClpRowBuilder::new();
-
-            if let NodeType::Link = node.node_type() {
-                let incoming_edges = node.get_incoming_edges().unwrap();
-                let outgoing_edges = node.get_outgoing_edges().unwrap();
-
-                // TODO check for length >= 1
-
-                for edge in &incoming_edges {
-                    row.add_element(edge.index() as i32, 1.0);
-                }
-                for edge in &outgoing_edges {
-                    row.add_element(edge.index() as i32, -1.0);
-                }
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new(self.settings.clone()))
+    }
 
-                row.set_upper(0.0);
-                row.set_lower(0.0);
-            }
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.solver_settings = settings.clone();
+        self.batch = None;
+        self.horizon = None;
+        let lp = NetworkLp::new(model, settings)?;
+        let mut simplex = ClpSimplex::from_builder(lp.builder());
+        simplex.apply_settings(settings);
+        simplex.algorithm = self.settings.algorithm;
+        self.model = Some(simplex);
+        self.lp = Some(lp);
 
-            self.builder.add_row(row);
-        }
+        Ok(())
     }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, simplex) = match (&mut self.lp, &mut self.model) {
+            (Some(lp), Some(simplex)) => (lp, simplex),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
 
-    /// Create node constraints
-    ///
-    /// One constraint is created per node to enforce any constraints (flow or storage)
-    /// that it may define.
-    fn create_node_constraints(&mut self, model: &Model) {
-        let start_row = self.builder.nrows();
-
-        for node in &model.nodes {
-            // Create empty arrays to store the matrix data
-            let mut row = ClpRowBuilder::new();
-
-            match node.node_type() {
-                NodeType::Link => {
-                    for edge in node.get_outgoing_edges().unwrap() {
-                        row.add_element(edge.index() as i32, 1.0);
-                    }
-                }
-                NodeType::Input => {
-                    for edge in node.get_outgoing_edges().unwrap() {
-                        row.add_element(edge.index() as i32, 1.0);
-                    }
-                }
-                NodeType::Output => {
-                    for edge in node.get_incoming_edges().unwrap() {
-                        row.add_element(edge.index() as i32, 1.0);
-                    }
-                }
-                NodeType::Storage => {
-                    for edge in node.get_incoming_edges().unwrap() {
-                        row.add_element(edge.index() as i32, 1.0);
-                    }
-                    for edge in node.get_outgoing_edges().unwrap() {
-                        row.add_element(edge.index() as i32, -1.0);
-                    }
-                }
-            }
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = simplex.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), simplex.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
 
-            self.builder.add_row(row);
-            self.start_node_constraints = Some(start_row);
-        }
+        Ok(lp.solution(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
     }
 
-    /// Update edge objective coefficients
-    fn update_edge_objectives(&mut self, model: &Model, parameter_states: &[f64]) -> Result<(), PywrError> {
-        for edge in &model.edges {
-            let cost: f64 = edge.cost(parameter_states)?;
-            self.builder.set_obj_coefficient(edge.index(), cost);
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of scenarios changes.
+        if !matches!(&self.batch, Some((lp, _)) if lp.nscenarios() == updates.len()) {
+            let lp = BatchNetworkLp::new(network_lp, updates.len());
+            let mut simplex = ClpSimplex::from_builder(lp.builder());
+            simplex.apply_settings(&self.solver_settings);
+            simplex.algorithm = self.settings.algorithm;
+            self.batch = Some((lp, simplex));
         }
-        Ok(())
-    }
-
-    /// Update node constraints
-    fn update_node_constraint_bounds(
-        &mut self,
-        model: &Model,
-        timestep: &Timestep,
-        network_state: &NetworkState,
-        parameter_states: &[f64],
-    ) -> Result<(), PywrError> {
-        let start_row = match self.start_node_constraints {
-            Some(r) => r,
+        let (lp, simplex) = match &mut self.batch {
+            Some((lp, simplex)) => (lp, simplex),
             None => return Err(PywrError::SolverNotSetup),
         };
 
-        for node in &model.nodes {
-            let (lb, ub): (f64, f64) = match node.get_current_flow_bounds(parameter_states) {
-                Ok(bnds) => bnds,
-                Err(PywrError::FlowConstraintsUndefined) => {
-                    // Must be a storage node
-                    let (avail, missing) =
-                        match node.get_current_available_volume_bounds(network_state, parameter_states) {
-                            Ok(bnds) => bnds,
-                            Err(e) => return Err(e),
-                        };
-                    let dt = timestep.days();
-                    (-avail / dt, missing / dt)
-                }
-                Err(e) => return Err(e),
-            };
-
-            self.builder.set_row_bounds(start_row + node.index(), lb, ub);
-        }
+        lp.update(updates)?;
+        let start = Instant::now();
+        let result = simplex.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), simplex.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
 
-        Ok(())
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
     }
-}
 
-impl Solver for ClpSolver {
-    fn setup(&mut self, model: &Model) -> Result<(), PywrError> {
-        // Create the columns
-        self.create_columns(model)?;
-        // Create edge mass balance constraints
-        self.create_mass_balance_constraints(model);
-        // Create the nodal constraints
-        self.create_node_constraints(model);
+    fn solve_horizon(&mut self, update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of timesteps changes.
+        if !matches!(&self.horizon, Some((lp, _)) if lp.ntimesteps() == update.updates.len()) {
+            let lp = HorizonNetworkLp::new(network_lp, update.updates.len());
+            let mut simplex = ClpSimplex::from_builder(lp.builder());
+            simplex.apply_settings(&self.solver_settings);
+            simplex.algorithm = self.settings.algorithm;
+            self.horizon = Some((lp, simplex));
+        }
+        let (lp, simplex) = match &mut self.horizon {
+            Some((lp, simplex)) => (lp, simplex),
+            None => return Err(PywrError::SolverNotSetup),
+        };
 
-        self.builder.setup();
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = simplex.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), simplex.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
 
-        Ok(())
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
     }
-    fn solve(
-        &mut self,
-        model: &Model,
-        timestep: &Timestep,
-        network_state: &NetworkState,
-        parameter_state: &[f64],
-    ) -> Result<NetworkState, PywrError> {
-        self.update_edge_objectives(model, parameter_state)?;
-        self.update_node_constraint_bounds(model, timestep, network_state, parameter_state)?;
-
-        let solution = self.builder.solve()?;
-        // println!("{:?}", solution);
-        // Create the updated network state from the results
-        let mut new_state = network_state.with_capacity();
 
-        for edge in &model.edges {
-            let flow = solution.get_solution(edge.index());
-            new_state.add_flow(edge, timestep, flow)?;
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
         }
+    }
 
-        Ok(new_state)
+    fn stats(&self) -> &SolverStats {
+        &self.stats
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
     use float_cmp::approx_eq;
 
     #[test]
@@ -557,13 +569,13 @@ mod tests {
 
     #[test]
     fn model_builder_new() {
-        let _builder = ClpModelBuilder::new();
+        let _builder = LpBuilder::new();
     }
 
     #[test]
     fn builder_add_rows() {
-        let mut builder = ClpModelBuilder::new();
-        let mut row = ClpRowBuilder::new();
+        let mut builder = LpBuilder::new();
+        let mut row = RowBuilder::new();
         row.add_element(0, 1.0);
         row.add_element(1, 1.0);
         row.set_lower(0.0);
@@ -573,14 +585,14 @@ mod tests {
 
     #[test]
     fn builder_solve() {
-        let mut builder = ClpModelBuilder::new();
+        let mut builder = LpBuilder::new();
 
         builder.add_column(1.0, Bounds::Double(0.0, 2.0));
         builder.add_column(0.0, Bounds::Lower(0.0));
         builder.add_column(4.0, Bounds::Double(0.0, 4.0));
 
         // Row1
-        let mut row = ClpRowBuilder::new();
+        let mut row = RowBuilder::new();
         row.add_element(0, 1.0);
         row.add_element(2, 1.0);
         row.set_lower(2.0);
@@ -588,7 +600,7 @@ mod tests {
         builder.add_row(row);
 
         // Row2
-        let mut row = ClpRowBuilder::new();
+        let mut row = RowBuilder::new();
         row.add_element(0, 1.0);
         row.add_element(1, -5.0);
         row.add_element(2, 1.0);
@@ -596,23 +608,22 @@ mod tests {
         row.set_upper(1.0);
         builder.add_row(row);
 
-        builder.setup();
-
-        let solution = builder.solve().unwrap();
+        let mut model = ClpSimplex::from_builder(&builder);
+        let solution = model.solve_builder(&builder).unwrap();
 
         assert!(approx_eq!(f64, solution.objective_value, 2.0));
     }
 
     #[test]
     fn builder_solve2() {
-        let mut builder = ClpModelBuilder::new();
+        let mut builder = LpBuilder::new();
 
         builder.add_column(-2.0, Bounds::Lower(0.0));
         builder.add_column(-3.0, Bounds::Lower(0.0));
         builder.add_column(-4.0, Bounds::Lower(0.0));
 
         // Row1
-        let mut row = ClpRowBuilder::new();
+        let mut row = RowBuilder::new();
         row.add_element(0, 3.0);
         row.add_element(1, 2.0);
         row.add_element(2, 1.0);
@@ -621,7 +632,7 @@ mod tests {
         builder.add_row(row);
 
         // Row2
-        let mut row = ClpRowBuilder::new();
+        let mut row = RowBuilder::new();
         row.add_element(0, 2.0);
         row.add_element(1, 5.0);
         row.add_element(2, 3.0);
@@ -629,11 +640,72 @@ mod tests {
         row.set_upper(15.0);
         builder.add_row(row);
 
-        builder.setup();
-
-        let solution = builder.solve().unwrap();
+        let mut model = ClpSimplex::from_builder(&builder);
+        let solution = model.solve_builder(&builder).unwrap();
 
         assert!(approx_eq!(f64, solution.objective_value, -20.0));
         assert_eq!(solution.primal_columns, vec![0.0, 0.0, 5.0])
     }
+
+    #[test]
+    fn builder_resolve() {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(-1.0, Bounds::Lower(0.0));
+        builder.add_column(-1.0, Bounds::Lower(0.0));
+
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, 1.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(4.0);
+        builder.add_row(row);
+
+        let mut model = ClpSimplex::from_builder(&builder);
+        let solution = model.solve_builder(&builder).unwrap();
+        assert!(approx_eq!(f64, solution.objective_value, -4.0));
+        assert!(model.has_basis);
+
+        // Change the bounds and re-solve from the previous basis
+        builder.set_row_bounds(0, f64::MIN, 2.0);
+        let solution = model.solve_builder(&builder).unwrap();
+        assert!(approx_eq!(f64, solution.objective_value, -2.0));
+
+        // An infeasible update is reported, and the basis is discarded
+        builder.set_row_bounds(0, 6.0, 2.0);
+        assert_eq!(model.solve_builder(&builder).unwrap_err(), ClpError::NotOptimal);
+        assert!(!model.has_basis);
+    }
+
+    #[test]
+    fn builder_update_changed_only() {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(-1.0, Bounds::Lower(0.0));
+        builder.add_column(-2.0, Bounds::Lower(0.0));
+
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.set_lower(0.0);
+        row.set_upper(4.0);
+        builder.add_row(row);
+
+        let mut row = RowBuilder::new();
+        row.add_element(1, 1.0);
+        row.set_lower(0.0);
+        row.set_upper(3.0);
+        builder.add_row(row);
+
+        let mut model = ClpSimplex::from_builder(&builder);
+        assert_eq!(model.update_from_builder(&builder), 0);
+
+        builder.set_row_bounds(1, 0.0, 5.0);
+        builder.set_obj_coefficient(0, -3.0);
+        assert_eq!(model.update_from_builder(&builder), 2);
+        assert_eq!(model.get_row_upper(2), vec![4.0, 5.0]);
+        assert_eq!(model.get_objective_coefficients(2), vec![-3.0, -2.0]);
+
+        let solution = model.solve_builder(&builder).unwrap();
+        assert!(approx_eq!(f64, solution.objective_value, -22.0));
+    }
 }
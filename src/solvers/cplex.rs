@@ -0,0 +1,584 @@
+use crate::model::Model;
+use crate::solvers::builder::{BatchNetworkLp, HorizonNetworkLp, LpBuilder, NetworkLp};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::PywrError;
+use cplex_sys::*;
+use libc::{c_char, c_double, c_int};
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use std::ptr;
+use std::time::Instant;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CplexError {
+    #[error("CPLEX error {0}: {1}")]
+    Error(i32, String),
+    #[error("CPLEX did not find an optimal solution (status {0}).")]
+    NotOptimal(i32),
+}
+
+/// The algorithm CPLEX uses to solve the LP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CplexMethod {
+    Automatic,
+    PrimalSimplex,
+    DualSimplex,
+    Barrier,
+    Concurrent,
+}
+
+impl CplexMethod {
+    fn value(&self) -> c_int {
+        match self {
+            Self::Automatic => CPX_ALG_AUTOMATIC,
+            Self::PrimalSimplex => CPX_ALG_PRIMAL,
+            Self::DualSimplex => CPX_ALG_DUAL,
+            Self::Barrier => CPX_ALG_BARRIER,
+            Self::Concurrent => CPX_ALG_CONCURRENT,
+        }
+    }
+}
+
+/// CPLEX specific parameters passed when the solver is setup.
+///
+/// Options common to all solvers are given by `SolverSettings`.
+#[derive(Clone, Debug)]
+pub struct CplexSettings {
+    pub method: CplexMethod,
+}
+
+impl Default for CplexSettings {
+    fn default() -> Self {
+        Self {
+            method: CplexMethod::DualSimplex,
+        }
+    }
+}
+
+/// Convert the builder's representation of infinite bounds to CPLEX's.
+fn to_cpx_bound(value: f64) -> c_double {
+    value.max(-CPX_INFBOUND).min(CPX_INFBOUND)
+}
+
+struct CplexModel {
+    env: CPXENVptr,
+    lp: CPXLPptr,
+    ncols: usize,
+    has_integers: bool,
+    /// Indices and bound types used to update the slack columns.
+    slack_indices: Vec<c_int>,
+    slack_lu: Vec<c_char>,
+    /// The matrix elements as last loaded from the builder.
+    elements: Vec<f64>,
+    /// Number of iterations in the most recent solve.
+    iterations: u64,
+}
+
+impl CplexModel {
+    /// Create a new CPLEX problem from the data in `builder`.
+    ///
+    /// As with the Gurobi backend each row is formulated as an equality with an additional
+    /// bounded slack column, so that the row bounds can be updated each timestep by changing
+    /// only the bounds of the slack columns.
+    fn from_builder(
+        builder: &LpBuilder,
+        settings: &SolverSettings,
+        cplex_settings: &CplexSettings,
+    ) -> Result<Self, CplexError> {
+        let ncols = builder.ncols();
+        let nrows = builder.nrows();
+
+        let mut status: c_int = 0;
+        let env = unsafe { CPXopenCPLEX(&mut status) };
+        if env.is_null() {
+            return Err(CplexError::Error(
+                status,
+                "Failed to create the CPLEX environment.".to_string(),
+            ));
+        }
+
+        let mut model = Self {
+            env,
+            lp: ptr::null_mut(),
+            ncols,
+            has_integers: builder.col_integer().iter().any(|&i| i),
+            slack_indices: (ncols..ncols + nrows).map(|i| i as c_int).collect(),
+            slack_lu: vec![b'L' as c_char; nrows]
+                .into_iter()
+                .chain(vec![b'U' as c_char; nrows])
+                .collect(),
+            elements: builder.elements().to_vec(),
+            iterations: 0,
+        };
+        model.apply_settings(settings, cplex_settings)?;
+
+        let name = CString::new("pywr").unwrap();
+        model.lp = unsafe { CPXcreateprob(env, &mut status, name.as_ptr()) };
+        if model.lp.is_null() {
+            return Err(model.error(status));
+        }
+
+        let mut obj: Vec<c_double> = builder.col_obj_coef().to_vec();
+        obj.resize(ncols + nrows, 0.0);
+        let lb: Vec<c_double> = builder
+            .col_lower()
+            .iter()
+            .chain(builder.row_lower())
+            .map(|&v| to_cpx_bound(v))
+            .collect();
+        let ub: Vec<c_double> = builder
+            .col_upper()
+            .iter()
+            .chain(builder.row_upper())
+            .map(|&v| to_cpx_bound(v))
+            .collect();
+        // Only give column types for MIPs; otherwise CPLEX treats the problem as an LP.
+        let ctype: Vec<c_char> = builder
+            .col_integer()
+            .iter()
+            .map(|&i| if i { CPX_INTEGER } else { CPX_CONTINUOUS })
+            .chain(vec![CPX_CONTINUOUS; nrows])
+            .collect();
+        let ctype_ptr = if model.has_integers {
+            ctype.as_ptr()
+        } else {
+            ptr::null()
+        };
+
+        let ret = unsafe {
+            CPXnewcols(
+                env,
+                model.lp,
+                (ncols + nrows) as c_int,
+                obj.as_ptr(),
+                lb.as_ptr(),
+                ub.as_ptr(),
+                ctype_ptr,
+                ptr::null(),
+            )
+        };
+        model.check(ret)?;
+
+        let mut rmatbeg: Vec<c_int> = Vec::with_capacity(nrows);
+        let mut rmatind: Vec<c_int> = Vec::new();
+        let mut rmatval: Vec<c_double> = Vec::new();
+        for row in 0..nrows {
+            rmatbeg.push(rmatind.len() as c_int);
+            for i in builder.row_starts()[row]..builder.row_starts()[row + 1] {
+                rmatind.push(builder.columns()[i] as c_int);
+                rmatval.push(builder.elements()[i]);
+            }
+            // Slack column for this row
+            rmatind.push((ncols + row) as c_int);
+            rmatval.push(-1.0);
+        }
+        let sense: Vec<c_char> = vec![b'E' as c_char; nrows];
+        let rhs: Vec<c_double> = vec![0.0; nrows];
+
+        let ret = unsafe {
+            CPXaddrows(
+                env,
+                model.lp,
+                0,
+                nrows as c_int,
+                rmatind.len() as c_int,
+                rhs.as_ptr(),
+                sense.as_ptr(),
+                rmatbeg.as_ptr(),
+                rmatind.as_ptr(),
+                rmatval.as_ptr(),
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        model.check(ret)?;
+
+        Ok(model)
+    }
+
+    fn apply_settings(&mut self, settings: &SolverSettings, cplex_settings: &CplexSettings) -> Result<(), CplexError> {
+        let screen = if settings.verbose { CPX_ON } else { CPX_OFF };
+        self.check(unsafe { CPXsetintparam(self.env, CPX_PARAM_SCRIND, screen) })?;
+        self.check(unsafe { CPXsetintparam(self.env, CPX_PARAM_LPMETHOD, cplex_settings.method.value()) })?;
+        if let Some(threads) = settings.threads {
+            self.check(unsafe { CPXsetintparam(self.env, CPX_PARAM_THREADS, threads as c_int) })?;
+        }
+        if let Some(time_limit) = settings.time_limit {
+            self.check(unsafe { CPXsetdblparam(self.env, CPX_PARAM_TILIM, time_limit) })?;
+        }
+        if let Some(presolve) = settings.presolve {
+            let presolve = if presolve { CPX_ON } else { CPX_OFF };
+            self.check(unsafe { CPXsetintparam(self.env, CPX_PARAM_PREIND, presolve) })?;
+        }
+        if let Some(tol) = settings.primal_tolerance {
+            self.check(unsafe { CPXsetdblparam(self.env, CPX_PARAM_EPRHS, tol) })?;
+        }
+        if let Some(tol) = settings.dual_tolerance {
+            self.check(unsafe { CPXsetdblparam(self.env, CPX_PARAM_EPOPT, tol) })?;
+        }
+        Ok(())
+    }
+
+    fn error(&self, ret: c_int) -> CplexError {
+        let mut buffer = vec![0 as c_char; CPXMESSAGEBUFSIZE];
+        let msg = unsafe {
+            let msg_ptr = CPXgeterrorstring(self.env, ret, buffer.as_mut_ptr());
+            if msg_ptr.is_null() {
+                "Unknown error.".to_string()
+            } else {
+                CStr::from_ptr(msg_ptr).to_string_lossy().trim().to_string()
+            }
+        };
+        CplexError::Error(ret, msg)
+    }
+
+    /// Convert a non-zero return code in to an error with CPLEX's error message.
+    fn check(&self, ret: c_int) -> Result<(), CplexError> {
+        match ret {
+            0 => Ok(()),
+            _ => Err(self.error(ret)),
+        }
+    }
+
+    /// Update any matrix elements that have changed since they were last loaded.
+    fn update_elements(&mut self, builder: &LpBuilder) -> Result<(), CplexError> {
+        let mut rows: Vec<c_int> = Vec::new();
+        let mut cols: Vec<c_int> = Vec::new();
+        let mut values: Vec<c_double> = Vec::new();
+        for (row, col, value) in builder.changed_elements(&self.elements) {
+            rows.push(row as c_int);
+            cols.push(col as c_int);
+            values.push(value);
+        }
+
+        if !values.is_empty() {
+            let ret = unsafe {
+                CPXchgcoeflist(
+                    self.env,
+                    self.lp,
+                    values.len() as c_int,
+                    rows.as_ptr(),
+                    cols.as_ptr(),
+                    values.as_ptr(),
+                )
+            };
+            self.check(ret)?;
+            self.elements.copy_from_slice(builder.elements());
+        }
+        Ok(())
+    }
+
+    /// The number of iterations in the most recent solve.
+    fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Update the objective, row bounds and matrix elements from `builder` and re-solve.
+    fn solve_builder(&mut self, builder: &LpBuilder) -> Result<CplexSolution, CplexError> {
+        self.update_elements(builder)?;
+
+        let col_indices: Vec<c_int> = (0..self.ncols as c_int).collect();
+        let ret = unsafe {
+            CPXchgobj(
+                self.env,
+                self.lp,
+                self.ncols as c_int,
+                col_indices.as_ptr(),
+                builder.col_obj_coef().as_ptr(),
+            )
+        };
+        self.check(ret)?;
+
+        let indices: Vec<c_int> = self.slack_indices.iter().chain(&self.slack_indices).copied().collect();
+        let bounds: Vec<c_double> = builder
+            .row_lower()
+            .iter()
+            .chain(builder.row_upper())
+            .map(|&v| to_cpx_bound(v))
+            .collect();
+        let ret = unsafe {
+            CPXchgbds(
+                self.env,
+                self.lp,
+                indices.len() as c_int,
+                indices.as_ptr(),
+                self.slack_lu.as_ptr(),
+                bounds.as_ptr(),
+            )
+        };
+        self.check(ret)?;
+
+        let ret = if self.has_integers {
+            unsafe { CPXmipopt(self.env, self.lp) }
+        } else {
+            unsafe { CPXlpopt(self.env, self.lp) }
+        };
+        self.check(ret)?;
+        self.iterations = if self.has_integers {
+            unsafe { CPXgetmipitcnt(self.env, self.lp) as u64 }
+        } else {
+            unsafe { CPXgetitcnt(self.env, self.lp) as u64 }
+        };
+
+        let status = unsafe { CPXgetstat(self.env, self.lp) };
+        match status {
+            CPX_STAT_OPTIMAL | CPXMIP_OPTIMAL | CPXMIP_OPTIMAL_TOL => {}
+            _ => return Err(CplexError::NotOptimal(status)),
+        }
+
+        let mut objective_value: c_double = 0.0;
+        self.check(unsafe { CPXgetobjval(self.env, self.lp, &mut objective_value) })?;
+
+        let mut primal_columns: Vec<c_double> = vec![0.0; self.ncols];
+        let ret = unsafe {
+            CPXgetx(
+                self.env,
+                self.lp,
+                primal_columns.as_mut_ptr(),
+                0,
+                self.ncols as c_int - 1,
+            )
+        };
+        self.check(ret)?;
+
+        // Duals are only available if there are no integer columns. The dual of each row's
+        // equality constraint is also the reduced cost of its slack column.
+        let (dual_rows, reduced_costs) = if self.has_integers {
+            (Vec::new(), Vec::new())
+        } else {
+            let nrows = builder.nrows();
+            let mut dual_rows: Vec<c_double> = vec![0.0; nrows];
+            if nrows > 0 {
+                let ret = unsafe { CPXgetpi(self.env, self.lp, dual_rows.as_mut_ptr(), 0, nrows as c_int - 1) };
+                self.check(ret)?;
+            }
+            let mut reduced_costs: Vec<c_double> = vec![0.0; self.ncols];
+            let ret = unsafe {
+                CPXgetdj(
+                    self.env,
+                    self.lp,
+                    reduced_costs.as_mut_ptr(),
+                    0,
+                    self.ncols as c_int - 1,
+                )
+            };
+            self.check(ret)?;
+            (dual_rows, reduced_costs)
+        };
+
+        Ok(CplexSolution {
+            objective_value,
+            primal_columns,
+            dual_rows,
+            reduced_costs,
+        })
+    }
+}
+
+impl Drop for CplexModel {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.lp.is_null() {
+                CPXfreeprob(self.env, &mut self.lp);
+            }
+            CPXcloseCPLEX(&mut self.env);
+        }
+    }
+}
+
+// SAFETY: the model is owned exclusively by this value and CPLEX does not tie it to the thread
+// that created it, so it may be moved to, used on and dropped by another thread.
+unsafe impl Send for CplexModel {}
+
+#[derive(Debug)]
+pub struct CplexSolution {
+    objective_value: f64,
+    primal_columns: Vec<f64>,
+    dual_rows: Vec<f64>,
+    reduced_costs: Vec<f64>,
+}
+
+/// A solver using the commercial IBM ILOG CPLEX optimiser.
+///
+/// A valid CPLEX licence is required at runtime.
+pub struct CplexSolver {
+    settings: CplexSettings,
+    lp: Option<NetworkLp>,
+    model: Option<CplexModel>,
+    batch: Option<(BatchNetworkLp, CplexModel)>,
+    horizon: Option<(HorizonNetworkLp, CplexModel)>,
+    solver_settings: SolverSettings,
+    stats: SolverStats,
+}
+
+impl CplexSolver {
+    pub(crate) fn new(settings: CplexSettings) -> Self {
+        Self {
+            settings,
+            lp: None,
+            model: None,
+            batch: None,
+            horizon: None,
+            solver_settings: SolverSettings::default(),
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+impl Solver for CplexSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[SolverFeature::IntegerVariables, SolverFeature::MultiTimestep]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new(self.settings.clone()))
+    }
+
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.solver_settings = settings.clone();
+        self.batch = None;
+        self.horizon = None;
+        let lp = NetworkLp::new(model, settings)?;
+        self.model = Some(CplexModel::from_builder(lp.builder(), settings, &self.settings)?);
+        self.lp = Some(lp);
+
+        Ok(())
+    }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, cpx) = match (&mut self.lp, &mut self.model) {
+            (Some(lp), Some(cpx)) => (lp, cpx),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = cpx.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), cpx.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solution(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of scenarios changes.
+        if !matches!(&self.batch, Some((lp, _)) if lp.nscenarios() == updates.len()) {
+            let lp = BatchNetworkLp::new(network_lp, updates.len());
+            let cpx = CplexModel::from_builder(lp.builder(), &self.solver_settings, &self.settings)?;
+            self.batch = Some((lp, cpx));
+        }
+        let (lp, cpx) = match &mut self.batch {
+            Some((lp, cpx)) => (lp, cpx),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(updates)?;
+        let start = Instant::now();
+        let result = cpx.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), cpx.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_horizon(&mut self, update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of timesteps changes.
+        if !matches!(&self.horizon, Some((lp, _)) if lp.ntimesteps() == update.updates.len()) {
+            let lp = HorizonNetworkLp::new(network_lp, update.updates.len());
+            let cpx = CplexModel::from_builder(lp.builder(), &self.solver_settings, &self.settings)?;
+            self.horizon = Some((lp, cpx));
+        }
+        let (lp, cpx) = match &mut self.horizon {
+            Some((lp, cpx)) => (lp, cpx),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = cpx.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), cpx.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn cplex_solve() {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(1.0, Bounds::Double(0.0, 2.0));
+        builder.add_column(0.0, Bounds::Lower(0.0));
+        builder.add_column(4.0, Bounds::Double(0.0, 4.0));
+
+        // Row1
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(2, 1.0);
+        row.set_lower(2.0);
+        row.set_upper(f64::MAX);
+        builder.add_row(row);
+
+        // Row2
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, -5.0);
+        row.add_element(2, 1.0);
+        row.set_lower(1.0);
+        row.set_upper(1.0);
+        builder.add_row(row);
+
+        let mut model =
+            CplexModel::from_builder(&builder, &SolverSettings::default(), &CplexSettings::default()).unwrap();
+        let solution = model.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, 2.0));
+
+        // Tighten the bounds of the first row and re-solve
+        builder.set_row_bounds(0, 3.0, f64::MAX);
+        let solution = model.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, 6.0));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::solvers::builder::LpBuilder;
+use crate::PywrError;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Bounds with a larger magnitude than this are written as infinite.
+const INFINITE_BOUND: f64 = 1e30;
+
+/// The file formats a problem can be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProblemFormat {
+    /// The CPLEX LP format.
+    Lp,
+    /// The free MPS format.
+    Mps,
+}
+
+impl ProblemFormat {
+    /// Determine the format from a file's extension.
+    pub fn from_path(path: &Path) -> Result<Self, PywrError> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("lp") => Ok(Self::Lp),
+            Some("mps") => Ok(Self::Mps),
+            _ => Err(PywrError::UnrecognisedProblemFormat(path.display().to_string())),
+        }
+    }
+}
+
+/// Convert `name` to a name that is valid in both LP and MPS files.
+pub(crate) fn sanitise_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Write the problem in `builder` to the file at `path`.
+pub(crate) fn write_problem(
+    builder: &LpBuilder,
+    col_names: &[String],
+    row_names: &[String],
+    path: &Path,
+    format: ProblemFormat,
+) -> Result<(), PywrError> {
+    let file = File::create(path).map_err(|e| PywrError::IOError(e.to_string()))?;
+    let mut writer = BufWriter::new(file);
+    match format {
+        ProblemFormat::Lp => write_lp(builder, col_names, row_names, &mut writer),
+        ProblemFormat::Mps => write_mps(builder, col_names, row_names, &mut writer),
+    }
+    .map_err(|e| PywrError::IOError(e.to_string()))
+}
+
+fn is_infinite(value: f64) -> bool {
+    value.abs() >= INFINITE_BOUND
+}
+
+/// Write the terms of row `row` in the LP format.
+fn write_lp_row_terms<W: Write>(
+    builder: &LpBuilder,
+    col_names: &[String],
+    row: usize,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    let (start, end) = (builder.row_starts()[row], builder.row_starts()[row + 1]);
+    if start == end {
+        // Rows must contain at least one term.
+        return write!(writer, " 0 {}", col_names[0]);
+    }
+    for i in start..end {
+        let value = builder.elements()[i];
+        let sign = if value < 0.0 { "-" } else { "+" };
+        write!(writer, " {} {} {}", sign, value.abs(), col_names[builder.columns()[i]])?;
+    }
+    Ok(())
+}
+
+/// Write the problem in the CPLEX LP format.
+///
+/// Rows with both a finite lower and upper bound are written as two constraints.
+pub(crate) fn write_lp<W: Write>(
+    builder: &LpBuilder,
+    col_names: &[String],
+    row_names: &[String],
+    writer: &mut W,
+) -> std::io::Result<()> {
+    writeln!(writer, "\\ Problem written by pywr")?;
+    writeln!(writer, "Minimize")?;
+    write!(writer, " obj:")?;
+    for (col, &coef) in builder.col_obj_coef().iter().enumerate() {
+        let sign = if coef < 0.0 { "-" } else { "+" };
+        write!(writer, " {} {} {}", sign, coef.abs(), col_names[col])?;
+    }
+    writeln!(writer)?;
+
+    writeln!(writer, "Subject To")?;
+    for (row, name) in row_names.iter().enumerate() {
+        let (lb, ub) = (builder.row_lower()[row], builder.row_upper()[row]);
+
+        if lb == ub {
+            write!(writer, " {}:", name)?;
+            write_lp_row_terms(builder, col_names, row, writer)?;
+            writeln!(writer, " = {}", lb)?;
+            continue;
+        }
+
+        let both = !is_infinite(lb) && !is_infinite(ub);
+        if !is_infinite(lb) {
+            write!(writer, " {}{}:", name, if both { "_lb" } else { "" })?;
+            write_lp_row_terms(builder, col_names, row, writer)?;
+            writeln!(writer, " >= {}", lb)?;
+        }
+        if !is_infinite(ub) {
+            write!(writer, " {}{}:", name, if both { "_ub" } else { "" })?;
+            write_lp_row_terms(builder, col_names, row, writer)?;
+            writeln!(writer, " <= {}", ub)?;
+        }
+    }
+
+    writeln!(writer, "Bounds")?;
+    for (col, name) in col_names.iter().enumerate() {
+        let (lb, ub) = (builder.col_lower()[col], builder.col_upper()[col]);
+        match (is_infinite(lb), is_infinite(ub)) {
+            (true, true) => writeln!(writer, " {} free", name)?,
+            (true, false) => writeln!(writer, " -inf <= {} <= {}", name, ub)?,
+            (false, true) => writeln!(writer, " {} >= {}", name, lb)?,
+            (false, false) if lb == ub => writeln!(writer, " {} = {}", name, lb)?,
+            (false, false) => writeln!(writer, " {} <= {} <= {}", lb, name, ub)?,
+        }
+    }
+
+    if builder.col_integer().iter().any(|&i| i) {
+        writeln!(writer, "Generals")?;
+        for (col, &integer) in builder.col_integer().iter().enumerate() {
+            if integer {
+                writeln!(writer, " {}", col_names[col])?;
+            }
+        }
+    }
+
+    writeln!(writer, "End")
+}
+
+/// Write the problem in the free MPS format.
+///
+/// Rows with both a finite lower and upper bound are written as ranged rows.
+pub(crate) fn write_mps<W: Write>(
+    builder: &LpBuilder,
+    col_names: &[String],
+    row_names: &[String],
+    writer: &mut W,
+) -> std::io::Result<()> {
+    writeln!(writer, "NAME pywr")?;
+
+    writeln!(writer, "ROWS")?;
+    writeln!(writer, " N obj")?;
+    for (row, name) in row_names.iter().enumerate() {
+        let (lb, ub) = (builder.row_lower()[row], builder.row_upper()[row]);
+        let row_type = match (is_infinite(lb), is_infinite(ub)) {
+            _ if lb == ub => "E",
+            (true, true) => "N",
+            (true, false) => "L",
+            (false, _) => "G",
+        };
+        writeln!(writer, " {} {}", row_type, name)?;
+    }
+
+    writeln!(writer, "COLUMNS")?;
+    let (col_starts, rows, elements) = builder.column_major();
+    let mut in_integer_block = false;
+    for col in 0..builder.ncols() {
+        let integer = builder.col_integer()[col];
+        if integer != in_integer_block {
+            let marker = if integer { "INTORG" } else { "INTEND" };
+            writeln!(writer, " MARKER 'MARKER' '{}'", marker)?;
+            in_integer_block = integer;
+        }
+
+        let name = &col_names[col];
+        writeln!(writer, " {} obj {}", name, builder.col_obj_coef()[col])?;
+        for i in col_starts[col]..col_starts[col + 1] {
+            writeln!(writer, " {} {} {}", name, row_names[rows[i]], elements[i])?;
+        }
+    }
+    if in_integer_block {
+        writeln!(writer, " MARKER 'MARKER' 'INTEND'")?;
+    }
+
+    writeln!(writer, "RHS")?;
+    for (row, name) in row_names.iter().enumerate() {
+        let (lb, ub) = (builder.row_lower()[row], builder.row_upper()[row]);
+        let rhs = if !is_infinite(lb) { lb } else { ub };
+        if !is_infinite(rhs) && rhs != 0.0 {
+            writeln!(writer, " rhs {} {}", name, rhs)?;
+        }
+    }
+
+    writeln!(writer, "RANGES")?;
+    for (row, name) in row_names.iter().enumerate() {
+        let (lb, ub) = (builder.row_lower()[row], builder.row_upper()[row]);
+        if !is_infinite(lb) && !is_infinite(ub) && lb != ub {
+            writeln!(writer, " rng {} {}", name, ub - lb)?;
+        }
+    }
+
+    writeln!(writer, "BOUNDS")?;
+    for (col, name) in col_names.iter().enumerate() {
+        let (lb, ub) = (builder.col_lower()[col], builder.col_upper()[col]);
+        match (is_infinite(lb), is_infinite(ub)) {
+            (true, true) => writeln!(writer, " FR bnd {}", name)?,
+            (false, false) if lb == ub => writeln!(writer, " FX bnd {} {}", name, lb)?,
+            _ => {
+                if is_infinite(lb) {
+                    writeln!(writer, " MI bnd {}", name)?;
+                } else if lb != 0.0 || builder.col_integer()[col] {
+                    writeln!(writer, " LO bnd {} {}", name, lb)?;
+                }
+                if !is_infinite(ub) {
+                    writeln!(writer, " UP bnd {} {}", name, ub)?;
+                } else if builder.col_integer()[col] {
+                    writeln!(writer, " PL bnd {}", name)?;
+                }
+            }
+        }
+    }
+
+    writeln!(writer, "ENDATA")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
+
+    fn example_builder() -> (LpBuilder, Vec<String>, Vec<String>) {
+        let mut builder = LpBuilder::new();
+        builder.add_column(1.0, Bounds::Double(0.0, 2.0));
+        builder.add_column(-3.0, Bounds::Lower(0.0));
+        builder.add_column(0.0, Bounds::Free);
+        builder.set_integer(2);
+
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, -5.0);
+        row.set_lower(1.0);
+        row.set_upper(1.0);
+        builder.add_row(row);
+
+        let mut row = RowBuilder::new();
+        row.add_element(1, 2.0);
+        row.add_element(2, 1.0);
+        row.set_lower(-1.0);
+        row.set_upper(4.0);
+        builder.add_row(row);
+
+        let col_names = vec!["x0".to_string(), "x1".to_string(), "x2".to_string()];
+        let row_names = vec!["r0".to_string(), "r1".to_string()];
+        (builder, col_names, row_names)
+    }
+
+    #[test]
+    fn test_write_lp() {
+        let (builder, col_names, row_names) = example_builder();
+        let mut buffer = Vec::new();
+        write_lp(&builder, &col_names, &row_names, &mut buffer).unwrap();
+
+        let expected = "\\ Problem written by pywr
+Minimize
+ obj: + 1 x0 - 3 x1 + 0 x2
+Subject To
+ r0: + 1 x0 - 5 x1 = 1
+ r1_lb: + 2 x1 + 1 x2 >= -1
+ r1_ub: + 2 x1 + 1 x2 <= 4
+Bounds
+ 0 <= x0 <= 2
+ x1 >= 0
+ x2 free
+Generals
+ x2
+End
+";
+        assert_eq!(String::from_utf8(buffer).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_mps() {
+        let (builder, col_names, row_names) = example_builder();
+        let mut buffer = Vec::new();
+        write_mps(&builder, &col_names, &row_names, &mut buffer).unwrap();
+
+        let expected = "NAME pywr
+ROWS
+ N obj
+ E r0
+ G r1
+COLUMNS
+ x0 obj 1
+ x0 r0 1
+ x1 obj -3
+ x1 r0 -5
+ x1 r1 2
+ MARKER 'MARKER' 'INTORG'
+ x2 obj 0
+ x2 r1 1
+ MARKER 'MARKER' 'INTEND'
+RHS
+ rhs r0 1
+ rhs r1 -1
+RANGES
+ rng r1 5
+BOUNDS
+ UP bnd x0 2
+ FR bnd x2
+ENDATA
+";
+        assert_eq!(String::from_utf8(buffer).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ProblemFormat::from_path(Path::new("a.lp")), Ok(ProblemFormat::Lp));
+        assert_eq!(ProblemFormat::from_path(Path::new("a.mps")), Ok(ProblemFormat::Mps));
+        assert!(ProblemFormat::from_path(Path::new("a.txt")).is_err());
+    }
+
+    #[test]
+    fn test_sanitise_name() {
+        assert_eq!(sanitise_name("my node-1"), "my_node_1");
+    }
+}
@@ -0,0 +1,473 @@
+use crate::model::Model;
+use crate::solvers::builder::{BatchNetworkLp, HorizonNetworkLp, LpBuilder, NetworkLp};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::PywrError;
+use glpk_sys::*;
+use libc::{c_double, c_int};
+use std::mem::MaybeUninit;
+use std::path::Path;
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum GlpkError {
+    #[error("GLPK failed to solve the problem (return code {0}).")]
+    SolveFailed(i32),
+    #[error("GLPK did not find an optimal solution (status {0}).")]
+    NotOptimal(i32),
+}
+
+/// Convert a pair of bounds to the GLPK variable type.
+///
+/// The builder uses `f64::MIN` and `f64::MAX` to represent unbounded values.
+fn bounds_type(lb: f64, ub: f64) -> c_int {
+    if lb <= f64::MIN && ub >= f64::MAX {
+        GLP_FR
+    } else if ub >= f64::MAX {
+        GLP_LO
+    } else if lb <= f64::MIN {
+        GLP_UP
+    } else if (ub - lb).abs() < f64::EPSILON {
+        GLP_FX
+    } else {
+        GLP_DB
+    }
+}
+
+/// A GLPK problem.
+///
+/// GLPK keeps its memory pools in thread local storage, so a problem may only be modified or
+/// deleted on the thread that created it.
+struct GlpkProblem {
+    ptr: *mut glp_prob,
+    /// The thread the problem was created on.
+    thread: ThreadId,
+    smcp: glp_smcp,
+    iocp: glp_iocp,
+    has_integers: bool,
+    /// The matrix elements as last loaded from the builder.
+    elements: Vec<f64>,
+    /// Number of iterations in the most recent solve.
+    iterations: u64,
+}
+
+impl GlpkProblem {
+    /// Create a new GLPK problem from the data in `builder`.
+    ///
+    /// GLPK indexes rows and columns from one, so all indices are shifted accordingly.
+    fn from_builder(builder: &LpBuilder) -> Self {
+        let nrows = builder.nrows() as c_int;
+        let ncols = builder.ncols() as c_int;
+
+        let (smcp, iocp, ptr) = unsafe {
+            let mut smcp = MaybeUninit::<glp_smcp>::uninit();
+            glp_init_smcp(smcp.as_mut_ptr());
+            let mut smcp = smcp.assume_init();
+            smcp.msg_lev = GLP_MSG_ERR;
+            smcp.meth = GLP_DUALP;
+
+            let mut iocp = MaybeUninit::<glp_iocp>::uninit();
+            glp_init_iocp(iocp.as_mut_ptr());
+            let mut iocp = iocp.assume_init();
+            iocp.msg_lev = GLP_MSG_ERR;
+
+            glp_term_out(GLP_OFF);
+            let ptr = glp_create_prob();
+            glp_set_obj_dir(ptr, GLP_MIN);
+            if nrows > 0 {
+                glp_add_rows(ptr, nrows);
+            }
+            glp_add_cols(ptr, ncols);
+            (smcp, iocp, ptr)
+        };
+
+        let mut has_integers = false;
+        unsafe {
+            for col in 0..builder.ncols() {
+                let lb = builder.col_lower()[col];
+                let ub = builder.col_upper()[col];
+                let j = col as c_int + 1;
+                glp_set_col_bnds(ptr, j, bounds_type(lb, ub), lb, ub);
+                glp_set_obj_coef(ptr, j, builder.col_obj_coef()[col]);
+                if builder.col_integer()[col] {
+                    glp_set_col_kind(ptr, j, GLP_IV);
+                    has_integers = true;
+                }
+            }
+
+            // The element arrays are one-based; the first entry is ignored.
+            let mut ia: Vec<c_int> = vec![0];
+            let mut ja: Vec<c_int> = vec![0];
+            let mut ar: Vec<c_double> = vec![0.0];
+            for row in 0..builder.nrows() {
+                for i in builder.row_starts()[row]..builder.row_starts()[row + 1] {
+                    ia.push(row as c_int + 1);
+                    ja.push(builder.columns()[i] as c_int + 1);
+                    ar.push(builder.elements()[i]);
+                }
+            }
+            glp_load_matrix(ptr, (ar.len() - 1) as c_int, ia.as_ptr(), ja.as_ptr(), ar.as_ptr());
+        }
+
+        let mut problem = Self {
+            ptr,
+            thread: thread::current().id(),
+            smcp,
+            iocp,
+            has_integers,
+            elements: builder.elements().to_vec(),
+            iterations: 0,
+        };
+        problem.update_row_bounds(builder);
+        problem
+    }
+
+    fn apply_settings(&mut self, settings: &SolverSettings) {
+        let msg_lev = if settings.verbose { GLP_MSG_ALL } else { GLP_MSG_ERR };
+        self.smcp.msg_lev = msg_lev;
+        self.iocp.msg_lev = msg_lev;
+        unsafe {
+            glp_term_out(if settings.verbose { GLP_ON } else { GLP_OFF });
+        }
+        // Note that the basis is not reused between solves when presolving.
+        if let Some(presolve) = settings.presolve {
+            self.smcp.presolve = if presolve { GLP_ON } else { GLP_OFF };
+        }
+        if let Some(tol) = settings.primal_tolerance {
+            self.smcp.tol_bnd = tol;
+        }
+        if let Some(tol) = settings.dual_tolerance {
+            self.smcp.tol_dj = tol;
+        }
+        if let Some(time_limit) = settings.time_limit {
+            // GLPK's time limits are in milliseconds.
+            let tm_lim = (time_limit * 1000.0) as c_int;
+            self.smcp.tm_lim = tm_lim;
+            self.iocp.tm_lim = tm_lim;
+        }
+    }
+
+    /// Replace the elements of any rows that have changed since they were last loaded.
+    fn update_elements(&mut self, builder: &LpBuilder) {
+        let mut rows: Vec<usize> = builder
+            .changed_elements(&self.elements)
+            .map(|(row, _, _)| row)
+            .collect();
+        rows.dedup();
+
+        for row in rows {
+            let (start, end) = (builder.row_starts()[row], builder.row_starts()[row + 1]);
+            // The element arrays are one-based; the first entry is ignored.
+            let mut ind: Vec<c_int> = vec![0];
+            ind.extend(builder.columns()[start..end].iter().map(|&col| col as c_int + 1));
+            let mut val: Vec<c_double> = vec![0.0];
+            val.extend_from_slice(&builder.elements()[start..end]);
+            unsafe {
+                glp_set_mat_row(
+                    self.ptr,
+                    row as c_int + 1,
+                    (end - start) as c_int,
+                    ind.as_ptr(),
+                    val.as_ptr(),
+                );
+            }
+        }
+        self.elements.copy_from_slice(builder.elements());
+    }
+
+    fn update_row_bounds(&mut self, builder: &LpBuilder) {
+        for (row, (&lb, &ub)) in builder.row_lower().iter().zip(builder.row_upper()).enumerate() {
+            unsafe {
+                glp_set_row_bnds(self.ptr, row as c_int + 1, bounds_type(lb, ub), lb, ub);
+            }
+        }
+    }
+
+    /// The number of iterations in the most recent solve.
+    fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Update the objective and row bounds from `builder` and re-solve.
+    ///
+    /// The basis from the previous solve is retained, and used as the starting point.
+    fn solve_builder(&mut self, builder: &LpBuilder) -> Result<GlpkSolution, GlpkError> {
+        for (col, &coef) in builder.col_obj_coef().iter().enumerate() {
+            unsafe {
+                glp_set_obj_coef(self.ptr, col as c_int + 1, coef);
+            }
+        }
+        self.update_row_bounds(builder);
+        self.update_elements(builder);
+
+        unsafe {
+            // GLPK's iteration count is cumulative over all solves of the problem.
+            let it_cnt = glp_get_it_cnt(self.ptr);
+            let ret = glp_simplex(self.ptr, &self.smcp);
+            self.iterations = (glp_get_it_cnt(self.ptr) - it_cnt) as u64;
+            if ret != 0 {
+                return Err(GlpkError::SolveFailed(ret));
+            }
+            let status = glp_get_status(self.ptr);
+            if status != GLP_OPT {
+                return Err(GlpkError::NotOptimal(status));
+            }
+
+            if self.has_integers {
+                let ret = glp_intopt(self.ptr, &self.iocp);
+                self.iterations = (glp_get_it_cnt(self.ptr) - it_cnt) as u64;
+                if ret != 0 {
+                    return Err(GlpkError::SolveFailed(ret));
+                }
+                let status = glp_mip_status(self.ptr);
+                if status != GLP_OPT {
+                    return Err(GlpkError::NotOptimal(status));
+                }
+
+                Ok(GlpkSolution {
+                    objective_value: glp_mip_obj_val(self.ptr),
+                    primal_columns: (1..=builder.ncols() as c_int)
+                        .map(|j| glp_mip_col_val(self.ptr, j))
+                        .collect(),
+                    dual_rows: Vec::new(),
+                    reduced_costs: Vec::new(),
+                })
+            } else {
+                Ok(GlpkSolution {
+                    objective_value: glp_get_obj_val(self.ptr),
+                    primal_columns: (1..=builder.ncols() as c_int)
+                        .map(|j| glp_get_col_prim(self.ptr, j))
+                        .collect(),
+                    dual_rows: (1..=builder.nrows() as c_int)
+                        .map(|i| glp_get_row_dual(self.ptr, i))
+                        .collect(),
+                    reduced_costs: (1..=builder.ncols() as c_int)
+                        .map(|j| glp_get_col_dual(self.ptr, j))
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+impl Drop for GlpkProblem {
+    fn drop(&mut self) {
+        // Deleting the problem from another thread would corrupt that thread's memory pools, so
+        // the problem is leaked instead.
+        if self.thread == thread::current().id() {
+            unsafe {
+                glp_delete_prob(self.ptr);
+            }
+        }
+    }
+}
+
+// SAFETY: the problem is only ever used on the thread that created it. `GlpkSolver` recreates
+// the problem if it is solved on a different thread, and `Drop` does not delete it elsewhere.
+unsafe impl Send for GlpkProblem {}
+
+#[derive(Debug)]
+pub struct GlpkSolution {
+    objective_value: f64,
+    primal_columns: Vec<f64>,
+    dual_rows: Vec<f64>,
+    reduced_costs: Vec<f64>,
+}
+
+/// A solver using the GNU Linear Programming Kit.
+pub struct GlpkSolver {
+    lp: Option<NetworkLp>,
+    problem: Option<GlpkProblem>,
+    batch: Option<(BatchNetworkLp, GlpkProblem)>,
+    horizon: Option<(HorizonNetworkLp, GlpkProblem)>,
+    settings: SolverSettings,
+    stats: SolverStats,
+}
+
+impl GlpkSolver {
+    pub(crate) fn new() -> Self {
+        Self {
+            lp: None,
+            problem: None,
+            batch: None,
+            horizon: None,
+            settings: SolverSettings::default(),
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+impl Solver for GlpkSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[SolverFeature::IntegerVariables, SolverFeature::MultiTimestep]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new())
+    }
+
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.settings = settings.clone();
+        self.batch = None;
+        self.horizon = None;
+        let lp = NetworkLp::new(model, settings)?;
+        let mut problem = GlpkProblem::from_builder(lp.builder());
+        problem.apply_settings(settings);
+        self.problem = Some(problem);
+        self.lp = Some(lp);
+
+        Ok(())
+    }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, problem) = match (&mut self.lp, &mut self.problem) {
+            (Some(lp), Some(problem)) => (lp, problem),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        if problem.thread != thread::current().id() {
+            // The problem can not be used on this thread, so recreate it from the builder.
+            *problem = GlpkProblem::from_builder(lp.builder());
+            problem.apply_settings(&self.settings);
+        }
+        let start = Instant::now();
+        let result = problem.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), problem.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solution(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of scenarios or
+        // the thread changes.
+        let (nscenarios, thread) = (updates.len(), thread::current().id());
+        if !matches!(&self.batch, Some((lp, problem)) if lp.nscenarios() == nscenarios && problem.thread == thread) {
+            let lp = BatchNetworkLp::new(network_lp, nscenarios);
+            let mut problem = GlpkProblem::from_builder(lp.builder());
+            problem.apply_settings(&self.settings);
+            self.batch = Some((lp, problem));
+        }
+        let (lp, problem) = match &mut self.batch {
+            Some((lp, problem)) => (lp, problem),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(updates)?;
+        let start = Instant::now();
+        let result = problem.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), problem.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_horizon(&mut self, update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of timesteps or
+        // the thread changes.
+        let (ntimesteps, thread) = (update.updates.len(), thread::current().id());
+        if !matches!(&self.horizon, Some((lp, problem)) if lp.ntimesteps() == ntimesteps && problem.thread == thread) {
+            let lp = HorizonNetworkLp::new(network_lp, ntimesteps);
+            let mut problem = GlpkProblem::from_builder(lp.builder());
+            problem.apply_settings(&self.settings);
+            self.horizon = Some((lp, problem));
+        }
+        let (lp, problem) = match &mut self.horizon {
+            Some((lp, problem)) => (lp, problem),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = problem.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), problem.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn glpk_bounds_type() {
+        assert_eq!(bounds_type(f64::MIN, f64::MAX), GLP_FR);
+        assert_eq!(bounds_type(0.0, f64::MAX), GLP_LO);
+        assert_eq!(bounds_type(f64::MIN, 1.0), GLP_UP);
+        assert_eq!(bounds_type(0.0, 1.0), GLP_DB);
+        assert_eq!(bounds_type(1.0, 1.0), GLP_FX);
+    }
+
+    #[test]
+    fn glpk_solve() {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(-2.0, Bounds::Lower(0.0));
+        builder.add_column(-3.0, Bounds::Lower(0.0));
+        builder.add_column(-4.0, Bounds::Lower(0.0));
+
+        // Row1
+        let mut row = RowBuilder::new();
+        row.add_element(0, 3.0);
+        row.add_element(1, 2.0);
+        row.add_element(2, 1.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(10.0);
+        builder.add_row(row);
+
+        // Row2
+        let mut row = RowBuilder::new();
+        row.add_element(0, 2.0);
+        row.add_element(1, 5.0);
+        row.add_element(2, 3.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(15.0);
+        builder.add_row(row);
+
+        let mut problem = GlpkProblem::from_builder(&builder);
+        let solution = problem.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, -20.0));
+        assert_eq!(solution.primal_columns, vec![0.0, 0.0, 5.0])
+    }
+}
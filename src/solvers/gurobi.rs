@@ -0,0 +1,532 @@
+use crate::model::Model;
+use crate::solvers::builder::{BatchNetworkLp, HorizonNetworkLp, LpBuilder, NetworkLp};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::PywrError;
+use gurobi_sys::*;
+use libc::{c_char, c_double, c_int};
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use std::ptr;
+use std::time::Instant;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum GurobiError {
+    #[error("Gurobi error {0}: {1}")]
+    Error(i32, String),
+    #[error("Gurobi did not find an optimal solution (status {0}).")]
+    NotOptimal(i32),
+}
+
+/// The algorithm Gurobi uses to solve the LP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GurobiMethod {
+    Automatic,
+    PrimalSimplex,
+    DualSimplex,
+    Barrier,
+    Concurrent,
+}
+
+impl GurobiMethod {
+    fn value(&self) -> c_int {
+        match self {
+            Self::Automatic => -1,
+            Self::PrimalSimplex => 0,
+            Self::DualSimplex => 1,
+            Self::Barrier => 2,
+            Self::Concurrent => 3,
+        }
+    }
+}
+
+/// Gurobi specific parameters passed when the solver is setup.
+///
+/// Options common to all solvers are given by `SolverSettings`.
+#[derive(Clone, Debug)]
+pub struct GurobiSettings {
+    pub method: GurobiMethod,
+}
+
+impl Default for GurobiSettings {
+    fn default() -> Self {
+        Self {
+            method: GurobiMethod::DualSimplex,
+        }
+    }
+}
+
+/// Convert the builder's representation of infinite bounds to Gurobi's.
+fn to_grb_bound(value: f64) -> c_double {
+    value.max(-GRB_INFINITY).min(GRB_INFINITY)
+}
+
+struct GurobiModel {
+    env: *mut GRBenv,
+    ptr: *mut GRBmodel,
+    ncols: usize,
+    /// The matrix elements as last loaded from the builder.
+    elements: Vec<f64>,
+    /// Number of iterations in the most recent solve.
+    iterations: u64,
+}
+
+impl GurobiModel {
+    /// Create a new Gurobi model from the data in `builder`.
+    ///
+    /// Each row is formulated as an equality with an additional bounded slack column (i.e.
+    /// `a.x - s = 0` with `lb <= s <= ub`). The row bounds are then updated each timestep by
+    /// changing the bounds of the slack columns only.
+    fn from_builder(
+        builder: &LpBuilder,
+        settings: &SolverSettings,
+        gurobi_settings: &GurobiSettings,
+    ) -> Result<Self, GurobiError> {
+        let ncols = builder.ncols();
+        let nrows = builder.nrows();
+
+        let mut env: *mut GRBenv = ptr::null_mut();
+        let ret = unsafe { GRBloadenv(&mut env, ptr::null()) };
+        if ret != 0 {
+            return Err(GurobiError::Error(
+                ret,
+                "Failed to create the Gurobi environment.".to_string(),
+            ));
+        }
+
+        let mut model = Self {
+            env,
+            ptr: ptr::null_mut(),
+            ncols,
+            elements: builder.elements().to_vec(),
+            iterations: 0,
+        };
+
+        let mut obj: Vec<c_double> = builder.col_obj_coef().to_vec();
+        obj.resize(ncols + nrows, 0.0);
+        let lb: Vec<c_double> = builder
+            .col_lower()
+            .iter()
+            .chain(builder.row_lower())
+            .map(|&v| to_grb_bound(v))
+            .collect();
+        let ub: Vec<c_double> = builder
+            .col_upper()
+            .iter()
+            .chain(builder.row_upper())
+            .map(|&v| to_grb_bound(v))
+            .collect();
+        let mut vtype: Vec<c_char> = builder
+            .col_integer()
+            .iter()
+            .map(|&i| if i { GRB_INTEGER } else { GRB_CONTINUOUS })
+            .collect();
+        vtype.resize(ncols + nrows, GRB_CONTINUOUS);
+
+        let name = CString::new("pywr").unwrap();
+        let ret = unsafe {
+            GRBnewmodel(
+                env,
+                &mut model.ptr,
+                name.as_ptr(),
+                (ncols + nrows) as c_int,
+                obj.as_ptr(),
+                lb.as_ptr(),
+                ub.as_ptr(),
+                vtype.as_ptr(),
+                ptr::null(),
+            )
+        };
+        model.check(ret)?;
+        model.apply_settings(settings, gurobi_settings)?;
+
+        let mut cbeg: Vec<c_int> = Vec::with_capacity(nrows);
+        let mut cind: Vec<c_int> = Vec::new();
+        let mut cval: Vec<c_double> = Vec::new();
+        for row in 0..nrows {
+            cbeg.push(cind.len() as c_int);
+            for i in builder.row_starts()[row]..builder.row_starts()[row + 1] {
+                cind.push(builder.columns()[i] as c_int);
+                cval.push(builder.elements()[i]);
+            }
+            // Slack column for this row
+            cind.push((ncols + row) as c_int);
+            cval.push(-1.0);
+        }
+        let sense: Vec<c_char> = vec![GRB_EQUAL; nrows];
+        let rhs: Vec<c_double> = vec![0.0; nrows];
+
+        let ret = unsafe {
+            GRBaddconstrs(
+                model.ptr,
+                nrows as c_int,
+                cind.len() as c_int,
+                cbeg.as_ptr(),
+                cind.as_ptr(),
+                cval.as_ptr(),
+                sense.as_ptr(),
+                rhs.as_ptr(),
+                ptr::null(),
+            )
+        };
+        model.check(ret)?;
+        model.check(unsafe { GRBupdatemodel(model.ptr) })?;
+
+        Ok(model)
+    }
+
+    fn apply_settings(
+        &mut self,
+        settings: &SolverSettings,
+        gurobi_settings: &GurobiSettings,
+    ) -> Result<(), GurobiError> {
+        self.set_int_param("OutputFlag", if settings.verbose { 1 } else { 0 })?;
+        self.set_int_param("Method", gurobi_settings.method.value())?;
+        if let Some(presolve) = settings.presolve {
+            // Presolve of -1 lets Gurobi choose automatically.
+            self.set_int_param("Presolve", if presolve { -1 } else { 0 })?;
+        }
+        if let Some(threads) = settings.threads {
+            self.set_int_param("Threads", threads as c_int)?;
+        }
+        if let Some(time_limit) = settings.time_limit {
+            self.set_dbl_param("TimeLimit", time_limit)?;
+        }
+        if let Some(tol) = settings.primal_tolerance {
+            self.set_dbl_param("FeasibilityTol", tol)?;
+        }
+        if let Some(tol) = settings.dual_tolerance {
+            self.set_dbl_param("OptimalityTol", tol)?;
+        }
+        Ok(())
+    }
+
+    fn set_int_param(&mut self, name: &str, value: c_int) -> Result<(), GurobiError> {
+        let name = CString::new(name).unwrap();
+        let ret = unsafe { GRBsetintparam(GRBgetenv(self.ptr), name.as_ptr(), value) };
+        self.check(ret)
+    }
+
+    fn set_dbl_param(&mut self, name: &str, value: c_double) -> Result<(), GurobiError> {
+        let name = CString::new(name).unwrap();
+        let ret = unsafe { GRBsetdblparam(GRBgetenv(self.ptr), name.as_ptr(), value) };
+        self.check(ret)
+    }
+
+    fn get_dbl_attr_array(&mut self, name: &str, len: usize) -> Result<Vec<c_double>, GurobiError> {
+        let mut values: Vec<c_double> = vec![0.0; len];
+        let name = CString::new(name).unwrap();
+        let ret = unsafe { GRBgetdblattrarray(self.ptr, name.as_ptr(), 0, len as c_int, values.as_mut_ptr()) };
+        self.check(ret)?;
+        Ok(values)
+    }
+
+    fn set_dbl_attr_array(&mut self, name: &str, first: usize, values: &[c_double]) -> Result<(), GurobiError> {
+        let name = CString::new(name).unwrap();
+        let ret = unsafe {
+            GRBsetdblattrarray(
+                self.ptr,
+                name.as_ptr(),
+                first as c_int,
+                values.len() as c_int,
+                values.as_ptr(),
+            )
+        };
+        self.check(ret)
+    }
+
+    /// Convert a non-zero return code in to an error with Gurobi's error message.
+    fn check(&self, ret: c_int) -> Result<(), GurobiError> {
+        if ret == 0 {
+            return Ok(());
+        }
+        let msg = unsafe {
+            let env = if self.ptr.is_null() {
+                self.env
+            } else {
+                GRBgetenv(self.ptr)
+            };
+            CStr::from_ptr(GRBgeterrormsg(env)).to_string_lossy().into_owned()
+        };
+        Err(GurobiError::Error(ret, msg))
+    }
+
+    /// Update any matrix elements that have changed since they were last loaded.
+    fn update_elements(&mut self, builder: &LpBuilder) -> Result<(), GurobiError> {
+        let mut cind: Vec<c_int> = Vec::new();
+        let mut vind: Vec<c_int> = Vec::new();
+        let mut val: Vec<c_double> = Vec::new();
+        for (row, col, value) in builder.changed_elements(&self.elements) {
+            cind.push(row as c_int);
+            vind.push(col as c_int);
+            val.push(value);
+        }
+
+        if !val.is_empty() {
+            let ret = unsafe { GRBchgcoeffs(self.ptr, val.len() as c_int, cind.as_ptr(), vind.as_ptr(), val.as_ptr()) };
+            self.check(ret)?;
+            self.elements.copy_from_slice(builder.elements());
+        }
+        Ok(())
+    }
+
+    /// The number of iterations in the most recent solve.
+    fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Update the objective, row bounds and matrix elements from `builder` and re-solve.
+    fn solve_builder(&mut self, builder: &LpBuilder) -> Result<GurobiSolution, GurobiError> {
+        self.update_elements(builder)?;
+        self.set_dbl_attr_array("Obj", 0, builder.col_obj_coef())?;
+        let lb: Vec<c_double> = builder.row_lower().iter().map(|&v| to_grb_bound(v)).collect();
+        let ub: Vec<c_double> = builder.row_upper().iter().map(|&v| to_grb_bound(v)).collect();
+        self.set_dbl_attr_array("LB", self.ncols, &lb)?;
+        self.set_dbl_attr_array("UB", self.ncols, &ub)?;
+
+        self.check(unsafe { GRBoptimize(self.ptr) })?;
+
+        let mut iterations: c_double = 0.0;
+        let attr = CString::new("IterCount").unwrap();
+        self.check(unsafe { GRBgetdblattr(self.ptr, attr.as_ptr(), &mut iterations) })?;
+        self.iterations = iterations as u64;
+
+        let mut status: c_int = 0;
+        let attr = CString::new("Status").unwrap();
+        self.check(unsafe { GRBgetintattr(self.ptr, attr.as_ptr(), &mut status) })?;
+        if status != GRB_OPTIMAL {
+            return Err(GurobiError::NotOptimal(status));
+        }
+
+        let mut objective_value: c_double = 0.0;
+        let attr = CString::new("ObjVal").unwrap();
+        self.check(unsafe { GRBgetdblattr(self.ptr, attr.as_ptr(), &mut objective_value) })?;
+
+        let primal_columns = self.get_dbl_attr_array("X", self.ncols)?;
+
+        // Duals are only available if there are no integer columns. The dual of each row's
+        // equality constraint is also the reduced cost of its slack column.
+        let (dual_rows, reduced_costs) = if builder.col_integer().iter().any(|&i| i) {
+            (Vec::new(), Vec::new())
+        } else {
+            (
+                self.get_dbl_attr_array("Pi", builder.nrows())?,
+                self.get_dbl_attr_array("RC", self.ncols)?,
+            )
+        };
+
+        Ok(GurobiSolution {
+            objective_value,
+            primal_columns,
+            dual_rows,
+            reduced_costs,
+        })
+    }
+}
+
+impl Drop for GurobiModel {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.ptr.is_null() {
+                GRBfreemodel(self.ptr);
+            }
+            GRBfreeenv(self.env);
+        }
+    }
+}
+
+// SAFETY: the model is owned exclusively by this value and Gurobi does not tie it to the thread
+// that created it, so it may be moved to, used on and dropped by another thread.
+unsafe impl Send for GurobiModel {}
+
+#[derive(Debug)]
+pub struct GurobiSolution {
+    objective_value: f64,
+    primal_columns: Vec<f64>,
+    dual_rows: Vec<f64>,
+    reduced_costs: Vec<f64>,
+}
+
+/// A solver using the commercial Gurobi optimiser.
+///
+/// A valid Gurobi licence is required at runtime.
+pub struct GurobiSolver {
+    settings: GurobiSettings,
+    lp: Option<NetworkLp>,
+    model: Option<GurobiModel>,
+    batch: Option<(BatchNetworkLp, GurobiModel)>,
+    horizon: Option<(HorizonNetworkLp, GurobiModel)>,
+    solver_settings: SolverSettings,
+    stats: SolverStats,
+}
+
+impl GurobiSolver {
+    pub(crate) fn new(settings: GurobiSettings) -> Self {
+        Self {
+            settings,
+            lp: None,
+            model: None,
+            batch: None,
+            horizon: None,
+            solver_settings: SolverSettings::default(),
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+impl Solver for GurobiSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[SolverFeature::IntegerVariables, SolverFeature::MultiTimestep]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new(self.settings.clone()))
+    }
+
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.solver_settings = settings.clone();
+        self.batch = None;
+        self.horizon = None;
+        let lp = NetworkLp::new(model, settings)?;
+        self.model = Some(GurobiModel::from_builder(lp.builder(), settings, &self.settings)?);
+        self.lp = Some(lp);
+
+        Ok(())
+    }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, grb) = match (&mut self.lp, &mut self.model) {
+            (Some(lp), Some(grb)) => (lp, grb),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = grb.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), grb.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solution(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of scenarios changes.
+        if !matches!(&self.batch, Some((lp, _)) if lp.nscenarios() == updates.len()) {
+            let lp = BatchNetworkLp::new(network_lp, updates.len());
+            let grb = GurobiModel::from_builder(lp.builder(), &self.solver_settings, &self.settings)?;
+            self.batch = Some((lp, grb));
+        }
+        let (lp, grb) = match &mut self.batch {
+            Some((lp, grb)) => (lp, grb),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(updates)?;
+        let start = Instant::now();
+        let result = grb.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), grb.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_horizon(&mut self, update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of timesteps changes.
+        if !matches!(&self.horizon, Some((lp, _)) if lp.ntimesteps() == update.updates.len()) {
+            let lp = HorizonNetworkLp::new(network_lp, update.updates.len());
+            let grb = GurobiModel::from_builder(lp.builder(), &self.solver_settings, &self.settings)?;
+            self.horizon = Some((lp, grb));
+        }
+        let (lp, grb) = match &mut self.horizon {
+            Some((lp, grb)) => (lp, grb),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = grb.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), grb.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.solver_settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn gurobi_solve() {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(1.0, Bounds::Double(0.0, 2.0));
+        builder.add_column(0.0, Bounds::Lower(0.0));
+        builder.add_column(4.0, Bounds::Double(0.0, 4.0));
+
+        // Row1
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(2, 1.0);
+        row.set_lower(2.0);
+        row.set_upper(f64::MAX);
+        builder.add_row(row);
+
+        // Row2
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, -5.0);
+        row.add_element(2, 1.0);
+        row.set_lower(1.0);
+        row.set_upper(1.0);
+        builder.add_row(row);
+
+        let mut model =
+            GurobiModel::from_builder(&builder, &SolverSettings::default(), &GurobiSettings::default()).unwrap();
+        let solution = model.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, 2.0));
+
+        // Tighten the bounds of the first row and re-solve
+        builder.set_row_bounds(0, 3.0, f64::MAX);
+        let solution = model.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, 6.0));
+    }
+}
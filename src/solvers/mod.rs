@@ -1,17 +1,310 @@
 use crate::model::Model;
-use crate::state::NetworkState;
-use crate::timestep::Timestep;
+use crate::solvers::export::ProblemFormat;
 use crate::PywrError;
+use std::path::Path;
+use std::time::Duration;
 
+pub mod builder;
+#[cfg(feature = "cbc")]
+pub mod cbc;
+#[cfg(feature = "clp")]
 pub mod clp;
+#[cfg(feature = "cplex")]
+pub mod cplex;
+pub mod export;
+#[cfg(feature = "glpk")]
+pub mod glpk;
+#[cfg(feature = "gurobi")]
+pub mod gurobi;
+pub mod pdlp;
+pub mod pool;
+pub mod py;
+pub mod route;
+pub mod simplex;
 
-pub trait Solver {
-    fn setup(&mut self, model: &Model) -> Result<(), PywrError>;
-    fn solve(
-        &mut self,
-        model: &Model,
-        timestep: &Timestep,
-        network_state: &NetworkState,
-        parameter_state: &[f64],
-    ) -> Result<NetworkState, PywrError>;
+/// Options common to all solver backends.
+///
+/// Each backend translates these to its own parameters when it is setup. Options that are `None`
+/// leave the backend's own default in place, and options a backend does not support are ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SolverSettings {
+    /// Primal feasibility tolerance.
+    pub primal_tolerance: Option<f64>,
+    /// Dual feasibility (optimality) tolerance.
+    pub dual_tolerance: Option<f64>,
+    /// Whether to presolve the problem before solving.
+    pub presolve: Option<bool>,
+    /// Number of threads the solver may use.
+    pub threads: Option<usize>,
+    /// Number of threads used to solve scenarios in parallel, each with its own instance of the
+    /// solver. Defaults to one per CPU.
+    pub scenario_threads: Option<usize>,
+    /// Whether to solve all of the scenarios of a timestep as one combined problem.
+    ///
+    /// This amortises the overhead of each solve and allows the solver's own parallelism to be
+    /// used, which can be faster than many small solves for large ensembles.
+    pub batch_scenarios: bool,
+    /// Number of consecutive timesteps solved as one problem, in which storage carries over from
+    /// one timestep to the next.
+    ///
+    /// The flows of every timestep of a window are applied, so the allocation anticipates the
+    /// inflows and demands of its later timesteps, e.g. releasing water before a flood or
+    /// conserving it before a drought. `None` or one solves each timestep separately. Windows
+    /// require a solver that supports `SolverFeature::MultiTimestep`.
+    pub timestep_window: Option<usize>,
+    /// Whether the solver should print its log output.
+    pub verbose: bool,
+    /// Time limit in seconds for each solve.
+    pub time_limit: Option<f64>,
+    /// Weight of a secondary objective that penalises changes in each edge's flow from the
+    /// previous timestep.
+    ///
+    /// Problems with several allocations of equal cost can otherwise give flows that switch
+    /// arbitrarily between them from one timestep to the next. The weight should be small
+    /// relative to the edge costs, so that it only breaks ties and does not change the
+    /// allocation. It is ignored by the route solver.
+    pub flow_smoothing: Option<f64>,
+    /// Whether to identify the violated node constraints when a solve fails.
+    ///
+    /// If enabled, a failed solve is re-solved with the node constraints relaxed, and any that
+    /// are violated are returned in `PywrError::Infeasible`. This is intended for debugging, as
+    /// the relaxed problem is solved with the built-in simplex.
+    pub diagnose_infeasibility: bool,
+}
+
+/// Optional capabilities of a solver backend.
+///
+/// Models that use a feature can only be run with a solver that supports it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SolverFeature {
+    /// Integer and binary columns, as used by mutually exclusive nodes.
+    IntegerVariables,
+    /// Solving several consecutive timesteps as one problem, as used by the rolling horizon.
+    MultiTimestep,
+}
+
+/// The values of a model's allocation problem that change between solves.
+///
+/// These are computed by the model from the network and parameter states of a timestep and
+/// scenario. The structure of the problem (nodes, edges, mutual exclusivities and flow factors) is
+/// fixed, and is extracted by each solver from the model when it is setup.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProblemUpdate {
+    /// The cost of unit flow along each edge, in edge index order.
+    pub edge_costs: Vec<f64>,
+    /// The lower and upper bounds on the flow through each node, in node index order.
+    ///
+    /// For storage nodes these are the bounds on the net inflow given the available volume.
+    pub node_bounds: Vec<(f64, f64)>,
+    /// The lower and upper bounds on the flow along each edge, in edge index order.
+    pub edge_bounds: Vec<(f64, f64)>,
+    /// The maximum flow of each mutually exclusive node, in the order of the model's mutual
+    /// exclusivities and then the nodes within each.
+    pub exclusivity_max_flows: Vec<f64>,
+    /// The factor of each node of each group with flow factors, in the order of the model's
+    /// groups and then the nodes within each.
+    pub flow_factors: Vec<f64>,
+    /// The flow along each edge in the previous timestep, used by the flow smoothing objective.
+    pub previous_edge_flows: Vec<f64>,
+}
+
+/// The values of the allocation problem of several consecutive timesteps solved as one problem.
+///
+/// The volume of each storage node carries over from one timestep to the next, so the bounds of
+/// the storage nodes in each timestep's update are the bounds on the change in volume from the
+/// start of the first timestep to the end of that timestep, rather than on its net inflow.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HorizonUpdate {
+    /// The values of each timestep's problem, in timestep order.
+    pub updates: Vec<ProblemUpdate>,
+    /// The length of each timestep in days, which converts the storage nodes' net inflows to volumes.
+    pub days: Vec<f64>,
+}
+
+/// The solution of a model's allocation problem.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProblemSolution {
+    /// The flow along each edge, in edge index order.
+    pub edge_flows: Vec<f64>,
+    /// The dual value of each node's flow constraint, if the solver provides them.
+    pub node_duals: Option<Vec<f64>>,
+    /// The reduced cost of each edge, if the solver provides them.
+    pub edge_reduced_costs: Option<Vec<f64>>,
+}
+
+/// Statistics of the solves performed by a solver.
+///
+/// Iterations and factorisations are only counted by backends that report them, and are
+/// otherwise zero. For PDLP the iterations are first-order iterations rather than pivots.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SolverStats {
+    /// Number of solves; a batch of scenarios solved as one problem counts as a single solve.
+    pub solves: usize,
+    /// Total time spent solving, excluding the time taken to update the problem.
+    pub solve_time: Duration,
+    /// Total number of iterations.
+    pub iterations: u64,
+    /// Total number of factorisations of the basis.
+    pub factorisations: u64,
+}
+
+impl SolverStats {
+    /// Record a solve that took `solve_time`.
+    pub(crate) fn record(&mut self, solve_time: Duration, iterations: u64, factorisations: u64) {
+        self.solves += 1;
+        self.solve_time += solve_time;
+        self.iterations += iterations;
+        self.factorisations += factorisations;
+    }
+
+    /// Add the statistics of `other`, e.g. from another instance of the same solver.
+    pub fn merge(&mut self, other: &SolverStats) {
+        self.solves += other.solves;
+        self.solve_time += other.solve_time;
+        self.iterations += other.iterations;
+        self.factorisations += other.factorisations;
+    }
+}
+
+/// A solver for the allocation problem of a `Model`.
+///
+/// The problem is extracted from the model once in `setup`. Each solve is then given only the
+/// values that have changed, so a solver does not hold on to, or need access to, the model.
+///
+/// Solvers must be `Send` so that each of the threads solving scenarios in parallel can be
+/// given its own instance (see `pool::SolverPool`).
+pub trait Solver: Send {
+    /// The optional features supported by this solver.
+    fn features(&self) -> &'static [SolverFeature];
+    /// Create a new instance of this solver with the same options.
+    ///
+    /// The new instance must be setup before it is used.
+    fn new_instance(&self) -> Box<dyn Solver>;
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError>;
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError>;
+    /// Solve the problems of several scenarios for the same timestep.
+    ///
+    /// The default implementation solves each scenario in turn. Backends using `NetworkLp`
+    /// combine the scenarios in to a single block-diagonal problem and solve it in one call.
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        updates.iter().map(|update| self.solve(update)).collect()
+    }
+    /// Solve the problems of several consecutive timesteps of a scenario as one problem, and return
+    /// the solution of each timestep.
+    ///
+    /// This is only supported by solvers with `SolverFeature::MultiTimestep`; the default
+    /// implementation returns an error.
+    fn solve_horizon(&mut self, _update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        Err(PywrError::MissingSolverFeatures(vec![SolverFeature::MultiTimestep]))
+    }
+    /// Write the problem from the most recent solve to `path` in the given format.
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError>;
+    /// The statistics of the solves performed since this solver was setup.
+    fn stats(&self) -> &SolverStats;
+}
+
+/// Every solver known to pywr, the Cargo feature it requires, and whether it is compiled in.
+const SOLVERS: [(&str, Option<&str>, bool); 8] = [
+    ("clp", Some("clp"), cfg!(feature = "clp")),
+    ("cbc", Some("cbc"), cfg!(feature = "cbc")),
+    ("glpk", Some("glpk"), cfg!(feature = "glpk")),
+    ("gurobi", Some("gurobi"), cfg!(feature = "gurobi")),
+    ("cplex", Some("cplex"), cfg!(feature = "cplex")),
+    ("simplex", None, true),
+    ("route", None, true),
+    ("pdlp", None, true),
+];
+
+/// The names of the solvers that are available in this build.
+pub fn available_solvers() -> Vec<&'static str> {
+    SOLVERS
+        .iter()
+        .filter(|(_, _, available)| *available)
+        .map(|(name, _, _)| *name)
+        .collect()
+}
+
+/// Create a new solver from its name (e.g. `"clp"`).
+///
+/// Backends with their own settings are created with the default settings.
+pub fn solver_by_name(name: &str) -> Result<Box<dyn Solver>, PywrError> {
+    let solver: Box<dyn Solver> = match name {
+        #[cfg(feature = "clp")]
+        "clp" => Box::new(clp::ClpSolver::new(clp::ClpSettings::default())),
+        #[cfg(feature = "cbc")]
+        "cbc" => Box::new(cbc::CbcSolver::new()),
+        #[cfg(feature = "glpk")]
+        "glpk" => Box::new(glpk::GlpkSolver::new()),
+        #[cfg(feature = "gurobi")]
+        "gurobi" => Box::new(gurobi::GurobiSolver::new(gurobi::GurobiSettings::default())),
+        #[cfg(feature = "cplex")]
+        "cplex" => Box::new(cplex::CplexSolver::new(cplex::CplexSettings::default())),
+        "simplex" => Box::new(simplex::SimplexSolver::new()),
+        "route" => Box::new(route::RouteSolver::new()),
+        "pdlp" => Box::new(pdlp::PdlpSolver::new()),
+        _ => {
+            let available = available_solvers().iter().map(|s| s.to_string()).collect();
+            return match SOLVERS.iter().find(|(n, _, _)| *n == name) {
+                Some((_, Some(feature), _)) => Err(PywrError::SolverNotAvailable {
+                    name: name.to_string(),
+                    feature: feature.to_string(),
+                    available,
+                }),
+                _ => Err(PywrError::UnrecognisedSolver {
+                    name: name.to_string(),
+                    available,
+                }),
+            };
+        }
+    };
+    Ok(solver)
+}
+
+/// The solver used by tests that are not specific to a backend.
+#[cfg(all(test, feature = "clp"))]
+pub(crate) fn default_solver() -> Box<dyn Solver> {
+    Box::new(clp::ClpSolver::new(clp::ClpSettings::default()))
+}
+
+/// The solver used by tests that are not specific to a backend.
+#[cfg(all(test, not(feature = "clp")))]
+pub(crate) fn default_solver() -> Box<dyn Solver> {
+    Box::new(simplex::SimplexSolver::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solver_by_name() {
+        assert!(available_solvers().contains(&"simplex"));
+        assert_eq!(
+            solver_by_name("simplex").unwrap().features(),
+            &[SolverFeature::MultiTimestep]
+        );
+        assert_eq!(solver_by_name("route").unwrap().features(), &[]);
+        assert_eq!(
+            solver_by_name("pdlp").unwrap().features(),
+            &[SolverFeature::MultiTimestep]
+        );
+
+        match solver_by_name("not-a-solver") {
+            Err(PywrError::UnrecognisedSolver { name, available }) => {
+                assert_eq!(name, "not-a-solver");
+                assert!(available.contains(&"route".to_string()));
+            }
+            _ => panic!("Expected an unrecognised solver error."),
+        }
+    }
+
+    #[cfg(not(feature = "gurobi"))]
+    #[test]
+    fn test_solver_not_available() {
+        assert!(!available_solvers().contains(&"gurobi"));
+        match solver_by_name("gurobi") {
+            Err(PywrError::SolverNotAvailable { feature, .. }) => assert_eq!(feature, "gurobi"),
+            _ => panic!("Expected a solver not available error."),
+        }
+    }
 }
@@ -0,0 +1,588 @@
+use crate::model::Model;
+use crate::solvers::builder::{BatchNetworkLp, HorizonNetworkLp, LpBuilder, NetworkLp};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::PywrError;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PdlpError {
+    #[error("the iteration limit was reached before converging")]
+    IterationLimit,
+    #[error("the time limit was reached")]
+    TimeLimit,
+}
+
+/// Default relative tolerance for the primal residual.
+const PRIMAL_TOL: f64 = 1e-8;
+/// Default relative tolerance for the dual residual and duality gap.
+const DUAL_TOL: f64 = 1e-8;
+/// Bounds with a larger magnitude than this are treated as infinite.
+const INFINITE_BOUND: f64 = 1e30;
+/// Maximum number of iterations for each solve.
+const ITERATION_LIMIT: usize = 200_000;
+/// Number of iterations between convergence and restart checks.
+const CHECK_FREQUENCY: usize = 64;
+/// Number of power iterations used to estimate the norm of the constraint matrix.
+const POWER_ITERATIONS: usize = 32;
+/// Restart if the KKT error has reduced by this factor since the last restart.
+const RESTART_SUFFICIENT: f64 = 0.2;
+/// Restart if the KKT error has reduced by this factor and is no longer improving.
+const RESTART_NECESSARY: f64 = 0.8;
+/// Restart if the iterations since the last restart exceed this fraction of the total.
+const RESTART_ARTIFICIAL: f64 = 0.36;
+
+fn is_infinite(value: f64) -> bool {
+    value.abs() >= INFINITE_BOUND
+}
+
+fn norm(values: &[f64]) -> f64 {
+    values.iter().map(|v| v * v).sum::<f64>().sqrt()
+}
+
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f64>().sqrt()
+}
+
+/// The residuals and objective values of a primal-dual point.
+struct KktError {
+    primal_residual: f64,
+    dual_residual: f64,
+    primal_objective: f64,
+    dual_objective: f64,
+}
+
+impl KktError {
+    fn gap(&self) -> f64 {
+        (self.primal_objective - self.dual_objective).abs()
+    }
+
+    /// The combined error used to compare points when restarting.
+    fn error(&self) -> f64 {
+        (self.primal_residual.powi(2) + self.dual_residual.powi(2) + self.gap().powi(2)).sqrt()
+    }
+}
+
+/// A first-order LP solver using the restarted primal-dual hybrid gradient method (PDLP).
+///
+/// The LP `min c.x` subject to `l <= A.x <= u` and column bounds is solved as a saddle point
+/// problem. Each iteration only requires products with `A` and its transpose and element-wise
+/// projections, so no factorisation is needed and the method is well suited to SIMD or GPU
+/// execution on very large problems, such as many scenarios combined with `BatchNetworkLp`.
+///
+/// The iterates are restarted from their average when the KKT error has reduced sufficiently,
+/// and the primal weight is rebalanced at each restart. The solution of the previous solve is
+/// used as the starting point for the next. Solutions are only accurate to the tolerances, and
+/// infeasible problems are not detected; they fail to converge within the iteration limit.
+pub struct Pdlp {
+    nrows: usize,
+    ncols: usize,
+    /// The constraint matrix in row-major (CSR) format.
+    row_starts: Vec<usize>,
+    columns: Vec<usize>,
+    elements: Vec<f64>,
+    /// The constraint matrix in column-major (CSC) format.
+    col_starts: Vec<usize>,
+    rows: Vec<usize>,
+    col_elements: Vec<f64>,
+    /// Step size, as a fraction of the inverse of the norm of the constraint matrix.
+    step_size: f64,
+    primal_weight: f64,
+    x: Vec<f64>,
+    y: Vec<f64>,
+    primal_tol: f64,
+    dual_tol: f64,
+    time_limit: Option<Duration>,
+    /// Number of iterations in the most recent solve.
+    iterations: u64,
+}
+
+impl Pdlp {
+    pub fn from_builder(builder: &LpBuilder) -> Self {
+        let mut pdlp = Self {
+            nrows: builder.nrows(),
+            ncols: builder.ncols(),
+            row_starts: Vec::new(),
+            columns: Vec::new(),
+            elements: Vec::new(),
+            col_starts: Vec::new(),
+            rows: Vec::new(),
+            col_elements: Vec::new(),
+            step_size: 1.0,
+            primal_weight: 1.0,
+            x: vec![0.0; builder.ncols()],
+            y: vec![0.0; builder.nrows()],
+            primal_tol: PRIMAL_TOL,
+            dual_tol: DUAL_TOL,
+            time_limit: None,
+            iterations: 0,
+        };
+        pdlp.load_matrix(builder);
+        pdlp
+    }
+
+    /// Apply the tolerances and time limit from `settings`.
+    pub fn apply_settings(&mut self, settings: &SolverSettings) {
+        self.primal_tol = settings.primal_tolerance.unwrap_or(PRIMAL_TOL);
+        self.dual_tol = settings.dual_tolerance.unwrap_or(DUAL_TOL);
+        self.time_limit = settings.time_limit.map(Duration::from_secs_f64);
+    }
+
+    /// Load the constraint matrix from `builder`, and estimate its norm to set the step size.
+    fn load_matrix(&mut self, builder: &LpBuilder) {
+        self.row_starts = builder.row_starts().to_vec();
+        self.columns = builder.columns().to_vec();
+        self.elements = builder.elements().to_vec();
+        let (col_starts, rows, col_elements) = builder.column_major();
+        self.col_starts = col_starts;
+        self.rows = rows;
+        self.col_elements = col_elements;
+
+        // Power iteration on A^T.A for the largest singular value of A.
+        let mut v = vec![1.0; self.ncols];
+        let mut sigma = 0.0;
+        for _ in 0..POWER_ITERATIONS {
+            let v_norm = norm(&v);
+            if v_norm == 0.0 {
+                break;
+            }
+            v.iter_mut().for_each(|vi| *vi /= v_norm);
+            v = self.transpose_product(&self.product(&v));
+            sigma = norm(&v).sqrt();
+        }
+        self.step_size = if sigma > 0.0 { 0.9 / sigma } else { 1.0 };
+    }
+
+    /// Compute `A.x`
+    fn product(&self, x: &[f64]) -> Vec<f64> {
+        (0..self.nrows)
+            .map(|row| {
+                (self.row_starts[row]..self.row_starts[row + 1])
+                    .map(|i| self.elements[i] * x[self.columns[i]])
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Compute `A^T.y`
+    fn transpose_product(&self, y: &[f64]) -> Vec<f64> {
+        (0..self.ncols)
+            .map(|col| {
+                (self.col_starts[col]..self.col_starts[col + 1])
+                    .map(|i| self.col_elements[i] * y[self.rows[i]])
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Compute the residuals and objective values of the point `(x, y)`.
+    fn kkt_error(&self, builder: &LpBuilder, x: &[f64], y: &[f64]) -> KktError {
+        let ax = self.product(x);
+        let primal_residual = ax
+            .iter()
+            .enumerate()
+            .map(|(row, &v)| (v - v.max(builder.row_lower()[row]).min(builder.row_upper()[row])).powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        let mut dual_objective: f64 = y
+            .iter()
+            .enumerate()
+            .map(|(row, &v)| {
+                if v > 0.0 {
+                    v * builder.row_lower()[row]
+                } else {
+                    v * builder.row_upper()[row]
+                }
+            })
+            .sum();
+
+        // Reduced costs that can not be balanced by a finite column bound are dual infeasible.
+        let aty = self.transpose_product(y);
+        let mut dual_residual = 0.0;
+        for (col, a) in aty.iter().enumerate() {
+            let rc = builder.col_obj_coef()[col] - a;
+            let bound = if rc > 0.0 {
+                builder.col_lower()[col]
+            } else {
+                builder.col_upper()[col]
+            };
+            if is_infinite(bound) {
+                dual_residual += rc * rc;
+            } else {
+                dual_objective += rc * bound;
+            }
+        }
+
+        KktError {
+            primal_residual,
+            dual_residual: dual_residual.sqrt(),
+            primal_objective: x.iter().zip(builder.col_obj_coef()).map(|(x, c)| x * c).sum(),
+            dual_objective,
+        }
+    }
+
+    /// Whether `kkt` satisfies the relative tolerances.
+    fn is_converged(&self, builder: &LpBuilder, kkt: &KktError) -> bool {
+        let finite_bounds = builder
+            .row_lower()
+            .iter()
+            .chain(builder.row_upper())
+            .filter(|b| !is_infinite(**b))
+            .map(|b| b * b)
+            .sum::<f64>()
+            .sqrt();
+
+        kkt.primal_residual <= self.primal_tol * (1.0 + finite_bounds)
+            && kkt.dual_residual <= self.dual_tol * (1.0 + norm(builder.col_obj_coef()))
+            && kkt.gap() <= self.dual_tol * (1.0 + kkt.primal_objective.abs() + kkt.dual_objective.abs())
+    }
+
+    /// The number of iterations in the most recent solve.
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Update the problem from `builder` and solve it from the previous solution.
+    pub fn solve_builder(&mut self, builder: &LpBuilder) -> Result<PdlpSolution, PdlpError> {
+        if builder.elements() != self.elements.as_slice() {
+            self.load_matrix(builder);
+        }
+
+        let (lc, uc) = (builder.col_lower(), builder.col_upper());
+        let (lr, ur) = (builder.row_lower(), builder.row_upper());
+        let cost = builder.col_obj_coef();
+
+        let mut x: Vec<f64> = self
+            .x
+            .iter()
+            .enumerate()
+            .map(|(j, &v)| v.max(lc[j]).min(uc[j]))
+            .collect();
+        let mut y = self.y.clone();
+        let mut x_avg = x.clone();
+        let mut y_avg = y.clone();
+        let mut averaged = 0;
+
+        let mut x_restart = x.clone();
+        let mut y_restart = y.clone();
+        let mut restart_error = self.kkt_error(builder, &x, &y).error();
+        let mut last_candidate_error = restart_error;
+        let mut restart_iteration = 0;
+
+        let start = Instant::now();
+        for iteration in 1..=ITERATION_LIMIT {
+            self.iterations = iteration as u64;
+            let tau = self.step_size / self.primal_weight;
+            let sigma = self.step_size * self.primal_weight;
+
+            // Primal step; a projected gradient step on the Lagrangian.
+            let aty = self.transpose_product(&y);
+            let x_new: Vec<f64> = (0..self.ncols)
+                .map(|j| (x[j] - tau * (cost[j] - aty[j])).max(lc[j]).min(uc[j]))
+                .collect();
+
+            // Dual step at the extrapolated primal point.
+            let x_bar: Vec<f64> = x_new.iter().zip(&x).map(|(n, o)| 2.0 * n - o).collect();
+            let ax = self.product(&x_bar);
+            for (row, y_i) in y.iter_mut().enumerate() {
+                let v = *y_i - sigma * ax[row];
+                *y_i = (v + sigma * lr[row]).max(0.0) + (v + sigma * ur[row]).min(0.0);
+            }
+            x = x_new;
+
+            averaged += 1;
+            let weight = 1.0 / averaged as f64;
+            x_avg.iter_mut().zip(&x).for_each(|(a, v)| *a += (v - *a) * weight);
+            y_avg.iter_mut().zip(&y).for_each(|(a, v)| *a += (v - *a) * weight);
+
+            if iteration % CHECK_FREQUENCY != 0 {
+                continue;
+            }
+
+            let current = self.kkt_error(builder, &x, &y);
+            let average = self.kkt_error(builder, &x_avg, &y_avg);
+            for (kkt, x, y) in &[(&current, &x, &y), (&average, &x_avg, &y_avg)] {
+                if self.is_converged(builder, kkt) {
+                    self.x = x.to_vec();
+                    self.y = y.to_vec();
+                    return Ok(self.solution(builder, kkt.primal_objective));
+                }
+            }
+
+            if let Some(limit) = self.time_limit {
+                if start.elapsed() > limit {
+                    return Err(PdlpError::TimeLimit);
+                }
+            }
+
+            // Restart from the better of the current and average points.
+            let use_average = average.error() < current.error();
+            let candidate_error = if use_average { average.error() } else { current.error() };
+            let restart = candidate_error <= RESTART_SUFFICIENT * restart_error
+                || (candidate_error <= RESTART_NECESSARY * restart_error && candidate_error > last_candidate_error)
+                || (iteration - restart_iteration) as f64 >= RESTART_ARTIFICIAL * iteration as f64;
+            last_candidate_error = candidate_error;
+
+            if restart {
+                if use_average {
+                    x.copy_from_slice(&x_avg);
+                    y.copy_from_slice(&y_avg);
+                }
+
+                // Rebalance the primal weight using the movement since the last restart.
+                let dx = distance(&x, &x_restart);
+                let dy = distance(&y, &y_restart);
+                if dx > 1e-10 && dy > 1e-10 {
+                    self.primal_weight = (0.5 * (dy / dx).ln() + 0.5 * self.primal_weight.ln()).exp();
+                }
+
+                x_restart.copy_from_slice(&x);
+                y_restart.copy_from_slice(&y);
+                x_avg.copy_from_slice(&x);
+                y_avg.copy_from_slice(&y);
+                averaged = 0;
+                restart_error = candidate_error;
+                last_candidate_error = candidate_error;
+                restart_iteration = iteration;
+            }
+        }
+
+        Err(PdlpError::IterationLimit)
+    }
+
+    fn solution(&self, builder: &LpBuilder, objective_value: f64) -> PdlpSolution {
+        let aty = self.transpose_product(&self.y);
+        PdlpSolution {
+            objective_value,
+            primal_columns: self.x.clone(),
+            dual_rows: self.y.clone(),
+            reduced_costs: builder.col_obj_coef().iter().zip(aty).map(|(c, a)| c - a).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PdlpSolution {
+    objective_value: f64,
+    primal_columns: Vec<f64>,
+    dual_rows: Vec<f64>,
+    reduced_costs: Vec<f64>,
+}
+
+/// An experimental solver using the built-in first-order PDLP implementation.
+///
+/// This is intended for very large problems, in particular with `SolverSettings::batch_scenarios`,
+/// where the cost of factorising the basis in a simplex solver becomes the bottleneck.
+pub struct PdlpSolver {
+    lp: Option<NetworkLp>,
+    pdlp: Option<Pdlp>,
+    batch: Option<(BatchNetworkLp, Pdlp)>,
+    horizon: Option<(HorizonNetworkLp, Pdlp)>,
+    settings: SolverSettings,
+    stats: SolverStats,
+}
+
+impl PdlpSolver {
+    pub(crate) fn new() -> Self {
+        Self {
+            lp: None,
+            pdlp: None,
+            batch: None,
+            horizon: None,
+            settings: SolverSettings::default(),
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+impl Solver for PdlpSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[SolverFeature::MultiTimestep]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new())
+    }
+
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.settings = settings.clone();
+        self.batch = None;
+        self.horizon = None;
+        let lp = NetworkLp::new(model, settings)?;
+        let mut pdlp = Pdlp::from_builder(lp.builder());
+        pdlp.apply_settings(settings);
+        self.pdlp = Some(pdlp);
+        self.lp = Some(lp);
+
+        Ok(())
+    }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, pdlp) = match (&mut self.lp, &mut self.pdlp) {
+            (Some(lp), Some(pdlp)) => (lp, pdlp),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = pdlp.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), pdlp.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solution(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of scenarios changes.
+        if !matches!(&self.batch, Some((lp, _)) if lp.nscenarios() == updates.len()) {
+            let lp = BatchNetworkLp::new(network_lp, updates.len());
+            let mut pdlp = Pdlp::from_builder(lp.builder());
+            pdlp.apply_settings(&self.settings);
+            self.batch = Some((lp, pdlp));
+        }
+        let (lp, pdlp) = match &mut self.batch {
+            Some((lp, pdlp)) => (lp, pdlp),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(updates)?;
+        let start = Instant::now();
+        let result = pdlp.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), pdlp.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_horizon(&mut self, update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of timesteps changes.
+        if !matches!(&self.horizon, Some((lp, _)) if lp.ntimesteps() == update.updates.len()) {
+            let lp = HorizonNetworkLp::new(network_lp, update.updates.len());
+            let mut pdlp = Pdlp::from_builder(lp.builder());
+            pdlp.apply_settings(&self.settings);
+            self.horizon = Some((lp, pdlp));
+        }
+        let (lp, pdlp) = match &mut self.horizon {
+            Some((lp, pdlp)) => (lp, pdlp),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = pdlp.solve_builder(lp.builder());
+        self.stats.record(start.elapsed(), pdlp.iterations(), 0);
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
+    use float_cmp::approx_eq;
+
+    fn example_builder() -> LpBuilder {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(-2.0, Bounds::Lower(0.0));
+        builder.add_column(-3.0, Bounds::Lower(0.0));
+        builder.add_column(-4.0, Bounds::Lower(0.0));
+
+        // Row1
+        let mut row = RowBuilder::new();
+        row.add_element(0, 3.0);
+        row.add_element(1, 2.0);
+        row.add_element(2, 1.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(10.0);
+        builder.add_row(row);
+
+        // Row2
+        let mut row = RowBuilder::new();
+        row.add_element(0, 2.0);
+        row.add_element(1, 5.0);
+        row.add_element(2, 3.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(15.0);
+        builder.add_row(row);
+
+        builder
+    }
+
+    #[test]
+    fn pdlp_solve() {
+        let builder = example_builder();
+        let mut pdlp = Pdlp::from_builder(&builder);
+        let solution = pdlp.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, -20.0, epsilon = 1e-5));
+        assert!(approx_eq!(f64, solution.primal_columns[2], 5.0, epsilon = 1e-5));
+        // Only the second row is binding.
+        assert!(approx_eq!(f64, solution.dual_rows[0], 0.0, epsilon = 1e-5));
+        assert!(approx_eq!(f64, solution.dual_rows[1], -4.0 / 3.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn pdlp_resolve() {
+        let mut builder = example_builder();
+        let mut pdlp = Pdlp::from_builder(&builder);
+        pdlp.solve_builder(&builder).unwrap();
+
+        // Loosen the second row and re-solve from the previous solution
+        builder.set_row_bounds(1, f64::MIN, 30.0);
+        let solution = pdlp.solve_builder(&builder).unwrap();
+        assert!(approx_eq!(f64, solution.objective_value, -40.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn pdlp_time_limit() {
+        let builder = example_builder();
+        let mut pdlp = Pdlp::from_builder(&builder);
+        pdlp.apply_settings(&SolverSettings {
+            time_limit: Some(0.0),
+            primal_tolerance: Some(0.0),
+            dual_tolerance: Some(0.0),
+            ..SolverSettings::default()
+        });
+
+        assert_eq!(pdlp.solve_builder(&builder).unwrap_err(), PdlpError::TimeLimit);
+    }
+}
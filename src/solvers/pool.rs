@@ -0,0 +1,110 @@
+use crate::model::Model;
+use crate::solvers::{Solver, SolverSettings, SolverStats};
+use crate::PywrError;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::Mutex;
+
+/// A pool of solver instances, one for each thread of its own rayon thread pool.
+///
+/// Each instance is setup, used and dropped on the same worker thread. This keeps any
+/// warm-start state (e.g. the basis from the previous timestep) with the thread that solves the
+/// next scenario, and supports solvers whose problems are tied to the thread that created them.
+pub struct SolverPool {
+    thread_pool: ThreadPool,
+    solvers: Vec<Mutex<Option<Box<dyn Solver>>>>,
+    batch_scenarios: bool,
+}
+
+impl SolverPool {
+    /// Create a pool of instances of `solver`, each setup for `model` with `settings`.
+    ///
+    /// The number of threads is given by `settings.scenario_threads`, or rayon's default if it
+    /// is `None`.
+    pub fn new(model: &Model, solver: &dyn Solver, settings: &SolverSettings) -> Result<Self, PywrError> {
+        let thread_pool = ThreadPoolBuilder::new()
+            .num_threads(settings.scenario_threads.unwrap_or(0))
+            .build()
+            .map_err(|e| PywrError::ThreadPoolBuildError(e.to_string()))?;
+
+        let solvers = (0..thread_pool.current_num_threads())
+            .map(|_| Mutex::new(Some(solver.new_instance())))
+            .collect();
+        let pool = Self {
+            thread_pool,
+            solvers,
+            batch_scenarios: settings.batch_scenarios,
+        };
+
+        pool.thread_pool
+            .broadcast(|ctx| pool.with_solver_at(ctx.index(), |solver| solver.setup(model, settings)))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(pool)
+    }
+
+    /// The number of threads, and solver instances, in the pool.
+    pub fn num_threads(&self) -> usize {
+        self.solvers.len()
+    }
+
+    /// Whether the scenarios of each timestep are solved as one combined problem.
+    pub fn batch_scenarios(&self) -> bool {
+        self.batch_scenarios
+    }
+
+    /// The combined statistics of all of the solver instances in the pool.
+    ///
+    /// The solve times are summed over the instances, so may exceed the elapsed time when
+    /// scenarios are solved in parallel.
+    pub fn stats(&self) -> SolverStats {
+        let mut stats = SolverStats::default();
+        for solver in &self.solvers {
+            if let Some(solver) = solver.lock().unwrap().as_ref() {
+                stats.merge(solver.stats());
+            }
+        }
+        stats
+    }
+
+    /// Run `op` on the pool's threads.
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        self.thread_pool.install(op)
+    }
+
+    /// Call `f` with the solver instance of the current thread.
+    ///
+    /// This should be called from within `install`. Callers from other threads are given an
+    /// arbitrary instance, which is correct but loses the benefit of warm-starting.
+    pub fn with_solver<F, R>(&self, f: F) -> Result<R, PywrError>
+    where
+        F: FnOnce(&mut dyn Solver) -> Result<R, PywrError>,
+    {
+        let index = rayon::current_thread_index().unwrap_or(0) % self.num_threads();
+        self.with_solver_at(index, f)
+    }
+
+    fn with_solver_at<F, R>(&self, index: usize, f: F) -> Result<R, PywrError>
+    where
+        F: FnOnce(&mut dyn Solver) -> Result<R, PywrError>,
+    {
+        match self.solvers[index].lock().unwrap().as_mut() {
+            Some(solver) => f(solver.as_mut()),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+}
+
+impl Drop for SolverPool {
+    fn drop(&mut self) {
+        // Drop each instance on the thread it was created on.
+        let solvers = &self.solvers;
+        self.thread_pool.broadcast(|ctx| {
+            solvers[ctx.index()].lock().unwrap().take();
+        });
+    }
+}
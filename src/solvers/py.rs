@@ -0,0 +1,150 @@
+use super::{ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats};
+use crate::model::Model;
+use crate::node::NodeType;
+use crate::solvers::export::ProblemFormat;
+use crate::PywrError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::path::Path;
+use std::time::Instant;
+
+/// A solver whose allocation is computed by a Python object, e.g. to prototype a heuristic or a
+/// learned policy against a real model.
+///
+/// The object's `setup(model_info)` method is called once before each run with a dict of the
+/// problem's structure: `nodes`, a list of dicts of each node's `name` and `type`; `edges`, a
+/// list of the `(from, to)` node indices of each edge; and `flow_factors`, a list of the node
+/// indices of each group of nodes with flow factors. Its `solve(bounds, costs)` method is then
+/// called for every timestep and scenario with a dict of the `(min, max)` bounds of the `nodes`
+/// and `edges` and the `flow_factors` of each node in those groups, and the cost of each edge.
+/// It must return the flow along each edge, in edge index order.
+///
+/// Every instance (one per thread solving scenarios) calls the same object, so any state it
+/// keeps is shared between scenarios. Mutual exclusivities and rolling horizons are not
+/// supported, and there is no problem to write.
+pub struct PySolver {
+    object: PyObject,
+    nedges: Option<usize>,
+    stats: SolverStats,
+}
+
+impl PySolver {
+    pub fn new(object: PyObject) -> Self {
+        Self {
+            object,
+            nedges: None,
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+fn python_error(err: PyErr) -> PywrError {
+    PywrError::PythonError(err.to_string())
+}
+
+fn node_type_name(node_type: NodeType) -> &'static str {
+    match node_type {
+        NodeType::Input => "input",
+        NodeType::Output => "output",
+        NodeType::Link => "link",
+        NodeType::Storage => "storage",
+    }
+}
+
+impl Solver for PySolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        let gil = Python::acquire_gil();
+        Box::new(PySolver::new(self.object.clone_ref(gil.python())))
+    }
+
+    fn setup(&mut self, model: &Model, _settings: &SolverSettings) -> Result<(), PywrError> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let nodes = model
+            .nodes
+            .iter()
+            .map(|node| {
+                let info = PyDict::new(py);
+                info.set_item("name", node.name())?;
+                info.set_item("type", node_type_name(node.node_type()))?;
+                Ok(info.to_object(py))
+            })
+            .collect::<PyResult<Vec<_>>>()
+            .map_err(python_error)?;
+        let edges: Vec<(usize, usize)> = model
+            .edges
+            .iter()
+            .map(|edge| (*edge.from_node_index(), *edge.to_node_index()))
+            .collect();
+        let flow_factors: Vec<Vec<usize>> = model
+            .flow_factors()
+            .iter()
+            .map(|group| group.nodes().iter().map(|idx| **idx).collect())
+            .collect();
+
+        let model_info = PyDict::new(py);
+        model_info.set_item("nodes", nodes).map_err(python_error)?;
+        model_info.set_item("edges", edges).map_err(python_error)?;
+        model_info
+            .set_item("flow_factors", flow_factors)
+            .map_err(python_error)?;
+
+        if self.object.as_ref(py).hasattr("setup").map_err(python_error)? {
+            self.object
+                .call_method1(py, "setup", (model_info,))
+                .map_err(python_error)?;
+        }
+        self.nedges = Some(model.edges.len());
+        self.stats = SolverStats::default();
+        Ok(())
+    }
+
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let nedges = self.nedges.ok_or(PywrError::SolverNotSetup)?;
+        let start = Instant::now();
+
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let bounds = PyDict::new(py);
+        bounds.set_item("nodes", &update.node_bounds).map_err(python_error)?;
+        bounds.set_item("edges", &update.edge_bounds).map_err(python_error)?;
+        bounds
+            .set_item("flow_factors", &update.flow_factors)
+            .map_err(python_error)?;
+
+        let edge_flows: Vec<f64> = self
+            .object
+            .call_method1(py, "solve", (bounds, update.edge_costs.clone()))
+            .and_then(|flows| flows.extract(py))
+            .map_err(python_error)?;
+        if edge_flows.len() != nedges {
+            return Err(PywrError::PythonError(format!(
+                "solve returned {} edge flows for {} edges",
+                edge_flows.len(),
+                nedges
+            )));
+        }
+
+        self.stats.record(start.elapsed(), 0, 0);
+        Ok(ProblemSolution {
+            edge_flows,
+            node_duals: None,
+            edge_reduced_costs: None,
+        })
+    }
+
+    fn write_problem(&self, _path: &Path, _format: ProblemFormat) -> Result<(), PywrError> {
+        Err(PywrError::PythonError(
+            "a Python solver has no problem to write".to_string(),
+        ))
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
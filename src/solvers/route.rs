@@ -0,0 +1,322 @@
+use crate::graph::{routes_from, Route};
+use crate::model::Model;
+use crate::node::NodeType;
+use crate::solvers::builder::{add_flow_factor_rows, diagnose_error, Bounds, FactorElement, LpBuilder, RowBuilder};
+use crate::solvers::export::{sanitise_name, write_problem, ProblemFormat};
+use crate::solvers::simplex::DenseSimplex;
+use crate::solvers::{ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats};
+use crate::PywrError;
+use std::path::Path;
+use std::time::Instant;
+
+/// Enumerate all of the routes in a model.
+///
+/// Routes start at every input and storage node and follow outgoing edges through link nodes until
+/// they reach an output or storage node. Routes that would revisit a node are discarded.
+pub fn enumerate_routes(model: &Model) -> Result<Vec<Route>, PywrError> {
+    let mut routes = Vec::new();
+
+    for node in &model.nodes {
+        match node.node_type() {
+            NodeType::Input | NodeType::Storage => routes.extend(routes_from(model, node)?),
+            NodeType::Link | NodeType::Output => {}
+        }
+    }
+
+    Ok(routes)
+}
+
+/// The route-flow formulation of the allocation problem.
+///
+/// There is one column per route, and one row per node constraining the total flow of the routes
+/// passing through it. Edges with their own minimum or maximum flow add a row constraining the
+/// total flow of the routes along them, and nodes with flow factors add rows keeping their flows
+/// in proportion. Mass balance is implicit in the formulation, which makes the problem
+/// much smaller than the node-arc formulation used by `NetworkLp` for tree-like networks.
+pub struct RouteLp {
+    builder: LpBuilder,
+    routes: Vec<Route>,
+    nedges: usize,
+    /// The (row, edge index) of the constraint of each edge with a minimum or maximum flow.
+    edge_rows: Vec<(usize, usize)>,
+    /// The elements of the flow factor rows, and the number of flow factors.
+    factor_elements: Vec<FactorElement>,
+    nflow_factors: usize,
+    node_names: Vec<String>,
+    row_names: Vec<String>,
+}
+
+impl RouteLp {
+    pub fn new(model: &Model) -> Result<Self, PywrError> {
+        let routes = enumerate_routes(model)?;
+        if routes.is_empty() {
+            return Err(PywrError::NoRoutesDefined);
+        }
+
+        let mut builder = LpBuilder::new();
+        for _ in &routes {
+            builder.add_column(0.0, Bounds::Lower(0.0));
+        }
+
+        let mut row_names = Vec::with_capacity(model.nodes.len());
+        let mut node_names = Vec::with_capacity(model.nodes.len());
+        for node in &model.nodes {
+            let mut row = RowBuilder::new();
+
+            for (col, route) in routes.iter().enumerate() {
+                if route.first_node() == node.index() {
+                    match node.node_type() {
+                        NodeType::Storage => row.add_element(col, -1.0),
+                        _ => row.add_element(col, 1.0),
+                    }
+                } else if route.nodes().contains(&node.index()) {
+                    row.add_element(col, 1.0);
+                }
+            }
+
+            builder.add_row(row);
+            row_names.push(sanitise_name(&format!("node{}_{}", node.index(), node.name())));
+            node_names.push(node.name());
+        }
+
+        let mut edge_rows = Vec::new();
+        for edge in model.edges.iter().filter(|edge| edge.is_constrained()) {
+            let mut row = RowBuilder::new();
+            for (col, route) in routes.iter().enumerate() {
+                if route.edges().contains(&edge.index()) {
+                    row.add_element(col, 1.0);
+                }
+            }
+
+            edge_rows.push((builder.nrows(), *edge.index()));
+            builder.add_row(row);
+            row_names.push(format!("edge{}", edge.index()));
+        }
+
+        let factor_elements = add_flow_factor_rows(&mut builder, &mut row_names, model, |node| {
+            routes
+                .iter()
+                .enumerate()
+                .filter(|(_, route)| route.first_node() == node.index() || route.nodes().contains(&node.index()))
+                .map(|(col, _)| (col, 1.0))
+                .collect()
+        })?;
+
+        Ok(Self {
+            builder,
+            routes,
+            nedges: model.edges.len(),
+            edge_rows,
+            factor_elements,
+            nflow_factors: model.flow_factors().iter().map(|g| g.nodes().len()).sum(),
+            node_names,
+            row_names,
+        })
+    }
+
+    pub fn builder(&self) -> &LpBuilder {
+        &self.builder
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// Update the objective coefficients and row bounds from `update`.
+    pub fn update(&mut self, update: &ProblemUpdate) -> Result<(), PywrError> {
+        if update.edge_costs.len() != self.nedges
+            || update.edge_bounds.len() != self.nedges
+            || update.node_bounds.len() != self.node_names.len()
+            || update.flow_factors.len() != self.nflow_factors
+        {
+            return Err(PywrError::ProblemUpdateMismatch);
+        }
+
+        for (col, route) in self.routes.iter().enumerate() {
+            let cost = route.edges().iter().map(|&e| update.edge_costs[*e]).sum();
+            self.builder.set_obj_coefficient(col, cost);
+        }
+
+        for (node_index, &(lb, ub)) in update.node_bounds.iter().enumerate() {
+            self.builder.set_row_bounds(node_index, lb, ub);
+        }
+        for &(row, edge_index) in &self.edge_rows {
+            let (lb, ub) = update.edge_bounds[edge_index];
+            self.builder.set_row_bounds(row, lb, ub);
+        }
+        for element in &self.factor_elements {
+            self.builder
+                .set_element(element.row, element.col, element.value(&update.flow_factors));
+        }
+
+        Ok(())
+    }
+
+    /// Replace a solver's `error` with the node constraints that make the current problem
+    /// infeasible, if any can be found.
+    pub fn diagnose_error(&self, error: PywrError) -> PywrError {
+        diagnose_error(
+            &self.builder,
+            self.node_names.iter().map(|name| name.as_str()).enumerate(),
+            error,
+        )
+    }
+
+    /// Write the current problem to `path` in the given format.
+    pub fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        let col_names: Vec<String> = (0..self.routes.len()).map(|i| format!("route{}", i)).collect();
+        write_problem(&self.builder, &col_names, &self.row_names, path, format)
+    }
+
+    /// Extract the edge flows and node duals from the route flows and row duals of a solution.
+    ///
+    /// The node duals are those of the node constraints, which are the first rows; there are no
+    /// edge columns to give reduced costs.
+    pub fn solution(&self, route_flows: &[f64], dual_rows: &[f64]) -> ProblemSolution {
+        let mut edge_flows = vec![0.0; self.nedges];
+        for (route, flow) in self.routes.iter().zip(route_flows) {
+            for &edge in route.edges() {
+                edge_flows[*edge] += flow;
+            }
+        }
+
+        ProblemSolution {
+            edge_flows,
+            node_duals: Some(dual_rows.iter().take(self.node_names.len()).copied().collect()),
+            edge_reduced_costs: None,
+        }
+    }
+}
+
+/// A solver using the route-flow formulation.
+///
+/// The routes are enumerated once during setup, and the resulting LP is solved with the built-in
+/// simplex implementation. This is well suited to tree-like networks with few routes.
+pub struct RouteSolver {
+    lp: Option<RouteLp>,
+    simplex: Option<DenseSimplex>,
+    diagnose_infeasibility: bool,
+    stats: SolverStats,
+}
+
+impl RouteSolver {
+    pub(crate) fn new() -> Self {
+        Self {
+            lp: None,
+            simplex: None,
+            diagnose_infeasibility: false,
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+impl Solver for RouteSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new())
+    }
+
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.diagnose_infeasibility = settings.diagnose_infeasibility;
+        let lp = RouteLp::new(model)?;
+        let mut simplex = DenseSimplex::from_builder(lp.builder());
+        simplex.apply_settings(settings);
+        self.simplex = Some(simplex);
+        self.lp = Some(lp);
+
+        Ok(())
+    }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, simplex) = match (&mut self.lp, &mut self.simplex) {
+            (Some(lp), Some(simplex)) => (lp, simplex),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = simplex.solve_builder(lp.builder());
+        self.stats
+            .record(start.elapsed(), simplex.iterations(), simplex.factorisations());
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solution(solution.primal_columns(), solution.dual_rows()))
+    }
+
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::EdgeIndex;
+    use crate::node::NodeIndex;
+
+    #[test]
+    fn test_enumerate_routes() {
+        let mut model = Model::new();
+
+        let input_node = model.add_input_node("input").unwrap();
+        let link_node = model.add_link_node("link").unwrap();
+        let output_a = model.add_output_node("output-a").unwrap();
+        let output_b = model.add_output_node("output-b").unwrap();
+
+        model.connect_nodes(&input_node, &link_node).unwrap();
+        model.connect_nodes(&link_node, &output_a).unwrap();
+        model.connect_nodes(&link_node, &output_b).unwrap();
+
+        let routes = enumerate_routes(&model).unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].nodes(), &[NodeIndex(0), NodeIndex(1), NodeIndex(2)]);
+        assert_eq!(routes[0].edges(), &[EdgeIndex(0), EdgeIndex(1)]);
+        assert_eq!(routes[1].nodes(), &[NodeIndex(0), NodeIndex(1), NodeIndex(3)]);
+        assert_eq!(routes[1].edges(), &[EdgeIndex(0), EdgeIndex(2)]);
+    }
+
+    #[test]
+    fn test_enumerate_routes_with_cycle() {
+        let mut model = Model::new();
+
+        let input_node = model.add_input_node("input").unwrap();
+        let link_a = model.add_link_node("link-a").unwrap();
+        let link_b = model.add_link_node("link-b").unwrap();
+        let output_node = model.add_output_node("output").unwrap();
+
+        model.connect_nodes(&input_node, &link_a).unwrap();
+        model.connect_nodes(&link_a, &link_b).unwrap();
+        model.connect_nodes(&link_b, &link_a).unwrap();
+        model.connect_nodes(&link_b, &output_node).unwrap();
+
+        let routes = enumerate_routes(&model).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(
+            routes[0].nodes(),
+            &[NodeIndex(0), NodeIndex(1), NodeIndex(2), NodeIndex(3)]
+        );
+    }
+
+    #[test]
+    fn test_no_routes() {
+        let mut model = Model::new();
+        model.add_input_node("input").unwrap();
+        model.add_output_node("output").unwrap();
+
+        assert_eq!(RouteLp::new(&model).err(), Some(PywrError::NoRoutesDefined));
+    }
+}
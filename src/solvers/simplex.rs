@@ -0,0 +1,825 @@
+use crate::model::Model;
+use crate::solvers::builder::{BatchNetworkLp, HorizonNetworkLp, LpBuilder, NetworkLp};
+use crate::solvers::export::ProblemFormat;
+use crate::solvers::{
+    HorizonUpdate, ProblemSolution, ProblemUpdate, Solver, SolverFeature, SolverSettings, SolverStats,
+};
+use crate::PywrError;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SimplexError {
+    #[error("the problem is infeasible")]
+    Infeasible,
+    #[error("the problem is unbounded")]
+    Unbounded,
+    #[error("the iteration limit was reached")]
+    IterationLimit,
+    #[error("the time limit was reached")]
+    TimeLimit,
+    #[error("the basis matrix is singular")]
+    SingularBasis,
+}
+
+/// Default tolerance for primal feasibility.
+const PRIMAL_TOL: f64 = 1e-9;
+/// Default tolerance for optimality of the reduced costs.
+const DUAL_TOL: f64 = 1e-9;
+/// Tolerance for pivot elements.
+const PIVOT_TOL: f64 = 1e-11;
+/// Bounds with a larger magnitude than this are treated as infinite.
+const INFINITE_BOUND: f64 = 1e30;
+/// Number of pivots between refactorisations of the basis inverse.
+const REFACTOR_FREQUENCY: usize = 100;
+/// Number of consecutive degenerate pivots before switching to Bland's rule.
+const DEGENERATE_LIMIT: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VarStatus {
+    Basic,
+    AtLower,
+    AtUpper,
+    /// A non-basic free variable held at zero.
+    Free,
+}
+
+/// A dense bounded-variable primal simplex solver.
+///
+/// The LP is converted to the form `A.x - s = 0`, where the slack variables `s` take the row
+/// bounds. The slacks give an initial basis, and the basis from the previous solve is retained
+/// so that subsequent solves with updated bounds and costs start from the previous optimum.
+/// This is intended for small and medium sized models, and has no external dependencies.
+pub struct DenseSimplex {
+    nrows: usize,
+    ncols: usize,
+    /// Dense constraint matrix (row-major; `nrows` x `ncols`).
+    matrix: Vec<f64>,
+    /// The matrix elements as last loaded from the builder.
+    elements: Vec<f64>,
+    lower: Vec<f64>,
+    upper: Vec<f64>,
+    cost: Vec<f64>,
+    values: Vec<f64>,
+    status: Vec<VarStatus>,
+    /// The variable that is basic in each row.
+    basis: Vec<usize>,
+    /// Dense inverse of the basis matrix (row-major; `nrows` x `nrows`).
+    binv: Vec<f64>,
+    pivots_since_refactor: usize,
+    /// Number of iterations and factorisations in the most recent solve.
+    iterations: u64,
+    factorisations: u64,
+    primal_tol: f64,
+    dual_tol: f64,
+    time_limit: Option<Duration>,
+}
+
+impl DenseSimplex {
+    pub fn from_builder(builder: &LpBuilder) -> Self {
+        let nrows = builder.nrows();
+        let ncols = builder.ncols();
+        let nvars = ncols + nrows;
+
+        let mut binv = vec![0.0; nrows * nrows];
+        for i in 0..nrows {
+            // The slack columns are -I, which is its own inverse.
+            binv[i * nrows + i] = -1.0;
+        }
+
+        let mut status = vec![VarStatus::AtLower; nvars];
+        for s in status.iter_mut().skip(ncols) {
+            *s = VarStatus::Basic;
+        }
+
+        let mut simplex = Self {
+            nrows,
+            ncols,
+            matrix: Vec::new(),
+            elements: Vec::new(),
+            lower: vec![0.0; nvars],
+            upper: vec![0.0; nvars],
+            cost: vec![0.0; nvars],
+            values: vec![0.0; nvars],
+            status,
+            basis: (ncols..nvars).collect(),
+            binv,
+            pivots_since_refactor: 0,
+            iterations: 0,
+            factorisations: 0,
+            primal_tol: PRIMAL_TOL,
+            dual_tol: DUAL_TOL,
+            time_limit: None,
+        };
+        simplex.load_matrix(builder);
+        simplex
+    }
+
+    /// Load the dense constraint matrix from the elements of `builder`.
+    fn load_matrix(&mut self, builder: &LpBuilder) {
+        let ncols = self.ncols;
+        self.matrix = vec![0.0; self.nrows * ncols];
+        for row in 0..self.nrows {
+            for i in builder.row_starts()[row]..builder.row_starts()[row + 1] {
+                self.matrix[row * ncols + builder.columns()[i]] += builder.elements()[i];
+            }
+        }
+        self.elements = builder.elements().to_vec();
+    }
+
+    /// Apply the tolerances and time limit from `settings`.
+    pub fn apply_settings(&mut self, settings: &SolverSettings) {
+        self.primal_tol = settings.primal_tolerance.unwrap_or(PRIMAL_TOL);
+        self.dual_tol = settings.dual_tolerance.unwrap_or(DUAL_TOL);
+        self.time_limit = settings.time_limit.map(Duration::from_secs_f64);
+    }
+
+    fn nvars(&self) -> usize {
+        self.ncols + self.nrows
+    }
+
+    /// Return the value of column `j` of `[A -I]` in `row`.
+    fn element(&self, row: usize, j: usize) -> f64 {
+        if j < self.ncols {
+            self.matrix[row * self.ncols + j]
+        } else if j - self.ncols == row {
+            -1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Compute `B^-1 a_j`
+    fn ftran(&self, j: usize) -> Vec<f64> {
+        let m = self.nrows;
+        let mut alpha = vec![0.0; m];
+        if j >= self.ncols {
+            let r = j - self.ncols;
+            for (i, a) in alpha.iter_mut().enumerate() {
+                *a = -self.binv[i * m + r];
+            }
+        } else {
+            for k in 0..m {
+                let a = self.matrix[k * self.ncols + j];
+                if a != 0.0 {
+                    for (i, alpha_i) in alpha.iter_mut().enumerate() {
+                        *alpha_i += self.binv[i * m + k] * a;
+                    }
+                }
+            }
+        }
+        alpha
+    }
+
+    /// Compute the reduced cost of every non-basic variable.
+    fn reduced_costs(&self, costs: &[f64]) -> Vec<f64> {
+        let m = self.nrows;
+        // y = c_B^T B^-1
+        let mut y = vec![0.0; m];
+        for (i, &b) in self.basis.iter().enumerate() {
+            let cb = costs[b];
+            if cb != 0.0 {
+                for (k, y_k) in y.iter_mut().enumerate() {
+                    *y_k += cb * self.binv[i * m + k];
+                }
+            }
+        }
+
+        (0..self.nvars())
+            .map(|j| {
+                if self.status[j] == VarStatus::Basic {
+                    0.0
+                } else {
+                    let ya: f64 = (0..m).map(|k| y[k] * self.element(k, j)).sum();
+                    costs[j] - ya
+                }
+            })
+            .collect()
+    }
+
+    /// Recompute the basis inverse using Gauss-Jordan elimination with partial pivoting.
+    fn refactor(&mut self) -> Result<(), SimplexError> {
+        let m = self.nrows;
+        let mut b = vec![0.0; m * m];
+        for (col, &var) in self.basis.iter().enumerate() {
+            for row in 0..m {
+                b[row * m + col] = self.element(row, var);
+            }
+        }
+
+        let mut inv = vec![0.0; m * m];
+        for i in 0..m {
+            inv[i * m + i] = 1.0;
+        }
+
+        for col in 0..m {
+            let pivot_row = (col..m)
+                .max_by(|&a, &c| b[a * m + col].abs().partial_cmp(&b[c * m + col].abs()).unwrap())
+                .unwrap();
+            if b[pivot_row * m + col].abs() < PIVOT_TOL {
+                return Err(SimplexError::SingularBasis);
+            }
+            if pivot_row != col {
+                for k in 0..m {
+                    b.swap(pivot_row * m + k, col * m + k);
+                    inv.swap(pivot_row * m + k, col * m + k);
+                }
+            }
+            let p = b[col * m + col];
+            for k in 0..m {
+                b[col * m + k] /= p;
+                inv[col * m + k] /= p;
+            }
+            for row in 0..m {
+                let f = b[row * m + col];
+                if row != col && f != 0.0 {
+                    for k in 0..m {
+                        b[row * m + k] -= f * b[col * m + k];
+                        inv[row * m + k] -= f * inv[col * m + k];
+                    }
+                }
+            }
+        }
+
+        self.binv = inv;
+        self.pivots_since_refactor = 0;
+        self.factorisations += 1;
+        Ok(())
+    }
+
+    /// Recompute the values of the basic variables from the non-basic values.
+    fn compute_basic_values(&mut self) {
+        let m = self.nrows;
+        // B x_B = - N x_N
+        let mut rhs = vec![0.0; m];
+        for j in 0..self.nvars() {
+            if self.status[j] != VarStatus::Basic && self.values[j] != 0.0 {
+                for (row, r) in rhs.iter_mut().enumerate() {
+                    *r -= self.element(row, j) * self.values[j];
+                }
+            }
+        }
+        for i in 0..m {
+            let v: f64 = (0..m).map(|k| self.binv[i * m + k] * rhs[k]).sum();
+            self.values[self.basis[i]] = v;
+        }
+    }
+
+    /// Update the bounds and costs from `builder`, and move non-basic variables to their bounds.
+    fn load_bounds(&mut self, builder: &LpBuilder) {
+        let ncols = self.ncols;
+        self.lower[..ncols].copy_from_slice(builder.col_lower());
+        self.upper[..ncols].copy_from_slice(builder.col_upper());
+        self.lower[ncols..].copy_from_slice(builder.row_lower());
+        self.upper[ncols..].copy_from_slice(builder.row_upper());
+        self.cost[..ncols].copy_from_slice(builder.col_obj_coef());
+
+        for j in 0..self.nvars() {
+            let lb_finite = self.lower[j] > -INFINITE_BOUND;
+            let ub_finite = self.upper[j] < INFINITE_BOUND;
+
+            let status = match self.status[j] {
+                VarStatus::Basic => continue,
+                VarStatus::AtUpper if ub_finite => VarStatus::AtUpper,
+                _ if lb_finite => VarStatus::AtLower,
+                _ if ub_finite => VarStatus::AtUpper,
+                _ => VarStatus::Free,
+            };
+            self.status[j] = status;
+            self.values[j] = match status {
+                VarStatus::AtLower => self.lower[j],
+                VarStatus::AtUpper => self.upper[j],
+                _ => 0.0,
+            };
+        }
+    }
+
+    /// The phase one costs for the current values of the basic variables.
+    ///
+    /// Returns `None` if the current basis is primal feasible.
+    fn infeasibility_costs(&self) -> Option<Vec<f64>> {
+        let mut costs = vec![0.0; self.nvars()];
+        let mut infeasible = false;
+        for &b in &self.basis {
+            if self.values[b] < self.lower[b] - self.primal_tol {
+                costs[b] = -1.0;
+                infeasible = true;
+            } else if self.values[b] > self.upper[b] + self.primal_tol {
+                costs[b] = 1.0;
+                infeasible = true;
+            }
+        }
+        if infeasible {
+            Some(costs)
+        } else {
+            None
+        }
+    }
+
+    /// Choose the entering variable and its direction of movement.
+    fn choose_entering(&self, reduced_costs: &[f64], bland: bool) -> Option<(usize, f64)> {
+        let mut best: Option<(usize, f64, f64)> = None;
+        for (j, &d) in reduced_costs.iter().enumerate() {
+            let direction = match self.status[j] {
+                VarStatus::Basic => continue,
+                VarStatus::AtLower if d < -self.dual_tol && self.upper[j] > self.lower[j] => 1.0,
+                VarStatus::AtUpper if d > self.dual_tol && self.upper[j] > self.lower[j] => -1.0,
+                VarStatus::Free if d.abs() > self.dual_tol => -d.signum(),
+                _ => continue,
+            };
+            if bland {
+                return Some((j, direction));
+            }
+            match best {
+                Some((_, _, best_d)) if d.abs() <= best_d => {}
+                _ => best = Some((j, direction, d.abs())),
+            }
+        }
+        best.map(|(j, direction, _)| (j, direction))
+    }
+
+    /// Perform one iteration of the simplex method with the given costs.
+    ///
+    /// Returns `None` if the current basis is optimal for the costs, otherwise whether the step
+    /// taken was degenerate.
+    fn iterate(&mut self, costs: &[f64], phase_one: bool, bland: bool) -> Result<Option<bool>, SimplexError> {
+        let reduced_costs = self.reduced_costs(costs);
+        let (entering, direction) = match self.choose_entering(&reduced_costs, bland) {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+
+        let alpha = self.ftran(entering);
+
+        // Ratio test; first the entering variable moving to its other bound.
+        let mut step = self.upper[entering] - self.lower[entering];
+        let mut leaving: Option<(usize, f64)> = None;
+
+        for (i, &a) in alpha.iter().enumerate() {
+            if a.abs() < PIVOT_TOL {
+                continue;
+            }
+            let b = self.basis[i];
+            let delta = -direction * a;
+            let x = self.values[b];
+
+            let (limit, bound) = if delta < 0.0 {
+                if phase_one && x > self.upper[b] + self.primal_tol {
+                    ((x - self.upper[b]) / -delta, self.upper[b])
+                } else if x >= self.lower[b] - self.primal_tol && self.lower[b] > -INFINITE_BOUND {
+                    (((x - self.lower[b]) / -delta).max(0.0), self.lower[b])
+                } else {
+                    continue;
+                }
+            } else if phase_one && x < self.lower[b] - self.primal_tol {
+                ((self.lower[b] - x) / delta, self.lower[b])
+            } else if x <= self.upper[b] + self.primal_tol && self.upper[b] < INFINITE_BOUND {
+                (((self.upper[b] - x) / delta).max(0.0), self.upper[b])
+            } else {
+                continue;
+            };
+
+            let better = match leaving {
+                Some((r, _)) if limit == step => bland && self.basis[i] < self.basis[r],
+                _ => limit < step,
+            };
+            if better {
+                step = limit;
+                leaving = Some((i, bound));
+            }
+        }
+
+        if step >= INFINITE_BOUND {
+            return Err(SimplexError::Unbounded);
+        }
+
+        // Update the primal values
+        self.values[entering] += direction * step;
+        for (i, &a) in alpha.iter().enumerate() {
+            let b = self.basis[i];
+            self.values[b] -= direction * a * step;
+        }
+
+        match leaving {
+            None => {
+                // Bound flip of the entering variable
+                self.status[entering] = if direction > 0.0 {
+                    VarStatus::AtUpper
+                } else {
+                    VarStatus::AtLower
+                };
+                self.values[entering] = if direction > 0.0 {
+                    self.upper[entering]
+                } else {
+                    self.lower[entering]
+                };
+            }
+            Some((r, bound)) => {
+                let leaving_var = self.basis[r];
+                self.values[leaving_var] = bound;
+                self.status[leaving_var] = if (bound - self.lower[leaving_var]).abs() <= self.primal_tol {
+                    VarStatus::AtLower
+                } else {
+                    VarStatus::AtUpper
+                };
+                self.status[entering] = VarStatus::Basic;
+                self.basis[r] = entering;
+                self.pivot(r, &alpha);
+            }
+        }
+
+        if self.pivots_since_refactor >= REFACTOR_FREQUENCY {
+            self.refactor()?;
+            self.compute_basic_values();
+        }
+
+        Ok(Some(step <= self.primal_tol))
+    }
+
+    /// Update the basis inverse after `alpha` enters the basis in row `r`.
+    fn pivot(&mut self, r: usize, alpha: &[f64]) {
+        let m = self.nrows;
+        let p = alpha[r];
+        for k in 0..m {
+            self.binv[r * m + k] /= p;
+        }
+        for (i, &f) in alpha.iter().enumerate() {
+            if i != r && f != 0.0 {
+                for k in 0..m {
+                    self.binv[i * m + k] -= f * self.binv[r * m + k];
+                }
+            }
+        }
+        self.pivots_since_refactor += 1;
+    }
+
+    /// The number of iterations in the most recent solve.
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// The number of factorisations of the basis in the most recent solve.
+    pub fn factorisations(&self) -> u64 {
+        self.factorisations
+    }
+
+    /// Update the problem from `builder` and solve it from the current basis.
+    pub fn solve_builder(&mut self, builder: &LpBuilder) -> Result<SimplexSolution, SimplexError> {
+        self.iterations = 0;
+        self.factorisations = 0;
+        // The basis is refactorised below, so it remains valid if any elements have changed.
+        if builder.elements() != self.elements.as_slice() {
+            self.load_matrix(builder);
+        }
+        self.load_bounds(builder);
+        self.refactor()?;
+        self.compute_basic_values();
+
+        let start = Instant::now();
+        let max_iterations = 50 * (self.nvars() as u64 + 1);
+        let mut degenerate = 0;
+
+        // Phase one; minimise the sum of infeasibilities.
+        while let Some(costs) = self.infeasibility_costs() {
+            match self.iterate(&costs, true, degenerate > DEGENERATE_LIMIT)? {
+                Some(true) => degenerate += 1,
+                Some(false) => degenerate = 0,
+                None => return Err(SimplexError::Infeasible),
+            }
+            self.iterations += 1;
+            if self.iterations > max_iterations {
+                return Err(SimplexError::IterationLimit);
+            }
+            if let Some(limit) = self.time_limit {
+                if start.elapsed() > limit {
+                    return Err(SimplexError::TimeLimit);
+                }
+            }
+        }
+
+        // Phase two; minimise the objective.
+        let costs = self.cost.clone();
+        degenerate = 0;
+        loop {
+            match self.iterate(&costs, false, degenerate > DEGENERATE_LIMIT)? {
+                Some(true) => degenerate += 1,
+                Some(false) => degenerate = 0,
+                None => break,
+            }
+            self.iterations += 1;
+            if self.iterations > max_iterations {
+                return Err(SimplexError::IterationLimit);
+            }
+            if let Some(limit) = self.time_limit {
+                if start.elapsed() > limit {
+                    return Err(SimplexError::TimeLimit);
+                }
+            }
+        }
+
+        let primal_columns = self.values[..self.ncols].to_vec();
+        let objective_value = primal_columns.iter().zip(&self.cost).map(|(x, c)| x * c).sum();
+        // The reduced cost of each row's slack variable is that row's dual value.
+        let mut reduced_costs = self.reduced_costs(&costs);
+        let dual_rows = reduced_costs.split_off(self.ncols);
+
+        Ok(SimplexSolution {
+            objective_value,
+            primal_columns,
+            dual_rows,
+            reduced_costs,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SimplexSolution {
+    objective_value: f64,
+    primal_columns: Vec<f64>,
+    dual_rows: Vec<f64>,
+    reduced_costs: Vec<f64>,
+}
+
+impl SimplexSolution {
+    pub fn primal_columns(&self) -> &[f64] {
+        &self.primal_columns
+    }
+
+    pub fn dual_rows(&self) -> &[f64] {
+        &self.dual_rows
+    }
+}
+
+/// A solver using the built-in dense simplex implementation.
+///
+/// This requires no external solver libraries, but is only suitable for small and medium
+/// sized models.
+pub struct SimplexSolver {
+    lp: Option<NetworkLp>,
+    simplex: Option<DenseSimplex>,
+    batch: Option<(BatchNetworkLp, DenseSimplex)>,
+    horizon: Option<(HorizonNetworkLp, DenseSimplex)>,
+    settings: SolverSettings,
+    stats: SolverStats,
+}
+
+impl SimplexSolver {
+    pub(crate) fn new() -> Self {
+        Self {
+            lp: None,
+            simplex: None,
+            batch: None,
+            horizon: None,
+            settings: SolverSettings::default(),
+            stats: SolverStats::default(),
+        }
+    }
+}
+
+impl Solver for SimplexSolver {
+    fn features(&self) -> &'static [SolverFeature] {
+        &[SolverFeature::MultiTimestep]
+    }
+
+    fn new_instance(&self) -> Box<dyn Solver> {
+        Box::new(Self::new())
+    }
+
+    fn setup(&mut self, model: &Model, settings: &SolverSettings) -> Result<(), PywrError> {
+        self.stats = SolverStats::default();
+        self.settings = settings.clone();
+        self.batch = None;
+        self.horizon = None;
+        let lp = NetworkLp::new(model, settings)?;
+        let mut simplex = DenseSimplex::from_builder(lp.builder());
+        simplex.apply_settings(settings);
+        self.simplex = Some(simplex);
+        self.lp = Some(lp);
+
+        Ok(())
+    }
+    fn solve(&mut self, update: &ProblemUpdate) -> Result<ProblemSolution, PywrError> {
+        let (lp, simplex) = match (&mut self.lp, &mut self.simplex) {
+            (Some(lp), Some(simplex)) => (lp, simplex),
+            _ => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = simplex.solve_builder(lp.builder());
+        self.stats
+            .record(start.elapsed(), simplex.iterations(), simplex.factorisations());
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solution(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_batch(&mut self, updates: &[ProblemUpdate]) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of scenarios changes.
+        if !matches!(&self.batch, Some((lp, _)) if lp.nscenarios() == updates.len()) {
+            let lp = BatchNetworkLp::new(network_lp, updates.len());
+            let mut simplex = DenseSimplex::from_builder(lp.builder());
+            simplex.apply_settings(&self.settings);
+            self.batch = Some((lp, simplex));
+        }
+        let (lp, simplex) = match &mut self.batch {
+            Some((lp, simplex)) => (lp, simplex),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(updates)?;
+        let start = Instant::now();
+        let result = simplex.solve_builder(lp.builder());
+        self.stats
+            .record(start.elapsed(), simplex.iterations(), simplex.factorisations());
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn solve_horizon(&mut self, update: &HorizonUpdate) -> Result<Vec<ProblemSolution>, PywrError> {
+        let network_lp = match &self.lp {
+            Some(lp) => lp,
+            None => return Err(PywrError::SolverNotSetup),
+        };
+        // The combined problem is created on first use, and again if the number of timesteps changes.
+        if !matches!(&self.horizon, Some((lp, _)) if lp.ntimesteps() == update.updates.len()) {
+            let lp = HorizonNetworkLp::new(network_lp, update.updates.len());
+            let mut simplex = DenseSimplex::from_builder(lp.builder());
+            simplex.apply_settings(&self.settings);
+            self.horizon = Some((lp, simplex));
+        }
+        let (lp, simplex) = match &mut self.horizon {
+            Some((lp, simplex)) => (lp, simplex),
+            None => return Err(PywrError::SolverNotSetup),
+        };
+
+        lp.update(update)?;
+        let start = Instant::now();
+        let result = simplex.solve_builder(lp.builder());
+        self.stats
+            .record(start.elapsed(), simplex.iterations(), simplex.factorisations());
+        let solution = match result {
+            Ok(solution) => solution,
+            Err(e) if self.settings.diagnose_infeasibility => return Err(lp.diagnose_error(e.into())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(lp.solutions(&solution.primal_columns, &solution.dual_rows, &solution.reduced_costs))
+    }
+
+    fn write_problem(&self, path: &Path, format: ProblemFormat) -> Result<(), PywrError> {
+        match &self.lp {
+            Some(lp) => lp.write_problem(path, format),
+            None => Err(PywrError::SolverNotSetup),
+        }
+    }
+
+    fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::builder::{Bounds, RowBuilder};
+    use float_cmp::approx_eq;
+
+    fn example_builder() -> LpBuilder {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(1.0, Bounds::Double(0.0, 2.0));
+        builder.add_column(0.0, Bounds::Lower(0.0));
+        builder.add_column(4.0, Bounds::Double(0.0, 4.0));
+
+        // Row1
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(2, 1.0);
+        row.set_lower(2.0);
+        row.set_upper(f64::MAX);
+        builder.add_row(row);
+
+        // Row2
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, -5.0);
+        row.add_element(2, 1.0);
+        row.set_lower(1.0);
+        row.set_upper(1.0);
+        builder.add_row(row);
+
+        builder
+    }
+
+    #[test]
+    fn simplex_solve() {
+        let builder = example_builder();
+        let mut simplex = DenseSimplex::from_builder(&builder);
+        let solution = simplex.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, 2.0, epsilon = 1e-9));
+    }
+
+    #[test]
+    fn simplex_solve2() {
+        let mut builder = LpBuilder::new();
+
+        builder.add_column(-2.0, Bounds::Lower(0.0));
+        builder.add_column(-3.0, Bounds::Lower(0.0));
+        builder.add_column(-4.0, Bounds::Lower(0.0));
+
+        // Row1
+        let mut row = RowBuilder::new();
+        row.add_element(0, 3.0);
+        row.add_element(1, 2.0);
+        row.add_element(2, 1.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(10.0);
+        builder.add_row(row);
+
+        // Row2
+        let mut row = RowBuilder::new();
+        row.add_element(0, 2.0);
+        row.add_element(1, 5.0);
+        row.add_element(2, 3.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(15.0);
+        builder.add_row(row);
+
+        let mut simplex = DenseSimplex::from_builder(&builder);
+        let solution = simplex.solve_builder(&builder).unwrap();
+
+        assert!(approx_eq!(f64, solution.objective_value, -20.0, epsilon = 1e-9));
+        assert!(approx_eq!(f64, solution.primal_columns[2], 5.0, epsilon = 1e-9));
+    }
+
+    #[test]
+    fn simplex_resolve() {
+        let mut builder = example_builder();
+        let mut simplex = DenseSimplex::from_builder(&builder);
+        simplex.solve_builder(&builder).unwrap();
+
+        // Tighten the bounds of the first row and re-solve from the previous basis
+        builder.set_row_bounds(0, 3.0, f64::MAX);
+        let solution = simplex.solve_builder(&builder).unwrap();
+        assert!(approx_eq!(f64, solution.objective_value, 6.0, epsilon = 1e-9));
+
+        // Changing the costs should change the solution
+        builder.set_obj_coefficient(2, 0.5);
+        let solution = simplex.solve_builder(&builder).unwrap();
+        assert!(approx_eq!(f64, solution.primal_columns[2], 3.0, epsilon = 1e-9));
+    }
+
+    #[test]
+    fn simplex_time_limit() {
+        let builder = example_builder();
+        let mut simplex = DenseSimplex::from_builder(&builder);
+        simplex.apply_settings(&SolverSettings {
+            time_limit: Some(0.0),
+            ..SolverSettings::default()
+        });
+
+        assert_eq!(simplex.solve_builder(&builder).unwrap_err(), SimplexError::TimeLimit);
+    }
+
+    #[test]
+    fn simplex_infeasible() {
+        let mut builder = example_builder();
+        builder.set_row_bounds(0, 10.0, f64::MAX);
+
+        let mut simplex = DenseSimplex::from_builder(&builder);
+        assert_eq!(simplex.solve_builder(&builder).unwrap_err(), SimplexError::Infeasible);
+    }
+
+    #[test]
+    fn simplex_unbounded() {
+        let mut builder = LpBuilder::new();
+        builder.add_column(-1.0, Bounds::Lower(0.0));
+        builder.add_column(0.0, Bounds::Lower(0.0));
+
+        let mut row = RowBuilder::new();
+        row.add_element(0, 1.0);
+        row.add_element(1, -1.0);
+        row.set_lower(f64::MIN);
+        row.set_upper(1.0);
+        builder.add_row(row);
+
+        let mut simplex = DenseSimplex::from_builder(&builder);
+        assert_eq!(simplex.solve_builder(&builder).unwrap_err(), SimplexError::Unbounded);
+    }
+}
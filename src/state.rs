@@ -1,8 +1,12 @@
 use crate::edge::{Edge, EdgeIndex};
 use crate::node::NodeIndex;
+use crate::parameters::ParameterIndex;
 use crate::timestep::Timestep;
 use crate::PywrError;
 use pyo3::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Deref;
+use std::str::FromStr;
 
 #[derive(Clone, Copy, Debug)]
 pub enum NodeState {
@@ -85,6 +89,8 @@ impl FlowState {
 #[derive(Clone, Copy, Debug)]
 pub struct StorageState {
     pub volume: f64,
+    /// The volume at the start of the timestep, before its flows were added.
+    pub start_volume: f64,
     pub flows: FlowState,
 }
 
@@ -92,13 +98,15 @@ impl StorageState {
     fn new(volume: f64) -> Self {
         Self {
             volume,
+            start_volume: volume,
             flows: FlowState::new(),
         }
     }
 
     fn reset(&mut self) {
         self.flows.reset();
-        // Volume remains unchanged
+        // Volume remains unchanged, and is where the next timestep starts from
+        self.start_volume = self.volume;
     }
 
     fn add_in_flow(&mut self, flow: f64, timestep: &Timestep) {
@@ -125,7 +133,237 @@ impl EdgeState {
     }
 }
 
-pub type ParameterState = Vec<f64>;
+/// Quantities derived from the solution of a timestep for a node.
+///
+/// These are computed once after each solve with the constraints of that timestep, so that the
+/// recorders of the timestep and the parameters of the next read the same values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DerivedValues {
+    /// The volume as a proportion of the maximum volume; only defined for storage nodes.
+    pub proportional_volume: Option<f64>,
+    /// The amount by which the flow is below the maximum flow; not defined for storage nodes.
+    pub deficit: Option<f64>,
+}
+
+/// The precision values are stored in between timesteps, e.g. by recorders and flow histories.
+///
+/// Values are always computed and solved in `f64`. Storing them in `f32` halves the memory used
+/// by very large ensembles, at the cost of keeping only about seven significant digits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    Double,
+    Single,
+}
+
+impl Precision {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Double => "f64",
+            Self::Single => "f32",
+        }
+    }
+}
+
+impl FromStr for Precision {
+    type Err = PywrError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "f64" => Ok(Self::Double),
+            "f32" => Ok(Self::Single),
+            _ => Err(PywrError::InvalidPrecision(name.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Flows {
+    Double(VecDeque<f64>),
+    Single(VecDeque<f32>),
+}
+
+/// The flows of a node in the most recent timesteps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowHistory {
+    pub(crate) node_index: NodeIndex,
+    /// Whether the in-flow rather than the out-flow of the node is kept, e.g. for output nodes.
+    pub(crate) in_flow: bool,
+    /// The number of timesteps kept.
+    pub(crate) length: usize,
+    /// The flows, most recent first.
+    flows: Flows,
+}
+
+impl FlowHistory {
+    pub(crate) fn new(node_index: NodeIndex, in_flow: bool, length: usize, precision: Precision) -> Self {
+        let flows = match precision {
+            Precision::Double => Flows::Double(VecDeque::with_capacity(length)),
+            Precision::Single => Flows::Single(VecDeque::with_capacity(length)),
+        };
+        Self {
+            node_index,
+            in_flow,
+            length,
+            flows,
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        match self.flows {
+            Flows::Double(_) => Precision::Double,
+            Flows::Single(_) => Precision::Single,
+        }
+    }
+
+    /// The number of timesteps of flows in the history.
+    pub fn len(&self) -> usize {
+        match &self.flows {
+            Flows::Double(flows) => flows.len(),
+            Flows::Single(flows) => flows.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The flow `index` timesteps before the most recent one.
+    pub fn get(&self, index: usize) -> Option<f64> {
+        match &self.flows {
+            Flows::Double(flows) => flows.get(index).copied(),
+            Flows::Single(flows) => flows.get(index).map(|f| *f as f64),
+        }
+    }
+
+    /// The flows, most recent first.
+    pub fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+
+    /// Add the flow of the most recent timestep, dropping the oldest if the history is full.
+    pub(crate) fn push(&mut self, flow: f64) {
+        let full = self.len() >= self.length;
+        match &mut self.flows {
+            Flows::Double(flows) => {
+                if full {
+                    flows.pop_back();
+                }
+                flows.push_front(flow);
+            }
+            Flows::Single(flows) => {
+                if full {
+                    flows.pop_back();
+                }
+                flows.push_front(flow as f32);
+            }
+        }
+    }
+
+    /// Add the flow of a timestep before the oldest in the history, e.g. when it is read.
+    pub(crate) fn push_oldest(&mut self, flow: f64) {
+        match &mut self.flows {
+            Flows::Double(flows) => flows.push_back(flow),
+            Flows::Single(flows) => flows.push_back(flow as f32),
+        }
+    }
+}
+
+/// The value of a parameter in a timestep.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterValue {
+    Float(f64),
+    /// An index, e.g. of the control curve zone a reservoir is in.
+    Index(usize),
+    /// Several values, e.g. the outputs of a rule that sets more than one release.
+    Multi(Vec<f64>),
+}
+
+/// The values of the parameters in a timestep, indexed by parameter index.
+///
+/// Every parameter has a float value, which is what nodes and metrics use; this dereferences to
+/// those values. The float value of an index is the index itself, and that of a multi-value
+/// parameter is NaN. Index and multi-values are kept alongside, and are only available through
+/// their typed accessors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParameterState {
+    values: Vec<f64>,
+    indices: HashMap<ParameterIndex, usize>,
+    multi_values: HashMap<ParameterIndex, Vec<f64>>,
+}
+
+impl ParameterState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Add the float value of the next parameter.
+    pub fn push(&mut self, value: f64) {
+        self.values.push(value);
+    }
+
+    /// Add the value of the next parameter.
+    pub fn push_value(&mut self, value: ParameterValue) {
+        let index = ParameterIndex(self.values.len());
+        match value {
+            ParameterValue::Float(v) => self.values.push(v),
+            ParameterValue::Index(i) => {
+                self.values.push(i as f64);
+                self.indices.insert(index, i);
+            }
+            ParameterValue::Multi(values) => {
+                self.values.push(f64::NAN);
+                self.multi_values.insert(index, values);
+            }
+        }
+    }
+
+    pub fn get_value(&self, index: ParameterIndex) -> Result<f64, PywrError> {
+        match self.values.get(*index) {
+            Some(v) => Ok(*v),
+            None => Err(PywrError::ParameterIndexNotFound(index)),
+        }
+    }
+
+    pub fn get_index(&self, index: ParameterIndex) -> Result<usize, PywrError> {
+        match self.indices.get(&index) {
+            Some(i) => Ok(*i),
+            None if *index < self.values.len() => Err(PywrError::ParameterValueTypeMismatch(index, "index")),
+            None => Err(PywrError::ParameterIndexNotFound(index)),
+        }
+    }
+
+    pub fn get_multi_value(&self, index: ParameterIndex) -> Result<&[f64], PywrError> {
+        match self.multi_values.get(&index) {
+            Some(v) => Ok(v),
+            None if *index < self.values.len() => Err(PywrError::ParameterValueTypeMismatch(index, "multi-value")),
+            None => Err(PywrError::ParameterIndexNotFound(index)),
+        }
+    }
+}
+
+impl Deref for ParameterState {
+    type Target = [f64];
+
+    fn deref(&self) -> &[f64] {
+        &self.values
+    }
+}
+
+impl From<Vec<f64>> for ParameterState {
+    fn from(values: Vec<f64>) -> Self {
+        Self {
+            values,
+            ..Self::default()
+        }
+    }
+}
 
 // State of the nodes and edges
 #[pyclass]
@@ -133,6 +371,14 @@ pub type ParameterState = Vec<f64>;
 pub struct NetworkState {
     node_states: Vec<NodeState>,
     edge_states: Vec<EdgeState>,
+    /// Dual values of each node's constraint; empty if the solver did not provide them.
+    node_duals: Vec<f64>,
+    /// Reduced cost of each edge; empty if the solver did not provide them.
+    edge_reduced_costs: Vec<f64>,
+    /// The recent flows of the nodes whose history is tracked.
+    flow_histories: Vec<FlowHistory>,
+    /// The values derived from the solution for each node; empty until they are computed.
+    derived_values: Vec<DerivedValues>,
 }
 
 impl NetworkState {
@@ -140,24 +386,57 @@ impl NetworkState {
         Self {
             node_states: Vec::new(),
             edge_states: Vec::new(),
+            node_duals: Vec::new(),
+            edge_reduced_costs: Vec::new(),
+            flow_histories: Vec::new(),
+            derived_values: Vec::new(),
         }
     }
 
-    pub(crate) fn with_capacity(&self) -> Self {
-        let mut node_states = self.node_states.clone();
-        for node_state in node_states.iter_mut() {
+    /// Reset this state to the start of the timestep after `current`, reusing its memory.
+    ///
+    /// The volumes and flow histories are those of `current`, and the flows, duals, reduced costs
+    /// and derived values are cleared.
+    pub(crate) fn reset_from(&mut self, current: &NetworkState) {
+        self.node_states.clone_from(&current.node_states);
+        for node_state in self.node_states.iter_mut() {
             node_state.reset();
         }
 
-        let mut edge_states = Vec::with_capacity(self.edge_states.len());
-        for _ in 0..self.edge_states.len() {
-            edge_states.push(EdgeState::new())
-        }
+        self.edge_states.clear();
+        self.edge_states.resize(current.edge_states.len(), EdgeState::new());
+        self.node_duals.clear();
+        self.edge_reduced_costs.clear();
+        self.flow_histories.clone_from(&current.flow_histories);
+        self.derived_values.clear();
+    }
 
-        Self {
-            node_states,
-            edge_states,
+    /// Make this state a copy of `other`, reusing its memory.
+    pub(crate) fn copy_from(&mut self, other: &NetworkState) {
+        self.node_states.clone_from(&other.node_states);
+        self.edge_states.clone_from(&other.edge_states);
+        self.node_duals.clone_from(&other.node_duals);
+        self.edge_reduced_costs.clone_from(&other.edge_reduced_costs);
+        self.flow_histories.clone_from(&other.flow_histories);
+        self.derived_values.clone_from(&other.derived_values);
+    }
+
+    /// Keep the flows of a node in the most recent `length` timesteps.
+    pub(crate) fn push_flow_history(&mut self, flow_history: FlowHistory) {
+        self.flow_histories.push(flow_history);
+    }
+
+    /// Add the current flows of the nodes whose history is tracked to their histories.
+    pub(crate) fn update_flow_histories(&mut self) -> Result<(), PywrError> {
+        for history in self.flow_histories.iter_mut() {
+            let flow = match self.node_states.get(*history.node_index) {
+                Some(s) if history.in_flow => s.get_in_flow(),
+                Some(s) => s.get_out_flow(),
+                None => return Err(PywrError::NodeIndexNotFound(history.node_index)),
+            };
+            history.push(flow);
         }
+        Ok(())
     }
 
     pub(crate) fn push_node_state(&mut self, node_state: NodeState) {
@@ -169,40 +448,107 @@ impl NetworkState {
     }
 
     pub(crate) fn add_flow(&mut self, edge: &Edge, timestep: &Timestep, flow: f64) -> Result<(), PywrError> {
-        match self.node_states.get_mut(edge.from_node_index()) {
+        match self.node_states.get_mut(*edge.from_node_index()) {
             Some(s) => s.add_out_flow(flow, timestep),
-            None => return Err(PywrError::NodeIndexNotFound),
+            None => return Err(PywrError::NodeIndexNotFound(edge.from_node_index())),
         };
 
-        match self.node_states.get_mut(edge.to_node_index()) {
+        match self.node_states.get_mut(*edge.to_node_index()) {
             Some(s) => s.add_in_flow(flow, timestep),
-            None => return Err(PywrError::NodeIndexNotFound),
+            None => return Err(PywrError::NodeIndexNotFound(edge.to_node_index())),
         };
 
-        match self.edge_states.get_mut(edge.index()) {
+        match self.edge_states.get_mut(*edge.index()) {
             Some(s) => s.add_flow(flow),
-            None => return Err(PywrError::EdgeIndexNotFound),
+            None => return Err(PywrError::EdgeIndexNotFound(edge.index())),
         };
 
         Ok(())
     }
 
+    /// Create a state from its saved parts, e.g. from a checkpoint.
+    pub(crate) fn from_parts(
+        node_states: Vec<NodeState>,
+        edge_flows: &[f64],
+        node_duals: Vec<f64>,
+        edge_reduced_costs: Vec<f64>,
+        flow_histories: Vec<FlowHistory>,
+        derived_values: Vec<DerivedValues>,
+    ) -> Self {
+        Self {
+            node_states,
+            edge_states: edge_flows.iter().map(|flow| EdgeState { flow: *flow }).collect(),
+            node_duals,
+            edge_reduced_costs,
+            flow_histories,
+            derived_values,
+        }
+    }
+
+    /// Whether `other` has the same nodes, of the same types, and edges as this state.
+    pub(crate) fn has_same_shape(&self, other: &NetworkState) -> bool {
+        self.node_states.len() == other.node_states.len()
+            && self.edge_states.len() == other.edge_states.len()
+            && self
+                .node_states
+                .iter()
+                .zip(&other.node_states)
+                .all(|(a, b)| std::mem::discriminant(a) == std::mem::discriminant(b))
+    }
+
+    pub(crate) fn node_states(&self) -> &[NodeState] {
+        &self.node_states
+    }
+
+    pub(crate) fn edge_flows(&self) -> Vec<f64> {
+        self.edge_states.iter().map(|s| s.flow).collect()
+    }
+
+    pub(crate) fn node_duals(&self) -> &[f64] {
+        &self.node_duals
+    }
+
+    pub(crate) fn edge_reduced_costs(&self) -> &[f64] {
+        &self.edge_reduced_costs
+    }
+
+    pub(crate) fn flow_histories(&self) -> &[FlowHistory] {
+        &self.flow_histories
+    }
+
+    pub(crate) fn derived_values(&self) -> &[DerivedValues] {
+        &self.derived_values
+    }
+
+    /// The derived values of each node, to be replaced after a solve.
+    pub(crate) fn derived_values_mut(&mut self) -> &mut Vec<DerivedValues> {
+        &mut self.derived_values
+    }
+
+    pub(crate) fn set_node_duals(&mut self, node_duals: Vec<f64>) {
+        self.node_duals = node_duals;
+    }
+
+    pub(crate) fn set_edge_reduced_costs(&mut self, edge_reduced_costs: Vec<f64>) {
+        self.edge_reduced_costs = edge_reduced_costs;
+    }
+
     pub fn get_node_in_flow(&self, node_index: NodeIndex) -> Result<f64, PywrError> {
-        match self.node_states.get(node_index) {
+        match self.node_states.get(*node_index) {
             Some(s) => Ok(s.get_in_flow()),
-            None => Err(PywrError::NodeIndexNotFound),
+            None => Err(PywrError::NodeIndexNotFound(node_index)),
         }
     }
 
     pub fn get_node_out_flow(&self, node_index: NodeIndex) -> Result<f64, PywrError> {
-        match self.node_states.get(node_index) {
+        match self.node_states.get(*node_index) {
             Some(s) => Ok(s.get_out_flow()),
-            None => Err(PywrError::NodeIndexNotFound),
+            None => Err(PywrError::NodeIndexNotFound(node_index)),
         }
     }
 
     pub fn get_node_volume(&self, node_index: NodeIndex) -> Result<f64, PywrError> {
-        match self.node_states.get(node_index) {
+        match self.node_states.get(*node_index) {
             Some(s) => match s {
                 NodeState::Storage(ss) => Ok(ss.volume),
                 NodeState::Flow(_) => Err(PywrError::MetricNotDefinedForNode),
@@ -211,10 +557,90 @@ impl NetworkState {
         }
     }
 
+    /// The volume of a storage node at the start of the timestep of this state.
+    pub fn get_node_start_volume(&self, node_index: NodeIndex) -> Result<f64, PywrError> {
+        match self.node_states.get(*node_index) {
+            Some(NodeState::Storage(ss)) => Ok(ss.start_volume),
+            Some(NodeState::Flow(_)) => Err(PywrError::MetricNotDefinedForNode),
+            None => Err(PywrError::NodeIndexNotFound(node_index)),
+        }
+    }
+
+    /// Set the volume of a storage node, e.g. to a value given by a coupled model.
+    pub fn set_node_volume(&mut self, node_index: NodeIndex, volume: f64) -> Result<(), PywrError> {
+        match self.node_states.get_mut(*node_index) {
+            Some(NodeState::Storage(ss)) => {
+                ss.volume = volume;
+                Ok(())
+            }
+            Some(NodeState::Flow(_)) => Err(PywrError::MetricNotDefinedForNode),
+            None => Err(PywrError::NodeIndexNotFound(node_index)),
+        }
+    }
+
     pub fn get_edge_flow(&self, edge_index: EdgeIndex) -> Result<f64, PywrError> {
-        match self.edge_states.get(edge_index) {
+        match self.edge_states.get(*edge_index) {
             Some(s) => Ok(s.flow),
-            None => Err(PywrError::EdgeIndexNotFound),
+            None => Err(PywrError::EdgeIndexNotFound(edge_index)),
+        }
+    }
+
+    fn get_derived_values(&self, node_index: NodeIndex) -> Result<&DerivedValues, PywrError> {
+        if self.derived_values.is_empty() {
+            return Err(PywrError::DerivedValuesNotAvailable);
+        }
+        match self.derived_values.get(*node_index) {
+            Some(d) => Ok(d),
+            None => Err(PywrError::NodeIndexNotFound(node_index)),
+        }
+    }
+
+    /// Return the volume of a storage node as a proportion of its maximum volume, as derived
+    /// from the solution of the most recent timestep.
+    pub fn get_node_proportional_volume(&self, node_index: NodeIndex) -> Result<f64, PywrError> {
+        self.get_derived_values(node_index)?
+            .proportional_volume
+            .ok_or(PywrError::MetricNotDefinedForNode)
+    }
+
+    /// Return the deficit of a node's flow below its maximum flow, as derived from the solution of
+    /// the most recent timestep.
+    pub fn get_node_deficit(&self, node_index: NodeIndex) -> Result<f64, PywrError> {
+        self.get_derived_values(node_index)?
+            .deficit
+            .ok_or(PywrError::MetricNotDefinedForNode)
+    }
+
+    /// Return the flows of a node in the most recent timesteps, most recent first.
+    ///
+    /// The history is only kept for nodes that are tracked, see `Model::track_flow_history`, and
+    /// is shorter than its length until that many timesteps have been solved.
+    pub fn get_node_flow_history(&self, node_index: NodeIndex) -> Result<&FlowHistory, PywrError> {
+        match self.flow_histories.iter().find(|h| h.node_index == node_index) {
+            Some(h) => Ok(h),
+            None => Err(PywrError::FlowHistoryNotTracked(node_index)),
+        }
+    }
+
+    /// Return the dual value (shadow price) of a node's constraint.
+    pub fn get_node_dual(&self, node_index: NodeIndex) -> Result<f64, PywrError> {
+        if self.node_duals.is_empty() {
+            return Err(PywrError::DualsNotAvailable);
+        }
+        match self.node_duals.get(*node_index) {
+            Some(d) => Ok(*d),
+            None => Err(PywrError::NodeIndexNotFound(node_index)),
+        }
+    }
+
+    /// Return the reduced cost of an edge.
+    pub fn get_edge_reduced_cost(&self, edge_index: EdgeIndex) -> Result<f64, PywrError> {
+        if self.edge_reduced_costs.is_empty() {
+            return Err(PywrError::DualsNotAvailable);
+        }
+        match self.edge_reduced_costs.get(*edge_index) {
+            Some(d) => Ok(*d),
+            None => Err(PywrError::EdgeIndexNotFound(edge_index)),
         }
     }
 }
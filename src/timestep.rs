@@ -1,43 +1,313 @@
+use crate::schema::TimestepperSchema;
 use crate::PywrError;
-use chrono::{Duration as ChronoDuration, NaiveDate};
+use chrono::{Datelike, Timelike};
+use chrono::{Duration as ChronoDuration, NaiveDate, NaiveDateTime, NaiveTime};
 use pyo3::prelude::*;
+use pyo3::types::PyDate;
+use std::fmt;
 use std::ops::Add;
 type TimestepIndex = usize;
 
+/// The calendar of the dates of a run.
+///
+/// Climate model data often uses a calendar without leap days, or with twelve months of 30 days,
+/// and running in the same calendar lets this data be used directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Calendar {
+    /// The (proleptic) Gregorian calendar, with leap years.
+    Gregorian,
+    /// The Gregorian calendar without the 29th of February ("noleap" or "365_day").
+    NoLeap,
+    /// Twelve months of 30 days each ("360_day").
+    Days360,
+}
+
+impl Calendar {
+    fn is_leap_year(year: i32) -> bool {
+        (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    }
+
+    fn days_in_month(&self, year: i32, month: u32) -> u32 {
+        match (self, month) {
+            (Self::Days360, _) => 30,
+            (Self::Gregorian, 2) if Self::is_leap_year(year) => 29,
+            (_, 2) => 28,
+            (_, 4) | (_, 6) | (_, 9) | (_, 11) => 30,
+            _ => 31,
+        }
+    }
+
+    /// The number of days in the longest year of the calendar.
+    pub fn max_days_in_year(&self) -> usize {
+        match self {
+            Self::Gregorian => 366,
+            Self::NoLeap => 365,
+            Self::Days360 => 360,
+        }
+    }
+
+    /// The day after `date`.
+    fn next_day(&self, date: CalendarDate) -> CalendarDate {
+        if date.day < self.days_in_month(date.year, date.month) {
+            CalendarDate {
+                day: date.day + 1,
+                ..date
+            }
+        } else if date.month < 12 {
+            CalendarDate {
+                month: date.month + 1,
+                day: 1,
+                ..date
+            }
+        } else {
+            CalendarDate {
+                year: date.year + 1,
+                month: 1,
+                day: 1,
+            }
+        }
+    }
+}
+
+/// A date in the calendar of a run, which need not be a Gregorian date (e.g. the 30th of February
+/// of a 360 day calendar).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CalendarDate {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl CalendarDate {
+    /// The nearest Gregorian date, on the last day of the month if the month is shorter.
+    fn to_naive_date(self) -> NaiveDate {
+        let mut day = self.day;
+        loop {
+            match NaiveDate::from_ymd_opt(self.year, self.month, day) {
+                Some(d) => return d,
+                None => day -= 1,
+            }
+        }
+    }
+}
+
+impl From<NaiveDate> for CalendarDate {
+    fn from(date: NaiveDate) -> Self {
+        Self {
+            year: date.year(),
+            month: date.month(),
+            day: date.day(),
+        }
+    }
+}
+
+impl fmt::Display for CalendarDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
 #[pyclass]
 #[derive(Debug, Copy, Clone)]
 pub struct Timestep {
+    /// The Gregorian date the timestep starts, or the nearest to `calendar_date` in other calendars.
     pub date: NaiveDate,
+    /// The time of day the timestep starts; midnight unless the timesteps are shorter than a day.
+    pub time: NaiveTime,
     pub index: TimestepIndex,
     pub duration: ChronoDuration,
+    pub calendar: Calendar,
+    /// The date the timestep starts in `calendar`.
+    pub calendar_date: CalendarDate,
+    /// The index of the timestep a forecast of this timestep was issued at, if this timestep is
+    /// part of a rolling horizon's lookahead rather than the timestep being simulated.
+    pub forecast_from: Option<TimestepIndex>,
 }
 
 impl Timestep {
     pub fn new(date: NaiveDate, index: TimestepIndex, duration: ChronoDuration) -> Self {
-        Self { date, index, duration }
+        Self::new_at(date.and_hms_opt(0, 0, 0).unwrap(), index, duration)
+    }
+
+    /// Create a timestep that starts at the given date and time of day.
+    pub fn new_at(datetime: NaiveDateTime, index: TimestepIndex, duration: ChronoDuration) -> Self {
+        Self {
+            date: datetime.date(),
+            time: datetime.time(),
+            index,
+            duration,
+            calendar: Calendar::Gregorian,
+            calendar_date: CalendarDate::from(datetime.date()),
+            forecast_from: None,
+        }
+    }
+
+    /// Create a daily (or longer) timestep that starts on `date` of `calendar`.
+    pub fn new_in_calendar(
+        date: CalendarDate,
+        calendar: Calendar,
+        index: TimestepIndex,
+        duration: ChronoDuration,
+    ) -> Self {
+        Self {
+            calendar,
+            calendar_date: date,
+            ..Self::new(date.to_naive_date(), index, duration)
+        }
     }
 
     pub fn parse_from_str(date: &str, fmt: &str, index: TimestepIndex, timestep: i64) -> Result<Self, PywrError> {
-        Ok(Self {
-            date: NaiveDate::parse_from_str(date, fmt)?,
+        Ok(Self::new(
+            NaiveDate::parse_from_str(date, fmt)?,
             index,
-            duration: ChronoDuration::days(timestep),
-        })
+            ChronoDuration::days(timestep),
+        ))
+    }
+
+    /// The date and time the timestep starts.
+    pub fn datetime(&self) -> NaiveDateTime {
+        self.date.and_time(self.time)
+    }
+
+    /// The hour of the day the timestep starts, from 0 to 23.
+    pub fn hour(&self) -> u32 {
+        self.time.hour()
+    }
+
+    /// The day of the year the timestep starts in its calendar, from 1.
+    pub fn day_of_year(&self) -> u32 {
+        let date = self.calendar_date;
+        (1..date.month)
+            .map(|month| self.calendar.days_in_month(date.year, month))
+            .sum::<u32>()
+            + date.day
     }
 
+    /// The position of the day of the year in a profile with a value for each day of the longest
+    /// year of the calendar, from 0. The 29th of February is skipped in Gregorian years that are
+    /// not leap years, so that each date has the same position in every year.
+    pub fn day_of_year_index(&self) -> usize {
+        let day = self.day_of_year() as usize - 1;
+        if self.calendar == Calendar::Gregorian && !Calendar::is_leap_year(self.date.year()) && day >= 59 {
+            day + 1
+        } else {
+            day
+        }
+    }
+
+    /// The length of the timestep in days, which is fractional for sub-daily timesteps.
+    ///
+    /// Flows are rates per day, so this converts a flow over the timestep to a volume.
     pub(crate) fn days(&self) -> f64 {
         self.duration.num_seconds() as f64 / 3600.0 / 24.0
     }
+
+    /// The start of the timestep as written to outputs; the date, and the time for timesteps
+    /// shorter than a day.
+    pub(crate) fn label(&self) -> String {
+        if self.duration < ChronoDuration::days(1) {
+            self.datetime().to_string()
+        } else {
+            self.calendar_date.to_string()
+        }
+    }
+}
+
+#[pymethods]
+impl Timestep {
+    #[getter]
+    fn get_index(&self) -> TimestepIndex {
+        self.index
+    }
+
+    #[getter]
+    fn get_date<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDate> {
+        PyDate::new(py, self.date.year(), self.date.month() as u8, self.date.day() as u8)
+    }
+
+    #[getter]
+    fn get_hour(&self) -> u32 {
+        self.hour()
+    }
+
+    #[getter]
+    fn get_days(&self) -> f64 {
+        self.days()
+    }
 }
 
 impl Add<ChronoDuration> for Timestep {
     type Output = Timestep;
 
     fn add(self, other: ChronoDuration) -> Self {
-        Self {
-            date: self.date + other,
-            index: self.index + 1,
-            duration: other,
+        Self::new_at(self.datetime() + other, self.index + 1, other)
+    }
+}
+
+/// The length of the timesteps of a `Timestepper`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimestepDuration {
+    /// A fixed number of days, e.g. one for daily or seven for weekly timesteps.
+    Days(i64),
+    /// A fixed length of time, which may be shorter than a day (e.g. `ChronoDuration::hours(1)`
+    /// for the hourly timesteps of a pumped storage model).
+    Fixed(ChronoDuration),
+    /// A fixed number of calendar months. Each timestep starts on the same day of the month as
+    /// the start date, or on the last day of the month if it is shorter.
+    Months(u32),
+    /// Timesteps starting on each of the given dates, which must be increasing. Each timestep
+    /// lasts until the next date, so the last date only marks the end of the final timestep.
+    Dates(Vec<NaiveDate>),
+}
+
+impl TimestepDuration {
+    fn validate(&self) -> Result<(), PywrError> {
+        match self {
+            Self::Days(days) if *days < 1 => Err(PywrError::InvalidTimestepDuration(format!(
+                "{} days is not positive",
+                days
+            ))),
+            Self::Fixed(duration) if *duration < ChronoDuration::seconds(1) => Err(PywrError::InvalidTimestepDuration(
+                format!("{} is not positive", duration),
+            )),
+            Self::Months(0) => Err(PywrError::InvalidTimestepDuration(
+                "0 months is not positive".to_string(),
+            )),
+            Self::Dates(dates) if dates.len() < 2 => Err(PywrError::InvalidTimestepDuration(
+                "at least two dates are required".to_string(),
+            )),
+            Self::Dates(dates) => match dates.windows(2).find(|w| w[1] <= w[0]) {
+                Some(w) => Err(PywrError::InvalidTimestepDuration(format!(
+                    "{} does not follow {}",
+                    w[1], w[0]
+                ))),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parse the `name` (e.g. "start") date of a timestepper from `date` with the format `fmt`.
+fn parse_date(name: &str, date: &str, fmt: &str) -> Result<NaiveDate, PywrError> {
+    NaiveDate::parse_from_str(date, fmt).map_err(|reason| PywrError::InvalidDate {
+        name: name.to_string(),
+        date: date.to_string(),
+        format: fmt.to_string(),
+        reason,
+    })
+}
+
+/// The date `months` calendar months after `date`, on the last day of the month if it is shorter.
+fn add_months(date: NaiveDate, months: u32) -> NaiveDate {
+    let month0 = date.month0() + months;
+    let year = date.year() + (month0 / 12) as i32;
+    let month = month0 % 12 + 1;
+    let mut day = date.day();
+    loop {
+        match NaiveDate::from_ymd_opt(year, month, day) {
+            Some(d) => return d,
+            None => day -= 1,
         }
     }
 }
@@ -46,28 +316,258 @@ impl Add<ChronoDuration> for Timestep {
 pub struct Timestepper {
     start: NaiveDate,
     end: NaiveDate,
-    timestep: ChronoDuration,
+    duration: TimestepDuration,
+    calendar: Calendar,
+    warm_up_end: Option<NaiveDate>,
 }
 
 impl Timestepper {
     pub(crate) fn new(start: &str, end: &str, fmt: &str, timestep: i64) -> Result<Self, PywrError> {
+        Self::with_duration(start, end, fmt, TimestepDuration::Days(timestep))
+    }
+
+    /// Create a timestepper from start and end dates parsed with `fmt`, and timesteps of the given
+    /// duration (e.g. weekly or monthly).
+    pub fn with_duration(start: &str, end: &str, fmt: &str, duration: TimestepDuration) -> Result<Self, PywrError> {
+        let start = parse_date("start", start, fmt)?;
+        let end = parse_date("end", end, fmt)?;
+        Self::from_dates(start, end, duration)
+    }
+
+    /// Create a timestepper with timesteps of the given duration that start from `start` until
+    /// `end` (inclusive).
+    pub fn from_dates(start: NaiveDate, end: NaiveDate, duration: TimestepDuration) -> Result<Self, PywrError> {
+        duration.validate()?;
+        if end < start {
+            return Err(PywrError::EndDateBeforeStartDate { start, end });
+        }
         Ok(Self {
-            start: NaiveDate::parse_from_str(start, fmt)?,
-            end: NaiveDate::parse_from_str(end, fmt)?,
-            timestep: ChronoDuration::days(timestep),
+            start,
+            end,
+            duration,
+            calendar: Calendar::Gregorian,
+            warm_up_end: None,
         })
     }
 
-    /// Create a vector of `Timestep`s between the start and end dates at the given duration.
+    /// The timestepper as a schema, or `None` if its timesteps are not a number of days of the
+    /// Gregorian calendar. Any warm-up period is not included.
+    pub(crate) fn schema(&self) -> Option<TimestepperSchema> {
+        match (&self.duration, self.calendar) {
+            (TimestepDuration::Days(days), Calendar::Gregorian) => Some(TimestepperSchema {
+                start: self.start.format("%Y-%m-%d").to_string(),
+                end: self.end.format("%Y-%m-%d").to_string(),
+                timestep: *days,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Treat the timesteps before `end` as a warm-up period.
+    ///
+    /// These timesteps are simulated, so that storage and other state reach realistic values, but
+    /// are not saved by any recorder or included in their aggregated values.
+    pub fn with_warm_up(mut self, end: NaiveDate) -> Self {
+        self.warm_up_end = Some(end);
+        self
+    }
+
+    /// Whether `timestep` is part of the warm-up period.
+    pub(crate) fn is_warm_up(&self, timestep: &Timestep) -> bool {
+        match self.warm_up_end {
+            Some(end) => timestep.date < end,
+            None => false,
+        }
+    }
+
+    /// Use `calendar` for the dates of the timesteps.
+    ///
+    /// Calendars other than the Gregorian calendar only support timesteps of a whole number of
+    /// days, which are counted in the calendar's days.
+    pub fn with_calendar(mut self, calendar: Calendar) -> Result<Self, PywrError> {
+        if calendar != Calendar::Gregorian && !matches!(self.duration, TimestepDuration::Days(_)) {
+            return Err(PywrError::InvalidTimestepDuration(format!(
+                "{:?} is not supported by the {:?} calendar",
+                self.duration, calendar
+            )));
+        }
+        self.calendar = calendar;
+        Ok(self)
+    }
+
+    /// Create a vector of `Timestep`s that start between the start and end dates.
+    ///
+    /// The duration of each `Timestep` is the time until the start of the next, which varies
+    /// between timesteps of calendar months or of given dates.
     pub(crate) fn timesteps(&self) -> Vec<Timestep> {
         let mut timesteps: Vec<Timestep> = Vec::new();
-        let mut current = Timestep::new(self.start, 0, self.timestep);
 
+        match &self.duration {
+            TimestepDuration::Days(days) if self.calendar != Calendar::Gregorian => {
+                let end = CalendarDate::from(self.end);
+                let mut date = CalendarDate::from(self.start);
+                while date <= end {
+                    let timestep =
+                        Timestep::new_in_calendar(date, self.calendar, timesteps.len(), ChronoDuration::days(*days));
+                    timesteps.push(timestep);
+                    for _ in 0..*days {
+                        date = self.calendar.next_day(date);
+                    }
+                }
+            }
+            TimestepDuration::Days(days) => self.push_fixed(&mut timesteps, ChronoDuration::days(*days)),
+            TimestepDuration::Fixed(duration) => self.push_fixed(&mut timesteps, *duration),
+            TimestepDuration::Months(months) => {
+                let mut date = self.start;
+                while date <= self.end {
+                    let next = add_months(self.start, (timesteps.len() as u32 + 1) * months);
+                    timesteps.push(Timestep::new(date, timesteps.len(), next - date));
+                    date = next;
+                }
+            }
+            TimestepDuration::Dates(dates) => {
+                for w in dates.windows(2) {
+                    if w[0] >= self.start && w[0] <= self.end {
+                        timesteps.push(Timestep::new(w[0], timesteps.len(), w[1] - w[0]));
+                    }
+                }
+            }
+        }
+        timesteps
+    }
+
+    /// Push timesteps of a fixed `duration`; sub-daily timesteps continue through the end date.
+    fn push_fixed(&self, timesteps: &mut Vec<Timestep>, duration: ChronoDuration) {
+        let mut current = Timestep::new(self.start, 0, duration);
         while current.date <= self.end {
-            let next = current + self.timestep;
+            let next = current + duration;
             timesteps.push(current);
             current = next;
         }
-        timesteps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    /// Test a timestepper is created from dates, and errors identify the date that is invalid
+    fn test_timestepper_dates() {
+        let timestepper = Timestepper::from_dates(date(2020, 1, 1), date(2020, 1, 10), TimestepDuration::Days(1));
+        assert_eq!(timestepper.unwrap().timesteps().len(), 10);
+
+        assert_eq!(
+            Timestepper::from_dates(date(2020, 1, 10), date(2020, 1, 1), TimestepDuration::Days(1)).err(),
+            Some(PywrError::EndDateBeforeStartDate {
+                start: date(2020, 1, 10),
+                end: date(2020, 1, 1)
+            })
+        );
+
+        let error = Timestepper::new("2020-01-01", "2020-13-01", "%Y-%m-%d", 1).unwrap_err();
+        assert!(matches!(&error, PywrError::InvalidDate { name, date, .. } if name == "end" && date == "2020-13-01"));
+        assert_eq!(
+            error.to_string(),
+            "invalid end date `2020-13-01` for the format `%Y-%m-%d`: input is out of range"
+        );
+    }
+
+    #[test]
+    /// Test weekly timesteps last seven days
+    fn test_weekly_timesteps() {
+        let timestepper = Timestepper::with_duration("2020-01-01", "2020-01-31", "%Y-%m-%d", TimestepDuration::Days(7));
+        let timesteps = timestepper.unwrap().timesteps();
+        assert_eq!(timesteps.len(), 5);
+        assert_eq!(timesteps[4].date, date(2020, 1, 29));
+        assert!(timesteps.iter().all(|ts| ts.days() == 7.0));
+    }
+
+    #[test]
+    /// Test hourly timesteps cover the whole of the end date
+    fn test_hourly_timesteps() {
+        let duration = TimestepDuration::Fixed(ChronoDuration::hours(1));
+        let timestepper = Timestepper::with_duration("2020-01-01", "2020-01-02", "%Y-%m-%d", duration);
+        let timesteps = timestepper.unwrap().timesteps();
+        assert_eq!(timesteps.len(), 48);
+        assert_eq!(timesteps[25].date, date(2020, 1, 2));
+        assert_eq!(timesteps[25].hour(), 1);
+        assert_eq!(timesteps[25].label(), "2020-01-02 01:00:00");
+        assert_eq!(timesteps[25].days(), 1.0 / 24.0);
+    }
+
+    #[test]
+    /// Test the days of a year in each calendar
+    fn test_calendars() {
+        let timestepper = Timestepper::new("2020-01-01", "2021-12-31", "%Y-%m-%d", 1).unwrap();
+        let timesteps = timestepper.timesteps();
+        assert_eq!(timesteps.len(), 366 + 365);
+        assert_eq!(timesteps[365].day_of_year(), 366);
+        // The 1st of March has the same position in leap and other years.
+        assert_eq!(timesteps[60].day_of_year_index(), 60);
+        assert_eq!(timesteps[366 + 59].day_of_year_index(), 60);
+
+        let timestepper = Timestepper::new("2020-01-01", "2021-12-31", "%Y-%m-%d", 1).unwrap();
+        let timesteps = timestepper.with_calendar(Calendar::NoLeap).unwrap().timesteps();
+        assert_eq!(timesteps.len(), 365 * 2);
+        assert_eq!(timesteps[59].label(), "2020-03-01");
+        assert_eq!(timesteps[364].day_of_year(), 365);
+        assert_eq!(timesteps[365].date, date(2021, 1, 1));
+
+        let timestepper = Timestepper::new("2020-01-01", "2021-12-30", "%Y-%m-%d", 1).unwrap();
+        let timesteps = timestepper.with_calendar(Calendar::Days360).unwrap().timesteps();
+        assert_eq!(timesteps.len(), 360 * 2);
+        assert_eq!(timesteps[59].label(), "2020-02-30");
+        assert_eq!(timesteps[59].date, date(2020, 2, 29));
+        assert_eq!(timesteps[59].day_of_year_index(), 59);
+        assert_eq!(timesteps[359].day_of_year(), 360);
+
+        let timestepper =
+            Timestepper::with_duration("2020-01-01", "2020-12-31", "%Y-%m-%d", TimestepDuration::Months(1));
+        assert!(matches!(
+            timestepper.unwrap().with_calendar(Calendar::Days360),
+            Err(PywrError::InvalidTimestepDuration(_))
+        ));
+    }
+
+    #[test]
+    /// Test monthly timesteps last until the same day of the next month
+    fn test_monthly_timesteps() {
+        let timestepper =
+            Timestepper::with_duration("2020-01-31", "2020-12-31", "%Y-%m-%d", TimestepDuration::Months(1));
+        let timesteps = timestepper.unwrap().timesteps();
+        assert_eq!(timesteps.len(), 12);
+        assert_eq!(timesteps[1].date, date(2020, 2, 29));
+        assert_eq!(timesteps[2].date, date(2020, 3, 31));
+        assert_eq!(timesteps[0].days(), 29.0);
+        assert_eq!(timesteps[1].days(), 31.0);
+        assert_eq!(timesteps[11].date, date(2020, 12, 31));
+        assert_eq!(timesteps[11].days(), 31.0);
+        assert_eq!(timesteps[11].index, 11);
+    }
+
+    #[test]
+    /// Test irregular timesteps last until the next date
+    fn test_irregular_timesteps() {
+        let dates = vec![date(2020, 1, 1), date(2020, 1, 11), date(2020, 1, 21), date(2020, 2, 1)];
+        let timestepper =
+            Timestepper::with_duration("2020-01-01", "2020-12-31", "%Y-%m-%d", TimestepDuration::Dates(dates));
+        let timesteps = timestepper.unwrap().timesteps();
+        let days: Vec<f64> = timesteps.iter().map(|ts| ts.days()).collect();
+        assert_eq!(days, vec![10.0, 10.0, 11.0]);
+
+        for duration in &[
+            TimestepDuration::Days(0),
+            TimestepDuration::Fixed(ChronoDuration::zero()),
+            TimestepDuration::Months(0),
+            TimestepDuration::Dates(vec![date(2020, 1, 2), date(2020, 1, 1)]),
+        ] {
+            let result = Timestepper::with_duration("2020-01-01", "2020-12-31", "%Y-%m-%d", duration.clone());
+            assert!(matches!(result, Err(PywrError::InvalidTimestepDuration(_))));
+        }
     }
 }
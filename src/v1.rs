@@ -0,0 +1,712 @@
+use crate::json::Json;
+use crate::metric::Metric;
+use crate::model::Model;
+use crate::node::{Constraint, ConstraintValue, Node, NodeType};
+use crate::parameters::{self, AggFunc, Parameter};
+use crate::recorders::{self, AggregatedRecorder, Array2Recorder, Direction, RecorderAggregation};
+use crate::timestep::{TimestepDuration, Timestepper};
+use crate::PywrError;
+use chrono::{Datelike, Duration, NaiveDate};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A component of a pywr v1 model file that could not be loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unsupported {
+    /// The kind of component, e.g. `node` or `parameter`.
+    pub kind: &'static str,
+    pub name: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} `{}`: {}", self.kind, self.name, self.reason)
+    }
+}
+
+/// A model loaded from a pywr v1 model file.
+pub struct V1Model {
+    pub model: Model,
+    pub timestepper: Timestepper,
+    /// The components of the file that are not supported, which are missing from `model` or
+    /// loaded without the unsupported attributes.
+    pub unsupported: Vec<Unsupported>,
+}
+
+/// Load a pywr v1 JSON model file.
+///
+/// The input, output, link and storage nodes (and the `catchment`, `river` and `reservoir` nodes
+/// that are equivalent to them), the edges between them, the scenarios, the constant, aggregated,
+/// profile, array and CSV `dataframe` parameters, and the CSV, HDF5, array and flow, deficit and
+/// volume aggregating recorders are loaded. Any other component is listed in `unsupported` rather
+/// than failing the load, so that the model can be checked for what is missing before it is run.
+pub fn load(path: &Path) -> Result<V1Model, PywrError> {
+    let json = fs::read_to_string(path).map_err(|e| PywrError::IOError(e.to_string()))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    load_str(&json, directory)
+}
+
+/// Load a pywr v1 model from `json`, with the paths of its tables and outputs relative to
+/// `directory`.
+pub fn load_str(json: &str, directory: &Path) -> Result<V1Model, PywrError> {
+    let json = Json::parse(json).map_err(|e| invalid(&e))?;
+    let mut loader = Loader {
+        json: &json,
+        directory,
+        model: Model::new(),
+        unsupported: Vec::new(),
+        parameters: HashMap::new(),
+        loading: Vec::new(),
+        unsupported_nodes: HashSet::new(),
+    };
+
+    for (key, _) in members(&json, "model")? {
+        if ![
+            "metadata",
+            "timestepper",
+            "scenarios",
+            "nodes",
+            "edges",
+            "parameters",
+            "recorders",
+            "tables",
+        ]
+        .contains(&key.as_str())
+        {
+            loader.unsupported("section", key, "this section is not supported");
+        }
+    }
+
+    let timestepper = timestepper(required(&json, "timestepper", "model")?)?;
+    loader.load_scenarios()?;
+    loader.load_tables()?;
+    loader.load_parameters()?;
+    loader.load_nodes()?;
+    loader.load_edges()?;
+    loader.load_recorders()?;
+
+    Ok(V1Model {
+        model: loader.model,
+        timestepper,
+        unsupported: loader.unsupported,
+    })
+}
+
+fn invalid(reason: &str) -> PywrError {
+    PywrError::InvalidV1Model(reason.to_string())
+}
+
+fn required<'a>(json: &'a Json, key: &str, owner: &str) -> Result<&'a Json, PywrError> {
+    json.get(key)
+        .ok_or_else(|| invalid(&format!("{} is missing `{}`", owner, key)))
+}
+
+fn string<'a>(json: &'a Json, key: &str, owner: &str) -> Result<&'a str, PywrError> {
+    let value = required(json, key, owner)?;
+    value
+        .as_str()
+        .ok_or_else(|| invalid(&format!("expected `{}` of {} to be a string", key, owner)))
+}
+
+fn number(json: &Json, key: &str, owner: &str) -> Result<f64, PywrError> {
+    let value = required(json, key, owner)?;
+    value
+        .as_f64()
+        .ok_or_else(|| invalid(&format!("expected `{}` of {} to be a number", key, owner)))
+}
+
+fn members<'a>(json: &'a Json, owner: &str) -> Result<&'a [(String, Json)], PywrError> {
+    json.as_object()
+        .ok_or_else(|| invalid(&format!("expected {} to be an object, not {}", owner, json.type_name())))
+}
+
+fn numbers(json: &Json, key: &str, owner: &str) -> Result<Vec<f64>, PywrError> {
+    let values = required(json, key, owner)?;
+    values
+        .as_array()
+        .and_then(|values| values.iter().map(|v| v.as_f64()).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| invalid(&format!("expected `{}` of {} to be an array of numbers", key, owner)))
+}
+
+/// The lower case type of a component without its `suffix`, e.g. `constant` for `ConstantParameter`.
+fn component_type(json: &Json, suffix: &str, owner: &str) -> Result<String, PywrError> {
+    let kind = string(json, "type", owner)?.to_lowercase();
+    Ok(match kind.strip_suffix(suffix) {
+        Some(kind) if !kind.is_empty() => kind.to_string(),
+        _ => kind,
+    })
+}
+
+fn timestepper(json: &Json) -> Result<Timestepper, PywrError> {
+    let owner = "the timestepper";
+    let duration = match json.get("timestep") {
+        None => TimestepDuration::Days(1),
+        Some(Json::Number(days)) if days.fract() == 0.0 => TimestepDuration::Days(*days as i64),
+        Some(Json::String(frequency)) => match frequency.as_str() {
+            "D" => TimestepDuration::Days(1),
+            "W" => TimestepDuration::Days(7),
+            "M" | "MS" => TimestepDuration::Months(1),
+            _ => return Err(invalid(&format!("unsupported timestep frequency `{}`", frequency))),
+        },
+        Some(_) => return Err(invalid("expected the timestep to be a number of days or a frequency")),
+    };
+    Timestepper::with_duration(
+        string(json, "start", owner)?,
+        string(json, "end", owner)?,
+        "%Y-%m-%d",
+        duration,
+    )
+}
+
+/// Expand the 12 values of a monthly profile to a value for each day of a leap year.
+fn monthly_to_daily(values: &[f64]) -> Vec<f64> {
+    let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+    (0..366)
+        .map(|day| values[(start + Duration::days(day)).month0() as usize])
+        .collect()
+}
+
+/// The values of `column` of a CSV file, which has a header row.
+fn csv_column(path: &Path, column: &str) -> Result<Vec<f64>, PywrError> {
+    let contents = fs::read_to_string(path).map_err(|e| PywrError::CSVError(e.to_string()))?;
+    let mut lines = contents.lines().filter(|l| !l.trim().is_empty());
+    let header = lines.next().unwrap_or_default();
+    let position = match header.split(',').position(|name| name.trim() == column) {
+        Some(p) => p,
+        None => return Err(invalid(&format!("{} has no column `{}`", path.display(), column))),
+    };
+    lines
+        .map(|line| match line.split(',').nth(position).map(|v| v.trim().parse()) {
+            Some(Ok(value)) => Ok(value),
+            _ => Err(invalid(&format!(
+                "invalid value of column `{}` in {}: `{}`",
+                column,
+                path.display(),
+                line
+            ))),
+        })
+        .collect()
+}
+
+struct Loader<'a> {
+    json: &'a Json,
+    directory: &'a Path,
+    model: Model,
+    unsupported: Vec<Unsupported>,
+    /// The parameters loaded by name, or `None` for those that are not supported.
+    parameters: HashMap<String, Option<Parameter>>,
+    /// The names of the parameters being loaded, to detect circular references.
+    loading: Vec<String>,
+    unsupported_nodes: HashSet<String>,
+}
+
+impl<'a> Loader<'a> {
+    fn unsupported(&mut self, kind: &'static str, name: &str, reason: &str) {
+        self.unsupported.push(Unsupported {
+            kind,
+            name: name.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    fn section(&self, key: &str) -> Result<&'a [(String, Json)], PywrError> {
+        match self.json.get(key) {
+            Some(section) => members(section, &format!("`{}`", key)),
+            None => Ok(&[]),
+        }
+    }
+
+    fn load_scenarios(&mut self) -> Result<(), PywrError> {
+        let scenarios = match self.json.get("scenarios") {
+            Some(Json::Array(scenarios)) => scenarios,
+            Some(_) => return Err(invalid("expected `scenarios` to be an array")),
+            None => return Ok(()),
+        };
+        for scenario in scenarios {
+            let name = string(scenario, "name", "a scenario")?;
+            let owner = format!("scenario `{}`", name);
+            match scenario.get("ensemble_names").and_then(|n| n.as_array()) {
+                Some(labels) => {
+                    let labels = labels
+                        .iter()
+                        .map(|l| l.as_str())
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| invalid(&format!("expected the ensemble names of {} to be strings", owner)))?;
+                    self.model.add_labelled_scenario(name, &labels)?;
+                }
+                None => self
+                    .model
+                    .add_scenario(name, number(scenario, "size", &owner)? as usize)?,
+            }
+            for (key, _) in members(scenario, &owner)? {
+                if !["name", "size", "ensemble_names", "comment"].contains(&key.as_str()) {
+                    self.unsupported("scenario", name, &format!("`{}` is not supported", key));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Report the tables that cannot be read; only CSV files are supported.
+    fn load_tables(&mut self) -> Result<(), PywrError> {
+        for (name, table) in self.section("tables")? {
+            let url = string(table, "url", &format!("table `{}`", name))?;
+            if !url.to_lowercase().ends_with(".csv") {
+                self.unsupported("table", name, "only CSV tables are supported");
+            }
+        }
+        Ok(())
+    }
+
+    fn load_parameters(&mut self) -> Result<(), PywrError> {
+        for (name, _) in self.section("parameters")? {
+            self.named_parameter(name)?;
+        }
+        Ok(())
+    }
+
+    /// The parameter called `name` in the file, loading it if it has not been already.
+    fn named_parameter(&mut self, name: &str) -> Result<Option<Parameter>, PywrError> {
+        if let Some(parameter) = self.parameters.get(name) {
+            return Ok(parameter.clone());
+        }
+        if self.loading.iter().any(|n| n == name) {
+            return Err(invalid(&format!("parameter `{}` refers to itself", name)));
+        }
+        let definition = match self.json.get("parameters").and_then(|p| p.get(name)) {
+            Some(definition) => definition,
+            None => return Err(invalid(&format!("parameter `{}` is not defined", name))),
+        };
+
+        self.loading.push(name.to_string());
+        let parameter = self.load_parameter(name, definition);
+        self.loading.pop();
+        let parameter = parameter?;
+        self.parameters.insert(name.to_string(), parameter.clone());
+        Ok(parameter)
+    }
+
+    /// The parameter given by `json`, which is a reference to a named parameter, a parameter
+    /// defined in place (called `name`) or a number.
+    fn parameter(&mut self, name: &str, json: &Json) -> Result<Option<Parameter>, PywrError> {
+        match json {
+            Json::String(reference) => self.named_parameter(reference),
+            Json::Object(_) => self.load_parameter(name, json),
+            Json::Number(value) => Ok(Some(
+                self.model
+                    .add_parameter(Box::new(parameters::ConstantParameter::new(name, *value)))?,
+            )),
+            _ => Err(invalid(&format!(
+                "expected a parameter for `{}`, not {}",
+                name,
+                json.type_name()
+            ))),
+        }
+    }
+
+    fn load_parameter(&mut self, name: &str, json: &Json) -> Result<Option<Parameter>, PywrError> {
+        let owner = format!("parameter `{}`", name);
+        let parameter: Box<dyn parameters::_Parameter> = match component_type(json, "parameter", &owner)?.as_str() {
+            "constant" => match json.get("value") {
+                Some(_) => Box::new(parameters::ConstantParameter::new(name, number(json, "value", &owner)?)),
+                None => {
+                    self.unsupported("parameter", name, "constants without a `value` are not supported");
+                    return Ok(None);
+                }
+            },
+            "aggregated" => {
+                let agg_func = string(json, "agg_func", &owner)?;
+                let agg_func = match AggFunc::from_str(agg_func) {
+                    Ok(agg_func) => agg_func,
+                    Err(_) => {
+                        let reason = format!("the aggregation function `{}` is not supported", agg_func);
+                        self.unsupported("parameter", name, &reason);
+                        return Ok(None);
+                    }
+                };
+                let references = match required(json, "parameters", &owner)?.as_array() {
+                    Some(references) => references,
+                    None => return Err(invalid(&format!("expected `parameters` of {} to be an array", owner))),
+                };
+                let mut parameters = Vec::with_capacity(references.len());
+                for (i, reference) in references.iter().enumerate() {
+                    match self.parameter(&format!("{}.{}", name, i), reference)? {
+                        Some(p) => parameters.push(p),
+                        None => {
+                            self.unsupported("parameter", name, "it aggregates an unsupported parameter");
+                            return Ok(None);
+                        }
+                    }
+                }
+                Box::new(parameters::AggregatedParameter::new(name, parameters, agg_func))
+            }
+            "dailyprofile" => {
+                let values = numbers(json, "values", &owner)?;
+                if values.len() != 366 {
+                    return Err(invalid(&format!("{} must have 366 values", owner)));
+                }
+                Box::new(parameters::DailyProfileParameter::new(name, values))
+            }
+            "monthlyprofile" => {
+                let values = numbers(json, "values", &owner)?;
+                if values.len() != 12 {
+                    return Err(invalid(&format!("{} must have 12 values", owner)));
+                }
+                Box::new(parameters::DailyProfileParameter::new(name, monthly_to_daily(&values)))
+            }
+            "array" | "arrayindexed" => {
+                Box::new(parameters::VectorParameter::new(name, numbers(json, "values", &owner)?))
+            }
+            "dataframe" => {
+                let url = match (json.get("url"), json.get("table").and_then(|t| t.as_str())) {
+                    (Some(url), _) => url.as_str(),
+                    (None, Some(table)) => self
+                        .json
+                        .get("tables")
+                        .and_then(|tables| tables.get(table))
+                        .and_then(|table| table.get("url"))
+                        .and_then(|url| url.as_str()),
+                    (None, None) => None,
+                };
+                let (url, column) = match (url, json.get("column").and_then(|c| c.as_str())) {
+                    (Some(url), Some(column)) if url.to_lowercase().ends_with(".csv") => (url, column),
+                    _ => {
+                        let reason = "only dataframes of a column of a CSV file are supported";
+                        self.unsupported("parameter", name, reason);
+                        return Ok(None);
+                    }
+                };
+                let values = csv_column(&self.directory.join(url), column)?;
+                Box::new(parameters::VectorParameter::new(name, values))
+            }
+            kind => {
+                let reason = format!("the parameter type `{}` is not supported", kind);
+                self.unsupported("parameter", name, &reason);
+                return Ok(None);
+            }
+        };
+        Ok(Some(self.model.add_parameter(parameter)?))
+    }
+
+    /// The constraint value given by `json`, or `None` if it is an unsupported parameter.
+    fn constraint_value(&mut self, name: &str, json: &Json) -> Result<Option<ConstraintValue>, PywrError> {
+        match json {
+            Json::Number(value) => Ok(Some(ConstraintValue::Scalar(*value))),
+            json => Ok(self.parameter(name, json)?.map(ConstraintValue::Parameter)),
+        }
+    }
+
+    fn load_nodes(&mut self) -> Result<(), PywrError> {
+        let nodes = match self.json.get("nodes") {
+            Some(Json::Array(nodes)) => nodes,
+            Some(_) => return Err(invalid("expected `nodes` to be an array")),
+            None => return Ok(()),
+        };
+        for json in nodes {
+            let name = string(json, "name", "a node")?;
+            let owner = format!("node `{}`", name);
+            let kind = component_type(json, "node", &owner)?;
+            let node = match kind.as_str() {
+                "input" | "catchment" => self.model.add_input_node(name)?,
+                "link" | "river" => self.model.add_link_node(name)?,
+                "output" => self.model.add_output_node(name)?,
+                "storage" | "reservoir" => {
+                    let initial_volume = match (json.get("initial_volume"), json.get("initial_volume_pc")) {
+                        (Some(volume), _) => volume.as_f64(),
+                        (None, Some(proportion)) => match (proportion.as_f64(), json.get("max_volume")) {
+                            (Some(proportion), Some(Json::Number(max_volume))) => Some(proportion * max_volume),
+                            _ => None,
+                        },
+                        (None, None) => Some(0.0),
+                    };
+                    let initial_volume = initial_volume.ok_or_else(|| {
+                        invalid(&format!(
+                            "{} must have a number `initial_volume`, or `initial_volume_pc` and `max_volume`",
+                            owner
+                        ))
+                    })?;
+                    self.model.add_storage_node(name, initial_volume)?
+                }
+                kind => {
+                    let reason = format!("the node type `{}` is not supported", kind);
+                    self.unsupported("node", name, &reason);
+                    self.unsupported_nodes.insert(name.to_string());
+                    continue;
+                }
+            };
+            self.load_node_attributes(&node, &kind, json)?;
+        }
+        Ok(())
+    }
+
+    fn load_node_attributes(&mut self, node: &Node, kind: &str, json: &Json) -> Result<(), PywrError> {
+        let name = node.name();
+        let storage = matches!(node.node_type(), NodeType::Storage);
+        for (key, value) in members(json, &format!("node `{}`", name))? {
+            let constraint = match key.as_str() {
+                "name" | "type" | "comment" | "position" => continue,
+                "initial_volume" | "initial_volume_pc" if storage => continue,
+                "max_flow" if !storage => Constraint::MaxFlow,
+                "min_flow" if !storage => Constraint::MinFlow,
+                "flow" if kind == "catchment" => Constraint::MinAndMaxFlow,
+                "max_volume" if storage => Constraint::MaxVolume,
+                "min_volume" if storage => Constraint::MinVolume,
+                "cost" => {
+                    match self.constraint_value(&format!("{}.cost", name), value)? {
+                        Some(cost) => node.set_cost(cost),
+                        None => self.unsupported("node", &name, "its cost is an unsupported parameter"),
+                    }
+                    continue;
+                }
+                _ => {
+                    self.unsupported("node", &name, &format!("the attribute `{}` is not supported", key));
+                    continue;
+                }
+            };
+            match self.constraint_value(&format!("{}.{}", name, key), value)? {
+                Some(value) => node.set_constraint(value, constraint)?,
+                None => {
+                    let reason = format!("its `{}` is an unsupported parameter", key);
+                    self.unsupported("node", &name, &reason)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn load_edges(&mut self) -> Result<(), PywrError> {
+        let edges = match self.json.get("edges") {
+            Some(Json::Array(edges)) => edges,
+            Some(_) => return Err(invalid("expected `edges` to be an array")),
+            None => return Ok(()),
+        };
+        for edge in edges {
+            let names = edge
+                .as_array()
+                .and_then(|names| names.iter().map(|n| n.as_str()).collect::<Option<Vec<_>>>())
+                .filter(|names| names.len() >= 2)
+                .ok_or_else(|| invalid("expected each edge to be an array of node names"))?;
+            let name = format!("{} -> {}", names[0], names[1]);
+            if names.len() > 2 {
+                self.unsupported("edge", &name, "edges between slots are not supported");
+                continue;
+            }
+            match names.iter().find(|n| self.unsupported_nodes.contains(**n)) {
+                Some(node) => {
+                    let reason = format!("node `{}` is not supported", node);
+                    self.unsupported("edge", &name, &reason);
+                }
+                None => {
+                    let from = self.model.get_node_by_name(names[0])?;
+                    let to = self.model.get_node_by_name(names[1])?;
+                    self.model.connect_nodes(&from, &to)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn load_recorders(&mut self) -> Result<(), PywrError> {
+        for (name, json) in self.section("recorders")? {
+            let owner = format!("recorder `{}`", name);
+            let kind = component_type(json, "recorder", &owner)?;
+            if let Some(recorder) = self.load_recorder(name, &kind, json)? {
+                self.model.add_recorder(recorder)?;
+                self.load_recorder_targets(name, json)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_recorder(
+        &mut self,
+        name: &str,
+        kind: &str,
+        json: &Json,
+    ) -> Result<Option<Box<dyn recorders::_Recorder>>, PywrError> {
+        let owner = format!("recorder `{}`", name);
+        let directory = self.directory;
+        let path = |key: &str| -> Result<_, PywrError> { Ok(directory.join(string(json, key, &owner)?)) };
+        let (metric, temporal_aggregation) = match kind {
+            "csv" => return Ok(Some(Box::new(recorders::csv::CSVRecorder::new(name, path("url")?)))),
+            "tables" => return Ok(Some(Box::new(recorders::hdf::HDF5Recorder::new(name, path("url")?)))),
+            "numpyarraynode" | "numpyarraystorage" => {
+                let node = match self.recorded_node(name, json)? {
+                    Some(node) => node,
+                    None => return Ok(None),
+                };
+                return Ok(Some(Box::new(Array2Recorder::new(name, node.default_metric()))));
+            }
+            "totalflownode" | "meanflownode" => {
+                let node = match self.recorded_node(name, json)? {
+                    Some(node) => node,
+                    None => return Ok(None),
+                };
+                let aggregation = match kind {
+                    "totalflownode" => RecorderAggregation::Sum,
+                    _ => RecorderAggregation::Mean,
+                };
+                (node.default_metric(), aggregation)
+            }
+            "totaldeficitnode" => match self.recorded_node(name, json)? {
+                Some(node) => (Metric::NodeDeficit(node.index()), RecorderAggregation::Sum),
+                None => return Ok(None),
+            },
+            "minimumvolumestorage" => match self.recorded_node(name, json)? {
+                Some(node) => (Metric::NodeVolume(node.index()), RecorderAggregation::Min),
+                None => return Ok(None),
+            },
+            kind => {
+                let reason = format!("the recorder type `{}` is not supported", kind);
+                self.unsupported("recorder", name, &reason);
+                return Ok(None);
+            }
+        };
+
+        let agg_func = json.get("agg_func").and_then(|f| f.as_str()).unwrap_or("mean");
+        let scenario_aggregation = match RecorderAggregation::from_str(agg_func) {
+            Ok(aggregation) => aggregation,
+            Err(_) => {
+                let reason = format!("the aggregation function `{}` is not supported", agg_func);
+                self.unsupported("recorder", name, &reason);
+                return Ok(None);
+            }
+        };
+        Ok(Some(Box::new(AggregatedRecorder::new(
+            name,
+            metric,
+            temporal_aggregation,
+            scenario_aggregation,
+        ))))
+    }
+
+    /// The node recorded by a recorder, or `None` if the node is not supported.
+    fn recorded_node(&mut self, name: &str, json: &Json) -> Result<Option<Node>, PywrError> {
+        let node = string(json, "node", &format!("recorder `{}`", name))?;
+        if self.unsupported_nodes.contains(node) {
+            let reason = format!("node `{}` is not supported", node);
+            self.unsupported("recorder", name, &reason);
+            return Ok(None);
+        }
+        Ok(Some(self.model.get_node_by_name(node)?))
+    }
+
+    /// Flag the recorder as an objective or constraint of an optimisation.
+    fn load_recorder_targets(&mut self, name: &str, json: &Json) -> Result<(), PywrError> {
+        match json.get("is_objective").and_then(|o| o.as_str()) {
+            Some("minimise") | Some("min") => self.model.set_recorder_objective(name, Direction::Minimise)?,
+            Some("maximise") | Some("max") => self.model.set_recorder_objective(name, Direction::Maximise)?,
+            Some(objective) => return Err(invalid(&format!("invalid objective `{}` of `{}`", objective, name))),
+            None => {}
+        }
+        let lower = json.get("constraint_lower_bounds").and_then(|b| b.as_f64());
+        let upper = json.get("constraint_upper_bounds").and_then(|b| b.as_f64());
+        if lower.is_some() || upper.is_some() {
+            self.model.set_recorder_constraint(name, lower, upper)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::simplex::SimplexSolver;
+    use crate::solvers::SolverSettings;
+    use float_cmp::approx_eq;
+    use tempdir::TempDir;
+
+    const MODEL: &str = r#"{
+        "metadata": {"title": "Simple", "minimum_version": "1.0"},
+        "timestepper": {"start": "2015-01-01", "end": "2015-12-31", "timestep": 1},
+        "scenarios": [{"name": "demand", "ensemble_names": ["low", "high"]}],
+        "nodes": [
+            {"name": "catchment", "type": "Catchment", "flow": "inflow"},
+            {"name": "reservoir", "type": "Storage", "max_volume": 100, "initial_volume_pc": 0.5,
+             "cost": -1, "position": {"schematic": [1, 2]}},
+            {"name": "works", "type": "link", "max_flow": {"type": "monthlyprofile",
+             "values": [12, 12, 12, 12, 12, 12, 6, 6, 6, 12, 12, 12]}},
+            {"name": "demand", "type": "Output", "max_flow": "demand", "cost": -10},
+            {"name": "spill", "type": "Output"},
+            {"name": "split", "type": "RiverSplit"}
+        ],
+        "edges": [
+            ["catchment", "reservoir"], ["reservoir", "works"], ["works", "demand"], ["reservoir", "spill"],
+            ["works", "split"]
+        ],
+        "parameters": {
+            "demand": {"type": "aggregated", "agg_func": "product", "parameters": ["base-demand", 1.2]},
+            "base-demand": {"type": "constant", "value": 10},
+            "inflow": {"type": "dataframe", "table": "flows", "column": "catchment"},
+            "curve": {"type": "controlcurveindex", "storage_node": "reservoir"}
+        },
+        "recorders": {
+            "deficit": {"type": "TotalDeficitNodeRecorder", "node": "demand", "is_objective": "minimise"},
+            "split-flow": {"type": "NumpyArrayNodeRecorder", "node": "split"}
+        },
+        "tables": {"flows": {"url": "flows.csv", "index_col": "date", "parse_dates": true}}
+    }"#;
+
+    #[test]
+    fn test_load() {
+        let dir = TempDir::new("v1").unwrap();
+        let rows: Vec<String> = (0..365)
+            .map(|i| format!("2015-01-01,{}", 20.0 + (i % 2) as f64))
+            .collect();
+        fs::write(
+            dir.path().join("flows.csv"),
+            format!("date,catchment\n{}\n", rows.join("\n")),
+        )
+        .unwrap();
+        fs::write(dir.path().join("model.json"), MODEL).unwrap();
+
+        let V1Model {
+            mut model,
+            timestepper,
+            unsupported,
+        } = load(&dir.path().join("model.json")).unwrap();
+        let unsupported: Vec<String> = unsupported.iter().map(|u| u.to_string()).collect();
+        assert_eq!(
+            unsupported,
+            vec![
+                "parameter `curve`: the parameter type `controlcurveindex` is not supported",
+                "node `split`: the node type `riversplit` is not supported",
+                "edge `works -> split`: node `split` is not supported",
+                "recorder `split-flow`: node `split` is not supported",
+            ]
+        );
+        assert_eq!(model.nodes.len(), 5);
+        assert_eq!(model.edges.len(), 4);
+        assert_eq!(timestepper.timesteps().len(), 365);
+        assert!(model.get_parameter_by_name("demand.1").is_ok());
+
+        model
+            .run(timestepper, &SimplexSolver::new(), &SolverSettings::default())
+            .unwrap();
+        // The demand of 12 is met but for the 6 allowed through the works in the summer.
+        assert!(approx_eq!(
+            f64,
+            model.get_aggregated_value("deficit").unwrap(),
+            6.0 * 92.0,
+            epsilon = 1e-6
+        ));
+        assert_eq!(model.objectives().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let dir = Path::new(".");
+        assert!(matches!(load_str("{}", dir), Err(PywrError::InvalidV1Model(_))));
+        let model = r#"{"timestepper": {"start": "2015-01-01", "end": "2015-01-02"},
+            "nodes": [{"name": "a", "type": "output", "max_flow": "a"}],
+            "parameters": {"a": {"type": "aggregated", "agg_func": "sum", "parameters": ["a"]}}}"#;
+        assert_eq!(
+            load_str(model, dir).err(),
+            Some(PywrError::InvalidV1Model("parameter `a` refers to itself".to_string()))
+        );
+    }
+}
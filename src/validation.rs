@@ -0,0 +1,72 @@
+use crate::parameters::ParameterIndex;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// The model can not be run, or would give meaningless results.
+    Error,
+    /// The model can be run but is probably not what was intended.
+    Warning,
+}
+
+/// A problem with a model found by `Model::validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationIssue {
+    /// The link or storage node has no edges, so nothing flows through it.
+    UnconnectedNode(String),
+    /// The output node has no incoming edges, so nothing reaches it.
+    OutputWithoutIncomingEdges(String),
+    /// The input node has no outgoing edges, so nothing leaves it.
+    InputWithoutOutgoingEdges(String),
+    /// An attribute of the node (e.g. `max_flow`) is a parameter that is not in the model.
+    MissingParameter {
+        node: String,
+        attribute: &'static str,
+        parameter: ParameterIndex,
+    },
+    /// The storage node has no maximum volume, so is unbounded and has no proportional volume.
+    StorageWithoutMaxVolume(String),
+    /// The nodes form a cycle that does not pass through a storage node (see `graph::cycles`).
+    Cycle(Vec<String>),
+}
+
+impl ValidationIssue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UnconnectedNode(_)
+            | Self::OutputWithoutIncomingEdges(_)
+            | Self::InputWithoutOutgoingEdges(_)
+            | Self::Cycle(_) => Severity::Warning,
+            Self::MissingParameter { .. } | Self::StorageWithoutMaxVolume(_) => Severity::Error,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnconnectedNode(node) => write!(f, "node `{}` is not connected to any other node", node),
+            Self::OutputWithoutIncomingEdges(node) => write!(f, "output node `{}` has no incoming edges", node),
+            Self::InputWithoutOutgoingEdges(node) => write!(f, "input node `{}` has no outgoing edges", node),
+            Self::MissingParameter {
+                node,
+                attribute,
+                parameter,
+            } => write!(
+                f,
+                "the {} of node `{}` is parameter {}, which is not in the model",
+                attribute, node, parameter
+            ),
+            Self::StorageWithoutMaxVolume(node) => write!(f, "storage node `{}` has no maximum volume", node),
+            Self::Cycle(nodes) => write!(
+                f,
+                "nodes `{}` form a cycle that does not pass through a storage node",
+                nodes.join("`, `")
+            ),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::node::{Constraint, NodeIndex};
+use crate::scenario::{ScenarioGroupCollection, ScenarioIndex};
+use crate::PywrError;
+
+/// A change to the network that is applied only in some scenarios.
+///
+/// Variations allow options such as "with scheme" and "without scheme" to be appraised in a single
+/// run, with a scenario group selecting between them. Edges have no bounds of their own, so to
+/// switch off an edge route it through a link node and disable that instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variation {
+    /// Switch off the node, so that it has no flow (or, for storage, no change in volume).
+    DisableNode(NodeIndex),
+    /// Remove a constraint of the node, as if it were not set.
+    RemoveConstraint(NodeIndex, Constraint),
+}
+
+impl Variation {
+    pub fn node(&self) -> NodeIndex {
+        match self {
+            Self::DisableNode(node) => *node,
+            Self::RemoveConstraint(node, _) => *node,
+        }
+    }
+}
+
+/// A variation applied in the given members of a scenario group.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScenarioVariation {
+    group: String,
+    members: Vec<usize>,
+    variation: Variation,
+}
+
+impl ScenarioVariation {
+    pub(crate) fn new(group: &str, members: &[usize], variation: Variation) -> Self {
+        Self {
+            group: group.to_string(),
+            members: members.to_vec(),
+            variation,
+        }
+    }
+
+    pub(crate) fn variation(&self) -> &Variation {
+        &self.variation
+    }
+
+    /// Check the group exists in `scenarios` and has each of the members.
+    pub(crate) fn check(&self, scenarios: &ScenarioGroupCollection) -> Result<(), PywrError> {
+        let size = scenarios.group_size(&self.group)?;
+        match self.members.iter().find(|&&member| member >= size) {
+            Some(&member) => Err(PywrError::InvalidScenarioVariation {
+                group: self.group.clone(),
+                member,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the variation applies in the scenario `scenario_index` of `scenarios`.
+    pub(crate) fn applies(
+        &self,
+        scenarios: &ScenarioGroupCollection,
+        scenario_index: &ScenarioIndex,
+    ) -> Result<bool, PywrError> {
+        let member = scenario_index.group_index(scenarios, &self.group)?;
+        Ok(self.members.contains(&member))
+    }
+}
@@ -0,0 +1,82 @@
+use crate::model::Model;
+use crate::node::{NodeIndex, NodeType};
+use crate::state::NetworkState;
+use crate::PywrError;
+
+index_type!(VirtualNodeIndex);
+
+/// The quantity a `VirtualNode` measures.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VirtualNodeType {
+    /// The total flow through the nodes, each scaled by a factor. The flow through an input node
+    /// is its outflow, and through any other node its inflow.
+    Sum(Vec<(NodeIndex, f64)>),
+    /// The inflow to the nodes less their outflow. The flows between the nodes cancel, so this is
+    /// the net inflow to the group from the rest of the network.
+    Balance(Vec<NodeIndex>),
+}
+
+/// A named quantity computed from the flows of other nodes, e.g. the total supply of a zone.
+///
+/// Virtual nodes are not part of the allocation problem, so they add nothing to the solve. Their
+/// values are available as the `virtual_node` metric, which can be recorded like any other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VirtualNode {
+    index: VirtualNodeIndex,
+    name: String,
+    virtual_node_type: VirtualNodeType,
+}
+
+impl VirtualNode {
+    pub(crate) fn new(index: VirtualNodeIndex, name: &str, virtual_node_type: VirtualNodeType) -> Self {
+        Self {
+            index,
+            name: name.to_string(),
+            virtual_node_type,
+        }
+    }
+
+    pub fn index(&self) -> VirtualNodeIndex {
+        self.index
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn virtual_node_type(&self) -> &VirtualNodeType {
+        &self.virtual_node_type
+    }
+
+    /// The indices of the nodes the virtual node observes.
+    pub fn nodes(&self) -> Vec<NodeIndex> {
+        match &self.virtual_node_type {
+            VirtualNodeType::Sum(nodes) => nodes.iter().map(|(node, _)| *node).collect(),
+            VirtualNodeType::Balance(nodes) => nodes.clone(),
+        }
+    }
+
+    /// The value of the virtual node given the flows of `network_state`.
+    pub(crate) fn get_value(&self, model: &Model, network_state: &NetworkState) -> Result<f64, PywrError> {
+        match &self.virtual_node_type {
+            VirtualNodeType::Sum(nodes) => {
+                let mut total = 0.0;
+                for &(index, factor) in nodes {
+                    let flow = match model.get_node(index)?.node_type() {
+                        NodeType::Input => network_state.get_node_out_flow(index)?,
+                        _ => network_state.get_node_in_flow(index)?,
+                    };
+                    total += factor * flow;
+                }
+                Ok(total)
+            }
+            VirtualNodeType::Balance(nodes) => {
+                let mut balance = 0.0;
+                for &index in nodes {
+                    balance += network_state.get_node_in_flow(index)? - network_state.get_node_out_flow(index)?;
+                }
+                Ok(balance)
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use pywr::builder::ModelBuilder;
+use pywr::solvers::{solver_by_name, SolverSettings};
+use pywr::timestep::{TimestepDuration, Timestepper};
+use pywr::PywrError;
+
+#[test]
+/// Test stepping a run through the public API, with the volume of a reservoir replaced between
+/// steps as a coupled model would.
+fn test_step_run_with_coupled_volume() {
+    let mut model = ModelBuilder::new()
+        .storage("reservoir", 100.0)
+        .output("demand")
+        .connect("reservoir", "demand")
+        .max_volume("reservoir", 100.0)
+        .max_flow("demand", 10.0)
+        .cost("demand", -10.0)
+        .scenario("climate", 2)
+        .build()
+        .unwrap();
+    let reservoir = model.get_node_by_name("reservoir").unwrap().index();
+    let solver = solver_by_name("simplex").unwrap();
+    let timestepper =
+        Timestepper::with_duration("2020-01-01", "2020-01-10", "%Y-%m-%d", TimestepDuration::Days(1)).unwrap();
+
+    let mut run = model
+        .start_run(&timestepper, solver.as_ref(), &SolverSettings::default())
+        .unwrap();
+    assert_eq!(run.scenario_indices().len(), 2);
+    while let Some(timestep) = run.next_timestep() {
+        let index = timestep.index;
+        model.step_run(&mut run).unwrap();
+        for state in run.states() {
+            let volume = state.get_node_volume(reservoir).unwrap();
+            match index {
+                0..=3 => assert!((volume - (90.0 - 10.0 * index as f64)).abs() < 1e-6),
+                _ => assert!((volume - (100.0 - 10.0 * (index - 3) as f64)).abs() < 1e-6),
+            }
+        }
+        // The coupled model refills the reservoir after the fourth timestep.
+        if index == 3 {
+            for state in run.states_mut() {
+                state.set_node_volume(reservoir, 100.0).unwrap();
+            }
+        }
+    }
+    assert_eq!(model.step_run(&mut run), Err(PywrError::RunComplete));
+
+    let results = model.finish_run(run).unwrap();
+    assert_eq!(results.statistics.timesteps, 10);
+    assert_eq!(results.statistics.scenarios, 2);
+}
@@ -1,3 +1,4 @@
+import datetime
 import numpy as np
 import pandas
 from pywr.nodes import Model, HDF5Output
@@ -65,3 +66,186 @@ def test_model(model_dir: Path, tmpdir: Path, model_name: str):
             np.testing.assert_allclose(
                 np.squeeze(fh[node.name]), expected_data[node.name]
             )
+
+
+def test_build_with_extension_module(tmpdir: Path):
+    """Test building and running a model directly with the extension module"""
+    from pywr.pywr import PyModel  # type: ignore
+
+    r_model = PyModel()
+    r_model.add_scenario("demand-scenario", 2)
+    r_model.add_input_node("supply")
+    r_model.add_link_node("works")
+    r_model.add_output_node("demand")
+    r_model.connect_nodes("supply", "works")
+    r_model.connect_nodes("works", "demand")
+
+    r_model.add_array2("demand-flow", np.array([[5.0, 8.0]] * 10), "demand-scenario")
+    r_model.add_daily_profile("supply-flow", [20.0] * 366)
+    r_model.set_node_constraint("supply", "max_flow", "supply-flow")
+    r_model.set_node_constraint("demand", "max_flow", "demand-flow")
+    r_model.set_node_cost("demand", -10.0)
+    r_model.set_edge_constraint("works", "demand", "max_flow", 6.0)
+
+    output_fn = tmpdir / "output.csv"
+    r_model.add_csv_output("csv", str(output_fn))
+    statistics = r_model.run("clp", "2020-01-01", "2020-01-10", 1)
+
+    assert statistics["timesteps"] == 10
+    assert statistics["scenarios"] == 2
+    assert not statistics["cancelled"]
+    assert output_fn.exists()
+
+
+def test_array_recorder():
+    """Test the values of an array recorder are read as a NumPy array after a run"""
+    from pywr.pywr import PyModel  # type: ignore
+
+    r_model = PyModel()
+    r_model.add_scenario("scenario", 2)
+    r_model.add_input_node("input")
+    r_model.add_output_node("output")
+    r_model.connect_nodes("input", "output")
+    r_model.set_node_constraint("output", "max_flow", 5.0)
+    r_model.set_node_cost("output", -10.0)
+    r_model.add_array_recorder("flows", "output", "node_inflow")
+    r_model.run("clp", "2020-01-01", "2020-01-10", 1)
+
+    flows = r_model.recorder_array("flows")
+    assert isinstance(flows, np.ndarray)
+    np.testing.assert_allclose(flows, np.full((10, 2), 5.0))
+
+
+def test_results_dataframe():
+    """Test the values of an array recorder are read as a DataFrame indexed by date and scenario"""
+    data = {
+        "timestepper": {"start": "2020-01-01", "end": "2020-01-10", "timestep": 1},
+        "scenarios": [{"name": "demand", "size": 2}, {"name": "climate", "size": 3}],
+        "nodes": [
+            {"name": "input1", "type": "input"},
+            {"name": "output1", "type": "output", "cost": -10.0, "max_flow": 10.0},
+        ],
+        "edges": [{"from_node": "input1", "to_node": "output1"}],
+        "recorders": [
+            {
+                "name": "flows",
+                "type": "array",
+                "component": "output1",
+                "metric": "node_inflow",
+            }
+        ],
+    }
+    model = Model(**data)
+    results = model.run()
+
+    assert results.statistics["timesteps"] == 10
+    df = results.to_dataframe("flows")
+    assert df.shape == (10, 6)
+    assert isinstance(df.index, pandas.DatetimeIndex)
+    assert df.index[0] == pandas.Timestamp("2020-01-01")
+    assert list(df.columns.names) == ["demand", "climate"]
+    assert df.columns[-1] == (1, 2)
+    np.testing.assert_allclose(df.values, 10.0)
+
+
+def test_steps():
+    """Test a model is solved a timestep at a time by iterating over its steps"""
+    data = {
+        "timestepper": {"start": "2020-01-01", "end": "2020-01-05", "timestep": 1},
+        "scenarios": [{"name": "demand", "size": 2}],
+        "nodes": [
+            {"name": "input1", "type": "input"},
+            {"name": "output1", "type": "output", "cost": -10.0, "max_flow": 10.0},
+        ],
+        "edges": [{"from_node": "input1", "to_node": "output1"}],
+    }
+    model = Model(**data)
+
+    steps = model.steps()
+    dates = []
+    for state in steps:
+        dates.append(state.timestep.date)
+        assert state.num_scenarios == 2
+        assert state.node_in_flow("output1") == [10.0, 10.0]
+
+    assert len(dates) == 5
+    assert steps.statistics["timesteps"] == 5
+
+    # A run may be finished early.
+    steps = model.steps()
+    next(steps)
+    assert steps.finish()["timesteps"] == 1
+
+
+def test_steps_superseded():
+    """Test the steps of a run cannot continue once another run of the model has started"""
+    from pywr.pywr import PyModel, PywrError  # type: ignore
+
+    r_model = PyModel()
+    r_model.add_input_node("input")
+    r_model.add_output_node("output")
+    r_model.connect_nodes("input", "output")
+    r_model.set_node_cost("output", -10.0)
+
+    steps = r_model.steps("clp", "2020-01-01", "2020-01-05", 1)
+    next(steps)
+    assert r_model.run("clp", "2020-01-01", "2020-01-05", 1)["timesteps"] == 5
+    with pytest.raises(PywrError):
+        next(steps)
+
+
+class GreedySolver:
+    """Send as much flow as the bounds allow along each edge with a negative cost."""
+
+    def setup(self, model_info):
+        self.node_types = [node["type"] for node in model_info["nodes"]]
+        self.edges = model_info["edges"]
+
+    def solve(self, bounds, costs):
+        flows = []
+        for (from_node, to_node), (_, edge_max), cost in zip(self.edges, bounds["edges"], costs):
+            if cost < 0.0:
+                flows.append(min(bounds["nodes"][from_node][1], bounds["nodes"][to_node][1], edge_max))
+            else:
+                flows.append(0.0)
+        return flows
+
+
+def test_python_solver():
+    """Test a model is run with a solver implemented in Python"""
+    from pywr.pywr import PyModel  # type: ignore
+
+    r_model = PyModel()
+    r_model.add_scenario("scenario", 2)
+    r_model.add_input_node("input")
+    r_model.add_output_node("output")
+    r_model.connect_nodes("input", "output")
+    r_model.set_node_constraint("output", "max_flow", 5.0)
+    r_model.set_node_cost("output", -10.0)
+    r_model.add_array_recorder("flows", "output", "node_inflow")
+
+    solver = GreedySolver()
+    r_model.run(solver, "2020-01-01", "2020-01-10", 1)
+    assert solver.node_types == ["input", "output"]
+    assert solver.edges == [(0, 1)]
+    np.testing.assert_allclose(r_model.recorder_array("flows"), np.full((10, 2), 5.0))
+
+
+def test_progress_callback():
+    """Test a progress callback is called as a run progresses"""
+    data = {
+        "timestepper": {"start": "2020-01-01", "end": "2020-01-10", "timestep": 1},
+        "nodes": [
+            {"name": "input1", "type": "input"},
+            {"name": "output1", "type": "output", "cost": -10.0, "max_flow": 10.0},
+        ],
+        "edges": [{"from_node": "input1", "to_node": "output1"}],
+    }
+    model = Model(**data)
+
+    reports = []
+    model.run(progress=lambda fraction, date, speed: reports.append((fraction, date)))
+
+    assert len(reports) == 10
+    assert reports[0] == (0.1, datetime.date(2020, 1, 1))
+    assert reports[-1] == (1.0, datetime.date(2020, 1, 10))
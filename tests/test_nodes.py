@@ -63,6 +63,27 @@ class TestSimple1:
         # TODO test the outputs
 
 
+def test_scalar_constraints():
+    """Test constants are accepted as constraints and costs without a parameter"""
+
+    data = {
+        "timestepper": {"start": "2020-01-01", "end": "2020-01-31", "timestep": 1},
+        "nodes": [
+            {"name": "input1", "type": "input", "max_flow": 15.0},
+            {"name": "link1", "type": "link", "min_flow": 1.0},
+            {"name": "output1", "type": "output", "cost": -10.0, "max_flow": 10.0},
+        ],
+        "edges": [
+            {"from_node": "input1", "to_node": "link1"},
+            {"from_node": "link1", "to_node": "output1"},
+        ],
+    }
+
+    model = Model(**data)
+    assert len(model.parameters) == 0
+    model.run()
+
+
 def test_duplicate_node_name_error():
 
     data = {
@@ -76,3 +97,28 @@ def test_duplicate_node_name_error():
 
     with pytest.raises(ValueError):
         Model(**data)
+
+
+def test_error_classes():
+    """Test errors are raised as the exception class of their kind."""
+    from pywr.pywr import (  # type: ignore
+        PyModel,
+        PywrError,
+        NodeNotFoundError,
+        NameAlreadyExistsError,
+        TimestepperError,
+    )
+
+    r_model = PyModel()
+    r_model.add_input_node("input")
+
+    with pytest.raises(NodeNotFoundError, match="missing"):
+        r_model.connect_nodes("input", "missing")
+    with pytest.raises(NameAlreadyExistsError):
+        r_model.add_output_node("input")
+    with pytest.raises(TimestepperError):
+        r_model.run("clp", "2020-01-10", "2020-01-01", 1)
+
+    # The classes share a base class, which is a `RuntimeError`.
+    assert issubclass(NodeNotFoundError, PywrError)
+    assert issubclass(PywrError, RuntimeError)
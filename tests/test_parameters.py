@@ -106,3 +106,93 @@ class TestAggregatedParameter:
 
         with pytest.raises(RuntimeError):
             model.run()
+
+
+def test_python_parameter_arguments():
+    """Test a Python parameter is given the timestep, scenario and values of its metrics."""
+    from pywr.pywr import PyModel  # type: ignore
+
+    class DoublingParameter:
+        def __init__(self):
+            self.calls = []
+
+        def compute(self, timestep, scenario_index, scenario_indices, values):
+            self.calls.append((timestep.index, scenario_index, scenario_indices, values))
+            return 2.0 * values[0]
+
+    r_model = PyModel()
+    r_model.add_scenario("scenario", 2)
+    r_model.add_input_node("input")
+    r_model.add_output_node("output")
+    r_model.connect_nodes("input", "output")
+    r_model.add_constant("base", 3.0)
+    parameter = DoublingParameter()
+    r_model.add_python_parameter("doubled", parameter, [("parameter", "base")])
+    r_model.set_node_constraint("output", "max_flow", "doubled")
+    r_model.set_node_cost("output", -10.0)
+    r_model.run("clp", "2020-01-01", "2020-01-03", 1)
+
+    assert len(parameter.calls) == 6
+    assert sorted(parameter.calls)[0] == (0, 0, [0], [3.0])
+    assert all(values == [3.0] for _, _, _, values in parameter.calls)
+
+
+def test_python_parameter_lifecycle():
+    """Test a Python parameter is set up with its keyword arguments, and told of each solution."""
+    from pywr.pywr import PyModel  # type: ignore
+
+    class StatefulParameter:
+        def setup(self, model_info, scenario_count, scale=1.0):
+            assert model_info["name"] == "stateful"
+            assert model_info["scenario_groups"] == {"scenario": 3}
+            self.scale = scale
+            self.delivered = [0.0] * scenario_count
+
+        def compute(self, timestep, scenario_index, scenario_indices, values):
+            return self.scale * (scenario_index + 1)
+
+        def after(self, timestep, scenario_index, values):
+            self.delivered[scenario_index] += values[0]
+
+    r_model = PyModel()
+    r_model.add_scenario("scenario", 3)
+    r_model.add_input_node("input")
+    r_model.add_output_node("output")
+    r_model.connect_nodes("input", "output")
+    parameter = StatefulParameter()
+    r_model.add_python_parameter("stateful", parameter, [("node_inflow", "output")], {"scale": 2.0})
+    r_model.set_node_constraint("output", "max_flow", "stateful")
+    r_model.set_node_cost("output", -10.0)
+    r_model.run("clp", "2020-01-01", "2020-01-05", 1)
+
+    np.testing.assert_allclose(parameter.delivered, [10.0, 20.0, 30.0])
+
+
+def test_python_parameter_instances():
+    """Test each scenario has its own copy of a Python parameter's object."""
+    from pywr.pywr import PyModel  # type: ignore
+
+    class CountingParameter:
+        def setup(self, model_info, scenario_count):
+            self.count = 0
+            self.counts = [0] * scenario_count
+
+        def compute(self, timestep, scenario_index, scenario_indices, values):
+            self.count += 1
+            return float(self.count)
+
+        def after(self, timestep, scenario_index, values):
+            self.counts[scenario_index] = self.count
+
+    r_model = PyModel()
+    r_model.add_scenario("scenario", 3)
+    r_model.add_input_node("input")
+    r_model.add_output_node("output")
+    r_model.connect_nodes("input", "output")
+    parameter = CountingParameter()
+    r_model.add_python_parameter("counting", parameter)
+    r_model.set_node_constraint("output", "max_flow", "counting")
+    r_model.set_node_cost("output", -10.0)
+    r_model.run("clp", "2020-01-01", "2020-01-05", 1)
+
+    assert parameter.counts == [5, 5, 5]